@@ -57,4 +57,11 @@ impl ReadOnlyChunksStore {
             _ => Err(Error::ChunkMissing(chunk_hash.clone())),
         }
     }
+    /// Whether the partial chunk, and with `with_chunk` the full chunk as well, is in the
+    /// store. Unlike the getters this skips the caches, which may still hold chunks that
+    /// have been removed from the store.
+    pub fn has_chunk(&self, chunk_hash: &ChunkHash, with_chunk: bool) -> std::io::Result<bool> {
+        Ok(self.store.exists(DBCol::PartialChunks, chunk_hash.as_ref())?
+            && (!with_chunk || self.store.exists(DBCol::Chunks, chunk_hash.as_ref())?))
+    }
 }
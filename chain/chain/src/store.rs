@@ -32,7 +32,7 @@ use unc_primitives::transaction::{
 use unc_primitives::trie_key::{trie_key_parsers, TrieKey};
 use unc_primitives::types::chunk_extra::ChunkExtra;
 use unc_primitives::types::{
-    BlockExtra, BlockHeight, EpochId, NumBlocks, ShardId, StateChanges, StateChangesExt,
+    Balance, BlockExtra, BlockHeight, EpochId, NumBlocks, ShardId, StateChanges, StateChangesExt,
     StateChangesForResharding, StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
 };
 use unc_primitives::utils::{
@@ -798,6 +798,25 @@ impl ChainStore {
             .unwrap_or_default())
     }
 
+    /// Returns the sum of `tokens_burnt` over the execution outcomes of applying the chunk
+    /// of the given shard in the given block. Looks the outcomes up by their ids directly
+    /// instead of scanning all the forks they were executed on.
+    pub fn get_tokens_burnt_by_block_hash_and_shard_id(
+        &self,
+        block_hash: &CryptoHash,
+        shard_id: ShardId,
+    ) -> Result<Balance, Error> {
+        let mut tokens_burnt: Balance = 0;
+        for id in self.get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)? {
+            let outcome =
+                self.get_outcome_by_id_and_block_hash(&id, block_hash)?.ok_or_else(|| {
+                    Error::DBNotFoundErr(format!("OUTCOME: {} in block {}", id, block_hash))
+                })?;
+            tokens_burnt += outcome.outcome.tokens_burnt;
+        }
+        Ok(tokens_burnt)
+    }
+
     /// Get all execution outcomes generated when the chunk are applied
     pub fn get_block_execution_outcomes(
         &self,
@@ -898,6 +917,28 @@ impl ChainStore {
         store_update.commit().map_err(|err| err.into())
     }
 
+    /// Deletes a block, chunk or partial chunk from the store and drops the cached copy, so
+    /// that tests can check how the node copes with it going missing. Panics for any other
+    /// column, whose caches aren't handled here.
+    #[cfg(feature = "test_features")]
+    pub fn delete_for_test(&self, col: DBCol, key: &[u8]) -> Result<(), Error> {
+        let mut store_update = self.store.store_update();
+        store_update.delete(col, key);
+        match col {
+            DBCol::Block => {
+                self.blocks.pop(key);
+            }
+            DBCol::Chunks => {
+                self.chunks.pop(key);
+            }
+            DBCol::PartialChunks => {
+                self.partial_chunks.pop(key);
+            }
+            _ => panic!("delete_for_test() can't delete from {col}"),
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
     /// Save epoch sync info
     #[cfg(feature = "new_epoch_sync")]
     pub fn get_epoch_sync_info(&self, epoch_id: &EpochId) -> Result<EpochSyncInfo, Error> {
@@ -9,7 +9,10 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use unc_chain_configs::{ProtocolConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP};
 use unc_chain_primitives::Error;
 use unc_crypto::{KeyType, PublicKey, SecretKey, Signature};
-use unc_epoch_manager::types::BlockHeaderInfo;
+use unc_epoch_manager::types::{
+    BlockHeaderInfo, EpochInfoAggregator, NetworkTotals, NextEpochStatus, ValidatorDiff,
+    NEUTRAL_CHUNK_PRODUCER_RELIABILITY,
+};
 use unc_epoch_manager::{EpochManagerAdapter, RngSeed};
 use unc_pool::types::PoolIterator;
 use unc_primitives::account::{AccessKey, Account};
@@ -72,6 +75,8 @@ pub struct KeyValueRuntime {
     state: RwLock<HashMap<StateRoot, KVState>>,
     state_size: RwLock<HashMap<StateRoot, u64>>,
     headers_cache: RwLock<HashMap<CryptoHash, BlockHeader>>,
+    // balance the next applied chunk reports as burnt on top of what its transactions burn
+    injected_balance_burnt: RwLock<Balance>,
 }
 
 /// DEPRECATED. DO NOT USE for new tests. Use the real EpochManager, familiarize
@@ -291,6 +296,47 @@ impl MockEpochManager {
         self.validators_by_valset[valset].chunk_producers[shard_id as usize].clone()
     }
 
+    fn get_epoch_info_for_valset(&self, valset: usize) -> EpochInfo {
+        let validators = self.validators.iter().map(|(_, pledge)| pledge.clone()).collect();
+        let mut validator_to_index = HashMap::new();
+        for (i, (account_id, _)) in self.validators.iter().enumerate() {
+            validator_to_index.insert(account_id.clone(), i as u64);
+        }
+        let bp_settlement = self.validators_by_valset[valset]
+            .block_producers
+            .iter()
+            .map(|pledge| *validator_to_index.get(pledge.account_id()).unwrap())
+            .collect();
+        let cp_settlement = self.validators_by_valset[valset]
+            .chunk_producers
+            .iter()
+            .map(|vec| {
+                vec.iter()
+                    .map(|pledge| *validator_to_index.get(pledge.account_id()).unwrap())
+                    .collect()
+            })
+            .collect();
+        EpochInfo::new(
+            10,
+            validators,
+            validator_to_index,
+            bp_settlement,
+            cp_settlement,
+            vec![],
+            vec![],
+            HashMap::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            1,
+            1,
+            1,
+            RngSeed::default(),
+            Default::default(),
+        )
+    }
+
     fn get_valset_for_epoch(&self, epoch_id: &EpochId) -> Result<usize, EpochError> {
         // conveniently here if the prev_hash is passed mipledgenly instead of the epoch_hash,
         // the `unwrap` will trigger
@@ -369,9 +415,16 @@ impl KeyValueRuntime {
             headers_cache: RwLock::new(HashMap::new()),
             state: RwLock::new(state),
             state_size: RwLock::new(state_size),
+            injected_balance_burnt: RwLock::new(0),
         })
     }
 
+    /// Makes the next applied chunk report `amount` more balance burnt than its execution
+    /// outcomes add up to, for testing that such an imbalance gets caught.
+    pub fn inject_balance_burnt(&self, amount: Balance) {
+        *self.injected_balance_burnt.write().unwrap() += amount;
+    }
+
     fn get_block_header(&self, hash: &CryptoHash) -> Result<Option<BlockHeader>, EpochError> {
         let mut headers_cache = self.headers_cache.write().unwrap();
         if headers_cache.get(hash).is_some() {
@@ -486,44 +539,7 @@ impl EpochManagerAdapter for MockEpochManager {
     /// - chunk producers
     /// All the other fields have a hardcoded value or left empty.
     fn get_epoch_info(&self, _epoch_id: &EpochId) -> Result<Arc<EpochInfo>, EpochError> {
-        let validators = self.validators.iter().map(|(_, pledge)| pledge.clone()).collect();
-        let mut validator_to_index = HashMap::new();
-        for (i, (account_id, _)) in self.validators.iter().enumerate() {
-            validator_to_index.insert(account_id.clone(), i as u64);
-        }
-        let bp_settlement = self.validators_by_valset[0]
-            .block_producers
-            .iter()
-            .map(|pledge| *validator_to_index.get(pledge.account_id()).unwrap())
-            .collect();
-        let cp_settlement = self.validators_by_valset[0]
-            .chunk_producers
-            .iter()
-            .map(|vec| {
-                vec.iter()
-                    .map(|pledge| *validator_to_index.get(pledge.account_id()).unwrap())
-                    .collect()
-            })
-            .collect();
-        Ok(Arc::new(EpochInfo::new(
-            10,
-            validators,
-            validator_to_index,
-            bp_settlement,
-            cp_settlement,
-            vec![],
-            vec![],
-            HashMap::new(),
-            BTreeMap::new(),
-            BTreeMap::new(),
-            HashMap::new(),
-            HashMap::new(),
-            1,
-            1,
-            1,
-            RngSeed::default(),
-            Default::default(),
-        )))
+        Ok(Arc::new(self.get_epoch_info_for_valset(0)))
     }
 
     fn get_shard_layout(&self, _epoch_id: &EpochId) -> Result<ShardLayout, EpochError> {
@@ -683,6 +699,29 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(vec![])
     }
 
+    fn chunk_producer_reliability(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+    ) -> Result<Vec<(AccountId, f64)>, EpochError> {
+        // Chunk production isn't tracked here, so every producer is neutral.
+        let valset = self.get_valset_for_epoch(epoch_id)?;
+        Ok(self
+            .get_chunk_producers(valset, shard_id)
+            .into_iter()
+            .map(|producer| (producer.take_account_id(), NEUTRAL_CHUNK_PRODUCER_RELIABILITY))
+            .collect())
+    }
+
+    fn shard_chunk_density(
+        &self,
+        _shard_id: ShardId,
+        _last_n_epochs: usize,
+    ) -> Result<Vec<(EpochId, f64)>, EpochError> {
+        // Chunk production isn't tracked here, so no epoch has any counts.
+        Ok(vec![])
+    }
+
     fn get_block_producer(
         &self,
         epoch_id: &EpochId,
@@ -764,6 +803,53 @@ impl EpochManagerAdapter for MockEpochManager {
         })
     }
 
+    fn validator_diff(
+        &self,
+        prev_epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+    ) -> Result<ValidatorDiff, EpochError> {
+        let prev_epoch_info =
+            self.get_epoch_info_for_valset(self.get_valset_for_epoch(prev_epoch_id)?);
+        let next_epoch_info =
+            self.get_epoch_info_for_valset(self.get_valset_for_epoch(next_epoch_id)?);
+        let shard_layout = ShardLayout::v0(self.num_shards, 0);
+        Ok(ValidatorDiff::new(
+            prev_epoch_id.clone(),
+            &prev_epoch_info,
+            &shard_layout,
+            next_epoch_id.clone(),
+            &next_epoch_info,
+            &shard_layout,
+        ))
+    }
+
+    fn validator_status_next_epoch(
+        &self,
+        account_id: &AccountId,
+        current_block_hash: &CryptoHash,
+    ) -> Result<NextEpochStatus, EpochError> {
+        let next_epoch_id = self.get_next_epoch_id(current_block_hash)?;
+        let next_epoch_info =
+            self.get_epoch_info_for_valset(self.get_valset_for_epoch(&next_epoch_id)?);
+        Ok(NextEpochStatus::new(account_id, &next_epoch_info))
+    }
+
+    fn network_totals(&self, block_hash: &CryptoHash) -> Result<NetworkTotals, EpochError> {
+        let epoch_id = self.get_epoch_id(block_hash)?;
+        let epoch_info = self.get_epoch_info_for_valset(self.get_valset_for_epoch(&epoch_id)?);
+        Ok(NetworkTotals::active(epoch_id, &epoch_info))
+    }
+
+    fn pending_network_totals(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<NetworkTotals, EpochError> {
+        // there are no proposals in the mock
+        let epoch_id = self.get_epoch_id(block_hash)?;
+        let epoch_info = self.get_epoch_info_for_valset(self.get_valset_for_epoch(&epoch_id)?);
+        Ok(NetworkTotals::pending(&epoch_info, &EpochInfoAggregator::new(epoch_id, *block_hash)))
+    }
+
     fn add_validator_proposals(
         &self,
         _block_header_info: BlockHeaderInfo,
@@ -1209,7 +1295,7 @@ impl RuntimeAdapter for KeyValueRuntime {
             validator_power_proposals: vec![],
             validator_pledge_proposals: vec![],
             total_gas_burnt: 0,
-            total_balance_burnt: 0,
+            total_balance_burnt: std::mem::take(&mut *self.injected_balance_burnt.write().unwrap()),
             proof: None,
             processed_delayed_receipts: vec![],
         })
@@ -267,6 +267,10 @@ pub struct ShardsManager {
     // header_head is new, but we would only know that the older chunks are old because
     // header_head is much newer.
     chain_header_head: Tip,
+    // If set, parts owned by chunk producers of the shard whose reliability, as
+    // reported by the epoch manager, is below this threshold are requested
+    // from the shard representative target instead of their owners.
+    chunk_producer_reliability_threshold: Option<f64>,
 }
 
 impl ShardsManager {
@@ -303,9 +307,19 @@ impl ShardsManager {
             chunk_forwards_cache: lru::LruCache::new(CHUNK_FORWARD_CACHE_SIZE),
             chain_head: initial_chain_head,
             chain_header_head: initial_chain_header_head,
+            chunk_producer_reliability_threshold: None,
         }
     }
 
+    /// Makes the shards manager stop requesting parts from chunk producers
+    /// which missed too many of their chunks so far in the epoch, i.e. whose
+    /// [`EpochManagerAdapter::chunk_producer_reliability`] is below `threshold`.
+    /// Their parts are requested from the shard representative target instead,
+    /// like our own parts are.
+    pub fn set_chunk_producer_reliability_threshold(&mut self, threshold: Option<f64>) {
+        self.chunk_producer_reliability_threshold = threshold;
+    }
+
     pub fn update_chain_heads(&mut self, head: Tip, header_head: Tip) {
         self.encoded_chunks.update_largest_seen_height(
             head.height,
@@ -380,6 +394,7 @@ impl ShardsManager {
         };
 
         let epoch_id = self.epoch_manager.get_epoch_id_from_prev_block(ancestor_hash)?;
+        let unreliable_producers = self.get_unreliable_chunk_producers(&epoch_id, shard_id)?;
 
         for part_ord in 0..self.rs.total_shard_count() {
             let part_ord = part_ord as u64;
@@ -404,6 +419,9 @@ impl ShardsManager {
             } else if we_own_part {
                 // If missing own part, request it from the chunk producer / node tracking shard
                 shard_representative_target.clone()
+            } else if unreliable_producers.contains(&part_owner) {
+                // The part owner keeps missing its own chunks, so it's unlikely to answer either
+                shard_representative_target.clone()
             } else {
                 Some(part_owner)
             };
@@ -472,6 +490,25 @@ impl ShardsManager {
         Ok(())
     }
 
+    /// Returns the chunk producers of the shard which aren't reliable enough to
+    /// request parts from; see [`Self::set_chunk_producer_reliability_threshold`].
+    fn get_unreliable_chunk_producers(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+    ) -> Result<HashSet<AccountId>, unc_chain::Error> {
+        let Some(threshold) = self.chunk_producer_reliability_threshold else {
+            return Ok(HashSet::new());
+        };
+        Ok(self
+            .epoch_manager
+            .chunk_producer_reliability(epoch_id, shard_id)?
+            .into_iter()
+            .filter(|(_, reliability)| *reliability < threshold)
+            .map(|(account_id, _)| account_id)
+            .collect())
+    }
+
     /// Get a random shard block producer that is not me.
     fn get_random_target_tracking_shard(
         &self,
@@ -590,18 +627,24 @@ impl ShardsManager {
             return;
         }
 
-        if let Some(entry) = self.encoded_chunks.get(&chunk_header.chunk_hash()) {
-            if entry.complete {
-                debug!(target: "chunks", height, shard_id, ?chunk_hash, "Not requesting chunk, already complete.");
-                return;
-            }
-        } else {
+        let complete = match self.encoded_chunks.get(&chunk_header.chunk_hash()) {
+            Some(entry) => entry.complete,
             // In all code paths that lead to this function, we have already inserted the header.
             // However, if the chunk had just been processed and marked as complete, it might have
             // been removed from the cache if it is out of horizon. So in this case, the chunk is
-            // already complete and we don't need to request anything.
-            debug!(target: "chunks", height, shard_id, ?chunk_hash, "Not requesting chunk, already complete and GC-ed.");
-            return;
+            // already complete.
+            None => true,
+        };
+        if complete {
+            if !self.completed_chunk_lost(chunk_header) {
+                debug!(target: "chunks", height, shard_id, ?chunk_hash, "Not requesting chunk, already complete.");
+                return;
+            }
+            // The chunk went missing from the store after it was completed, so the client
+            // can't use it. Start over with the header and fetch the parts again.
+            warn!(target: "chunks", height, shard_id, ?chunk_hash, "Requesting chunk again, it's complete but missing from the store.");
+            self.encoded_chunks.remove(&chunk_hash);
+            self.encoded_chunks.get_or_insert_from_header(chunk_header);
         }
 
         let prev_block_hash = *chunk_header.prev_block_hash();
@@ -664,6 +707,26 @@ impl ShardsManager {
         }
     }
 
+    /// Whether a chunk that was completed is missing from the store, which happens if it's
+    /// deleted from there afterwards. As in the chain, the full chunk is needed only for the
+    /// shards tracked in this or the next epoch, and just the partial chunk otherwise.
+    fn completed_chunk_lost(&self, chunk_header: &ShardChunkHeader) -> bool {
+        let with_chunk = cares_about_shard_this_or_next_epoch(
+            self.me.as_ref(),
+            chunk_header.prev_block_hash(),
+            chunk_header.shard_id(),
+            true,
+            &self.shard_tracker,
+        );
+        match self.store.has_chunk(&chunk_header.chunk_hash(), with_chunk) {
+            Ok(stored) => !stored,
+            Err(err) => {
+                error!(target: "chunks", ?err, "Failed to check whether the chunk is stored");
+                false
+            }
+        }
+    }
+
     /// send chunk requests for some chunks in a block
     /// `chunks_to_request`: chunks to request
     /// `prev_hash`: hash of prev block of the block we are requesting missing chunks for
@@ -2510,7 +2573,7 @@ mod test {
     // chunk header first arrives as part of a block, it should store the the forward and use it
     // when it receives the header.
     fn test_receive_forward_before_chunk_header_from_block() {
-        let fixture = ChunkTestFixture::default();
+        let mut fixture = ChunkTestFixture::default();
         let clock = FakeClock::default();
         let mut shards_manager = ShardsManager::new(
             clock.clock(),
@@ -2544,9 +2607,9 @@ mod test {
                 panic!("Unexpected process_result: {:?}", process_result);
             }
         }
-        assert_eq!(fixture.count_chunk_completion_messages(), 1);
+        assert_eq!(fixture.persist_completed_chunks(), 1);
         // Requesting it again should not send any actual requests as the chunk is already
-        // complete. Sleeping and resending later should also not send any requests.
+        // complete and saved. Sleeping and resending later should also not send any requests.
         shards_manager.request_chunk_single(
             &fixture.mock_chunk_header,
             *fixture.mock_chunk_header.prev_block_hash(),
@@ -2570,6 +2633,59 @@ mod test {
             .is_none());
     }
 
+    #[test]
+    // Test that a chunk which was completed but then went missing from the store, so that the
+    // client asks for it again, is requested from the network and completed once more.
+    fn test_request_completed_chunk_missing_from_store() {
+        let fixture = ChunkTestFixture::default();
+        let clock = FakeClock::default();
+        let mut shards_manager = ShardsManager::new(
+            clock.clock(),
+            Some(fixture.mock_shard_tracker.clone()),
+            Arc::new(fixture.epoch_manager.clone()),
+            fixture.shard_tracker.clone(),
+            fixture.mock_network.as_sender(),
+            fixture.mock_client_adapter.as_sender(),
+            fixture.chain_store.new_read_only_chunks_store(),
+            fixture.mock_chain_head.clone(),
+            fixture.mock_chain_head.clone(),
+        );
+        let forward = PartialEncodedChunkForwardMsg::from_header_and_parts(
+            &fixture.mock_chunk_header,
+            fixture.mock_chunk_parts.clone(),
+        );
+        assert!(shards_manager.process_partial_encoded_chunk_forward(forward).is_ok());
+        shards_manager.insert_header_if_not_exists_and_process_cached_chunk_forwards(
+            &fixture.mock_chunk_header,
+        );
+        let process_result = shards_manager
+            .try_process_chunk_parts_and_receipts(&fixture.mock_chunk_header)
+            .unwrap();
+        assert_matches!(process_result, ProcessPartialEncodedChunkResult::HaveAllPartsAndReceipts);
+        // The completed chunk doesn't make it to the store, as if it was deleted from there.
+        assert_eq!(fixture.count_chunk_completion_messages(), 1);
+
+        shards_manager.request_chunk_single(
+            &fixture.mock_chunk_header,
+            *fixture.mock_chunk_header.prev_block_hash(),
+            false,
+        );
+        clock.advance(CHUNK_REQUEST_RETRY * 2);
+        shards_manager.resend_chunk_requests();
+        assert!(fixture.mock_network.requests.read().unwrap().iter().any(|r| matches!(
+            r.as_network_requests_ref(),
+            NetworkRequests::PartialEncodedChunkRequest { .. }
+        )));
+
+        // The response completes the chunk again, instead of being ignored as known.
+        let partial_encoded_chunk = fixture.make_partial_encoded_chunk(&fixture.all_part_ords);
+        let result = shards_manager
+            .process_partial_encoded_chunk(MaybeValidated::from(partial_encoded_chunk))
+            .unwrap();
+        assert_matches!(result, ProcessPartialEncodedChunkResult::HaveAllPartsAndReceipts);
+        assert_eq!(fixture.count_chunk_completion_messages(), 1);
+    }
+
     #[test]
     fn test_chunk_cache_hit_for_produced_chunk() {
         let fixture = ChunkTestFixture::default();
@@ -24,6 +24,7 @@ use std::sync::{Arc, Mutex, RwLock};
 
 use crate::adapter::ShardsManagerRequestFromClient;
 use crate::client::ShardsManagerResponse;
+use crate::test_loop::ShardsManagerResendChunkRequests;
 use crate::ShardsManager;
 
 /// Deprecated. Use `MockChainForShardsManager`.
@@ -219,6 +220,24 @@ impl ChunkTestFixture {
         chunks_completed
     }
 
+    /// Saves the chunks completed by the shards manager to the store, as the client does,
+    /// and returns how many there were.
+    pub fn persist_completed_chunks(&mut self) -> usize {
+        let mut chunks_completed = 0;
+        let mut update = self.chain_store.store_update();
+        while let Some(message) = self.mock_client_adapter.pop() {
+            if let ShardsManagerResponse::ChunkCompleted { partial_chunk, shard_chunk } = message {
+                update.save_partial_chunk(partial_chunk);
+                if let Some(shard_chunk) = shard_chunk {
+                    update.save_chunk(shard_chunk);
+                }
+                chunks_completed += 1;
+            }
+        }
+        update.commit().unwrap();
+        chunks_completed
+    }
+
     pub fn count_chunk_ready_for_inclusion_messages(&self) -> usize {
         let mut chunks_ready = 0;
         while let Some(message) = self.mock_client_adapter.pop() {
@@ -301,6 +320,13 @@ impl CanSend<ShardsManagerRequestFromNetwork> for SynchronousShardsManagerAdapte
     }
 }
 
+impl CanSend<ShardsManagerResendChunkRequests> for SynchronousShardsManagerAdapter {
+    fn send(&self, _: ShardsManagerResendChunkRequests) {
+        let mut shards_manager = self.shards_manager.lock().unwrap();
+        shards_manager.resend_chunk_requests();
+    }
+}
+
 impl SynchronousShardsManagerAdapter {
     pub fn new(shards_manager: ShardsManager) -> Self {
         Self { shards_manager: Arc::new(Mutex::new(shards_manager)) }
@@ -2487,7 +2487,7 @@ impl Client {
         Ok(result)
     }
 
-    fn clear_data(&mut self) -> Result<(), unc_chain::Error> {
+    pub(crate) fn clear_data(&mut self) -> Result<(), unc_chain::Error> {
         // A RPC node should do regular garbage collection.
         if !self.config.archive {
             let tries = self.runtime_adapter.get_tries();
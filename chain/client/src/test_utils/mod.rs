@@ -1,13 +1,19 @@
 pub mod block_stats;
 pub mod client;
+pub mod multi_run;
 pub mod peer_manager_mock;
 pub mod setup;
+pub mod shards_manager_log;
 pub mod test_env;
 pub mod test_env_builder;
+pub mod tx_builder;
 
 pub use block_stats::*;
 pub use client::*;
+pub use multi_run::*;
 pub use peer_manager_mock::*;
 pub use setup::*;
+pub use shards_manager_log::*;
 pub use test_env::*;
 pub use test_env_builder::*;
+pub use tx_builder::*;
@@ -0,0 +1,149 @@
+use super::test_env::TestEnv;
+use super::test_env_builder::TestEnvBuilder;
+use std::any::Any;
+use std::fmt;
+use std::ops::Range;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+
+/// Why a scenario run by [`MultiRun`] failed.
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+#[error("{0}")]
+pub struct ScenarioFailure(pub String);
+
+impl ScenarioFailure {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+/// A scenario failing with the environment built from the given seed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeedFailure {
+    pub seed: u64,
+    pub failure: ScenarioFailure,
+}
+
+/// What happened in [`MultiRun::run`].
+#[derive(Clone, Debug, Default)]
+pub struct MultiRunReport {
+    /// Seeds the scenario was run with, in order.
+    pub seeds_run: Vec<u64>,
+    /// Failed runs, in the order they were run.
+    pub failures: Vec<SeedFailure>,
+    /// Set if some seeds weren't run because the wall time budget ran out.
+    pub out_of_time: bool,
+}
+
+impl MultiRunReport {
+    pub fn failing_seeds(&self) -> Vec<u64> {
+        self.failures.iter().map(|failure| failure.seed).collect()
+    }
+
+    /// Panics listing all the failing seeds, if there are any.
+    #[track_caller]
+    pub fn assert_ok(&self) {
+        if !self.failures.is_empty() {
+            panic!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for MultiRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} of {} runs failed", self.failures.len(), self.seeds_run.len())?;
+        for SeedFailure { seed, failure } in self.failures.iter() {
+            writeln!(f, "  seed {}: {}", seed, failure)?;
+        }
+        if !self.failures.is_empty() {
+            writeln!(f, "replay a failing seed with MultiRun::run_single")?;
+        }
+        if self.out_of_time {
+            writeln!(f, "ran out of time before trying all seeds")?;
+        }
+        Ok(())
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Runs a scenario on a fresh [`TestEnv`] for each of a range of seeds, to
+/// shake out bugs which only show up with some orderings.  Each environment
+/// is built with [`TestEnvBuilder::seed`] set to the seed of the run.  Created
+/// with [`TestEnvBuilder::multi_run`].
+pub struct MultiRun<B> {
+    make_builder: B,
+    max_wall_time: Option<Duration>,
+    stop_on_first_failure: bool,
+}
+
+impl<B: Fn() -> TestEnvBuilder> MultiRun<B> {
+    pub(crate) fn new(make_builder: B) -> Self {
+        Self { make_builder, max_wall_time: None, stop_on_first_failure: false }
+    }
+
+    /// Stops starting new runs once `max` has passed since the first one
+    /// started.  The run in progress is finished regardless.
+    pub fn max_wall_time(mut self, max: Duration) -> Self {
+        self.max_wall_time = Some(max);
+        self
+    }
+
+    /// Stops after the first failing run instead of trying all seeds.
+    pub fn stop_on_first_failure(mut self, stop: bool) -> Self {
+        self.stop_on_first_failure = stop;
+        self
+    }
+
+    /// Runs `scenario` on an environment built with `seed`.  A panic in the
+    /// scenario counts as a failure, but one while building the environment
+    /// doesn't, since it's a mistake in the setup rather than something the
+    /// seed uncovered.
+    pub fn run_single(
+        &self,
+        seed: u64,
+        scenario: impl Fn(TestEnv) -> Result<(), ScenarioFailure>,
+    ) -> Result<(), ScenarioFailure> {
+        let env = (self.make_builder)().seed(seed).build();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| scenario(env))) {
+            Ok(result) => result,
+            Err(payload) => {
+                Err(ScenarioFailure(format!("panicked: {}", panic_message(&*payload))))
+            }
+        }
+    }
+
+    /// Runs `scenario` once for each of `seeds` and reports all the failures
+    /// together.
+    pub fn run(
+        &self,
+        seeds: Range<u64>,
+        scenario: impl Fn(TestEnv) -> Result<(), ScenarioFailure>,
+    ) -> MultiRunReport {
+        let start = Instant::now();
+        let mut report = MultiRunReport::default();
+        for seed in seeds {
+            if self.max_wall_time.is_some_and(|max| start.elapsed() >= max) {
+                report.out_of_time = true;
+                break;
+            }
+            report.seeds_run.push(seed);
+            if let Err(failure) = self.run_single(seed, &scenario) {
+                tracing::warn!(target: "test", seed, %failure, "scenario failed");
+                report.failures.push(SeedFailure { seed, failure });
+                if self.stop_on_first_failure {
+                    break;
+                }
+            }
+        }
+        report
+    }
+}
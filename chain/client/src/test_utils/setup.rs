@@ -21,7 +21,7 @@ use unc_chain::state_snapshot_actor::SnapshotCallbacks;
 use unc_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
 use unc_chain::types::{ChainConfig, RuntimeAdapter};
 use unc_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
-use unc_chain_configs::{ClientConfig, MutableConfigValue, ReshardingConfig};
+use unc_chain_configs::{ClientConfig, MutableConfigValue, ReshardingConfig, StateSyncConfig};
 use unc_chunks::adapter::ShardsManagerRequestFromClient;
 use unc_chunks::client::ShardsManagerResponse;
 use unc_chunks::shards_manager_actor::start_shards_manager;
@@ -909,9 +909,48 @@ pub fn setup_no_network_with_validity_period_and_no_epoch_sync(
     )
 }
 
+/// Client config knobs that can differ between the clients of one test setup, e.g. to
+/// model a network where only some nodes have a feature turned on.  `None` keeps the
+/// value of [`ClientConfig::test`].
+///
+/// Only what is toggled through [`ClientConfig`] can be overridden here.  Protocol
+/// features, like chunk validation, are enabled by the protocol version of the epoch (or
+/// at compile time with `cfg`s), so all clients always agree on them.  In-memory tries
+/// are a property of the runtime rather than the client; give each client its own
+/// `TrieConfig` when creating the runtimes for that.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFeatureOverrides {
+    /// Whether the client uses state sync rather than block sync when it falls behind.
+    pub state_sync_enabled: Option<bool>,
+    /// Where the client syncs state from and whether it dumps state parts.
+    pub state_sync: Option<StateSyncConfig>,
+    /// Whether the client creates flat storage in the background.
+    pub flat_storage_creation_enabled: Option<bool>,
+}
+
+impl ClientFeatureOverrides {
+    fn apply(&self, config: &mut ClientConfig) {
+        if let Some(state_sync_enabled) = self.state_sync_enabled {
+            config.state_sync_enabled = state_sync_enabled;
+        }
+        if let Some(state_sync) = &self.state_sync {
+            config.state_sync = state_sync.clone();
+        }
+        if let Some(flat_storage_creation_enabled) = self.flat_storage_creation_enabled {
+            config.flat_storage_creation_enabled = flat_storage_creation_enabled;
+        }
+    }
+}
+
+/// The validator signer of a client for `account_id`, with the key `create_test_signer`
+/// derives from its name.
+fn test_validator_signer(account_id: Option<AccountId>) -> Option<Arc<dyn ValidatorSigner>> {
+    account_id.map(|x| Arc::new(create_test_signer(x.as_str())) as Arc<dyn ValidatorSigner>)
+}
+
 pub fn setup_client_with_runtime(
     num_validator_seats: NumSeats,
-    account_id: Option<AccountId>,
+    validator_signer: Option<Arc<dyn ValidatorSigner>>,
     enable_doomslug: bool,
     network_adapter: PeerManagerAdapter,
     shards_manager_adapter: ShardsManagerAdapterForTest,
@@ -923,9 +962,8 @@ pub fn setup_client_with_runtime(
     archive: bool,
     save_trie_changes: bool,
     snapshot_callbacks: Option<SnapshotCallbacks>,
+    feature_overrides: &ClientFeatureOverrides,
 ) -> Client {
-    let validator_signer =
-        account_id.map(|x| Arc::new(create_test_signer(x.as_str())) as Arc<dyn ValidatorSigner>);
     let mut config = ClientConfig::test(
         true,
         10,
@@ -936,6 +974,7 @@ pub fn setup_client_with_runtime(
         true,
     );
     config.epoch_length = chain_genesis.epoch_length;
+    feature_overrides.apply(&mut config);
     let state_sync_adapter =
         Arc::new(RwLock::new(SyncAdapter::new(Sender::noop(), Sender::noop())));
     let mut client = Client::new(
@@ -976,7 +1015,7 @@ pub fn setup_client(
     let runtime = KeyValueRuntime::new(store, epoch_manager.as_ref());
     setup_client_with_runtime(
         num_validator_seats,
-        account_id,
+        test_validator_signer(account_id),
         enable_doomslug,
         network_adapter,
         shards_manager_adapter,
@@ -988,6 +1027,7 @@ pub fn setup_client(
         archive,
         save_trie_changes,
         None,
+        &ClientFeatureOverrides::default(),
     )
 }
 
@@ -1000,6 +1040,31 @@ pub fn setup_synchronous_shards_manager(
     runtime: Arc<dyn RuntimeAdapter>,
     chain_genesis: &ChainGenesis,
 ) -> ShardsManagerAdapterForTest {
+    setup_synchronous_shards_manager_adapter(
+        time::Clock::real(),
+        account_id,
+        client_adapter,
+        network_adapter,
+        epoch_manager,
+        shard_tracker,
+        runtime,
+        chain_genesis,
+    )
+    .into()
+}
+
+/// Same as [`setup_synchronous_shards_manager`], but returns the adapter itself
+/// rather than just the senders to it.
+pub fn setup_synchronous_shards_manager_adapter(
+    clock: time::Clock,
+    account_id: Option<AccountId>,
+    client_adapter: Sender<ShardsManagerResponse>,
+    network_adapter: PeerManagerAdapter,
+    epoch_manager: Arc<dyn EpochManagerAdapter>,
+    shard_tracker: ShardTracker,
+    runtime: Arc<dyn RuntimeAdapter>,
+    chain_genesis: &ChainGenesis,
+) -> Arc<SynchronousShardsManagerAdapter> {
     // Initialize the chain, to make sure that if the store is empty, we write the genesis
     // into the store, and as a short cut to get the parameters needed to instantiate
     // ShardsManager. This way we don't have to wait to construct the Client first.
@@ -1025,7 +1090,7 @@ pub fn setup_synchronous_shards_manager(
     let chain_head = chain.head().unwrap();
     let chain_header_head = chain.header_head().unwrap();
     let shards_manager = ShardsManager::new(
-        time::Clock::real(),
+        clock,
         account_id,
         epoch_manager,
         shard_tracker,
@@ -1035,7 +1100,7 @@ pub fn setup_synchronous_shards_manager(
         chain_head,
         chain_header_head,
     );
-    Arc::new(SynchronousShardsManagerAdapter::new(shards_manager)).into()
+    Arc::new(SynchronousShardsManagerAdapter::new(shards_manager))
 }
 
 pub fn setup_client_with_synchronous_shards_manager(
@@ -1069,7 +1134,7 @@ pub fn setup_client_with_synchronous_shards_manager(
     );
     setup_client_with_runtime(
         num_validator_seats,
-        account_id,
+        test_validator_signer(account_id),
         enable_doomslug,
         network_adapter,
         shards_manager_adapter,
@@ -1081,6 +1146,7 @@ pub fn setup_client_with_synchronous_shards_manager(
         archive,
         save_trie_changes,
         None,
+        &ClientFeatureOverrides::default(),
     )
 }
 
@@ -0,0 +1,277 @@
+use std::sync::{Arc, Mutex};
+
+use unc_async::messaging::{CanSend, IntoSender, Sender};
+use unc_async::time;
+use unc_chunks::adapter::ShardsManagerRequestFromClient;
+use unc_network::shards_manager::ShardsManagerRequestFromNetwork;
+use unc_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
+use unc_primitives::sharding::{ChunkHash, PartialEncodedChunkPart};
+use unc_primitives::types::AccountId;
+
+use super::setup::ShardsManagerAdapterForTest;
+
+/// Whether a message was received or sent by the shards manager.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShardsManagerMessageDirection {
+    /// Sent to the shards manager by its client or by the network.
+    Received,
+    /// Sent by the shards manager to the network.
+    Sent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShardsManagerMessageKind {
+    ProcessChunkHeaderFromBlock,
+    UpdateChainHeads,
+    DistributeEncodedChunk,
+    RequestChunks,
+    RequestChunksForOrphan,
+    CheckIncompleteChunks,
+    PartialEncodedChunk,
+    PartialEncodedChunkForward,
+    PartialEncodedChunkRequest,
+    PartialEncodedChunkResponse,
+}
+
+/// A single message recorded by [`ShardsManagerLog`].  Messages concerning
+/// several chunks, like [`ShardsManagerRequestFromClient::RequestChunks`], are
+/// recorded as one entry per chunk.
+#[derive(Clone, Debug)]
+pub struct ShardsManagerLogEntry {
+    pub time: time::Instant,
+    pub direction: ShardsManagerMessageDirection,
+    pub kind: ShardsManagerMessageKind,
+    /// None for messages which aren't about a particular chunk.
+    pub chunk_hash: Option<ChunkHash>,
+    /// Ordinals of the parts requested or carried by the message.
+    pub part_ords: Vec<u64>,
+    /// Account the chunk part request was addressed to, for sent requests.
+    pub target: Option<AccountId>,
+}
+
+/// Records the traffic of the shards manager of a single test client.  Used by
+/// [`TestEnv`](super::TestEnv) when built with
+/// [`record_shards_manager_requests`](super::TestEnvBuilder::record_shards_manager_requests).
+#[derive(Clone)]
+pub struct ShardsManagerLog {
+    clock: time::Clock,
+    entries: Arc<Mutex<Vec<ShardsManagerLogEntry>>>,
+}
+
+impl ShardsManagerLog {
+    /// Creates an empty log, which timestamps the messages with `clock`.
+    pub(crate) fn new(clock: time::Clock) -> Self {
+        Self { clock, entries: Default::default() }
+    }
+
+    pub fn entries(&self) -> Vec<ShardsManagerLogEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Returns times at which the shards manager sent requests for the given
+    /// part of the given chunk.
+    pub fn sent_part_requests(&self, chunk_hash: &ChunkHash, part_ord: u64) -> Vec<time::Instant> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| {
+                entry.direction == ShardsManagerMessageDirection::Sent
+                    && entry.kind == ShardsManagerMessageKind::PartialEncodedChunkRequest
+                    && entry.chunk_hash.as_ref() == Some(chunk_hash)
+                    && entry.part_ords.contains(&part_ord)
+            })
+            .map(|entry| entry.time)
+            .collect()
+    }
+
+    fn record(
+        &self,
+        direction: ShardsManagerMessageDirection,
+        kind: ShardsManagerMessageKind,
+        chunk_hash: Option<ChunkHash>,
+        part_ords: Vec<u64>,
+    ) {
+        self.record_with_target(direction, kind, chunk_hash, part_ords, None)
+    }
+
+    fn record_with_target(
+        &self,
+        direction: ShardsManagerMessageDirection,
+        kind: ShardsManagerMessageKind,
+        chunk_hash: Option<ChunkHash>,
+        part_ords: Vec<u64>,
+        target: Option<AccountId>,
+    ) {
+        let time = self.clock.now();
+        let entry = ShardsManagerLogEntry { time, direction, kind, chunk_hash, part_ords, target };
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Wraps the adapter the client and the network use to talk to the shards
+    /// manager so that all messages passing through it are recorded.
+    pub(crate) fn wrap_adapter(
+        &self,
+        adapter: ShardsManagerAdapterForTest,
+    ) -> ShardsManagerAdapterForTest {
+        ShardsManagerAdapterForTest {
+            client: RecordingSender { inner: adapter.client, log: self.clone() }.into_sender(),
+            network: RecordingSender { inner: adapter.network, log: self.clone() }.into_sender(),
+        }
+    }
+
+    /// Wraps the network adapter the shards manager sends its messages with so
+    /// that chunk requests and responses it sends are recorded.
+    pub(crate) fn wrap_network_adapter(&self, adapter: PeerManagerAdapter) -> PeerManagerAdapter {
+        PeerManagerAdapter {
+            request_sender: RecordingSender { inner: adapter.request_sender, log: self.clone() }
+                .into_sender(),
+            ..adapter
+        }
+    }
+}
+
+fn part_ords(parts: &[PartialEncodedChunkPart]) -> Vec<u64> {
+    parts.iter().map(|part| part.part_ord).collect()
+}
+
+struct RecordingSender<M: 'static> {
+    inner: Sender<M>,
+    log: ShardsManagerLog,
+}
+
+impl CanSend<ShardsManagerRequestFromClient> for RecordingSender<ShardsManagerRequestFromClient> {
+    fn send(&self, msg: ShardsManagerRequestFromClient) {
+        use ShardsManagerMessageKind as Kind;
+        let received = ShardsManagerMessageDirection::Received;
+        match &msg {
+            ShardsManagerRequestFromClient::ProcessChunkHeaderFromBlock(header) => {
+                self.log.record(
+                    received,
+                    Kind::ProcessChunkHeaderFromBlock,
+                    Some(header.chunk_hash()),
+                    vec![],
+                )
+            }
+            ShardsManagerRequestFromClient::UpdateChainHeads { .. } => {
+                self.log.record(received, Kind::UpdateChainHeads, None, vec![])
+            }
+            ShardsManagerRequestFromClient::DistributeEncodedChunk { partial_chunk, .. } => {
+                self.log.record(
+                    received,
+                    Kind::DistributeEncodedChunk,
+                    Some(partial_chunk.chunk_hash()),
+                    part_ords(partial_chunk.parts()),
+                )
+            }
+            ShardsManagerRequestFromClient::RequestChunks { chunks_to_request, .. } => {
+                for header in chunks_to_request {
+                    let chunk_hash = Some(header.chunk_hash());
+                    self.log.record(received, Kind::RequestChunks, chunk_hash, vec![])
+                }
+            }
+            ShardsManagerRequestFromClient::RequestChunksForOrphan { chunks_to_request, .. } => {
+                for header in chunks_to_request {
+                    self.log.record(
+                        received,
+                        Kind::RequestChunksForOrphan,
+                        Some(header.chunk_hash()),
+                        vec![],
+                    )
+                }
+            }
+            ShardsManagerRequestFromClient::CheckIncompleteChunks(_) => {
+                self.log.record(received, Kind::CheckIncompleteChunks, None, vec![])
+            }
+        }
+        self.inner.send(msg);
+    }
+}
+
+impl CanSend<ShardsManagerRequestFromNetwork>
+    for RecordingSender<ShardsManagerRequestFromNetwork>
+{
+    fn send(&self, msg: ShardsManagerRequestFromNetwork) {
+        use ShardsManagerMessageKind as Kind;
+        let received = ShardsManagerMessageDirection::Received;
+        match &msg {
+            ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunk(partial_chunk) => {
+                self.log.record(
+                    received,
+                    Kind::PartialEncodedChunk,
+                    Some(partial_chunk.chunk_hash()),
+                    part_ords(partial_chunk.parts()),
+                )
+            }
+            ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunkForward(forward) => {
+                self.log.record(
+                    received,
+                    Kind::PartialEncodedChunkForward,
+                    Some(forward.chunk_hash.clone()),
+                    part_ords(&forward.parts),
+                )
+            }
+            ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunkResponse {
+                partial_encoded_chunk_response: response,
+                ..
+            } => self.log.record(
+                received,
+                Kind::PartialEncodedChunkResponse,
+                Some(response.chunk_hash.clone()),
+                part_ords(&response.parts),
+            ),
+            ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunkRequest {
+                partial_encoded_chunk_request: request,
+                ..
+            } => self.log.record(
+                received,
+                Kind::PartialEncodedChunkRequest,
+                Some(request.chunk_hash.clone()),
+                request.part_ords.clone(),
+            ),
+        }
+        self.inner.send(msg);
+    }
+}
+
+impl CanSend<PeerManagerMessageRequest> for RecordingSender<PeerManagerMessageRequest> {
+    fn send(&self, msg: PeerManagerMessageRequest) {
+        use ShardsManagerMessageKind as Kind;
+        let sent = ShardsManagerMessageDirection::Sent;
+        if let PeerManagerMessageRequest::NetworkRequests(request) = &msg {
+            match request {
+                NetworkRequests::PartialEncodedChunkRequest { target, request, .. } => {
+                    self.log.record_with_target(
+                        sent,
+                        Kind::PartialEncodedChunkRequest,
+                        Some(request.chunk_hash.clone()),
+                        request.part_ords.clone(),
+                        target.account_id.clone(),
+                    )
+                }
+                NetworkRequests::PartialEncodedChunkResponse { response, .. } => self.log.record(
+                    sent,
+                    Kind::PartialEncodedChunkResponse,
+                    Some(response.chunk_hash.clone()),
+                    part_ords(&response.parts),
+                ),
+                NetworkRequests::PartialEncodedChunkMessage { partial_encoded_chunk, .. } => {
+                    self.log.record(
+                        sent,
+                        Kind::PartialEncodedChunk,
+                        Some(partial_encoded_chunk.header.chunk_hash()),
+                        part_ords(&partial_encoded_chunk.parts),
+                    )
+                }
+                NetworkRequests::PartialEncodedChunkForward { forward, .. } => self.log.record(
+                    sent,
+                    Kind::PartialEncodedChunkForward,
+                    Some(forward.chunk_hash.clone()),
+                    part_ords(&forward.parts),
+                ),
+                _ => {}
+            }
+        }
+        self.inner.send(msg);
+    }
+}
@@ -1,15 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use crate::adapter::ProcessTxResponse;
 use crate::Client;
-use unc_async::messaging::CanSend;
+use unc_async::messaging::{CanSend, Sender};
 use unc_chain::test_utils::ValidatorSchedule;
-use unc_chain::{ChainGenesis, Provenance};
+use unc_chain::{ChainGenesis, ChainStoreAccess, Provenance};
+use unc_chain_primitives::error::QueryError;
 use unc_chunks::client::ShardsManagerResponse;
+use unc_chunks::test_loop::ShardsManagerResendChunkRequests;
 use unc_chunks::test_utils::MockClientAdapterForShardsManager;
-use unc_crypto::{InMemorySigner, KeyType, Signer};
+use unc_crypto::{InMemorySigner, KeyType, PublicKey, Signer};
 use unc_network::shards_manager::ShardsManagerRequestFromNetwork;
 use unc_network::test_utils::MockPeerManagerAdapter;
 use unc_network::types::NetworkRequests;
@@ -22,20 +24,32 @@ use unc_primitives::block::Block;
 use unc_primitives::chunk_validation::ChunkEndorsement;
 use unc_primitives::epoch_manager::RngSeed;
 use unc_primitives::errors::InvalidTxError;
-use unc_primitives::hash::CryptoHash;
+use unc_primitives::hash::{hash, CryptoHash};
+use unc_primitives::shard_layout::ShardLayout;
+#[cfg(feature = "test_features")]
+use unc_primitives::sharding::ChunkHash;
 use unc_primitives::sharding::PartialEncodedChunk;
-use unc_primitives::test_utils::create_test_signer;
 use unc_primitives::transaction::{Action, FunctionCallAction, SignedTransaction};
-use unc_primitives::types::{AccountId, Balance, BlockHeight, EpochId, NumSeats};
+use unc_primitives::types::{
+    AccountId, Balance, BlockHeight, EpochId, Nonce, NumSeats, StateRoot,
+};
 use unc_primitives::utils::MaybeValidated;
+use unc_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use unc_primitives::version::ProtocolVersion;
 use unc_primitives::views::{
-    AccountView, FinalExecutionOutcomeView, QueryRequest, QueryResponseKind, StateItem,
+    AccessKeyView, AccountView, FinalExecutionOutcomeView, QueryRequest, QueryResponseKind,
+    StateItem,
 };
+use unc_store::{DBCol, TrieCachingStorage};
 use once_cell::sync::OnceCell;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 
-use super::setup::{setup_client_with_runtime, ShardsManagerAdapterForTest};
+use super::setup::{setup_client_with_runtime, ClientFeatureOverrides, ShardsManagerAdapterForTest};
+use super::shards_manager_log::ShardsManagerLog;
 use super::test_env_builder::TestEnvBuilder;
+use super::tx_builder::TxBuilder;
 use super::TEST_SEED;
 
 /// An environment for writing integration tests with multiple clients.
@@ -46,14 +60,65 @@ pub struct TestEnv {
     pub network_adapters: Vec<Arc<MockPeerManagerAdapter>>,
     pub client_adapters: Vec<Arc<MockClientAdapterForShardsManager>>,
     pub shards_manager_adapters: Vec<ShardsManagerAdapterForTest>,
+    pub(crate) shards_manager_resend_senders: Vec<Sender<ShardsManagerResendChunkRequests>>,
+    pub(crate) shards_manager_logs: Vec<Option<ShardsManagerLog>>,
     pub clients: Vec<Client>,
     pub(crate) account_indices: AccountIndices,
     pub(crate) paused_blocks: Arc<Mutex<HashMap<CryptoHash, Arc<OnceCell<()>>>>>,
     // random seed to be inject in each client according to AccountId
     // if not set, a default constant TEST_SEED will be injected
     pub(crate) seeds: HashMap<AccountId, RngSeed>,
+    // seed of the keys of each client and validator, see `validator_signer` and
+    // `client_signer`
+    pub(crate) signer_seeds: HashMap<AccountId, String>,
+    // clients without a validator signer, see `TestEnvBuilder::observer_clients`
+    pub(crate) observers: Vec<AccountId>,
+    // seed set with `TestEnvBuilder::seed`, or zero
+    pub(crate) seed: u64,
+    // seeded with `seed`, see `rng`
+    pub(crate) rng: StdRng,
+    // whether `process_partial_encoded_chunks` shuffles the messages, which it does once
+    // `TestEnvBuilder::seed` is set
+    pub(crate) shuffle_chunk_messages: bool,
+    // see `TestEnvBuilder::drop_chunk_messages`
+    pub(crate) chunk_message_drop_probability: f64,
     pub(crate) archive: bool,
     pub(crate) save_trie_changes: bool,
+    pub(crate) client_feature_overrides: Vec<ClientFeatureOverrides>,
+    // last nonce handed out by `tx_builder()` for each signer account
+    pub(crate) tx_nonces: HashMap<AccountId, Nonce>,
+    // indices of the clients `tamper_store()` may be used on
+    pub(crate) tamperable_clients: HashSet<usize>,
+}
+
+/// A way of damaging the store of a client, see [`TestEnv::tamper_store`].  Deleting needs
+/// the `test_features` feature, which lets the chain store drop its cached copies.
+#[derive(Debug, Clone)]
+pub enum TamperOp {
+    /// Deletes the block with the given hash, leaving its header in place.
+    #[cfg(feature = "test_features")]
+    DeleteBlock(CryptoHash),
+    /// Deletes both the chunk with the given hash and the client's parts of it.
+    #[cfg(feature = "test_features")]
+    DeleteChunk(ChunkHash),
+    /// Overwrites with garbage the deepest trie node visited when seeking to the given key
+    /// prefix in the trie with the given state root.
+    CorruptTrieNode(StateRoot, Vec<u8>),
+}
+
+/// How the orphan pool of a client was used while [`TestEnv::deliver_blocks_shuffled`] fed
+/// blocks to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShuffledDelivery {
+    /// Hashes of the blocks in the order they were delivered.
+    pub order: Vec<CryptoHash>,
+    /// Number of blocks which went to the orphan pool when delivered, because their
+    /// parent hadn't been delivered yet.
+    pub orphaned: usize,
+    /// Most orphans in the pool at once.
+    pub max_orphans: usize,
+    /// Number of orphans evicted from the pool during the delivery.
+    pub orphans_evicted: usize,
 }
 
 impl TestEnv {
@@ -61,12 +126,65 @@ impl TestEnv {
         TestEnvBuilder::new(chain_genesis)
     }
 
+    /// The seed this environment was built with, see [`TestEnvBuilder::seed`].
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The RNG seeded with [`Self::seed`].  The environment draws from it
+    /// too, to shuffle the messages and blocks it delivers and to decide which
+    /// messages it drops, so everything random in a run follows from the seed.
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
     /// Process a given block in the client with index `id`.
     /// Simulate the block processing logic in `Client`, i.e, it would run catchup and then process accepted blocks and possibly produce chunks.
     pub fn process_block(&mut self, id: usize, block: Block, provenance: Provenance) {
         self.clients[id].process_block_test(MaybeValidated::from(block), provenance).unwrap();
     }
 
+    /// Delivers `blocks`, already produced by another client, to the client with index
+    /// `client_idx` in an order shuffled with [`Self::rng`], so that children may arrive
+    /// before their parents and have to wait in the orphan pool, like they do on a real
+    /// network.  Once all are delivered, asserts that the client's head is the highest of
+    /// `blocks`.
+    pub fn deliver_blocks_shuffled(
+        &mut self,
+        client_idx: usize,
+        mut blocks: Vec<Block>,
+    ) -> ShuffledDelivery {
+        let expected_tip =
+            blocks.iter().max_by_key(|block| block.header().height()).map(|block| *block.hash());
+        blocks.shuffle(&mut self.rng);
+        let client = &mut self.clients[client_idx];
+        let evicted_before = client.chain.orphans_evicted_len();
+        let mut delivery = ShuffledDelivery::default();
+        for block in blocks {
+            let hash = *block.hash();
+            delivery.order.push(hash);
+            match client.process_block_test(block.into(), Provenance::NONE) {
+                Ok(_) => {}
+                Err(unc_chain::Error::Orphan) => delivery.orphaned += 1,
+                Err(err) => panic!("failed to process block {}: {:?}", hash, err),
+            }
+            // Orphans whose parent just got accepted are processed in the background.
+            client.finish_blocks_in_processing();
+            delivery.max_orphans = delivery.max_orphans.max(client.chain.orphans_len());
+        }
+        delivery.orphans_evicted = client.chain.orphans_evicted_len() - evicted_before;
+        if let Some(expected_tip) = expected_tip {
+            assert_eq!(
+                client.chain.head().unwrap().last_block_hash,
+                expected_tip,
+                "client {} didn't reach the tip with blocks delivered in order {:?}",
+                client_idx,
+                delivery.order
+            );
+        }
+        delivery
+    }
+
     /// Produces block by given client, which may kick off chunk production.
     /// This means that transactions added before this call will be included in the next block produced by this validator.
     pub fn produce_block(&mut self, id: usize, height: BlockHeight) {
@@ -118,6 +236,28 @@ impl TestEnv {
         self.account_indices.lookup(&self.shards_manager_adapters, account)
     }
 
+    /// Returns the log of the shards manager traffic of the client at given
+    /// index.  Panics unless the environment was built with
+    /// [`TestEnvBuilder::record_shards_manager_requests`].
+    pub fn shards_manager_log(&self, client_idx: usize) -> &ShardsManagerLog {
+        self.shards_manager_logs[client_idx]
+            .as_ref()
+            .expect("TestEnv was built without record_shards_manager_requests")
+    }
+
+    /// Makes the shards manager of the client at given index resend requests
+    /// for chunks it's still missing, the same as it periodically does when
+    /// running in an actor.  Requests are only resent once the retry period
+    /// has passed since they were last sent.
+    pub fn resend_chunk_requests(&mut self, client_idx: usize) {
+        self.shards_manager_resend_senders[client_idx].send(ShardsManagerResendChunkRequests);
+    }
+
+    /// Delivers the chunk parts and forwards the clients sent each other, until
+    /// none are left.  Once [`TestEnvBuilder::seed`] is set, the messages sent
+    /// by each client are delivered in an order shuffled with [`Self::rng`],
+    /// which also decides the ones lost with
+    /// [`TestEnvBuilder::drop_chunk_messages`].
     pub fn process_partial_encoded_chunks(&mut self) {
         let network_adapters = self.network_adapters.clone();
 
@@ -130,6 +270,7 @@ impl TestEnv {
                 let _span =
                     tracing::debug_span!(target: "test", "process_partial_encoded_chunks", client=i).entered();
 
+                let mut messages = vec![];
                 keep_going |= network_adapter.handle_filtered(|request| match request {
                     PeerManagerMessageRequest::NetworkRequests(
                         NetworkRequests::PartialEncodedChunkMessage {
@@ -142,7 +283,7 @@ impl TestEnv {
                         let message = ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunk(
                             partial_encoded_chunk,
                         );
-                        self.shards_manager(&account_id).send(message);
+                        messages.push((account_id, message));
                         None
                     }
                     PeerManagerMessageRequest::NetworkRequests(
@@ -152,11 +293,22 @@ impl TestEnv {
                             ShardsManagerRequestFromNetwork::ProcessPartialEncodedChunkForward(
                                 forward,
                             );
-                        self.shards_manager(&account_id).send(message);
+                        messages.push((account_id, message));
                         None
                     }
                     _ => Some(request),
                 });
+                if self.shuffle_chunk_messages {
+                    messages.shuffle(&mut self.rng);
+                }
+                for (account_id, message) in messages {
+                    if self.chunk_message_drop_probability > 0.0
+                        && self.rng.gen_bool(self.chunk_message_drop_probability)
+                    {
+                        continue;
+                    }
+                    self.shards_manager(&account_id).send(message);
+                }
             }
         }
     }
@@ -323,6 +475,30 @@ impl TestEnv {
         self.clients[id].process_tx(tx, false, false)
     }
 
+    /// Produces blocks on client 0 until the head is one block past the validity period of
+    /// `tx`, that is `transaction_validity_period + 1` blocks above the block `tx` refers to,
+    /// then checks that client 0 rejects `tx` as expired.  Panics if the head is already
+    /// beyond that height, since then the boundary isn't what's being tested.
+    pub fn submit_expiring_tx(&mut self, tx: SignedTransaction) {
+        let chain = &self.clients[0].chain;
+        let base_height = chain.get_block_header(&tx.transaction.block_hash).unwrap().height();
+        let expired_height = base_height + chain.transaction_validity_period + 1;
+        let head_height = chain.head().unwrap().height;
+        assert!(
+            head_height <= expired_height,
+            "head {} is already past height {}, the first one at which the transaction expires",
+            head_height,
+            expired_height
+        );
+        for height in head_height + 1..=expired_height {
+            self.produce_block(0, height);
+        }
+        assert_eq!(
+            self.clients[0].process_tx(tx, false, false),
+            ProcessTxResponse::InvalidTx(InvalidTxError::Expired)
+        );
+    }
+
     /// This function will actually bump to the latest protocol version instead of the provided one.
     /// See https://github.com/utnet-org/utility/issues/8590 for details.
     pub fn upgrade_protocol(&mut self, protocol_version: ProtocolVersion) {
@@ -339,7 +515,7 @@ impl TestEnv {
         let mut block = self.clients[0].produce_block(tip.height + 1).unwrap().unwrap();
         eprintln!("Producing block with version {protocol_version}");
         block.mut_header().set_latest_protocol_version(protocol_version);
-        block.mut_header().resign(&create_test_signer(block_producer.as_str()));
+        block.mut_header().resign(&self.validator_signer_of(&block_producer));
 
         let _ = self.clients[0]
             .process_block_test_no_produce_chunk(block.into(), Provenance::NONE)
@@ -350,15 +526,37 @@ impl TestEnv {
         }
     }
 
-    pub fn query_account(&mut self, account_id: AccountId) -> AccountView {
-        let client = &self.clients[0];
+    /// Index of the client view queries go to: the first observer client if there is one,
+    /// otherwise client 0.
+    fn view_client_idx(&self) -> usize {
+        self.observers.first().map_or(0, |observer| self.account_indices.index(observer))
+    }
+
+    /// Whether the client at given index is an observer, see
+    /// [`TestEnvBuilder::observer_clients`].
+    pub fn is_observer(&self, idx: usize) -> bool {
+        self.observers.contains(self.get_client_id(idx))
+    }
+
+    /// Runs a view query against the state of `account_id`'s shard as of the head of the
+    /// client picked by `view_client_idx`.
+    fn query_view(&self, account_id: &AccountId, request: &QueryRequest) -> QueryResponseKind {
+        self.try_query_view(account_id, request).unwrap()
+    }
+
+    fn try_query_view(
+        &self,
+        account_id: &AccountId,
+        request: &QueryRequest,
+    ) -> Result<QueryResponseKind, QueryError> {
+        let client = &self.clients[self.view_client_idx()];
         let head = client.chain.head().unwrap();
         let last_block = client.chain.get_block(&head.last_block_hash).unwrap();
         let shard_id =
-            client.epoch_manager.account_id_to_shard_id(&account_id, &head.epoch_id).unwrap();
+            client.epoch_manager.account_id_to_shard_id(account_id, &head.epoch_id).unwrap();
         let shard_uid = client.epoch_manager.shard_id_to_uid(shard_id, &head.epoch_id).unwrap();
         let last_chunk_header = &last_block.chunks()[shard_id as usize];
-        let response = client
+        client
             .runtime_adapter
             .query(
                 shard_uid,
@@ -368,41 +566,39 @@ impl TestEnv {
                 last_block.header().prev_hash(),
                 last_block.header().hash(),
                 last_block.header().epoch_id(),
-                &QueryRequest::ViewAccount { account_id },
+                request,
             )
-            .unwrap();
-        match response.kind {
+            .map(|response| response.kind)
+    }
+
+    pub fn query_account(&mut self, account_id: AccountId) -> AccountView {
+        let request = QueryRequest::ViewAccount { account_id: account_id.clone() };
+        match self.query_view(&account_id, &request) {
             QueryResponseKind::ViewAccount(account_view) => account_view,
             _ => panic!("Wrong return value"),
         }
     }
 
+    pub fn query_access_key(
+        &mut self,
+        account_id: AccountId,
+        public_key: PublicKey,
+    ) -> AccessKeyView {
+        let request = QueryRequest::ViewAccessKey { account_id: account_id.clone(), public_key };
+        match self.query_view(&account_id, &request) {
+            QueryResponseKind::AccessKey(access_key_view) => access_key_view,
+            _ => panic!("Wrong return value"),
+        }
+    }
+
     pub fn query_state(&mut self, account_id: AccountId) -> Vec<StateItem> {
-        let client = &self.clients[0];
-        let head = client.chain.head().unwrap();
-        let last_block = client.chain.get_block(&head.last_block_hash).unwrap();
-        let shard_id =
-            client.epoch_manager.account_id_to_shard_id(&account_id, &head.epoch_id).unwrap();
-        let shard_uid = client.epoch_manager.shard_id_to_uid(shard_id, &head.epoch_id).unwrap();
-        let last_chunk_header = &last_block.chunks()[shard_id as usize];
-        let response = client
-            .runtime_adapter
-            .query(
-                shard_uid,
-                &last_chunk_header.prev_state_root(),
-                last_block.header().height(),
-                last_block.header().raw_timestamp(),
-                last_block.header().prev_hash(),
-                last_block.header().hash(),
-                last_block.header().epoch_id(),
-                &QueryRequest::ViewState {
-                    account_id,
-                    prefix: vec![].into(),
-                    include_proof: false,
-                },
-            )
-            .unwrap();
-        match response.kind {
+        let request = QueryRequest::ViewState {
+            account_id: account_id.clone(),
+            prefix: vec![].into(),
+            include_proof: false,
+            state_size_limit_override: None,
+        };
+        match self.query_view(&account_id, &request) {
             QueryResponseKind::ViewState(view_state_result) => view_state_result.values,
             _ => panic!("Wrong return value"),
         }
@@ -412,6 +608,17 @@ impl TestEnv {
         self.query_account(account_id).amount
     }
 
+    /// Like [`Self::query_balance`], but returns None if the account doesn't exist.
+    pub fn query_balance_if_exists(&mut self, account_id: AccountId) -> Option<Balance> {
+        let request = QueryRequest::ViewAccount { account_id: account_id.clone() };
+        match self.try_query_view(&account_id, &request) {
+            Ok(QueryResponseKind::ViewAccount(account_view)) => Some(account_view.amount),
+            Ok(_) => panic!("Wrong return value"),
+            Err(QueryError::UnknownAccount { .. }) => None,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
     /// Restarts client at given index. Note that the new client reuses runtime
     /// adapter of old client.
     /// TODO (#8269): create new `KeyValueRuntime` for new client. Currently it
@@ -426,9 +633,12 @@ impl TestEnv {
         };
         let vs = ValidatorSchedule::new().block_producers_per_epoch(vec![self.validators.clone()]);
         let num_validator_seats = vs.all_block_producers().count() as NumSeats;
+        let validator_signer = (!self.is_observer(idx)).then(|| {
+            Arc::new(self.validator_signer_of(&account_id)) as Arc<dyn ValidatorSigner>
+        });
         self.clients[idx] = setup_client_with_runtime(
             num_validator_seats,
-            Some(self.get_client_id(idx).clone()),
+            validator_signer,
             false,
             self.network_adapters[idx].clone().into(),
             self.shards_manager_adapters[idx].clone(),
@@ -440,19 +650,256 @@ impl TestEnv {
             self.archive,
             self.save_trie_changes,
             None,
+            &self.client_feature_overrides[idx],
         )
     }
 
+    /// Damages the store of the client at given index, to test how it copes with data going
+    /// missing or getting corrupted.  Cached copies of deleted blocks and chunks are dropped
+    /// as well.  Corrupted trie nodes are only replaced in the store though, so reads served
+    /// from the shard cache or from flat storage won't notice them.
+    ///
+    /// Panics unless the client was allowed to be tampered with by
+    /// [`TestEnvBuilder::tamperable_clients`], so that a test can't break the wrong client
+    /// by accident.
+    pub fn tamper_store(&mut self, client_idx: usize, op: TamperOp) {
+        assert!(
+            self.tamperable_clients.contains(&client_idx),
+            "client {} isn't allowed to be tampered with, see TestEnvBuilder::tamperable_clients",
+            client_idx
+        );
+        let client = &self.clients[client_idx];
+        let chain_store = client.chain.chain_store();
+        match op {
+            #[cfg(feature = "test_features")]
+            TamperOp::DeleteBlock(block_hash) => {
+                chain_store.delete_for_test(DBCol::Block, block_hash.as_ref()).unwrap();
+            }
+            #[cfg(feature = "test_features")]
+            TamperOp::DeleteChunk(chunk_hash) => {
+                chain_store.delete_for_test(DBCol::Chunks, chunk_hash.as_ref()).unwrap();
+                chain_store.delete_for_test(DBCol::PartialChunks, chunk_hash.as_ref()).unwrap();
+            }
+            TamperOp::CorruptTrieNode(state_root, key_prefix) => {
+                let store = chain_store.store();
+                let state_key = TrieCachingStorage::get_key_from_shard_uid_and_hash;
+                let epoch_id = client.chain.head().unwrap().epoch_id;
+                let shard_layout = client.epoch_manager.get_shard_layout(&epoch_id).unwrap();
+                let shard_uid = shard_layout
+                    .shard_uids()
+                    .find(|shard_uid| {
+                        store.exists(DBCol::State, &state_key(*shard_uid, &state_root)).unwrap()
+                    })
+                    .unwrap_or_else(|| panic!("state root {} not found in any shard", state_root));
+                let trie =
+                    client.runtime_adapter.get_tries().get_trie_for_shard(shard_uid, state_root);
+                let mut iter = trie.iter().unwrap();
+                iter.remember_visited_nodes(true);
+                iter.seek_prefix(&key_prefix).unwrap();
+                let node_hash =
+                    iter.into_visited_nodes().last().map_or(state_root, |node| hash(node));
+                let key = state_key(shard_uid, &node_hash);
+                // State is a reference counted column, whose values end with their refcount.
+                let value = [&b"corrupted trie node"[..], &1i64.to_le_bytes()].concat();
+                let mut store_update = store.store_update();
+                store_update.set_raw_bytes(DBCol::State, &key, &value);
+                store_update.commit().unwrap();
+            }
+        }
+    }
+
+    /// Runs a single garbage collection step on the client at given index, the
+    /// same one that is otherwise triggered after processing a block.
+    pub fn run_gc(&mut self, idx: usize) {
+        self.clients[idx].clear_data().unwrap();
+    }
+
+    /// Reports how far garbage collection got on the client at given index,
+    /// and which of the given blocks can still be fully read from its store.
+    pub fn gc_status(&self, idx: usize, block_hashes: &[CryptoHash]) -> GcStatusView {
+        let chain = &self.clients[idx].chain;
+        let chain_store = chain.chain_store();
+        let tail = chain.tail().unwrap();
+        let blocks = block_hashes
+            .iter()
+            .map(|block_hash| {
+                let header = chain.get_block_header(block_hash).is_ok();
+                let block = chain.get_block(block_hash).ok();
+                let chunks = block.as_ref().map_or(false, |block| {
+                    block.chunks().iter().all(|chunk_header| {
+                        chunk_header.height_included() != block.header().height()
+                            || chain.get_chunk(&chunk_header.chunk_hash()).is_ok()
+                    })
+                });
+                let outcomes = chain_store.get_block_execution_outcomes(block_hash).is_ok();
+                (
+                    *block_hash,
+                    BlockRetention { header, block: block.is_some(), chunks, outcomes },
+                )
+            })
+            .collect();
+        GcStatusView {
+            head_height: chain.head().unwrap().height,
+            tail,
+            chunk_tail: chain_store.chunk_tail().unwrap(),
+            fork_tail: chain_store.fork_tail().unwrap(),
+            num_cleared_heights: tail - chain.genesis().height(),
+            blocks,
+        }
+    }
+
+    /// Checks that the total supply in the headers of the canonical chain of the client at
+    /// given index only changes by the amount minted at the start of each epoch and the
+    /// balance burnt by the chunks included in each block, and that the balance each chunk
+    /// reports as burnt matches the tokens burnt by the execution outcomes stored for it.
+    /// Garbage collected blocks are skipped. Returns a description of the first divergence.
+    pub fn check_supply_invariant(&self, client_idx: usize) -> Result<(), String> {
+        let client = &self.clients[client_idx];
+        let chain = &client.chain;
+        let chain_store = chain.chain_store();
+        let epoch_manager = client.epoch_manager.as_ref();
+        let genesis = chain.genesis();
+        if genesis.total_supply() != self.chain_genesis.total_supply {
+            return Err(format!(
+                "genesis total supply is {}, expected {}",
+                genesis.total_supply(),
+                self.chain_genesis.total_supply
+            ));
+        }
+
+        let tail = chain.tail().unwrap();
+        let mut blocks = vec![];
+        let mut block_hash = chain.head().unwrap().last_block_hash;
+        while let Ok(block) = chain.get_block(&block_hash) {
+            if block.header().height() <= std::cmp::max(tail, genesis.height()) {
+                break;
+            }
+            block_hash = *block.header().prev_hash();
+            blocks.push(block);
+        }
+
+        for block in blocks.iter().rev() {
+            let height = block.header().height();
+            let prev_hash = block.header().prev_hash();
+            let prev = chain.get_block_header(prev_hash).unwrap();
+            let minted = if epoch_manager.is_next_block_epoch_start(prev_hash).unwrap() {
+                epoch_manager.get_epoch_minted_amount(block.header().next_epoch_id()).unwrap()
+            } else {
+                0
+            };
+            let mut burnt = 0;
+            for chunk in block.chunks().iter() {
+                if chunk.height_included() != height {
+                    continue;
+                }
+                let shard_id = chunk.shard_id();
+                let shard_uid = epoch_manager.shard_id_to_uid(shard_id, prev.epoch_id()).unwrap();
+                // without the chunk extra the client didn't apply the previous chunk of this
+                // shard, so there are no outcomes to compare against
+                if chain_store.get_chunk_extra(prev_hash, &shard_uid).is_ok() {
+                    let tokens_burnt = chain_store
+                        .get_tokens_burnt_by_block_hash_and_shard_id(prev_hash, shard_id)
+                        .unwrap();
+                    if chunk.prev_balance_burnt() != tokens_burnt {
+                        return Err(format!(
+                            "at height {} the chunk of shard {} reports balance burnt {}, but \
+                            its execution outcomes burnt {}",
+                            height,
+                            shard_id,
+                            chunk.prev_balance_burnt(),
+                            tokens_burnt
+                        ));
+                    }
+                }
+                burnt += chunk.prev_balance_burnt();
+            }
+            let expected = prev.total_supply() + minted - burnt;
+            if block.header().total_supply() != expected {
+                return Err(format!(
+                    "at height {} the total supply is {}, expected {} (previous {}, minted {}, \
+                    burnt {})",
+                    height,
+                    block.header().total_supply(),
+                    expected,
+                    prev.total_supply(),
+                    minted,
+                    burnt
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Panics if [`Self::check_supply_invariant`] finds a divergence.
+    pub fn assert_supply_invariant(&self, client_idx: usize) {
+        if let Err(err) = self.check_supply_invariant(client_idx) {
+            panic!("total supply invariant violated on client {}: {}", client_idx, err);
+        }
+    }
+
+    fn signer_seed(&self, account_id: &AccountId) -> &str {
+        self.signer_seeds.get(account_id).unwrap_or_else(|| {
+            panic!("{} is neither a client nor a validator of this TestEnv", account_id)
+        })
+    }
+
+    fn validator_signer_of(&self, account_id: &AccountId) -> InMemoryValidatorSigner {
+        let seed = self.signer_seed(account_id);
+        let signer = InMemoryValidatorSigner::from_seed(account_id.clone(), KeyType::ED25519, seed);
+        if let Some(&idx) = self.account_indices.0.get(account_id) {
+            // observer clients have no validator signer to compare with
+            if let Some(client_signer) = &self.clients[idx].validator_signer {
+                assert_eq!(
+                    client_signer.public_key(),
+                    signer.public_key(),
+                    "client {} wasn't built with the key of seed {:?}",
+                    idx,
+                    seed
+                );
+            }
+        }
+        signer
+    }
+
+    /// Returns a validator signer for the validator at given index in
+    /// [`Self::validators`], with the same key as the validator signer of its
+    /// client, if it has one, and as its validator key in genesis.
+    pub fn validator_signer(&self, idx: usize) -> InMemoryValidatorSigner {
+        self.validator_signer_of(&self.validators[idx])
+    }
+
+    /// Returns a signer for transactions of `account_id`, which has to be one
+    /// of the clients or validators, with the full access key its account is
+    /// given in genesis.
+    pub fn client_signer(&self, account_id: &AccountId) -> InMemorySigner {
+        let seed = self.signer_seed(account_id);
+        InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, seed)
+    }
+
     /// Returns an [`AccountId`] used by a client at given index.  More
-    /// specifically, returns validator id of the client’s validator signer.
+    /// specifically, returns validator id of the client’s validator signer, or
+    /// for an observer client the account it was added with.
     pub fn get_client_id(&self, idx: usize) -> &AccountId {
-        self.clients[idx].validator_signer.as_ref().unwrap().validator_id()
+        match &self.clients[idx].validator_signer {
+            Some(signer) => signer.validator_id(),
+            None => {
+                self.account_indices.0.iter().find(|(_, &i)| i == idx).map(|(id, _)| id).unwrap()
+            }
+        }
     }
 
     pub fn get_runtime_config(&self, idx: usize, epoch_id: EpochId) -> RuntimeConfig {
         self.clients[idx].runtime_adapter.get_protocol_config(&epoch_id).unwrap().runtime_config
     }
 
+    /// Returns the shard layout of the epoch of the head of the client at
+    /// given index.
+    pub fn current_shard_layout(&self, client_idx: usize) -> ShardLayout {
+        let client = &self.clients[client_idx];
+        let head = client.chain.head().unwrap();
+        client.epoch_manager.get_shard_layout(&head.epoch_id).unwrap()
+    }
+
     /// Create and sign transaction ready for execution.
     pub fn tx_from_actions(
         &mut self,
@@ -471,6 +918,24 @@ impl TestEnv {
         )
     }
 
+    /// Returns a builder for a transaction signed by `signer_account` with the key
+    /// `InMemorySigner::from_seed` derives from its id. The nonce is the next one after both
+    /// the current nonce of that key and the last one handed out by this method, so several
+    /// transactions can be built one after the other without processing them in between.
+    pub fn tx_builder(&mut self, signer_account: &AccountId) -> TxBuilder {
+        let signer = InMemorySigner::from_seed(
+            signer_account.clone(),
+            KeyType::ED25519,
+            signer_account.as_str(),
+        );
+        let key_nonce = self.query_access_key(signer_account.clone(), signer.public_key()).nonce;
+        let last_nonce = self.tx_nonces.entry(signer_account.clone()).or_default();
+        let nonce = std::cmp::max(key_nonce, *last_nonce) + 1;
+        *last_nonce = nonce;
+        let block_hash = self.clients[0].chain.head().unwrap().last_block_hash;
+        TxBuilder::new(signer, nonce, block_hash)
+    }
+
     /// Wrap actions in a delegate action, put it in a transaction, sign.
     pub fn meta_tx_from_actions(
         &mut self,
@@ -566,6 +1031,41 @@ impl Drop for TestEnv {
     }
 }
 
+/// Garbage collection progress of a client, see [`TestEnv::gc_status`].
+#[derive(Debug)]
+pub struct GcStatusView {
+    pub head_height: BlockHeight,
+    /// Lowest height whose data has not been garbage collected.
+    pub tail: BlockHeight,
+    pub chunk_tail: BlockHeight,
+    pub fork_tail: BlockHeight,
+    /// Number of heights above genesis whose data has been garbage collected.
+    pub num_cleared_heights: u64,
+    /// Which parts of the requested blocks are still in the store.
+    pub blocks: HashMap<CryptoHash, BlockRetention>,
+}
+
+impl GcStatusView {
+    pub fn is_fully_retrievable(&self, block_hash: &CryptoHash) -> bool {
+        self.blocks[block_hash].is_fully_retrievable()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRetention {
+    pub header: bool,
+    pub block: bool,
+    /// All the chunks included in the block.
+    pub chunks: bool,
+    pub outcomes: bool,
+}
+
+impl BlockRetention {
+    pub fn is_fully_retrievable(&self) -> bool {
+        self.header && self.block && self.chunks && self.outcomes
+    }
+}
+
 pub(crate) struct AccountIndices(pub(crate) HashMap<AccountId, usize>);
 
 impl AccountIndices {
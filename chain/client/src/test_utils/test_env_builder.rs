@@ -1,25 +1,39 @@
-use super::setup::{setup_client_with_runtime, setup_synchronous_shards_manager};
+use super::multi_run::MultiRun;
+use super::setup::{
+    setup_client_with_runtime, setup_synchronous_shards_manager_adapter, ClientFeatureOverrides,
+    ShardsManagerAdapterForTest,
+};
+use super::shards_manager_log::ShardsManagerLog;
 use super::test_env::TestEnv;
 use super::{AccountIndices, TEST_SEED};
 use actix_rt::System;
 use itertools::{multizip, Itertools};
 use unc_async::messaging::IntoSender;
+use unc_async::time;
 use unc_chain::state_snapshot_actor::SnapshotCallbacks;
 use unc_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
 use unc_chain::types::RuntimeAdapter;
 use unc_chain::ChainGenesis;
 use unc_chain_configs::GenesisConfig;
 use unc_chunks::test_utils::MockClientAdapterForShardsManager;
+use unc_crypto::KeyType;
 use unc_epoch_manager::shard_tracker::ShardTracker;
 use unc_epoch_manager::{EpochManager, EpochManagerAdapter, EpochManagerHandle};
 use unc_network::test_utils::MockPeerManagerAdapter;
+use unc_network::types::PeerManagerAdapter;
 use unc_parameters::RuntimeConfigStore;
 use unc_primitives::epoch_manager::{AllEpochConfigTestOverrides, RngSeed};
-use unc_primitives::types::{AccountId, NumShards};
+use unc_primitives::hash::hash;
+use unc_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use unc_primitives::types::{AccountId, NumBlocks, NumShards, ProtocolVersion};
+use unc_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use unc_store::config::StateSnapshotType;
 use unc_store::test_utils::create_test_store;
 use unc_store::{NodeStorage, ShardUId, Store, StoreConfig, TrieConfig};
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+use std::panic::Location;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -38,10 +52,133 @@ impl EpochManagerKind {
     }
 }
 
+/// The parts of a [`TestEnvBuilder`] setup.  Setting some of them makes the
+/// builder initialize the ones they depend on with defaults, so they have to be
+/// set in order: clients before stores, stores before epoch managers, epoch
+/// managers before shard trackers and runtimes, and resharding before epoch
+/// managers and runtimes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BuilderPhase {
+    Clients,
+    Validators,
+    NumShards,
+    Stores,
+    EpochManagers,
+    ShardTrackers,
+    Runtimes,
+    NetworkAdapters,
+    StateSnapshots,
+    ClientFeatures,
+    Resharding,
+}
+
+impl BuilderPhase {
+    pub const ALL: [BuilderPhase; 11] = [
+        Self::Clients,
+        Self::Validators,
+        Self::NumShards,
+        Self::Stores,
+        Self::EpochManagers,
+        Self::ShardTrackers,
+        Self::Runtimes,
+        Self::NetworkAdapters,
+        Self::StateSnapshots,
+        Self::ClientFeatures,
+        Self::Resharding,
+    ];
+
+    /// Phases which must not have been initialized yet when this one is set.
+    fn must_precede(self) -> &'static [BuilderPhase] {
+        match self {
+            Self::Clients => &[
+                Self::Stores,
+                Self::EpochManagers,
+                Self::ShardTrackers,
+                Self::Runtimes,
+                Self::NetworkAdapters,
+                Self::ClientFeatures,
+            ],
+            Self::Validators | Self::NumShards => &[Self::EpochManagers],
+            Self::Stores => &[Self::EpochManagers, Self::Runtimes],
+            Self::EpochManagers => &[Self::ShardTrackers, Self::Runtimes],
+            Self::StateSnapshots => &[Self::Runtimes],
+            Self::Resharding => &[Self::EpochManagers, Self::Runtimes],
+            Self::ShardTrackers
+            | Self::Runtimes
+            | Self::NetworkAdapters
+            | Self::ClientFeatures => &[],
+        }
+    }
+
+    /// Phases which can't be set explicitly together with this one.
+    fn conflicts_with(self) -> &'static [BuilderPhase] {
+        match self {
+            Self::EpochManagers => &[Self::NumShards],
+            _ => &[],
+        }
+    }
+
+    /// Whether setting this phase a second time is an error.
+    fn set_once(self) -> bool {
+        matches!(
+            self,
+            Self::Stores
+                | Self::EpochManagers
+                | Self::ShardTrackers
+                | Self::Runtimes
+                | Self::ClientFeatures
+                | Self::Resharding
+        )
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Clients => "clients",
+            Self::Validators => "validators",
+            Self::NumShards => "num_shards",
+            Self::Stores => "stores",
+            Self::EpochManagers => "epoch_managers",
+            Self::ShardTrackers => "shard_trackers",
+            Self::Runtimes => "runtimes",
+            Self::NetworkAdapters => "network_adapters",
+            Self::StateSnapshots => "state_snapshots",
+            Self::ClientFeatures => "client_feature_overrides",
+            Self::Resharding => "resharding",
+        }
+    }
+}
+
+impl std::fmt::Display for BuilderPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A mistake in the setup of a [`TestEnvBuilder`], returned by
+/// [`TestEnvBuilder::try_build`].  Locations point at the setter call, or at
+/// the call which made the builder initialize the phase with its default.
+#[derive(thiserror::Error, Clone, Debug)]
+pub enum TestEnvBuilderError {
+    #[error("{phase} must be set before {later}; {later} already initialized at {at}")]
+    OutOfOrder { phase: BuilderPhase, later: BuilderPhase, at: &'static Location<'static> },
+    #[error("{phase} already initialized at {at}")]
+    AlreadyInitialized { phase: BuilderPhase, at: &'static Location<'static> },
+    #[error("cannot set both {phase} and {other}; {other} already set at {at}")]
+    Conflict { phase: BuilderPhase, other: BuilderPhase, at: &'static Location<'static> },
+    #[error("{phase} must have one element per client; got {got} for {expected} clients")]
+    WrongCount { phase: BuilderPhase, expected: usize, got: usize },
+    #[error("{phase} must not be empty")]
+    Empty { phase: BuilderPhase },
+    #[error("resharding scheduled at {at}: {reason}")]
+    InvalidResharding { reason: String, at: &'static Location<'static> },
+}
+
 /// A builder for the TestEnv structure.
 pub struct TestEnvBuilder {
     chain_genesis: ChainGenesis,
     clients: Vec<AccountId>,
+    // the clients added with `observer_clients`, which are the last ones in `clients`
+    observers: Vec<AccountId>,
     validators: Vec<AccountId>,
     home_dirs: Option<Vec<PathBuf>>,
     stores: Option<Vec<Store>>,
@@ -49,13 +186,28 @@ pub struct TestEnvBuilder {
     shard_trackers: Option<Vec<ShardTracker>>,
     runtimes: Option<Vec<Arc<dyn RuntimeAdapter>>>,
     network_adapters: Option<Vec<Arc<MockPeerManagerAdapter>>>,
+    client_feature_overrides: Option<Vec<ClientFeatureOverrides>>,
     num_shards: Option<NumShards>,
     // random seed to be inject in each client according to AccountId
     // if not set, a default constant TEST_SEED will be injected
     seeds: HashMap<AccountId, RngSeed>,
+    // seed of the whole environment, see `seed`
+    seed: Option<u64>,
     archive: bool,
     save_trie_changes: bool,
     state_snapshot_enabled: bool,
+    record_shards_manager_requests: bool,
+    shards_manager_clock: time::Clock,
+    chunk_message_drop_probability: f64,
+    chunk_producer_reliability_threshold: Option<f64>,
+    tamperable_clients: HashSet<usize>,
+    // the shard layout to switch to and the protocol version it comes with, see
+    // `schedule_resharding`. Taken when the epoch managers are built
+    resharding: Option<(ProtocolVersion, ShardLayout)>,
+    // where each phase was initialized, see `validate`
+    initialized: HashMap<BuilderPhase, &'static Location<'static>>,
+    // the first setup mistake, reported by `try_build`
+    error: Option<TestEnvBuilderError>,
 }
 
 /// Builder for the [`TestEnv`] structure.
@@ -71,6 +223,7 @@ impl TestEnvBuilder {
         Self {
             chain_genesis,
             clients,
+            observers: Vec::new(),
             validators,
             home_dirs: None,
             stores: None,
@@ -78,24 +231,96 @@ impl TestEnvBuilder {
             shard_trackers: None,
             runtimes: None,
             network_adapters: None,
+            client_feature_overrides: None,
             num_shards: None,
             seeds,
+            seed: None,
             archive: false,
             save_trie_changes: true,
             state_snapshot_enabled: false,
+            record_shards_manager_requests: false,
+            shards_manager_clock: time::Clock::real(),
+            chunk_message_drop_probability: 0.0,
+            chunk_producer_reliability_threshold: None,
+            tamperable_clients: HashSet::new(),
+            resharding: None,
+            initialized: HashMap::new(),
+            error: None,
+        }
+    }
+
+    /// Checks that `phase` can be set now, given `len` elements (one per client
+    /// for the per-client phases).  `explicit` is false when the builder fills
+    /// in a default, which doesn't conflict with anything.
+    ///
+    /// Every setter consults this before changing anything.  On success the
+    /// phase is recorded as initialized at the caller's location.  Otherwise
+    /// the first error is kept for [`Self::try_build`], and this and all later
+    /// setters leave the builder unchanged.
+    #[track_caller]
+    fn validate(&mut self, phase: BuilderPhase, len: Option<usize>, explicit: bool) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        let error = if let Some(&later) =
+            phase.must_precede().iter().find(|later| self.initialized.contains_key(later))
+        {
+            Some(TestEnvBuilderError::OutOfOrder { phase, later, at: self.initialized[&later] })
+        } else if let Some(&at) = self.initialized.get(&phase).filter(|_| phase.set_once()) {
+            Some(TestEnvBuilderError::AlreadyInitialized { phase, at })
+        } else if let Some(&other) = phase
+            .conflicts_with()
+            .iter()
+            .find(|other| explicit && self.initialized.contains_key(other))
+        {
+            Some(TestEnvBuilderError::Conflict { phase, other, at: self.initialized[&other] })
+        } else {
+            let per_client = !matches!(phase, BuilderPhase::Clients | BuilderPhase::Validators);
+            match len {
+                Some(0) if !per_client => Some(TestEnvBuilderError::Empty { phase }),
+                Some(got) if per_client && got != self.clients.len() => {
+                    Some(TestEnvBuilderError::WrongCount {
+                        phase,
+                        expected: self.clients.len(),
+                        got,
+                    })
+                }
+                _ => None,
+            }
+        };
+        if let Some(error) = error {
+            self.error = Some(error);
+            return false;
         }
+        self.initialized.insert(phase, Location::caller());
+        true
     }
 
-    /// Sets list of client [`AccountId`]s to the one provided.  Panics if the
-    /// vector is empty.
+    /// Sets list of client [`AccountId`]s to the one provided.  Fails the
+    /// build if the vector is empty.  Clients added with
+    /// [`Self::observer_clients`] stay after them.
+    #[track_caller]
     pub fn clients(mut self, clients: Vec<AccountId>) -> Self {
-        assert!(!clients.is_empty());
-        assert!(self.stores.is_none(), "Cannot set clients after stores");
-        assert!(self.epoch_managers.is_none(), "Cannot set clients after epoch_managers");
-        assert!(self.shard_trackers.is_none(), "Cannot set clients after shard_trackers");
-        assert!(self.runtimes.is_none(), "Cannot set clients after runtimes");
-        assert!(self.network_adapters.is_none(), "Cannot set clients after network_adapters");
-        self.clients = clients;
+        if self.validate(BuilderPhase::Clients, Some(clients.len()), true) {
+            self.clients = clients.into_iter().chain(self.observers.iter().cloned()).collect();
+        }
+        self
+    }
+
+    /// Adds clients which track shards and answer queries but never sign
+    /// anything, like a public RPC node.  They're built without a validator
+    /// signer, whether or not they're among the validators, and come after the
+    /// other clients, in the given order.  They're still connected to the
+    /// other clients and can be found with [`TestEnv::get_client_id`] like
+    /// them.  The view helpers of [`TestEnv`] such as
+    /// [`TestEnv::query_account`] query the first observer when there is one.
+    #[track_caller]
+    pub fn observer_clients(mut self, observers: Vec<AccountId>) -> Self {
+        let len = self.clients.len() + observers.len();
+        if self.validate(BuilderPhase::Clients, Some(len), true) {
+            self.clients.extend(observers.iter().cloned());
+            self.observers.extend(observers);
+        }
         self
     }
 
@@ -105,10 +330,29 @@ impl TestEnvBuilder {
         self
     }
 
+    /// Seeds the randomness of the whole environment with `seed`: clients
+    /// without a seed from [`Self::clients_random_seeds`] get one derived from
+    /// it and their account, and [`TestEnv::rng`] is seeded with it.  The
+    /// environment shuffles the chunk messages and blocks it delivers with that
+    /// RNG, and decides with it which messages [`Self::drop_chunk_messages`]
+    /// loses, so a run can be replayed from its seed.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Returns a harness running a scenario on a fresh environment from
+    /// `make_builder` for each of a range of seeds; see [`MultiRun`].
+    pub fn multi_run<F: Fn() -> TestEnvBuilder>(make_builder: F) -> MultiRun<F> {
+        MultiRun::new(make_builder)
+    }
+
     /// Sets number of clients to given one.  To get [`AccountId`] used by the
     /// validator associated with the client the [`TestEnv::get_client_id`]
     /// method can be used.  Tests should not rely on any particular format of
-    /// account identifiers used by the builder.  Panics if `num` is zero.
+    /// account identifiers used by the builder.  Fails the build if `num` is
+    /// zero.
+    #[track_caller]
     pub fn clients_count(self, num: usize) -> Self {
         self.clients(Self::make_accounts(num))
     }
@@ -117,19 +361,22 @@ impl TestEnvBuilder {
         self.clients.len()
     }
 
-    /// Sets list of validator [`AccountId`]s to the one provided.  Panics if
-    /// the vector is empty.
+    /// Sets list of validator [`AccountId`]s to the one provided.  Fails the
+    /// build if the vector is empty.
+    #[track_caller]
     pub fn validators(mut self, validators: Vec<AccountId>) -> Self {
-        assert!(!validators.is_empty());
-        assert!(self.epoch_managers.is_none(), "Cannot set validators after epoch_managers");
-        self.validators = validators;
+        if self.validate(BuilderPhase::Validators, Some(validators.len()), true) {
+            self.validators = validators;
+        }
         self
     }
 
     /// Sets number of validator seats to given one.  To get [`AccountId`] used
     /// in the test environment the `validators` field of the built [`TestEnv`]
     /// object can be used.  Tests should not rely on any particular format of
-    /// account identifiers used by the builder.  Panics if `num` is zero.
+    /// account identifiers used by the builder.  Fails the build if `num` is
+    /// zero.
+    #[track_caller]
     pub fn validator_seats(self, num: usize) -> Self {
         self.validators(Self::make_accounts(num))
     }
@@ -148,16 +395,19 @@ impl TestEnvBuilder {
     }
 
     /// Overrides the stores that are used to create epoch managers and runtimes.
+    #[track_caller]
     pub fn stores(mut self, stores: Vec<Store>) -> Self {
-        assert_eq!(stores.len(), self.clients.len());
-        assert!(self.stores.is_none(), "Cannot override twice");
-        assert!(self.epoch_managers.is_none(), "Cannot override store after epoch_managers");
-        assert!(self.runtimes.is_none(), "Cannot override store after runtimes");
-        self.stores = Some(stores);
+        if self.validate(BuilderPhase::Stores, Some(stores.len()), true) {
+            self.stores = Some(stores);
+        }
         self
     }
 
+    #[track_caller]
     pub fn real_stores(self) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
         let ret = self.ensure_home_dirs();
         let stores = ret
             .home_dirs
@@ -181,8 +431,9 @@ impl TestEnvBuilder {
     }
 
     /// Internal impl to make sure the stores are initialized.
+    #[track_caller]
     fn ensure_stores(self) -> Self {
-        if self.stores.is_some() {
+        if self.stores.is_some() || self.error.is_some() {
             self
         } else {
             let num_clients = self.clients.len();
@@ -196,20 +447,13 @@ impl TestEnvBuilder {
     /// The vector must have the same number of elements as they are clients
     /// (one by default).  If that does not hold, [`Self::build`] method will
     /// panic.
+    #[track_caller]
     pub fn mock_epoch_managers(mut self, epoch_managers: Vec<Arc<MockEpochManager>>) -> Self {
-        assert_eq!(epoch_managers.len(), self.clients.len());
-        assert!(self.epoch_managers.is_none(), "Cannot override twice");
-        assert!(
-            self.num_shards.is_none(),
-            "Cannot set both num_shards and epoch_managers at the same time"
-        );
-        assert!(
-            self.shard_trackers.is_none(),
-            "Cannot override epoch_managers after shard_trackers"
-        );
-        assert!(self.runtimes.is_none(), "Cannot override epoch_managers after runtimes");
-        self.epoch_managers =
-            Some(epoch_managers.into_iter().map(|epoch_manager| epoch_manager.into()).collect());
+        if self.validate(BuilderPhase::EpochManagers, Some(epoch_managers.len()), true) {
+            self.epoch_managers = Some(
+                epoch_managers.into_iter().map(|epoch_manager| epoch_manager.into()).collect(),
+            );
+        }
         self
     }
 
@@ -219,38 +463,42 @@ impl TestEnvBuilder {
     /// The vector must have the same number of elements as they are clients
     /// (one by default).  If that does not hold, [`Self::build`] method will
     /// panic.
+    #[track_caller]
     pub fn epoch_managers(mut self, epoch_managers: Vec<Arc<EpochManagerHandle>>) -> Self {
-        assert_eq!(epoch_managers.len(), self.clients.len());
-        assert!(self.epoch_managers.is_none(), "Cannot override twice");
-        assert!(
-            self.num_shards.is_none(),
-            "Cannot set both num_shards and epoch_managers at the same time"
-        );
-        assert!(
-            self.shard_trackers.is_none(),
-            "Cannot override epoch_managers after shard_trackers"
-        );
-        assert!(self.runtimes.is_none(), "Cannot override epoch_managers after runtimes");
-        self.epoch_managers =
-            Some(epoch_managers.into_iter().map(|epoch_manager| epoch_manager.into()).collect());
+        if self.validate(BuilderPhase::EpochManagers, Some(epoch_managers.len()), true) {
+            self.epoch_managers = Some(
+                epoch_managers.into_iter().map(|epoch_manager| epoch_manager.into()).collect(),
+            );
+        }
         self
     }
 
+    #[track_caller]
     pub fn real_epoch_managers(self, genesis_config: &GenesisConfig) -> Self {
         self.real_epoch_managers_with_test_overrides(genesis_config, None)
     }
 
     /// Constructs real EpochManager implementations for each instance.
+    #[track_caller]
     pub fn real_epoch_managers_with_test_overrides(
         self,
         genesis_config: &GenesisConfig,
         test_overrides: Option<AllEpochConfigTestOverrides>,
     ) -> Self {
-        assert!(
-            self.num_shards.is_none(),
-            "Cannot set both num_shards and epoch_managers at the same time"
-        );
-        let ret = self.ensure_stores();
+        let mut ret = self.ensure_stores();
+        if ret.error.is_some() {
+            return ret;
+        }
+        let mut test_overrides = test_overrides;
+        if let Some((protocol_version, shard_layout)) = ret.resharding.take() {
+            if let Err(reason) = check_split(&genesis_config.shard_layout, &shard_layout) {
+                let at = ret.initialized[&BuilderPhase::Resharding];
+                ret.error = Some(TestEnvBuilderError::InvalidResharding { reason, at });
+                return ret;
+            }
+            test_overrides.get_or_insert_with(Default::default).resharding =
+                Some((protocol_version, shard_layout));
+        }
         let epoch_managers = (0..ret.clients.len())
             .map(|i| {
                 EpochManager::new_arc_handle_with_test_overrides(
@@ -264,9 +512,13 @@ impl TestEnvBuilder {
     }
 
     /// Internal impl to make sure EpochManagers are initialized.
+    #[track_caller]
     fn ensure_epoch_managers(self) -> Self {
         let mut ret = self.ensure_stores();
-        if ret.epoch_managers.is_some() {
+        if ret.epoch_managers.is_some() || ret.error.is_some() {
+            return ret;
+        }
+        if !ret.validate(BuilderPhase::EpochManagers, Some(ret.clients.len()), false) {
             return ret;
         }
         let epoch_managers: Vec<EpochManagerKind> = (0..ret.clients.len())
@@ -281,19 +533,12 @@ impl TestEnvBuilder {
                 .into()
             })
             .collect();
-        assert!(
-            ret.shard_trackers.is_none(),
-            "Cannot override shard_trackers without overriding epoch_managers"
-        );
-        assert!(
-            ret.runtimes.is_none(),
-            "Cannot override runtimes without overriding epoch_managers"
-        );
         ret.epoch_managers = Some(epoch_managers);
         ret
     }
 
     /// Visible for extension methods in integration-tests.
+    #[track_caller]
     pub fn internal_initialize_nightshade_runtimes(
         self,
         runtime_configs: Vec<RuntimeConfigStore>,
@@ -307,6 +552,9 @@ impl TestEnvBuilder {
         ) -> Arc<dyn RuntimeAdapter>,
     ) -> Self {
         let builder = self.ensure_home_dirs().ensure_epoch_managers().ensure_stores();
+        if builder.error.is_some() {
+            return builder;
+        }
         let runtimes = multizip((
             builder.home_dirs.clone().unwrap(),
             builder.stores.clone().unwrap(),
@@ -329,18 +577,23 @@ impl TestEnvBuilder {
 
     /// Specifies custom ShardTracker for each client.  This allows us to
     /// construct [`TestEnv`] with a custom implementation.
+    #[track_caller]
     pub fn shard_trackers(mut self, shard_trackers: Vec<ShardTracker>) -> Self {
-        assert_eq!(shard_trackers.len(), self.clients.len());
-        assert!(self.shard_trackers.is_none(), "Cannot override twice");
-        self.shard_trackers = Some(shard_trackers);
+        if self.validate(BuilderPhase::ShardTrackers, Some(shard_trackers.len()), true) {
+            self.shard_trackers = Some(shard_trackers);
+        }
         self
     }
 
     /// Constructs ShardTracker that tracks all shards for each instance.
     ///
     /// Note that in order to track *NO* shards, just don't override shard_trackers.
+    #[track_caller]
     pub fn track_all_shards(self) -> Self {
         let ret = self.ensure_epoch_managers();
+        if ret.error.is_some() {
+            return ret;
+        }
         let shard_trackers = ret
             .epoch_managers
             .as_ref()
@@ -354,9 +607,10 @@ impl TestEnvBuilder {
     }
 
     /// Internal impl to make sure ShardTrackers are initialized.
+    #[track_caller]
     fn ensure_shard_trackers(self) -> Self {
         let ret = self.ensure_epoch_managers();
-        if ret.shard_trackers.is_some() {
+        if ret.shard_trackers.is_some() || ret.error.is_some() {
             return ret;
         }
         let shard_trackers = ret
@@ -373,18 +627,20 @@ impl TestEnvBuilder {
 
     /// Specifies custom RuntimeAdapter for each client.  This allows us to
     /// construct [`TestEnv`] with a custom implementation.
+    #[track_caller]
     pub fn runtimes(mut self, runtimes: Vec<Arc<dyn RuntimeAdapter>>) -> Self {
-        assert_eq!(runtimes.len(), self.clients.len());
-        assert!(self.runtimes.is_none(), "Cannot override twice");
-        self.runtimes = Some(runtimes);
+        if self.validate(BuilderPhase::Runtimes, Some(runtimes.len()), true) {
+            self.runtimes = Some(runtimes);
+        }
         self
     }
 
     /// Internal impl to make sure runtimes are initialized.
+    #[track_caller]
     fn ensure_runtimes(self) -> Self {
         let state_snapshot_enabled = self.state_snapshot_enabled;
         let ret = self.ensure_epoch_managers();
-        if ret.runtimes.is_some() {
+        if ret.runtimes.is_some() || ret.error.is_some() {
             return ret;
         }
         assert!(
@@ -411,14 +667,18 @@ impl TestEnvBuilder {
     /// The vector must have the same number of elements as they are clients
     /// (one by default).  If that does not hold, [`Self::build`] method will
     /// panic.
+    #[track_caller]
     pub fn network_adapters(mut self, adapters: Vec<Arc<MockPeerManagerAdapter>>) -> Self {
-        self.network_adapters = Some(adapters);
+        if self.validate(BuilderPhase::NetworkAdapters, Some(adapters.len()), true) {
+            self.network_adapters = Some(adapters);
+        }
         self
     }
 
     /// Internal impl to make sure network adapters are initialized.
+    #[track_caller]
     fn ensure_network_adapters(self) -> Self {
-        if self.network_adapters.is_some() {
+        if self.network_adapters.is_some() || self.error.is_some() {
             self
         } else {
             let num_clients = self.clients.len();
@@ -426,12 +686,20 @@ impl TestEnvBuilder {
         }
     }
 
+    #[track_caller]
     pub fn num_shards(mut self, num_shards: NumShards) -> Self {
-        assert!(
-            self.epoch_managers.is_none(),
-            "Cannot set both num_shards and epoch_managers at the same time"
-        );
-        self.num_shards = Some(num_shards);
+        if self.validate(BuilderPhase::NumShards, None, true) {
+            self.num_shards = Some(num_shards);
+        }
+        self
+    }
+
+    /// Overrides the number of blocks after the block a transaction refers to in which the
+    /// transaction may still be included, for tests of transaction expiry.  The chain genesis
+    /// isn't handed to any component until the environment is built, so this can be called
+    /// at any point before [`Self::build`].
+    pub fn transaction_validity_period(mut self, period: NumBlocks) -> Self {
+        self.chain_genesis.transaction_validity_period = period;
         self
     }
 
@@ -445,51 +713,175 @@ impl TestEnvBuilder {
         self
     }
 
+    /// Overrides client config knobs per client, for tests of networks where not all
+    /// nodes have the same features turned on.  See [`ClientFeatureOverrides`] for
+    /// which features can't be set this way.
+    #[track_caller]
+    pub fn client_feature_overrides(mut self, overrides: Vec<ClientFeatureOverrides>) -> Self {
+        if self.validate(BuilderPhase::ClientFeatures, Some(overrides.len()), true) {
+            self.client_feature_overrides = Some(overrides);
+        }
+        self
+    }
+
+    /// Records the messages passed to and the chunk requests and responses
+    /// sent by the shards manager of each client, so that tests can inspect
+    /// them with [`TestEnv::shards_manager_log`].
+    pub fn record_shards_manager_requests(mut self) -> Self {
+        self.record_shards_manager_requests = true;
+        self
+    }
+
+    /// Makes [`TestEnv::process_partial_encoded_chunks`] lose each chunk part
+    /// message and forward with the given probability, as drawn from
+    /// [`TestEnv::rng`].
+    pub fn drop_chunk_messages(mut self, probability: f64) -> Self {
+        assert!((0.0..=1.0).contains(&probability), "invalid probability {}", probability);
+        self.chunk_message_drop_probability = probability;
+        self
+    }
+
+    /// Makes the shards manager of each client, and the log of its traffic,
+    /// read the time from `clock`.  With the clock of a
+    /// [`time::FakeClock`], tests decide when chunk requests are due to be
+    /// resent instead of sleeping until they are.
+    pub fn shards_manager_clock(mut self, clock: time::Clock) -> Self {
+        self.shards_manager_clock = clock;
+        self
+    }
+
+    /// Makes the shards manager of each client request parts owned by chunk
+    /// producers whose reliability is below `threshold` from the shard
+    /// representative instead; see
+    /// [`unc_chunks::ShardsManager::set_chunk_producer_reliability_threshold`].
+    pub fn chunk_producer_reliability_threshold(mut self, threshold: f64) -> Self {
+        self.chunk_producer_reliability_threshold = Some(threshold);
+        self
+    }
+
+    /// Allows [`TestEnv::tamper_store`] to be used on the clients with the
+    /// given indices.  Tampering with any other client panics.
+    pub fn tamperable_clients(mut self, clients: impl IntoIterator<Item = usize>) -> Self {
+        self.tamperable_clients.extend(clients);
+        self
+    }
+
     /// Constructs new `TestEnv` structure.
     ///
     /// If no clients were configured (either through count or vector) one
     /// client is created.  Similarly, if no validator seats were configured,
     /// one seat is configured.
     ///
-    /// Panics if `runtime_adapters` or `network_adapters` methods were used and
-    /// the length of the vectors passed to them did not equal number of
-    /// configured clients.
+    /// Panics if the setters were used in the wrong order or with vectors
+    /// whose length doesn't equal the number of configured clients; see
+    /// [`Self::try_build`].
+    #[track_caller]
     pub fn build(self) -> TestEnv {
-        self.ensure_shard_trackers().ensure_runtimes().ensure_network_adapters().build_impl()
+        self.try_build().unwrap_or_else(|err| panic!("invalid TestEnvBuilder setup: {}", err))
+    }
+
+    /// Like [`Self::build`] but returns the first mistake in the setup instead
+    /// of panicking.
+    #[track_caller]
+    pub fn try_build(self) -> Result<TestEnv, TestEnvBuilderError> {
+        let mut builder = self.ensure_shard_trackers();
+        // The resharding is taken by real_epoch_managers, so the epoch
+        // managers wouldn't know about it.
+        if builder.error.is_none() && builder.resharding.is_some() {
+            builder.error = Some(TestEnvBuilderError::InvalidResharding {
+                reason: "the epoch managers weren't built with real_epoch_managers".to_string(),
+                at: builder.initialized[&BuilderPhase::Resharding],
+            });
+        }
+        let mut builder = builder.ensure_runtimes().ensure_network_adapters();
+        if let Some(err) = builder.error.take() {
+            return Err(err);
+        }
+        Ok(builder.build_impl())
     }
 
     fn build_impl(self) -> TestEnv {
         let chain_genesis = self.chain_genesis;
         let clients = self.clients.clone();
         let num_clients = clients.len();
+        let observers = self.observers;
         let validators = self.validators;
         let num_validators = validators.len();
-        let seeds = self.seeds;
+        let mut seeds = self.seeds;
+        if let Some(seed) = self.seed {
+            for account_id in clients.iter() {
+                seeds.entry(account_id.clone()).or_insert_with(|| {
+                    hash(&[&seed.to_le_bytes()[..], account_id.as_bytes()].concat()).0
+                });
+            }
+        }
+        // the seed of the key of each client and validator, used both for its validator signer
+        // and for the access key its account is given in genesis
+        let signer_seeds: HashMap<AccountId, String> = clients
+            .iter()
+            .chain(validators.iter())
+            .map(|account_id| (account_id.clone(), account_id.to_string()))
+            .collect();
         let epoch_managers = self.epoch_managers.unwrap();
         let shard_trackers = self.shard_trackers.unwrap();
         let runtimes = self.runtimes.unwrap();
         let network_adapters = self.network_adapters.unwrap();
+        let client_feature_overrides = self
+            .client_feature_overrides
+            .unwrap_or_else(|| vec![ClientFeatureOverrides::default(); num_clients]);
         let client_adapters = (0..num_clients)
             .map(|_| Arc::new(MockClientAdapterForShardsManager::default()))
             .collect::<Vec<_>>();
-        let shards_manager_adapters = (0..num_clients)
+        let shards_manager_logs = (0..num_clients)
+            .map(|_| {
+                self.record_shards_manager_requests
+                    .then(|| ShardsManagerLog::new(self.shards_manager_clock.clone()))
+            })
+            .collect::<Vec<_>>();
+        let shards_managers = (0..num_clients)
             .map(|i| {
                 let epoch_manager = epoch_managers[i].clone();
                 let shard_tracker = shard_trackers[i].clone();
                 let runtime = runtimes[i].clone();
-                let network_adapter = network_adapters[i].clone();
+                let mut network_adapter: PeerManagerAdapter = network_adapters[i].clone().into();
+                if let Some(log) = &shards_manager_logs[i] {
+                    network_adapter = log.wrap_network_adapter(network_adapter);
+                }
                 let client_adapter = client_adapters[i].clone();
-                setup_synchronous_shards_manager(
-                    Some(clients[i].clone()),
+                let me = Some(&clients[i]).filter(|me| !observers.contains(me)).cloned();
+                let shards_manager = setup_synchronous_shards_manager_adapter(
+                    self.shards_manager_clock.clone(),
+                    me,
                     client_adapter.as_sender(),
-                    network_adapter.into(),
+                    network_adapter,
                     epoch_manager.into_adapter(),
                     shard_tracker,
                     runtime,
                     &chain_genesis,
-                )
+                );
+                shards_manager
+                    .shards_manager
+                    .lock()
+                    .unwrap()
+                    .set_chunk_producer_reliability_threshold(
+                        self.chunk_producer_reliability_threshold,
+                    );
+                shards_manager
+            })
+            .collect::<Vec<_>>();
+        let shards_manager_adapters = shards_managers
+            .iter()
+            .zip(shards_manager_logs.iter())
+            .map(|(shards_manager, log)| {
+                let adapter = ShardsManagerAdapterForTest::from(shards_manager.clone());
+                match log {
+                    Some(log) => log.wrap_adapter(adapter),
+                    None => adapter,
+                }
             })
             .collect::<Vec<_>>();
+        let shards_manager_resend_senders =
+            shards_managers.iter().map(|shards_manager| shards_manager.as_sender()).collect();
         let clients = (0..num_clients)
                 .map(|i| {
                     let account_id = clients[i].clone();
@@ -517,9 +909,16 @@ impl TestEnvBuilder {
                         make_snapshot_callback,
                         delete_snapshot_callback,
                     };
+                    let validator_signer = (!observers.contains(&account_id)).then(|| {
+                        Arc::new(InMemoryValidatorSigner::from_seed(
+                            account_id.clone(),
+                            KeyType::ED25519,
+                            &signer_seeds[&account_id],
+                        )) as Arc<dyn ValidatorSigner>
+                    });
                     setup_client_with_runtime(
                         u64::try_from(num_validators).unwrap(),
-                        Some(account_id),
+                        validator_signer,
                         false,
                         network_adapter.into(),
                         shards_manager_adapter,
@@ -531,6 +930,7 @@ impl TestEnvBuilder {
                         self.archive,
                         self.save_trie_changes,
                         Some(snapshot_callbacks),
+                        &client_feature_overrides[i],
                     )
                 })
                 .collect();
@@ -541,6 +941,8 @@ impl TestEnvBuilder {
             network_adapters,
             client_adapters,
             shards_manager_adapters,
+            shards_manager_resend_senders,
+            shards_manager_logs,
             clients,
             account_indices: AccountIndices(
                 self.clients
@@ -551,8 +953,17 @@ impl TestEnvBuilder {
             ),
             paused_blocks: Default::default(),
             seeds,
+            signer_seeds,
+            observers,
+            seed: self.seed.unwrap_or_default(),
+            rng: StdRng::seed_from_u64(self.seed.unwrap_or_default()),
+            shuffle_chunk_messages: self.seed.is_some(),
+            chunk_message_drop_probability: self.chunk_message_drop_probability,
             archive: self.archive,
             save_trie_changes: self.save_trie_changes,
+            client_feature_overrides,
+            tx_nonces: HashMap::new(),
+            tamperable_clients: self.tamperable_clients,
         }
     }
 
@@ -560,9 +971,11 @@ impl TestEnvBuilder {
         (0..count).map(|i| format!("test{}", i).parse().unwrap()).collect()
     }
 
+    #[track_caller]
     pub fn use_state_snapshots(mut self) -> Self {
-        assert!(self.runtimes.is_none(), "Set up snapshot config before runtimes");
-        self.state_snapshot_enabled = true;
+        if self.validate(BuilderPhase::StateSnapshots, None, true) {
+            self.state_snapshot_enabled = true;
+        }
         self
     }
 
@@ -573,4 +986,210 @@ impl TestEnvBuilder {
             StateSnapshotType::ForReshardingOnly
         }
     }
+
+    /// Switches the shard layout to `shard_layout` in the first epoch whose
+    /// protocol version is at least `protocol_version`, so tests pick the
+    /// boundary by when they upgrade the protocol (see
+    /// [`TestEnv::upgrade_protocol`]).  The children shards' state is built
+    /// during the epoch before, as on a real network.
+    ///
+    /// The new layout has to be a split of the genesis shard layout, which is
+    /// checked when the epoch managers are built; those have to be real ones,
+    /// so this must be followed by [`Self::real_epoch_managers`] or
+    /// [`Self::real_epoch_managers_with_test_overrides`].  State snapshots are
+    /// turned on, since resharding builds the children shards from them.
+    #[track_caller]
+    pub fn schedule_resharding(
+        mut self,
+        protocol_version: ProtocolVersion,
+        shard_layout: ShardLayout,
+    ) -> Self {
+        if self.validate(BuilderPhase::Resharding, None, true) {
+            self.resharding = Some((protocol_version, shard_layout));
+            self.state_snapshot_enabled = true;
+        }
+        self
+    }
+}
+
+/// Checks that each shard of `children` holds the accounts of a single shard
+/// of `parent`, which is the one `children` names as its parent.
+fn check_split(parent: &ShardLayout, children: &ShardLayout) -> Result<(), String> {
+    if children.version() <= parent.version() {
+        return Err(format!(
+            "the new shard layout has version {}, which isn't above the current version {}",
+            children.version(),
+            parent.version()
+        ));
+    }
+    let num_parents = parent.shard_ids().count();
+    if num_parents > 1 && parent.boundary_accounts().is_empty() {
+        return Err("the current shard layout maps accounts by hash and can't be split".into());
+    }
+    for parent_shard_id in parent.shard_ids() {
+        if children.get_children_shards_ids(parent_shard_id).map_or(true, |ids| ids.is_empty()) {
+            return Err(format!("shard {} has no children in the new layout", parent_shard_id));
+        }
+    }
+    if children.get_children_shards_ids(num_parents as u64).is_some() {
+        return Err(format!("the new layout splits more than {} shards", num_parents));
+    }
+    // Shards are ranges of accounts, so it's enough for the current boundaries
+    // to be kept and for each child's first account to be in its parent.
+    if let Some(account) =
+        parent.boundary_accounts().iter().find(|a| !children.boundary_accounts().contains(a))
+    {
+        return Err(format!("the boundary at {} isn't kept in the new layout", account));
+    }
+    if children.get_parent_shard_id(0).map_err(|err| format!("{:?}", err))? != 0 {
+        return Err("shard 0 of the new layout isn't a child of shard 0".into());
+    }
+    for account in children.boundary_accounts() {
+        let child = account_id_to_shard_id(account, children);
+        let want = account_id_to_shard_id(account, parent);
+        let got = children.get_parent_shard_id(child).map_err(|err| format!("{:?}", err))?;
+        if got != want {
+            return Err(format!(
+                "shard {} starting at {} has parent {} but holds accounts of shard {}",
+                child, account, got, want
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuilderPhase, TestEnvBuilder, TestEnvBuilderError};
+    use crate::test_utils::TestEnv;
+    use unc_chain::test_utils::{KeyValueRuntime, MockEpochManager};
+    use unc_chain::types::RuntimeAdapter;
+    use unc_chain::ChainGenesis;
+    use unc_epoch_manager::shard_tracker::ShardTracker;
+    use unc_primitives::shard_layout::ShardLayout;
+    use unc_store::test_utils::create_test_store;
+    use std::sync::Arc;
+    use BuilderPhase::*;
+
+    /// Pairs of phases where the first one has to be set before the second.
+    const ORDERING: &[(BuilderPhase, BuilderPhase)] = &[
+        (Clients, Stores),
+        (Clients, EpochManagers),
+        (Clients, ShardTrackers),
+        (Clients, Runtimes),
+        (Clients, NetworkAdapters),
+        (Clients, ClientFeatures),
+        (Validators, EpochManagers),
+        (NumShards, EpochManagers),
+        (Stores, EpochManagers),
+        (Stores, Runtimes),
+        (EpochManagers, ShardTrackers),
+        (EpochManagers, Runtimes),
+        (StateSnapshots, Runtimes),
+        (Resharding, EpochManagers),
+        (Resharding, Runtimes),
+    ];
+
+    const SET_ONCE: &[BuilderPhase] =
+        &[Stores, EpochManagers, ShardTrackers, Runtimes, ClientFeatures, Resharding];
+
+    /// Sets `phase` explicitly, for a single client.
+    fn set(builder: TestEnvBuilder, phase: BuilderPhase) -> TestEnvBuilder {
+        let epoch_manager = MockEpochManager::new(create_test_store(), 5);
+        match phase {
+            Clients => builder.clients_count(1),
+            Validators => builder.validator_seats(1),
+            NumShards => builder.num_shards(1),
+            Stores => builder.stores(vec![create_test_store()]),
+            EpochManagers => builder.mock_epoch_managers(vec![epoch_manager]),
+            ShardTrackers => builder.shard_trackers(vec![ShardTracker::new_empty(epoch_manager)]),
+            Runtimes => builder.runtimes(vec![
+                KeyValueRuntime::new(create_test_store(), &epoch_manager) as Arc<dyn RuntimeAdapter>
+            ]),
+            NetworkAdapters => builder.network_adapters(vec![Arc::new(Default::default())]),
+            StateSnapshots => builder.use_state_snapshots(),
+            ClientFeatures => builder.client_feature_overrides(vec![Default::default()]),
+            Resharding => builder.schedule_resharding(1, ShardLayout::v1_test()),
+        }
+    }
+
+    #[test]
+    fn test_phase_transitions() {
+        for first in BuilderPhase::ALL {
+            for second in BuilderPhase::ALL {
+                let builder = set(set(TestEnv::builder(ChainGenesis::test()), first), second);
+                let want = if ORDERING.contains(&(second, first)) {
+                    "out of order"
+                } else if first == second && SET_ONCE.contains(&first) {
+                    "already initialized"
+                } else if (first, second) == (NumShards, EpochManagers) {
+                    "conflict"
+                } else {
+                    "ok"
+                };
+                let got = match &builder.error {
+                    None => "ok",
+                    Some(TestEnvBuilderError::OutOfOrder { phase, later, .. }) => {
+                        assert_eq!((*phase, *later), (second, first));
+                        "out of order"
+                    }
+                    Some(TestEnvBuilderError::AlreadyInitialized { .. }) => "already initialized",
+                    Some(TestEnvBuilderError::Conflict { .. }) => "conflict",
+                    Some(err) => panic!("{} then {}: {}", first, second, err),
+                };
+                assert_eq!(got, want, "{} then {}", first, second);
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_build_errors() {
+        // The stores are initialized with their defaults by track_all_shards.
+        let err = TestEnv::builder(ChainGenesis::test())
+            .track_all_shards()
+            .clients_count(2)
+            .try_build()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            err.starts_with("clients must be set before stores; stores already initialized at "),
+            "{}",
+            err
+        );
+        assert!(err.contains(file!()), "{}", err);
+
+        let err = TestEnv::builder(ChainGenesis::test())
+            .clients_count(2)
+            .stores(vec![create_test_store()])
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                TestEnvBuilderError::WrongCount { phase: Stores, expected: 2, got: 1 }
+            ),
+            "{}",
+            err
+        );
+
+        // Later setters don't replace the first error.
+        let err = TestEnv::builder(ChainGenesis::test())
+            .clients(vec![])
+            .num_shards(2)
+            .mock_epoch_managers(vec![])
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, TestEnvBuilderError::Empty { phase: Clients }), "{}", err);
+
+        // The default mock epoch managers can't switch shard layouts.
+        let err = TestEnv::builder(ChainGenesis::test())
+            .schedule_resharding(1, ShardLayout::v1_test())
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, TestEnvBuilderError::InvalidResharding { .. }), "{}", err);
+    }
 }
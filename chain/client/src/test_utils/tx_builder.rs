@@ -0,0 +1,101 @@
+use unc_crypto::{InMemorySigner, PublicKey};
+use unc_primitives::hash::CryptoHash;
+use unc_primitives::transaction::{Action, SignedTransaction};
+use unc_primitives::types::{AccountId, Balance, Gas, Nonce};
+
+/// Builds a single transaction signed by one of the accounts of a
+/// [`TestEnv`](super::TestEnv), see [`TestEnv::tx_builder`](super::TestEnv::tx_builder).
+/// The nonce and the block hash are already filled in, so all that's left is
+/// to pick the actions.
+pub struct TxBuilder {
+    signer: InMemorySigner,
+    nonce: Nonce,
+    block_hash: CryptoHash,
+}
+
+impl TxBuilder {
+    pub(crate) fn new(signer: InMemorySigner, nonce: Nonce, block_hash: CryptoHash) -> Self {
+        Self { signer, nonce, block_hash }
+    }
+
+    pub fn signer(&self) -> &InMemorySigner {
+        &self.signer
+    }
+
+    pub fn nonce(&self) -> Nonce {
+        self.nonce
+    }
+
+    pub fn actions(self, receiver_id: AccountId, actions: Vec<Action>) -> SignedTransaction {
+        SignedTransaction::from_actions(
+            self.nonce,
+            self.signer.account_id.clone(),
+            receiver_id,
+            &self.signer,
+            actions,
+            self.block_hash,
+        )
+    }
+
+    pub fn transfer(self, receiver_id: AccountId, amount: Balance) -> SignedTransaction {
+        SignedTransaction::send_money(
+            self.nonce,
+            self.signer.account_id.clone(),
+            receiver_id,
+            &self.signer,
+            amount,
+            self.block_hash,
+        )
+    }
+
+    pub fn function_call(
+        self,
+        receiver_id: AccountId,
+        method_name: &str,
+        args: Vec<u8>,
+        gas: Gas,
+        deposit: Balance,
+    ) -> SignedTransaction {
+        SignedTransaction::call(
+            self.nonce,
+            self.signer.account_id.clone(),
+            receiver_id,
+            &self.signer,
+            deposit,
+            method_name.to_string(),
+            args,
+            gas,
+            self.block_hash,
+        )
+    }
+
+    /// Creates `new_account_id` with a full access key `public_key`.
+    pub fn create_account(
+        self,
+        new_account_id: AccountId,
+        amount: Balance,
+        public_key: PublicKey,
+    ) -> SignedTransaction {
+        SignedTransaction::create_account(
+            self.nonce,
+            self.signer.account_id.clone(),
+            new_account_id,
+            amount,
+            public_key,
+            &self.signer,
+            self.block_hash,
+        )
+    }
+
+    /// Pledges `pledge` from the signer account with `public_key` as the validator key.
+    pub fn pledge(self, pledge: Balance, public_key: PublicKey) -> SignedTransaction {
+        SignedTransaction::pledge(
+            self.nonce,
+            self.signer.account_id.clone(),
+            &self.signer,
+            pledge,
+            public_key,
+            self.block_hash,
+        )
+    }
+}
@@ -1,12 +1,16 @@
 use std::collections::HashSet;
 
-use crate::test_utils::TestEnv;
+use crate::test_utils::{
+    ShardsManagerMessageDirection, ShardsManagerMessageKind, TamperOp, TestEnv,
+};
 use unc_async::messaging::CanSend;
-use unc_chain::ChainGenesis;
+use unc_async::time::FakeClock;
+use unc_chain::{ChainGenesis, ChainStoreAccess, Provenance};
+use unc_chunks::CHUNK_REQUEST_RETRY;
 use unc_network::shards_manager::ShardsManagerRequestFromNetwork;
 use unc_network::types::NetworkRequests;
 use unc_network::types::PartialEncodedChunkRequestMsg;
-use unc_o11y::testonly::init_integration_logger;
+use unc_o11y::testonly::{init_integration_logger, init_test_logger};
 use unc_primitives::hash::CryptoHash;
 
 // TODO(#8269) Enable test after fixing the issue related to KeyValueRuntime. See env.restart()
@@ -49,3 +53,126 @@ fn test_request_chunk_restart() {
         assert!(false);
     }
 }
+
+/// Withhold the parts of a chunk from client 1 and drop all its requests for
+/// them, then check that the shards manager keeps re-requesting the same parts,
+/// once per retry period.
+#[test]
+fn test_chunk_request_retries() {
+    init_test_logger();
+    let clock = FakeClock::default();
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients_count(2)
+        .validator_seats(1)
+        .shards_manager_clock(clock.clock())
+        .record_shards_manager_requests()
+        .build();
+    // Producing a block also distributes the chunk for the next one, so the
+    // parts sent out after block 2 are the ones of the chunk in block 3.
+    for height in 1..=3 {
+        env.produce_block(0, height);
+        if height == 2 {
+            while env.network_adapters[0].pop().is_some() {}
+        } else {
+            env.process_partial_encoded_chunks();
+        }
+        let block = env.clients[0].chain.get_block_by_height(height).unwrap();
+        let _ = env.clients[1].process_block_test(block.into(), Provenance::NONE);
+        env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    }
+    let block = env.clients[0].chain.get_block_by_height(3).unwrap();
+    let chunk_hash = block.chunks()[0].chunk_hash();
+    assert_ne!(env.clients[1].chain.head().unwrap().last_block_hash, *block.hash());
+
+    let log = env.shards_manager_log(1).clone();
+    assert!(log.entries().iter().any(|entry| {
+        entry.kind == ShardsManagerMessageKind::RequestChunks
+            && entry.chunk_hash.as_ref() == Some(&chunk_hash)
+    }));
+    let part_ord = log
+        .entries()
+        .iter()
+        .find(|entry| {
+            entry.direction == ShardsManagerMessageDirection::Sent
+                && entry.kind == ShardsManagerMessageKind::PartialEncodedChunkRequest
+                && entry.chunk_hash.as_ref() == Some(&chunk_hash)
+                && !entry.part_ords.is_empty()
+        })
+        .expect("client 1 never requested the withheld chunk")
+        .part_ords[0];
+
+    // Ask for resends ten times per retry period, for five retry periods.
+    for _ in 0..50 {
+        while env.network_adapters[1].pop().is_some() {}
+        clock.advance(CHUNK_REQUEST_RETRY / 10);
+        env.resend_chunk_requests(1);
+    }
+
+    // The first request, and a resend at the end of each retry period.
+    let requests = log.sent_part_requests(&chunk_hash, part_ord);
+    assert_eq!(requests.len(), 6, "{:?}", requests);
+    for pair in requests.windows(2) {
+        assert_eq!(pair[1] - pair[0], CHUNK_REQUEST_RETRY, "{:?}", requests);
+    }
+}
+
+/// Delete the chunk of block 3 from the store of client 1 after it was
+/// completed there but before the block arrived, and check that client 1
+/// requests the chunk from client 0 again and then accepts the block.
+#[cfg(feature = "test_features")]
+#[test]
+fn test_deleted_chunk_is_fetched_again() {
+    init_test_logger();
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients_count(2)
+        .validator_seats(1)
+        .tamperable_clients([1])
+        .build();
+    // Producing a block also distributes the chunk for the next one, so the
+    // chunk of block 3 is in the store of client 1 once it's done with block 2.
+    for height in 1..=2 {
+        env.produce_block(0, height);
+        env.process_partial_encoded_chunks();
+        let block = env.clients[0].chain.get_block_by_height(height).unwrap();
+        let _ = env.clients[1].process_block_test(block.into(), Provenance::NONE);
+        env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    }
+    env.produce_block(0, 3);
+    let block = env.clients[0].chain.get_block_by_height(3).unwrap();
+    let chunk_hash = block.chunks()[0].chunk_hash();
+    assert!(env.clients[1].chain.chain_store().get_partial_chunk(&chunk_hash).is_ok());
+
+    env.tamper_store(1, TamperOp::DeleteChunk(chunk_hash.clone()));
+    assert!(env.clients[1].chain.chain_store().get_partial_chunk(&chunk_hash).is_err());
+    let _ = env.clients[1].process_block_test(block.clone().into(), Provenance::NONE);
+    env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    assert!(env.clients[1].chain.blocks_with_missing_chunks.contains(block.hash()));
+
+    // The block waits for the chunk, whose parts client 1 asks client 0 for again.
+    let mut requested = false;
+    while let Some(request) = env.network_adapters[1].pop() {
+        if let unc_network::types::PeerManagerMessageRequest::NetworkRequests(
+            NetworkRequests::PartialEncodedChunkRequest { request: msg, .. },
+        ) = &request
+        {
+            requested |= msg.chunk_hash == chunk_hash;
+            env.process_partial_encoded_chunk_request(1, request);
+        }
+    }
+    assert!(requested);
+    env.process_shards_manager_responses_and_finish_processing_blocks(1);
+    assert!(env.clients[1].chain.chain_store().get_partial_chunk(&chunk_hash).is_ok());
+    assert_eq!(env.clients[1].chain.head().unwrap().last_block_hash, *block.hash());
+}
+
+#[test]
+#[should_panic(expected = "isn't allowed to be tampered with")]
+fn test_tamper_store_requires_allowlist() {
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients_count(2)
+        .validator_seats(1)
+        .tamperable_clients([1])
+        .build();
+    let state_root = env.clients[0].chain.genesis_block().chunks()[0].prev_state_root();
+    env.tamper_store(0, TamperOp::CorruptTrieNode(state_root, vec![]));
+}
@@ -0,0 +1,37 @@
+use crate::test_utils::TestEnv;
+use unc_chain::{ChainGenesis, Provenance};
+use unc_chain_configs::DEFAULT_GC_NUM_EPOCHS_TO_KEEP;
+
+/// Produce more epochs than GC keeps around, force a GC step, and check that
+/// old blocks are gone while the ones in the current epoch are untouched.
+#[test]
+fn test_gc_status_after_forced_gc() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).build();
+    let epoch_length = env.clients[0].chain.epoch_length;
+    let genesis_height = env.clients[0].chain.genesis().height();
+
+    let mut blocks = vec![];
+    for height in 1..=(DEFAULT_GC_NUM_EPOCHS_TO_KEEP + 3) * epoch_length {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        blocks.push((*block.hash(), block.header().epoch_id().clone()));
+        env.process_block(0, block, Provenance::PRODUCED);
+    }
+    env.run_gc(0);
+
+    let hashes = blocks.iter().map(|(hash, _)| *hash).collect::<Vec<_>>();
+    let status = env.gc_status(0, &hashes);
+    assert!(status.tail > genesis_height, "{:?}", status);
+    assert_eq!(status.num_cleared_heights, status.tail - genesis_height);
+    assert!(!status.is_fully_retrievable(&hashes[0]));
+
+    let head = env.clients[0].chain.head().unwrap();
+    assert_eq!(status.head_height, head.height);
+    let mut num_head_epoch_blocks = 0;
+    for (hash, epoch_id) in blocks.iter() {
+        if epoch_id == &head.epoch_id {
+            assert!(status.is_fully_retrievable(hash), "{:?}", status.blocks[hash]);
+            num_head_epoch_blocks += 1;
+        }
+    }
+    assert!(num_head_epoch_blocks > 0);
+}
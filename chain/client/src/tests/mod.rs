@@ -4,6 +4,8 @@ mod chunks_management;
 mod consensus;
 mod cross_shard_tx;
 mod doomslug;
+mod garbage_collection;
 mod maintenance_windows;
+mod multi_run;
 mod process_blocks;
 mod query_client;
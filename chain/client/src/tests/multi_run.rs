@@ -0,0 +1,146 @@
+use crate::test_utils::{
+    ScenarioFailure, ShardsManagerMessageDirection, ShardsManagerMessageKind, TestEnv,
+    TestEnvBuilder,
+};
+use unc_chain::{ChainGenesis, Provenance};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+fn builder() -> TestEnvBuilder {
+    TestEnv::builder(ChainGenesis::test()).clients_count(2).validator_seats(2)
+}
+
+// whether `scenario` fails with the given seed, worked out without building an env
+fn is_bad_seed(seed: u64) -> bool {
+    StdRng::seed_from_u64(seed).gen_range(0..4) == 0
+}
+
+// Produces a block and fails depending on the env's RNG, so some seeds fail.
+fn scenario(mut env: TestEnv) -> Result<(), ScenarioFailure> {
+    let block = env.clients[0].produce_block(1).unwrap().unwrap();
+    env.process_block(0, block, Provenance::PRODUCED);
+    if env.rng().gen_range(0..4) == 0 {
+        return Err(ScenarioFailure::new(format!("bad seed {}", env.seed())));
+    }
+    Ok(())
+}
+
+/// The report lists exactly the seeds the scenario failed with, and each can
+/// be replayed on its own.
+#[test]
+fn test_multi_run_pinpoints_failing_seeds() {
+    let expected = (0..16).filter(|seed| is_bad_seed(*seed)).collect::<Vec<_>>();
+    assert!(!expected.is_empty() && expected.len() < 16);
+
+    let harness = TestEnvBuilder::multi_run(builder);
+    let report = harness.run(0..16, scenario);
+    assert_eq!(report.seeds_run, (0..16).collect::<Vec<_>>());
+    assert_eq!(report.failing_seeds(), expected);
+    assert!(!report.out_of_time);
+    assert!(report.to_string().contains(&format!("seed {}: bad seed", expected[0])));
+
+    let bad = expected[0];
+    assert_eq!(
+        harness.run_single(bad, scenario),
+        Err(ScenarioFailure::new(format!("bad seed {}", bad)))
+    );
+    let good = (0..16).find(|seed| !is_bad_seed(*seed)).unwrap();
+    assert_eq!(harness.run_single(good, scenario), Ok(()));
+}
+
+#[test]
+fn test_multi_run_budget() {
+    let expected = (0..16).filter(|seed| is_bad_seed(*seed)).collect::<Vec<_>>();
+    let report =
+        TestEnvBuilder::multi_run(builder).stop_on_first_failure(true).run(0..16, scenario);
+    assert_eq!(report.failing_seeds(), vec![expected[0]]);
+    assert_eq!(report.seeds_run, (0..=expected[0]).collect::<Vec<_>>());
+
+    let report = TestEnvBuilder::multi_run(builder)
+        .max_wall_time(Duration::ZERO)
+        .run(0..16, |_| panic!("shouldn't be run"));
+    assert!(report.seeds_run.is_empty());
+    assert!(report.out_of_time);
+}
+
+/// Panics in the scenario are recorded as failures of their seed.
+#[test]
+fn test_multi_run_catches_panics() {
+    let report = TestEnvBuilder::multi_run(builder).run(0..4, |env| {
+        assert_ne!(env.seed(), 2, "unlucky");
+        Ok(())
+    });
+    assert_eq!(report.failing_seeds(), vec![2]);
+    assert!(report.failures[0].failure.0.contains("unlucky"));
+}
+
+/// The seed decides the order in which chunk messages are delivered and which
+/// of them get lost, so runs with the same seed see the same traffic.
+#[test]
+fn test_seed_sets_chunk_message_schedule() {
+    // the chunk parts and forwards each client received, in order
+    let run = |seed: u64| {
+        let mut env =
+            builder().seed(seed).drop_chunk_messages(0.5).record_shards_manager_requests().build();
+        for height in 1..=6 {
+            let epoch_manager = env.clients[0].epoch_manager.clone();
+            let head = env.clients[0].chain.head().unwrap();
+            let epoch_id =
+                epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+            let producer = epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+            let Ok(Some(block)) = env.client(&producer).produce_block(height) else { break };
+            for i in 0..2 {
+                let provenance = if env.get_client_id(i) == &producer {
+                    Provenance::PRODUCED
+                } else {
+                    Provenance::NONE
+                };
+                let _ = env.clients[i].process_block_test(block.clone().into(), provenance);
+            }
+            env.process_partial_encoded_chunks();
+            for i in 0..2 {
+                env.process_shards_manager_responses_and_finish_processing_blocks(i);
+            }
+        }
+        (0..2)
+            .map(|i| {
+                env.shards_manager_log(i)
+                    .entries()
+                    .into_iter()
+                    .filter(|entry| {
+                        entry.direction == ShardsManagerMessageDirection::Received
+                            && matches!(
+                                entry.kind,
+                                ShardsManagerMessageKind::PartialEncodedChunk
+                                    | ShardsManagerMessageKind::PartialEncodedChunkForward
+                            )
+                    })
+                    .map(|entry| (entry.kind, entry.chunk_hash, entry.part_ords))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    let first = run(1);
+    assert!(first.iter().any(|received| !received.is_empty()));
+    assert_eq!(run(1), first);
+    let others = (2..10).map(run).collect::<Vec<_>>();
+    assert!(others.iter().any(|received| received != &first));
+}
+
+/// The seed is propagated into the clients' random seeds, unless they're set
+/// explicitly.
+#[test]
+fn test_seed_sets_client_seeds() {
+    let env1 = builder().seed(1).build();
+    let env2 = builder().seed(2).build();
+    let (a, b) = (env1.get_client_id(0).clone(), env1.get_client_id(1).clone());
+    assert_ne!(env1.seeds[&a], env1.seeds[&b]);
+    assert_ne!(env1.seeds[&a], env2.seeds[&a]);
+    assert_eq!(env1.seeds[&a], builder().seed(1).build().seeds[&a]);
+
+    let explicit = [(a.clone(), [7; 32])].into_iter().collect();
+    let env = builder().clients_random_seeds(explicit).seed(1).build();
+    assert_eq!(env.seeds[&a], [7; 32]);
+    assert_eq!(env.seeds[&b], env1.seeds[&b]);
+}
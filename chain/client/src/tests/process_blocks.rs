@@ -1,8 +1,10 @@
-use crate::test_utils::TestEnv;
+use crate::test_utils::{ClientFeatureOverrides, TestEnv};
 use assert_matches::assert_matches;
+use unc_chain::test_utils::{KeyValueRuntime, MockEpochManager, ValidatorSchedule};
+use unc_chain::types::RuntimeAdapter;
 use unc_chain::{test_utils, ChainGenesis, Provenance};
 use unc_crypto::vrf::Value;
-use unc_crypto::{KeyType, PublicKey, Signature};
+use unc_crypto::{KeyType, PublicKey, Signature, Signer};
 use unc_network::types::{NetworkRequests, PeerManagerMessageRequest};
 use unc_primitives::block::Block;
 use unc_primitives::network::PeerId;
@@ -11,6 +13,8 @@ use unc_primitives::sharding::ShardChunkHeaderV3;
 use unc_primitives::test_utils::create_test_signer;
 use unc_primitives::types::validator_stake::ValidatorPledge;
 use unc_primitives::utils::MaybeValidated;
+use unc_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use unc_store::test_utils::create_test_store;
 use std::sync::Arc;
 
 /// Only process one block per height
@@ -50,6 +54,29 @@ fn test_not_process_height_twice() {
     }
 }
 
+/// Blocks delivered out of order wait in the orphan pool until their parents arrive, and
+/// the client ends up on the same tip as the one which produced them.
+#[test]
+fn test_deliver_blocks_shuffled() {
+    let mut env = TestEnv::builder(ChainGenesis::test()).clients_count(2).seed(42).build();
+    let mut blocks = vec![];
+    for height in 1..=20 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        blocks.push(block);
+    }
+    let delivery = env.deliver_blocks_shuffled(1, blocks);
+    assert_eq!(delivery.order.len(), 20);
+    assert!(delivery.orphaned > 0, "{:?}", delivery);
+    assert!(delivery.max_orphans > 0, "{:?}", delivery);
+    assert_eq!(delivery.orphans_evicted, 0);
+    assert_eq!(env.clients[1].chain.orphans_len(), 0);
+    assert_eq!(
+        env.clients[1].chain.head().unwrap().last_block_hash,
+        env.clients[0].chain.head().unwrap().last_block_hash
+    );
+}
+
 /// Test that if a block contains chunks with invalid shard_ids, the client will return error.
 #[test]
 fn test_bad_shard_id() {
@@ -143,3 +170,103 @@ fn test_bad_block_signature() {
     let _ =
         env.clients[0].process_block_test(MaybeValidated::from(block), Provenance::NONE).unwrap();
 }
+
+/// Test that the total supply check of `TestEnv` catches a runtime reporting more balance
+/// burnt than its execution outcomes add up to.
+#[test]
+fn test_supply_invariant_injected_balance_burnt() {
+    let chain_genesis = ChainGenesis::test();
+    let store = create_test_store();
+    let vs =
+        ValidatorSchedule::new().block_producers_per_epoch(vec![vec!["test0".parse().unwrap()]]);
+    let epoch_manager =
+        MockEpochManager::new_with_validators(store.clone(), vs, chain_genesis.epoch_length);
+    let runtime = KeyValueRuntime::new(store.clone(), &epoch_manager);
+    let mut env = TestEnv::builder(chain_genesis)
+        .stores(vec![store])
+        .mock_epoch_managers(vec![epoch_manager])
+        .runtimes(vec![runtime.clone() as Arc<dyn RuntimeAdapter>])
+        .build();
+    for height in 1..=3 {
+        env.produce_block(0, height);
+    }
+    env.assert_supply_invariant(0);
+
+    // the chunk applied in block 4 reports the extra balance burnt, which the chunk included
+    // in block 5 carries over to the header
+    runtime.inject_balance_burnt(7);
+    for height in 4..=6 {
+        env.produce_block(0, height);
+    }
+    let err = env.check_supply_invariant(0).unwrap_err();
+    assert!(err.starts_with("at height 5 the chunk of shard 0 reports balance burnt 7"), "{}", err);
+}
+
+/// Test that clients with different client-side features turned on still follow and
+/// finalize the same chain.
+#[test]
+fn test_client_feature_overrides() {
+    let mut env = TestEnv::builder(ChainGenesis::test())
+        .clients_count(2)
+        .client_feature_overrides(vec![
+            ClientFeatureOverrides {
+                state_sync_enabled: Some(false),
+                flat_storage_creation_enabled: Some(false),
+                ..Default::default()
+            },
+            ClientFeatureOverrides::default(),
+        ])
+        .build();
+    assert!(!env.clients[0].config.state_sync_enabled);
+    assert!(!env.clients[0].config.flat_storage_creation_enabled);
+    assert!(env.clients[1].config.state_sync_enabled);
+
+    for height in 1..=10 {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        env.process_block(1, block, Provenance::NONE);
+    }
+    let final_head = env.clients[0].chain.final_head().unwrap();
+    assert!(final_head.height > 1, "{:?}", final_head);
+    assert_eq!(env.clients[1].chain.final_head().unwrap(), final_head);
+
+    // the overrides survive a restart
+    env.restart(0);
+    assert!(!env.clients[0].config.state_sync_enabled);
+}
+
+/// Test that a block signed with the signer `TestEnv::validator_signer` returns for its
+/// producer is accepted by a peer client, while one signed for the same validator with a key
+/// from another seed is rejected.
+#[test]
+fn test_validator_signer_matches_client() {
+    let mut env =
+        TestEnv::builder(ChainGenesis::test()).clients_count(3).validator_seats(1).build();
+    let producer = env.validators[0].clone();
+    let signer = env.validator_signer(0);
+    assert_eq!(signer.validator_id(), &producer);
+    // the genesis access key of an account has the same key as its validator key
+    assert_eq!(env.client_signer(&producer).public_key(), signer.public_key());
+
+    let block = env.clients[0].produce_block(1).unwrap().unwrap();
+    let mut bad_block = block.clone();
+    bad_block.mut_header().resign(&InMemoryValidatorSigner::from_seed(
+        producer,
+        KeyType::ED25519,
+        "not the seed of the validator",
+    ));
+    let err = env.clients[2]
+        .receive_block_impl(
+            bad_block,
+            PeerId::new(PublicKey::empty(KeyType::ED25519)),
+            false,
+            Arc::new(|_| {}),
+        )
+        .unwrap_err();
+    assert_matches!(err, unc_chain::Error::InvalidSignature);
+
+    let mut block = block;
+    block.mut_header().resign(&signer);
+    env.process_block(1, block, Provenance::NONE);
+    assert_eq!(env.clients[1].chain.head().unwrap().height, 1);
+}
@@ -0,0 +1,58 @@
+//! Benchmarks for catching up the epoch info aggregator on a long range of
+//! blocks, e.g. after a node has been offline for a while.
+//!
+//! Compares feeding the blocks one at a time with `update_tail` with feeding
+//! them in batches with `update_range`.
+
+#[macro_use]
+extern crate bencher;
+
+use bencher::{black_box, Bencher};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use unc_epoch_manager::test_utils::{aggregator_test_epoch_info, random_chain_segment};
+use unc_epoch_manager::types::EpochInfoAggregator;
+use unc_primitives::epoch_manager::block_info::BlockInfo;
+use unc_primitives::epoch_manager::epoch_info::EpochInfo;
+
+const NUM_BLOCKS: usize = 10_000;
+const NUM_SHARDS: usize = 4;
+const NUM_VALIDATORS: usize = 100;
+const BATCH_SIZE: usize = 1000;
+
+fn setup() -> (EpochInfo, Vec<BlockInfo>) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let epoch_info = aggregator_test_epoch_info(NUM_VALIDATORS, NUM_SHARDS);
+    let blocks = random_chain_segment(&mut rng, 0, NUM_BLOCKS, NUM_SHARDS, NUM_VALIDATORS);
+    (epoch_info, blocks)
+}
+
+fn aggregator_update_tail(bench: &mut Bencher) {
+    let (epoch_info, blocks) = setup();
+    bench.iter(|| {
+        let mut aggregator = EpochInfoAggregator::default();
+        for i in (0..blocks.len()).rev() {
+            let prev_height = if i == 0 { 0 } else { blocks[i - 1].height() };
+            aggregator.update_tail(&blocks[i], &epoch_info, prev_height).unwrap();
+        }
+        black_box(aggregator)
+    });
+}
+
+fn aggregator_update_range(bench: &mut Bencher) {
+    let (epoch_info, blocks) = setup();
+    bench.iter(|| {
+        let mut aggregator = EpochInfoAggregator::default();
+        let mut end = blocks.len();
+        while end > 0 {
+            let start = end.saturating_sub(BATCH_SIZE);
+            let prev_height = if start == 0 { 0 } else { blocks[start - 1].height() };
+            aggregator.update_range(&blocks[start..end], &epoch_info, prev_height).unwrap();
+            end = start;
+        }
+        black_box(aggregator)
+    });
+}
+
+benchmark_group!(benches, aggregator_update_tail, aggregator_update_range);
+benchmark_main!(benches);
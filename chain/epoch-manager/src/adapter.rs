@@ -1,4 +1,4 @@
-use crate::types::BlockHeaderInfo;
+use crate::types::{BlockHeaderInfo, NetworkTotals, NextEpochStatus, ValidatorDiff};
 #[cfg(feature = "new_epoch_sync")]
 use crate::EpochInfoAggregator;
 use crate::EpochManagerHandle;
@@ -169,6 +169,28 @@ pub trait EpochManagerAdapter: Send + Sync {
         epoch_id: &EpochId,
     ) -> Result<Vec<ValidatorPowerAndPledge>, EpochError>;
 
+    /// Returns the chunk producers of the given shard along with the share of
+    /// their chunks which made it into blocks so far in the epoch, between 0
+    /// and 1.  Producers which haven't been expected to produce enough chunks
+    /// yet to tell get a neutral 1.0.  Cheap enough to call for every chunk
+    /// request.
+    fn chunk_producer_reliability(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+    ) -> Result<Vec<(AccountId, f64)>, EpochError>;
+
+    /// Returns the share of the chunks expected in the given shard which made
+    /// it into blocks in each of the last `last_n_epochs` finalized epochs,
+    /// oldest first.  Epochs in which the shard had no chunks expected are
+    /// left out.  Only compact counts saved when each epoch was finalized are
+    /// read, so no headers are replayed.
+    fn shard_chunk_density(
+        &self,
+        shard_id: ShardId,
+        last_n_epochs: usize,
+    ) -> Result<Vec<(EpochId, f64)>, EpochError>;
+
     /// Block producers for given height for the main block. Return EpochError if outside of known boundaries.
     fn get_block_producer(
         &self,
@@ -227,6 +249,38 @@ pub trait EpochManagerAdapter: Send + Sync {
         epoch_id: ValidatorInfoIdentifier,
     ) -> Result<EpochValidatorInfo, EpochError>;
 
+    /// Block and chunk producers that entered or left between the two epochs,
+    /// along with pledge and power changes of the validators present in both.
+    ///
+    /// This function is intended for diagnostic use in logging & rpc.
+    fn validator_diff(
+        &self,
+        prev_epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+    ) -> Result<ValidatorDiff, EpochError>;
+
+    /// Whether `account_id` is elected, kicked out, or not a validator in the epoch after
+    /// the one of `current_block_hash`, combining the next epoch's validators, kickouts and
+    /// seat price.
+    fn validator_status_next_epoch(
+        &self,
+        account_id: &AccountId,
+        current_block_hash: &CryptoHash,
+    ) -> Result<NextEpochStatus, EpochError>;
+
+    /// Total power and pledge of the active validators as of `block_hash`, along with how
+    /// many of them produce blocks and chunks.
+    ///
+    /// This function is intended for diagnostic use in logging & rpc.
+    fn network_totals(&self, block_hash: &CryptoHash) -> Result<NetworkTotals, EpochError>;
+
+    /// Same as `network_totals()`, but with the proposals made so far in the epoch of
+    /// `block_hash` applied, as a preview of the next epoch's totals.
+    ///
+    /// WARNING: this call may be expensive.
+    fn pending_network_totals(&self, block_hash: &CryptoHash)
+        -> Result<NetworkTotals, EpochError>;
+
     fn add_validator_proposals(
         &self,
         block_header_info: BlockHeaderInfo,
@@ -652,6 +706,24 @@ impl EpochManagerAdapter for EpochManagerHandle {
         Ok(epoch_manager.get_all_chunk_producers(epoch_id)?.to_vec())
     }
 
+    fn chunk_producer_reliability(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+    ) -> Result<Vec<(AccountId, f64)>, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.get_chunk_producer_reliability(epoch_id, shard_id)
+    }
+
+    fn shard_chunk_density(
+        &self,
+        shard_id: ShardId,
+        last_n_epochs: usize,
+    ) -> Result<Vec<(EpochId, f64)>, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.get_shard_chunk_density(shard_id, last_n_epochs)
+    }
+
     fn get_block_producer(
         &self,
         epoch_id: &EpochId,
@@ -725,6 +797,39 @@ impl EpochManagerAdapter for EpochManagerHandle {
         epoch_manager.get_validator_info(epoch_id)
     }
 
+    fn validator_diff(
+        &self,
+        prev_epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+    ) -> Result<ValidatorDiff, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.validator_diff(prev_epoch_id, next_epoch_id)
+    }
+
+    fn validator_status_next_epoch(
+        &self,
+        account_id: &AccountId,
+        current_block_hash: &CryptoHash,
+    ) -> Result<NextEpochStatus, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.validator_status_next_epoch(account_id, current_block_hash)
+    }
+
+    fn network_totals(&self, block_hash: &CryptoHash) -> Result<NetworkTotals, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.network_totals(block_hash)
+    }
+
+    /// WARNING: this function calls EpochManager::get_epoch_info_aggregator_upto_last
+    /// underneath which can be very expensive.
+    fn pending_network_totals(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<NetworkTotals, EpochError> {
+        let epoch_manager = self.read();
+        epoch_manager.pending_network_totals(block_hash)
+    }
+
     fn add_validator_proposals(
         &self,
         block_header_info: BlockHeaderInfo,
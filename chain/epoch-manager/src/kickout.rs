@@ -0,0 +1,45 @@
+use crate::types::EpochInfoAggregator;
+use crate::EpochManager;
+use unc_primitives::epoch_manager::epoch_info::EpochInfo;
+use unc_primitives::epoch_manager::{EpochConfig, SlashState};
+use unc_primitives::types::{AccountId, BlockChunkValidatorStats, ValidatorKickoutReason};
+use std::collections::HashMap;
+
+/// Computes which validators of `epoch_info` get kicked out for producing too
+/// few blocks or chunks, from the block and chunk counts of the epoch collected
+/// in `aggregator`.
+///
+/// This touches no store, so it can be run offline on a persisted aggregator
+/// and an [`EpochInfo`] dump.  It assumes no validator was slashed and none
+/// were kicked out in the previous epoch; use
+/// [`compute_kickouts_with_history`] to give those, which is what the epoch
+/// finalization does.
+pub fn compute_kickouts(
+    aggregator: &EpochInfoAggregator,
+    epoch_info: &EpochInfo,
+    config: &EpochConfig,
+) -> HashMap<AccountId, ValidatorKickoutReason> {
+    compute_kickouts_with_history(aggregator, epoch_info, config, &HashMap::new(), &HashMap::new())
+        .0
+}
+
+/// Same as [`compute_kickouts`], but skips the `slashed` validators and
+/// avoids kicking out everyone that wasn't already in
+/// `prev_validator_kickout`.  Also returns the block and chunk stats of the
+/// validators which stay.
+pub fn compute_kickouts_with_history(
+    aggregator: &EpochInfoAggregator,
+    epoch_info: &EpochInfo,
+    config: &EpochConfig,
+    slashed: &HashMap<AccountId, SlashState>,
+    prev_validator_kickout: &HashMap<AccountId, ValidatorKickoutReason>,
+) -> (HashMap<AccountId, ValidatorKickoutReason>, HashMap<AccountId, BlockChunkValidatorStats>) {
+    EpochManager::compute_kickout_info(
+        config,
+        epoch_info,
+        &aggregator.block_tracker,
+        &aggregator.shard_tracker,
+        slashed,
+        prev_validator_kickout,
+    )
+}
@@ -1,11 +1,11 @@
 use num_bigint::{BigInt, ToBigInt};
 use crate::proposals::proposals_to_block_summary;
 use crate::proposals::proposals_to_epoch_info;
-use crate::types::EpochInfoAggregator;
+use crate::types::{BlockOrderError, ChunkDensityHistory, DenseIdMap, EpochInfoAggregator};
 use unc_cache::SyncLruCache;
 use unc_chain_configs::GenesisConfig;
 use unc_primitives::checked_feature;
-use unc_primitives::epoch_manager::block_info::{BlockInfo, BlockInfoV2};
+use unc_primitives::epoch_manager::block_info::{BlockInfo, BlockInfoV2, BlockInfoV3};
 use unc_primitives::epoch_manager::block_summary::{BlockSummary, BlockSummaryV1};
 use unc_primitives::epoch_manager::epoch_info::{EpochInfo, EpochSummary};
 use unc_primitives::epoch_manager::{
@@ -30,14 +30,17 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use num_traits::Zero;
 use tracing::{debug, warn};
-use types::BlockHeaderInfo;
+use types::{BlockHeaderInfo, NetworkTotals, NextEpochStatus, ValidatorDiff};
 
 pub use crate::adapter::EpochManagerAdapter;
+pub use crate::kickout::{compute_kickouts, compute_kickouts_with_history};
 pub use crate::reward_calculator::RewardCalculator;
 pub use crate::reward_calculator::NUM_SECONDS_IN_A_YEAR;
 pub use crate::types::RngSeed;
 
 mod adapter;
+mod kickout;
+mod metrics;
 mod proposals;
 mod reward_calculator;
 mod shard_assignment;
@@ -53,6 +56,14 @@ const BLOCK_CACHE_SIZE: usize = if cfg!(feature = "no_cache") { 5 } else { 1000
 
 const _HASH_CACHE_SIZE: usize = if cfg!(feature = "no_cache") { 1 } else { 2 };
 const AGGREGATOR_SAVE_PERIOD: u64 = 1000;
+/// Number of blocks [`EpochInfoAggregator::update_range`] is called with at
+/// once when aggregating a long range of blocks.
+const AGGREGATOR_UPDATE_BATCH_SIZE: usize = 1000;
+/// Key of the `DBCol::Misc` row holding the [`ChunkDensityHistory`].
+const CHUNK_DENSITY_KEY: &[u8] = b"EPOCH_CHUNK_DENSITY";
+/// Number of finalized epochs whose chunk counts are kept for
+/// [`EpochManager::get_shard_chunk_density`].
+const CHUNK_DENSITY_RETENTION: usize = 500;
 
 // In epoch_manager or a common module
 
@@ -350,6 +361,13 @@ impl EpochManager {
             store_update.commit()?;
 
         }
+        if cfg!(debug_assertions) {
+            let aggregator = &epoch_manager.epoch_info_aggregator;
+            let epoch_info = epoch_manager.get_epoch_info(&aggregator.epoch_id).ok();
+            if let Err(err) = aggregator.invariants(epoch_info.as_deref()) {
+                panic!("epoch info aggregator loaded from the store is inconsistent: {}", err);
+            }
+        }
         Ok(epoch_manager)
     }
 
@@ -529,8 +547,8 @@ impl EpochManager {
     fn compute_kickout_info(
         config: &EpochConfig,
         epoch_info: &EpochInfo,
-        block_validator_tracker: &HashMap<ValidatorId, ValidatorStats>,
-        chunk_validator_tracker: &HashMap<ShardId, HashMap<ValidatorId, ValidatorStats>>,
+        block_validator_tracker: &DenseIdMap<ValidatorStats>,
+        chunk_validator_tracker: &DenseIdMap<DenseIdMap<ValidatorStats>>,
         slashed: &HashMap<AccountId, SlashState>,
         prev_validator_kickout: &HashMap<AccountId, ValidatorKickoutReason>,
     ) -> (HashMap<AccountId, ValidatorKickoutReason>, HashMap<AccountId, BlockChunkValidatorStats>)
@@ -630,39 +648,36 @@ impl EpochManager {
         &mut self,
         last_block_info: &BlockInfo,
         last_block_hash: &CryptoHash,
+        aggregator: EpochInfoAggregator,
     ) -> Result<EpochSummary, EpochError> {
         let epoch_info = self.get_epoch_info(last_block_info.epoch_id())?;
         let next_epoch_id = self.get_next_epoch_id(last_block_hash)?;
         let next_epoch_info = self.get_epoch_info(&next_epoch_id)?;
 
+        // Compute kick outs for validators who are offline.
+        let (kickout, validator_block_chunk_stats) = compute_kickouts_with_history(
+            &aggregator,
+            &epoch_info,
+            &self.config.for_protocol_version(epoch_info.protocol_version()),
+            last_block_info.slashed(),
+            next_epoch_info.validator_kickout(),
+        );
+
+        // Next protocol version calculation.
+        let (versions, total_voting_pledge) = aggregator.version_votes(&epoch_info);
+
         let EpochInfoAggregator {
             block_tracker: block_validator_tracker,
             shard_tracker: chunk_validator_tracker,
             all_power_proposals,
             all_pledge_proposals,
-            version_tracker,
             ..
-        } = self.get_epoch_info_aggregator_upto_last(last_block_hash)?;
+        } = aggregator;
 
         let mut power_proposals = vec![];
         let mut pledge_proposals = vec![];
         let mut validator_kickout = HashMap::new();
 
-        // Next protocol version calculation.
-        let mut versions = HashMap::new();
-        for (validator_id, version) in version_tracker {
-            let pledge = epoch_info.validator_stake(validator_id);
-            *versions.entry(version).or_insert(0) += pledge;
-        }
-        let total_block_producer_pledge: u128 = epoch_info
-            .block_producers_settlement()
-            .iter()
-            .copied()
-            .collect::<HashSet<_>>()
-            .iter()
-            .map(|&id| epoch_info.validator_stake(id))
-            .sum();
-
         let protocol_version =
             if epoch_info.protocol_version() >= UPGRADABILITY_FIX_PROTOCOL_VERSION {
                 next_epoch_info.protocol_version()
@@ -678,7 +693,7 @@ impl EpochManager {
         {
             let numer = *config.protocol_upgrade_pledge_threshold.numer() as u128;
             let denom = *config.protocol_upgrade_pledge_threshold.denom() as u128;
-            let threshold = total_block_producer_pledge * numer / denom;
+            let threshold = total_voting_pledge * numer / denom;
             if pledge > threshold {
                 version
             } else {
@@ -718,18 +733,6 @@ impl EpochManager {
 
         let prev_epoch_last_block_hash =
             *self.get_block_info(last_block_info.epoch_first_block())?.prev_hash();
-        let prev_validator_kickout = next_epoch_info.validator_kickout();
-
-        let config = self.config.for_protocol_version(epoch_info.protocol_version());
-        // Compute kick outs for validators who are offline.
-        let (kickout, validator_block_chunk_stats) = Self::compute_kickout_info(
-            &config,
-            &epoch_info,
-            &block_validator_tracker,
-            &chunk_validator_tracker,
-            slashed_validators,
-            prev_validator_kickout,
-        );
         validator_kickout.extend(kickout);
         debug!(
             target: "epoch_manager",
@@ -767,7 +770,7 @@ impl EpochManager {
                 (&summary.all_power_proposals,&summary.all_pledge_proposals,&summary.validator_kickout)
                 // Add more fields as needed
             },
-            BlockInfo::V2(summary) => {
+            BlockInfo::V2(summary) | BlockInfo::V3(BlockInfoV3 { base: summary, .. }) => {
                 // Now you can access the fields of BlockSummaryV1 through `summary`
                 (&summary.all_power_proposals,&summary.all_pledge_proposals,&summary.validator_kickout)
                 // Add more fields as needed
@@ -775,7 +778,8 @@ impl EpochManager {
         };
 
         //FIXME: This is a hack to get the block reward and minted amount
-        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash)?;
+        let aggregator = self.get_epoch_info_aggregator_upto_last(last_block_hash)?;
+        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash, aggregator)?;
         let EpochSummary {
             //    all_power_proposals,
             //    all_pledge_proposals,
@@ -842,7 +846,9 @@ impl EpochManager {
         last_block_hash: &CryptoHash,
         rng_seed: RngSeed,
     ) -> Result<(), EpochError> {
-        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash)?;
+        let aggregator = self.get_epoch_info_aggregator_upto_last(last_block_hash)?;
+        self.save_chunk_density(store_update, block_info.epoch_id(), &aggregator)?;
+        let epoch_summary = self.collect_blocks_info(block_info, last_block_hash, aggregator)?;
         let epoch_info = self.get_epoch_info(block_info.epoch_id())?;
         let epoch_protocol_version = epoch_info.protocol_version();
         let validator_stake =
@@ -870,7 +876,7 @@ impl EpochManager {
                 (summary.clone().all_power_proposals, summary.clone().all_pledge_proposals, summary.clone().validator_kickout)
                 // Add more fields as needed
             },
-            BlockInfo::V2(summary) => {
+            BlockInfo::V2(summary) | BlockInfo::V3(BlockInfoV3 { base: summary, .. }) => {
                 // Now you can access the fields of BlockSummaryV1 through `summary`
                 (summary.clone().all_power_proposals, summary.clone().all_pledge_proposals, summary.clone().validator_kickout)
                 // Add more fields as needed
@@ -1183,6 +1189,74 @@ impl EpochManager {
         })
     }
 
+    /// Returns the chunk producers of the given shard along with their
+    /// reliability, the share of their chunks in that shard which made it into
+    /// blocks so far in the epoch; see
+    /// [`EpochInfoAggregator::chunk_producer_reliability`].
+    ///
+    /// Only `self.epoch_info_aggregator` is consulted, so this is cheap but
+    /// lags behind the head up to the last final block.  Producers of epochs
+    /// the aggregator has no statistics on get the neutral reliability.
+    pub fn get_chunk_producer_reliability(
+        &self,
+        epoch_id: &EpochId,
+        shard_id: ShardId,
+    ) -> Result<Vec<(AccountId, f64)>, EpochError> {
+        let epoch_info = self.get_epoch_info(epoch_id)?;
+        let chunk_producers =
+            epoch_info.chunk_producers_settlement().get(shard_id as usize).ok_or_else(|| {
+                EpochError::ShardingError(format!(
+                    "Invalid shard ID {} for epoch {:?}",
+                    shard_id, epoch_id
+                ))
+            })?;
+        let aggregator = &self.epoch_info_aggregator;
+        let mut seen = HashSet::new();
+        Ok(chunk_producers
+            .iter()
+            .filter(|validator_id| seen.insert(**validator_id))
+            .map(|validator_id| {
+                let reliability = if &aggregator.epoch_id == epoch_id {
+                    aggregator.chunk_producer_reliability(shard_id, *validator_id)
+                } else {
+                    types::NEUTRAL_CHUNK_PRODUCER_RELIABILITY
+                };
+                (epoch_info.validator_account_id(*validator_id).clone(), reliability)
+            })
+            .collect())
+    }
+
+    /// Returns the share of the expected chunks of the given shard which made
+    /// it into blocks in each of the last `last_n_epochs` finalized epochs,
+    /// oldest first.  Only the compact per-epoch counts written when each
+    /// epoch is finalized are read, which go back [`CHUNK_DENSITY_RETENTION`]
+    /// epochs at most.  See [`ChunkDensityHistory::shard_density`].
+    pub fn get_shard_chunk_density(
+        &self,
+        shard_id: ShardId,
+        last_n_epochs: usize,
+    ) -> Result<Vec<(EpochId, f64)>, EpochError> {
+        Ok(self.get_chunk_density_history()?.shard_density(shard_id, last_n_epochs))
+    }
+
+    fn get_chunk_density_history(&self) -> Result<ChunkDensityHistory, EpochError> {
+        Ok(self.store.get_ser(DBCol::Misc, CHUNK_DENSITY_KEY)?.unwrap_or_default())
+    }
+
+    /// Adds the chunk counts of the epoch being finalized, as summed up by
+    /// `aggregator`, to the [`ChunkDensityHistory`].
+    fn save_chunk_density(
+        &self,
+        store_update: &mut StoreUpdate,
+        epoch_id: &EpochId,
+        aggregator: &EpochInfoAggregator,
+    ) -> Result<(), EpochError> {
+        let mut history = self.get_chunk_density_history()?;
+        history.record(epoch_id.clone(), aggregator.shard_chunk_counts(), CHUNK_DENSITY_RETENTION);
+        store_update.set_ser(DBCol::Misc, CHUNK_DENSITY_KEY, &history)?;
+        Ok(())
+    }
+
     /// Returns the list of chunk validators for the given shard_id and height.
     pub fn get_chunk_validators(
         &self,
@@ -1697,9 +1771,9 @@ impl EpochManager {
                                 if let Some(stats) = tracker.get(&(validator_id as u64)) {
                                     chunk_stats.produced += stats.produced;
                                     chunk_stats.expected += stats.expected;
-                                    *chunks_produced_by_shard.entry(*shard).or_insert(0) +=
+                                    *chunks_produced_by_shard.entry(shard).or_insert(0) +=
                                         stats.produced;
-                                    *chunks_expected_by_shard.entry(*shard).or_insert(0) +=
+                                    *chunks_expected_by_shard.entry(shard).or_insert(0) +=
                                         stats.expected;
                                 }
                             }
@@ -1804,7 +1878,7 @@ impl EpochManager {
             pledge_proposals = ?block_header_info.pledge_proposals,
             "add_validator_proposals");
         // Deal with validator proposals and epoch finishing.
-        let block_info = BlockInfo::new(
+        let mut block_info = BlockInfo::new(
             block_header_info.hash,
             block_header_info.height,
             block_header_info.last_finalized_height,
@@ -1833,6 +1907,7 @@ impl EpochManager {
             vec![],
             Default::default(),
             Default::default());
+        block_info.set_chunk_producer_versions(block_header_info.chunk_producer_versions);
         let rng_seed = block_header_info.random_value.0;
         self.record_block_info(block_info, rng_seed)
     }
@@ -1877,7 +1952,10 @@ impl EpochManager {
             if block_header_info.hash ==  CryptoHash::default() {
             BlockSummary::default()
         } else {
-            let BlockInfo::V2(BlockInfoV2{
+            let (BlockInfo::V2(prev_block_info)
+            | BlockInfo::V3(BlockInfoV3 { base: prev_block_info, .. })) =
+                &*self.get_block_info(&block_header_info.prev_hash)? else { todo!() };
+            let BlockInfoV2 {
                 validators,
                 validator_to_index,
                 block_producers_settlement,
@@ -1893,7 +1971,7 @@ impl EpochManager {
                 all_pledge_proposals,
                 validator_kickout,
                 validator_mandates, ..
-            }) = &*self.get_block_info(&block_header_info.prev_hash)? else { todo!() };
+            } = prev_block_info;
             let all_power_proposals : Vec<_> = remove_duplicate_power_proposals(all_power_proposals.clone().into_iter().chain(block_header_info.power_proposals.clone().into_iter()).collect());
             let all_pledge_proposals : Vec<_> = remove_duplicate_pledge_proposals(all_pledge_proposals.clone().into_iter().chain(block_header_info.pledge_proposals.clone().into_iter()).collect());
 
@@ -1938,7 +2016,7 @@ impl EpochManager {
 
         // end customized by James Savechives
         // Deal with validator proposals and epoch finishing.
-        let block_info = BlockInfo::new(
+        let mut block_info = BlockInfo::new(
             block_header_info.hash,
             block_header_info.height,
             block_header_info.last_finalized_height,
@@ -1970,6 +2048,7 @@ impl EpochManager {
             validator_mandates
             // end customized by James Savechives
         );
+        block_info.set_chunk_producer_versions(block_header_info.chunk_producer_versions);
 
         debug!(target: "epoch_manager", "the random value is: {:?}, the validators value is: {:?}, the block producers settlement from block_info is: {:?}",
         block_header_info.random_value,
@@ -2152,6 +2231,56 @@ impl EpochManager {
         Ok(shard_layout)
     }
 
+    /// Returns the changes in block and chunk producers between two epochs.
+    pub fn validator_diff(
+        &self,
+        prev_epoch_id: &EpochId,
+        next_epoch_id: &EpochId,
+    ) -> Result<ValidatorDiff, EpochError> {
+        let prev_epoch_info = self.get_epoch_info(prev_epoch_id)?;
+        let next_epoch_info = self.get_epoch_info(next_epoch_id)?;
+        Ok(ValidatorDiff::new(
+            prev_epoch_id.clone(),
+            &prev_epoch_info,
+            &self.get_shard_layout(prev_epoch_id)?,
+            next_epoch_id.clone(),
+            &next_epoch_info,
+            &self.get_shard_layout(next_epoch_id)?,
+        ))
+    }
+
+    /// Totals of the active validators in the epoch of `block_hash`.
+    pub fn network_totals(&self, block_hash: &CryptoHash) -> Result<NetworkTotals, EpochError> {
+        let epoch_id = self.get_epoch_id(block_hash)?;
+        let epoch_info = self.get_epoch_info(&epoch_id)?;
+        Ok(NetworkTotals::active(epoch_id, &epoch_info))
+    }
+
+    /// Totals of the validators in the epoch of `block_hash` with the proposals made in that
+    /// epoch up to `block_hash` applied. This goes through
+    /// `get_epoch_info_aggregator_upto_last()`, so it can be expensive.
+    pub fn pending_network_totals(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<NetworkTotals, EpochError> {
+        let aggregator = self.get_epoch_info_aggregator_upto_last(block_hash)?;
+        let epoch_info = self.get_epoch_info(&aggregator.epoch_id)?;
+        Ok(NetworkTotals::pending(&epoch_info, &aggregator))
+    }
+
+    /// Returns what happens to `account_id` in the epoch after the one of `block_hash`.
+    /// Validators of the next epoch are selected at the end of the previous one, so this is
+    /// final as soon as the block is known.
+    pub fn validator_status_next_epoch(
+        &self,
+        account_id: &AccountId,
+        block_hash: &CryptoHash,
+    ) -> Result<NextEpochStatus, EpochError> {
+        let next_epoch_id = self.get_next_epoch_id(block_hash)?;
+        let next_epoch_info = self.get_epoch_info(&next_epoch_id)?;
+        Ok(NextEpochStatus::new(account_id, &next_epoch_info))
+    }
+
     pub fn will_shard_layout_change(&self, parent_hash: &CryptoHash) -> Result<bool, EpochError> {
         let epoch_id = self.get_epoch_id_from_prev_block(parent_hash)?;
         let next_epoch_id = self.get_next_epoch_id_from_prev_block(parent_hash)?;
@@ -2366,6 +2495,24 @@ impl EpochManager {
         let epoch_info = self.get_epoch_info(&epoch_id)?;
 
         let mut aggregator = EpochInfoAggregator::new(epoch_id.clone(), *block_hash);
+        // Blocks not yet added to the aggregator, from the newest to the
+        // oldest, and the height of the parent of the oldest one.  When
+        // catching up after being offline there can be many thousands of
+        // them, so they are fed to the aggregator in batches.
+        let mut batch = Vec::new();
+        let mut batch_prev_height = 0;
+        let flush = |aggregator: &mut EpochInfoAggregator,
+                     batch: &mut Vec<Arc<BlockInfo>>,
+                     prev_height: BlockHeight| {
+            batch.reverse();
+            if let Err(err) = aggregator.update_range(batch, &epoch_info, prev_height) {
+                // Blocks out of order are left out of the batch below, so this
+                // isn't expected to happen.
+                warn!(target: "epoch_manager", %err, "Skipping batch of blocks out of order");
+                metrics::BLOCKS_OUT_OF_ORDER_SKIPPED.inc_by(batch.len() as u64);
+            }
+            batch.clear();
+        };
         let mut cur_hash = *block_hash;
         Ok(Some(loop {
             #[cfg(test)]
@@ -2374,9 +2521,6 @@ impl EpochManager {
                     .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
             }
 
-            // To avoid cloning BlockInfo we need to first get reference to the
-            // current block, but then drop it so that we can call
-            // get_block_info for previous block.
             let block_info = self.get_block_info(&cur_hash)?;
             let prev_hash = *block_info.prev_hash();
             let different_epoch = &epoch_id != block_info.epoch_id();
@@ -2388,20 +2532,42 @@ impl EpochManager {
                 // belongs to different epoch or we’re on different fork (though
                 // the latter should never happen).  In either case, the
                 // aggregator contains full epoch information.
+                flush(&mut aggregator, &mut batch, batch_prev_height);
                 break (aggregator, true);
             }
 
             let prev_info = self.get_block_info(&prev_hash)?;
-            let prev_height = prev_info.height();
             let prev_epoch = prev_info.epoch_id().clone();
+            let (prev_height, height) = (prev_info.height(), block_info.height());
+            debug_assert!(
+                prev_height < height,
+                "#{cur_hash} {height} comes after #{prev_hash} {prev_height}",
+            );
 
-            let block_info = self.get_block_info(&cur_hash)?;
-            aggregator.update_tail(&block_info, &epoch_info, prev_height);
+            if prev_height < height {
+                batch_prev_height = prev_height;
+                batch.push(block_info);
+                if batch.len() >= AGGREGATOR_UPDATE_BATCH_SIZE {
+                    flush(&mut aggregator, &mut batch, batch_prev_height);
+                }
+            } else {
+                // Counting the block would corrupt the stats, so it's left out.
+                // The blocks after it in the batch are added on their own.
+                let err = BlockOrderError { prev: prev_height, current: height };
+                warn!(
+                    target: "epoch_manager",
+                    block_hash = %cur_hash,
+                    %err,
+                    "Skipping block out of order");
+                metrics::BLOCKS_OUT_OF_ORDER_SKIPPED.inc();
+                flush(&mut aggregator, &mut batch, batch_prev_height);
+            }
 
             if prev_hash == self.epoch_info_aggregator.last_block_hash {
                 // We’ve reached sync point of the old aggregator.  If old
                 // aggregator was for a different epoch, we have full info in
                 // our aggregator; otherwise we don’t.
+                flush(&mut aggregator, &mut batch, batch_prev_height);
                 break (aggregator, epoch_id != prev_epoch);
             }
 
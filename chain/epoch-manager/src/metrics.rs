@@ -0,0 +1,10 @@
+use unc_o11y::metrics::{try_create_int_counter, IntCounter};
+use once_cell::sync::Lazy;
+
+pub static BLOCKS_OUT_OF_ORDER_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "unc_epoch_manager_blocks_out_of_order_skipped_total",
+        "Number of blocks left out of the epoch info aggregator because their height was not above the height of their parent",
+    )
+    .unwrap()
+});
@@ -3,6 +3,7 @@ use std::collections::{BTreeMap, HashMap};
 use unc_primitives::types::{EpochId, Power};
 use unc_store::Store;
 use num_rational::Ratio;
+use rand::Rng;
 
 use crate::proposals::find_threshold;
 use crate::RewardCalculator;
@@ -10,7 +11,7 @@ use crate::RngSeed;
 use crate::{BlockInfo, EpochManager};
 use unc_crypto::{KeyType, SecretKey};
 use unc_primitives::challenge::SlashedValidator;
-use unc_primitives::epoch_manager::block_info::BlockInfoV2;
+use unc_primitives::epoch_manager::block_info::{BlockInfoV2, BlockInfoV3};
 use unc_primitives::epoch_manager::epoch_info::EpochInfo;
 use unc_primitives::epoch_manager::{AllEpochConfig, EpochConfig, ValidatorWeight};
 use unc_primitives::hash::{hash, CryptoHash};
@@ -21,7 +22,7 @@ use unc_primitives::types::{
 };
 use unc_primitives::utils::get_num_seats_per_shard;
 use unc_primitives::validator_mandates::{ValidatorMandates, ValidatorMandatesConfig};
-use unc_primitives::version::PROTOCOL_VERSION;
+use unc_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use unc_store::test_utils::create_test_store;
 
 use unc_primitives::shard_layout::ShardLayout;
@@ -527,3 +528,116 @@ pub fn block_info(
 pub fn record_with_block_info(epoch_manager: &mut EpochManager, block_info: BlockInfo) {
     epoch_manager.record_block_info(block_info, [0; 32]).unwrap().commit().unwrap();
 }
+
+/// Epoch info with `num_validators` validators of equal pledge, all of which
+/// produce blocks and chunks in each of the `num_shards` shards.
+pub fn aggregator_test_epoch_info(num_validators: usize, num_shards: usize) -> EpochInfo {
+    let accounts = (0..num_validators)
+        .map(|i| (format!("test{}", i).parse().unwrap(), 0, 1_000_000))
+        .collect();
+    let settlement: Vec<ValidatorId> = (0..num_validators as ValidatorId).collect();
+    epoch_info(
+        0,
+        accounts,
+        settlement.clone(),
+        vec![settlement; num_shards],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    )
+}
+
+/// Epoch info of `protocol_version` in which `test0` produces all blocks and
+/// each of the chunk-only producers `test1`, `test2`, ... produces the chunks
+/// of its own shard, with the given pledges.
+pub fn chunk_only_producers_epoch_info(
+    protocol_version: ProtocolVersion,
+    block_producer_pledge: Balance,
+    chunk_only_pledges: &[Balance],
+) -> EpochInfo {
+    let validators: Vec<_> = std::iter::once(block_producer_pledge)
+        .chain(chunk_only_pledges.iter().copied())
+        .enumerate()
+        .map(|(i, pledge)| {
+            let account_id: AccountId = format!("test{}", i).parse().unwrap();
+            let secret_key = SecretKey::from_seed(KeyType::ED25519, account_id.as_ref());
+            ValidatorPowerAndPledge::new(account_id, secret_key.public_key(), 1, pledge)
+        })
+        .collect();
+    let validator_to_index = validators
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.account_id().clone(), i as ValidatorId))
+        .collect();
+    let num_shards = chunk_only_pledges.len();
+    let chunk_producers_settlement = (1..=num_shards as ValidatorId).map(|id| vec![id]).collect();
+    let config = ValidatorMandatesConfig::new(block_producer_pledge, 0, num_shards);
+    let validator_mandates = ValidatorMandates::new(config, &validators);
+    EpochInfo::new(
+        0,
+        validators,
+        validator_to_index,
+        vec![0],
+        chunk_producers_settlement,
+        vec![],
+        vec![],
+        HashMap::new(),
+        BTreeMap::new(),
+        BTreeMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+        0,
+        block_producer_pledge,
+        protocol_version,
+        TEST_SEED,
+        validator_mandates,
+    )
+}
+
+/// Generates a random chain of `num_blocks` blocks starting right after
+/// `prev_height`, ordered by increasing height.  Heights are skipped, chunks
+/// are missed and proposals are made at random.  Block hashes aren't set
+/// since the aggregator doesn't look at them.
+pub fn random_chain_segment<R: Rng>(
+    rng: &mut R,
+    prev_height: BlockHeight,
+    num_blocks: usize,
+    num_shards: usize,
+    num_validators: usize,
+) -> Vec<BlockInfo> {
+    let mut height = prev_height;
+    (0..num_blocks)
+        .map(|_| {
+            height += if rng.gen_bool(0.8) { 1 } else { rng.gen_range(2..5) };
+            let account = |rng: &mut R| -> AccountId {
+                format!("test{}", rng.gen_range(0..num_validators)).parse().unwrap()
+            };
+            let power_proposals = if rng.gen_bool(0.1) {
+                vec![do_power(account(rng), rng.gen_range(0..1_000))]
+            } else {
+                vec![]
+            };
+            let pledge_proposals = if rng.gen_bool(0.1) {
+                vec![pledge(account(rng), rng.gen_range(0..1_000_000))]
+            } else {
+                vec![]
+            };
+            let base = BlockInfoV2 {
+                height,
+                power_proposals,
+                pledge_proposals,
+                chunk_mask: (0..num_shards).map(|_| rng.gen_bool(0.9)).collect(),
+                latest_protocol_version: PROTOCOL_VERSION - rng.gen_range(0..2),
+                ..Default::default()
+            };
+            let chunk_producer_versions = (0..num_shards)
+                .map(|_| rng.gen_bool(0.8).then(|| PROTOCOL_VERSION - rng.gen_range(0..2)))
+                .collect();
+            BlockInfo::V3(BlockInfoV3 { base, chunk_producer_versions })
+        })
+        .collect()
+}
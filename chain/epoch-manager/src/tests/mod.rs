@@ -3,22 +3,29 @@ mod random_epochs;
 use super::*;
 use crate::reward_calculator::NUM_NS_IN_SECOND;
 use crate::test_utils::{
-    block_info, change_power, default_reward_calculator, epoch_config,
-    epoch_config_with_production_config, epoch_info, epoch_info_with_num_seats, hash_range,
-    record_block, record_block_with_final_block_hash, record_block_with_slashes,
-    record_with_block_info, reward, setup_default_epoch_manager, setup_epoch_manager, do_power,
-    DEFAULT_TOTAL_SUPPLY,
+    aggregator_test_epoch_info, block_info, change_power, chunk_only_producers_epoch_info,
+    default_reward_calculator, epoch_config, epoch_config_with_production_config, epoch_info,
+    epoch_info_with_num_seats, hash_range, random_chain_segment, record_block,
+    record_block_with_final_block_hash, record_block_with_slashes, record_with_block_info, reward,
+    setup_default_epoch_manager, setup_epoch_manager, do_power, pledge, DEFAULT_TOTAL_SUPPLY,
+};
+use crate::types::{
+    AggregatorDelta, ChunkDensityHistory, DenseIdMap, ShardChunkCounts, ShardValidatorDiff,
+    ValidatorChange, MIN_EXPECTED_CHUNKS_FOR_RELIABILITY, NEUTRAL_CHUNK_PRODUCER_RELIABILITY,
 };
 use unc_primitives::account::id::AccountIdRef;
 use unc_primitives::challenge::SlashedValidator;
 use unc_primitives::epoch_manager::EpochConfig;
 use unc_primitives::hash::hash;
-use unc_primitives::shard_layout::ShardLayout;
+use unc_primitives::shard_layout::{ShardLayout, ShardUId};
 use unc_primitives::types::ValidatorKickoutReason::{NotEnoughBlocks, NotEnoughChunks};
 use unc_primitives::version::ProtocolFeature::SimpleNightshade;
+use unc_primitives::version::ProtocolFeature;
 use unc_primitives::version::PROTOCOL_VERSION;
 use unc_store::test_utils::create_test_store;
 use num_rational::Ratio;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use unc_primitives::types::Power;
 
 impl EpochManager {
@@ -1173,6 +1180,193 @@ fn test_expected_chunks_prev_block_not_produced() {
     );
 }
 
+#[test]
+fn test_chunk_producer_reliability_ratio() {
+    let mut aggregator = EpochInfoAggregator::new(EpochId::default(), CryptoHash::default());
+    let min = MIN_EXPECTED_CHUNKS_FOR_RELIABILITY;
+    let tracker = HashMap::from([
+        (0, ValidatorStats { produced: 0, expected: 0 }),
+        (1, ValidatorStats { produced: 0, expected: min - 1 }),
+        (2, ValidatorStats { produced: 0, expected: min }),
+        (3, ValidatorStats { produced: min, expected: min }),
+        (4, ValidatorStats { produced: 3, expected: 4 * min }),
+        // Inconsistent, but shouldn't make a producer look more than fully reliable.
+        (5, ValidatorStats { produced: min + 1, expected: min }),
+    ]);
+    aggregator.shard_tracker.insert(0, tracker.into());
+
+    // Nothing known about the validator or the shard.
+    assert_eq!(aggregator.chunk_producer_reliability(0, 6), NEUTRAL_CHUNK_PRODUCER_RELIABILITY);
+    assert_eq!(aggregator.chunk_producer_reliability(1, 0), NEUTRAL_CHUNK_PRODUCER_RELIABILITY);
+    // Too few expected chunks to tell, including none at all.
+    assert_eq!(aggregator.chunk_producer_reliability(0, 0), NEUTRAL_CHUNK_PRODUCER_RELIABILITY);
+    assert_eq!(aggregator.chunk_producer_reliability(0, 1), NEUTRAL_CHUNK_PRODUCER_RELIABILITY);
+    assert_eq!(aggregator.chunk_producer_reliability(0, 2), 0.0);
+    assert_eq!(aggregator.chunk_producer_reliability(0, 3), 1.0);
+    assert_eq!(aggregator.chunk_producer_reliability(0, 4), 3.0 / (4 * min) as f64);
+    assert_eq!(aggregator.chunk_producer_reliability(0, 5), 1.0);
+}
+
+#[test]
+fn test_chunk_producer_reliability() {
+    let pledge_amount = 1_000_000;
+    let validators = vec![
+        ("test1".parse().unwrap(), pledge_amount),
+        ("test2".parse().unwrap(), pledge_amount),
+        ("test3".parse().unwrap(), pledge_amount),
+    ];
+    let epoch_length = 50;
+    let total_supply = pledge_amount * validators.len() as u128;
+    let mut epoch_manager = setup_epoch_manager(
+        validators,
+        epoch_length,
+        1,
+        3,
+        0,
+        90,
+        90,
+        0,
+        default_reward_calculator(),
+    );
+    let rng_seed = [0; 32];
+    let hashes = hash_range(40);
+    record_block(&mut epoch_manager, Default::default(), hashes[0], 0, vec![]);
+    let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&hashes[0]).unwrap();
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id).unwrap();
+
+    // Early in the epoch nothing is known yet.
+    let reliability = epoch_manager.get_chunk_producer_reliability(&epoch_id, 0).unwrap();
+    assert_eq!(reliability.len(), 3);
+    assert!(reliability.iter().all(|(_, r)| *r == NEUTRAL_CHUNK_PRODUCER_RELIABILITY));
+
+    for (i, curr_block) in hashes.iter().enumerate().skip(1) {
+        let height = i as u64;
+        let prev_block = hashes[i - 1];
+        // test1 misses all its chunks
+        let should_produce_chunk =
+            EpochManager::chunk_producer_from_info(&epoch_info, height, 0) != 0;
+        epoch_manager
+            .record_block_info(
+                block_info(
+                    *curr_block,
+                    height,
+                    height - 1,
+                    prev_block,
+                    prev_block,
+                    epoch_id.0,
+                    vec![should_produce_chunk],
+                    total_supply,
+                ),
+                rng_seed,
+            )
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+    let test1_stats = &epoch_manager.epoch_info_aggregator.shard_tracker[&0][&0];
+    assert_eq!(test1_stats.produced, 0);
+    assert!(test1_stats.expected >= MIN_EXPECTED_CHUNKS_FOR_RELIABILITY);
+
+    let reliability = epoch_manager.get_chunk_producer_reliability(&epoch_id, 0).unwrap();
+    for (account_id, reliability) in reliability {
+        let expected = if account_id.as_str() == "test1" { 0.0 } else { 1.0 };
+        assert_eq!(reliability, expected, "{}", account_id);
+    }
+    assert!(epoch_manager.get_chunk_producer_reliability(&epoch_id, 1).is_err());
+    // The aggregator has no statistics for other epochs, like the genesis one.
+    assert_ne!(epoch_id, EpochId::default());
+    let reliability =
+        epoch_manager.get_chunk_producer_reliability(&EpochId::default(), 0).unwrap();
+    assert!(reliability.iter().all(|(_, r)| *r == NEUTRAL_CHUNK_PRODUCER_RELIABILITY));
+}
+
+#[test]
+fn test_chunk_density_history() {
+    let counts = |produced, expected| vec![ShardChunkCounts { shard_id: 0, produced, expected }];
+    let mut history = ChunkDensityHistory::default();
+    history.record(EpochId(hash(&[1])), counts(1, 2), 2);
+    history.record(EpochId(hash(&[2])), counts(0, 0), 2);
+    // Finalized again on another fork.
+    history.record(EpochId(hash(&[1])), counts(3, 4), 2);
+    assert_eq!(history.shard_density(0, 10), vec![(EpochId(hash(&[1])), 0.75)]);
+    assert_eq!(history.shard_density(1, 10), vec![]);
+
+    history.record(EpochId(hash(&[3])), counts(2, 2), 2);
+    assert_eq!(history.shard_density(0, 10), vec![(EpochId(hash(&[3])), 1.0)]);
+    assert_eq!(history.shard_density(0, 0), vec![]);
+}
+
+#[test]
+fn test_shard_chunk_density() {
+    let pledge_amount = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), pledge_amount), ("test2".parse().unwrap(), pledge_amount)];
+    let epoch_length = 10;
+    let total_supply = pledge_amount * validators.len() as u128;
+    let mut em = setup_epoch_manager(
+        validators,
+        epoch_length,
+        2,
+        2,
+        0,
+        0,
+        0,
+        0,
+        default_reward_calculator(),
+    );
+    let h = hash_range((4 * epoch_length) as usize);
+    record_with_block_info(
+        &mut em,
+        block_info(
+            h[0],
+            0,
+            0,
+            Default::default(),
+            Default::default(),
+            h[0],
+            vec![true],
+            total_supply,
+        ),
+    );
+    // (epoch, produced, expected) of the chunks of shard 1, in the order of the epochs
+    let mut tallies: Vec<(EpochId, u64, u64)> = vec![];
+    for height in 1..(4 * epoch_length) {
+        let i = height as usize;
+        let epoch_id = em.get_epoch_id_from_prev_block(&h[i - 1]).unwrap();
+        if tallies.last().map(|(id, _, _)| id) != Some(&epoch_id) {
+            tallies.push((epoch_id.clone(), 0, 0));
+        }
+        // Shard 1 misses more chunks in each epoch, shard 0 none.
+        let missed = height % 4 < tallies.len() as u64;
+        let tally = tallies.last_mut().unwrap();
+        tally.1 += !missed as u64;
+        tally.2 += 1;
+        let chunk_mask = vec![true, !missed];
+        record_with_block_info(
+            &mut em,
+            block_info(h[i], height, height, h[i - 1], h[i - 1], h[i], chunk_mask, total_supply),
+        );
+    }
+
+    let density = em.get_shard_chunk_density(1, 100).unwrap();
+    // Only finalized epochs have counts, so not the last one.
+    assert!(density.len() >= 2 && density.len() < tallies.len());
+    for ((epoch_id, density), (tally_epoch_id, produced, expected)) in
+        density.iter().zip(tallies.iter())
+    {
+        assert_eq!(epoch_id, tally_epoch_id);
+        assert_eq!(*density, *produced as f64 / *expected as f64);
+    }
+
+    let shard0_density = em.get_shard_chunk_density(0, 100).unwrap();
+    assert_eq!(shard0_density.len(), density.len());
+    assert!(shard0_density.iter().all(|(_, density)| *density == 1.0));
+
+    let last_two = em.get_shard_chunk_density(1, 2).unwrap();
+    assert_eq!(last_two, density[density.len() - 2..]);
+    assert_eq!(em.get_shard_chunk_density(2, 100).unwrap(), vec![]);
+}
+
 fn update_tracker(
     epoch_info: &EpochInfo,
     heights: std::ops::Range<BlockHeight>,
@@ -1278,6 +1472,432 @@ fn test_epoch_info_aggregator_data_loss() {
     );
 }
 
+/// Feeding blocks to the aggregator in batches with `update_range` must give
+/// exactly the same result as calling `update_tail` for each block.
+#[test]
+fn test_epoch_info_aggregator_update_range() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..200 {
+        let num_validators = rng.gen_range(1..8);
+        let num_shards = rng.gen_range(1..5);
+        let epoch_info = aggregator_test_epoch_info(num_validators, num_shards);
+        let num_blocks = rng.gen_range(1..300);
+        let prev_height = rng.gen_range(0..100);
+        let blocks = random_chain_segment(
+            &mut rng,
+            prev_height,
+            num_blocks + 1,
+            num_shards,
+            num_validators,
+        );
+
+        // Both aggregators already have the last block, so that entries from
+        // it take precedence over those from the rest of the segment.
+        let mut expected = EpochInfoAggregator::default();
+        expected
+            .update_tail(&blocks[num_blocks], &epoch_info, blocks[num_blocks - 1].height())
+            .unwrap();
+        let mut actual = expected.clone();
+
+        for i in (0..num_blocks).rev() {
+            let prev = if i == 0 { prev_height } else { blocks[i - 1].height() };
+            expected.update_tail(&blocks[i], &epoch_info, prev).unwrap();
+        }
+
+        // Split the rest into random batches, added from the newest one.
+        let mut end = num_blocks;
+        while end > 0 {
+            let start = end - rng.gen_range(1..=end);
+            let prev = if start == 0 { prev_height } else { blocks[start - 1].height() };
+            actual.update_range(&blocks[start..end], &epoch_info, prev).unwrap();
+            end = start;
+        }
+
+        assert_eq!(borsh::to_vec(&actual).unwrap(), borsh::to_vec(&expected).unwrap());
+    }
+}
+
+/// A block whose height isn't above its parent's is rejected by both
+/// `update_tail` and `update_range`, leaving the aggregator as it was.
+#[test]
+fn test_epoch_info_aggregator_block_order() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let epoch_info = aggregator_test_epoch_info(3, 2);
+    let blocks = random_chain_segment(&mut rng, 10, 4, 2, 3);
+    let mut aggregator = EpochInfoAggregator::default();
+    aggregator.update_tail(&blocks[3], &epoch_info, blocks[2].height()).unwrap();
+    let before = borsh::to_vec(&aggregator).unwrap();
+
+    // Equal and inverted heights.
+    let height = blocks[2].height();
+    for prev in [height, height + 1] {
+        let err = BlockOrderError { prev, current: height };
+        assert_eq!(aggregator.update_tail(&blocks[2], &epoch_info, prev), Err(err));
+        assert_eq!(borsh::to_vec(&aggregator).unwrap(), before);
+    }
+    let equal = [&blocks[0], &blocks[1], &blocks[1]];
+    let inverted = [&blocks[0], &blocks[2], &blocks[1]];
+    for range in [equal, inverted] {
+        let err = BlockOrderError { prev: range[1].height(), current: range[2].height() };
+        assert_eq!(aggregator.update_range(&range, &epoch_info, 10), Err(err));
+        assert_eq!(borsh::to_vec(&aggregator).unwrap(), before);
+    }
+
+    // Blocks in order are counted as before.
+    let mut expected = aggregator.clone();
+    for i in (0..3).rev() {
+        let prev = if i == 0 { 10 } else { blocks[i - 1].height() };
+        expected.update_tail(&blocks[i], &epoch_info, prev).unwrap();
+    }
+    aggregator.update_range(&blocks[..3], &epoch_info, 10).unwrap();
+    assert_ne!(borsh::to_vec(&aggregator).unwrap(), before);
+    assert_eq!(borsh::to_vec(&aggregator).unwrap(), borsh::to_vec(&expected).unwrap());
+}
+
+/// Aggregates `blocks[start..end]` with `update_tail`, newest block first.
+fn aggregate_tail(
+    blocks: &[BlockInfo],
+    start: usize,
+    end: usize,
+    epoch_info: &EpochInfo,
+    prev_height: BlockHeight,
+) -> EpochInfoAggregator {
+    let mut aggregator = EpochInfoAggregator::default();
+    for i in (start..end).rev() {
+        let prev = if i == 0 { prev_height } else { blocks[i - 1].height() };
+        aggregator.update_tail(&blocks[i], epoch_info, prev).unwrap();
+    }
+    aggregator
+}
+
+/// Splitting a chain segment at arbitrary points, aggregating each piece on
+/// its own and combining the pieces with either `merge` or `merge_prefix` must
+/// give the same result as aggregating the whole segment at once.
+#[test]
+fn test_epoch_info_aggregator_merge_splits() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..200 {
+        let num_validators = rng.gen_range(1..8);
+        let num_shards = rng.gen_range(1..5);
+        let epoch_info = aggregator_test_epoch_info(num_validators, num_shards);
+        let num_blocks = rng.gen_range(1..100);
+        let prev_height = rng.gen_range(0..100);
+        let blocks =
+            random_chain_segment(&mut rng, prev_height, num_blocks, num_shards, num_validators);
+
+        let expected = aggregate_tail(&blocks, 0, num_blocks, &epoch_info, prev_height);
+        expected.invariants(Some(&epoch_info)).unwrap();
+
+        let mut splits = (0..rng.gen_range(0..5))
+            .map(|_| rng.gen_range(0..=num_blocks))
+            .chain([0, num_blocks])
+            .collect::<Vec<_>>();
+        splits.sort();
+        let pieces = splits
+            .windows(2)
+            .map(|w| aggregate_tail(&blocks, w[0], w[1], &epoch_info, prev_height))
+            .collect::<Vec<_>>();
+        for piece in pieces.iter() {
+            piece.invariants(Some(&epoch_info)).unwrap();
+        }
+
+        // Oldest piece first, merging the following ones into it.
+        let mut merged = pieces[0].clone();
+        for piece in pieces[1..].iter() {
+            merged.merge(piece.clone());
+        }
+        merged.invariants(Some(&epoch_info)).unwrap();
+        assert_eq!(borsh::to_vec(&merged).unwrap(), borsh::to_vec(&expected).unwrap());
+
+        // Newest piece first, merging the preceding ones into it.
+        let mut merged = pieces[pieces.len() - 1].clone();
+        for piece in pieces[..pieces.len() - 1].iter().rev() {
+            merged.merge_prefix(piece);
+        }
+        merged.invariants(Some(&epoch_info)).unwrap();
+        assert_eq!(borsh::to_vec(&merged).unwrap(), borsh::to_vec(&expected).unwrap());
+    }
+}
+
+/// Applying `new.diff_since(&old)` to `old` must give back exactly `new`, for
+/// an aggregator advancing through a chain segment piece by piece as well as
+/// for one going back to an earlier state, whose delta removes entries.  The
+/// delta must survive a round trip through Borsh and JSON.
+#[test]
+fn test_epoch_info_aggregator_delta() {
+    fn check_delta(new: &EpochInfoAggregator, old: &EpochInfoAggregator) -> AggregatorDelta {
+        let delta = new.diff_since(old);
+        let decoded: AggregatorDelta = borsh::from_slice(&borsh::to_vec(&delta).unwrap()).unwrap();
+        assert_eq!(decoded, delta);
+        let decoded: AggregatorDelta =
+            serde_json::from_str(&serde_json::to_string(&delta).unwrap()).unwrap();
+        assert_eq!(decoded, delta);
+
+        let mut applied = old.clone();
+        applied.apply_delta(delta.clone());
+        assert_eq!(borsh::to_vec(&applied).unwrap(), borsh::to_vec(new).unwrap());
+        delta
+    }
+
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..200 {
+        let num_validators = rng.gen_range(1..8);
+        let num_shards = rng.gen_range(1..5);
+        let epoch_info = aggregator_test_epoch_info(num_validators, num_shards);
+        let num_blocks = rng.gen_range(1..100);
+        let prev_height = rng.gen_range(0..100);
+        let blocks =
+            random_chain_segment(&mut rng, prev_height, num_blocks, num_shards, num_validators);
+
+        let mut splits = (0..rng.gen_range(0..5))
+            .map(|_| rng.gen_range(0..=num_blocks))
+            .chain([0, num_blocks])
+            .collect::<Vec<_>>();
+        splits.sort();
+        let mut old = EpochInfoAggregator::default();
+        for w in splits.windows(2) {
+            let mut new = old.clone();
+            new.merge(aggregate_tail(&blocks, w[0], w[1], &epoch_info, prev_height));
+
+            let delta = check_delta(&new, &old);
+            assert!(delta.removed.is_empty());
+            let unchanged = AggregatorDelta {
+                epoch_id: new.epoch_id.clone(),
+                last_block_hash: new.last_block_hash,
+                ..Default::default()
+            };
+            assert_eq!(check_delta(&new, &new), unchanged);
+            check_delta(&old, &new);
+            old = new;
+        }
+    }
+}
+
+/// `DenseIdMap` must be Borsh-serialized exactly like the `HashMap`s the
+/// aggregator trackers used to be, so that aggregators already stored in the
+/// database can still be read.
+#[test]
+fn test_dense_id_map_borsh_compatibility() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        let map: HashMap<u64, ValidatorStats> = (0..rng.gen_range(0..20))
+            .map(|_| {
+                let stats = ValidatorStats { produced: rng.gen(), expected: rng.gen() };
+                (rng.gen_range(0..50), stats)
+            })
+            .collect();
+        let mut dense = DenseIdMap::with_ids(100);
+        dense.extend(map.clone());
+        assert_eq!(dense, map);
+
+        let bytes = borsh::to_vec(&map).unwrap();
+        assert_eq!(borsh::to_vec(&dense).unwrap(), bytes);
+        let decoded: DenseIdMap<ValidatorStats> = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, dense);
+    }
+}
+
+/// From `ChunkProducerUpgradeVotes` on, a chunk-only producer's version is
+/// tracked and counts towards the upgrade with its pledge, out of the pledge of
+/// all validators.  Before it, only the block producer votes.
+#[test]
+fn test_chunk_producer_upgrade_votes() {
+    let new_version = ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version();
+    let old_version = new_version - 1;
+    // test1 produced a chunk on a newer version, test2's version isn't known
+    // and test3 missed its chunk.
+    let block_info = BlockInfo::V3(BlockInfoV3 {
+        base: BlockInfoV2 {
+            height: 2,
+            chunk_mask: vec![true, true, false],
+            latest_protocol_version: old_version,
+            ..Default::default()
+        },
+        chunk_producer_versions: vec![Some(new_version), None, Some(new_version)],
+    });
+
+    let epoch_info = chunk_only_producers_epoch_info(new_version, 100, &[500, 200, 300]);
+    let mut aggregator = EpochInfoAggregator::default();
+    aggregator.update_tail(&block_info, &epoch_info, 1).unwrap();
+    assert_eq!(aggregator.version_tracker, HashMap::from([(0, old_version), (1, new_version)]));
+    let (versions, total_pledge) = aggregator.version_votes(&epoch_info);
+    assert_eq!(versions, HashMap::from([(old_version, 100), (new_version, 500)]));
+    assert_eq!(total_pledge, 1100);
+    let mut range = EpochInfoAggregator::default();
+    range.update_range(&[&block_info], &epoch_info, 1).unwrap();
+    assert_eq!(borsh::to_vec(&range).unwrap(), borsh::to_vec(&aggregator).unwrap());
+
+    let epoch_info = chunk_only_producers_epoch_info(old_version, 100, &[500, 200, 300]);
+    let mut aggregator = EpochInfoAggregator::default();
+    aggregator.update_tail(&block_info, &epoch_info, 1).unwrap();
+    assert_eq!(aggregator.version_tracker, HashMap::from([(0, old_version)]));
+    let (versions, total_pledge) = aggregator.version_votes(&epoch_info);
+    assert_eq!(versions, HashMap::from([(old_version, 100)]));
+    assert_eq!(total_pledge, 100);
+}
+
+/// The block producer's own version wins over the one it advertised as the
+/// producer of a chunk of the same block.
+#[test]
+fn test_chunk_producer_upgrade_votes_block_producer_first() {
+    let new_version = ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version();
+    let epoch_info = aggregator_test_epoch_info(1, 1);
+    let block_info = BlockInfo::V3(BlockInfoV3 {
+        base: BlockInfoV2 {
+            height: 1,
+            chunk_mask: vec![true],
+            latest_protocol_version: new_version,
+            ..Default::default()
+        },
+        chunk_producer_versions: vec![Some(new_version + 1)],
+    });
+    let mut aggregator = EpochInfoAggregator::default();
+    aggregator.update_tail(&block_info, &epoch_info, 0).unwrap();
+    assert_eq!(aggregator.version_tracker, HashMap::from([(0, new_version)]));
+}
+
+/// Chunk producer versions reach the upgrade vote through the
+/// `BlockHeaderInfo`s recorded by `add_validator_proposals`.  The vote is out
+/// of the pledge of all validators either way.
+#[test]
+fn test_chunk_producer_upgrade_votes_from_block_header_info() {
+    let new_version = ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version();
+    let amount: Power = 1_000_000;
+    for with_chunk_versions in [true, false] {
+        let mut epoch_manager = EpochManager::new(
+            create_test_store(),
+            epoch_config(10, 1, 1, 0, 0, 0, 0),
+            new_version,
+            default_reward_calculator(),
+            vec![
+                do_power("test0".parse().unwrap(), amount),
+                do_power("test1".parse().unwrap(), amount - 1),
+            ],
+            vec![
+                pledge("test0".parse().unwrap(), amount as Balance),
+                pledge("test1".parse().unwrap(), amount as Balance - 1),
+            ],
+        )
+        .unwrap();
+        let h = hash_range(9);
+        for (i, hash) in h.iter().enumerate() {
+            let height = i as BlockHeight;
+            let prev_hash = if i == 0 { CryptoHash::default() } else { h[i - 1] };
+            let chunk_producer_versions = if with_chunk_versions && i > 0 {
+                vec![Some(new_version + 1)]
+            } else {
+                vec![]
+            };
+            let block_header_info = BlockHeaderInfo {
+                hash: *hash,
+                prev_hash,
+                height,
+                last_finalized_height: height.saturating_sub(2),
+                last_finalized_block_hash: prev_hash,
+                chunk_mask: if i == 0 { vec![] } else { vec![true] },
+                total_supply: DEFAULT_TOTAL_SUPPLY,
+                latest_protocol_version: new_version,
+                timestamp_nanosec: height * NUM_NS_IN_SECOND,
+                chunk_producer_versions,
+                ..Default::default()
+            };
+            epoch_manager.add_validator_proposals(block_header_info).unwrap().commit().unwrap();
+        }
+
+        let last_block_info = epoch_manager.get_block_info(&h[8]).unwrap();
+        let epoch_info = epoch_manager.get_epoch_info(last_block_info.epoch_id()).unwrap();
+        let block_producers: HashSet<ValidatorId> =
+            epoch_info.block_producers_settlement().iter().copied().collect();
+        assert!((1..9).any(|height| {
+            let chunk_producer = EpochManager::chunk_producer_from_info(&epoch_info, height, 0);
+            !block_producers.contains(&chunk_producer)
+        }));
+        let block_producers_pledge: Balance =
+            block_producers.iter().map(|id| epoch_info.validator_stake(*id)).sum();
+        let all_pledge: Balance = (0..epoch_info.validators_len() as ValidatorId)
+            .map(|id| epoch_info.validator_stake(id))
+            .sum();
+        assert!(block_producers_pledge < all_pledge);
+
+        let aggregator = epoch_manager.get_epoch_info_aggregator_upto_last(&h[8]).unwrap();
+        let (versions, total_pledge) = aggregator.version_votes(&epoch_info);
+        if with_chunk_versions {
+            assert!(versions.contains_key(&(new_version + 1)), "{:?}", versions);
+            assert_eq!(total_pledge, all_pledge);
+        } else {
+            assert_eq!(versions, HashMap::from([(new_version, block_producers_pledge)]));
+            assert_eq!(total_pledge, all_pledge);
+        }
+    }
+}
+
+/// A chunk-only producer voting for the new version never turns an upgrade
+/// which passes without its vote into one which doesn't: the total the
+/// threshold is a fraction of stays the pledge of all validators.
+#[test]
+fn test_chunk_producer_upgrade_vote_does_not_block_upgrade() {
+    let new_version = ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version();
+    // test0 produces the block and votes for the next version, test1's version
+    // isn't known and test2 produces its chunk on the next version, or not.
+    let votes = |epoch_info: &EpochInfo, chunk_version: Option<ProtocolVersion>| {
+        let block_info = BlockInfo::V3(BlockInfoV3 {
+            base: BlockInfoV2 {
+                height: 2,
+                chunk_mask: vec![false, true],
+                latest_protocol_version: new_version + 1,
+                ..Default::default()
+            },
+            chunk_producer_versions: vec![None, chunk_version],
+        });
+        let mut aggregator = EpochInfoAggregator::default();
+        aggregator.update_tail(&block_info, epoch_info, 1).unwrap();
+        let (versions, total_pledge) = aggregator.version_votes(epoch_info);
+        let pledge = versions.get(&(new_version + 1)).copied().unwrap_or_default();
+        // The 80% threshold of `epoch_config`.
+        (pledge, total_pledge, pledge * 100 > total_pledge * 80)
+    };
+
+    let epoch_info = chunk_only_producers_epoch_info(new_version, 850, &[100, 50]);
+    assert_eq!(votes(&epoch_info, None), (850, 1000, true));
+    assert_eq!(votes(&epoch_info, Some(new_version + 1)), (900, 1000, true));
+
+    // Without test2's vote, test0 doesn't hold 80% of all the pledge, and the
+    // vote doesn't get there either, but the total doesn't depend on it.
+    let epoch_info = chunk_only_producers_epoch_info(new_version, 500, &[400, 100]);
+    assert_eq!(votes(&epoch_info, None), (500, 1000, false));
+    assert_eq!(votes(&epoch_info, Some(new_version + 1)), (600, 1000, false));
+}
+
+#[test]
+fn test_epoch_info_aggregator_invariants() {
+    let epoch_info = aggregator_test_epoch_info(2, 2);
+    let blocks = random_chain_segment(&mut StdRng::seed_from_u64(0), 0, 10, 2, 2);
+    let aggregator = aggregate_tail(&blocks, 0, blocks.len(), &epoch_info, 0);
+    aggregator.invariants(Some(&epoch_info)).unwrap();
+
+    let mut broken = aggregator.clone();
+    broken.block_tracker.insert(0, ValidatorStats { produced: 2, expected: 1 });
+    let err = broken.invariants(None).unwrap_err();
+    assert!(err.contains("2 produced but only 1 expected"), "{}", err);
+
+    let mut broken = aggregator.clone();
+    broken.version_tracker.insert(5, PROTOCOL_VERSION);
+    broken.invariants(None).unwrap();
+    let err = broken.invariants(Some(&epoch_info)).unwrap_err();
+    assert!(err.contains("validator id 5 but the epoch has 2 validators"), "{}", err);
+
+    let mut broken = aggregator.clone();
+    broken.shard_tracker.insert(2, DenseIdMap::new());
+    let err = broken.invariants(Some(&epoch_info)).unwrap_err();
+    assert!(err.contains("shard 2 but the epoch has 2 shards"), "{}", err);
+
+    let mut broken = aggregator;
+    let proposal = crate::test_utils::pledge("test0".parse().unwrap(), 1);
+    broken.all_pledge_proposals.insert("test1".parse().unwrap(), proposal);
+    let err = broken.invariants(None).unwrap_err();
+    assert!(err.contains("proposal of test0 under test1"), "{}", err);
+}
+
 /// Aggregator should still work even if there is a reorg past the last final block.
 #[test]
 fn test_epoch_info_aggregator_reorg_past_final_block() {
@@ -2101,7 +2721,9 @@ fn test_fisherman_kickout() {
 fn set_block_info_protocol_version(info: &mut BlockInfo, protocol_version: ProtocolVersion) {
     match info {
         BlockInfo::V1(v1) => v1.latest_protocol_version = protocol_version,
-        BlockInfo::V2(v2) => v2.latest_protocol_version = protocol_version,
+        BlockInfo::V2(v2) | BlockInfo::V3(BlockInfoV3 { base: v2, .. }) => {
+            v2.latest_protocol_version = protocol_version
+        }
     }
 }
 
@@ -2452,17 +3074,17 @@ fn test_validator_kickout_sanity() {
     let (kickouts, validator_stats) = EpochManager::compute_kickout_info(
         &epoch_config,
         &epoch_info,
-        &HashMap::from([
+        &DenseIdMap::from_iter([
             (0, ValidatorStats { produced: 100, expected: 100 }),
             (1, ValidatorStats { produced: 90, expected: 100 }),
             (2, ValidatorStats { produced: 100, expected: 100 }),
             // test3 will be kicked out
             (3, ValidatorStats { produced: 89, expected: 100 }),
         ]),
-        &HashMap::from([
+        &DenseIdMap::from_iter([
             (
                 0,
-                HashMap::from([
+                DenseIdMap::from_iter([
                     (0, ValidatorStats { produced: 100, expected: 100 }),
                     (1, ValidatorStats { produced: 80, expected: 100 }),
                     (2, ValidatorStats { produced: 70, expected: 100 }),
@@ -2470,7 +3092,7 @@ fn test_validator_kickout_sanity() {
             ),
             (
                 1,
-                HashMap::from([
+                DenseIdMap::from_iter([
                     (0, ValidatorStats { produced: 70, expected: 100 }),
                     (1, ValidatorStats { produced: 79, expected: 100 }),
                     (3, ValidatorStats { produced: 100, expected: 100 }),
@@ -2510,6 +3132,105 @@ fn test_validator_kickout_sanity() {
     );
 }
 
+/// Tests compute_kickouts on a fixture aggregator, with a validator kicked out
+/// for missing blocks, one for missing chunks and one for missing both.
+#[test]
+fn test_compute_kickouts() {
+    let epoch_config = epoch_config(5, 2, 4, 0, 90, 80, 0).for_protocol_version(PROTOCOL_VERSION);
+    let accounts = vec![
+        ("test0".parse().unwrap(), 1000),
+        ("test1".parse().unwrap(), 1000),
+        ("test2".parse().unwrap(), 1000),
+        ("test3".parse().unwrap(), 1000),
+        ("test4".parse().unwrap(), 500),
+    ];
+    let epoch_info = epoch_info(
+        0,
+        accounts,
+        vec![0, 1, 2, 3],
+        vec![vec![0, 1, 2], vec![0, 1, 3, 4]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    let aggregator = EpochInfoAggregator {
+        block_tracker: DenseIdMap::from_iter([
+            (0, ValidatorStats { produced: 100, expected: 100 }),
+            // test1 misses both blocks and chunks
+            (1, ValidatorStats { produced: 80, expected: 100 }),
+            (2, ValidatorStats { produced: 100, expected: 100 }),
+            // test3 only misses blocks
+            (3, ValidatorStats { produced: 89, expected: 100 }),
+        ]),
+        shard_tracker: DenseIdMap::from_iter([
+            (
+                0,
+                DenseIdMap::from_iter([
+                    (0, ValidatorStats { produced: 100, expected: 100 }),
+                    (1, ValidatorStats { produced: 0, expected: 100 }),
+                    // test2 only misses chunks
+                    (2, ValidatorStats { produced: 70, expected: 100 }),
+                ]),
+            ),
+            (
+                1,
+                DenseIdMap::from_iter([
+                    (0, ValidatorStats { produced: 100, expected: 100 }),
+                    (1, ValidatorStats { produced: 0, expected: 100 }),
+                    (3, ValidatorStats { produced: 100, expected: 100 }),
+                    (4, ValidatorStats { produced: 100, expected: 100 }),
+                ]),
+            ),
+        ]),
+        ..Default::default()
+    };
+
+    let kickouts = compute_kickouts(&aggregator, &epoch_info, &epoch_config);
+    assert_eq!(
+        kickouts,
+        HashMap::from([
+            // missing blocks takes precedence over missing chunks
+            ("test1".parse().unwrap(), NotEnoughBlocks { produced: 80, expected: 100 }),
+            ("test2".parse().unwrap(), NotEnoughChunks { produced: 70, expected: 100 }),
+            ("test3".parse().unwrap(), NotEnoughBlocks { produced: 89, expected: 100 }),
+        ])
+    );
+
+    // the same as what epoch finalization computes with nothing slashed or kicked out before
+    let (with_history, validator_stats) = compute_kickouts_with_history(
+        &aggregator,
+        &epoch_info,
+        &epoch_config,
+        &HashMap::new(),
+        &HashMap::new(),
+    );
+    assert_eq!(with_history, kickouts);
+    assert_eq!(
+        validator_stats.keys().cloned().collect::<HashSet<AccountId>>(),
+        HashSet::from(["test0".parse().unwrap(), "test4".parse().unwrap()])
+    );
+
+    // slashed validators are left to be handled separately
+    let slashed = HashMap::from([("test3".parse().unwrap(), SlashState::Other)]);
+    let (with_history, _) = compute_kickouts_with_history(
+        &aggregator,
+        &epoch_info,
+        &epoch_config,
+        &slashed,
+        &HashMap::new(),
+    );
+    assert_eq!(
+        with_history,
+        HashMap::from([
+            ("test1".parse().unwrap(), NotEnoughBlocks { produced: 80, expected: 100 }),
+            ("test2".parse().unwrap(), NotEnoughChunks { produced: 70, expected: 100 }),
+        ])
+    );
+}
+
 #[test]
 /// Test that the pledge of validators kicked out in an epoch doesn't exceed the max_kickout_pledge_ratio
 fn test_max_kickout_pledge_ratio() {
@@ -2534,7 +3255,7 @@ fn test_max_kickout_pledge_ratio() {
         HashMap::new(),
         0,
     );
-    let block_stats = HashMap::from([
+    let block_stats = DenseIdMap::from_iter([
         (0, ValidatorStats { produced: 50, expected: 100 }),
         // here both test1 and test2 produced the most number of blocks, we made that intentionally
         // to test the algorithm to pick one deterministically to save in this case.
@@ -2543,17 +3264,17 @@ fn test_max_kickout_pledge_ratio() {
         // validator 3 doesn't need to produce any block or chunk
         (3, ValidatorStats { produced: 0, expected: 0 }),
     ]);
-    let chunk_stats = HashMap::from([
+    let chunk_stats = DenseIdMap::from_iter([
         (
             0,
-            HashMap::from([
+            DenseIdMap::from_iter([
                 (0, ValidatorStats { produced: 0, expected: 100 }),
                 (1, ValidatorStats { produced: 0, expected: 100 }),
             ]),
         ),
         (
             1,
-            HashMap::from([
+            DenseIdMap::from_iter([
                 (2, ValidatorStats { produced: 100, expected: 100 }),
                 (4, ValidatorStats { produced: 50, expected: 100 }),
             ]),
@@ -2654,3 +3375,299 @@ fn test_max_kickout_pledge_ratio() {
         ])
     );
 }
+
+#[test]
+fn test_validator_diff_rotation() {
+    let amount_powered: Power = 1_000_000;
+    let validators = vec![("test1".parse().unwrap(), amount_powered)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, vec![], 1, 1, 2, 2, 90, 60);
+
+    let h = hash_range(4);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![], vec![]);
+    record_block(
+        &mut epoch_manager,
+        h[0],
+        h[1],
+        1,
+        vec![do_power("test2".parse().unwrap(), amount_powered)],
+        vec![],
+    );
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![], vec![]);
+    record_block(&mut epoch_manager, h[2], h[3], 3, vec![], vec![]);
+
+    let epoch2 = epoch_manager.get_epoch_id(&h[2]).unwrap();
+    let epoch3 = epoch_manager.get_epoch_id(&h[3]).unwrap();
+
+    let diff = epoch_manager.validator_diff(&epoch2, &epoch2).unwrap();
+    assert!(diff.block_producers_added.is_empty());
+    assert!(diff.block_producers_removed.is_empty());
+    assert!(diff.changed.is_empty());
+
+    // test2 powered in epoch 1 and joins in epoch 3
+    let test2: AccountId = "test2".parse().unwrap();
+    let diff = epoch_manager.validator_diff(&epoch2, &epoch3).unwrap();
+    assert_eq!(diff.prev_epoch_id, epoch2);
+    assert_eq!(diff.next_epoch_id, epoch3);
+    assert_eq!(diff.block_producers_added, vec![test2.clone()]);
+    assert!(diff.block_producers_removed.is_empty());
+    let shard_diff = diff.chunk_producers.get(&ShardUId::single_shard()).unwrap();
+    assert_eq!(shard_diff.added, vec![test2.clone()]);
+    assert!(shard_diff.removed.is_empty());
+
+    // and the other way around
+    let diff = epoch_manager.validator_diff(&epoch3, &epoch2).unwrap();
+    assert_eq!(diff.block_producers_removed, vec![test2.clone()]);
+    let shard_diff = diff.chunk_producers.get(&ShardUId::single_shard()).unwrap();
+    assert_eq!(shard_diff.removed, vec![test2]);
+}
+
+#[test]
+fn test_validator_status_next_epoch() {
+    let amount_powered: Power = 1_000_000;
+    let validators = vec![("test1".parse().unwrap(), amount_powered)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, vec![], 1, 1, 2, 2, 90, 60);
+
+    let h = hash_range(4);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![], vec![]);
+    record_block(
+        &mut epoch_manager,
+        h[0],
+        h[1],
+        1,
+        vec![do_power("test2".parse().unwrap(), amount_powered)],
+        vec![],
+    );
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![], vec![]);
+    record_block(&mut epoch_manager, h[2], h[3], 3, vec![], vec![]);
+
+    // test2 powered in epoch 1 and joins in the epoch of h[3], which follows the one of h[2]
+    let next_epoch_info =
+        epoch_manager.get_epoch_info(&epoch_manager.get_epoch_id(&h[3]).unwrap()).unwrap();
+    for account_id in ["test1", "test2"] {
+        let account_id: AccountId = account_id.parse().unwrap();
+        let validator_id = *next_epoch_info.get_validator_id(&account_id).unwrap();
+        let status = epoch_manager.validator_status_next_epoch(&account_id, &h[2]).unwrap();
+        match status {
+            NextEpochStatus::Elected { seats, pledge } => {
+                assert!(seats > 0);
+                assert_eq!(pledge, next_epoch_info.validator_stake(validator_id));
+            }
+            _ => panic!("{} should be elected, got {:?}", account_id, status),
+        }
+    }
+    assert_eq!(
+        epoch_manager.validator_status_next_epoch(&"test3".parse().unwrap(), &h[2]).unwrap(),
+        NextEpochStatus::NotProposed
+    );
+}
+
+#[test]
+fn test_next_epoch_status_variants() {
+    let test1: AccountId = "test1".parse().unwrap();
+    let test2: AccountId = "test2".parse().unwrap();
+    let next_epoch_info = epoch_info(
+        2,
+        vec![(test1.clone(), 10, 2_000), (test2.clone(), 10, 1_000)],
+        vec![0, 0, 1],
+        vec![vec![0, 1]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        BTreeMap::new(),
+        vec![
+            ("test3".parse().unwrap(), NotEnoughBlocks { produced: 1, expected: 10 }),
+            (
+                "test4".parse().unwrap(),
+                ValidatorKickoutReason::NotEnoughPledge { pledge: 5, threshold: 100 },
+            ),
+            ("test5".parse().unwrap(), ValidatorKickoutReason::DidNotGetASeat),
+        ],
+        HashMap::new(),
+        0,
+    );
+    let status = |account_id: &str| {
+        NextEpochStatus::new(&account_id.parse().unwrap(), &next_epoch_info)
+    };
+
+    assert_eq!(status("test1"), NextEpochStatus::Elected { seats: 2, pledge: 2_000 });
+    assert_eq!(status("test2"), NextEpochStatus::Elected { seats: 1, pledge: 1_000 });
+    assert_eq!(
+        status("test3"),
+        NextEpochStatus::KickedOut(NotEnoughBlocks { produced: 1, expected: 10 })
+    );
+    assert_eq!(status("test4"), NextEpochStatus::NotEnoughPledge { needed: 100 });
+    assert_eq!(
+        status("test5"),
+        NextEpochStatus::NotEnoughPledge { needed: next_epoch_info.seat_price() }
+    );
+    assert_eq!(status("test6"), NextEpochStatus::NotProposed);
+}
+
+#[test]
+fn test_network_totals_of_epoch_info() {
+    let test1: AccountId = "test1".parse().unwrap();
+    let test2: AccountId = "test2".parse().unwrap();
+    let test3: AccountId = "test3".parse().unwrap();
+    let epoch_id = EpochId(hash(&[1]));
+    let epoch_info = epoch_info(
+        1,
+        vec![(test1.clone(), 10, 2_000), (test2.clone(), 20, 1_000), (test3, 5, 500)],
+        vec![0, 0, 1],
+        vec![vec![0, 1], vec![2]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    assert_eq!(
+        NetworkTotals::active(epoch_id.clone(), &epoch_info),
+        NetworkTotals {
+            epoch_id: epoch_id.clone(),
+            total_power: 35,
+            total_pledge: 3_500,
+            num_block_producers: 2,
+            num_chunk_producers: 3,
+        }
+    );
+
+    // test1 takes its power and pledge away, test2 raises its pledge and test4 is new
+    let mut aggregator = EpochInfoAggregator::new(epoch_id.clone(), hash(&[2]));
+    for proposal in [do_power(test1.clone(), 0), do_power("test4".parse().unwrap(), 7)] {
+        aggregator.all_power_proposals.insert(proposal.account_id().clone(), proposal);
+    }
+    for proposal in [
+        crate::test_utils::pledge(test1, 0),
+        crate::test_utils::pledge(test2, 4_000),
+        crate::test_utils::pledge("test4".parse().unwrap(), 100),
+    ] {
+        aggregator.all_pledge_proposals.insert(proposal.account_id().clone(), proposal);
+    }
+    assert_eq!(
+        NetworkTotals::pending(&epoch_info, &aggregator),
+        NetworkTotals {
+            epoch_id,
+            total_power: 32,
+            total_pledge: 4_600,
+            num_block_producers: 3,
+            num_chunk_producers: 3,
+        }
+    );
+}
+
+#[test]
+fn test_network_totals() {
+    let test1: AccountId = "test1".parse().unwrap();
+    let test2: AccountId = "test2".parse().unwrap();
+    let mut epoch_manager = setup_default_epoch_manager(
+        vec![(test1.clone(), 1_000_000)],
+        vec![],
+        10,
+        1,
+        2,
+        0,
+        90,
+        60,
+    );
+    let h = hash_range(3);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![], vec![]);
+    record_block(
+        &mut epoch_manager,
+        h[0],
+        h[1],
+        1,
+        vec![do_power(test2.clone(), 500_000)],
+        vec![crate::test_utils::pledge(test2, 300)],
+    );
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![], vec![]);
+
+    let active = epoch_manager.network_totals(&h[2]).unwrap();
+    assert_eq!(active.epoch_id, epoch_manager.get_epoch_id(&h[2]).unwrap());
+    assert_eq!(active.total_power, 1_000_000);
+    assert_eq!(active.num_block_producers, 1);
+    assert_eq!(active.num_chunk_producers, 1);
+    // the proposal only shows up in the pending totals
+    let pending = epoch_manager.pending_network_totals(&h[2]).unwrap();
+    assert_eq!(pending.epoch_id, active.epoch_id);
+    assert_eq!(pending.total_power, 1_500_000);
+    assert_eq!(pending.total_pledge, active.total_pledge + 300);
+    assert_eq!(pending.num_block_producers, 2);
+}
+
+#[test]
+fn test_validator_diff_shard_layout_change() {
+    let test1: AccountId = "test1".parse().unwrap();
+    let test2: AccountId = "test2".parse().unwrap();
+    let test3: AccountId = "test3".parse().unwrap();
+    let prev_epoch_info = epoch_info(
+        1,
+        vec![(test1.clone(), 10, 1_000), (test2.clone(), 10, 1_000)],
+        vec![0, 1],
+        vec![vec![0], vec![1]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    let next_epoch_info = epoch_info(
+        2,
+        vec![(test1.clone(), 10, 2_000), (test3.clone(), 10, 1_000)],
+        vec![0, 1],
+        vec![vec![0, 1]],
+        vec![],
+        vec![],
+        BTreeMap::new(),
+        BTreeMap::new(),
+        vec![],
+        HashMap::new(),
+        0,
+    );
+    let prev_layout = ShardLayout::v0(2, 0);
+    let next_layout = ShardLayout::v0(1, 1);
+    let diff = ValidatorDiff::new(
+        EpochId(hash(&[1])),
+        &prev_epoch_info,
+        &prev_layout,
+        EpochId(hash(&[2])),
+        &next_epoch_info,
+        &next_layout,
+    );
+
+    assert_eq!(diff.block_producers_added, vec![test3.clone()]);
+    assert_eq!(diff.block_producers_removed, vec![test2.clone()]);
+    // the shards of the old layout only lose producers, and the new shard only gains them
+    assert_eq!(
+        diff.chunk_producers,
+        BTreeMap::from([
+            (
+                ShardUId { version: 0, shard_id: 0 },
+                ShardValidatorDiff { added: vec![], removed: vec![test1.clone()] }
+            ),
+            (
+                ShardUId { version: 0, shard_id: 1 },
+                ShardValidatorDiff { added: vec![], removed: vec![test2] }
+            ),
+            (
+                ShardUId { version: 1, shard_id: 0 },
+                ShardValidatorDiff { added: vec![test1.clone(), test3], removed: vec![] }
+            ),
+        ])
+    );
+    assert_eq!(
+        diff.changed,
+        vec![ValidatorChange {
+            account_id: test1,
+            prev_pledge: 1_000,
+            next_pledge: 2_000,
+            prev_power: 10,
+            next_power: 10,
+        }]
+    );
+    // for the debug page
+    serde_json::to_string(&diff).unwrap();
+}
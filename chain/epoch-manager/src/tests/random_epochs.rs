@@ -327,11 +327,11 @@ fn verify_block_stats(
             let aggregator =
                 epoch_manager.get_epoch_info_aggregator_upto_last(&block_hashes[i]).unwrap();
             let epoch_info = epoch_manager.get_epoch_info(block_infos[i].epoch_id()).unwrap();
-            for key in aggregator.block_tracker.keys().copied() {
+            for key in aggregator.block_tracker.keys() {
                 assert!(key < epoch_info.validators_iter().len() as u64);
             }
             for shard_stats in aggregator.shard_tracker.values() {
-                for key in shard_stats.keys().copied() {
+                for key in shard_stats.keys() {
                     assert!(key < epoch_info.validators_iter().len() as u64);
                 }
             }
@@ -1,15 +1,21 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use unc_primitives::block_header::BlockHeader;
 use unc_primitives::challenge::SlashedValidator;
+use unc_primitives::checked_feature;
 use unc_primitives::epoch_manager::block_info::BlockInfo;
 use unc_primitives::epoch_manager::epoch_info::EpochInfo;
 use unc_primitives::hash::CryptoHash;
+use unc_primitives::serialize::dec_format;
+use unc_primitives::shard_layout::{ShardLayout, ShardUId};
 use unc_primitives::types::validator_power::ValidatorPower;
 use unc_primitives::types::{
-    AccountId, Balance, BlockHeight, EpochId, ShardId, ValidatorId, ValidatorStats,
+    AccountId, Balance, BlockHeight, EpochId, NumBlocks, NumSeats, Power, ShardId, ValidatorId,
+    ValidatorKickoutReason, ValidatorStats,
 };
 use unc_primitives::version::ProtocolVersion;
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use tracing::{debug, debug_span};
 use unc_primitives::types::validator_stake::ValidatorPledge;
 
@@ -34,6 +40,11 @@ pub struct BlockHeaderInfo {
     pub total_supply: Balance,
     pub latest_protocol_version: ProtocolVersion,
     pub timestamp_nanosec: u64,
+    /// Latest protocol version observed by the producer of each chunk in the block, by shard,
+    /// for the chunk producers to be counted in the upgrade vote.  Taken from the block header,
+    /// so that header sync records the same votes as block processing, and empty for headers
+    /// which don't carry them.
+    pub chunk_producer_versions: Vec<Option<ProtocolVersion>>,
 }
 
 impl BlockHeaderInfo {
@@ -52,19 +63,330 @@ impl BlockHeaderInfo {
             total_supply: header.total_supply(),
             latest_protocol_version: header.latest_protocol_version(),
             timestamp_nanosec: header.raw_timestamp(),
+            chunk_producer_versions: header
+                .chunk_producer_versions()
+                .map(<[_]>::to_vec)
+                .unwrap_or_default(),
         }
     }
 }
 
+/// Map keyed by small dense ids, i.e. validator or shard ids, stored as
+/// a vector indexed by the id.  Updating an entry neither hashes nor, once
+/// the map has room for all the ids of the epoch, allocates, which matters
+/// on the [`EpochInfoAggregator::update_tail`] path taken for every block.
+///
+/// It is Borsh-serialized exactly like a `HashMap<u64, V>` so that
+/// aggregators stored in the database stay readable.
+#[derive(Clone)]
+pub struct DenseIdMap<V> {
+    entries: Vec<Option<V>>,
+    len: usize,
+}
+
+impl<V> Default for DenseIdMap<V> {
+    fn default() -> Self {
+        Self { entries: Vec::new(), len: 0 }
+    }
+}
+
+impl<V> DenseIdMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a map with room for ids below `num_ids`.
+    pub fn with_ids(num_ids: usize) -> Self {
+        let mut map = Self::new();
+        map.reserve_ids(num_ids);
+        map
+    }
+
+    /// Makes room for ids below `num_ids` so that inserting them doesn't
+    /// allocate.
+    pub fn reserve_ids(&mut self, num_ids: usize) {
+        if self.entries.len() < num_ids {
+            self.entries.resize_with(num_ids, || None);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains_key(&self, id: &u64) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: &u64) -> Option<&V> {
+        self.entries.get(*id as usize).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: &u64) -> Option<&mut V> {
+        self.entries.get_mut(*id as usize).and_then(Option::as_mut)
+    }
+
+    /// Returns the value for `id`, inserting the one returned by `default`
+    /// first if there is none.
+    pub fn get_or_insert_with(&mut self, id: u64, default: impl FnOnce() -> V) -> &mut V {
+        let idx = id as usize;
+        if idx >= self.entries.len() {
+            self.entries.resize_with(idx + 1, || None);
+        }
+        let entry = &mut self.entries[idx];
+        if entry.is_none() {
+            self.len += 1;
+        }
+        entry.get_or_insert_with(default)
+    }
+
+    pub fn remove(&mut self, id: &u64) -> Option<V> {
+        let value = self.entries.get_mut(*id as usize).and_then(Option::take);
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    pub fn insert(&mut self, id: u64, value: V) -> Option<V> {
+        let mut value = Some(value);
+        let entry = self.get_or_insert_with(id, || value.take().unwrap());
+        value.map(|value| std::mem::replace(entry, value))
+    }
+
+    /// Iterates over the entries in increasing order of ids.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &V)> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(id, value)| value.as_ref().map(|value| (id as u64, value)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.entries.iter().flatten()
+    }
+}
+
+impl<V> IntoIterator for DenseIdMap<V> {
+    type Item = (u64, V);
+    type IntoIter = std::iter::FilterMap<
+        std::iter::Enumerate<std::vec::IntoIter<Option<V>>>,
+        fn((usize, Option<V>)) -> Option<(u64, V)>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let entry: fn((usize, Option<V>)) -> Option<(u64, V)> =
+            |(id, value)| Some((id as u64, value?));
+        self.entries.into_iter().enumerate().filter_map(entry)
+    }
+}
+
+impl<V> FromIterator<(u64, V)> for DenseIdMap<V> {
+    fn from_iter<I: IntoIterator<Item = (u64, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<V> Extend<(u64, V)> for DenseIdMap<V> {
+    fn extend<I: IntoIterator<Item = (u64, V)>>(&mut self, iter: I) {
+        for (id, value) in iter {
+            self.insert(id, value);
+        }
+    }
+}
+
+impl<V> From<HashMap<u64, V>> for DenseIdMap<V> {
+    fn from(map: HashMap<u64, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<V> std::ops::Index<&u64> for DenseIdMap<V> {
+    type Output = V;
+
+    fn index(&self, id: &u64) -> &V {
+        self.get(id).expect("no entry found for id")
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for DenseIdMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Maps are equal if they have the same entries, regardless of how many ids
+/// they have room for.
+impl<V: PartialEq> PartialEq for DenseIdMap<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V: Eq> Eq for DenseIdMap<V> {}
+
+impl<V: PartialEq> PartialEq<HashMap<u64, V>> for DenseIdMap<V> {
+    fn eq(&self, other: &HashMap<u64, V>) -> bool {
+        self.len == other.len() && self.iter().all(|(id, value)| other.get(&id) == Some(value))
+    }
+}
+
+impl<V: BorshSerialize> BorshSerialize for DenseIdMap<V> {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        // Borsh writes the entries of a HashMap sorted by key, which is the
+        // order they are iterated in here.
+        let len = u32::try_from(self.len).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        len.serialize(writer)?;
+        for (id, value) in self.iter() {
+            id.serialize(writer)?;
+            value.serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: BorshDeserialize> BorshDeserialize for DenseIdMap<V> {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(HashMap::<u64, V>::deserialize_reader(reader)?.into())
+    }
+}
+
+/// A block given to [`EpochInfoAggregator`] whose height isn't above the
+/// height of its parent.  Counting it would either skip the block or, for
+/// equal heights, record it as produced without it ever being expected.
+#[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("block at height {current} does not come after its parent at height {prev}")]
+pub struct BlockOrderError {
+    pub prev: BlockHeight,
+    pub current: BlockHeight,
+}
+
+impl BlockOrderError {
+    fn check(prev: BlockHeight, current: BlockHeight) -> Result<(), Self> {
+        if prev < current {
+            Ok(())
+        } else {
+            Err(Self { prev, current })
+        }
+    }
+}
+
+/// What changed in an [`EpochInfoAggregator`] since an earlier state of it,
+/// for followers which mirror the aggregator without being sent all of it
+/// for every block.  See [`EpochInfoAggregator::diff_since`] and
+/// [`EpochInfoAggregator::apply_delta`].
+///
+/// Entries are listed in increasing order of their keys.  Proposals are
+/// keyed by their own account, as [`EpochInfoAggregator::invariants`]
+/// requires.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct AggregatorDelta {
+    pub epoch_id: EpochId,
+    pub last_block_hash: CryptoHash,
+    /// Block production stats which are new or differ from the baseline's.
+    pub block_stats: Vec<(ValidatorId, ValidatorStats)>,
+    /// Chunk production stats which are new or differ from the baseline's,
+    /// by shard.  A shard the baseline doesn't have is listed even if it has
+    /// no stats, so that it gets created.
+    pub chunk_stats: Vec<(ShardId, Vec<(ValidatorId, ValidatorStats)>)>,
+    pub versions: Vec<(ValidatorId, ProtocolVersion)>,
+    pub power_proposals: Vec<ValidatorPower>,
+    pub pledge_proposals: Vec<ValidatorPledge>,
+    /// Entries of the baseline which are gone.  This stays empty while an
+    /// aggregator only advances within an epoch, since then entries are only
+    /// ever added or updated.
+    pub removed: AggregatorRemovals,
+}
+
+/// Keys of the entries an [`AggregatorDelta`] removes.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct AggregatorRemovals {
+    pub block_stats: Vec<ValidatorId>,
+    pub shards: Vec<ShardId>,
+    pub chunk_stats: Vec<(ShardId, ValidatorId)>,
+    pub versions: Vec<ValidatorId>,
+    pub power_proposals: Vec<AccountId>,
+    pub pledge_proposals: Vec<AccountId>,
+}
+
+impl AggregatorRemovals {
+    pub fn is_empty(&self) -> bool {
+        self.block_stats.is_empty()
+            && self.shards.is_empty()
+            && self.chunk_stats.is_empty()
+            && self.versions.is_empty()
+            && self.power_proposals.is_empty()
+            && self.pledge_proposals.is_empty()
+    }
+}
+
+/// Entries of `new` which aren't in `old` with the same value, adding the
+/// ids only `old` has to `removed`.
+fn diff_dense_id_maps<V: Clone + PartialEq>(
+    new: &DenseIdMap<V>,
+    old: &DenseIdMap<V>,
+    removed: &mut Vec<u64>,
+) -> Vec<(u64, V)> {
+    removed.extend(old.keys().filter(|id| !new.contains_key(id)));
+    new.iter()
+        .filter(|(id, value)| old.get(id) != Some(*value))
+        .map(|(id, value)| (id, value.clone()))
+        .collect()
+}
+
+/// Proposals of `new` which aren't in `old`, adding the accounts whose
+/// proposals only `old` has to `removed`.
+fn diff_proposals<P: Clone + PartialEq>(
+    new: &BTreeMap<AccountId, P>,
+    old: &BTreeMap<AccountId, P>,
+    removed: &mut Vec<AccountId>,
+) -> Vec<P> {
+    removed.extend(old.keys().filter(|account_id| !new.contains_key(*account_id)).cloned());
+    new.iter()
+        .filter(|(account_id, proposal)| old.get(*account_id) != Some(*proposal))
+        .map(|(_, proposal)| proposal.clone())
+        .collect()
+}
+
 /// Aggregator of information needed for validator computation at the end of the epoch.
 #[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default)]
 pub struct EpochInfoAggregator {
     /// Map from validator index to (num_blocks_produced, num_blocks_expected) so far in the given epoch.
-    pub block_tracker: HashMap<ValidatorId, ValidatorStats>,
+    pub block_tracker: DenseIdMap<ValidatorStats>,
     /// For each shard, a map of validator id to (num_chunks_produced, num_chunks_expected) so far in the given epoch.
-    pub shard_tracker: HashMap<ShardId, HashMap<ValidatorId, ValidatorStats>>,
+    pub shard_tracker: DenseIdMap<DenseIdMap<ValidatorStats>>,
     /// Latest protocol version that each validator supports.
-    pub version_tracker: HashMap<ValidatorId, ProtocolVersion>,
+    pub version_tracker: DenseIdMap<ProtocolVersion>,
     /// All power proposals in this epoch up to this block.
     pub all_power_proposals: BTreeMap<AccountId, ValidatorPower>,
     /// All pledge proposals in this epoch up to this block.
@@ -105,71 +427,69 @@ impl EpochInfoAggregator {
     /// H or I blocks into the aggregator.  The expected usage is to create
     /// a new aggregator starting from I, add H and G into it (using this
     /// method) and then [merge][`Self::merge`] it into `self`.
+    ///
+    /// Fails without changing the aggregator if `prev_block_height` isn't
+    /// below the height of the block.
     pub fn update_tail(
         &mut self,
         block_info: &BlockInfo,
         epoch_info: &EpochInfo,
         prev_block_height: BlockHeight,
-    ) {
+    ) -> Result<(), BlockOrderError> {
+        BlockOrderError::check(prev_block_height, block_info.height())?;
         let _span =
             debug_span!(target: "epoch_tracker", "update_tail", prev_block_height).entered();
+        // The trackers only allocate the first time they are updated for the
+        // epoch, after which they have room for all of its validators.
+        let num_validators = epoch_info.validators_len();
+        self.block_tracker.reserve_ids(num_validators);
+        self.version_tracker.reserve_ids(num_validators);
+
         // Step 1: update block tracer
         let block_info_height = block_info.height();
         for height in prev_block_height + 1..=block_info_height {
             let block_producer_id = EpochManager::block_producer_from_info(epoch_info, height);
-            let entry = self.block_tracker.entry(block_producer_id);
+            let stats =
+                self.block_tracker.get_or_insert_with(block_producer_id, ValidatorStats::default);
+            stats.expected += 1;
             if height == block_info_height {
-                entry
-                    .and_modify(|validator_stats| {
-                        validator_stats.produced += 1;
-                        validator_stats.expected += 1;
-                    })
-                    .or_insert(ValidatorStats { produced: 1, expected: 1 });
+                stats.produced += 1;
             } else {
                 debug!(
                     target: "epoch_tracker",
                     block_producer = ?epoch_info.validator_account_id(block_producer_id),
                     block_height = height, "Missed block");
-                entry
-                    .and_modify(|validator_stats| {
-                        validator_stats.expected += 1;
-                    })
-                    .or_insert(ValidatorStats { produced: 0, expected: 1 });
             }
         }
 
         // Step 2: update shard tracker
-        for (i, mask) in block_info.chunk_mask().iter().enumerate() {
+        let chunk_mask = block_info.chunk_mask();
+        self.shard_tracker.reserve_ids(chunk_mask.len());
+        for (i, mask) in chunk_mask.iter().enumerate() {
             let chunk_validator_id = EpochManager::chunk_producer_from_info(
                 epoch_info,
                 prev_block_height + 1,
                 i as ShardId,
             );
-            let tracker = self.shard_tracker.entry(i as ShardId).or_insert_with(HashMap::new);
-            tracker
-                .entry(chunk_validator_id)
-                .and_modify(|stats| {
-                    if *mask {
-                        stats.produced += 1;
-                    } else {
-                        debug!(
-                            target: "epoch_tracker",
-                            chunk_validator = ?epoch_info.validator_account_id(chunk_validator_id),
-                            shard_id = i,
-                            block_height = prev_block_height + 1,
-                            "Missed chunk");
-                    }
-                    stats.expected += 1;
-                })
-                .or_insert(ValidatorStats { produced: u64::from(*mask), expected: 1 });
+            let stats = self
+                .shard_tracker
+                .get_or_insert_with(i as ShardId, || DenseIdMap::with_ids(num_validators))
+                .get_or_insert_with(chunk_validator_id, ValidatorStats::default);
+            stats.expected += 1;
+            if *mask {
+                stats.produced += 1;
+            } else {
+                debug!(
+                    target: "epoch_tracker",
+                    chunk_validator = ?epoch_info.validator_account_id(chunk_validator_id),
+                    shard_id = i,
+                    block_height = prev_block_height + 1,
+                    "Missed chunk");
+            }
         }
 
         // Step 3: update version tracker
-        let block_producer_id =
-            EpochManager::block_producer_from_info(epoch_info, block_info_height);
-        self.version_tracker
-            .entry(block_producer_id)
-            .or_insert_with(|| *block_info.latest_protocol_version());
+        self.update_versions(block_info, epoch_info, prev_block_height);
 
         // Step 4: update proposals
         for proposal in block_info.power_proposals_iter() {
@@ -179,6 +499,202 @@ impl EpochInfoAggregator {
         for proposal in block_info.pledge_proposals_iter() {
             self.all_pledge_proposals.entry(proposal.account_id().clone()).or_insert(proposal);
         }
+        Ok(())
+    }
+
+    /// Aggregates data from a contiguous range of blocks which directly
+    /// precede the first block this aggregator has statistic on.
+    ///
+    /// `blocks` must be ordered by increasing height, each block being the
+    /// parent of the next one, and `prev_block_height` is the height of the
+    /// parent of the first block.  The result is the same as calling
+    /// [`Self::update_tail`] for each of the blocks starting from the last
+    /// one, but the statistics are accumulated in dense vectors indexed by
+    /// validator id and merged into the trackers once per call rather than
+    /// once per block.  This is what makes catching up on a long range of
+    /// blocks cheap.
+    ///
+    /// Fails without changing the aggregator if any block's height isn't
+    /// above the height of its parent.
+    pub fn update_range<B: Borrow<BlockInfo>>(
+        &mut self,
+        blocks: &[B],
+        epoch_info: &EpochInfo,
+        prev_block_height: BlockHeight,
+    ) -> Result<(), BlockOrderError> {
+        let Some(last_block) = blocks.last() else {
+            return Ok(());
+        };
+        let mut prev_height = prev_block_height;
+        for block_info in blocks {
+            let height = block_info.borrow().height();
+            BlockOrderError::check(prev_height, height)?;
+            prev_height = height;
+        }
+        let _span = debug_span!(
+            target: "epoch_tracker",
+            "update_range",
+            prev_block_height,
+            last_block_height = last_block.borrow().height())
+        .entered();
+
+        fn stats_for(stats: &mut Vec<ValidatorStats>, id: ValidatorId) -> &mut ValidatorStats {
+            let idx = id as usize;
+            if idx >= stats.len() {
+                stats.resize(idx + 1, ValidatorStats::default());
+            }
+            &mut stats[idx]
+        }
+
+        // Step 1 and 2: count produced and expected blocks and chunks
+        let num_validators = epoch_info.validators_len();
+        let mut block_stats = vec![ValidatorStats::default(); num_validators];
+        let mut shard_stats: Vec<Vec<ValidatorStats>> = Vec::new();
+        let mut prev_height = prev_block_height;
+        for block_info in blocks {
+            let block_info = block_info.borrow();
+            let block_info_height = block_info.height();
+            for height in prev_height + 1..=block_info_height {
+                let block_producer_id = EpochManager::block_producer_from_info(epoch_info, height);
+                let stats = stats_for(&mut block_stats, block_producer_id);
+                stats.expected += 1;
+                if height == block_info_height {
+                    stats.produced += 1;
+                } else {
+                    debug!(
+                        target: "epoch_tracker",
+                        block_producer = ?epoch_info.validator_account_id(block_producer_id),
+                        block_height = height, "Missed block");
+                }
+            }
+
+            let chunk_mask = block_info.chunk_mask();
+            if shard_stats.len() < chunk_mask.len() {
+                let empty = vec![ValidatorStats::default(); num_validators];
+                shard_stats.resize(chunk_mask.len(), empty);
+            }
+            for (i, mask) in chunk_mask.iter().enumerate() {
+                let chunk_validator_id = EpochManager::chunk_producer_from_info(
+                    epoch_info,
+                    prev_height + 1,
+                    i as ShardId,
+                );
+                let stats = stats_for(&mut shard_stats[i], chunk_validator_id);
+                stats.expected += 1;
+                if *mask {
+                    stats.produced += 1;
+                } else {
+                    debug!(
+                        target: "epoch_tracker",
+                        chunk_validator = ?epoch_info.validator_account_id(chunk_validator_id),
+                        shard_id = i,
+                        block_height = prev_height + 1,
+                        "Missed chunk");
+                }
+            }
+            prev_height = block_info_height;
+        }
+
+        Self::merge_dense_stats(&mut self.block_tracker, block_stats);
+        for (shard_id, stats) in shard_stats.into_iter().enumerate() {
+            if stats.iter().all(|stats| stats.expected == 0) {
+                continue;
+            }
+            let tracker = self.shard_tracker.get_or_insert_with(shard_id as ShardId, || {
+                DenseIdMap::with_ids(num_validators)
+            });
+            Self::merge_dense_stats(tracker, stats);
+        }
+
+        // Step 3 and 4: update version tracker and proposals.  Entries already
+        // present take precedence, and so do those of later blocks in the range.
+        for (i, block_info) in blocks.iter().enumerate().rev() {
+            let block_info = block_info.borrow();
+            let prev_height =
+                if i == 0 { prev_block_height } else { blocks[i - 1].borrow().height() };
+            self.update_versions(block_info, epoch_info, prev_height);
+            for proposal in block_info.power_proposals_iter() {
+                self.all_power_proposals.entry(proposal.account_id().clone()).or_insert(proposal);
+            }
+            for proposal in block_info.pledge_proposals_iter() {
+                self.all_pledge_proposals.entry(proposal.account_id().clone()).or_insert(proposal);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the version of the producer of the block and, from
+    /// `ChunkProducerUpgradeVotes` on, those of the producers of its chunks,
+    /// for the validators which don't have a version recorded yet.  The block
+    /// producer's version takes precedence over the one it may have advertised
+    /// as a chunk producer of the same block.
+    fn update_versions(
+        &mut self,
+        block_info: &BlockInfo,
+        epoch_info: &EpochInfo,
+        prev_block_height: BlockHeight,
+    ) {
+        let block_producer_id =
+            EpochManager::block_producer_from_info(epoch_info, block_info.height());
+        self.version_tracker
+            .get_or_insert_with(block_producer_id, || *block_info.latest_protocol_version());
+        if !checked_feature!("stable", ChunkProducerUpgradeVotes, epoch_info.protocol_version()) {
+            return;
+        }
+        let chunk_mask = block_info.chunk_mask();
+        for (i, version) in block_info.chunk_producer_versions().iter().enumerate() {
+            let (Some(version), Some(true)) = (version, chunk_mask.get(i)) else {
+                continue;
+            };
+            let chunk_producer_id = EpochManager::chunk_producer_from_info(
+                epoch_info,
+                prev_block_height + 1,
+                i as ShardId,
+            );
+            self.version_tracker.get_or_insert_with(chunk_producer_id, || *version);
+        }
+    }
+
+    /// Pledge of the validators which advertised each protocol version, and
+    /// the total pledge the upgrade threshold is a fraction of.  Only block
+    /// producers vote before `ChunkProducerUpgradeVotes`, so the total is their
+    /// pledge.  From then on, it's the pledge of all validators, whether or not
+    /// any chunk-only producer's version got recorded, so that a vote for a
+    /// version can only bring the upgrade closer.
+    pub fn version_votes(
+        &self,
+        epoch_info: &EpochInfo,
+    ) -> (HashMap<ProtocolVersion, Balance>, Balance) {
+        let mut versions = HashMap::new();
+        for (validator_id, version) in self.version_tracker.iter() {
+            let pledge = epoch_info.validator_stake(validator_id);
+            *versions.entry(*version).or_insert(0) += pledge;
+        }
+        let protocol_version = epoch_info.protocol_version();
+        let all_validators_vote =
+            checked_feature!("stable", ChunkProducerUpgradeVotes, protocol_version);
+        let total_pledge = if all_validators_vote {
+            (0..epoch_info.validators_len() as ValidatorId)
+                .map(|id| epoch_info.validator_stake(id))
+                .sum()
+        } else {
+            let block_producers: HashSet<ValidatorId> =
+                epoch_info.block_producers_settlement().iter().copied().collect();
+            block_producers.into_iter().map(|id| epoch_info.validator_stake(id)).sum()
+        };
+        (versions, total_pledge)
+    }
+
+    fn merge_dense_stats(tracker: &mut DenseIdMap<ValidatorStats>, stats: Vec<ValidatorStats>) {
+        for (validator_id, stats) in stats.into_iter().enumerate() {
+            if stats.expected == 0 {
+                continue;
+            }
+            let existing =
+                tracker.get_or_insert_with(validator_id as ValidatorId, ValidatorStats::default);
+            existing.produced += stats.produced;
+            existing.expected += stats.expected;
+        }
     }
 
     /// Merges information from `other` aggregator into `self`.
@@ -237,10 +753,8 @@ impl EpochInfoAggregator {
         self.merge_common(&other);
 
         // merge version tracker
-        self.version_tracker.reserve(other.version_tracker.len());
-        // TODO(mina86): Use try_insert once map_try_insert is stabilised.
         for (k, v) in other.version_tracker.iter() {
-            self.version_tracker.entry(*k).or_insert_with(|| *v);
+            self.version_tracker.get_or_insert_with(k, || *v);
         }
 
         // merge proposals
@@ -263,29 +777,505 @@ impl EpochInfoAggregator {
 
         // merge block tracker
         for (block_producer_id, stats) in other.block_tracker.iter() {
-            self.block_tracker
-                .entry(*block_producer_id)
-                .and_modify(|e| {
-                    e.expected += stats.expected;
-                    e.produced += stats.produced
-                })
-                .or_insert_with(|| stats.clone());
+            let e = self.block_tracker.get_or_insert_with(block_producer_id, Default::default);
+            e.expected += stats.expected;
+            e.produced += stats.produced;
         }
         // merge shard tracker
         for (shard_id, stats) in other.shard_tracker.iter() {
-            self.shard_tracker
-                .entry(*shard_id)
-                .and_modify(|e| {
-                    for (chunk_producer_id, stat) in stats.iter() {
-                        e.entry(*chunk_producer_id)
-                            .and_modify(|entry| {
-                                entry.expected += stat.expected;
-                                entry.produced += stat.produced;
-                            })
-                            .or_insert_with(|| stat.clone());
+            let e = self.shard_tracker.get_or_insert_with(shard_id, DenseIdMap::new);
+            for (chunk_producer_id, stat) in stats.iter() {
+                let entry = e.get_or_insert_with(chunk_producer_id, Default::default);
+                entry.expected += stat.expected;
+                entry.produced += stat.produced;
+            }
+        }
+    }
+
+    /// Returns what changed in `self` since `baseline`, such that
+    /// [applying][`Self::apply_delta`] it to `baseline` gives back `self`.
+    /// Only the entries which differ are included, so when `baseline` is an
+    /// earlier state of the same aggregator the delta holds the stats of the
+    /// validators which were expected to produce something since then, the
+    /// new proposals and the new last block hash.
+    pub fn diff_since(&self, baseline: &EpochInfoAggregator) -> AggregatorDelta {
+        let mut removed = AggregatorRemovals::default();
+        let block_stats = diff_dense_id_maps(
+            &self.block_tracker,
+            &baseline.block_tracker,
+            &mut removed.block_stats,
+        );
+        removed.shards.extend(baseline.shard_tracker.keys().filter(|shard_id| {
+            !self.shard_tracker.contains_key(shard_id)
+        }));
+        let mut chunk_stats = Vec::new();
+        for (shard_id, tracker) in self.shard_tracker.iter() {
+            match baseline.shard_tracker.get(&shard_id) {
+                Some(old) => {
+                    let mut removed_ids = Vec::new();
+                    let stats = diff_dense_id_maps(tracker, old, &mut removed_ids);
+                    removed.chunk_stats.extend(removed_ids.into_iter().map(|id| (shard_id, id)));
+                    if !stats.is_empty() {
+                        chunk_stats.push((shard_id, stats));
                     }
+                }
+                None => chunk_stats.push((
+                    shard_id,
+                    tracker.iter().map(|(id, stats)| (id, stats.clone())).collect(),
+                )),
+            }
+        }
+        let versions = diff_dense_id_maps(
+            &self.version_tracker,
+            &baseline.version_tracker,
+            &mut removed.versions,
+        );
+        let power_proposals = diff_proposals(
+            &self.all_power_proposals,
+            &baseline.all_power_proposals,
+            &mut removed.power_proposals,
+        );
+        let pledge_proposals = diff_proposals(
+            &self.all_pledge_proposals,
+            &baseline.all_pledge_proposals,
+            &mut removed.pledge_proposals,
+        );
+        AggregatorDelta {
+            epoch_id: self.epoch_id.clone(),
+            last_block_hash: self.last_block_hash,
+            block_stats,
+            chunk_stats,
+            versions,
+            power_proposals,
+            pledge_proposals,
+            removed,
+        }
+    }
+
+    /// Applies a delta returned by [`Self::diff_since`].  If `self` is the
+    /// baseline the delta was computed against, it's then the same as the
+    /// aggregator the delta was computed from.
+    pub fn apply_delta(&mut self, delta: AggregatorDelta) {
+        let AggregatorDelta {
+            epoch_id,
+            last_block_hash,
+            block_stats,
+            chunk_stats,
+            versions,
+            power_proposals,
+            pledge_proposals,
+            removed,
+        } = delta;
+        for id in removed.block_stats {
+            self.block_tracker.remove(&id);
+        }
+        for shard_id in removed.shards {
+            self.shard_tracker.remove(&shard_id);
+        }
+        for (shard_id, id) in removed.chunk_stats {
+            if let Some(tracker) = self.shard_tracker.get_mut(&shard_id) {
+                tracker.remove(&id);
+            }
+        }
+        for id in removed.versions {
+            self.version_tracker.remove(&id);
+        }
+        for account_id in removed.power_proposals {
+            self.all_power_proposals.remove(&account_id);
+        }
+        for account_id in removed.pledge_proposals {
+            self.all_pledge_proposals.remove(&account_id);
+        }
+
+        self.block_tracker.extend(block_stats);
+        for (shard_id, stats) in chunk_stats {
+            self.shard_tracker.get_or_insert_with(shard_id, DenseIdMap::new).extend(stats);
+        }
+        self.version_tracker.extend(versions);
+        self.all_power_proposals.extend(
+            power_proposals.into_iter().map(|proposal| (proposal.account_id().clone(), proposal)),
+        );
+        self.all_pledge_proposals.extend(
+            pledge_proposals.into_iter().map(|proposal| (proposal.account_id().clone(), proposal)),
+        );
+        self.epoch_id = epoch_id;
+        self.last_block_hash = last_block_hash;
+    }
+
+    /// Checks the internal consistency of the aggregator: no validator produced
+    /// more blocks or chunks than it was expected to and proposals are keyed by
+    /// their own account.  If `epoch_info` is given, also checks that validator
+    /// and shard ids are within its ranges.
+    ///
+    /// Returns a description of the first problem found.
+    pub fn invariants(&self, epoch_info: Option<&EpochInfo>) -> Result<(), String> {
+        let check_validator_id = |what: &str, id: ValidatorId| match epoch_info {
+            Some(epoch_info) if id as usize >= epoch_info.validators_len() => Err(format!(
+                "{} has validator id {} but the epoch has {} validators",
+                what,
+                id,
+                epoch_info.validators_len()
+            )),
+            _ => Ok(()),
+        };
+        let check_stats = |what: &str, id: ValidatorId, stats: &ValidatorStats| {
+            if stats.produced > stats.expected {
+                return Err(format!(
+                    "{} of validator {} has {} produced but only {} expected",
+                    what, id, stats.produced, stats.expected
+                ));
+            }
+            check_validator_id(what, id)
+        };
+
+        for (id, stats) in self.block_tracker.iter() {
+            check_stats("block_tracker", id, stats)?;
+        }
+        for (shard_id, tracker) in self.shard_tracker.iter() {
+            if let Some(epoch_info) = epoch_info {
+                let num_shards = epoch_info.chunk_producers_settlement().len();
+                if shard_id as usize >= num_shards {
+                    return Err(format!(
+                        "shard_tracker has shard {} but the epoch has {} shards",
+                        shard_id, num_shards
+                    ));
+                }
+            }
+            for (id, stats) in tracker.iter() {
+                check_stats(&format!("shard_tracker for shard {}", shard_id), id, stats)?;
+            }
+        }
+        for id in self.version_tracker.keys() {
+            check_validator_id("version_tracker", id)?;
+        }
+        for (account_id, proposal) in self.all_power_proposals.iter() {
+            if proposal.account_id() != account_id {
+                return Err(format!(
+                    "all_power_proposals has a proposal of {} under {}",
+                    proposal.account_id(),
+                    account_id
+                ));
+            }
+        }
+        for (account_id, proposal) in self.all_pledge_proposals.iter() {
+            if proposal.account_id() != account_id {
+                return Err(format!(
+                    "all_pledge_proposals has a proposal of {} under {}",
+                    proposal.account_id(),
+                    account_id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the share of the chunks the given validator was expected to
+    /// produce in the given shard so far in the epoch which made it into
+    /// blocks.  Until the validator was expected to produce at least
+    /// [`MIN_EXPECTED_CHUNKS_FOR_RELIABILITY`] chunks there, which is the case
+    /// early in an epoch, a single missed chunk would say too much, so
+    /// [`NEUTRAL_CHUNK_PRODUCER_RELIABILITY`] is returned instead.
+    pub fn chunk_producer_reliability(&self, shard_id: ShardId, validator_id: ValidatorId) -> f64 {
+        let stats =
+            self.shard_tracker.get(&shard_id).and_then(|tracker| tracker.get(&validator_id));
+        match stats {
+            Some(stats) if stats.expected >= MIN_EXPECTED_CHUNKS_FOR_RELIABILITY => {
+                (stats.produced.min(stats.expected) as f64) / (stats.expected as f64)
+            }
+            _ => NEUTRAL_CHUNK_PRODUCER_RELIABILITY,
+        }
+    }
+
+    /// Returns the chunks produced and expected in each shard so far in the
+    /// epoch, summed over all of its chunk producers.
+    pub fn shard_chunk_counts(&self) -> Vec<ShardChunkCounts> {
+        self.shard_tracker
+            .iter()
+            .map(|(shard_id, tracker)| {
+                let (produced, expected) = tracker.values().fold((0, 0), |(p, e), stats| {
+                    (p + stats.produced.min(stats.expected), e + stats.expected)
+                });
+                ShardChunkCounts { shard_id, produced, expected }
+            })
+            .collect()
+    }
+}
+
+/// Number of chunks which made it into blocks and which were expected in a
+/// shard over an epoch.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShardChunkCounts {
+    pub shard_id: ShardId,
+    pub produced: NumBlocks,
+    pub expected: NumBlocks,
+}
+
+/// The [`ShardChunkCounts`] of every shard for each of the most recently
+/// finalized epochs, oldest first.  Only the last few hundred epochs are kept
+/// so that the whole history fits in a single row.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkDensityHistory {
+    epochs: Vec<(EpochId, Vec<ShardChunkCounts>)>,
+}
+
+impl ChunkDensityHistory {
+    /// Records the counts of a finalized epoch, and forgets the oldest epochs
+    /// beyond the last `retention` ones.  An epoch finalized again on another
+    /// fork replaces its earlier counts.
+    pub fn record(&mut self, epoch_id: EpochId, shards: Vec<ShardChunkCounts>, retention: usize) {
+        match self.epochs.iter_mut().find(|(id, _)| id == &epoch_id) {
+            Some((_, counts)) => *counts = shards,
+            None => self.epochs.push((epoch_id, shards)),
+        }
+        let excess = self.epochs.len().saturating_sub(retention);
+        self.epochs.drain(..excess);
+    }
+
+    /// Returns the share of the expected chunks of the given shard which made
+    /// it into blocks in each of the last `last_n_epochs` epochs recorded,
+    /// oldest first.  Epochs in which the shard wasn't expected to have any
+    /// chunks, e.g. because it didn't exist yet, are left out.
+    pub fn shard_density(&self, shard_id: ShardId, last_n_epochs: usize) -> Vec<(EpochId, f64)> {
+        let first = self.epochs.len().saturating_sub(last_n_epochs);
+        self.epochs[first..]
+            .iter()
+            .filter_map(|(epoch_id, shards)| {
+                let counts = shards.iter().find(|counts| counts.shard_id == shard_id)?;
+                (counts.expected > 0).then(|| {
+                    (epoch_id.clone(), counts.produced as f64 / counts.expected as f64)
                 })
-                .or_insert_with(|| stats.clone());
+            })
+            .collect()
+    }
+}
+
+/// Number of chunks a producer has to be expected to produce in a shard before
+/// [`EpochInfoAggregator::chunk_producer_reliability`] reports its actual
+/// ratio of produced to expected chunks.
+pub const MIN_EXPECTED_CHUNKS_FOR_RELIABILITY: NumBlocks = 4;
+
+/// Reliability of chunk producers we don't know enough about yet.  Such
+/// producers are treated like ones which haven't missed any chunk.
+pub const NEUTRAL_CHUNK_PRODUCER_RELIABILITY: f64 = 1.0;
+
+/// Changes in the validator set between two epochs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ValidatorDiff {
+    pub prev_epoch_id: EpochId,
+    pub next_epoch_id: EpochId,
+    pub block_producers_added: Vec<AccountId>,
+    pub block_producers_removed: Vec<AccountId>,
+    /// Chunk producer changes for every shard of either epoch. Shards are keyed by
+    /// `ShardUId`, so if the shard layout changed, the shards of the old layout only
+    /// have removals and the shards of the new one only have additions.
+    pub chunk_producers: BTreeMap<ShardUId, ShardValidatorDiff>,
+    /// Validators present in both epochs whose pledge or power changed.
+    pub changed: Vec<ValidatorChange>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ShardValidatorDiff {
+    pub added: Vec<AccountId>,
+    pub removed: Vec<AccountId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ValidatorChange {
+    pub account_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub prev_pledge: Balance,
+    #[serde(with = "dec_format")]
+    pub next_pledge: Balance,
+    pub prev_power: Power,
+    pub next_power: Power,
+}
+
+impl ValidatorDiff {
+    pub fn new(
+        prev_epoch_id: EpochId,
+        prev_epoch_info: &EpochInfo,
+        prev_shard_layout: &ShardLayout,
+        next_epoch_id: EpochId,
+        next_epoch_info: &EpochInfo,
+        next_shard_layout: &ShardLayout,
+    ) -> Self {
+        let block_producers = |info: &EpochInfo| -> BTreeSet<AccountId> {
+            info.block_producers_settlement()
+                .iter()
+                .map(|id| info.validator_account_id(*id).clone())
+                .collect()
+        };
+        let chunk_producers = |info: &EpochInfo, layout: &ShardLayout| {
+            let settlement = info.chunk_producers_settlement();
+            layout
+                .shard_uids()
+                .map(|shard_uid| {
+                    let producers: BTreeSet<AccountId> = settlement
+                        .get(shard_uid.shard_id as usize)
+                        .into_iter()
+                        .flatten()
+                        .map(|id| info.validator_account_id(*id).clone())
+                        .collect();
+                    (shard_uid, producers)
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let prev_bps = block_producers(prev_epoch_info);
+        let next_bps = block_producers(next_epoch_info);
+
+        let prev_cps = chunk_producers(prev_epoch_info, prev_shard_layout);
+        let next_cps = chunk_producers(next_epoch_info, next_shard_layout);
+        let empty = BTreeSet::new();
+        let mut shard_diffs = BTreeMap::new();
+        for shard_uid in prev_cps.keys().chain(next_cps.keys()) {
+            if shard_diffs.contains_key(shard_uid) {
+                continue;
+            }
+            let prev = prev_cps.get(shard_uid).unwrap_or(&empty);
+            let next = next_cps.get(shard_uid).unwrap_or(&empty);
+            shard_diffs.insert(
+                *shard_uid,
+                ShardValidatorDiff {
+                    added: next.difference(prev).cloned().collect(),
+                    removed: prev.difference(next).cloned().collect(),
+                },
+            );
+        }
+
+        let mut changed = vec![];
+        for prev in prev_epoch_info.validators_iter() {
+            let Some(next) = next_epoch_info.get_validator_by_account(prev.account_id()) else {
+                continue;
+            };
+            if prev.pledge() != next.pledge() || prev.power() != next.power() {
+                changed.push(ValidatorChange {
+                    account_id: prev.account_id().clone(),
+                    prev_pledge: prev.pledge(),
+                    next_pledge: next.pledge(),
+                    prev_power: prev.power(),
+                    next_power: next.power(),
+                });
+            }
+        }
+        changed.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+
+        Self {
+            prev_epoch_id,
+            next_epoch_id,
+            block_producers_added: next_bps.difference(&prev_bps).cloned().collect(),
+            block_producers_removed: prev_bps.difference(&next_bps).cloned().collect(),
+            chunk_producers: shard_diffs,
+            changed,
+        }
+    }
+}
+
+/// Total power and pledge of the validators of an epoch, see
+/// [`crate::EpochManagerAdapter::network_totals`].
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NetworkTotals {
+    /// For pending totals, the epoch the proposals were made in.
+    pub epoch_id: EpochId,
+    pub total_power: Power,
+    #[serde(with = "dec_format")]
+    pub total_pledge: Balance,
+    /// Number of validators holding a block producer seat. For pending totals, seats are
+    /// only assigned at the end of the epoch, so this is the number of validators and
+    /// proposers left with any power or pledge, which is how many could get one.
+    pub num_block_producers: NumSeats,
+    /// Number of validators producing chunks in any shard. For pending totals, the same as
+    /// `num_block_producers`.
+    pub num_chunk_producers: NumSeats,
+}
+
+impl NetworkTotals {
+    /// Totals of the validators of `epoch_info`, which are the active ones in its epoch.
+    pub fn active(epoch_id: EpochId, epoch_info: &EpochInfo) -> Self {
+        let mut total_power: Power = 0;
+        let mut total_pledge: Balance = 0;
+        for validator in epoch_info.validators_iter() {
+            total_power += validator.power();
+            total_pledge += validator.pledge();
+        }
+        let block_producers: BTreeSet<_> = epoch_info.block_producers_settlement().iter().collect();
+        let chunk_producers: BTreeSet<_> =
+            epoch_info.chunk_producers_settlement().iter().flatten().collect();
+        Self {
+            epoch_id,
+            total_power,
+            total_pledge,
+            num_block_producers: block_producers.len() as NumSeats,
+            num_chunk_producers: chunk_producers.len() as NumSeats,
+        }
+    }
+
+    /// Totals of the validators of `epoch_info` with the proposals collected in `aggregator`
+    /// applied on top, which is what the validators of the next epoch get selected from. A
+    /// proposal replaces the power or pledge of its account, so proposing 0 takes it away.
+    pub fn pending(epoch_info: &EpochInfo, aggregator: &EpochInfoAggregator) -> Self {
+        let mut validators: BTreeMap<AccountId, (Power, Balance)> = epoch_info
+            .validators_iter()
+            .map(|v| (v.account_id().clone(), (v.power(), v.pledge())))
+            .collect();
+        for (account_id, proposal) in aggregator.all_power_proposals.iter() {
+            validators.entry(account_id.clone()).or_default().0 = proposal.power();
+        }
+        for (account_id, proposal) in aggregator.all_pledge_proposals.iter() {
+            validators.entry(account_id.clone()).or_default().1 = proposal.pledge();
+        }
+        validators.retain(|_, (power, pledge)| *power > 0 || *pledge > 0);
+        let num_candidates = validators.len() as NumSeats;
+        Self {
+            epoch_id: aggregator.epoch_id.clone(),
+            total_power: validators.values().map(|(power, _)| power).sum(),
+            total_pledge: validators.values().map(|(_, pledge)| pledge).sum(),
+            num_block_producers: num_candidates,
+            num_chunk_producers: num_candidates,
+        }
+    }
+}
+
+/// What happens to an account in the epoch after the current one.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum NextEpochStatus {
+    /// The account is a validator in the next epoch, holding `seats` block producer seats.
+    Elected {
+        seats: NumSeats,
+        #[serde(with = "dec_format")]
+        pledge: Balance,
+    },
+    /// The account was a validator or proposed to become one, and was kicked out.
+    KickedOut(ValidatorKickoutReason),
+    /// The account proposed to become a validator, but its pledge was below `needed`.
+    NotEnoughPledge {
+        #[serde(with = "dec_format")]
+        needed: Balance,
+    },
+    /// The account has nothing to do with the validator set of the next epoch.
+    NotProposed,
+}
+
+impl NextEpochStatus {
+    pub fn new(account_id: &AccountId, next_epoch_info: &EpochInfo) -> Self {
+        if let Some(validator_id) = next_epoch_info.get_validator_id(account_id) {
+            let seats = next_epoch_info
+                .block_producers_settlement()
+                .iter()
+                .filter(|id| *id == validator_id)
+                .count() as NumSeats;
+            let pledge = next_epoch_info.validator_stake(*validator_id);
+            return Self::Elected { seats, pledge };
+        }
+        match next_epoch_info.validator_kickout().get(account_id) {
+            Some(ValidatorKickoutReason::NotEnoughPledge { threshold, .. }) => {
+                Self::NotEnoughPledge { needed: *threshold }
+            }
+            Some(ValidatorKickoutReason::DidNotGetASeat) => {
+                Self::NotEnoughPledge { needed: next_epoch_info.seat_price() }
+            }
+            Some(reason) => Self::KickedOut(reason.clone()),
+            None => Self::NotProposed,
         }
     }
 }
@@ -299,6 +299,7 @@ fn test_query_state() {
                     account_id: "test".parse().unwrap(),
                     prefix: vec![].into(),
                     include_proof: false,
+                    state_size_limit_override: None,
                 },
             })
             .await
@@ -74,6 +74,7 @@ fn parse_path_data(path: String, data: String) -> Result<RpcQueryRequest, RpcPar
             account_id,
             prefix: parse_data()?.into(),
             include_proof: false,
+            state_size_limit_override: None,
         },
         "call" => match maybe_extra_arg {
             Some(method_name) => QueryRequest::CallFunction {
@@ -287,6 +287,18 @@ impl JsonRpcHandler {
                     Ok(params) => params,
                     Err(err) => return (method_name, Err(RpcError::from(err))),
                 };
+                if let QueryRequest::ViewState { state_size_limit_override: Some(_), .. } =
+                    params.request
+                {
+                    if !self.enable_debug_rpc {
+                        return (
+                            method_name,
+                            Err(RpcError::invalid_params(
+                                "state_size_limit_override requires debug RPC to be enabled",
+                            )),
+                        );
+                    }
+                }
                 let metrics_name = match params.request {
                     QueryRequest::ViewAccount { .. } => "query_view_account",
                     QueryRequest::ViewCode { .. } => "query_view_code",
@@ -371,6 +371,12 @@ pub struct ClientConfig {
     pub view_client_throttle_period: Duration,
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     pub trie_viewer_state_size_limit: Option<u64>,
+    /// Treat contract code missing from the state as empty in view queries instead of
+    /// failing them with an error.
+    pub trie_viewer_allow_missing_code: bool,
+    /// Largest state size limit a view state query may ask for in place of
+    /// `trie_viewer_state_size_limit`. None rejects all such overrides.
+    pub trie_viewer_hard_state_size_limit: Option<u64>,
     /// Max burnt gas per view method.  If present, overrides value stored in
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
@@ -463,6 +469,8 @@ impl ClientConfig {
             view_client_threads: 1,
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
+            trie_viewer_allow_missing_code: false,
+            trie_viewer_hard_state_size_limit: None,
             max_gas_burnt_view: None,
             enable_statistics_export: true,
             client_background_migration_threads: 1,
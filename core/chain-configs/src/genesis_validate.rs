@@ -14,31 +14,30 @@ pub fn validate_genesis(genesis: &Genesis) -> Result<(), ValidationError> {
         // could still validate things that aren't related to records.
         return Ok(());
     }
-    let mut validation_errors = ValidationErrors::new();
-    let mut genesis_validator = GenesisValidator::new(&genesis.config, &mut validation_errors);
+    let mut genesis_validator = GenesisValidator::new(&genesis.config);
     tracing::info!(target: "config", "Validating Genesis config and records. This could take a few minutes...");
     genesis.for_each_record(|record: &StateRecord| {
         genesis_validator.process_record(record);
     });
-    genesis_validator.validate_processed_records();
-    genesis_validator.result_with_full_error()
+    genesis_validator.finish()
 }
 
-struct GenesisValidator<'a> {
+/// Runs the checks of [`validate_genesis`] on records fed to it one by one.
+/// Useful for callers which read the records themselves, e.g. to report
+/// malformed records as errors rather than panicking like
+/// [`Genesis::for_each_record`] does.
+pub struct GenesisValidator<'a> {
     genesis_config: &'a GenesisConfig,
     total_supply: u128,
     pledged_accounts: HashMap<AccountId, u128>,
     account_ids: HashSet<AccountId>,
     access_key_account_ids: HashSet<AccountId>,
     contract_account_ids: HashSet<AccountId>,
-    validation_errors: &'a mut ValidationErrors,
+    validation_errors: ValidationErrors,
 }
 
 impl<'a> GenesisValidator<'a> {
-    pub fn new(
-        genesis_config: &'a GenesisConfig,
-        validation_errors: &'a mut ValidationErrors,
-    ) -> Self {
+    pub fn new(genesis_config: &'a GenesisConfig) -> Self {
         Self {
             genesis_config,
             total_supply: 0,
@@ -46,10 +45,17 @@ impl<'a> GenesisValidator<'a> {
             account_ids: HashSet::new(),
             access_key_account_ids: HashSet::new(),
             contract_account_ids: HashSet::new(),
-            validation_errors: validation_errors,
+            validation_errors: ValidationErrors::new(),
         }
     }
 
+    /// Runs the checks which need all the records, and returns all the
+    /// problems found.
+    pub fn finish(mut self) -> Result<(), ValidationError> {
+        self.validate_processed_records();
+        self.result_with_full_error()
+    }
+
     pub fn process_record(&mut self, record: &StateRecord) {
         match record {
             StateRecord::Account { account_id, account } => {
@@ -79,7 +85,7 @@ impl<'a> GenesisValidator<'a> {
         }
     }
 
-    pub fn validate_processed_records(&mut self) {
+    fn validate_processed_records(&mut self) {
         let validators = self
             .genesis_config
             .validators
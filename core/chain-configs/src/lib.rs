@@ -23,6 +23,7 @@ pub use client_config::{
 };
 pub use genesis_config::{
     get_initial_supply, stream_records_from_file, Genesis, GenesisChangeConfig, GenesisConfig,
-    GenesisContents, GenesisRecords, GenesisValidationMode, ProtocolConfig, ProtocolConfigView,
+    GenesisContents, GenesisJsonHasher, GenesisRecords, GenesisValidationMode, ProtocolConfig,
+    ProtocolConfigView,
 };
 pub use updateable_config::{MutableConfigValue, UpdateableClientConfig};
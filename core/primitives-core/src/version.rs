@@ -130,6 +130,9 @@ pub enum ProtocolFeature {
     /// NEP: https://github.com/Utility/UEPs/pull/509
     ChunkValidation,
     EthImplicitAccounts,
+    /// Counts the protocol versions of chunk producers, and not only of block producers, when
+    /// deciding whether to upgrade, weighing each validator by its pledge.
+    ChunkProducerUpgradeVotes,
 }
 
 impl ProtocolFeature {
@@ -186,6 +189,7 @@ impl ProtocolFeature {
             ProtocolFeature::RejectBlocksWithOutdatedProtocolVersions => 132,
             ProtocolFeature::ChunkValidation => 137,
             ProtocolFeature::EthImplicitAccounts => 138,
+            ProtocolFeature::ChunkProducerUpgradeVotes => 139,
         }
     }
 }
@@ -249,6 +249,7 @@ impl Block {
         let mut gas_used = 0;
         // This computation of chunk_mask relies on the fact that chunks are ordered by shard_id.
         let mut chunk_mask = vec![];
+        let mut chunk_producer_versions = vec![];
         let mut balance_burnt = 0;
         let mut gas_limit = 0;
         for chunk in chunks.iter() {
@@ -259,8 +260,10 @@ impl Block {
                 gas_limit += chunk.gas_limit();
                 balance_burnt += chunk.prev_balance_burnt();
                 chunk_mask.push(true);
+                chunk_producer_versions.push(chunk.producer_protocol_version());
             } else {
                 chunk_mask.push(false);
+                chunk_producer_versions.push(None);
             }
         }
         let next_gas_price = Self::compute_next_gas_price(
@@ -298,6 +301,9 @@ impl Block {
             BlockHeader::BlockHeaderV4(_) => {
                 debug_assert_eq!(prev.block_ordinal() + 1, block_ordinal)
             }
+            BlockHeader::BlockHeaderV5(_) => {
+                debug_assert_eq!(prev.block_ordinal() + 1, block_ordinal)
+            }
         };
 
         let body = BlockBody { chunks, challenges, vrf_value, vrf_proof };
@@ -318,6 +324,7 @@ impl Block {
             prev_validator_power_proposals,
             prev_validator_pledge_proposals,
             chunk_mask,
+            chunk_producer_versions,
             block_ordinal,
             epoch_id,
             next_epoch_id,
@@ -608,6 +615,26 @@ impl Block {
             return Err(InvalidChunkMask);
         }
 
+        // Check that chunk producer versions stored in the header match the versions in the
+        // headers of the included chunks
+        if let Some(chunk_producer_versions) = self.header().chunk_producer_versions() {
+            let expected: Vec<_> = self
+                .chunks()
+                .iter()
+                .zip(&chunk_mask)
+                .map(|(chunk, &included)| {
+                    if included {
+                        chunk.producer_protocol_version()
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            if chunk_producer_versions != &expected[..] {
+                return Err(InvalidChunkMask);
+            }
+        }
+
         // Check that challenges root stored in the header matches the challenges root of the challenges
         let challenges_root = Block::compute_challenges_root(self.challenges());
         if self.header().challenges_root() != &challenges_root {
@@ -205,6 +205,56 @@ pub struct BlockHeaderInnerRestV4 {
     pub latest_protocol_version: ProtocolVersion,
 }
 
+/// Add `chunk_producer_versions`
+#[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct BlockHeaderInnerRestV5 {
+    /// Hash of block body
+    pub block_body_hash: CryptoHash,
+    /// Root hash of the previous chunks' outgoing receipts in the given block.
+    pub prev_chunk_outgoing_receipts_root: MerkleHash,
+    /// Root hash of the chunk headers in the given block.
+    pub chunk_headers_root: MerkleHash,
+    /// Root hash of the chunk transactions in the given block.
+    pub chunk_tx_root: MerkleHash,
+    /// Root hash of the challenges in the given block.
+    pub challenges_root: MerkleHash,
+    /// The output of the randomness beacon
+    pub random_value: CryptoHash,
+    /// Validator proposals from the previous chunks.
+    pub prev_validator_power_proposals: Vec<ValidatorPower>,
+    /// Validator proposals from the previous chunks.
+    pub prev_validator_pledge_proposals: Vec<ValidatorPledge>,
+    /// Mask for new chunks included in the block
+    pub chunk_mask: Vec<bool>,
+    /// Latest protocol version of the producer of each new chunk included in the block, taken
+    /// from the chunk header.  `None` for shards without a new chunk.
+    pub chunk_producer_versions: Vec<Option<ProtocolVersion>>,
+    /// Gas price for chunks in the next block.
+    pub next_gas_price: Balance,
+    /// Total supply of tokens in the system
+    pub total_supply: Balance,
+    /// List of challenges result from previous block.
+    pub challenges_result: ChallengesResult,
+
+    /// Last block that has full BFT finality
+    pub last_final_block: CryptoHash,
+    /// Last block that has doomslug finality
+    pub last_ds_final_block: CryptoHash,
+
+    /// The ordinal of the Block on the Canonical Chain
+    pub block_ordinal: NumBlocks,
+
+    pub prev_height: BlockHeight,
+
+    pub epoch_sync_data_hash: Option<CryptoHash>,
+
+    /// All the approvals included in this block
+    pub approvals: Vec<Option<Box<Signature>>>,
+
+    /// Latest protocol version that this block producer has.
+    pub latest_protocol_version: ProtocolVersion,
+}
+
 /// The part of the block approval that is different for endorsements and skips
 #[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ApprovalInner {
@@ -358,6 +408,25 @@ pub struct BlockHeaderV4 {
     pub hash: CryptoHash,
 }
 
+/// V4 -> V5: Add versions of the chunk producers to inner_rest
+#[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, Eq, PartialEq)]
+#[borsh(init=init)]
+pub struct BlockHeaderV5 {
+    pub prev_hash: CryptoHash,
+
+    /// Inner part of the block header that gets hashed, split into two parts, one that is sent
+    ///    to light clients, and the rest
+    pub inner_lite: BlockHeaderInnerLite,
+    pub inner_rest: BlockHeaderInnerRestV5,
+
+    /// Signature of the block producer.
+    pub signature: Signature,
+
+    /// Cached value of hash for this block.
+    #[borsh(skip)]
+    pub hash: CryptoHash,
+}
+
 impl BlockHeaderV2 {
     pub fn init(&mut self) {
         self.hash = BlockHeader::compute_hash(
@@ -388,6 +457,16 @@ impl BlockHeaderV4 {
     }
 }
 
+impl BlockHeaderV5 {
+    pub fn init(&mut self) {
+        self.hash = BlockHeader::compute_hash(
+            self.prev_hash,
+            &borsh::to_vec(&self.inner_lite).expect("Failed to serialize"),
+            &borsh::to_vec(&self.inner_rest).expect("Failed to serialize"),
+        );
+    }
+}
+
 /// Versioned BlockHeader data structure.
 /// For each next version, document what are the changes between versions.
 #[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, Eq, PartialEq)]
@@ -396,6 +475,7 @@ pub enum BlockHeader {
     BlockHeaderV2(Arc<BlockHeaderV2>),
     BlockHeaderV3(Arc<BlockHeaderV3>),
     BlockHeaderV4(Arc<BlockHeaderV4>),
+    BlockHeaderV5(Arc<BlockHeaderV5>),
 }
 
 impl BlockHeader {
@@ -428,6 +508,7 @@ impl BlockHeader {
         prev_validator_power_proposals: Vec<ValidatorPower>,
         prev_validator_pledge_proposals: Vec<ValidatorPledge>,
         chunk_mask: Vec<bool>,
+        chunk_producer_versions: Vec<Option<ProtocolVersion>>,
         block_ordinal: NumBlocks,
         epoch_id: EpochId,
         next_epoch_id: EpochId,
@@ -567,7 +648,11 @@ impl BlockHeader {
                 signature,
                 hash,
             }))
-        } else {
+        } else if !crate::checked_feature!(
+            "stable",
+            ChunkProducerUpgradeVotes,
+            this_epoch_protocol_version
+        ) {
             let inner_rest = BlockHeaderInnerRestV4 {
                 block_body_hash,
                 prev_chunk_outgoing_receipts_root,
@@ -601,6 +686,41 @@ impl BlockHeader {
                 signature,
                 hash,
             }))
+        } else {
+            let inner_rest = BlockHeaderInnerRestV5 {
+                block_body_hash,
+                prev_chunk_outgoing_receipts_root,
+                chunk_headers_root,
+                chunk_tx_root,
+                challenges_root,
+                random_value,
+                prev_validator_power_proposals,
+                prev_validator_pledge_proposals,
+                chunk_mask,
+                chunk_producer_versions,
+                next_gas_price,
+                block_ordinal,
+                total_supply,
+                challenges_result,
+                last_final_block,
+                last_ds_final_block,
+                prev_height,
+                epoch_sync_data_hash,
+                approvals,
+                latest_protocol_version: get_protocol_version(next_epoch_protocol_version),
+            };
+            let (hash, signature) = signer.sign_block_header_parts(
+                prev_hash,
+                &borsh::to_vec(&inner_lite).expect("Failed to serialize"),
+                &borsh::to_vec(&inner_rest).expect("Failed to serialize"),
+            );
+            Self::BlockHeaderV5(Arc::new(BlockHeaderV5 {
+                prev_hash,
+                inner_lite,
+                inner_rest,
+                signature,
+                hash,
+            }))
         }
     }
 
@@ -726,7 +846,11 @@ impl BlockHeader {
                 signature: Signature::empty(KeyType::ED25519),
                 hash,
             }))
-        } else {
+        } else if !crate::checked_feature!(
+            "stable",
+            ChunkProducerUpgradeVotes,
+            genesis_protocol_version
+        ) {
             let inner_rest = BlockHeaderInnerRestV4 {
                 prev_chunk_outgoing_receipts_root,
                 chunk_headers_root,
@@ -760,6 +884,41 @@ impl BlockHeader {
                 signature: Signature::empty(KeyType::ED25519),
                 hash,
             }))
+        } else {
+            let inner_rest = BlockHeaderInnerRestV5 {
+                prev_chunk_outgoing_receipts_root,
+                chunk_headers_root,
+                chunk_tx_root,
+                challenges_root,
+                block_body_hash,
+                random_value: CryptoHash::default(),
+                prev_validator_power_proposals: vec![],
+                prev_validator_pledge_proposals: vec![],
+                chunk_mask: vec![true; chunks_included as usize],
+                chunk_producer_versions: vec![None; chunks_included as usize],
+                block_ordinal: 1, // It is guaranteed that Chain has the only Block which is Genesis
+                next_gas_price: initial_gas_price,
+                total_supply: initial_total_supply,
+                challenges_result: vec![],
+                last_final_block: CryptoHash::default(),
+                last_ds_final_block: CryptoHash::default(),
+                prev_height: 0,
+                epoch_sync_data_hash: None, // Epoch Sync cannot be executed up to Genesis
+                approvals: vec![],
+                latest_protocol_version: genesis_protocol_version,
+            };
+            let hash = BlockHeader::compute_hash(
+                CryptoHash::default(),
+                &borsh::to_vec(&inner_lite).expect("Failed to serialize"),
+                &borsh::to_vec(&inner_rest).expect("Failed to serialize"),
+            );
+            Self::BlockHeaderV5(Arc::new(BlockHeaderV5 {
+                prev_hash: CryptoHash::default(),
+                inner_lite,
+                inner_rest,
+                signature: Signature::empty(KeyType::ED25519),
+                hash,
+            }))
         }
     }
 
@@ -770,6 +929,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.hash,
             BlockHeader::BlockHeaderV3(header) => &header.hash,
             BlockHeader::BlockHeaderV4(header) => &header.hash,
+            BlockHeader::BlockHeaderV5(header) => &header.hash,
         }
     }
 
@@ -780,6 +940,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.prev_hash,
             BlockHeader::BlockHeaderV3(header) => &header.prev_hash,
             BlockHeader::BlockHeaderV4(header) => &header.prev_hash,
+            BlockHeader::BlockHeaderV5(header) => &header.prev_hash,
         }
     }
 
@@ -790,6 +951,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.signature,
             BlockHeader::BlockHeaderV3(header) => &header.signature,
             BlockHeader::BlockHeaderV4(header) => &header.signature,
+            BlockHeader::BlockHeaderV5(header) => &header.signature,
         }
     }
 
@@ -800,6 +962,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.height,
             BlockHeader::BlockHeaderV3(header) => header.inner_lite.height,
             BlockHeader::BlockHeaderV4(header) => header.inner_lite.height,
+            BlockHeader::BlockHeaderV5(header) => header.inner_lite.height,
         }
     }
 
@@ -810,6 +973,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(_) => None,
             BlockHeader::BlockHeaderV3(header) => Some(header.inner_rest.prev_height),
             BlockHeader::BlockHeaderV4(header) => Some(header.inner_rest.prev_height),
+            BlockHeader::BlockHeaderV5(header) => Some(header.inner_rest.prev_height),
         }
     }
 
@@ -820,6 +984,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.epoch_id,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.epoch_id,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.epoch_id,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.epoch_id,
         }
     }
 
@@ -830,6 +995,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_epoch_id,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_epoch_id,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.next_epoch_id,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.next_epoch_id,
         }
     }
 
@@ -840,6 +1006,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.prev_state_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.prev_state_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.prev_state_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.prev_state_root,
         }
     }
 
@@ -858,6 +1025,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV4(header) => {
                 &header.inner_rest.prev_chunk_outgoing_receipts_root
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                &header.inner_rest.prev_chunk_outgoing_receipts_root
+            }
         }
     }
 
@@ -868,6 +1038,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_headers_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_headers_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_headers_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.chunk_headers_root,
         }
     }
 
@@ -878,6 +1049,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_tx_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_tx_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_tx_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.chunk_tx_root,
         }
     }
 
@@ -887,6 +1059,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_mask,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.chunk_mask,
         };
         mask.iter().map(|&x| u64::from(x)).sum::<u64>()
     }
@@ -898,6 +1071,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.challenges_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.challenges_root,
         }
     }
 
@@ -908,6 +1082,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.prev_outcome_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.prev_outcome_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.prev_outcome_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.prev_outcome_root,
         }
     }
 
@@ -918,6 +1093,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(_) => None,
             BlockHeader::BlockHeaderV3(_) => None,
             BlockHeader::BlockHeaderV4(header) => Some(header.inner_rest.block_body_hash),
+            BlockHeader::BlockHeaderV5(header) => Some(header.inner_rest.block_body_hash),
         }
     }
 
@@ -928,6 +1104,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.timestamp,
             BlockHeader::BlockHeaderV3(header) => header.inner_lite.timestamp,
             BlockHeader::BlockHeaderV4(header) => header.inner_lite.timestamp,
+            BlockHeader::BlockHeaderV5(header) => header.inner_lite.timestamp,
         }
     }
 
@@ -946,6 +1123,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV4(header) => {
                 ValidatorPowerIter::new(&header.inner_rest.prev_validator_power_proposals)
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                ValidatorPowerIter::new(&header.inner_rest.prev_validator_power_proposals)
+            }
         }
     }
 
@@ -964,6 +1144,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV4(header) => {
                 ValidatorPledgeIter::new(&header.inner_rest.prev_validator_pledge_proposals)
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                ValidatorPledgeIter::new(&header.inner_rest.prev_validator_pledge_proposals)
+            }
         }
     }
 
@@ -974,6 +1157,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_mask,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.chunk_mask,
         }
     }
 
@@ -984,6 +1168,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(_) => 0, // not applicable
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.block_ordinal,
             BlockHeader::BlockHeaderV4(header) => header.inner_rest.block_ordinal,
+            BlockHeader::BlockHeaderV5(header) => header.inner_rest.block_ordinal,
         }
     }
 
@@ -994,6 +1179,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.next_gas_price,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.next_gas_price,
             BlockHeader::BlockHeaderV4(header) => header.inner_rest.next_gas_price,
+            BlockHeader::BlockHeaderV5(header) => header.inner_rest.next_gas_price,
         }
     }
 
@@ -1004,6 +1190,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.total_supply,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.total_supply,
             BlockHeader::BlockHeaderV4(header) => header.inner_rest.total_supply,
+            BlockHeader::BlockHeaderV5(header) => header.inner_rest.total_supply,
         }
     }
 
@@ -1014,6 +1201,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.random_value,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.random_value,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.random_value,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.random_value,
         }
     }
 
@@ -1024,6 +1212,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_final_block,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_final_block,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.last_final_block,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.last_final_block,
         }
     }
 
@@ -1034,6 +1223,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_ds_final_block,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_ds_final_block,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.last_ds_final_block,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.last_ds_final_block,
         }
     }
 
@@ -1044,6 +1234,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_result,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_result,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.challenges_result,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.challenges_result,
         }
     }
 
@@ -1054,6 +1245,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_bp_hash,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_bp_hash,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.next_bp_hash,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.next_bp_hash,
         }
     }
 
@@ -1064,6 +1256,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.block_merkle_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.block_merkle_root,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite.block_merkle_root,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite.block_merkle_root,
         }
     }
 
@@ -1074,6 +1267,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(_) => None,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.epoch_sync_data_hash,
             BlockHeader::BlockHeaderV4(header) => header.inner_rest.epoch_sync_data_hash,
+            BlockHeader::BlockHeaderV5(header) => header.inner_rest.epoch_sync_data_hash,
         }
     }
 
@@ -1084,6 +1278,20 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.approvals,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.approvals,
             BlockHeader::BlockHeaderV4(header) => &header.inner_rest.approvals,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_rest.approvals,
+        }
+    }
+
+    /// Latest protocol version advertised by the producer of each chunk included in the
+    /// block, by shard, if the header carries them.
+    #[inline]
+    pub fn chunk_producer_versions(&self) -> Option<&[Option<ProtocolVersion>]> {
+        match self {
+            BlockHeader::BlockHeaderV1(_) => None,
+            BlockHeader::BlockHeaderV2(_) => None,
+            BlockHeader::BlockHeaderV3(_) => None,
+            BlockHeader::BlockHeaderV4(_) => None,
+            BlockHeader::BlockHeaderV5(header) => Some(&header.inner_rest.chunk_producer_versions),
         }
     }
 
@@ -1109,6 +1317,15 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(_header) => true,
             BlockHeader::BlockHeaderV3(_header) => true,
             BlockHeader::BlockHeaderV4(_header) => true,
+            BlockHeader::BlockHeaderV5(header) => {
+                let inner_rest = &header.inner_rest;
+                inner_rest.chunk_producer_versions.len() == inner_rest.chunk_mask.len()
+                    && inner_rest
+                        .chunk_producer_versions
+                        .iter()
+                        .zip(&inner_rest.chunk_mask)
+                        .all(|(version, &included)| included || version.is_none())
+            }
         }
     }
 
@@ -1119,6 +1336,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.latest_protocol_version,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.latest_protocol_version,
             BlockHeader::BlockHeaderV4(header) => header.inner_rest.latest_protocol_version,
+            BlockHeader::BlockHeaderV5(header) => header.inner_rest.latest_protocol_version,
         }
     }
 
@@ -1136,6 +1354,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV4(header) => {
                 borsh::to_vec(&header.inner_lite).expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                borsh::to_vec(&header.inner_lite).expect("Failed to serialize")
+            }
         }
     }
 
@@ -1153,6 +1374,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV4(header) => {
                 borsh::to_vec(&header.inner_rest).expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                borsh::to_vec(&header.inner_rest).expect("Failed to serialize")
+            }
         }
     }
 }
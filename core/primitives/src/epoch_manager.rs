@@ -116,6 +116,9 @@ impl ShardConfig {
 pub struct AllEpochConfigTestOverrides {
     pub block_producer_kickout_threshold: Option<u8>,
     pub chunk_producer_kickout_threshold: Option<u8>,
+    /// Shard layout to switch to from the given protocol version on, for tests of resharding.
+    /// Applied whether or not the production config is used.
+    pub resharding: Option<(ProtocolVersion, ShardLayout)>,
 }
 
 /// AllEpochConfig manages protocol configs that might be changing throughout epochs (hence EpochConfig).
@@ -166,6 +169,7 @@ impl AllEpochConfig {
     pub fn for_protocol_version(&self, protocol_version: ProtocolVersion) -> EpochConfig {
         let mut config = self.genesis_epoch_config.clone();
         if !self.use_production_config {
+            Self::config_test_resharding(&mut config, &self.test_overrides, protocol_version);
             return config;
         }
 
@@ -177,6 +181,8 @@ impl AllEpochConfig {
 
         Self::config_test_overrides(&mut config, &self.test_overrides);
 
+        Self::config_test_resharding(&mut config, &self.test_overrides, protocol_version);
+
         config
     }
 
@@ -247,6 +253,18 @@ impl AllEpochConfig {
             config.chunk_producer_kickout_threshold = chunk_producer_kickout_threshold;
         }
     }
+
+    fn config_test_resharding(
+        config: &mut EpochConfig,
+        test_overrides: &AllEpochConfigTestOverrides,
+        protocol_version: ProtocolVersion,
+    ) {
+        if let Some((resharding_version, shard_layout)) = &test_overrides.resharding {
+            if protocol_version >= *resharding_version {
+                Self::config_nightshade_impl(config, shard_layout.clone());
+            }
+        }
+    }
 }
 
 /// Additional configuration parameters for the new validator selection
@@ -542,6 +560,7 @@ pub mod block_info {
     pub enum BlockInfo {
         V1(BlockInfoV1),
         V2(BlockInfoV2),
+        V3(BlockInfoV3),
     }
 
     impl Default for BlockInfo {
@@ -582,7 +601,7 @@ pub mod block_info {
             validator_kickout: HashMap<AccountId, ValidatorKickoutReason>,
             validator_mandates: ValidatorMandates,
         ) -> Self {
-            Self::V2(BlockInfoV2 {
+            let base = BlockInfoV2 {
                 hash,
                 height,
                 last_finalized_height,
@@ -625,14 +644,17 @@ pub mod block_info {
                 validator_kickout,
                 validator_mandates,
                 // end customized by James Savechives
-            })
+            };
+            Self::V2(base)
         }
 
         #[inline]
         pub fn power_proposals_iter(&self) -> ValidatorPowerIter {
             match self {
                 Self::V1(v1) => ValidatorPowerIter::v1(&v1.power_proposals),
-                Self::V2(v2) => ValidatorPowerIter::new(&v2.power_proposals),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    ValidatorPowerIter::new(&v2.power_proposals)
+                }
             }
         }
 
@@ -640,21 +662,23 @@ pub mod block_info {
         pub fn pledge_proposals_iter(&self) -> ValidatorPledgeIter {
             match self {
                 Self::V1(v1) => ValidatorPledgeIter::v1(&v1.pledge_proposals),
-                Self::V2(v2) => ValidatorPledgeIter::new(&v2.pledge_proposals),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    ValidatorPledgeIter::new(&v2.pledge_proposals)
+                }
             }
         }
         #[inline]
         pub fn hash(&self) -> &CryptoHash {
             match self {
                 Self::V1(v1) => &v1.hash,
-                Self::V2(v2) => &v2.hash,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.hash,
             }
         }
         #[inline]
         pub fn slashed(&self) -> &HashMap<AccountId, SlashState> {
             match self {
                 Self::V1(v1) => &v1.slashed,
-                Self::V2(v2) => &v2.slashed,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.slashed,
             }
         }
 
@@ -662,7 +686,7 @@ pub mod block_info {
         pub fn slashed_mut(&mut self) -> &mut HashMap<AccountId, SlashState> {
             match self {
                 Self::V1(v1) => &mut v1.slashed,
-                Self::V2(v2) => &mut v2.slashed,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &mut v2.slashed,
             }
         }
 
@@ -670,7 +694,7 @@ pub mod block_info {
         pub fn height(&self) -> BlockHeight {
             match self {
                 Self::V1(v1) => v1.height,
-                Self::V2(v2) => v2.height,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2.height,
             }
         }
 
@@ -678,7 +702,7 @@ pub mod block_info {
         pub fn last_finalized_height(&self) -> BlockHeight {
             match self {
                 Self::V1(v1) => v1.last_finalized_height,
-                Self::V2(v2) => v2.last_finalized_height,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2.last_finalized_height,
             }
         }
 
@@ -686,7 +710,7 @@ pub mod block_info {
         pub fn last_final_block_hash(&self) -> &CryptoHash {
             match self {
                 Self::V1(v1) => &v1.last_final_block_hash,
-                Self::V2(v2) => &v2.last_final_block_hash,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.last_final_block_hash,
             }
         }
 
@@ -694,7 +718,7 @@ pub mod block_info {
         pub fn prev_hash(&self) -> &CryptoHash {
             match self {
                 Self::V1(v1) => &v1.prev_hash,
-                Self::V2(v2) => &v2.prev_hash,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.prev_hash,
             }
         }
 
@@ -702,7 +726,7 @@ pub mod block_info {
         pub fn epoch_first_block(&self) -> &CryptoHash {
             match self {
                 Self::V1(v1) => &v1.epoch_first_block,
-                Self::V2(v2) => &v2.epoch_first_block,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.epoch_first_block,
             }
         }
 
@@ -710,7 +734,7 @@ pub mod block_info {
         pub fn epoch_first_block_mut(&mut self) -> &mut CryptoHash {
             match self {
                 Self::V1(v1) => &mut v1.epoch_first_block,
-                Self::V2(v2) => &mut v2.epoch_first_block,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &mut v2.epoch_first_block,
             }
         }
 
@@ -718,7 +742,7 @@ pub mod block_info {
         pub fn epoch_id(&self) -> &EpochId {
             match self {
                 Self::V1(v1) => &v1.epoch_id,
-                Self::V2(v2) => &v2.epoch_id,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.epoch_id,
             }
         }
 
@@ -726,7 +750,7 @@ pub mod block_info {
         pub fn epoch_id_mut(&mut self) -> &mut EpochId {
             match self {
                 Self::V1(v1) => &mut v1.epoch_id,
-                Self::V2(v2) => &mut v2.epoch_id,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &mut v2.epoch_id,
             }
         }
 
@@ -734,7 +758,7 @@ pub mod block_info {
         pub fn chunk_mask(&self) -> &[bool] {
             match self {
                 Self::V1(v1) => &v1.chunk_mask,
-                Self::V2(v2) => &v2.chunk_mask,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.chunk_mask,
             }
         }
 
@@ -742,7 +766,35 @@ pub mod block_info {
         pub fn latest_protocol_version(&self) -> &ProtocolVersion {
             match self {
                 Self::V1(v1) => &v1.latest_protocol_version,
-                Self::V2(v2) => &v2.latest_protocol_version,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    &v2.latest_protocol_version
+                }
+            }
+        }
+
+        /// Protocol versions advertised by the producers of the chunks included in this block,
+        /// indexed by shard, with `None` for shards whose producer's version isn't known.
+        /// Empty for blocks stored before the versions were recorded.
+        #[inline]
+        pub fn chunk_producer_versions(&self) -> &[Option<ProtocolVersion>] {
+            match self {
+                Self::V1(_) | Self::V2(_) => &[],
+                Self::V3(v3) => &v3.chunk_producer_versions,
+            }
+        }
+
+        /// Records the versions returned by `chunk_producer_versions()`, upgrading a `V2` block
+        /// info to `V3` if there are any.  `V1` block infos predate the versions and are left
+        /// as they are.
+        pub fn set_chunk_producer_versions(&mut self, versions: Vec<Option<ProtocolVersion>>) {
+            match self {
+                Self::V1(_) => {}
+                Self::V2(_) if versions.is_empty() => {}
+                Self::V2(v2) => {
+                    let base = std::mem::take(v2);
+                    *self = Self::V3(BlockInfoV3 { base, chunk_producer_versions: versions });
+                }
+                Self::V3(v3) => v3.chunk_producer_versions = versions,
             }
         }
 
@@ -750,7 +802,7 @@ pub mod block_info {
         pub fn total_supply(&self) -> &Balance {
             match self {
                 Self::V1(v1) => &v1.total_supply,
-                Self::V2(v2) => &v2.total_supply,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.total_supply,
             }
         }
 
@@ -758,7 +810,7 @@ pub mod block_info {
         pub fn timestamp_nanosec(&self) -> &u64 {
             match self {
                 Self::V1(v1) => &v1.timestamp_nanosec,
-                Self::V2(v2) => &v2.timestamp_nanosec,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.timestamp_nanosec,
             }
         }
 
@@ -767,7 +819,7 @@ pub mod block_info {
         pub fn random_value(&self) -> &CryptoHash {
             match self {
                 Self::V1(v1) => &v1.random_value,
-                Self::V2(v2) => &v2.random_value,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.random_value,
             }
         }
 
@@ -775,7 +827,7 @@ pub mod block_info {
         pub fn seat_price(&self) -> Balance {
             match self {
                 Self::V1(v1) => v1.seat_price,
-                Self::V2(v2) => v2.seat_price,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2.seat_price,
             }
         }
 
@@ -783,7 +835,7 @@ pub mod block_info {
         pub fn minted_amount(&self) -> Balance {
             match self {
                 Self::V1(v1) => v1.minted_amount,
-                Self::V2(v2) => v2.minted_amount,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2.minted_amount,
             }
         }
 
@@ -791,7 +843,9 @@ pub mod block_info {
         pub fn block_producers_settlement(&self) -> &[ValidatorId] {
             match self {
                 Self::V1(v1) => &v1.block_producers_settlement,
-                Self::V2(v2) => &v2.block_producers_settlement,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    &v2.block_producers_settlement
+                }
             }
         }
 
@@ -799,7 +853,9 @@ pub mod block_info {
         pub fn chunk_producers_settlement(&self) -> &[Vec<ValidatorId>] {
             match self {
                 Self::V1(v1) => &v1.chunk_producers_settlement,
-                Self::V2(v2) => &v2.chunk_producers_settlement,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    &v2.chunk_producers_settlement
+                }
             }
         }
 
@@ -807,7 +863,7 @@ pub mod block_info {
         pub fn validator_kickout(&self) -> &HashMap<AccountId, ValidatorKickoutReason> {
             match self {
                 Self::V1(v1) => &v1.validator_kickout,
-                Self::V2(v2) => &v2.validator_kickout,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.validator_kickout,
             }
         }
 
@@ -815,7 +871,7 @@ pub mod block_info {
         pub fn pledge_change(&self) -> &BTreeMap<AccountId, Balance> {
             match self {
                 Self::V1(v1) => &v1.pledge_change,
-                Self::V2(v2) => &v2.pledge_change,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.pledge_change,
             }
         }
 
@@ -823,7 +879,7 @@ pub mod block_info {
         pub fn power_change(&self) -> &BTreeMap<AccountId, Power> {
             match self {
                 Self::V1(v1) => &v1.power_change,
-                Self::V2(v2) => &v2.power_change,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.power_change,
             }
         }
 
@@ -831,7 +887,7 @@ pub mod block_info {
         pub fn validator_reward(&self) -> &HashMap<AccountId, Balance> {
             match self {
                 Self::V1(v1) => &v1.validator_reward,
-                Self::V2(v2) => &v2.validator_reward,
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => &v2.validator_reward,
             }
         }
 
@@ -839,7 +895,9 @@ pub mod block_info {
         pub fn validators_iter(&self) -> ValidatorPowerAndPledgeIter {
             match self {
                 Self::V1(v1) => ValidatorPowerAndPledgeIter::new(&v1.validators),
-                Self::V2(v2) => ValidatorPowerAndPledgeIter::new(&v2.validators),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    ValidatorPowerAndPledgeIter::new(&v2.validators)
+                }
             }
         }
 
@@ -847,7 +905,9 @@ pub mod block_info {
         pub fn fishermen_iter(&self) -> ValidatorPowerAndPledgeIter {
             match self {
                 Self::V1(v1) => ValidatorPowerAndPledgeIter::new(&v1.fishermen),
-                Self::V2(v2) => ValidatorPowerAndPledgeIter::new(&v2.fishermen),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    ValidatorPowerAndPledgeIter::new(&v2.fishermen)
+                }
             }
         }
 
@@ -855,7 +915,9 @@ pub mod block_info {
         pub fn validator_power(&self, validator_id: u64) -> Power {
             match self {
                 Self::V1(v1) => v1.validators[validator_id as usize].power(),
-                Self::V2(v2) => v2.validators[validator_id as usize].power(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validators[validator_id as usize].power()
+                }
             }
         }
 
@@ -863,7 +925,9 @@ pub mod block_info {
         pub fn validator_stake(&self, validator_id: u64) -> Balance {
             match self {
                 Self::V1(v1) => v1.validators[validator_id as usize].pledge(),
-                Self::V2(v2) => v2.validators[validator_id as usize].pledge(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validators[validator_id as usize].pledge()
+                }
             }
         }
 
@@ -871,7 +935,9 @@ pub mod block_info {
         pub fn validator_account_id(&self, validator_id: u64) -> &AccountId {
             match self {
                 Self::V1(v1) => v1.validators[validator_id as usize].account_id(),
-                Self::V2(v2) => v2.validators[validator_id as usize].account_id(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validators[validator_id as usize].account_id()
+                }
             }
         }
         
@@ -879,14 +945,18 @@ pub mod block_info {
         pub fn account_is_validator(&self, account_id: &AccountId) -> bool {
             match self {
                 Self::V1(v1) => v1.validator_to_index.contains_key(account_id),
-                Self::V2(v2) => v2.validator_to_index.contains_key(account_id),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validator_to_index.contains_key(account_id)
+                }
             }
         }
 
         pub fn get_validator_id(&self, account_id: &AccountId) -> Option<&ValidatorId> {
             match self {
                 Self::V1(v1) => v1.validator_to_index.get(account_id),
-                Self::V2(v2) => v2.validator_to_index.get(account_id),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validator_to_index.get(account_id)
+                }
             }
         }
 
@@ -896,7 +966,7 @@ pub mod block_info {
                     .validator_to_index
                     .get(account_id)
                     .map(|validator_id| v1.validators[*validator_id as usize].clone()),
-                Self::V2(v2) => v2
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2
                     .validator_to_index
                     .get(account_id)
                     .map(|validator_id| v2.validators[*validator_id as usize].clone()),
@@ -907,7 +977,9 @@ pub mod block_info {
         pub fn get_validator(&self, validator_id: u64) -> ValidatorPowerAndPledge {
             match self {
                 Self::V1(v1) => v1.validators[validator_id as usize].clone(),
-                Self::V2(v2) => v2.validators[validator_id as usize].clone(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.validators[validator_id as usize].clone()
+                }
             }
         }
 
@@ -915,7 +987,9 @@ pub mod block_info {
         pub fn account_is_fisherman(&self, account_id: &AccountId) -> bool {
             match self {
                 Self::V1(v1) => v1.fishermen_to_index.contains_key(account_id),
-                Self::V2(v2) => v2.fishermen_to_index.contains_key(account_id),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.fishermen_to_index.contains_key(account_id)
+                }
             }
         }
 
@@ -925,7 +999,7 @@ pub mod block_info {
                     .fishermen_to_index
                     .get(account_id)
                     .map(|validator_id| v1.fishermen[*validator_id as usize].clone()),
-                Self::V2(v2) => v2
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2
                     .fishermen_to_index
                     .get(account_id)
                     .map(|validator_id| v2.fishermen[*validator_id as usize].clone()),
@@ -936,7 +1010,9 @@ pub mod block_info {
         pub fn get_fisherman(&self, fisherman_id: u64) -> ValidatorPowerAndPledge {
             match self {
                 Self::V1(v1) => v1.fishermen[fisherman_id as usize].clone(),
-                Self::V2(v2) => v2.fishermen[fisherman_id as usize].clone(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => {
+                    v2.fishermen[fisherman_id as usize].clone()
+                }
             }
         }
 
@@ -944,7 +1020,7 @@ pub mod block_info {
         pub fn validators_len(&self) -> usize {
             match self {
                 Self::V1(v1) => v1.validators.len(),
-                Self::V2(v2) => v2.validators.len(),
+                Self::V2(v2) | Self::V3(BlockInfoV3 { base: v2, .. }) => v2.validators.len(),
             }
         }
 
@@ -1001,7 +1077,16 @@ pub mod block_info {
         // end customized by James Savechives
     }
 
-
+    // V2 -> V3: Add the protocol versions of the chunk producers
+    #[derive(
+        Default, BorshSerialize, BorshDeserialize, Eq, PartialEq, Clone, Debug, serde::Serialize,
+    )]
+    pub struct BlockInfoV3 {
+        pub base: BlockInfoV2,
+        /// Latest protocol version observed by the producer of each chunk in the block, by
+        /// shard.
+        pub chunk_producer_versions: Vec<Option<ProtocolVersion>>,
+    }
 }
 
 /// Information per each block.
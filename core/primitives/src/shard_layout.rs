@@ -143,6 +143,31 @@ impl ShardLayout {
         )
     }
 
+    /// Returns the simple nightshade layout, which splits the single shard of V0 in four.
+    pub fn get_simple_nightshade_layout() -> ShardLayout {
+        ShardLayout::v1(
+            vec!["aurora", "aurora-0", "kkuuue2akv_1630967379.unc"]
+                .into_iter()
+                .map(|s| s.parse().unwrap())
+                .collect(),
+            Some(vec![vec![0, 1, 2, 3]]),
+            1,
+        )
+    }
+
+    /// Returns the second simple nightshade layout, which splits the last shard of
+    /// [`Self::get_simple_nightshade_layout`] in two.
+    pub fn get_simple_nightshade_layout_v2() -> ShardLayout {
+        ShardLayout::v1(
+            vec!["aurora", "aurora-0", "kkuuue2akv_1630967379.unc", "tge-lockup.sweat"]
+                .into_iter()
+                .map(|s| s.parse().unwrap())
+                .collect(),
+            Some(vec![vec![0], vec![1], vec![2], vec![3, 4]]),
+            2,
+        )
+    }
+
     /// Given a parent shard id, return the shard uids for the shards in the current shard layout that
     /// are split from this parent shard. If this shard layout has no parent shard layout, return None
     pub fn get_children_shards_uids(&self, parent_shard_id: ShardId) -> Option<Vec<ShardUId>> {
@@ -198,6 +223,15 @@ impl ShardLayout {
         }
     }
 
+    /// The accounts on the boundaries between shards, empty for V0 layouts, which map accounts
+    /// by hash.
+    pub fn boundary_accounts(&self) -> &[AccountId] {
+        match self {
+            Self::V0(_) => &[],
+            Self::V1(v1) => &v1.boundary_accounts,
+        }
+    }
+
     pub fn shard_ids(&self) -> impl Iterator<Item = ShardId> {
         0..self.num_shards()
     }
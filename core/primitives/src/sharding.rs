@@ -5,7 +5,9 @@ use crate::transaction::SignedTransaction;
 use crate::types::validator_power::{ValidatorPower, ValidatorPowerIter, ValidatorPowerV1};
 use crate::types::{Balance, BlockHeight, Gas, MerkleHash, ShardId, StateRoot, ValidatorPledgeV1};
 use crate::validator_signer::ValidatorSigner;
-use crate::version::{ProtocolFeature, ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
+use crate::version::{
+    get_protocol_version, ProtocolFeature, ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 use unc_crypto::Signature;
 use unc_fmt::AbbrBytes;
@@ -70,6 +72,7 @@ pub struct StateSyncInfo {
 pub mod shard_chunk_header_inner;
 pub use shard_chunk_header_inner::{
     ShardChunkHeaderInner, ShardChunkHeaderInnerV1, ShardChunkHeaderInnerV2,
+    ShardChunkHeaderInnerV3,
 };
 use crate::types::validator_stake::{ValidatorPledge, ValidatorPledgeIter};
 
@@ -306,6 +309,16 @@ impl ShardChunkHeader {
         }
     }
 
+    /// Latest protocol version observed by the producer of the chunk, if the
+    /// header carries it.
+    #[inline]
+    pub fn producer_protocol_version(&self) -> Option<ProtocolVersion> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(header) => header.inner.producer_protocol_version(),
+        }
+    }
+
     #[inline]
     pub fn prev_state_root(&self) -> StateRoot {
         match self {
@@ -418,12 +431,20 @@ impl ShardChunkHeader {
     pub fn valid_for(&self, version: ProtocolVersion) -> bool {
         const BLOCK_HEADER_V3_VERSION: ProtocolVersion =
             ProtocolFeature::BlockHeaderV3.protocol_version();
+        const CHUNK_PRODUCER_UPGRADE_VOTES_VERSION: ProtocolVersion =
+            ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version();
         match &self {
             ShardChunkHeader::V1(_) => version < SHARD_CHUNK_HEADER_UPGRADE_VERSION,
             ShardChunkHeader::V2(_) => {
                 SHARD_CHUNK_HEADER_UPGRADE_VERSION <= version && version < BLOCK_HEADER_V3_VERSION
             }
-            ShardChunkHeader::V3(_) => BLOCK_HEADER_V3_VERSION <= version,
+            ShardChunkHeader::V3(header) => match header.inner {
+                ShardChunkHeaderInner::V1(_) | ShardChunkHeaderInner::V2(_) => {
+                    BLOCK_HEADER_V3_VERSION <= version
+                        && version < CHUNK_PRODUCER_UPGRADE_VOTES_VERSION
+                }
+                ShardChunkHeaderInner::V3(_) => CHUNK_PRODUCER_UPGRADE_VOTES_VERSION <= version,
+            },
         }
     }
 
@@ -1119,6 +1140,28 @@ impl EncodedShardChunk {
             );
             let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V2(header), content };
             Ok((Self::V2(chunk), merkle_paths))
+        } else if ProtocolFeature::ChunkProducerUpgradeVotes.protocol_version() <= protocol_version
+        {
+            let inner = ShardChunkHeaderInner::V3(ShardChunkHeaderInnerV3 {
+                prev_block_hash,
+                prev_state_root,
+                prev_outcome_root,
+                encoded_merkle_root,
+                encoded_length,
+                height_created: height,
+                shard_id,
+                prev_gas_used,
+                gas_limit,
+                prev_balance_burnt,
+                prev_outgoing_receipts_root,
+                tx_root,
+                prev_validator_power_proposals,
+                prev_validator_pledge_proposals,
+                producer_protocol_version: get_protocol_version(protocol_version),
+            });
+            let header = ShardChunkHeaderV3::from_inner(inner, signer);
+            let chunk = EncodedShardChunkV2 { header: ShardChunkHeader::V3(header), content };
+            Ok((Self::V2(chunk), merkle_paths))
         } else {
             let header = ShardChunkHeaderV3::new(
                 prev_block_hash,
@@ -2,13 +2,14 @@ use crate::types::validator_power::{ValidatorPower, ValidatorPowerIter, Validato
 use crate::types::{StateRoot, ValidatorPledgeV1};
 use borsh::{BorshDeserialize, BorshSerialize};
 use unc_primitives_core::hash::CryptoHash;
-use unc_primitives_core::types::{Balance, BlockHeight, Gas, ShardId};
+use unc_primitives_core::types::{Balance, BlockHeight, Gas, ProtocolVersion, ShardId};
 use crate::types::validator_stake::{ValidatorPledge, ValidatorPledgeIter};
 
 #[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
 pub enum ShardChunkHeaderInner {
     V1(ShardChunkHeaderInnerV1),
     V2(ShardChunkHeaderInnerV2),
+    V3(ShardChunkHeaderInnerV3),
 }
 
 impl ShardChunkHeaderInner {
@@ -17,6 +18,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_state_root,
             Self::V2(inner) => &inner.prev_state_root,
+            Self::V3(inner) => &inner.prev_state_root,
         }
     }
 
@@ -25,6 +27,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_block_hash,
             Self::V2(inner) => &inner.prev_block_hash,
+            Self::V3(inner) => &inner.prev_block_hash,
         }
     }
 
@@ -33,6 +36,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.gas_limit,
             Self::V2(inner) => inner.gas_limit,
+            Self::V3(inner) => inner.gas_limit,
         }
     }
 
@@ -41,6 +45,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.prev_gas_used,
             Self::V2(inner) => inner.prev_gas_used,
+            Self::V3(inner) => inner.prev_gas_used,
         }
     }
 
@@ -49,6 +54,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => ValidatorPowerIter::v1(&inner.prev_validator_power_proposals),
             Self::V2(inner) => ValidatorPowerIter::new(&inner.prev_validator_power_proposals),
+            Self::V3(inner) => ValidatorPowerIter::new(&inner.prev_validator_power_proposals),
         }
     }
 
@@ -57,6 +63,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => ValidatorPledgeIter::v1(&inner.prev_validator_pledge_proposals),
             Self::V2(inner) => ValidatorPledgeIter::new(&inner.prev_validator_pledge_proposals),
+            Self::V3(inner) => ValidatorPledgeIter::new(&inner.prev_validator_pledge_proposals),
         }
     }
 
@@ -65,6 +72,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.height_created,
             Self::V2(inner) => inner.height_created,
+            Self::V3(inner) => inner.height_created,
         }
     }
 
@@ -73,6 +81,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.shard_id,
             Self::V2(inner) => inner.shard_id,
+            Self::V3(inner) => inner.shard_id,
         }
     }
 
@@ -81,6 +90,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_outcome_root,
             Self::V2(inner) => &inner.prev_outcome_root,
+            Self::V3(inner) => &inner.prev_outcome_root,
         }
     }
 
@@ -89,6 +99,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.encoded_merkle_root,
             Self::V2(inner) => &inner.encoded_merkle_root,
+            Self::V3(inner) => &inner.encoded_merkle_root,
         }
     }
 
@@ -97,6 +108,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.encoded_length,
             Self::V2(inner) => inner.encoded_length,
+            Self::V3(inner) => inner.encoded_length,
         }
     }
 
@@ -105,6 +117,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => inner.prev_balance_burnt,
             Self::V2(inner) => inner.prev_balance_burnt,
+            Self::V3(inner) => inner.prev_balance_burnt,
         }
     }
 
@@ -113,6 +126,7 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.prev_outgoing_receipts_root,
             Self::V2(inner) => &inner.prev_outgoing_receipts_root,
+            Self::V3(inner) => &inner.prev_outgoing_receipts_root,
         }
     }
 
@@ -121,6 +135,17 @@ impl ShardChunkHeaderInner {
         match self {
             Self::V1(inner) => &inner.tx_root,
             Self::V2(inner) => &inner.tx_root,
+            Self::V3(inner) => &inner.tx_root,
+        }
+    }
+
+    /// Latest protocol version observed by the chunk producer, only known for
+    /// chunks produced from `ChunkProducerUpgradeVotes` on.
+    #[inline]
+    pub fn producer_protocol_version(&self) -> Option<ProtocolVersion> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(inner) => Some(inner.producer_protocol_version),
         }
     }
 }
@@ -181,3 +206,35 @@ pub struct ShardChunkHeaderInnerV2 {
     /// Validator proposals from the previous chunk.
     pub prev_validator_pledge_proposals: Vec<ValidatorPledge>,
 }
+
+// V2 -> V3: Add the latest protocol version observed by the chunk producer
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ShardChunkHeaderInnerV3 {
+    /// Previous block hash.
+    pub prev_block_hash: CryptoHash,
+    pub prev_state_root: StateRoot,
+    /// Root of the outcomes from execution transactions and results of the previous chunk.
+    pub prev_outcome_root: CryptoHash,
+    pub encoded_merkle_root: CryptoHash,
+    pub encoded_length: u64,
+    pub height_created: BlockHeight,
+    /// Shard index.
+    pub shard_id: ShardId,
+    /// Gas used in the previous chunk.
+    pub prev_gas_used: Gas,
+    /// Gas limit voted by validators.
+    pub gas_limit: Gas,
+    /// Total balance burnt in the previous chunk.
+    pub prev_balance_burnt: Balance,
+    /// Previous chunk's outgoing receipts merkle root.
+    pub prev_outgoing_receipts_root: CryptoHash,
+    /// Tx merkle root.
+    pub tx_root: CryptoHash,
+    /// Validator proposals from the previous chunk.
+    pub prev_validator_power_proposals: Vec<ValidatorPower>,
+    /// Validator proposals from the previous chunk.
+    pub prev_validator_pledge_proposals: Vec<ValidatorPledge>,
+    /// Latest protocol version observed by the chunk producer, counted in the
+    /// upgrade vote.
+    pub producer_protocol_version: ProtocolVersion,
+}
@@ -1,11 +1,13 @@
 use crate::account::{AccessKey, Account};
+use crate::action::RegisterRsa2048KeysAction;
 use crate::hash::{hash, CryptoHash};
 use crate::receipt::{Receipt, ReceivedData};
 use crate::trie_key::trie_key_parsers::{
     parse_account_id_from_access_key_key, parse_account_id_from_account_key,
     parse_account_id_from_contract_code_key, parse_account_id_from_contract_data_key,
     parse_account_id_from_received_data_key, parse_data_id_from_received_data_key,
-    parse_data_key_from_contract_data_key, parse_public_key_from_access_key_key,
+    parse_account_id_from_rsa_key_key, parse_data_key_from_contract_data_key,
+    parse_public_key_from_access_key_key, parse_public_key_from_rsa_key_key,
 };
 use crate::trie_key::{col, TrieKey};
 use crate::types::{AccountId, StoreKey, StoreValue};
@@ -43,6 +45,12 @@ pub enum StateRecord {
     /// Delayed Receipt.
     /// The receipt was delayed because the shard was overwhelmed.
     DelayedReceipt(Box<Receipt>),
+    /// RSA2048 keys of a chip registered by the account, as listed by `view_chip_list`.
+    Rsa2048Keys {
+        account_id: AccountId,
+        public_key: PublicKey,
+        rsa_key: RegisterRsa2048KeysAction,
+    },
 }
 
 impl StateRecord {
@@ -103,6 +111,12 @@ impl StateRecord {
                 let receipt = Receipt::try_from_slice(&value)?;
                 Some(StateRecord::DelayedReceipt(Box::new(receipt)))
             }
+            col::RSA2048_KEY => {
+                let rsa_key = RegisterRsa2048KeysAction::try_from_slice(&value)?;
+                let account_id = parse_account_id_from_rsa_key_key(&key)?;
+                let public_key = parse_public_key_from_rsa_key_key(&key, &account_id)?;
+                Some(StateRecord::Rsa2048Keys { account_id, public_key, rsa_key })
+            }
             _ => {
                 println!("key[0]: {} is unreachable", key[0]);
                 None
@@ -119,6 +133,7 @@ impl StateRecord {
             StateRecord::PostponedReceipt { .. } => "PostponedReceipt",
             StateRecord::ReceivedData { .. } => "ReceivedData",
             StateRecord::DelayedReceipt { .. } => "DelayedReceipt",
+            StateRecord::Rsa2048Keys { .. } => "Rsa2048Keys",
         }
         .to_string()
     }
@@ -152,6 +167,9 @@ impl Display for StateRecord {
             ),
             StateRecord::PostponedReceipt(receipt) => write!(f, "Postponed receipt {:?}", receipt),
             StateRecord::DelayedReceipt(receipt) => write!(f, "Delayed receipt {:?}", receipt),
+            StateRecord::Rsa2048Keys { account_id, public_key, rsa_key } => {
+                write!(f, "RSA2048 keys {:?},{:?}: {:?}", account_id, public_key, rsa_key)
+            }
         }
     }
 }
@@ -177,7 +195,8 @@ pub fn state_record_to_account_id(state_record: &StateRecord) -> &AccountId {
         | StateRecord::AccessKey { account_id, .. }
         | StateRecord::Contract { account_id, .. }
         | StateRecord::ReceivedData { account_id, .. }
-        | StateRecord::Data { account_id, .. } => account_id,
+        | StateRecord::Data { account_id, .. }
+        | StateRecord::Rsa2048Keys { account_id, .. } => account_id,
         StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
             &receipt.receiver_id
         }
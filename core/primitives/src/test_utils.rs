@@ -281,6 +281,16 @@ impl BlockHeader {
                 panic!("old header should not appear in tests")
             }
             BlockHeader::BlockHeaderV4(header) => Arc::make_mut(header),
+            BlockHeader::BlockHeaderV5(_) => {
+                panic!("use get_mut_v5 for headers produced with chunk producer versions")
+            }
+        }
+    }
+
+    pub fn get_mut_v5(&mut self) -> &mut crate::block_header::BlockHeaderV5 {
+        match self {
+            BlockHeader::BlockHeaderV5(header) => Arc::make_mut(header),
+            _ => panic!("only headers produced with chunk producer versions are V5"),
         }
     }
 
@@ -302,6 +312,10 @@ impl BlockHeader {
                 let header = Arc::make_mut(header);
                 header.inner_rest.latest_protocol_version = latest_protocol_version;
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                let header = Arc::make_mut(header);
+                header.inner_rest.latest_protocol_version = latest_protocol_version;
+            }
         }
     }
 
@@ -332,6 +346,11 @@ impl BlockHeader {
                 header.hash = hash;
                 header.signature = signature;
             }
+            BlockHeader::BlockHeaderV5(header) => {
+                let header = Arc::make_mut(header);
+                header.hash = hash;
+                header.signature = signature;
+            }
         }
     }
 }
@@ -773,12 +773,12 @@ pub mod validator_stake {
     use borsh::{BorshDeserialize, BorshSerialize};
     use unc_crypto::PublicKey;
     use unc_primitives_core::types::{AccountId, Balance};
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
 
     pub use super::ValidatorPledgeV1;
 
     /// Stores validator and its pledge.
-    #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     #[serde(tag = "validator_validator_struct_version")]
     pub enum ValidatorPledge {
         V1(ValidatorPledgeV1),
@@ -930,12 +930,12 @@ pub mod validator_power {
     use borsh::{BorshDeserialize, BorshSerialize};
     use unc_crypto::PublicKey;
     use unc_primitives_core::types::{AccountId, Power};
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
 
     pub use super::ValidatorPowerV1;
 
     /// Stores validator and its power.
-    #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     #[serde(tag = "validator_power_struct_version")]
     pub enum ValidatorPower {
         V1(ValidatorPowerV1),
@@ -1094,7 +1094,16 @@ pub struct ValidatorPowerAndPledgeV1 {
 }
 
 /// Stores validator and its pledge.
-#[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+)]
 pub struct ValidatorPledgeV1 {
     /// Account that has pledge.
     pub account_id: AccountId,
@@ -1105,7 +1114,16 @@ pub struct ValidatorPledgeV1 {
 }
 
 /// Stores validator and its power.
-#[derive(BorshSerialize, BorshDeserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+)]
 pub struct ValidatorPowerV1 {
     /// Account that has power.
     pub account_id: AccountId,
@@ -1314,7 +1332,17 @@ impl From<Finality> for BlockReference {
     }
 }
 
-#[derive(Default, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(
+    Default,
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+)]
 pub struct ValidatorStats {
     pub produced: NumBlocks,
     pub expected: NumBlocks,
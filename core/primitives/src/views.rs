@@ -11,6 +11,7 @@ use crate::block_header::{
     BlockHeaderV1, BlockHeaderV2, BlockHeaderV3,
 };
 use crate::block_header::{BlockHeaderInnerRestV4, BlockHeaderV4};
+use crate::block_header::{BlockHeaderInnerRestV5, BlockHeaderV5};
 use crate::challenge::{Challenge, ChallengesResult};
 use crate::checked_feature;
 use crate::errors::TxExecutionError;
@@ -21,7 +22,7 @@ use crate::receipt::{ActionReceipt, DataReceipt, DataReceiver, Receipt, ReceiptE
 use crate::serialize::dec_format;
 use crate::sharding::{
     ChunkHash, ShardChunk, ShardChunkHeader, ShardChunkHeaderInner, ShardChunkHeaderInnerV2,
-    ShardChunkHeaderV3,
+    ShardChunkHeaderInnerV3, ShardChunkHeaderV3,
 };
 use crate::transaction::{
     Action, AddKeyAction, CreateAccountAction, DeleteAccountAction, DeleteKeyAction,
@@ -104,6 +105,30 @@ pub struct ViewApplyState {
     pub cache: Option<Box<dyn CompiledContractCache>>,
 }
 
+impl ViewApplyState {
+    /// State for view calls against the state as of the block `header`. The block height,
+    /// hashes, epoch and timestamp are all taken from the header, so they can't disagree with
+    /// each other. `epoch_height` and `current_protocol_version` aren't in the header and have
+    /// to be those of the header's epoch, as given by the epoch manager.
+    pub fn for_block(
+        header: &BlockHeader,
+        epoch_height: EpochHeight,
+        current_protocol_version: ProtocolVersion,
+        cache: Option<Box<dyn CompiledContractCache>>,
+    ) -> Self {
+        Self {
+            block_height: header.height(),
+            prev_block_hash: *header.prev_hash(),
+            block_hash: *header.hash(),
+            epoch_id: header.epoch_id().clone(),
+            epoch_height,
+            block_timestamp: header.raw_timestamp(),
+            current_protocol_version,
+            cache,
+        }
+    }
+}
+
 impl From<&Account> for AccountView {
     fn from(account: &Account) -> Self {
         AccountView {
@@ -348,6 +373,10 @@ pub enum QueryRequest {
         prefix: StoreKey,
         #[serde(default, skip_serializing_if = "is_false")]
         include_proof: bool,
+        /// Replaces the node's limit on the size of the returned state. Only accepted by
+        /// nodes with debug RPC enabled, and never past the node's configured hard limit.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        state_size_limit_override: Option<u64>,
     },
     ViewAccessKey {
         account_id: AccountId,
@@ -820,6 +849,10 @@ pub struct BlockHeaderView {
     pub approvals: Vec<Option<Box<Signature>>>,
     pub signature: Signature,
     pub latest_protocol_version: ProtocolVersion,
+    /// Latest protocol version of the producer of each new chunk included in the block, for
+    /// blocks produced from `ChunkProducerUpgradeVotes` on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_producer_versions: Option<Vec<Option<ProtocolVersion>>>,
 }
 
 impl From<BlockHeader> for BlockHeaderView {
@@ -863,6 +896,7 @@ impl From<BlockHeader> for BlockHeaderView {
             approvals: header.approvals().to_vec(),
             signature: header.signature().clone(),
             latest_protocol_version: header.latest_protocol_version(),
+            chunk_producer_versions: header.chunk_producer_versions().map(<[_]>::to_vec),
         }
     }
 }
@@ -991,6 +1025,45 @@ impl From<BlockHeaderView> for BlockHeader {
             };
             header.init();
             BlockHeader::BlockHeaderV3(Arc::new(header))
+        } else if let Some(chunk_producer_versions) = view.chunk_producer_versions {
+            let mut header = BlockHeaderV5 {
+                prev_hash: view.prev_hash,
+                inner_lite,
+                inner_rest: BlockHeaderInnerRestV5 {
+                    block_body_hash: view.block_body_hash.unwrap_or_default(),
+                    prev_chunk_outgoing_receipts_root: view.chunk_receipts_root,
+                    chunk_headers_root: view.chunk_headers_root,
+                    chunk_tx_root: view.chunk_tx_root,
+                    challenges_root: view.challenges_root,
+                    random_value: view.random_value,
+                    prev_validator_power_proposals: view
+                        .validator_power_proposals
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    prev_validator_pledge_proposals: view
+                        .validator_pledge_proposals
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    chunk_mask: view.chunk_mask,
+                    chunk_producer_versions,
+                    next_gas_price: view.gas_price,
+                    block_ordinal: view.block_ordinal.unwrap_or(0),
+                    total_supply: view.total_supply,
+                    challenges_result: view.challenges_result,
+                    last_final_block: view.last_final_block,
+                    last_ds_final_block: view.last_ds_final_block,
+                    prev_height: view.prev_height.unwrap_or_default(),
+                    epoch_sync_data_hash: view.epoch_sync_data_hash,
+                    approvals: view.approvals.clone(),
+                    latest_protocol_version: view.latest_protocol_version,
+                },
+                signature: view.signature,
+                hash: CryptoHash::default(),
+            };
+            header.init();
+            BlockHeader::BlockHeaderV5(Arc::new(header))
         } else {
             let mut header = BlockHeaderV4 {
                 prev_hash: view.prev_hash,
@@ -1064,6 +1137,7 @@ impl From<BlockHeader> for BlockHeaderInnerLiteView {
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite,
             BlockHeader::BlockHeaderV4(header) => &header.inner_lite,
+            BlockHeader::BlockHeaderV5(header) => &header.inner_lite,
         };
         BlockHeaderInnerLiteView {
             height: inner_lite.height,
@@ -1120,6 +1194,10 @@ pub struct ChunkHeaderView {
     pub validator_power_proposals: Vec<ValidatorPowerView>,
     pub validator_pledge_proposals: Vec<ValidatorPledgeView>,
     pub signature: Signature,
+    /// Latest protocol version observed by the chunk producer, for chunks
+    /// produced from `ChunkProducerUpgradeVotes` on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub producer_protocol_version: Option<ProtocolVersion>,
 }
 
 impl From<ShardChunkHeader> for ChunkHeaderView {
@@ -1148,14 +1226,19 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
             validator_power_proposals: inner.prev_validator_power_proposals().map(Into::into).collect(),
             validator_pledge_proposals: inner.prev_validator_pledge_proposals().map(Into::into).collect(),
             signature,
+            producer_protocol_version: inner.producer_protocol_version(),
         }
     }
 }
 
 impl From<ChunkHeaderView> for ShardChunkHeader {
     fn from(view: ChunkHeaderView) -> Self {
-        let mut header = ShardChunkHeaderV3 {
-            inner: ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
+        let prev_validator_power_proposals =
+            view.validator_power_proposals.into_iter().map(Into::into).collect();
+        let prev_validator_pledge_proposals =
+            view.validator_pledge_proposals.into_iter().map(Into::into).collect();
+        let inner = match view.producer_protocol_version {
+            None => ShardChunkHeaderInner::V2(ShardChunkHeaderInnerV2 {
                 prev_block_hash: view.prev_block_hash,
                 prev_state_root: view.prev_state_root,
                 prev_outcome_root: view.outcome_root,
@@ -1168,17 +1251,31 @@ impl From<ChunkHeaderView> for ShardChunkHeader {
                 prev_balance_burnt: view.balance_burnt,
                 prev_outgoing_receipts_root: view.outgoing_receipts_root,
                 tx_root: view.tx_root,
-                prev_validator_power_proposals: view
-                    .validator_power_proposals
-                    .into_iter()
-                    .map(Into::into)
-                    .collect(),
-                prev_validator_pledge_proposals: view
-                    .validator_pledge_proposals
-                    .into_iter()
-                    .map(Into::into)
-                    .collect(),
+                prev_validator_power_proposals,
+                prev_validator_pledge_proposals,
             }),
+            Some(producer_protocol_version) => {
+                ShardChunkHeaderInner::V3(ShardChunkHeaderInnerV3 {
+                    prev_block_hash: view.prev_block_hash,
+                    prev_state_root: view.prev_state_root,
+                    prev_outcome_root: view.outcome_root,
+                    encoded_merkle_root: view.encoded_merkle_root,
+                    encoded_length: view.encoded_length,
+                    height_created: view.height_created,
+                    shard_id: view.shard_id,
+                    prev_gas_used: view.gas_used,
+                    gas_limit: view.gas_limit,
+                    prev_balance_burnt: view.balance_burnt,
+                    prev_outgoing_receipts_root: view.outgoing_receipts_root,
+                    tx_root: view.tx_root,
+                    prev_validator_power_proposals,
+                    prev_validator_pledge_proposals,
+                    producer_protocol_version,
+                })
+            }
+        };
+        let mut header = ShardChunkHeaderV3 {
+            inner,
             height_included: view.height_included,
             signature: view.signature,
             hash: ChunkHash::default(),
@@ -1,7 +1,8 @@
 use crate::flat::FlatStateChanges;
 use crate::{
     get_account, get_received_data, set, set_access_key, set_account, set_code,
-    set_delayed_receipt, set_postponed_receipt, set_received_data, ShardTries, TrieUpdate,
+    set_delayed_receipt, set_postponed_receipt, set_received_data, set_rsa2048_keys, ShardTries,
+    TrieUpdate,
 };
 
 use unc_chain_configs::Genesis;
@@ -59,6 +60,12 @@ impl<'a> StorageComputer<'a> {
             StateRecord::PostponedReceipt(_) => None,
             StateRecord::ReceivedData { .. } => None,
             StateRecord::DelayedReceipt(_) => None,
+            // the same as what registering the chip adds to the account's storage usage
+            StateRecord::Rsa2048Keys { account_id, rsa_key, .. } => {
+                let storage_usage = self.config.num_extra_bytes_record
+                    + borsh::object_length(rsa_key).unwrap() as u64;
+                Some((account_id.clone(), storage_usage))
+            }
         };
         if let Some((account_id, storage_usage)) = account_and_storage {
             *self.result.entry(account_id).or_default() += storage_usage;
@@ -243,6 +250,16 @@ impl GenesisStateApplier {
                 StateRecord::DelayedReceipt(receipt) => storage.modify(|state_update| {
                     set_delayed_receipt(state_update, delayed_receipts_indices, &*receipt);
                 }),
+                StateRecord::Rsa2048Keys { account_id, public_key, rsa_key } => {
+                    storage.modify(|state_update| {
+                        set_rsa2048_keys(
+                            state_update,
+                            account_id.clone(),
+                            public_key.clone(),
+                            rsa_key,
+                        );
+                    })
+                }
             }
         });
 
@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
 use unc_primitives::hash::CryptoHash;
 
 use crate::trie::nibble_slice::NibbleSlice;
@@ -41,6 +44,21 @@ impl Crumb {
     }
 }
 
+/// What a [`TrieIterator`] went through, recorded once enabled with
+/// [`TrieIterator::record_diagnostics`]. Meant for finding out why iterating over some prefix
+/// is slow, e.g. because it lands on a dense part of the trie.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrieIteratorDiagnostics {
+    /// Number of nodes on the path from the root to where the last seek stopped.
+    pub seek_depth: u64,
+    /// Number of trie nodes retrieved at each depth, the root being at depth 0, while seeking
+    /// and iterating.
+    pub nodes_per_level: Vec<u64>,
+    /// Number of values the last seek passed over before reaching the key sought: the values
+    /// of branches on the way whose keys are shorter, and that of a leaf that doesn't match.
+    pub values_skipped: u64,
+}
+
 /// Trie iteration is done using a stack based approach.
 /// There are two stacks that we track while iterating: the trail and the key_nibbles.
 /// The trail is a vector of trie nodes on the path from root node to the node that is
@@ -57,6 +75,13 @@ pub struct TrieIterator<'a> {
     /// If not `None`, a list of all nodes that the iterator has visited.
     visited_nodes: Option<Vec<std::sync::Arc<[u8]>>>,
 
+    /// Number of trie nodes the iterator has retrieved so far.
+    nodes_visited: u64,
+    /// Total size of the trie nodes and values the iterator has retrieved so far.
+    bytes_read: u64,
+    /// If not `None`, what the iterator went through since that was enabled.
+    diagnostics: Option<TrieIteratorDiagnostics>,
+
     /// Prune condition is an optional closure that given the key nibbles
     /// decides if the given trie node should be pruned.
     ///
@@ -68,6 +93,9 @@ pub struct TrieIterator<'a> {
     /// the key also should be pruned. Otherwise it would be possible to bypass
     /// the pruning by seeking inside of the pruned sub-tree.
     prune_condition: Option<Box<dyn Fn(&Vec<u8>) -> bool>>,
+
+    /// Value returned by the last call to [`Self::next_borrowed`].
+    current_value: Option<Arc<[u8]>>,
 }
 
 /// The TrieTiem is a tuple of (key, value) of the node.
@@ -94,7 +122,11 @@ impl<'a> TrieIterator<'a> {
             trail: Vec::with_capacity(8),
             key_nibbles: Vec::with_capacity(64),
             visited_nodes: None,
+            nodes_visited: 0,
+            bytes_read: 0,
+            diagnostics: None,
             prune_condition,
+            current_value: None,
         };
         r.descend_into_node(&trie.root)?;
         Ok(r)
@@ -123,6 +155,32 @@ impl<'a> TrieIterator<'a> {
         self.visited_nodes.unwrap_or(Vec::new())
     }
 
+    /// Returns the number of trie nodes the iterator has retrieved so far,
+    /// including the ones retrieved while seeking.
+    pub fn nodes_visited(&self) -> u64 {
+        self.nodes_visited
+    }
+
+    /// Returns the total size in bytes of the trie nodes and values the
+    /// iterator has retrieved so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Configures whether the iterator should record where in the trie it
+    /// goes, at the cost of a few counters per node.
+    ///
+    /// Use [`Self::diagnostics`] to retrieve them.
+    pub fn record_diagnostics(&mut self, record: bool) {
+        self.diagnostics = record.then(TrieIteratorDiagnostics::default);
+    }
+
+    /// Returns what the iterator went through since
+    /// [`Self::record_diagnostics`] was enabled, or `None` if it wasn't.
+    pub fn diagnostics(&self) -> Option<&TrieIteratorDiagnostics> {
+        self.diagnostics.as_ref()
+    }
+
     /// Returns the hash of the last node
     pub(crate) fn seek_nibble_slice(
         &mut self,
@@ -147,6 +205,7 @@ impl<'a> TrieIterator<'a> {
 
         let mut hash = self.trie.root;
         let mut prev_prefix_boundary = &mut false;
+        let mut values_skipped = 0;
         loop {
             *prev_prefix_boundary = is_prefix_seek;
             self.descend_into_node(&hash)?;
@@ -159,13 +218,17 @@ impl<'a> TrieIterator<'a> {
                     if !check_ext_key(&key, &existing_key) {
                         self.key_nibbles.extend(existing_key.iter());
                         *status = CrumbStatus::Exiting;
+                        values_skipped += 1;
                     }
                     break;
                 }
-                TrieNode::Branch(children, _) => {
+                TrieNode::Branch(children, value) => {
                     if key.is_empty() {
                         break;
                     }
+                    if value.is_some() {
+                        values_skipped += 1;
+                    }
                     let idx = key.at(0);
                     self.key_nibbles.push(idx);
                     *status = CrumbStatus::AtChild(idx);
@@ -194,6 +257,10 @@ impl<'a> TrieIterator<'a> {
                 }
             }
         }
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.seek_depth = self.trail.len() as u64;
+            diagnostics.values_skipped = values_skipped;
+        }
         Ok(hash)
     }
 
@@ -205,6 +272,17 @@ impl<'a> TrieIterator<'a> {
     /// list.
     fn descend_into_node(&mut self, hash: &CryptoHash) -> Result<(), StorageError> {
         let (bytes, node) = self.trie.retrieve_node(hash)?;
+        if let Some(bytes) = &bytes {
+            self.nodes_visited += 1;
+            self.bytes_read += bytes.len() as u64;
+            if let Some(diagnostics) = &mut self.diagnostics {
+                let level = self.trail.len();
+                if diagnostics.nodes_per_level.len() <= level {
+                    diagnostics.nodes_per_level.resize(level + 1, 0);
+                }
+                diagnostics.nodes_per_level[level] += 1;
+            }
+        }
         if let Some(ref mut visited) = self.visited_nodes {
             visited.push(bytes.ok_or_else(|| {
                 StorageError::MissingTrieValue(MissingTrieValueContext::TrieIterator, *hash)
@@ -391,10 +469,28 @@ enum IterStep {
     Value(CryptoHash),
 }
 
-impl<'a> Iterator for TrieIterator<'a> {
-    type Item = Result<TrieItem, StorageError>;
+impl<'a> TrieIterator<'a> {
+    /// Like [`Iterator::next`], but the value is borrowed from the trie storage
+    /// rather than copied when the storage keeps it in memory.  The borrow lasts
+    /// until the iterator is advanced again, so callers that only serialize the
+    /// value can do so without allocating a copy of it.
+    pub fn next_borrowed(&mut self) -> Option<Result<(Vec<u8>, Cow<'_, [u8]>), StorageError>> {
+        let hash = match self.next_value_hash()? {
+            Ok(hash) => hash,
+            Err(err) => return Some(Err(err)),
+        };
+        let value = match self.trie.retrieve_value_shared(&hash) {
+            Ok(value) => value,
+            Err(err) => return Some(Err(err)),
+        };
+        self.bytes_read += value.len() as u64;
+        let key = self.key();
+        let value = self.current_value.insert(value);
+        Some(Ok((key, Cow::Borrowed(&value[..]))))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Advances the iterator to the next value and returns its hash.
+    fn next_value_hash(&mut self) -> Option<Result<CryptoHash, StorageError>> {
         loop {
             let iter_step = self.iter_step()?;
 
@@ -414,16 +510,27 @@ impl<'a> Iterator for TrieIterator<'a> {
                     Ok(_) => (),
                     Err(err) => return Some(Err(err)),
                 },
-                (IterStep::Value(hash), true) => {
-                    return Some(
-                        self.trie.retrieve_value(&hash).map(|value| (self.key(), value.to_vec())),
-                    )
-                }
+                (IterStep::Value(hash), true) => return Some(Ok(hash)),
             }
         }
     }
 }
 
+impl<'a> Iterator for TrieIterator<'a> {
+    type Item = Result<TrieItem, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hash = match self.next_value_hash()? {
+            Ok(hash) => hash,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.trie.retrieve_value(&hash).map(|value| {
+            self.bytes_read += value.len() as u64;
+            (self.key(), value)
+        }))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeMap;
@@ -485,6 +592,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_iterator_next_borrowed() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (_, map, trie) = gen_random_trie(&mut rng);
+            let mut iter = trie.iter().unwrap();
+            let mut result = vec![];
+            while let Some(item) = iter.next_borrowed() {
+                let (key, value) = item.unwrap();
+                result.push((key, value.into_owned()));
+            }
+            let expected: Vec<_> = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            assert_eq!(result, expected);
+            let mut owned_iter = trie.iter().unwrap();
+            owned_iter.by_ref().for_each(drop);
+            assert_eq!(iter.bytes_read(), owned_iter.bytes_read());
+        }
+    }
+
     #[test]
     fn test_iterator_with_prune_condition_base() {
         let mut rng = rand::thread_rng();
@@ -648,6 +774,78 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn test_iterator_stats() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (_, map, trie) = gen_random_trie(&mut rng);
+            let mut iterator = trie.iter().unwrap();
+            let num_items = iterator.by_ref().map(Result::unwrap).count() as u64;
+            assert_eq!(num_items, map.len() as u64);
+            let value_bytes: u64 = map.values().map(|v| v.len() as u64).sum();
+            if !map.is_empty() {
+                // every value is stored in a separate leaf or branch node
+                assert!(iterator.nodes_visited() >= num_items);
+                assert!(iterator.bytes_read() > value_bytes);
+            }
+
+            let mut again = trie.iter().unwrap();
+            again.by_ref().for_each(drop);
+            assert_eq!(again.nodes_visited(), iterator.nodes_visited());
+            assert_eq!(again.bytes_read(), iterator.bytes_read());
+        }
+    }
+
+    #[test]
+    fn test_iterator_diagnostics() {
+        // "a" and "ab" are prefixes of the other keys, so they're stored in branches
+        let keys: [&[u8]; 5] = [b"a", b"ab", b"abc", b"abd", b"b"];
+        let trie_changes = keys.iter().map(|k| (k.to_vec(), value())).collect();
+        let tries = TestTriesBuilder::new().build();
+        let state_root =
+            test_populate_trie(&tries, &Trie::EMPTY_ROOT, ShardUId::single_shard(), trie_changes);
+        let trie = tries.get_trie_for_shard(ShardUId::single_shard(), state_root);
+        let seek = |prefix: &[u8]| {
+            let mut iter = trie.iter().unwrap();
+            iter.record_diagnostics(true);
+            iter.seek_prefix(prefix).unwrap();
+            let num_values = iter.by_ref().map(Result::unwrap).count();
+            (num_values, iter.diagnostics().unwrap().clone())
+        };
+
+        let (num_values, all) = seek(b"");
+        assert_eq!(num_values, 5);
+        assert_eq!(all.seek_depth, 1);
+        assert_eq!(all.values_skipped, 0);
+        assert_eq!(all.nodes_per_level[0], 1);
+        assert!(all.nodes_per_level.iter().all(|n| *n > 0), "{:?}", all);
+
+        let (num_values, a) = seek(b"a");
+        assert_eq!(num_values, 4);
+        assert_eq!(a.values_skipped, 0);
+        let (num_values, abc) = seek(b"abc");
+        assert_eq!(num_values, 1);
+        // passing over the values of "a" and "ab" on the way down
+        assert_eq!(abc.values_skipped, 2);
+        assert!(abc.seek_depth > a.seek_depth && a.seek_depth > all.seek_depth, "{abc:?} {a:?}");
+        // a seek goes down a single path, and iterating goes deeper only
+        assert!(abc.nodes_per_level.len() as u64 >= abc.seek_depth, "{:?}", abc);
+        assert!(abc.nodes_per_level[..abc.seek_depth as usize].iter().all(|n| *n == 1));
+        assert!(
+            abc.nodes_per_level.iter().sum::<u64>() < all.nodes_per_level.iter().sum::<u64>()
+        );
+
+        // "ac" ends at a branch without that child
+        let (num_values, ac) = seek(b"ac");
+        assert_eq!(num_values, 0);
+        assert_eq!(ac.values_skipped, 1);
+
+        // no diagnostics unless asked for
+        let mut iter = trie.iter().unwrap();
+        iter.seek_prefix(b"a").unwrap();
+        assert!(iter.diagnostics().is_none());
+    }
+
     #[test]
     fn test_has_value() {
         let mut rng = rand::thread_rng();
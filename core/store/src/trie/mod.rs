@@ -1356,10 +1356,16 @@ impl Trie {
     /// Returns the raw bytes corresponding to a ValueRef that came from a node with
     /// value (either Leaf or BranchWithValue).
     pub fn retrieve_value(&self, hash: &CryptoHash) -> Result<Vec<u8>, StorageError> {
-        let bytes = self.internal_retrieve_trie_node(hash, true)?;
+        let bytes = self.retrieve_value_shared(hash)?;
         Ok(bytes.to_vec())
     }
 
+    /// Like [`Self::retrieve_value`], but returns the bytes shared with the
+    /// storage (and its caches) instead of a copy of them.
+    pub fn retrieve_value_shared(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        self.internal_retrieve_trie_node(hash, true)
+    }
+
     /// Retrieves an `OptimizedValueRef`` for the given key. See `OptimizedValueRef`.
     ///
     /// `mode`: whether we will try to perform the lookup through flat storage or trie.
@@ -272,6 +272,14 @@ pub struct Config {
     pub view_client_threads: usize,
     pub view_client_throttle_period: Duration,
     pub trie_viewer_state_size_limit: Option<u64>,
+    /// Treat contract code missing from the state as empty in view queries instead of
+    /// failing them. Only meant for nodes knowingly running with pruned stores.
+    #[serde(skip_serializing_if = "is_false")]
+    pub trie_viewer_allow_missing_code: bool,
+    /// Largest state size limit a view state query may ask for with
+    /// `state_size_limit_override`. Such queries are rejected if this isn't set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trie_viewer_hard_state_size_limit: Option<u64>,
     /// If set, overrides value in genesis configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_gas_burnt_view: Option<Gas>,
@@ -336,6 +344,8 @@ impl Default for Config {
             view_client_threads: default_view_client_threads(),
             view_client_throttle_period: default_view_client_throttle_period(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
+            trie_viewer_allow_missing_code: false,
+            trie_viewer_hard_state_size_limit: None,
             max_gas_burnt_view: None,
             store: unc_store::StoreConfig::default(),
             cold_store: None,
@@ -632,6 +642,8 @@ impl UncConfig {
                 view_client_threads: config.view_client_threads,
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
+                trie_viewer_allow_missing_code: config.trie_viewer_allow_missing_code,
+                trie_viewer_hard_state_size_limit: config.trie_viewer_hard_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
                 enable_statistics_export: config.store.enable_statistics_export,
                 client_background_migration_threads: config.store.background_migration_threads,
@@ -1,4 +1,4 @@
-use node_runtime::state_viewer::errors::ViewChipError;
+use node_runtime::state_viewer::errors::{CallFunctionError, ViewChipError};
 use unc_chain::unc_chain_primitives::error::QueryError;
 
 #[easy_ext::ext(FromStateViewerErrors)]
@@ -21,6 +21,14 @@ impl QueryError {
             node_runtime::state_viewer::errors::CallFunctionError::VMError { error_message } => {
                 Self::ContractExecutionError { error_message, block_height, block_hash }
             }
+            error @ node_runtime::state_viewer::errors::CallFunctionError::ContractCodeMissing {
+                ..
+            } => Self::InternalError { error_message: error.to_string(), block_height, block_hash },
+            error @ (CallFunctionError::ProtocolVersionOverrideNotAllowed { .. }
+            | CallFunctionError::Busy { .. }
+            | CallFunctionError::InconsistentViewState { .. }) => {
+                Self::InternalError { error_message: error.to_string(), block_height, block_hash }
+            }
         }
     }
 
@@ -60,6 +68,9 @@ impl QueryError {
             node_runtime::state_viewer::errors::ViewContractCodeError::NoContractCode {
                 contract_account_id,
             } => Self::NoContractCode { contract_account_id, block_height, block_hash },
+            error @ node_runtime::state_viewer::errors::ViewContractCodeError::ContractCodeMissing {
+                ..
+            } => Self::InternalError { error_message: error.to_string(), block_height, block_hash },
         }
     }
 
@@ -81,6 +92,14 @@ impl QueryError {
             node_runtime::state_viewer::errors::ViewStateError::AccountStateTooLarge {
                 requested_account_id,
             } => Self::TooLargeContractState { requested_account_id, block_height, block_hash },
+            error @ (node_runtime::state_viewer::errors::ViewStateError::ContractCodeMissing {
+                ..
+            }
+            | node_runtime::state_viewer::errors::ViewStateError::StateSizeLimitOverrideNotAllowed {
+                ..
+            }) => {
+                Self::InternalError { error_message: error.to_string(), block_height, block_hash }
+            }
         }
     }
 
@@ -118,6 +137,9 @@ impl QueryError {
                 block_height,
                 block_hash,
             },
+            ViewChipError::ChipListTooLarge { requested_account_id, .. } => {
+                Self::TooLargeContractState { requested_account_id, block_height, block_hash }
+            }
             ViewChipError::InternalError { error_message } => Self::InternalError {
                 error_message,
                 block_height,
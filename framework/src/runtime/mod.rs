@@ -103,6 +103,8 @@ impl NightshadeRuntime {
             epoch_manager,
             config.client_config.trie_viewer_state_size_limit,
             config.client_config.max_gas_burnt_view,
+            config.client_config.trie_viewer_allow_missing_code,
+            config.client_config.trie_viewer_hard_state_size_limit,
             None,
             config.config.gc.gc_num_epochs_to_keep(),
             TrieConfig::from_store_config(&config.config.store),
@@ -116,6 +118,8 @@ impl NightshadeRuntime {
         epoch_manager: Arc<EpochManagerHandle>,
         trie_viewer_state_size_limit: Option<u64>,
         max_gas_burnt_view: Option<Gas>,
+        trie_viewer_allow_missing_code: bool,
+        trie_viewer_hard_state_size_limit: Option<u64>,
         runtime_config_store: Option<RuntimeConfigStore>,
         gc_num_epochs_to_keep: u64,
         trie_config: TrieConfig,
@@ -127,7 +131,9 @@ impl NightshadeRuntime {
         };
 
         let runtime = Runtime::new();
-        let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view);
+        let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view)
+            .with_allow_missing_code(trie_viewer_allow_missing_code)
+            .with_hard_state_size_limit(trie_viewer_hard_state_size_limit);
         let flat_storage_manager = FlatStorageManager::new(store.clone());
         let shard_uids: Vec<_> = genesis_config.shard_layout.shard_uids().collect();
         let tries = ShardTries::new(
@@ -174,6 +180,8 @@ impl NightshadeRuntime {
             epoch_manager,
             None,
             None,
+            false,
+            None,
             Some(runtime_config_store),
             DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
             Default::default(),
@@ -201,6 +209,8 @@ impl NightshadeRuntime {
             epoch_manager,
             None,
             None,
+            false,
+            None,
             None,
             DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
             trie_config,
@@ -986,7 +996,12 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
-            QueryRequest::ViewState { account_id, prefix, include_proof } => {
+            QueryRequest::ViewState {
+                account_id,
+                prefix,
+                include_proof,
+                state_size_limit_override,
+            } => {
                 let view_state_result = self
                     .view_state(
                         &shard_uid,
@@ -994,6 +1009,7 @@ impl RuntimeAdapter for NightshadeRuntime {
                         account_id,
                         prefix.as_ref(),
                         *include_proof,
+                        *state_size_limit_override,
                     )
                     .map_err(|err| {
                         unc_chain::unc_chain_primitives::error::QueryError::from_view_state_error(
@@ -1321,6 +1337,7 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
             contract_id,
             method_name,
             args,
+            None,
             logs,
             epoch_info_provider,
         )
@@ -1366,8 +1383,15 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         account_id: &AccountId,
         prefix: &[u8],
         include_proof: bool,
+        state_size_limit_override: Option<u64>,
     ) -> Result<ViewStateResult, node_runtime::state_viewer::errors::ViewStateError> {
         let state_update = self.tries.new_trie_update_view(*shard_uid, state_root);
-        self.trie_viewer.view_state(&state_update, account_id, prefix, include_proof)
+        self.trie_viewer.view_state(
+            &state_update,
+            account_id,
+            prefix,
+            include_proof,
+            state_size_limit_override,
+        )
     }
 }
@@ -180,6 +180,8 @@ impl TestEnv {
             epoch_manager.clone(),
             None,
             None,
+            false,
+            None,
             Some(RuntimeConfigStore::free()),
             DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
             Default::default(),
@@ -228,6 +230,7 @@ impl TestEnv {
                 total_supply: genesis_total_supply,
                 latest_protocol_version: genesis_protocol_version,
                 timestamp_nanosec: 0,
+                chunk_producer_versions: vec![],
             })
             .unwrap()
             .commit()
@@ -299,6 +302,7 @@ impl TestEnv {
                 total_supply: self.runtime.genesis_config.total_supply,
                 latest_protocol_version: self.runtime.genesis_config.protocol_version,
                 timestamp_nanosec: self.time + 10u64.pow(9),
+                chunk_producer_versions: vec![],
             })
             .unwrap()
             .commit()
@@ -702,6 +706,7 @@ fn test_state_sync() {
                 total_supply: new_env.runtime.genesis_config.total_supply,
                 latest_protocol_version: new_env.runtime.genesis_config.protocol_version,
                 timestamp_nanosec: new_env.time,
+                chunk_producer_versions: vec![],
             })
             .unwrap()
             .commit()
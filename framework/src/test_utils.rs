@@ -1,13 +1,19 @@
 use unc_chain::types::RuntimeAdapter;
+use unc_chain::ChainGenesis;
 use unc_chain_configs::Genesis;
-use unc_client::test_utils::TestEnvBuilder;
+use unc_client::test_utils::{TestEnv, TestEnvBuilder};
+use unc_crypto::{InMemorySigner, KeyType, Signer};
 use unc_epoch_manager::EpochManagerHandle;
 use unc_parameters::RuntimeConfigStore;
+use unc_primitives::transaction::{Action, SignedTransaction, Transaction};
+use unc_primitives::types::{AccountId, Balance, BlockHeightDelta};
+use unc_primitives::views::FinalExecutionStatus;
 use unc_store::genesis::initialize_genesis_state;
 use unc_store::{Store, TrieConfig};
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use crate::config::GenesisExt;
 use crate::NightshadeRuntime;
 
 pub trait TestEnvNightshadeSetupExt {
@@ -98,3 +104,182 @@ impl TestEnvNightshadeSetupExt for TestEnvBuilder {
         )
     }
 }
+
+/// Long enough for no epoch to end while a scenario runs, so that no validator rewards get
+/// mixed into the balances compared by [`TestEnvRuntimeComparisonExt::compare_runtimes`].
+const COMPARISON_EPOCH_LENGTH: BlockHeightDelta = 10_000;
+
+/// How a transaction ended, as compared by [`TestEnvRuntimeComparisonExt::compare_runtimes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxStatus {
+    Success,
+    Failure,
+    /// The transaction was rejected before being included in a chunk.
+    Invalid,
+}
+
+/// Why `KeyValueRuntime` can't run a transaction the way `NightshadeRuntime` would.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnsupportedTx {
+    /// The mock only applies transactions made of a single transfer.
+    NotASingleTransfer,
+    /// The mock drops transfers the signer can't pay for without any outcome, so they never
+    /// finish.
+    InsufficientMockBalance,
+}
+
+/// A difference in what the two runtimes did with a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    Status { key_value: TxStatus, nightshade: TxStatus },
+    /// The balance of a signer or receiver changed differently. The tokens burnt for gas by
+    /// `NightshadeRuntime` are added back to its signer's change, as the mock burns none.
+    BalanceChange { account_id: AccountId, key_value: i128, nightshade: i128 },
+}
+
+/// Result of [`TestEnvRuntimeComparisonExt::compare_runtimes`]. Transactions are referred to
+/// by their index in the scenario.
+#[derive(Debug, Default)]
+pub struct RuntimeComparison {
+    /// Transactions that were only run on `NightshadeRuntime`, so that the ones after them see
+    /// the state they expect there.
+    pub unsupported: Vec<(usize, UnsupportedTx)>,
+    /// Status of each transaction run on `NightshadeRuntime` only.
+    pub nightshade_only: Vec<(usize, TxStatus)>,
+    pub divergences: Vec<(usize, Divergence)>,
+}
+
+impl RuntimeComparison {
+    pub fn is_conformant(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+pub trait TestEnvRuntimeComparisonExt {
+    /// Applies `scenario` on a single client env with `KeyValueRuntime` and on another with
+    /// `NightshadeRuntime`, both started from the same genesis, and compares the status of
+    /// each transaction and the balance changes of its signer and receiver.
+    ///
+    /// Only the signers of the scenario and `test0`, the block producer, are in the genesis.
+    /// The transactions are signed again for each env with the key
+    /// `InMemorySigner::from_seed` derives from the signer id, since they need a recent block
+    /// hash from the chain they're sent to. Nonces are kept as they are.
+    fn compare_runtimes(scenario: Vec<SignedTransaction>) -> RuntimeComparison;
+}
+
+impl TestEnvRuntimeComparisonExt for TestEnv {
+    fn compare_runtimes(scenario: Vec<SignedTransaction>) -> RuntimeComparison {
+        let mut accounts: Vec<AccountId> = vec!["test0".parse().unwrap()];
+        for tx in &scenario {
+            if !accounts.contains(&tx.transaction.signer_id) {
+                accounts.push(tx.transaction.signer_id.clone());
+            }
+        }
+        let mut genesis = Genesis::test(accounts, 1);
+        genesis.config.epoch_length = COMPARISON_EPOCH_LENGTH;
+        let mut key_value = TestEnv::builder(ChainGenesis::new(&genesis)).build();
+        let mut nightshade = TestEnv::builder(ChainGenesis::new(&genesis))
+            .real_epoch_managers(&genesis.config)
+            .nightshade_runtimes(&genesis)
+            .build();
+
+        let mut comparison = RuntimeComparison::default();
+        for (index, signed_tx) in scenario.into_iter().enumerate() {
+            let tx = signed_tx.transaction;
+            let signer_balance = key_value.query_balance(tx.signer_id.clone());
+            let unsupported = unsupported_by_key_value(&tx, signer_balance);
+            let nightshade_run = run_for_comparison(&mut nightshade, &tx);
+            if let Some(reason) = unsupported {
+                comparison.unsupported.push((index, reason));
+                comparison.nightshade_only.push((index, nightshade_run.status));
+                continue;
+            }
+            let key_value_run = run_for_comparison(&mut key_value, &tx);
+            if key_value_run.status != nightshade_run.status {
+                comparison.divergences.push((
+                    index,
+                    Divergence::Status {
+                        key_value: key_value_run.status,
+                        nightshade: nightshade_run.status,
+                    },
+                ));
+            }
+            let changes = key_value_run.changes.into_iter().zip(nightshade_run.changes);
+            for (account_id, (key_value_change, nightshade_change)) in
+                nightshade_run.accounts.into_iter().zip(changes)
+            {
+                if key_value_change != nightshade_change {
+                    comparison.divergences.push((
+                        index,
+                        Divergence::BalanceChange {
+                            account_id,
+                            key_value: key_value_change,
+                            nightshade: nightshade_change,
+                        },
+                    ));
+                }
+            }
+        }
+        comparison
+    }
+}
+
+fn unsupported_by_key_value(tx: &Transaction, signer_balance: Balance) -> Option<UnsupportedTx> {
+    match tx.actions.as_slice() {
+        [Action::Transfer(transfer)] if transfer.deposit > signer_balance => {
+            Some(UnsupportedTx::InsufficientMockBalance)
+        }
+        [Action::Transfer(_)] => None,
+        _ => Some(UnsupportedTx::NotASingleTransfer),
+    }
+}
+
+/// What's compared of a transaction run on one of the envs.
+struct ComparedRun {
+    status: TxStatus,
+    /// The signer, then the receiver unless it's the signer.
+    accounts: Vec<AccountId>,
+    changes: Vec<i128>,
+}
+
+fn run_for_comparison(env: &mut TestEnv, tx: &Transaction) -> ComparedRun {
+    let mut accounts = vec![tx.signer_id.clone()];
+    if tx.receiver_id != tx.signer_id {
+        accounts.push(tx.receiver_id.clone());
+    }
+    let balances = |env: &mut TestEnv| -> Vec<Balance> {
+        accounts
+            .iter()
+            .map(|account_id| env.query_balance_if_exists(account_id.clone()).unwrap_or(0))
+            .collect()
+    };
+    let before = balances(env);
+    let signer =
+        InMemorySigner::from_seed(tx.signer_id.clone(), KeyType::ED25519, tx.signer_id.as_str());
+    let signed_tx = Transaction {
+        public_key: signer.public_key(),
+        block_hash: env.clients[0].chain.head().unwrap().last_block_hash,
+        ..tx.clone()
+    }
+    .sign(&signer);
+    let (status, tokens_burnt) = match env.execute_tx(signed_tx) {
+        Ok(outcome) => {
+            let status = match outcome.status {
+                FinalExecutionStatus::SuccessValue(_) => TxStatus::Success,
+                _ => TxStatus::Failure,
+            };
+            let tokens_burnt: Balance = std::iter::once(&outcome.transaction_outcome)
+                .chain(&outcome.receipts_outcome)
+                .map(|outcome| outcome.outcome.tokens_burnt)
+                .sum();
+            (status, tokens_burnt)
+        }
+        Err(_) => (TxStatus::Invalid, 0),
+    };
+    let after = balances(env);
+    let mut changes: Vec<i128> =
+        before.iter().zip(&after).map(|(before, after)| *after as i128 - *before as i128).collect();
+    // the signer pays for the gas of the transaction and its receipts, refunds included
+    changes[0] += tokens_burnt as i128;
+    ComparedRun { status, accounts, changes }
+}
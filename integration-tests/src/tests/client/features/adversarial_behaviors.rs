@@ -3,7 +3,9 @@ use std::{collections::HashSet, sync::Arc};
 use unc_async::messaging::CanSend;
 use unc_chain::{ChainGenesis, Provenance};
 use unc_chain_configs::Genesis;
-use unc_client::test_utils::TestEnv;
+use unc_client::test_utils::{
+    ShardsManagerLogEntry, ShardsManagerMessageDirection, ShardsManagerMessageKind, TestEnv,
+};
 use unc_network::{
     shards_manager::ShardsManagerRequestFromNetwork,
     types::{NetworkRequests, PeerManagerMessageRequest},
@@ -20,6 +22,12 @@ use tracing::log::debug;
 struct AdversarialBehaviorTestData {
     num_validators: usize,
     env: TestEnv,
+    /// Validator whose node is down: chunk parts sent to it and requests for
+    /// parts addressed to it are dropped.
+    offline_validator: Option<AccountId>,
+    /// Whether to drop the parts validators forward to each other, so that
+    /// nodes tracking a shard have to request the parts they don't own.
+    drop_chunk_forwards: bool,
 }
 
 const EPOCH_LENGTH: u64 = 20;
@@ -58,11 +66,64 @@ impl AdversarialBehaviorTestData {
             .nightshade_runtimes(&genesis)
             .build();
 
-        AdversarialBehaviorTestData { num_validators, env }
+        AdversarialBehaviorTestData {
+            num_validators,
+            env,
+            offline_validator: None,
+            drop_chunk_forwards: false,
+        }
+    }
+
+    /// Single shard setup where every validator is a block producer, so that
+    /// chunks are split into enough parts for a single one not to be enough to
+    /// reconstruct a chunk.  The last validator is offline, and clients only
+    /// get the parts they own from chunk producers.
+    fn new_with_offline_validator(
+        epoch_length: u64,
+        chunk_producer_reliability_threshold: f64,
+    ) -> AdversarialBehaviorTestData {
+        let num_validators = 8;
+        let accounts: Vec<AccountId> =
+            (0..num_validators).map(|i| format!("test{}", i).parse().unwrap()).collect();
+        let mut genesis = Genesis::test(accounts.clone(), num_validators as u64);
+        {
+            let config = &mut genesis.config;
+            config.epoch_length = epoch_length;
+            config.shard_layout = ShardLayout::v0_single_shard();
+            config.num_block_producer_seats_per_shard = vec![num_validators as u64];
+            config.num_block_producer_seats = num_validators as u64;
+        }
+        let chain_genesis = ChainGenesis::new(&genesis);
+        let env = TestEnv::builder(chain_genesis)
+            .clients_count(num_validators)
+            .validator_seats(num_validators)
+            .real_epoch_managers(&genesis.config)
+            .track_all_shards()
+            .nightshade_runtimes(&genesis)
+            .record_shards_manager_requests()
+            .chunk_producer_reliability_threshold(chunk_producer_reliability_threshold)
+            .build();
+
+        AdversarialBehaviorTestData {
+            num_validators,
+            env,
+            offline_validator: accounts.last().cloned(),
+            drop_chunk_forwards: true,
+        }
+    }
+
+    fn is_offline(&self, account_id: Option<&AccountId>) -> bool {
+        account_id.is_some() && account_id == self.offline_validator.as_ref()
     }
 
     fn process_one_peer_message(&mut self, client_id: usize, requests: NetworkRequests) {
         match requests {
+            NetworkRequests::PartialEncodedChunkRequest { ref target, .. }
+                if self.is_offline(target.account_id.as_ref()) => {}
+            NetworkRequests::PartialEncodedChunkMessage { ref account_id, .. }
+                if self.is_offline(Some(account_id)) => {}
+            NetworkRequests::PartialEncodedChunkForward { ref account_id, .. }
+                if self.drop_chunk_forwards || self.is_offline(Some(account_id)) => {}
             NetworkRequests::PartialEncodedChunkRequest { .. } => {
                 self.env.process_partial_encoded_chunk_request(
                     client_id,
@@ -340,3 +401,111 @@ fn test_banning_chunk_producer_when_seeing_invalid_tx_in_chunk() {
     test.env.clients[7].produce_invalid_tx_in_chunks = true;
     test_banning_chunk_producer_when_seeing_invalid_chunk_base(test);
 }
+
+/// A validator whose node is down misses all its chunks.  Once the epoch
+/// manager has seen enough of them, the other validators should request the
+/// chunk parts it owns from the chunk producers instead of from it.
+#[test]
+fn test_chunk_part_requests_avoid_unreliable_chunk_producer() {
+    init_test_logger();
+    let epoch_length = 100;
+    let threshold = 0.5;
+    let mut test =
+        AdversarialBehaviorTestData::new_with_offline_validator(epoch_length, threshold);
+    let offline_validator = test.offline_validator.clone().unwrap();
+    let num_online = test.num_validators - 1;
+    let epoch_manager = test.env.clients[0].epoch_manager.clone();
+    // For each online validator, the length of its shards manager log when its
+    // epoch manager first reported the offline validator as unreliable.
+    let mut unreliable_since: Vec<Option<usize>> = vec![None; num_online];
+
+    // Stay in the first epoch, so that the offline validator isn't kicked out.
+    for height in 1..epoch_length - 5 {
+        debug!(target: "test", "======= Height {} ======", height);
+        test.process_all_actor_messages();
+        let epoch_id = epoch_manager
+            .get_epoch_id_from_prev_block(
+                &test.env.clients[0].chain.head().unwrap().last_block_hash,
+            )
+            .unwrap();
+        let block_producer = epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+        if block_producer == offline_validator {
+            continue;
+        }
+        let block = test.env.client(&block_producer).produce_block(height).unwrap().unwrap();
+
+        for i in 0..num_online {
+            let provenance = if test.env.get_client_id(i) == &block_producer {
+                Provenance::PRODUCED
+            } else {
+                Provenance::NONE
+            };
+            let _ = test.env.clients[i].start_process_block(
+                block.clone().into(),
+                provenance,
+                Arc::new(|_| {}),
+            );
+            let mut accepted_blocks =
+                test.env.clients[i].finish_block_in_processing(block.header().hash());
+            test.process_all_actor_messages();
+            accepted_blocks.extend(test.env.clients[i].finish_blocks_in_processing());
+            assert_eq!(
+                accepted_blocks,
+                vec![*block.header().hash()],
+                "Processing of block {} failed at validator #{}",
+                height,
+                i
+            );
+        }
+
+        for i in 0..num_online {
+            if unreliable_since[i].is_some() {
+                continue;
+            }
+            let epoch_manager = &test.env.clients[i].epoch_manager;
+            let reliability = epoch_manager.chunk_producer_reliability(&epoch_id, 0).unwrap();
+            if reliability
+                .iter()
+                .any(|(account_id, r)| account_id == &offline_validator && *r < threshold)
+            {
+                unreliable_since[i] = Some(test.env.shards_manager_log(i).entries().len());
+            }
+        }
+    }
+
+    let mut requests_to_offline_before = 0;
+    let mut requests_after = 0;
+    for i in 0..num_online {
+        let since = unreliable_since[i].unwrap_or_else(|| {
+            panic!("validator #{} never saw {} as unreliable", i, offline_validator)
+        });
+        let entries = test.env.shards_manager_log(i).entries();
+        let sent_requests = |entries: &[ShardsManagerLogEntry]| {
+            entries
+                .iter()
+                .filter(|entry| {
+                    entry.direction == ShardsManagerMessageDirection::Sent
+                        && entry.kind == ShardsManagerMessageKind::PartialEncodedChunkRequest
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        requests_to_offline_before += sent_requests(&entries[..since])
+            .iter()
+            .filter(|entry| entry.target.as_ref() == Some(&offline_validator))
+            .count();
+        for entry in sent_requests(&entries[since..]) {
+            assert_ne!(
+                entry.target.as_ref(),
+                Some(&offline_validator),
+                "validator #{} requested parts {:?} from the offline validator",
+                i,
+                entry.part_ords
+            );
+            requests_after += 1;
+        }
+    }
+    // Make sure both the requests to the offline validator and their redirection happened.
+    assert!(requests_to_offline_before > 0);
+    assert!(requests_after > 0);
+}
@@ -0,0 +1,55 @@
+use unc_chain::{ChainGenesis, Provenance};
+use unc_chain_configs::Genesis;
+use unc_client::test_utils::TestEnv;
+use unc_o11y::testonly::init_test_logger;
+use unc_primitives_core::checked_feature;
+use unc_primitives_core::version::PROTOCOL_VERSION;
+use framework::config::GenesisExt;
+use framework::test_utils::TestEnvNightshadeSetupExt;
+
+/// Client 0 processes the blocks of a few epochs while client 1 only syncs their headers.  The
+/// versions of the chunk producers are taken from the block headers, so both record the same
+/// block infos, votes included, and pick the same protocol version for every epoch.
+#[test]
+fn test_header_sync_records_chunk_producer_votes() {
+    init_test_logger();
+
+    if !checked_feature!("stable", ChunkProducerUpgradeVotes, PROTOCOL_VERSION) {
+        println!("Test not applicable without ChunkProducerUpgradeVotes enabled");
+        return;
+    }
+
+    let epoch_length = 5;
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.epoch_length = epoch_length;
+    genesis.config.protocol_version = PROTOCOL_VERSION;
+    let mut env = TestEnv::builder(ChainGenesis::new(&genesis))
+        .clients_count(2)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let mut blocks = vec![];
+    for height in 1..=3 * epoch_length {
+        let block = env.clients[0].produce_block(height).unwrap().unwrap();
+        env.process_block(0, block.clone(), Provenance::PRODUCED);
+        blocks.push(block);
+    }
+    let headers = blocks.iter().map(|block| block.header().clone()).collect();
+    env.clients[1].sync_block_headers(headers).unwrap();
+
+    let mut votes = 0;
+    for block in &blocks {
+        let processed = env.clients[0].epoch_manager.get_block_info(block.hash()).unwrap();
+        let synced = env.clients[1].epoch_manager.get_block_info(block.hash()).unwrap();
+        assert_eq!(processed, synced, "block info of {}", block.header().height());
+        votes += synced.chunk_producer_versions().iter().filter(|v| v.is_some()).count();
+
+        let next_epoch_id = block.header().next_epoch_id();
+        assert_eq!(
+            env.clients[0].epoch_manager.get_epoch_protocol_version(next_epoch_id).unwrap(),
+            env.clients[1].epoch_manager.get_epoch_protocol_version(next_epoch_id).unwrap(),
+        );
+    }
+    assert!(votes > 0, "no chunk producer versions recorded");
+}
@@ -15,4 +15,6 @@ mod sandbox;
 mod state_dump;
 mod state_snapshot;
 mod sync_state_nodes;
+mod trie_corruption;
+mod tx_builder;
 mod undo_block;
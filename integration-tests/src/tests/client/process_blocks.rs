@@ -1055,6 +1055,39 @@ fn test_process_invalid_tx() {
     );
 }
 
+fn expiry_test_env() -> TestEnv {
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    TestEnv::builder(ChainGenesis::new(&genesis))
+        .transaction_validity_period(3)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build()
+}
+
+/// A transaction is accepted while the head is at most `transaction_validity_period` blocks
+/// above the block it refers to.
+#[test]
+fn test_tx_accepted_at_end_of_validity_period() {
+    init_test_logger();
+    let mut env = expiry_test_env();
+    let tx = env.tx_builder(&"test0".parse().unwrap()).transfer("test1".parse().unwrap(), 100);
+    for i in 1..=3 {
+        env.produce_block(0, i);
+    }
+    assert_eq!(env.clients[0].process_tx(tx, false, false), ProcessTxResponse::ValidTx);
+}
+
+/// One block later, the same transaction is rejected as expired.
+#[test]
+fn test_tx_expired_after_validity_period() {
+    init_test_logger();
+    let mut env = expiry_test_env();
+    env.produce_block(0, 1);
+    let tx = env.tx_builder(&"test0".parse().unwrap()).transfer("test1".parse().unwrap(), 100);
+    env.submit_expiring_tx(tx);
+    assert_eq!(env.clients[0].chain.head().unwrap().height, 5);
+}
+
 /// If someone produce a block with Utc::now() + 1 min, we should produce a block with valid timestamp
 #[test]
 fn test_time_attack() {
@@ -1199,6 +1232,7 @@ fn test_bad_orphan() {
             match &mut chunk.inner {
                 ShardChunkHeaderInner::V1(inner) => inner.prev_outcome_root = CryptoHash([1; 32]),
                 ShardChunkHeaderInner::V2(inner) => inner.prev_outcome_root = CryptoHash([1; 32]),
+                ShardChunkHeaderInner::V3(inner) => inner.prev_outcome_root = CryptoHash([1; 32]),
             }
             chunk.hash = ShardChunkHeaderV3::compute_hash(&chunk.inner);
         }
@@ -2047,6 +2081,7 @@ fn test_gas_price_overflow() {
         assert!(block.header().next_gas_price() <= max_gas_price);
         env.process_block(0, block, Provenance::PRODUCED);
     }
+    env.assert_supply_invariant(0);
 }
 
 #[test]
@@ -2662,6 +2697,7 @@ fn test_refund_receipts_processing() {
             unreachable!("Transaction must succeed");
         }
     }
+    env.assert_supply_invariant(0);
 }
 
 // Tests that the number of delayed receipts in each shard is bounded based on the gas limit of
@@ -3489,6 +3525,52 @@ fn test_long_chain_with_restart_from_snapshot() {
     }
 }
 
+/// An observer client follows the chain and answers view queries for the accounts of every
+/// shard, without ever being picked to produce a block.
+#[test]
+fn test_observer_client() {
+    let accounts: Vec<AccountId> =
+        vec!["test0".parse().unwrap(), "test1".parse().unwrap(), "test2".parse().unwrap()];
+    let observer: AccountId = "rpc".parse().unwrap();
+    let mut genesis = Genesis::test_sharded_new_version(accounts.clone(), 2, vec![2, 2]);
+    genesis.config.epoch_length = 5;
+    let mut env = TestEnv::builder(ChainGenesis::new(&genesis))
+        .clients(accounts[..2].to_vec())
+        .validators(accounts[..2].to_vec())
+        .observer_clients(vec![observer.clone()])
+        .real_epoch_managers(&genesis.config)
+        .track_all_shards()
+        .nightshade_runtimes(&genesis)
+        .build();
+    assert_eq!(env.clients.len(), 3);
+    assert_eq!(env.get_client_id(2), &observer);
+    assert!(env.is_observer(2) && !env.is_observer(0));
+    assert!(env.clients[2].validator_signer.is_none());
+
+    for height in 1..=12 {
+        let head = env.clients[2].chain.head().unwrap();
+        let epoch_manager = env.clients[2].epoch_manager.clone();
+        let epoch_id = epoch_manager.get_epoch_id_from_prev_block(&head.last_block_hash).unwrap();
+        let producer = epoch_manager.get_block_producer(&epoch_id, height).unwrap();
+        assert_ne!(producer, observer);
+        assert!(env.clients[2].produce_block(height).is_err());
+
+        let idx = (0..2).find(|&i| env.get_client_id(i) == &producer).unwrap();
+        let block = env.clients[idx].produce_block(height).unwrap().unwrap();
+        for i in 0..3 {
+            let provenance = if i == idx { Provenance::PRODUCED } else { Provenance::NONE };
+            env.process_block(i, block.clone(), provenance);
+        }
+    }
+    assert_eq!(env.clients[2].chain.head().unwrap().height, 12);
+
+    // the queries go to the observer, which tracks both shards
+    for account_id in accounts {
+        let account = env.query_account(account_id.clone());
+        assert!(account.amount > 0, "{} has no balance", account_id);
+    }
+}
+
 /// These tests fail on aarch because the WasmtimeVM::precompile method doesn't populate the cache.
 mod contract_precompilation_tests {
     use super::*;
@@ -3613,6 +3695,7 @@ mod contract_precompilation_tests {
                 &"test0".parse().unwrap(),
                 "log_something",
                 &[],
+                None,
                 &mut logs,
                 &MockEpochInfoProvider::default(),
             )
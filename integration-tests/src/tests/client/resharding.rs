@@ -211,6 +211,7 @@ impl TestReshardingEnv {
         let epoch_config_test_overrides = Some(AllEpochConfigTestOverrides {
             block_producer_kickout_threshold: Some(0),
             chunk_producer_kickout_threshold: Some(0),
+            ..Default::default()
         });
         let env = builder
             .clients_count(num_clients)
@@ -1009,6 +1010,76 @@ fn test_shard_layout_upgrade_simple_v2_seed_44() {
     test_shard_layout_upgrade_simple_impl(ReshardingType::V2, 44, false);
 }
 
+/// Splits the single genesis shard in two at a layout and protocol version
+/// picked by the test with `TestEnvBuilder::schedule_resharding`.
+#[test]
+fn test_schedule_resharding() {
+    init_test_logger();
+    let genesis_protocol_version = SIMPLE_NIGHTSHADE_V2_PROTOCOL_VERSION - 1;
+    let target_protocol_version = SIMPLE_NIGHTSHADE_V2_PROTOCOL_VERSION;
+    let epoch_length = 5;
+    let num_clients = 2;
+    let mut rng: StdRng = SeedableRng::seed_from_u64(42);
+    let validators: Vec<AccountId> =
+        (0..num_clients).map(|i| format!("test{}", i).parse().unwrap()).collect();
+    let initial_accounts = [validators, gen_unique_accounts(&mut rng, 20, 20)].concat();
+    let genesis = setup_genesis(
+        epoch_length,
+        num_clients as u64,
+        initial_accounts.clone(),
+        None,
+        genesis_protocol_version,
+    );
+    let old_layout = genesis.config.shard_layout.clone();
+    let new_layout = ShardLayout::v1(vec!["test1".parse().unwrap()], Some(vec![vec![0, 1]]), 1);
+    let env = TestEnv::builder(ChainGenesis::new(&genesis))
+        .clients_count(num_clients)
+        .validator_seats(num_clients)
+        .schedule_resharding(target_protocol_version, new_layout.clone())
+        .real_stores()
+        .real_epoch_managers_with_test_overrides(
+            &genesis.config,
+            Some(AllEpochConfigTestOverrides {
+                block_producer_kickout_threshold: Some(0),
+                chunk_producer_kickout_threshold: Some(0),
+                ..Default::default()
+            }),
+        )
+        .nightshade_runtimes(&genesis)
+        .track_all_shards()
+        .build();
+    let mut test_env = TestReshardingEnv {
+        env,
+        initial_accounts: initial_accounts.clone(),
+        init_txs: vec![],
+        txs_by_height: BTreeMap::new(),
+        epoch_length,
+        num_clients,
+        rng,
+        resharding_type: None,
+    };
+
+    let drop_chunk_condition = DropChunkCondition::new();
+    for _ in 1..4 * epoch_length {
+        test_env.step(&drop_chunk_condition, target_protocol_version);
+    }
+
+    for client_idx in 0..num_clients {
+        assert_eq!(test_env.env.current_shard_layout(client_idx), new_layout);
+    }
+    for account_id in initial_accounts.iter() {
+        let shard_id = account_id_to_shard_id(account_id, &new_layout);
+        let want = if account_id.as_str() < "test1" { 0 } else { 1 };
+        assert_eq!(shard_id, want, "{}", account_id);
+        assert_eq!(
+            new_layout.get_parent_shard_id(shard_id).unwrap(),
+            account_id_to_shard_id(account_id, &old_layout)
+        );
+    }
+    test_env.check_accounts(initial_accounts.iter().collect());
+    test_env.check_resharding_artifacts();
+}
+
 /// In this test we are checking whether we are properly deleting trie state and flat state
 /// from the old shard layout after resharding. This is handled as a part of Garbage Collection (GC)
 fn test_shard_layout_upgrade_gc_impl(resharding_type: ReshardingType, rng_seed: u64) {
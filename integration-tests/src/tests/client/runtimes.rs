@@ -4,16 +4,20 @@
 use unc_chain::ChainGenesis;
 use unc_chain_configs::Genesis;
 use unc_client::test_utils::TestEnv;
-use unc_crypto::KeyType;
+use unc_crypto::{InMemorySigner, KeyType, Signer};
 use unc_network::test_utils::MockPeerManagerAdapter;
 use unc_primitives::block::{Approval, ApprovalInner};
 use unc_primitives::block_header::ApprovalType;
 use unc_primitives::hash::hash;
 use unc_primitives::network::PeerId;
 use unc_primitives::test_utils::create_test_signer;
+use unc_primitives::transaction::SignedTransaction;
+use unc_primitives::types::AccountId;
 use unc_primitives::validator_signer::InMemoryValidatorSigner;
-use framework::config::GenesisExt;
-use framework::test_utils::TestEnvNightshadeSetupExt;
+use framework::config::{GenesisExt, UNC_BASE};
+use framework::test_utils::{
+    Divergence, TestEnvNightshadeSetupExt, TestEnvRuntimeComparisonExt, TxStatus, UnsupportedTx,
+};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -92,3 +96,51 @@ fn test_cap_max_gas_price() {
     let max_gas_price = env.clients[0].chain.block_economics_config.max_gas_price(protocol_version);
     assert!(max_gas_price <= 20 * min_gas_price);
 }
+
+#[test]
+fn test_compare_runtimes() {
+    let test0: AccountId = "test0".parse().unwrap();
+    let test1: AccountId = "test1".parse().unwrap();
+    let alice: AccountId = "alice.test0".parse().unwrap();
+    let bob: AccountId = "bob".parse().unwrap();
+    let signer0 = InMemorySigner::from_seed(test0.clone(), KeyType::ED25519, "test0");
+    let signer1 = InMemorySigner::from_seed(test1.clone(), KeyType::ED25519, "test1");
+    // the block hashes get replaced when the transactions are signed again for each env
+    let block_hash = hash(&[0]);
+    let scenario = vec![
+        SignedTransaction::send_money(1, test0.clone(), test1.clone(), &signer0, 100, block_hash),
+        SignedTransaction::create_account(
+            2,
+            test0.clone(),
+            alice.clone(),
+            UNC_BASE,
+            signer0.public_key(),
+            &signer0,
+            block_hash,
+        ),
+        SignedTransaction::send_money(3, test0.clone(), alice, &signer0, 50, block_hash),
+        // the mock credits accounts that don't exist instead of failing the transfer
+        SignedTransaction::send_money(4, test0.clone(), bob.clone(), &signer0, 50, block_hash),
+        // test1 has only what it got from test0 with the mock
+        SignedTransaction::send_money(5, test1, test0.clone(), &signer1, 1000, block_hash),
+    ];
+
+    let comparison = TestEnv::compare_runtimes(scenario);
+    assert_eq!(
+        comparison.unsupported,
+        vec![(1, UnsupportedTx::NotASingleTransfer), (4, UnsupportedTx::InsufficientMockBalance)]
+    );
+    assert_eq!(comparison.nightshade_only, vec![(1, TxStatus::Success), (4, TxStatus::Success)]);
+    assert!(!comparison.is_conformant());
+    assert_eq!(
+        comparison.divergences,
+        vec![
+            (3, Divergence::Status { key_value: TxStatus::Success, nightshade: TxStatus::Failure }),
+            (
+                3,
+                Divergence::BalanceChange { account_id: test0, key_value: -50, nightshade: 0 }
+            ),
+            (3, Divergence::BalanceChange { account_id: bob, key_value: 50, nightshade: 0 }),
+        ]
+    );
+}
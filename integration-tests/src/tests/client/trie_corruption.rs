@@ -0,0 +1,57 @@
+use unc_chain::{ChainGenesis, ChainStoreAccess};
+use unc_chain_configs::Genesis;
+use unc_client::test_utils::{TamperOp, TestEnv};
+use unc_o11y::testonly::init_test_logger;
+use unc_primitives::trie_key::TrieKey;
+use unc_primitives::types::EpochId;
+use unc_store::{DBCol, StorageError, Trie, TrieDBStorage};
+use framework::config::GenesisExt;
+use framework::test_utils::TestEnvNightshadeSetupExt;
+use std::rc::Rc;
+
+/// Reads the account of test0 from the genesis state of the client at given index, straight
+/// from its store, so that neither flat storage nor the trie caches hide what's stored.
+fn read_account(env: &TestEnv, client_idx: usize) -> Result<Option<Vec<u8>>, StorageError> {
+    let client = &env.clients[client_idx];
+    let state_root = client.chain.genesis_block().chunks()[0].prev_state_root();
+    let shard_uid = client.epoch_manager.shard_id_to_uid(0, &EpochId::default()).unwrap();
+    let storage = TrieDBStorage::new(client.chain.chain_store().store().clone(), shard_uid);
+    let trie = Trie::new(Rc::new(storage), state_root, None);
+    trie.get(&TrieKey::Account { account_id: "test0".parse().unwrap() }.to_vec())
+}
+
+/// Corrupts a trie node on the path to the account of test0 in the store of client 1, and
+/// checks that reading the account there fails as inconsistent state rather than returning
+/// garbage, while client 0 isn't affected. The node can't repair its state by itself, so the
+/// state is then copied over from client 0, as state sync would, after which client 1 reads
+/// the same account as client 0 again.
+#[test]
+fn test_corrupted_trie_node() {
+    init_test_logger();
+    let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::new(&genesis))
+        .clients_count(2)
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .tamperable_clients([1])
+        .build();
+    let account = read_account(&env, 0).unwrap();
+    assert!(account.is_some());
+    assert_eq!(read_account(&env, 1).unwrap(), account);
+
+    let state_root = env.clients[1].chain.genesis_block().chunks()[0].prev_state_root();
+    let key = TrieKey::Account { account_id: "test0".parse().unwrap() }.to_vec();
+    env.tamper_store(1, TamperOp::CorruptTrieNode(state_root, key));
+    let err = read_account(&env, 1).unwrap_err();
+    assert!(matches!(err, StorageError::StorageInconsistentState(_)), "{err:?}");
+    assert_eq!(read_account(&env, 0).unwrap(), account);
+
+    let source = env.clients[0].chain.chain_store().store().clone();
+    let mut store_update = env.clients[1].chain.chain_store().store().store_update();
+    for item in source.iter_raw_bytes(DBCol::State) {
+        let (key, value) = item.unwrap();
+        store_update.set_raw_bytes(DBCol::State, &key, &value);
+    }
+    store_update.commit().unwrap();
+    assert_eq!(read_account(&env, 1).unwrap(), account);
+}
@@ -0,0 +1,49 @@
+use assert_matches::assert_matches;
+use unc_chain::ChainGenesis;
+use unc_chain_configs::Genesis;
+use unc_client::test_utils::TestEnv;
+use unc_crypto::{InMemorySigner, KeyType, Signer};
+use unc_primitives::types::AccountId;
+use unc_primitives::views::FinalExecutionStatus;
+use framework::config::GenesisExt;
+use framework::test_utils::TestEnvNightshadeSetupExt;
+
+/// Builds three transactions from the same signer before executing any of them,
+/// relying on `tx_builder()` to hand out increasing nonces.
+#[test]
+fn test_tx_builder_chained_transactions() {
+    let test0: AccountId = "test0".parse().unwrap();
+    let test1: AccountId = "test1".parse().unwrap();
+    let new_account: AccountId = "sub.test0".parse().unwrap();
+    let genesis = Genesis::test(vec![test0.clone(), test1.clone()], 1);
+    let mut env = TestEnv::builder(ChainGenesis::new(&genesis))
+        .real_epoch_managers(&genesis.config)
+        .nightshade_runtimes(&genesis)
+        .build();
+
+    let initial_balance = env.query_balance(test1.clone());
+    let new_key =
+        InMemorySigner::from_seed(new_account.clone(), KeyType::ED25519, new_account.as_str())
+            .public_key();
+    let txs = vec![
+        env.tx_builder(&test0).transfer(test1.clone(), 100),
+        env.tx_builder(&test0).create_account(new_account.clone(), 1000, new_key),
+        env.tx_builder(&test0).transfer(test1.clone(), 200),
+    ];
+    let nonces: Vec<_> = txs.iter().map(|tx| tx.transaction.nonce).collect();
+    assert_eq!(nonces[1], nonces[0] + 1);
+    assert_eq!(nonces[2], nonces[1] + 1);
+
+    for tx in txs {
+        let outcome = env.execute_tx(tx).unwrap();
+        assert_matches!(outcome.status, FinalExecutionStatus::SuccessValue(_));
+    }
+    assert_eq!(env.query_balance(test1.clone()), initial_balance + 300);
+    assert_eq!(env.query_balance(new_account), 1000);
+
+    // the next transaction continues from the nonce now stored in the access key
+    let tx = env.tx_builder(&test0).transfer(test1, 1);
+    assert_eq!(tx.transaction.nonce, nonces[2] + 1);
+    let outcome = env.execute_tx(tx).unwrap();
+    assert_matches!(outcome.status, FinalExecutionStatus::SuccessValue(_));
+}
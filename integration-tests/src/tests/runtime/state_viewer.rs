@@ -12,8 +12,10 @@ use unc_primitives::{
     types::{AccountId, StateRoot},
     views::{StateItem, ViewApplyState},
 };
+use unc_crypto::{KeyType, PublicKey};
 use unc_primitives::{
     test_utils::MockEpochInfoProvider,
+    transaction::RegisterRsa2048KeysAction,
     trie_key::TrieKey,
     types::{EpochId, StateChangeCause},
     version::PROTOCOL_VERSION,
@@ -122,6 +124,7 @@ fn test_view_call() {
         &"test.contract".parse().unwrap(),
         "run_test",
         &[],
+        None,
         &mut logs,
         &MockEpochInfoProvider::default(),
     );
@@ -150,6 +153,7 @@ fn test_view_call_try_changing_storage() {
         &"test.contract".parse().unwrap(),
         "run_test_with_storage_change",
         &[],
+        None,
         &mut logs,
         &MockEpochInfoProvider::default(),
     );
@@ -182,12 +186,112 @@ fn test_view_call_with_args() {
         &"test.contract".parse().unwrap(),
         "sum_with_input",
         &args,
+        None,
         &mut logs,
         &MockEpochInfoProvider::default(),
     );
     assert_eq!(view_call_result.unwrap(), 3u64.to_le_bytes().to_vec());
 }
 
+#[test]
+fn test_probe_methods() {
+    let (viewer, root) = get_test_trie_viewer();
+    let args: Vec<_> = [1u64, 2u64].iter().flat_map(|x| (*x).to_le_bytes().to_vec()).collect();
+    let view_state = ViewApplyState {
+        block_height: 1,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        epoch_height: 0,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+    let results = viewer
+        .probe_methods(
+            root,
+            view_state,
+            &"test.contract".parse().unwrap(),
+            &[("run_test", &[]), ("nft_metadata", &[]), ("sum_with_input", &args)],
+            &MockEpochInfoProvider::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            ProbeResult::Value(10i32.to_le_bytes().to_vec()),
+            ProbeResult::MethodNotFound,
+            ProbeResult::Value(3u64.to_le_bytes().to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn test_view_call_protocol_version_override() {
+    let view_state = || ViewApplyState {
+        block_height: 1,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        epoch_height: 0,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+    let call = |viewer: TrieViewer, protocol_version_override, logs: &mut Vec<String>| {
+        let (_, root) = get_test_trie_viewer();
+        viewer.call_function_with_outcome(
+            root,
+            view_state(),
+            &"test.contract".parse().unwrap(),
+            "run_test",
+            &[],
+            protocol_version_override,
+            logs,
+            &MockEpochInfoProvider::default(),
+        )
+    };
+
+    // overrides are refused unless the operator allows them
+    let result = call(TrieViewer::default(), Some(49), &mut vec![]);
+    assert!(
+        matches!(
+            result,
+            Err(errors::CallFunctionError::ProtocolVersionOverrideNotAllowed {
+                requested: 49,
+                max: None
+            })
+        ),
+        "{:?}",
+        result
+    );
+    let viewer = || TrieViewer::default().with_max_protocol_version_override(Some(49));
+    let result = call(viewer(), Some(50), &mut vec![]);
+    assert!(
+        matches!(
+            result,
+            Err(errors::CallFunctionError::ProtocolVersionOverrideNotAllowed { .. })
+        ),
+        "{:?}",
+        result
+    );
+
+    // wasm_regular_op_cost went down in protocol version 49
+    let mut logs = vec![];
+    let before = call(viewer(), Some(48), &mut logs).unwrap();
+    assert_eq!(before.result, 10i32.to_le_bytes());
+    assert_eq!(before.simulated_protocol_version, Some(48));
+    assert!(logs[0].starts_with("simulated:"), "{:?}", logs);
+    let after = call(viewer(), Some(49), &mut vec![]).unwrap();
+    assert_eq!(after.result, before.result);
+    assert!(after.burnt_gas < before.burnt_gas, "{:?} {:?}", before, after);
+
+    let mut logs = vec![];
+    let current = call(viewer(), None, &mut logs).unwrap();
+    assert_eq!(current.simulated_protocol_version, None);
+    assert!(logs.iter().all(|log| !log.starts_with("simulated:")), "{:?}", logs);
+}
+
 fn assert_view_state(
     trie_viewer: &TrieViewer,
     state_update: &unc_store::TrieUpdate,
@@ -204,7 +308,7 @@ fn assert_view_state(
         .collect::<Vec<_>>();
 
     let view_state =
-        |include_proof| trie_viewer.view_state(&state_update, &alice, prefix, include_proof);
+        |include_proof| trie_viewer.view_state(&state_update, &alice, prefix, include_proof, None);
 
     // Test without proof
     let result = view_state(false).unwrap();
@@ -363,8 +467,129 @@ fn test_view_state_too_large() {
         &Account::new(0, 0, 0, CryptoHash::default(), 50_001),
     );
     let trie_viewer = TrieViewer::new(Some(50_000), None);
-    let result = trie_viewer.view_state(&state_update, &alice_account(), b"", false);
+    let result = trie_viewer.view_state(&state_update, &alice_account(), b"", false, None);
+    assert!(matches!(result, Err(errors::ViewStateError::AccountStateTooLarge { .. })));
+}
+
+// Registers `num_chips` chips for alice, and returns them sorted the way the trie iterates them
+// along with the total size of their records.
+fn setup_chips(num_chips: u32) -> (unc_store::TrieUpdate, Vec<PublicKey>, u64) {
+    let (_, tries, root) = get_runtime_and_trie();
+    let mut state_update = tries.new_trie_update(TEST_SHARD_UID, root);
+    let mut chips = vec![];
+    let mut bytes = 0;
+    for i in 0..num_chips {
+        let public_key = PublicKey::from_seed(KeyType::ED25519, &format!("chip{}", i));
+        let args = serde_json::json!({ "power": i.to_string(), "sn": format!("sn{}", i) });
+        let chip = RegisterRsa2048KeysAction {
+            public_key: public_key.clone(),
+            operation_type: 0,
+            args: serde_json::to_vec(&args).unwrap(),
+        };
+        let key = TrieKey::Rsa2048Keys { account_id: alice_account(), public_key };
+        let value = borsh::to_vec(&chip).unwrap();
+        bytes += (key.to_vec().len() + value.len()) as u64;
+        chips.push((key.to_vec(), chip.public_key));
+        state_update.set(key, value);
+    }
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().1;
+    let mut db_changes = tries.store_update();
+    let new_root = tries.apply_all(&trie_changes, TEST_SHARD_UID, &mut db_changes);
+    db_changes.commit().unwrap();
+    chips.sort();
+    let chips = chips.into_iter().map(|(_, public_key)| public_key).collect();
+    (tries.new_trie_update(TEST_SHARD_UID, new_root), chips, bytes)
+}
+
+#[test]
+fn test_view_chip_list_too_large() {
+    let (state_update, chips, bytes) = setup_chips(20);
+
+    let (views, stats) =
+        TrieViewer::default().view_chip_list_with_stats(&state_update, &alice_account()).unwrap();
+    assert_eq!(stats, ChipListStats { num_chips: 20, bytes });
+    let public_keys = views.iter().map(|view| view.public_key.clone()).collect::<Vec<_>>();
+    let want = chips.iter().map(|public_key| public_key.to_string()).collect::<Vec<_>>();
+    assert_eq!(public_keys, want);
+
+    let result =
+        TrieViewer::new(Some(bytes), None).view_chip_list(&state_update, &alice_account());
+    assert_eq!(result.unwrap().len(), 20);
+    let result =
+        TrieViewer::new(Some(bytes / 2), None).view_chip_list(&state_update, &alice_account());
+    assert!(matches!(
+        result,
+        Err(errors::ViewChipError::ChipListTooLarge { limit, .. }) if limit == bytes / 2
+    ));
+
+    // Paging isn't limited, and goes through all the chips.
+    let trie_viewer = TrieViewer::new(Some(bytes / 2), None);
+    let mut paged = vec![];
+    let mut after = None;
+    loop {
+        let (page, next) = trie_viewer
+            .view_chip_list_page(&state_update, &alice_account(), after.as_ref(), 6)
+            .unwrap();
+        assert!(page.len() <= 6);
+        paged.extend(page.into_iter().map(|view| view.public_key));
+        match next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(paged, want);
+}
+
+#[test]
+fn test_view_state_size_limit_override() {
+    let (_, tries, root) = get_runtime_and_trie();
+    let mut state_update = tries.new_trie_update(TEST_SHARD_UID, root);
+    set_account(
+        &mut state_update,
+        alice_account(),
+        &Account::new(0, 0, 0, CryptoHash::default(), 50_001),
+    );
+    let trie_viewer =
+        TrieViewer::new(Some(50_000), None).with_hard_state_size_limit(Some(100_000));
+    let view_state = |limit_override| {
+        trie_viewer.view_state(&state_update, &alice_account(), b"", false, limit_override)
+    };
+
+    // Without an override the default limit still applies.
+    let result = view_state(None);
+    assert!(matches!(result, Err(errors::ViewStateError::AccountStateTooLarge { .. })));
+
+    // An override up to the hard limit replaces the default one.
+    assert!(view_state(Some(60_000)).is_ok());
+    let result = view_state(Some(40_000));
     assert!(matches!(result, Err(errors::ViewStateError::AccountStateTooLarge { .. })));
+
+    // An override past the hard limit is rejected.
+    let result = view_state(Some(100_001));
+    assert!(matches!(
+        result,
+        Err(errors::ViewStateError::StateSizeLimitOverrideNotAllowed {
+            requested: 100_001,
+            max: Some(100_000)
+        })
+    ));
+
+    // Without a hard limit no override is allowed.
+    let result = TrieViewer::new(Some(50_000), None).view_state(
+        &state_update,
+        &alice_account(),
+        b"",
+        false,
+        Some(60_000),
+    );
+    assert!(matches!(
+        result,
+        Err(errors::ViewStateError::StateSizeLimitOverrideNotAllowed {
+            requested: 60_000,
+            max: None
+        })
+    ));
 }
 
 #[test]
@@ -379,10 +604,212 @@ fn test_view_state_with_large_contract() {
     );
     state_update.set(TrieKey::ContractCode { account_id: alice_account() }, contract_code);
     let trie_viewer = TrieViewer::new(Some(50_000), None);
-    let result = trie_viewer.view_state(&state_update, &alice_account(), b"", false);
+    let result = trie_viewer.view_state(&state_update, &alice_account(), b"", false, None);
     assert!(result.is_ok());
 }
 
+/// Sets up alice's account with a code hash of a contract which is not in the
+/// state, like in a corrupted or partially synced store.
+fn setup_missing_code() -> (unc_store::TrieUpdate, CryptoHash) {
+    let (_, tries, root) = get_runtime_and_trie();
+    let mut state_update = tries.new_trie_update(TEST_SHARD_UID, root);
+    let code_hash = sha256(b"missing code");
+    set_account(&mut state_update, alice_account(), &Account::new(0, 0, 0, code_hash, 1_000));
+    state_update.set(
+        TrieKey::ContractData { account_id: alice_account(), key: b"test123".to_vec() },
+        b"123".to_vec(),
+    );
+    (state_update, code_hash)
+}
+
+#[test]
+fn test_view_state_missing_code() {
+    let (state_update, code_hash) = setup_missing_code();
+
+    let result = TrieViewer::new(Some(50_000), None).view_state(
+        &state_update,
+        &alice_account(),
+        b"",
+        false,
+        None,
+    );
+    match result {
+        Err(errors::ViewStateError::ContractCodeMissing { account_id, code_hash: hash }) => {
+            assert_eq!(account_id, alice_account());
+            assert_eq!(hash, code_hash);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let result = TrieViewer::new(Some(50_000), None)
+        .with_allow_missing_code(true)
+        .view_state(&state_update, &alice_account(), b"", false, None)
+        .unwrap();
+    assert_eq!(result.values.len(), 1);
+}
+
+#[test]
+fn test_view_contract_code_missing_code() {
+    let (state_update, code_hash) = setup_missing_code();
+
+    let result = TrieViewer::default().view_contract_code(&state_update, &alice_account());
+    match result {
+        Err(errors::ViewContractCodeError::ContractCodeMissing { account_id, code_hash: hash }) => {
+            assert_eq!(account_id, alice_account());
+            assert_eq!(hash, code_hash);
+        }
+        other => panic!("unexpected result {:?}", other.map(|code| *code.hash())),
+    }
+
+    let result = TrieViewer::default()
+        .with_allow_missing_code(true)
+        .view_contract_code(&state_update, &alice_account());
+    assert!(matches!(result, Err(errors::ViewContractCodeError::NoContractCode { .. })));
+}
+
+#[test]
+fn test_verify_contract_hash() {
+    let (viewer, state_update) = get_test_trie_viewer();
+    let contract_id: AccountId = "test.contract".parse().unwrap();
+    let code_hash = *viewer.view_contract_code(&state_update, &contract_id).unwrap().hash();
+
+    let result = viewer.verify_contract_hash(&state_update, &contract_id, code_hash).unwrap();
+    assert_eq!(result, ContractHashCheck::Match);
+
+    let result =
+        viewer.verify_contract_hash(&state_update, &contract_id, sha256(b"other code")).unwrap();
+    assert_eq!(result, ContractHashCheck::Mismatch { actual: code_hash });
+
+    let result = viewer.verify_contract_hash(&state_update, &alice_account(), code_hash).unwrap();
+    assert_eq!(result, ContractHashCheck::NoContract);
+
+    let nobody = "nobody.unc".parse().unwrap();
+    let result = viewer.verify_contract_hash(&state_update, &nobody, code_hash);
+    assert!(matches!(result, Err(errors::ViewContractCodeError::AccountDoesNotExist { .. })));
+}
+
+#[test]
+fn test_verify_contract_hash_missing_code() {
+    // only the account is read, so code missing from the state doesn't matter
+    let (state_update, code_hash) = setup_missing_code();
+    let result =
+        TrieViewer::default().verify_contract_hash(&state_update, &alice_account(), code_hash);
+    assert_eq!(result.unwrap(), ContractHashCheck::Match);
+}
+
+#[test]
+fn test_call_function_inconsistent_epoch() {
+    // epochs are 10 blocks long, and the one at epoch height h has an ID of all h's
+    let checker = |epoch_id: &EpochId, epoch_height: u64, block_height: u64| {
+        if epoch_height != block_height / 10 {
+            return Err(format!("block {} isn't in epoch {}", block_height, epoch_height));
+        }
+        if epoch_id.0 != CryptoHash([epoch_height as u8; 32]) {
+            return Err(format!("epoch {} has another ID", epoch_height));
+        }
+        Ok(())
+    };
+    let viewer = || TrieViewer::default().with_epoch_consistency_checker(Some(Arc::new(checker)));
+    let view_state = |epoch_id: EpochId, epoch_height| ViewApplyState {
+        block_height: 21,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id,
+        epoch_height,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+    let call = |viewer: TrieViewer, view_state| {
+        let (_, root) = get_test_trie_viewer();
+        viewer.call_function(
+            root,
+            view_state,
+            &"test.contract".parse().unwrap(),
+            "run_test",
+            &[],
+            None,
+            &mut vec![],
+            &MockEpochInfoProvider::default(),
+        )
+    };
+
+    let result = call(viewer(), view_state(EpochId(CryptoHash([2; 32])), 2));
+    assert_eq!(result.unwrap(), 10i32.to_le_bytes());
+
+    // the epoch of another block
+    let result = call(viewer(), view_state(EpochId(CryptoHash([1; 32])), 1));
+    match result {
+        Err(errors::CallFunctionError::InconsistentViewState {
+            block_height,
+            epoch_height,
+            reason,
+            ..
+        }) => {
+            assert_eq!((block_height, epoch_height), (21, 1));
+            assert_eq!(reason, "block 21 isn't in epoch 1");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    // the right epoch height with the wrong ID
+    let result = call(viewer(), view_state(EpochId::default(), 2));
+    assert!(
+        matches!(result, Err(errors::CallFunctionError::InconsistentViewState { .. })),
+        "{:?}",
+        result
+    );
+    // nothing is checked without a checker
+    let result = call(TrieViewer::default(), view_state(EpochId::default(), 2));
+    assert_eq!(result.unwrap(), 10i32.to_le_bytes());
+}
+
+#[test]
+fn test_call_function_missing_code() {
+    let view_state = || ViewApplyState {
+        block_height: 1,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        epoch_height: 0,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+
+    let (state_update, code_hash) = setup_missing_code();
+    let result = TrieViewer::default().call_function(
+        state_update,
+        view_state(),
+        &alice_account(),
+        "run_test",
+        &[],
+        None,
+        &mut vec![],
+        &MockEpochInfoProvider::default(),
+    );
+    match result {
+        Err(errors::CallFunctionError::ContractCodeMissing { account_id, code_hash: hash }) => {
+            assert_eq!(account_id, alice_account());
+            assert_eq!(hash, code_hash);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // with missing code allowed the call goes through to the VM, which fails it
+    let (state_update, _) = setup_missing_code();
+    let result = TrieViewer::default().with_allow_missing_code(true).call_function(
+        state_update,
+        view_state(),
+        &alice_account(),
+        "run_test",
+        &[],
+        None,
+        &mut vec![],
+        &MockEpochInfoProvider::default(),
+    );
+    assert!(matches!(result, Err(errors::CallFunctionError::VMError { .. })), "{:?}", result);
+}
+
 #[test]
 fn test_log_when_panic() {
     let (viewer, root) = get_test_trie_viewer();
@@ -404,6 +831,7 @@ fn test_log_when_panic() {
             &"test.contract".parse().unwrap(),
             "panic_after_logging",
             &[],
+            None,
             &mut logs,
             &MockEpochInfoProvider::default(),
         )
@@ -411,3 +839,321 @@ fn test_log_when_panic() {
 
     assert_eq!(logs, vec!["hello".to_string()]);
 }
+
+fn setup_large_contract_state(num_keys: u32) -> (unc_store::TrieUpdate, Vec<(Vec<u8>, Vec<u8>)>) {
+    let (_, tries, root) = get_runtime_and_trie();
+    let shard_uid = TEST_SHARD_UID;
+    let mut state_update = tries.new_trie_update(shard_uid, root);
+    let mut values = vec![];
+    for i in 0..num_keys {
+        let key = format!("key{}", i).into_bytes();
+        // vary the value lengths a bit, and include an empty one
+        let value = vec![(i % 251) as u8; (i % 17) as usize];
+        state_update.set(
+            TrieKey::ContractData { account_id: alice_account(), key: key.clone() },
+            value.clone(),
+        );
+        values.push((key, value));
+    }
+    // data of another account should not be exported
+    state_update.set(
+        TrieKey::ContractData { account_id: "alina".parse().unwrap(), key: b"qqq".to_vec() },
+        b"321".to_vec(),
+    );
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().1;
+    let mut db_changes = tries.store_update();
+    let new_root = tries.apply_all(&trie_changes, shard_uid, &mut db_changes);
+    db_changes.commit().unwrap();
+    // the trie iterates keys in lexicographic order
+    values.sort();
+    (tries.new_trie_update(shard_uid, new_root), values)
+}
+
+#[test]
+fn test_export_contract_state() {
+    let (state_update, want) = setup_large_contract_state(10_000);
+    let trie_viewer = TrieViewer::default();
+
+    let mut out = vec![];
+    let written = trie_viewer
+        .export_contract_state(
+            &state_update,
+            &alice_account(),
+            &mut out,
+            ExportFormat::Ndjson,
+            u64::MAX,
+        )
+        .unwrap();
+    assert_eq!(written, out.len() as u64);
+    let got = std::str::from_utf8(&out)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let item: StateItem = serde_json::from_str(line).unwrap();
+            (item.key.into(), item.value.into())
+        })
+        .collect::<Vec<(Vec<u8>, Vec<u8>)>>();
+    assert_eq!(got, want);
+
+    let mut out = vec![];
+    let written = trie_viewer
+        .export_contract_state(
+            &state_update,
+            &alice_account(),
+            &mut out,
+            ExportFormat::LengthPrefixed,
+            u64::MAX,
+        )
+        .unwrap();
+    assert_eq!(written, out.len() as u64);
+    let mut got = vec![];
+    let mut rest = &out[..];
+    let read_chunk = |rest: &mut &[u8]| {
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let (chunk, tail) = tail.split_at(len);
+        *rest = tail;
+        chunk.to_vec()
+    };
+    while !rest.is_empty() {
+        let key = read_chunk(&mut rest);
+        let value = read_chunk(&mut rest);
+        got.push((key, value));
+    }
+    assert_eq!(got, want);
+}
+
+#[test]
+fn test_export_contract_state_byte_budget() {
+    let (state_update, _) = setup_large_contract_state(100);
+    let trie_viewer = TrieViewer::default();
+
+    let mut out = vec![];
+    let written = trie_viewer
+        .export_contract_state(
+            &state_update,
+            &alice_account(),
+            &mut out,
+            ExportFormat::LengthPrefixed,
+            u64::MAX,
+        )
+        .unwrap();
+
+    // exactly enough
+    let mut out = vec![];
+    trie_viewer
+        .export_contract_state(
+            &state_update,
+            &alice_account(),
+            &mut out,
+            ExportFormat::LengthPrefixed,
+            written,
+        )
+        .unwrap();
+
+    let mut out = vec![];
+    let result = trie_viewer.export_contract_state(
+        &state_update,
+        &alice_account(),
+        &mut out,
+        ExportFormat::LengthPrefixed,
+        written - 1,
+    );
+    assert!(matches!(
+        result,
+        Err(errors::ExportStateError::ByteBudgetExceeded { byte_budget, .. }) if byte_budget == written - 1
+    ));
+    assert!((out.len() as u64) < written);
+
+    let result = trie_viewer.export_contract_state(
+        &state_update,
+        &"nonexistent".parse().unwrap(),
+        &mut vec![],
+        ExportFormat::Ndjson,
+        u64::MAX,
+    );
+    assert!(matches!(result, Err(errors::ExportStateError::AccountDoesNotExist { .. })));
+}
+
+#[test]
+fn test_view_state_stats() {
+    let (state_update, _) = setup_large_contract_state(1000);
+    let trie_viewer = TrieViewer::default();
+    let view_state = |prefix: &[u8]| {
+        trie_viewer
+            .view_state_with_stats(&state_update, &alice_account(), prefix, false, None)
+            .unwrap()
+    };
+
+    // each prefix matches a subset of the keys matched by the next one
+    let mut prev = QueryStats::default();
+    for prefix in [&b"key100"[..], b"key10", b"key1", b""] {
+        let (result, stats) = view_state(prefix);
+        assert_eq!(stats.values_returned, result.values.len() as u64);
+        assert!(stats.nodes_visited > 0, "{:?}", stats);
+        assert!(stats.bytes_read > 0, "{:?}", stats);
+        assert!(stats.values_returned > prev.values_returned, "{:?} {:?}", prev, stats);
+        assert!(stats.nodes_visited > prev.nodes_visited, "{:?} {:?}", prev, stats);
+        assert!(stats.bytes_read > prev.bytes_read, "{:?} {:?}", prev, stats);
+        assert_eq!(view_state(prefix).1, stats);
+        prev = stats;
+    }
+    assert_eq!(prev.values_returned, 1000);
+}
+
+#[test]
+fn test_view_state_seek_diagnostics() {
+    let (state_update, _) = setup_large_contract_state(1000);
+    let view_state = |trie_viewer: &TrieViewer, prefix: &[u8]| {
+        trie_viewer
+            .view_state_with_stats(&state_update, &alice_account(), prefix, false, None)
+            .unwrap()
+            .1
+    };
+    assert_eq!(view_state(&TrieViewer::default(), b"key1").diagnostics, None);
+
+    let trie_viewer = TrieViewer::default().with_seek_diagnostics(true);
+    let narrow = view_state(&trie_viewer, b"key100");
+    let wide = view_state(&trie_viewer, b"");
+    let (narrow, narrow_nodes) = (narrow.diagnostics.unwrap(), narrow.nodes_visited);
+    let (wide, wide_nodes) = (wide.diagnostics.unwrap(), wide.nodes_visited);
+    // the values of "key1" and "key10" are on the way to "key100"
+    assert_eq!(narrow.values_skipped, 2);
+    assert_eq!(wide.values_skipped, 0);
+    assert!(narrow.seek_depth > wide.seek_depth, "{:?} {:?}", narrow, wide);
+    // seeking goes down a single path, and iterating only below where it stopped
+    let seek_depth = narrow.seek_depth as usize;
+    assert!(narrow.nodes_per_level.len() >= seek_depth, "{:?}", narrow);
+    assert!(narrow.nodes_per_level[..seek_depth].iter().all(|n| *n == 1), "{:?}", narrow);
+    let narrow_total = narrow.nodes_per_level.iter().sum::<u64>();
+    let wide_total = wide.nodes_per_level.iter().sum::<u64>();
+    assert!(narrow_total <= narrow_nodes && wide_total <= wide_nodes);
+    assert!(narrow_total < wide_total, "{:?} {:?}", narrow, wide);
+}
+
+#[test]
+fn test_shared_trie_viewer_stats() {
+    const NUM_THREADS: u64 = 8;
+    const NUM_ROUNDS: u64 = 20;
+    let (_, tries, root) = get_runtime_and_trie();
+    let viewer = TrieViewer::default().with_code_cache_size(4);
+    let contract_id: AccountId = "test.contract".parse().unwrap();
+    std::thread::scope(|s| {
+        for _ in 0..NUM_THREADS {
+            s.spawn(|| {
+                for _ in 0..NUM_ROUNDS {
+                    let state_update = tries.new_trie_update(TEST_SHARD_UID, root);
+                    viewer.view_account(&state_update, &alice_account()).unwrap();
+                    viewer.view_contract_code(&state_update, &contract_id).unwrap();
+                    let view_state = ViewApplyState {
+                        block_height: 1,
+                        prev_block_hash: CryptoHash::default(),
+                        block_hash: CryptoHash::default(),
+                        epoch_id: EpochId::default(),
+                        epoch_height: 0,
+                        block_timestamp: 1,
+                        current_protocol_version: PROTOCOL_VERSION,
+                        cache: None,
+                    };
+                    let result = viewer
+                        .call_function(
+                            state_update,
+                            view_state,
+                            &contract_id,
+                            "run_test",
+                            &[],
+                            None,
+                            &mut vec![],
+                            &MockEpochInfoProvider::default(),
+                        )
+                        .unwrap();
+                    assert_eq!(result, 10i32.to_le_bytes());
+                }
+            });
+        }
+    });
+
+    let stats = viewer.stats();
+    let n = NUM_THREADS * NUM_ROUNDS;
+    assert_eq!(stats.view_account, n);
+    assert_eq!(stats.view_contract_code, n);
+    assert_eq!(stats.call_function, n);
+    assert_eq!(stats.total_queries(), 3 * n);
+    assert_eq!(stats.code_cache_hits + stats.code_cache_misses, n);
+    // a thread can only miss before the code is in the cache, which it is once the first
+    // lookup of that thread is done
+    assert!((1..=NUM_THREADS).contains(&stats.code_cache_misses), "{:?}", stats);
+    assert!(stats.code_cache_hit_rate().unwrap() >= 0.95, "{:?}", stats);
+    let code = viewer
+        .view_contract_code(&tries.new_trie_update(TEST_SHARD_UID, root), &contract_id)
+        .unwrap();
+    assert_eq!(stats.bytes_served, n * (code.code().len() as u64 + 4));
+}
+
+#[test]
+fn test_max_concurrent_view_gas() {
+    const NUM_THREADS: usize = 8;
+    const MAX_GAS_BURNT_VIEW: u64 = 20_000_000_000_000;
+    const BUDGET: u64 = 2 * MAX_GAS_BURNT_VIEW;
+    let (_, tries, root) = get_runtime_and_trie();
+    let viewer = TrieViewer::new(None, Some(MAX_GAS_BURNT_VIEW))
+        .with_max_concurrent_view_gas(Some(BUDGET));
+    let contract_id: AccountId = "test.contract".parse().unwrap();
+    let view_state = || ViewApplyState {
+        block_height: 1,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        epoch_height: 0,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+    let call = |method_name: &str| {
+        viewer.call_function_with_outcome(
+            tries.new_trie_update(TEST_SHARD_UID, root),
+            view_state(),
+            &contract_id,
+            method_name,
+            &[],
+            None,
+            &mut vec![],
+            &MockEpochInfoProvider::default(),
+        )
+    };
+
+    // every call burns all of its gas, so that they're all in flight at the same time
+    let barrier = std::sync::Barrier::new(NUM_THREADS);
+    let results = std::thread::scope(|s| {
+        let threads = (0..NUM_THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    barrier.wait();
+                    call("loop_forever")
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>()
+    });
+    let mut num_busy = 0;
+    for result in results {
+        match result {
+            // out of gas
+            Err(errors::CallFunctionError::VMError { .. }) => {}
+            Err(errors::CallFunctionError::Busy { retry_after_hint }) => {
+                assert!(retry_after_hint > std::time::Duration::ZERO);
+                num_busy += 1;
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+    assert!(num_busy > 0);
+    let stats = viewer.stats();
+    assert_eq!(stats.call_function_busy, num_busy);
+    assert_eq!(stats.call_function, NUM_THREADS as u64);
+    assert!(stats.peak_view_gas_in_flight <= BUDGET, "{:?}", stats);
+
+    // all the gas was given back, including by the calls that failed
+    assert_eq!(call("run_test").unwrap().result, 10i32.to_le_bytes());
+}
@@ -80,6 +80,7 @@ impl User for RpcUser {
             account_id: account_id.clone(),
             prefix: prefix.to_vec().into(),
             include_proof: false,
+            state_size_limit_override: None,
         };
         match self.query(query)?.kind {
             QueryResponseKind::ViewState(view_state_result) => Ok(view_state_result),
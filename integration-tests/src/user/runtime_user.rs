@@ -248,7 +248,7 @@ impl User for RuntimeUser {
     fn view_state(&self, account_id: &AccountId, prefix: &[u8]) -> Result<ViewStateResult, String> {
         let state_update = self.client.read().expect(POISONED_LOCK_ERR).get_state_update();
         self.trie_viewer
-            .view_state(&state_update, account_id, prefix, false)
+            .view_state(&state_update, account_id, prefix, false, None)
             .map_err(|err| err.to_string())
     }
 
@@ -288,6 +288,7 @@ impl User for RuntimeUser {
                 account_id,
                 method_name,
                 args,
+                None,
                 &mut result.logs,
                 &self.epoch_info_provider,
             )
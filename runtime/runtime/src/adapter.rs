@@ -72,5 +72,6 @@ pub trait ViewRuntimeAdapter {
         account_id: &AccountId,
         prefix: &[u8],
         include_proof: bool,
+        state_size_limit_override: Option<u64>,
     ) -> Result<ViewStateResult, crate::state_viewer::errors::ViewStateError>;
 }
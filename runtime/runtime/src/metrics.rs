@@ -1,6 +1,7 @@
 use unc_o11y::metrics::{
-    try_create_histogram_vec, try_create_int_counter, try_create_int_counter_vec, HistogramVec,
-    IntCounter, IntCounterVec,
+    exponential_buckets, try_create_histogram_vec, try_create_histogram_with_buckets,
+    try_create_int_counter, try_create_int_counter_vec, Histogram, HistogramVec, IntCounter,
+    IntCounterVec,
 };
 use once_cell::sync::Lazy;
 
@@ -197,6 +198,31 @@ static CHUNK_TX_TGAS: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub static VIEW_STATE_NODES_VISITED: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "unc_view_state_nodes_visited",
+        "Number of trie nodes retrieved to answer a view_state query",
+        exponential_buckets(1., 4., 12).unwrap(),
+    )
+    .unwrap()
+});
+pub static VIEW_STATE_BYTES_READ: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "unc_view_state_bytes_read",
+        "Total size in bytes of the trie nodes and values retrieved to answer a view_state query",
+        exponential_buckets(64., 4., 12).unwrap(),
+    )
+    .unwrap()
+});
+pub static VIEW_STATE_VALUES_RETURNED: Lazy<Histogram> = Lazy::new(|| {
+    try_create_histogram_with_buckets(
+        "unc_view_state_values_returned",
+        "Number of key/value pairs returned by a view_state query",
+        exponential_buckets(1., 4., 12).unwrap(),
+    )
+    .unwrap()
+});
+
 /// Buckets used for burned gas in receipts.
 ///
 /// The maximum possible is 1300 Tgas for a full chunk.
@@ -0,0 +1,33 @@
+use unc_primitives::types::{BlockHeight, EpochHeight, EpochId};
+
+/// Checks the epoch of the `ViewApplyState` of view calls against the block they're made at,
+/// set with `TrieViewer::with_epoch_consistency_checker`. Contract host functions that ask
+/// about the epoch, such as the validator pledges, answer from `epoch_id` whatever the state
+/// root is, so a `ViewApplyState` put together by hand with the wrong epoch makes them return
+/// values of another epoch without any sign of it.
+///
+/// Closures taking the epoch ID, epoch height and block height are checkers too.
+pub trait EpochConsistencyChecker: Send + Sync {
+    /// Returns why `epoch_id`, `epoch_height` and `block_height` can't all be right, if they
+    /// can't.
+    fn check(
+        &self,
+        epoch_id: &EpochId,
+        epoch_height: EpochHeight,
+        block_height: BlockHeight,
+    ) -> Result<(), String>;
+}
+
+impl<F> EpochConsistencyChecker for F
+where
+    F: Fn(&EpochId, EpochHeight, BlockHeight) -> Result<(), String> + Send + Sync,
+{
+    fn check(
+        &self,
+        epoch_id: &EpochId,
+        epoch_height: EpochHeight,
+        block_height: BlockHeight,
+    ) -> Result<(), String> {
+        self(epoch_id, epoch_height, block_height)
+    }
+}
@@ -16,6 +16,11 @@ pub enum ViewContractCodeError {
     AccountDoesNotExist { requested_account_id: unc_primitives::types::AccountId },
     #[error("Contract code for contract ID #{contract_account_id} does not exist")]
     NoContractCode { contract_account_id: unc_primitives::types::AccountId },
+    #[error("Contract code {code_hash} of account {account_id} is missing from the state")]
+    ContractCodeMissing {
+        account_id: unc_primitives::types::AccountId,
+        code_hash: unc_primitives::hash::CryptoHash,
+    },
     #[error("Internal error: #{error_message}")]
     InternalError { error_message: String },
 }
@@ -36,6 +41,11 @@ pub enum ViewChipError {
     InvalidAccountId { requested_account_id: unc_primitives::types::AccountId },
     #[error("Access key for public key #{public_key} does not exist")]
     ChipDoesNotExist { public_key: unc_crypto::PublicKey },
+    #[error(
+        "The chips of {requested_account_id} take more than {limit} bytes, view them a page at \
+        a time instead"
+    )]
+    ChipListTooLarge { requested_account_id: unc_primitives::types::AccountId, limit: u64 },
     #[error("Internal error: #{error_message}")]
     InternalError { error_message: String },
 }
@@ -48,6 +58,31 @@ pub enum ViewStateError {
     AccountDoesNotExist { requested_account_id: unc_primitives::types::AccountId },
     #[error("The state of {requested_account_id} is too large")]
     AccountStateTooLarge { requested_account_id: unc_primitives::types::AccountId },
+    #[error("Contract code {code_hash} of account {account_id} is missing from the state")]
+    ContractCodeMissing {
+        account_id: unc_primitives::types::AccountId,
+        code_hash: unc_primitives::hash::CryptoHash,
+    },
+    #[error(
+        "Viewing state with a state size limit of {requested} is not allowed, the highest \
+        allowed override is {max:?}"
+    )]
+    StateSizeLimitOverrideNotAllowed { requested: u64, max: Option<u64> },
+    #[error("Internal error: #{error_message}")]
+    InternalError { error_message: String },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportStateError {
+    #[error("Account {requested_account_id} does not exist")]
+    AccountDoesNotExist { requested_account_id: unc_primitives::types::AccountId },
+    #[error("The state of {requested_account_id} does not fit in {byte_budget} bytes")]
+    ByteBudgetExceeded {
+        requested_account_id: unc_primitives::types::AccountId,
+        byte_budget: u64,
+    },
+    #[error("Failed writing the state: #{error_message}")]
+    IOError { error_message: String },
     #[error("Internal error: #{error_message}")]
     InternalError { error_message: String },
 }
@@ -62,6 +97,33 @@ pub enum CallFunctionError {
     InternalError { error_message: String },
     #[error("VM error occurred: #{error_message}")]
     VMError { error_message: String },
+    #[error("Contract code {code_hash} of account {account_id} is missing from the state")]
+    ContractCodeMissing {
+        account_id: unc_primitives::types::AccountId,
+        code_hash: unc_primitives::hash::CryptoHash,
+    },
+    #[error(
+        "View calls under protocol version {requested} are not allowed, the highest allowed \
+        override is {max:?}"
+    )]
+    ProtocolVersionOverrideNotAllowed {
+        requested: unc_primitives::version::ProtocolVersion,
+        max: Option<unc_primitives::version::ProtocolVersion>,
+    },
+    #[error(
+        "Too much gas is in flight in other view calls, retry in about {retry_after_hint:?}"
+    )]
+    Busy { retry_after_hint: std::time::Duration },
+    #[error(
+        "Epoch {epoch_id:?} at height {epoch_height} doesn't match the state of block height \
+        {block_height}: {reason}"
+    )]
+    InconsistentViewState {
+        block_height: unc_primitives::types::BlockHeight,
+        epoch_id: unc_primitives::types::EpochId,
+        epoch_height: unc_primitives::types::EpochHeight,
+        reason: String,
+    },
 }
 
 impl From<ViewAccountError> for ViewContractCodeError {
@@ -104,6 +166,18 @@ impl From<unc_primitives::errors::StorageError> for ViewStateError {
     }
 }
 
+impl From<unc_primitives::errors::StorageError> for ExportStateError {
+    fn from(storage_error: unc_primitives::errors::StorageError) -> Self {
+        Self::InternalError { error_message: storage_error.to_string() }
+    }
+}
+
+impl From<std::io::Error> for ExportStateError {
+    fn from(io_error: std::io::Error) -> Self {
+        Self::IOError { error_message: io_error.to_string() }
+    }
+}
+
 impl From<unc_primitives::errors::StorageError> for CallFunctionError {
     fn from(storage_error: unc_primitives::errors::StorageError) -> Self {
         Self::InternalError { error_message: storage_error.to_string() }
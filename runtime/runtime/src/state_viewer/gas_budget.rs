@@ -0,0 +1,74 @@
+use unc_primitives::types::Gas;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Retry hint given before any call has given its gas back, so there's nothing to go by.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_millis(100);
+
+/// Gas of the view calls in flight on a `TrieViewer`, capped at the budget set with
+/// `TrieViewer::with_max_concurrent_view_gas`. Calls check out the most gas they may burn
+/// before running and give it back when done. The counters are only updated with atomic
+/// read-modify-write operations, so relaxed ordering is enough.
+pub(crate) struct ViewGasBudget {
+    max: Gas,
+    in_flight: AtomicU64,
+    /// Highest `in_flight` so far.
+    peak: AtomicU64,
+    /// Moving average of how long calls held their gas, in microseconds. 0 until the
+    /// first call is done.
+    avg_hold_micros: AtomicU64,
+}
+
+impl ViewGasBudget {
+    pub(crate) fn new(max: Gas) -> Self {
+        Self {
+            max,
+            in_flight: AtomicU64::new(0),
+            peak: AtomicU64::new(0),
+            avg_hold_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Checks out `gas`, unless that would take the gas in flight over the budget, in which
+    /// case it returns how long a call takes on average as a hint of when to retry.
+    pub(crate) fn try_acquire(&self, gas: Gas) -> Result<ViewGasPermit<'_>, Duration> {
+        let admitted = self.in_flight.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            n.checked_add(gas).filter(|total| *total <= self.max)
+        });
+        match admitted {
+            Ok(prev) => {
+                self.peak.fetch_max(prev + gas, Ordering::Relaxed);
+                Ok(ViewGasPermit { budget: self, gas, since: Instant::now() })
+            }
+            Err(_) => match self.avg_hold_micros.load(Ordering::Relaxed) {
+                0 => Err(DEFAULT_RETRY_AFTER),
+                micros => Err(Duration::from_micros(micros)),
+            },
+        }
+    }
+
+    pub(crate) fn peak(&self) -> Gas {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+/// Gas checked out of a [`ViewGasBudget`]. It's given back when the permit is dropped, so
+/// that it's released however the call ends, including by an error or a panic.
+pub(crate) struct ViewGasPermit<'a> {
+    budget: &'a ViewGasBudget,
+    gas: Gas,
+    since: Instant,
+}
+
+impl Drop for ViewGasPermit<'_> {
+    fn drop(&mut self) {
+        self.budget.in_flight.fetch_sub(self.gas, Ordering::Relaxed);
+        let held = u64::try_from(self.since.elapsed().as_micros()).unwrap_or(u64::MAX).max(1);
+        // weighs the last eight calls or so
+        let _ = self.budget.avg_hold_micros.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |avg| Some(if avg == 0 { held } else { avg - avg / 8 + held / 8 }),
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::unc_primitives::version::PROTOCOL_VERSION;
 use crate::receipt_manager::ReceiptManager;
 use crate::{actions::execute_function_call, ext::RuntimeExt};
+use unc_cache::SyncLruCache;
 use unc_crypto::{KeyType, PublicKey};
 use unc_parameters::RuntimeConfigStore;
 use unc_primitives::account::{AccessKey, Account};
@@ -9,33 +10,194 @@ use unc_primitives::hash::CryptoHash;
 use unc_primitives::receipt::ActionReceipt;
 use unc_primitives::runtime::apply_state::ApplyState;
 use unc_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
-use unc_primitives::transaction::FunctionCallAction;
-use unc_primitives::trie_key::trie_key_parsers;
+use unc_primitives::serialize::to_base64;
+use unc_primitives::transaction::{FunctionCallAction, RegisterRsa2048KeysAction};
+use unc_primitives::trie_key::{trie_key_parsers, TrieKey};
 use unc_primitives::types::{AccountId, EpochInfoProvider, Gas};
+use unc_primitives::version::ProtocolVersion;
 use unc_primitives::views::{ChipView, StateItem, ViewApplyState, ViewStateResult};
 use unc_primitives_core::config::ViewConfig;
-use unc_store::{get_access_key, get_account, get_code, TrieUpdate};
-use unc_vm_runner::logic::ReturnData;
+use unc_store::{
+    get_access_key, get_account, get_code, KeyLookupMode, TrieIteratorDiagnostics, TrieUpdate,
+};
+use unc_vm_runner::logic::errors::{FunctionCallError, MethodResolveError};
+use unc_vm_runner::logic::{ReturnData, VMOutcome};
 use unc_vm_runner::ContractCode;
+use std::io::Write;
 use std::{str, sync::Arc, time::Instant};
 use tracing::debug;
 use crate::state_viewer::errors::ViewChipError;
+use crate::state_viewer::gas_budget::ViewGasBudget;
+use crate::state_viewer::stats::{QueryKind, TrieViewerCounters};
 
+mod epoch_check;
 pub mod errors;
+mod gas_budget;
+mod stats;
 
+pub use epoch_check::EpochConsistencyChecker;
+pub use stats::TrieViewerStats;
+
+/// Number of key/value pairs written by `TrieViewer::export_contract_state` between
+/// flushes of the sink.
+const EXPORT_FLUSH_INTERVAL: usize = 1000;
+
+/// Result of one of the methods called by `TrieViewer::probe_methods`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeResult {
+    /// The method returned this value.
+    Value(Vec<u8>),
+    /// The contract doesn't export the method.
+    MethodNotFound,
+    /// The method was found but its execution failed.
+    Failed { error_message: String },
+}
+
+/// Result of `TrieViewer::verify_contract_hash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractHashCheck {
+    /// The account's contract has the expected code hash.
+    Match,
+    /// The account has a contract, but with a different code hash.
+    Mismatch { actual: CryptoHash },
+    /// The account has no contract deployed.
+    NoContract,
+}
+
+/// Result of a view call made by `TrieViewer::call_function_with_outcome`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ViewCallOutcome {
+    /// Value returned by the method.
+    pub result: Vec<u8>,
+    /// Gas burnt by the call.
+    pub burnt_gas: Gas,
+    /// Set if the call was made with a protocol version override, in which case the
+    /// result is what the call would return under that version against the current
+    /// state, not what it returns on chain right now.
+    pub simulated_protocol_version: Option<ProtocolVersion>,
+}
+
+/// Encoding of the key/value pairs written by `TrieViewer::export_contract_state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One `{"key": <base64>, "value": <base64>}` JSON object per line, same as a
+    /// serialized `StateItem`.
+    Ndjson,
+    /// The key and then the value, each prefixed with its length as a little endian u32.
+    LengthPrefixed,
+}
+
+impl ExportFormat {
+    fn encode(self, key: &[u8], value: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Self::Ndjson => {
+                writeln!(
+                    out,
+                    "{{\"key\":\"{}\",\"value\":\"{}\"}}",
+                    to_base64(key),
+                    to_base64(value)
+                )
+                .expect("writing to a Vec can't fail");
+            }
+            Self::LengthPrefixed => {
+                out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                out.extend_from_slice(key);
+                out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                out.extend_from_slice(value);
+            }
+        }
+    }
+}
+
+/// Trie accounting of a single `view_state` query, see [`TrieViewer::view_state_with_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of trie nodes retrieved, including the ones on the path to the queried prefix.
+    pub nodes_visited: u64,
+    /// Total size in bytes of the trie nodes and values retrieved.
+    pub bytes_read: u64,
+    /// Number of key/value pairs in the result.
+    pub values_returned: u64,
+    /// Where in the trie the query went, if enabled with
+    /// [`TrieViewer::with_seek_diagnostics`].
+    pub diagnostics: Option<TrieIteratorDiagnostics>,
+}
+
+/// Size of the chip records of an account, see [`TrieViewer::view_chip_list_with_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChipListStats {
+    /// Number of chips registered by the account.
+    pub num_chips: u64,
+    /// Total size in bytes of the keys and values of the chip records.
+    pub bytes: u64,
+}
+
+impl QueryStats {
+    fn report(&self, account_id: &AccountId) {
+        crate::metrics::VIEW_STATE_NODES_VISITED.observe(self.nodes_visited as f64);
+        crate::metrics::VIEW_STATE_BYTES_READ.observe(self.bytes_read as f64);
+        crate::metrics::VIEW_STATE_VALUES_RETURNED.observe(self.values_returned as f64);
+        debug!(target: "runtime", %account_id, ?self, "view_state query stats");
+    }
+}
+
+/// Answers view queries against the state.
+///
+/// A single viewer is meant to be shared by all the threads serving queries, e.g. behind an
+/// `Arc`: every method takes `&self`, and the state it keeps between queries is behind
+/// interior mutability. The query counters are lock-free atomics, and the contract code cache
+/// enabled with [`Self::with_code_cache_size`] takes a short lock on each lookup, never held
+/// while reading the state or running a contract. Anything added here later has to keep it
+/// `Send + Sync`, which is checked at compile time below.
 pub struct TrieViewer {
     /// Upper bound of the byte size of contract state that is still viewable. None is no limit
     state_size_limit: Option<u64>,
+    /// Highest `state_size_limit` view_state calls may ask for instead of the configured one.
+    /// None disallows state size limit overrides altogether.
+    hard_state_size_limit: Option<u64>,
     /// Gas limit used when when handling call_function queries.
     max_gas_burnt_view: Gas,
+    /// Treat contract code missing from the state of an account with a non-zero code hash as
+    /// empty instead of failing with `ContractCodeMissing`. Only makes sense for pruned stores.
+    allow_missing_code: bool,
+    /// Highest protocol version view calls may ask to be simulated under. None disallows
+    /// protocol version overrides altogether.
+    max_protocol_version_override: Option<ProtocolVersion>,
+    /// Contract code by code hash, for `view_contract_code`. Code is addressed by its hash,
+    /// so entries never go stale. None disables the cache.
+    code_cache: Option<SyncLruCache<CryptoHash, Arc<[u8]>>>,
+    /// Totals of the queries served, see [`Self::stats`].
+    counters: TrieViewerCounters,
+    /// Cap on the gas of the view calls running at once. None is no cap.
+    view_gas_budget: Option<ViewGasBudget>,
+    /// Record the trie depth and nodes visited by each `view_state` query in its stats.
+    seek_diagnostics: bool,
+    /// Checks the epoch of the `ViewApplyState` of each view call. None doesn't check it.
+    epoch_consistency_checker: Option<Arc<dyn EpochConsistencyChecker>>,
 }
 
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TrieViewer>();
+};
+
 impl Default for TrieViewer {
     fn default() -> Self {
         let config_store = RuntimeConfigStore::new(None);
         let latest_runtime_config = config_store.get_config(PROTOCOL_VERSION);
         let max_gas_burnt = latest_runtime_config.wasm_config.limit_config.max_gas_burnt;
-        Self { state_size_limit: None, max_gas_burnt_view: max_gas_burnt }
+        Self {
+            state_size_limit: None,
+            hard_state_size_limit: None,
+            max_gas_burnt_view: max_gas_burnt,
+            allow_missing_code: false,
+            max_protocol_version_override: None,
+            code_cache: None,
+            counters: TrieViewerCounters::default(),
+            view_gas_budget: None,
+            seek_diagnostics: false,
+            epoch_consistency_checker: None,
+        }
     }
 }
 
@@ -43,13 +205,125 @@ impl TrieViewer {
     pub fn new(state_size_limit: Option<u64>, max_gas_burnt_view: Option<Gas>) -> Self {
         let max_gas_burnt_view =
             max_gas_burnt_view.unwrap_or_else(|| TrieViewer::default().max_gas_burnt_view);
-        Self { state_size_limit, max_gas_burnt_view }
+        Self {
+            state_size_limit,
+            hard_state_size_limit: None,
+            max_gas_burnt_view,
+            allow_missing_code: false,
+            max_protocol_version_override: None,
+            code_cache: None,
+            counters: TrieViewerCounters::default(),
+            view_gas_budget: None,
+            seek_diagnostics: false,
+            epoch_consistency_checker: None,
+        }
+    }
+
+    /// Makes the viewer treat contract code missing from the state as empty code, as it
+    /// used to, instead of reporting it as an error. Missing code for an account with a
+    /// non-zero code hash means the store is corrupted or only partially synced, so this
+    /// should only be set by operators knowingly running pruned stores.
+    pub fn with_allow_missing_code(mut self, allow_missing_code: bool) -> Self {
+        self.allow_missing_code = allow_missing_code;
+        self
+    }
+
+    /// Allows `call_function` to be asked to run under any protocol version up to `max`
+    /// instead of the current one, so that contract developers can check what their view
+    /// methods would return after an upcoming upgrade.
+    pub fn with_max_protocol_version_override(mut self, max: Option<ProtocolVersion>) -> Self {
+        self.max_protocol_version_override = max;
+        self
+    }
+
+    /// Allows `view_state` to be asked to use any state size limit up to `max` instead of the
+    /// configured one, so that trusted callers like the debug pages can look at contract state
+    /// too large for public queries.
+    pub fn with_hard_state_size_limit(mut self, max: Option<u64>) -> Self {
+        self.hard_state_size_limit = max;
+        self
+    }
+
+    /// Keeps the code of up to `size` contracts returned by `view_contract_code` in memory,
+    /// so that popular contracts aren't read from the state on every query. 0 disables the
+    /// cache, which is the default.
+    pub fn with_code_cache_size(mut self, size: usize) -> Self {
+        self.code_cache = (size > 0).then(|| SyncLruCache::new(size));
+        self
+    }
+
+    /// Caps the gas of the view calls running at once, by all the threads sharing this
+    /// viewer, at `max`, so that a burst of expensive calls can't take up every thread
+    /// serving queries. A call counts as `max_gas_burnt_view` while it runs, the most it
+    /// may burn, and calls which would take the total over `max` fail right away with
+    /// `CallFunctionError::Busy` instead of waiting. With `max` below `max_gas_burnt_view`
+    /// no call can run. None, the default, is no cap.
+    pub fn with_max_concurrent_view_gas(mut self, max: Option<Gas>) -> Self {
+        self.view_gas_budget = max.map(ViewGasBudget::new);
+        self
+    }
+
+    /// Makes `view_state` record, for each query, how deep in the trie seeking its prefix
+    /// went, how many nodes it retrieved at each depth and how many values it passed over on
+    /// the way, for finding out why some prefixes are slow. They're returned in the
+    /// `QueryStats` of `view_state_with_stats` and logged at debug level. Off by default.
+    pub fn with_seek_diagnostics(mut self, seek_diagnostics: bool) -> Self {
+        self.seek_diagnostics = seek_diagnostics;
+        self
+    }
+
+    /// Makes `call_function` and `probe_methods` check with `checker` that the epoch ID,
+    /// epoch height and block height of their `ViewApplyState` go together before running
+    /// anything, and fail with `CallFunctionError::InconsistentViewState` if they don't.
+    /// Meant for callers that put the `ViewApplyState` together themselves rather than with
+    /// `ViewApplyState::for_block`. None, the default, doesn't check.
+    pub fn with_epoch_consistency_checker(
+        mut self,
+        checker: Option<Arc<dyn EpochConsistencyChecker>>,
+    ) -> Self {
+        self.epoch_consistency_checker = checker;
+        self
+    }
+
+    /// Totals of the queries served so far, by all the threads sharing this viewer.
+    pub fn stats(&self) -> TrieViewerStats {
+        let mut stats = self.counters.snapshot();
+        if let Some(budget) = &self.view_gas_budget {
+            stats.peak_view_gas_in_flight = budget.peak();
+        }
+        stats
+    }
+
+    /// Whether `account` not having its contract code in the state should be reported as
+    /// an error.
+    fn missing_code_is_error(&self, account: &Account) -> bool {
+        !self.allow_missing_code && account.code_hash() != CryptoHash::default()
+    }
+
+    /// Returns the length of the contract code of `account_id`, or None if there is no code
+    /// in the state, without reading the code itself.
+    fn contract_code_len(
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+    ) -> Result<Option<u64>, unc_primitives::errors::StorageError> {
+        let key = TrieKey::ContractCode { account_id: account_id.clone() };
+        Ok(state_update.get_ref(&key, KeyLookupMode::FlatStorage)?.map(|code| code.len() as u64))
     }
 
     pub fn view_account(
         &self,
         state_update: &TrieUpdate,
         account_id: &AccountId,
+    ) -> Result<Account, errors::ViewAccountError> {
+        self.counters.query(QueryKind::ViewAccount, 1);
+        Self::read_account(state_update, account_id)
+    }
+
+    /// Same as [`Self::view_account`], for the queries that need the account without
+    /// counting as a `view_account` query.
+    fn read_account(
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
     ) -> Result<Account, errors::ViewAccountError> {
         get_account(state_update, account_id)?.ok_or_else(|| {
             errors::ViewAccountError::AccountDoesNotExist {
@@ -63,11 +337,53 @@ impl TrieViewer {
         state_update: &TrieUpdate,
         account_id: &AccountId,
     ) -> Result<ContractCode, errors::ViewContractCodeError> {
-        let account = self.view_account(state_update, account_id)?;
-        get_code(state_update, account_id, Some(account.code_hash()))?.ok_or_else(|| {
-            errors::ViewContractCodeError::NoContractCode {
-                contract_account_id: account_id.clone(),
+        self.counters.query(QueryKind::ViewContractCode, 1);
+        let account = Self::read_account(state_update, account_id)?;
+        let code_hash = account.code_hash();
+        let code_cache = self.code_cache.as_ref().filter(|_| code_hash != CryptoHash::default());
+        if let Some(cache) = code_cache {
+            let cached = cache.get(&code_hash);
+            self.counters.code_cache_lookup(cached.is_some());
+            if let Some(code) = cached {
+                self.counters.served(code.len() as u64);
+                return Ok(ContractCode::new(code.to_vec(), Some(code_hash)));
+            }
+        }
+        match get_code(state_update, account_id, Some(code_hash))? {
+            Some(code) => {
+                if let Some(cache) = code_cache {
+                    cache.put(code_hash, Arc::from(code.code()));
+                }
+                self.counters.served(code.code().len() as u64);
+                Ok(code)
+            }
+            None if self.missing_code_is_error(&account) => {
+                Err(errors::ViewContractCodeError::ContractCodeMissing {
+                    account_id: account_id.clone(),
+                    code_hash: account.code_hash(),
+                })
             }
+            None => Err(errors::ViewContractCodeError::NoContractCode {
+                contract_account_id: account_id.clone(),
+            }),
+        }
+    }
+
+    /// Checks whether the contract deployed to `account_id` has code hash `expected`, e.g. to
+    /// verify a deployment against the built artifact. Only the account is read, not the code.
+    pub fn verify_contract_hash(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        expected: CryptoHash,
+    ) -> Result<ContractHashCheck, errors::ViewContractCodeError> {
+        let actual = Self::read_account(state_update, account_id)?.code_hash();
+        Ok(if actual == CryptoHash::default() {
+            ContractHashCheck::NoContract
+        } else if actual == expected {
+            ContractHashCheck::Match
+        } else {
+            ContractHashCheck::Mismatch { actual }
         })
     }
 
@@ -77,6 +393,7 @@ impl TrieViewer {
         account_id: &AccountId,
         public_key: &PublicKey,
     ) -> Result<AccessKey, errors::ViewAccessKeyError> {
+        self.counters.query(QueryKind::ViewAccessKey, 1);
         get_access_key(state_update, account_id, public_key)?.ok_or_else(|| {
             errors::ViewAccessKeyError::AccessKeyDoesNotExist { public_key: public_key.clone() }
         })
@@ -87,6 +404,7 @@ impl TrieViewer {
         state_update: &TrieUpdate,
         account_id: &AccountId,
     ) -> Result<Vec<(PublicKey, AccessKey)>, errors::ViewAccessKeyError> {
+        self.counters.query(QueryKind::ViewAccessKeys, 1);
         let prefix = trie_key_parsers::get_raw_prefix_for_access_keys(account_id);
         let raw_prefix: &[u8] = prefix.as_ref();
         let access_keys =
@@ -118,128 +436,266 @@ impl TrieViewer {
         state_update: &TrieUpdate,
         account_id: &AccountId,
     ) -> Result<Vec<ChipView>, ViewChipError> {
-        let prefix = trie_key_parsers::get_raw_prefix_for_rsa_keys(account_id);
-        let raw_prefix: &[u8] = prefix.as_ref();
+        self.view_chip_list_with_stats(state_update, account_id).map(|(chips, _)| chips)
+    }
+
+    /// Same as [`Self::view_chip_list`], but also returns how much storage the chip records
+    /// of the account take up, so that operators can tell which accounts register the most.
+    /// Fails with `ChipListTooLarge` once the chip records read take more than
+    /// `state_size_limit` bytes, in which case [`Self::view_chip_list_page`] can be used to
+    /// go through them a page at a time.
+    pub fn view_chip_list_with_stats(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+    ) -> Result<(Vec<ChipView>, ChipListStats), ViewChipError> {
+        self.counters.query(QueryKind::ViewChipList, 1);
         let mut chip_views = Vec::new();
+        let mut stats = ChipListStats::default();
+        for item in Self::chip_keys(state_update, account_id)? {
+            let (key, public_key) = item?;
+            let (chip_action, value_len) = Self::read_chip(state_update, &key)?;
+            stats.num_chips += 1;
+            stats.bytes += (key.len() + value_len) as u64;
+            if let Some(limit) = self.state_size_limit {
+                if stats.bytes > limit {
+                    return Err(ViewChipError::ChipListTooLarge {
+                        requested_account_id: account_id.clone(),
+                        limit,
+                    });
+                }
+            }
+            chip_views.push(Self::chip_view(&public_key, &chip_action)?);
+        }
+        Ok((chip_views, stats))
+    }
 
-        let iter_result = state_update
-            .iter(&prefix)
-            .map_err(|_| ViewChipError::InternalError {
-                error_message: "Failed to iterate over state_update".to_string(),
-            })?;
+    /// Returns up to `limit` chips of `account_id`, at least one, starting after the one
+    /// with public key `after`, or from the first one if it's None. Along with them comes
+    /// the public key to pass as `after` for the next page, which is None once there are no
+    /// chips left. `state_size_limit` doesn't apply here, so this is how the chips of
+    /// accounts too large for [`Self::view_chip_list`] can be viewed.
+    pub fn view_chip_list_page(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        after: Option<&PublicKey>,
+        limit: usize,
+    ) -> Result<(Vec<ChipView>, Option<PublicKey>), ViewChipError> {
+        self.counters.query(QueryKind::ViewChipList, 1);
+        let limit = limit.max(1);
+        let after = after.map(|public_key| {
+            TrieKey::Rsa2048Keys { account_id: account_id.clone(), public_key: public_key.clone() }
+                .to_vec()
+        });
+        let mut chip_views = Vec::new();
+        let mut last = None;
+        // The iterator can't be seeked to `after`, so the keys before it are skipped, but
+        // only the values of the chips returned are read.
+        for item in Self::chip_keys(state_update, account_id)? {
+            let (key, public_key) = item?;
+            if after.as_ref().is_some_and(|after| &key <= after) {
+                continue;
+            }
+            if chip_views.len() == limit {
+                return Ok((chip_views, last));
+            }
+            let (chip_action, _) = Self::read_chip(state_update, &key)?;
+            chip_views.push(Self::chip_view(&public_key, &chip_action)?);
+            last = Some(public_key);
+        }
+        Ok((chip_views, None))
+    }
 
-        for key_result in iter_result {
+    /// Iterates over the raw trie keys of the chips of `account_id`, in the order they're
+    /// stored, along with the public keys they're registered under.
+    fn chip_keys<'a>(
+        state_update: &'a TrieUpdate,
+        account_id: &AccountId,
+    ) -> Result<
+        impl Iterator<Item = Result<(Vec<u8>, PublicKey), ViewChipError>> + 'a,
+        ViewChipError,
+    > {
+        let prefix = trie_key_parsers::get_raw_prefix_for_rsa_keys(account_id);
+        let iter_result = state_update.iter(&prefix).map_err(|_| ViewChipError::InternalError {
+            error_message: "Failed to iterate over state_update".to_string(),
+        })?;
+        Ok(iter_result.map(move |key_result| {
             let key = key_result.map_err(|_| ViewChipError::InternalError {
                 error_message: "Iteration error encountered".to_string(),
             })?;
-
-            let public_key_str = &key[raw_prefix.len()..];
-
-            let public_key = PublicKey::try_from_slice(public_key_str)
-                .map_err(|_| errors::ViewChipError::InternalError {
+            let public_key_str = &key[prefix.len()..];
+            let public_key = PublicKey::try_from_slice(public_key_str).map_err(|_| {
+                ViewChipError::InternalError {
                     error_message: format!(
                         "Unexpected invalid public key {:?} received from store",
                         public_key_str
                     ),
-                })?;
-            // Extract the part of the key that follows the prefix, if needed
-
-            let chip_action = unc_store::get_rsa2048_keys_raw(state_update, &key).map_err(|e| {
-                ViewChipError::InternalError {
-                    error_message: format!("Storage error encountered: {:?}", e),
                 }
+            })?;
+            Ok((key, public_key))
+        }))
+    }
+
+    /// Reads the chip stored under the raw trie key `key`, along with the size of its value.
+    fn read_chip(
+        state_update: &TrieUpdate,
+        key: &[u8],
+    ) -> Result<(RegisterRsa2048KeysAction, usize), ViewChipError> {
+        let trie_key = trie_key_parsers::parse_trie_key_rsa_key_from_raw_key(key).map_err(|e| {
+            ViewChipError::InternalError {
+                error_message: format!("Unexpected invalid chip key received from store: {}", e),
+            }
+        })?;
+        let value = state_update
+            .get(&trie_key)
+            .map_err(|e| ViewChipError::InternalError {
+                error_message: format!("Storage error encountered: {:?}", e),
             })?
-                .ok_or_else(|| ViewChipError::InternalError {
-                    error_message: "Unexpected missing key from iterator".to_string(),
-                })?;
+            .ok_or_else(|| ViewChipError::InternalError {
+                error_message: "Unexpected missing key from iterator".to_string(),
+            })?;
+        let chip_action = RegisterRsa2048KeysAction::try_from_slice(&value).map_err(|_| {
+            ViewChipError::InternalError {
+                error_message: "Failed to deserialize chip from store".to_string(),
+            }
+        })?;
+        Ok((chip_action, value.len()))
+    }
 
-            match serde_json::from_slice::<serde_json::Value>(&chip_action.args) {
-                Ok(parsed_args) => {
-                    let mut chip_view = ChipView {
-                        miner_id: String::new(),
-                        public_key: String::new(), // Assume initially empty, update if necessary
-                        power: 0,
-                        sn: String::new(),
-                        bus_id: String::new(),
-                        p2key: String::new(),
-                    };
-
-                    // Directly assign 'power'
-                    // if let Some(power_val) = parsed_args.get("power").and_then(|v| v.as_u64()) {
-                    //     chip_view.power = power_val;
-                    // }
-                    // Handle power field with dual-path parsing
-                    if let Some(power_val) = parsed_args.get("power") {
-                        if let Some(power_str) = power_val.as_str() {
-                            chip_view.power = power_str.parse::<u64>().unwrap_or(0);
-                        } else if let Some(power_number) = power_val.as_u64() {
-                            chip_view.power = power_number;
-                        } else {
-                            println!("Power value is not a string or a number that fits into u64");
-                        }
+    #[allow(deprecated)]
+    fn chip_view(
+        public_key: &PublicKey,
+        chip_action: &RegisterRsa2048KeysAction,
+    ) -> Result<ChipView, ViewChipError> {
+        match serde_json::from_slice::<serde_json::Value>(&chip_action.args) {
+            Ok(parsed_args) => {
+                let mut chip_view = ChipView {
+                    miner_id: String::new(),
+                    public_key: String::new(), // Assume initially empty, update if necessary
+                    power: 0,
+                    sn: String::new(),
+                    bus_id: String::new(),
+                    p2key: String::new(),
+                };
+
+                // Directly assign 'power'
+                // if let Some(power_val) = parsed_args.get("power").and_then(|v| v.as_u64()) {
+                //     chip_view.power = power_val;
+                // }
+                // Handle power field with dual-path parsing
+                if let Some(power_val) = parsed_args.get("power") {
+                    if let Some(power_str) = power_val.as_str() {
+                        chip_view.power = power_str.parse::<u64>().unwrap_or(0);
+                    } else if let Some(power_number) = power_val.as_u64() {
+                        chip_view.power = power_number;
+                    } else {
+                        println!("Power value is not a string or a number that fits into u64");
                     }
+                }
 
-                    chip_view.public_key = public_key.to_string();
+                chip_view.public_key = public_key.to_string();
 
-                    // Extract 'sn' directly
-                    if let Some(sn_val) = parsed_args.get("sn").and_then(|v| v.as_str()) {
-                        chip_view.sn = sn_val.to_string();
-                    }
+                // Extract 'sn' directly
+                if let Some(sn_val) = parsed_args.get("sn").and_then(|v| v.as_str()) {
+                    chip_view.sn = sn_val.to_string();
+                }
 
-                    // Extract 'public_key' directly
-                    if let Some(public_key_val) = parsed_args.get("public_key").and_then(|v| v.as_str()) {
-                        chip_view.public_key = public_key_val.to_string();
-                    }
+                // Extract 'public_key' directly
+                if let Some(public_key_val) =
+                    parsed_args.get("public_key").and_then(|v| v.as_str())
+                {
+                    chip_view.public_key = public_key_val.to_string();
+                }
 
-                    // Extract 'miner_id' directly
-                    if let Some(miner_id_val) = parsed_args.get("miner_id").and_then(|v| v.as_str()) {
-                        chip_view.miner_id = miner_id_val.to_string();
-                    }
+                // Extract 'miner_id' directly
+                if let Some(miner_id_val) = parsed_args.get("miner_id").and_then(|v| v.as_str()) {
+                    chip_view.miner_id = miner_id_val.to_string();
+                }
 
-                    // Extract 'bus_id' directly
-                    if let Some(bus_id_val) = parsed_args.get("bus_id").and_then(|v| v.as_str()) {
-                        chip_view.bus_id = bus_id_val.to_string();
-                    }
+                // Extract 'bus_id' directly
+                if let Some(bus_id_val) = parsed_args.get("bus_id").and_then(|v| v.as_str()) {
+                    chip_view.bus_id = bus_id_val.to_string();
+                }
 
-                    // Extract 'p2key' directly
-                    if let Some(p2key_val) = parsed_args.get("p2key").and_then(|v| v.as_str()) {
-                        chip_view.p2key = p2key_val.to_string();
-                    }
+                // Extract 'p2key' directly
+                if let Some(p2key_val) = parsed_args.get("p2key").and_then(|v| v.as_str()) {
+                    chip_view.p2key = p2key_val.to_string();
+                }
 
-                    // Example: Update public_key or other fields based on key_suffix if applicable
-                    // chip_view.public_key = String::from_utf8_lossy(key_suffix).to_string();
+                // Example: Update public_key or other fields based on key_suffix if applicable
+                // chip_view.public_key = String::from_utf8_lossy(key_suffix).to_string();
 
-                    // Continue to extract and assign other fields as needed
+                // Continue to extract and assign other fields as needed
 
-                    chip_views.push(chip_view);
-                }
-                Err(_) => {
-                    // Handle parsing error
-                    return Err(ViewChipError::InternalError {
-                        error_message: "Failed to parse JSON from args".to_string(),
-                    });
-                }
+                Ok(chip_view)
+            }
+            Err(_) => {
+                // Handle parsing error
+                Err(ViewChipError::InternalError {
+                    error_message: "Failed to parse JSON from args".to_string(),
+                })
             }
         }
-
-        Ok(chip_views)
     }
 
-
-
+    /// Returns the contract data of `account_id` under `prefix`. With
+    /// `state_size_limit_override` set, that limit is used instead of the configured one, as
+    /// long as it's not above the hard limit set with `with_hard_state_size_limit`.
     pub fn view_state(
         &self,
         state_update: &TrieUpdate,
         account_id: &AccountId,
         prefix: &[u8],
         include_proof: bool,
+        state_size_limit_override: Option<u64>,
     ) -> Result<ViewStateResult, errors::ViewStateError> {
+        self.view_state_with_stats(
+            state_update,
+            account_id,
+            prefix,
+            include_proof,
+            state_size_limit_override,
+        )
+        .map(|(result, _)| result)
+    }
+
+    /// Same as [`Self::view_state`], but also returns how much of the trie had to be read to
+    /// answer the query. The stats are reported to metrics either way.
+    pub fn view_state_with_stats(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        prefix: &[u8],
+        include_proof: bool,
+        state_size_limit_override: Option<u64>,
+    ) -> Result<(ViewStateResult, QueryStats), errors::ViewStateError> {
+        self.counters.query(QueryKind::ViewState, 1);
+        let state_size_limit = match state_size_limit_override {
+            Some(requested) => {
+                if self.hard_state_size_limit.map_or(true, |max| requested > max) {
+                    return Err(errors::ViewStateError::StateSizeLimitOverrideNotAllowed {
+                        requested,
+                        max: self.hard_state_size_limit,
+                    });
+                }
+                Some(requested)
+            }
+            None => self.state_size_limit,
+        };
         match get_account(state_update, account_id)? {
             Some(account) => {
-                let code_len = get_code(state_update, account_id, Some(account.code_hash()))?
-                    .map(|c| c.code().len() as u64)
-                    .unwrap_or_default();
-                if let Some(limit) = self.state_size_limit {
+                let code_len = match Self::contract_code_len(state_update, account_id)? {
+                    Some(code_len) => code_len,
+                    None if self.missing_code_is_error(&account) => {
+                        return Err(errors::ViewStateError::ContractCodeMissing {
+                            account_id: account_id.clone(),
+                            code_hash: account.code_hash(),
+                        });
+                    }
+                    None => 0,
+                };
+                if let Some(limit) = state_size_limit {
                     if account.storage_usage().saturating_sub(code_len) > limit {
                         return Err(errors::ViewStateError::AccountStateTooLarge {
                             requested_account_id: account_id.clone(),
@@ -255,54 +711,279 @@ impl TrieViewer {
         };
 
         let mut values = vec![];
+        let mut bytes_served = 0;
         let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, prefix);
         let acc_sep_len = query.len() - prefix.len();
         let mut iter = state_update.trie().iter()?;
         iter.remember_visited_nodes(include_proof);
+        iter.record_diagnostics(self.seek_diagnostics);
         iter.seek_prefix(&query)?;
         for item in &mut iter {
             let (key, value) = item?;
+            bytes_served += (key.len() - acc_sep_len + value.len()) as u64;
             values.push(StateItem { key: key[acc_sep_len..].to_vec().into(), value: value.into() });
         }
+        let stats = QueryStats {
+            nodes_visited: iter.nodes_visited(),
+            bytes_read: iter.bytes_read(),
+            values_returned: values.len() as u64,
+            diagnostics: iter.diagnostics().cloned(),
+        };
+        stats.report(account_id);
+        self.counters.served(bytes_served);
         let proof = iter.into_visited_nodes();
-        Ok(ViewStateResult { values, proof })
+        Ok((ViewStateResult { values, proof }, stats))
+    }
+
+    /// Writes all the contract data of `account_id` to `sink` in the given format, without
+    /// collecting it in memory first. `state_size_limit` doesn't apply here, instead the
+    /// export fails once more than `byte_budget` bytes would be written, in which case
+    /// whatever was written before stays in the sink. Returns the number of bytes written.
+    pub fn export_contract_state(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        sink: &mut dyn Write,
+        format: ExportFormat,
+        byte_budget: u64,
+    ) -> Result<u64, errors::ExportStateError> {
+        self.counters.query(QueryKind::ExportContractState, 1);
+        if get_account(state_update, account_id)?.is_none() {
+            return Err(errors::ExportStateError::AccountDoesNotExist {
+                requested_account_id: account_id.clone(),
+            });
+        }
+
+        let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, &[]);
+        let mut iter = state_update.trie().iter()?;
+        iter.seek_prefix(&query)?;
+        let mut written = 0;
+        let mut buf = vec![];
+        let mut i = 0;
+        // Values are borrowed from the trie storage and only copied into `buf` as they are
+        // encoded, which saves an allocation per value compared to the owned iterator.
+        while let Some(item) = iter.next_borrowed() {
+            let (key, value) = item?;
+            i += 1;
+            buf.clear();
+            format.encode(&key[query.len()..], &value, &mut buf);
+            written += buf.len() as u64;
+            if written > byte_budget {
+                sink.flush()?;
+                return Err(errors::ExportStateError::ByteBudgetExceeded {
+                    requested_account_id: account_id.clone(),
+                    byte_budget,
+                });
+            }
+            sink.write_all(&buf)?;
+            if i % EXPORT_FLUSH_INTERVAL == 0 {
+                sink.flush()?;
+            }
+        }
+        sink.flush()?;
+        self.counters.served(written);
+        Ok(written)
     }
 
+    /// Calls a view method of `contract_id`. With `protocol_version_override` set, the call
+    /// runs with the runtime config and VM features of that protocol version rather than the
+    /// current one, still against the current state. Such results are simulated, and a log
+    /// line saying so is put in front of the logs of the call.
     pub fn call_function(
         &self,
-        mut state_update: TrieUpdate,
+        state_update: TrieUpdate,
         view_state: ViewApplyState,
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        protocol_version_override: Option<ProtocolVersion>,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
     ) -> Result<Vec<u8>, errors::CallFunctionError> {
+        self.call_function_with_outcome(
+            state_update,
+            view_state,
+            contract_id,
+            method_name,
+            args,
+            protocol_version_override,
+            logs,
+            epoch_info_provider,
+        )
+        .map(|outcome| outcome.result)
+    }
+
+    /// Same as `call_function`, but also returns the gas burnt by the call and whether
+    /// it was simulated under another protocol version.
+    pub fn call_function_with_outcome(
+        &self,
+        state_update: TrieUpdate,
+        view_state: ViewApplyState,
+        contract_id: &AccountId,
+        method_name: &str,
+        args: &[u8],
+        protocol_version_override: Option<ProtocolVersion>,
+        logs: &mut Vec<String>,
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<ViewCallOutcome, errors::CallFunctionError> {
+        self.counters.query(QueryKind::CallFunction, 1);
+        if let Some(requested) = protocol_version_override {
+            if self.max_protocol_version_override.map_or(true, |max| requested > max) {
+                return Err(errors::CallFunctionError::ProtocolVersionOverrideNotAllowed {
+                    requested,
+                    max: self.max_protocol_version_override,
+                });
+            }
+            logs.push(format!(
+                "simulated: executed with the runtime config of protocol version {} instead \
+                of the current protocol version {}",
+                requested, view_state.current_protocol_version
+            ));
+        }
         let now = Instant::now();
+        let outcome = self
+            .run_view_calls(
+                state_update,
+                view_state,
+                contract_id,
+                &[(method_name, args)],
+                protocol_version_override,
+                epoch_info_provider,
+            )?
+            .pop()
+            .expect("one outcome per call");
+        let elapsed = now.elapsed();
+        let time_ms =
+            (elapsed.as_secs() as f64 / 1_000.0) + f64::from(elapsed.subsec_nanos()) / 1_000_000.0;
+        let time_str = format!("{:.*}ms", 2, time_ms);
+
+        if let Some(err) = outcome.aborted {
+            logs.extend(outcome.logs);
+            let message = format!("wasm execution failed with error: {:?}", err);
+            debug!(target: "runtime", "(exec time {}) {}", time_str, message);
+            Err(errors::CallFunctionError::VMError { error_message: message })
+        } else {
+            debug!(target: "runtime", "(exec time {}) result of execution: {:?}", time_str, outcome);
+            logs.extend(outcome.logs);
+            let result = match outcome.return_data {
+                ReturnData::Value(buf) => buf,
+                ReturnData::ReceiptIndex(_) | ReturnData::None => vec![],
+            };
+            self.counters.served(result.len() as u64);
+            Ok(ViewCallOutcome {
+                result,
+                burnt_gas: outcome.burnt_gas,
+                simulated_protocol_version: protocol_version_override,
+            })
+        }
+    }
+
+    /// Calls each of the given view methods of `contract_id` with its arguments and
+    /// returns one result per method, in order. Meant for checking which standards a
+    /// contract implements, so a method the contract doesn't export is reported as
+    /// `ProbeResult::MethodNotFound` rather than failing the whole probe.
+    ///
+    /// The account and its code are looked up once. Every method then runs in its own
+    /// VM instance with fresh logic state, since the VM runner has no way to reuse an
+    /// instance across calls, but with a compiled contract cache in `view_state` the
+    /// contract is only compiled once.
+    pub fn probe_methods(
+        &self,
+        state_update: TrieUpdate,
+        view_state: ViewApplyState,
+        contract_id: &AccountId,
+        methods: &[(&str, &[u8])],
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<Vec<ProbeResult>, errors::CallFunctionError> {
+        self.counters.query(QueryKind::CallFunction, methods.len() as u64);
+        let outcomes = self.run_view_calls(
+            state_update,
+            view_state,
+            contract_id,
+            methods,
+            None,
+            epoch_info_provider,
+        )?;
+        let results = outcomes
+            .into_iter()
+            .map(|outcome| match outcome.aborted {
+                Some(FunctionCallError::MethodResolveError(MethodResolveError::MethodNotFound)) => {
+                    ProbeResult::MethodNotFound
+                }
+                Some(err) => ProbeResult::Failed {
+                    error_message: format!("wasm execution failed with error: {:?}", err),
+                },
+                None => ProbeResult::Value(match outcome.return_data {
+                    ReturnData::Value(buf) => buf,
+                    ReturnData::ReceiptIndex(_) | ReturnData::None => vec![],
+                }),
+            })
+            .collect::<Vec<_>>();
+        for result in results.iter() {
+            if let ProbeResult::Value(value) = result {
+                self.counters.served(value.len() as u64);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Runs each of `calls` as a view call of `contract_id`. Changes one call makes to
+    /// `state_update` are rolled back before the next one runs. With
+    /// `protocol_version_override` set, the calls run as if that was the current protocol
+    /// version.
+    fn run_view_calls(
+        &self,
+        mut state_update: TrieUpdate,
+        view_state: ViewApplyState,
+        contract_id: &AccountId,
+        calls: &[(&str, &[u8])],
+        protocol_version_override: Option<ProtocolVersion>,
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<Vec<VMOutcome>, errors::CallFunctionError> {
+        if let Some(checker) = &self.epoch_consistency_checker {
+            checker
+                .check(&view_state.epoch_id, view_state.epoch_height, view_state.block_height)
+                .map_err(|reason| errors::CallFunctionError::InconsistentViewState {
+                    block_height: view_state.block_height,
+                    epoch_id: view_state.epoch_id.clone(),
+                    epoch_height: view_state.epoch_height,
+                    reason,
+                })?;
+        }
+        // The calls run one after the other, so only one of them is in flight at a time.
+        // The gas is given back when the permit is dropped, however this returns.
+        let _permit = match &self.view_gas_budget {
+            Some(budget) => {
+                let permit = budget.try_acquire(self.max_gas_burnt_view).map_err(|hint| {
+                    self.counters.busy();
+                    errors::CallFunctionError::Busy { retry_after_hint: hint }
+                })?;
+                Some(permit)
+            }
+            None => None,
+        };
         let root = *state_update.get_root();
-        let mut account = get_account(&state_update, contract_id)?.ok_or_else(|| {
+        let account = get_account(&state_update, contract_id)?.ok_or_else(|| {
             errors::CallFunctionError::AccountDoesNotExist {
                 requested_account_id: contract_id.clone(),
             }
         })?;
+        if self.missing_code_is_error(&account)
+            && Self::contract_code_len(&state_update, contract_id)?.is_none()
+        {
+            return Err(errors::CallFunctionError::ContractCodeMissing {
+                account_id: contract_id.clone(),
+                code_hash: account.code_hash(),
+            });
+        }
         // TODO(#1015): Add ability to pass public key and originator_id
         let originator_id = contract_id;
         let public_key = PublicKey::empty(KeyType::ED25519);
         let empty_hash = CryptoHash::default();
-        let mut receipt_manager = ReceiptManager::default();
-        let mut runtime_ext = RuntimeExt::new(
-            &mut state_update,
-            &mut receipt_manager,
-            contract_id,
-            &empty_hash,
-            &view_state.epoch_id,
-            &view_state.prev_block_hash,
-            &view_state.block_hash,
-            epoch_info_provider,
-            view_state.current_protocol_version,
-        );
         let config_store = RuntimeConfigStore::new(None);
-        let config = config_store.get_config(PROTOCOL_VERSION);
+        let config = config_store.get_config(protocol_version_override.unwrap_or(PROTOCOL_VERSION));
+        let protocol_version =
+            protocol_version_override.unwrap_or(view_state.current_protocol_version);
         let apply_state = ApplyState {
             block_height: view_state.block_height,
             // Used for legacy reasons
@@ -314,7 +995,7 @@ impl TrieViewer {
             block_timestamp: view_state.block_timestamp,
             gas_limit: None,
             random_seed: root,
-            current_protocol_version: view_state.current_protocol_version,
+            current_protocol_version: protocol_version,
             config: config.clone(),
             cache: view_state.cache,
             is_new_chunk: false,
@@ -329,45 +1010,47 @@ impl TrieViewer {
             input_data_ids: vec![],
             actions: vec![],
         };
-        let function_call = FunctionCallAction {
-            method_name: method_name.to_string(),
-            args: args.to_vec(),
-            gas: self.max_gas_burnt_view,
-            deposit: 0,
-        };
-        let outcome = execute_function_call(
-            &apply_state,
-            &mut runtime_ext,
-            &mut account,
-            originator_id,
-            &action_receipt,
-            &[],
-            &function_call,
-            &empty_hash,
-            config,
-            true,
-            Some(ViewConfig { max_gas_burnt: self.max_gas_burnt_view }),
-        )
-        .map_err(|e| errors::CallFunctionError::InternalError { error_message: e.to_string() })?;
-        let elapsed = now.elapsed();
-        let time_ms =
-            (elapsed.as_secs() as f64 / 1_000.0) + f64::from(elapsed.subsec_nanos()) / 1_000_000.0;
-        let time_str = format!("{:.*}ms", 2, time_ms);
-
-        if let Some(err) = outcome.aborted {
-            logs.extend(outcome.logs);
-            let message = format!("wasm execution failed with error: {:?}", err);
-            debug!(target: "runtime", "(exec time {}) {}", time_str, message);
-            Err(errors::CallFunctionError::VMError { error_message: message })
-        } else {
-            debug!(target: "runtime", "(exec time {}) result of execution: {:?}", time_str, outcome);
-            logs.extend(outcome.logs);
-            let result = match outcome.return_data {
-                ReturnData::Value(buf) => buf,
-                ReturnData::ReceiptIndex(_) | ReturnData::None => vec![],
+        let mut outcomes = Vec::with_capacity(calls.len());
+        for (method_name, args) in calls {
+            let mut account = account.clone();
+            let mut receipt_manager = ReceiptManager::default();
+            let mut runtime_ext = RuntimeExt::new(
+                &mut state_update,
+                &mut receipt_manager,
+                contract_id,
+                &empty_hash,
+                &view_state.epoch_id,
+                &view_state.prev_block_hash,
+                &view_state.block_hash,
+                epoch_info_provider,
+                protocol_version,
+            );
+            let function_call = FunctionCallAction {
+                method_name: method_name.to_string(),
+                args: args.to_vec(),
+                gas: self.max_gas_burnt_view,
+                deposit: 0,
             };
-            Ok(result)
+            let outcome = execute_function_call(
+                &apply_state,
+                &mut runtime_ext,
+                &mut account,
+                originator_id,
+                &action_receipt,
+                &[],
+                &function_call,
+                &empty_hash,
+                config,
+                true,
+                Some(ViewConfig { max_gas_burnt: self.max_gas_burnt_view }),
+            )
+            .map_err(|e| errors::CallFunctionError::InternalError {
+                error_message: e.to_string(),
+            })?;
+            state_update.rollback();
+            outcomes.push(outcome);
         }
+        Ok(outcomes)
     }
 }
 
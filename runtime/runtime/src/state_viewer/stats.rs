@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Kind of query served by a `TrieViewer`, counted in [`TrieViewerStats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum QueryKind {
+    ViewAccount,
+    ViewContractCode,
+    ViewAccessKey,
+    ViewAccessKeys,
+    ViewChipList,
+    ViewState,
+    ExportContractState,
+    CallFunction,
+}
+
+/// Totals of the queries a `TrieViewer` has served since it was created, see
+/// `TrieViewer::stats`. Queries are counted whether they succeed or not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrieViewerStats {
+    pub view_account: u64,
+    pub view_contract_code: u64,
+    pub view_access_key: u64,
+    pub view_access_keys: u64,
+    /// Calls to `view_chip_list`, `view_chip_list_with_stats` and `view_chip_list_page`.
+    pub view_chip_list: u64,
+    pub view_state: u64,
+    pub export_contract_state: u64,
+    /// View methods run, counting each method given to `probe_methods`.
+    pub call_function: u64,
+    /// Calls to `call_function`, `call_function_with_outcome` and `probe_methods` rejected
+    /// with `CallFunctionError::Busy`. Their methods are counted in `call_function` too.
+    pub call_function_busy: u64,
+    /// Highest gas of the view calls in flight at once, if it's capped with
+    /// `TrieViewer::with_max_concurrent_view_gas`.
+    pub peak_view_gas_in_flight: u64,
+    /// Contract code lookups answered from the code cache.
+    pub code_cache_hits: u64,
+    /// Contract code lookups which had to read the code from the state.
+    pub code_cache_misses: u64,
+    /// Bytes returned by successful queries: contract code, the keys and values of contract
+    /// state, and the results of view calls.
+    pub bytes_served: u64,
+}
+
+impl TrieViewerStats {
+    /// Total number of queries of any kind.
+    pub fn total_queries(&self) -> u64 {
+        self.view_account
+            + self.view_contract_code
+            + self.view_access_key
+            + self.view_access_keys
+            + self.view_chip_list
+            + self.view_state
+            + self.export_contract_state
+            + self.call_function
+    }
+
+    /// Share of contract code lookups answered from the code cache, or None if there
+    /// weren't any, e.g. because the cache is disabled.
+    pub fn code_cache_hit_rate(&self) -> Option<f64> {
+        let lookups = self.code_cache_hits + self.code_cache_misses;
+        (lookups != 0).then(|| self.code_cache_hits as f64 / lookups as f64)
+    }
+}
+
+/// Lock-free counters behind [`TrieViewerStats`]. They're only ever added to, so relaxed
+/// ordering is enough; a snapshot taken while queries are running may count a query in one
+/// counter but not yet in another.
+#[derive(Default)]
+pub(crate) struct TrieViewerCounters {
+    view_account: AtomicU64,
+    view_contract_code: AtomicU64,
+    view_access_key: AtomicU64,
+    view_access_keys: AtomicU64,
+    view_chip_list: AtomicU64,
+    view_state: AtomicU64,
+    export_contract_state: AtomicU64,
+    call_function: AtomicU64,
+    call_function_busy: AtomicU64,
+    code_cache_hits: AtomicU64,
+    code_cache_misses: AtomicU64,
+    bytes_served: AtomicU64,
+}
+
+impl TrieViewerCounters {
+    pub(crate) fn query(&self, kind: QueryKind, n: u64) {
+        let counter = match kind {
+            QueryKind::ViewAccount => &self.view_account,
+            QueryKind::ViewContractCode => &self.view_contract_code,
+            QueryKind::ViewAccessKey => &self.view_access_key,
+            QueryKind::ViewAccessKeys => &self.view_access_keys,
+            QueryKind::ViewChipList => &self.view_chip_list,
+            QueryKind::ViewState => &self.view_state,
+            QueryKind::ExportContractState => &self.export_contract_state,
+            QueryKind::CallFunction => &self.call_function,
+        };
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn code_cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.code_cache_hits } else { &self.code_cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn busy(&self) {
+        self.call_function_busy.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn served(&self, bytes: u64) {
+        self.bytes_served.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> TrieViewerStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        TrieViewerStats {
+            view_account: load(&self.view_account),
+            view_contract_code: load(&self.view_contract_code),
+            view_access_key: load(&self.view_access_key),
+            view_access_keys: load(&self.view_access_keys),
+            view_chip_list: load(&self.view_chip_list),
+            view_state: load(&self.view_state),
+            export_contract_state: load(&self.export_contract_state),
+            call_function: load(&self.call_function),
+            call_function_busy: load(&self.call_function_busy),
+            // filled in from the budget by TrieViewer::stats
+            peak_view_gas_in_flight: 0,
+            code_cache_hits: load(&self.code_cache_hits),
+            code_cache_misses: load(&self.code_cache_misses),
+            bytes_served: load(&self.bytes_served),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Checks that exporting contract state borrows values from the trie storage instead of
+//! copying them.  This is a separate test binary because it installs a counting global
+//! allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Write;
+
+use node_runtime::state_viewer::{ExportFormat, TrieViewer};
+use unc_primitives::account::Account;
+use unc_primitives::hash::CryptoHash;
+use unc_primitives::shard_layout::ShardUId;
+use unc_primitives::trie_key::{trie_key_parsers, TrieKey};
+use unc_primitives::types::{AccountId, StateChangeCause};
+use unc_store::test_utils::TestTriesBuilder;
+use unc_store::{set_account, Trie, TrieUpdate};
+
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATED_BYTES: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `try_with` because the thread locals may already be gone while a thread exits.
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            let _ = ALLOCATED_BYTES.try_with(|n| n.set(n.get() + layout.size() as u64));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of bytes allocated by the current thread while running `f`.
+fn allocated_bytes(f: impl FnOnce()) -> u64 {
+    ALLOCATED_BYTES.with(|n| n.set(0));
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATED_BYTES.with(Cell::get)
+}
+
+fn setup_contract_state(account_id: &AccountId, num_keys: u32, value_len: usize) -> TrieUpdate {
+    let tries = TestTriesBuilder::new().build();
+    let shard_uid = ShardUId::single_shard();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+    set_account(
+        &mut state_update,
+        account_id.clone(),
+        &Account::new(1, 0, 0, CryptoHash::default(), 0),
+    );
+    for i in 0..num_keys {
+        state_update.set(
+            TrieKey::ContractData {
+                account_id: account_id.clone(),
+                key: format!("key{}", i).into_bytes(),
+            },
+            vec![(i % 251) as u8; value_len],
+        );
+    }
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().1;
+    let mut store_update = tries.store_update();
+    let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+    store_update.commit().unwrap();
+    tries.new_trie_update(shard_uid, root)
+}
+
+/// What `export_contract_state` did before it borrowed values from the trie storage.
+fn export_with_owned_values(state_update: &TrieUpdate, account_id: &AccountId) {
+    let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, &[]);
+    let mut iter = state_update.trie().iter().unwrap();
+    iter.seek_prefix(&query).unwrap();
+    let mut sink = std::io::sink();
+    let mut buf = vec![];
+    for item in iter {
+        let (key, value) = item.unwrap();
+        let key = &key[query.len()..];
+        buf.clear();
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&value);
+        sink.write_all(&buf).unwrap();
+    }
+}
+
+#[test]
+fn test_export_contract_state_allocations() {
+    let account_id: AccountId = "alice".parse().unwrap();
+    let state_update = setup_contract_state(&account_id, 10_000, 512);
+    let trie_viewer = TrieViewer::default();
+    let export = || {
+        trie_viewer
+            .export_contract_state(
+                &state_update,
+                &account_id,
+                &mut std::io::sink(),
+                ExportFormat::LengthPrefixed,
+                u64::MAX,
+            )
+            .unwrap();
+    };
+
+    // Warm up the trie caches so that both runs below only read from memory.
+    export();
+    export_with_owned_values(&state_update, &account_id);
+
+    let owned = allocated_bytes(|| export_with_owned_values(&state_update, &account_id));
+    let borrowed = allocated_bytes(export);
+    assert!(
+        borrowed * 10 <= owned * 7,
+        "expected at least a 30% reduction in allocated bytes, got {borrowed} vs {owned}"
+    );
+}
@@ -0,0 +1,1849 @@
+use anyhow::Context;
+
+use chrono::Utc;
+use unc_chain_configs::{Genesis, GenesisValidationMode};
+use unc_crypto::PublicKey;
+use unc_primitives::hash::{hash, CryptoHash};
+use unc_primitives::shard_layout::ShardLayout;
+use unc_primitives::state_record::{state_record_to_account_id, StateRecord};
+use unc_primitives::types::{AccountId, AccountInfo};
+use unc_primitives::utils;
+use unc_primitives_core::account::{AccessKey, Account};
+use unc_primitives_core::types::{Balance, NumSeats, NumShards};
+use num_rational::Rational32;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufWriter, Read};
+use std::path::PathBuf;
+
+use crate::error::bail;
+use crate::intern::{self, Interned};
+use crate::{
+    chain_id, checks, compression, dedupe, diff, extract, generate, in_place, index, keys,
+    overrides, parse_validators, pledge_to_power, progress, records, remove, rename, scale,
+    smoke_test, split, storage, truncate, validate, wanted_records, write_genesis,
+    AccountRecords, AccountStatus, AmendGenesisConfig, AmendGenesisError, AmendGenesisOutcome,
+    AmendOptions, AmendPhase, EmptyShardPolicy, GenesisHashes, RecordAction, RecordStats,
+    RecordsFormat, RecordsSink, RecordsSource, ValidationMode, DEFAULT_NUM_BYTES_ACCOUNT,
+    DEFAULT_NUM_EXTRA_BYTES_RECORD, DEFAULT_VALIDATOR_AMOUNT,
+};
+
+/// The storage usage parameters the output accounts are written with.
+#[derive(Clone, Copy)]
+struct StorageParams {
+    /// storage usage of an account without any data
+    num_bytes_account: u64,
+    /// storage usage of each record on top of the size of its key and value
+    num_extra_bytes_record: u64,
+}
+
+/// The input and output files. Outputs which are also inputs are written next to them and
+/// renamed over them once everything else is done.
+struct AmendFiles {
+    genesis_file_in: PathBuf,
+    // where the output genesis config is written, a temporary file if it's amended in place
+    genesis_file_out: PathBuf,
+    genesis_in_place: Option<in_place::InPlaceOutput>,
+    records_in_place: Option<in_place::InPlaceOutput>,
+    records_in_name: String,
+    records_out_name: String,
+    // the input records file, if it has to be read once more to validate it
+    records_file_in: Option<PathBuf>,
+    // the input records file, if it has to be read once more to tally storage usage
+    records_file_tally: Option<PathBuf>,
+    // the output records file, if it has to be read back after it's written
+    records_file_out: Option<PathBuf>,
+}
+
+/// The input genesis config, along with what the later phases need to know about the output.
+struct LoadedGenesis {
+    genesis: Genesis,
+    // the output chain ID, if it's changed
+    chain_id: Option<String>,
+    storage: StorageParams,
+    // the output shard layout, if it's changed
+    shard_layout: Option<ShardLayout>,
+    shard_filter: Option<extract::ShardFilter>,
+}
+
+/// The input records, opened but not read yet.
+struct RecordsInput {
+    reader: Box<dyn Read>,
+    progress: progress::ProgressReporter,
+}
+
+/// Everything written while amending, along with the running totals of what was written.
+struct AmendOutput {
+    records: records::RecordWriter<BufWriter<compression::RecordsOut>>,
+    account_index: Option<index::AccountIndex>,
+    account_diff: Option<diff::AccountDiff>,
+    summary_out: Option<progress::SummaryWriter>,
+    outcome: AmendGenesisOutcome,
+    total_supply: Balance,
+    scaler: Option<scale::BalanceScaler>,
+}
+
+/// The validators and the accounts to change, and the state kept about them while the input
+/// records are streamed.
+struct Accounts {
+    validators: Vec<AccountInfo>,
+    validator_keys: keys::ValidatorKeys,
+    // validators of the input genesis config kept with --append-validators or without
+    // --validators, whose pledges are left as they are in the input records
+    kept_validators: Vec<AccountInfo>,
+    // validators no chip was found for yet. Only the ones with power are warned about in the
+    // end, since --pledge-to-power only sets it once the input records are read
+    chipless_validators: HashSet<AccountId>,
+    interners: intern::Interners,
+    // the accounts held back from the input records to be written at the end
+    wanted: HashMap<Interned<AccountId>, AccountRecords>,
+    renames: Option<rename::AccountRenames>,
+    account_filter: Option<remove::AccountFilter>,
+    balance_overrides: Option<overrides::BalanceOverrides>,
+    treasury_account: AccountId,
+    treasury_exists: bool,
+    // storage usage of the Contract and Data records left out with --strip-contracts, by
+    // account
+    stripped: HashMap<AccountId, u64>,
+    replacement_code: Option<(Vec<u8>, CryptoHash)>,
+    // Contract records of accounts whose Account record was already written when their code
+    // was replaced
+    num_replaced_not_adjusted: u64,
+    truncator: Option<truncate::ContractStateTruncator>,
+    storage_tally: Option<storage::StorageTally>,
+    nonce_check: Option<checks::NonceHeightCheck>,
+    duplicates: dedupe::DuplicateRecords,
+}
+
+impl AmendOptions {
+    // whether the output is hashed, for --emit-genesis-hash or --template-out
+    fn hashes_output(&self) -> bool {
+        self.emit_genesis_hash || !self.template_out.is_empty()
+    }
+
+    // whether the output records are read back after they're written
+    fn reads_output_back(&self) -> bool {
+        self.smoke_test || self.validate_output || self.hashes_output()
+    }
+}
+
+impl AmendFiles {
+    fn new(
+        genesis_file_in: PathBuf,
+        genesis_file_out: PathBuf,
+        records_in: &RecordsSource,
+        records_out: RecordsSink,
+        options: &AmendOptions,
+    ) -> Result<(Self, RecordsSink), AmendGenesisError> {
+        let genesis_in_place = if in_place::same_file(&genesis_file_in, &genesis_file_out) {
+            Some(in_place::InPlaceOutput::new(&genesis_file_out).map_err(AmendGenesisError::Io)?)
+        } else {
+            None
+        };
+        let genesis_file_out = match &genesis_in_place {
+            Some(output) => output.tmp_path().to_path_buf(),
+            None => genesis_file_out,
+        };
+        let records_in_place = match (records_in, &records_out) {
+            (RecordsSource::Path(input), RecordsSink::Path(output))
+                if in_place::same_file(input, output) =>
+            {
+                Some(in_place::InPlaceOutput::new(output).map_err(AmendGenesisError::Io)?)
+            }
+            _ => None,
+        };
+        let records_out = match &records_in_place {
+            Some(output) => RecordsSink::Path(output.tmp_path().to_path_buf()),
+            None => records_out,
+        };
+        let records_file_in = match (records_in, options.validation_mode) {
+            (RecordsSource::Path(path), ValidationMode::Full) => Some(path.clone()),
+            _ => None,
+        };
+        let records_file_tally = match records_in {
+            RecordsSource::Path(path) if options.recompute_storage_usage => Some(path.clone()),
+            _ => None,
+        };
+        let records_file_out = match &records_out {
+            RecordsSink::Path(path) if options.reads_output_back() => Some(path.clone()),
+            _ => None,
+        };
+        let files = Self {
+            genesis_file_in,
+            genesis_file_out,
+            genesis_in_place,
+            records_in_place,
+            // gzip-compressed records are decompressed and compressed as they're streamed
+            records_in_name: records_in.to_string(),
+            records_out_name: records_out.to_string(),
+            records_file_in,
+            records_file_tally,
+            records_file_out,
+        };
+        Ok((files, records_out))
+    }
+}
+
+impl AmendOutput {
+    fn new(
+        records: records::RecordWriter<BufWriter<compression::RecordsOut>>,
+        options: &AmendOptions,
+    ) -> Result<Self, AmendGenesisError> {
+        let account_index = options
+            .account_index_out
+            .as_deref()
+            .map(index::AccountIndex::create)
+            .transpose()
+            .map_err(AmendGenesisError::Io)?;
+        let account_diff = options
+            .diff_file
+            .as_deref()
+            .map(diff::AccountDiff::create)
+            .transpose()
+            .map_err(AmendGenesisError::Io)?;
+        let summary_out = options
+            .summary_out
+            .clone()
+            .map(|path| progress::SummaryWriter::new(path, options.summary_interval));
+        Ok(Self {
+            records,
+            account_index,
+            account_diff,
+            summary_out,
+            outcome: AmendGenesisOutcome::default(),
+            total_supply: 0,
+            scaler: options.scale_balances.map(scale::BalanceScaler::new),
+        })
+    }
+}
+
+impl AmendGenesisConfig {
+    /// Goes through the phases of `amend()` in order: check the options, load the input
+    /// genesis, stream and filter the input records, build the validators, write the records
+    /// of the accounts that were held back, update the genesis config, run the checks on the
+    /// output, and write it.
+    pub(crate) fn run(
+        &self,
+        genesis_file_in: PathBuf,
+        genesis_file_out: PathBuf,
+        records_in: RecordsSource,
+        records_out: RecordsSink,
+        record_hook: Option<&mut dyn FnMut(StateRecord) -> RecordAction>,
+    ) -> Result<AmendGenesisOutcome, AmendGenesisError> {
+        self.check_options(&records_in, &records_out, record_hook.is_some())?;
+        let (files, records_out) = AmendFiles::new(
+            genesis_file_in,
+            genesis_file_out,
+            &records_in,
+            records_out,
+            &self.options,
+        )?;
+        let mut loaded = self.load_genesis(&files)?;
+        let (input, records) = self.open_records(&files, &loaded, records_in, records_out)?;
+        let mut accounts = self.prepare_accounts(&files, &loaded)?;
+        let mut out = AmendOutput::new(records, &self.options)?;
+        self.stream_records(input, &files, &mut loaded, &mut accounts, &mut out, record_hook)?;
+        self.build_validators(&files, &mut accounts, &mut out);
+        let balances_set = self.write_new_records(&loaded, &mut accounts, &mut out)?;
+        let genesis = self.update_genesis(loaded, &mut accounts, &mut out);
+        self.run_checks(&genesis, &accounts, &out, balances_set)?;
+        self.write(files, genesis, out, &accounts.validator_keys)
+    }
+
+    /// Fails on options that contradict each other or the inputs and outputs, before anything
+    /// is read or written.
+    fn check_options(
+        &self,
+        records_in: &RecordsSource,
+        records_out: &RecordsSink,
+        with_hook: bool,
+    ) -> Result<(), AmendGenesisError> {
+        let options = &self.options;
+        if options.dry_run
+            && (options.smoke_test
+                || options.validate_output
+                || options.account_index_out.is_some())
+        {
+            bail!(
+                Conflict,
+                "--dry-run can't be given together with --smoke-test, --validate-output or \
+                --account-index-out, since they need output files to be written"
+            );
+        }
+        if options.dry_run && options.hashes_output() {
+            bail!(
+                Conflict,
+                "--dry-run can't be given together with --emit-genesis-hash or --template-out, \
+                since there's no output to hash"
+            );
+        }
+        if options.smoke_test && records_out.path().is_some_and(compression::is_gzip_path) {
+            bail!(
+                Conflict,
+                "--smoke-test can't be given with a gzip-compressed --records-file-out, since \
+                nodes only read uncompressed records files"
+            );
+        }
+        if let (RecordsSource::Reader(_), ValidationMode::Full) =
+            (records_in, options.validation_mode)
+        {
+            bail!(
+                Conflict,
+                "--validation-mode full can't be used with records read from stdin, since the \
+                input records are read twice"
+            );
+        }
+        if with_hook && options.recompute_storage_usage {
+            bail!(
+                Conflict,
+                "--recompute-storage-usage can't be used with amend_genesis_with(), since the \
+                storage usage is tallied from the input records without the hook"
+            );
+        }
+        if let (RecordsSource::Reader(_), true) = (records_in, options.recompute_storage_usage) {
+            bail!(
+                Conflict,
+                "--recompute-storage-usage can't be used with records read from stdin, since the \
+                input records are read twice"
+            );
+        }
+        if let (RecordsSink::Writer(_), true) = (records_out, options.reads_output_back()) {
+            bail!(
+                Conflict,
+                "--smoke-test, --validate-output, --emit-genesis-hash and --template-out can't \
+                be used with records written to stdout, since they read the output records back"
+            );
+        }
+        if options.balance_treasury && options.expected_total_supply.is_none() {
+            bail!(Conflict, "--balance-treasury needs --expected-total-supply");
+        }
+        if options.generated_keys_out.is_some() && options.generate_accounts.is_none() {
+            bail!(Conflict, "--generated-keys-out needs --generate-accounts");
+        }
+        if options.generate_validator_keys && options.keys_out_dir.is_none() {
+            bail!(
+                Conflict,
+                "--generate-keys needs --keys-out-dir, or the generated secret keys would be lost"
+            );
+        }
+        if options.pretty && options.output_format == RecordsFormat::Ndjson {
+            bail!(
+                Conflict,
+                "--pretty can't be given with --output-format ndjson, which has each record on a \
+                single line"
+            );
+        }
+        if options.smoke_test && options.output_format == RecordsFormat::Ndjson {
+            bail!(
+                Conflict,
+                "--smoke-test can't be given with --output-format ndjson, since nodes only read \
+                records files holding a JSON array"
+            );
+        }
+        if let Some(r) = options.pledge_to_power {
+            if r < Rational32::from_integer(0) {
+                bail!(Validation, "--pledge-to-power ratio must not be negative, got {}", r);
+            }
+        }
+        if let Some(r) = options.scale_balances {
+            if r <= Rational32::from_integer(0) {
+                bail!(Validation, "--scale-balances ratio must be above 0, got {}", r);
+            }
+        }
+        if let Some(r) = self.genesis_changes.max_inflation_rate {
+            if r < Rational32::from_integer(0) || r > Rational32::from_integer(1) {
+                bail!(Validation, "max_inflation_rate must be between 0 and 1, got {}", r);
+            }
+        }
+        if self.shard_layout_file.is_some() && self.shard_layout.is_some() {
+            bail!(Conflict, "--shard-layout-file and --shard-layout can't be given together");
+        }
+        if (options.append_validators || self.validators.is_none())
+            && options.extract_shard.is_some()
+        {
+            bail!(
+                Conflict,
+                "--extract-shard needs the validators to be given with --validators, without \
+                --append-validators, since it drops the ones whose accounts are in other shards"
+            );
+        }
+        if options.generate_validator_keys && self.validators.is_none() {
+            bail!(
+                Conflict,
+                "--generate-keys needs the validators to give keys to in --validators"
+            );
+        }
+        if options.extract_shard.is_some()
+            && (self.shard_layout_file.is_some() || self.shard_layout.is_some())
+        {
+            bail!(
+                Conflict,
+                "--extract-shard can't be given together with --shard-layout-file or \
+                --shard-layout"
+            );
+        }
+        if options.remove_accounts.is_some() && options.keep_only_accounts.is_some() {
+            bail!(Conflict, "--remove-accounts and --keep-only-accounts can't be given together");
+        }
+        if options.strip_contracts && !options.truncate_contract_state.is_empty() {
+            bail!(
+                Conflict,
+                "--strip-contracts and --truncate-contract-state can't be given together"
+            );
+        }
+        if options.strip_contracts && options.replace_contract_code.is_some() {
+            bail!(
+                Conflict,
+                "--replace-contract-code and --strip-contracts can't be given together"
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads the input genesis config, and works out the chain ID, storage parameters and
+    /// shard layout of the output.
+    fn load_genesis(&self, files: &AmendFiles) -> Result<LoadedGenesis, AmendGenesisError> {
+        let options = &self.options;
+        let genesis_changes = &self.genesis_changes;
+        let genesis = Genesis::from_file(&files.genesis_file_in, GenesisValidationMode::UnsafeFast)
+            .map_err(|e| AmendGenesisError::InputParse(e.into()))?;
+        let chain_id = chain_id::output_chain_id(
+            &genesis.config.chain_id,
+            genesis_changes.chain_id.as_deref(),
+            genesis_changes.chain_id_suffix.as_deref(),
+            Utc::now().date_naive(),
+        )
+        .map_err(AmendGenesisError::Conflict)?;
+        if let Some(records_file_in) = &files.records_file_in {
+            validate::validate_genesis(&genesis.config, records_file_in)
+                .context("the input genesis failed validation")
+                .map_err(AmendGenesisError::Validation)?;
+        }
+
+        let protocol_version =
+            genesis_changes.protocol_version.unwrap_or(genesis.config.protocol_version);
+        let (num_bytes_account, num_extra_bytes_record) = if options.storage_params_from_protocol {
+            checks::storage_params_for_protocol(protocol_version)
+        } else {
+            (self.num_bytes_account, self.num_extra_bytes_record)
+        };
+        if options.check_storage_params {
+            checks::check_storage_params(
+                protocol_version,
+                num_bytes_account,
+                num_extra_bytes_record,
+            )
+            .map_err(AmendGenesisError::Conflict)?;
+        }
+
+        let shard_layout = match (&self.shard_layout_file, self.shard_layout) {
+            (Some(path), _) => {
+                let s = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed reading shard layout file {}", path.display()))
+                    .map_err(AmendGenesisError::Io)?;
+                Some(
+                    serde_json::from_str::<ShardLayout>(&s)
+                        .context("failed deserializing --shard-layout-file")
+                        .map_err(AmendGenesisError::InputParse)?,
+                )
+            }
+            (None, Some(named)) => Some(named.shard_layout()),
+            (None, None) => None,
+        };
+        if let Some(protocol_version) = genesis_changes.protocol_version {
+            // --extract-shard always writes a single shard
+            let single_shard = ShardLayout::v0_single_shard();
+            let output_layout = if options.extract_shard.is_some() {
+                &single_shard
+            } else {
+                shard_layout.as_ref().unwrap_or(&genesis.config.shard_layout)
+            };
+            let default_storage_params = (!options.storage_params_from_protocol
+                && (num_bytes_account, num_extra_bytes_record)
+                    == (DEFAULT_NUM_BYTES_ACCOUNT, DEFAULT_NUM_EXTRA_BYTES_RECORD))
+                .then_some((num_bytes_account, num_extra_bytes_record));
+            let problems = checks::protocol_version_problems(
+                protocol_version,
+                output_layout,
+                default_storage_params,
+            );
+            if !problems.is_empty() {
+                bail!(
+                    Conflict,
+                    "--protocol-version {} doesn't support the output genesis:\n{}",
+                    protocol_version,
+                    problems.join("\n")
+                );
+            }
+        }
+
+        let shard_filter = options
+            .extract_shard
+            .map(|shard_id| {
+                extract::ShardFilter::new(shard_id, genesis.config.shard_layout.clone())
+            })
+            .transpose()
+            .map_err(AmendGenesisError::Conflict)?;
+        let shard_layout = if shard_filter.is_some() {
+            Some(ShardLayout::v0_single_shard())
+        } else {
+            shard_layout
+        };
+        let num_shards =
+            shard_layout.as_ref().unwrap_or(&genesis.config.shard_layout).shard_ids().count();
+        for (flag, seats) in [
+            (
+                "--num-block-producer-seats-per-shard",
+                &genesis_changes.num_block_producer_seats_per_shard,
+            ),
+            (
+                "--avg-hidden-validator-seats-per-shard",
+                &genesis_changes.avg_hidden_validator_seats_per_shard,
+            ),
+        ] {
+            if let Some(seats) = seats {
+                checks::check_seats_per_shard(flag, seats, num_shards)
+                    .map_err(AmendGenesisError::Conflict)?;
+            }
+        }
+        let storage = StorageParams { num_bytes_account, num_extra_bytes_record };
+        Ok(LoadedGenesis { genesis, chain_id, storage, shard_layout, shard_filter })
+    }
+
+    /// Opens the input records, and the output records they're streamed to.
+    fn open_records(
+        &self,
+        files: &AmendFiles,
+        loaded: &LoadedGenesis,
+        records_in: RecordsSource,
+        records_out: RecordsSink,
+    ) -> Result<
+        (RecordsInput, records::RecordWriter<BufWriter<compression::RecordsOut>>),
+        AmendGenesisError,
+    > {
+        let options = &self.options;
+        let records_in_size =
+            records_in.path().and_then(|path| std::fs::metadata(path).ok()).map(|m| m.len());
+        let progress = progress::ProgressReporter::new(options.progress_interval, records_in_size);
+        let reader = records_in
+            .open(progress.bytes_read())
+            .with_context(|| format!("Failed opening input records {}", files.records_in_name))
+            .map_err(AmendGenesisError::Io)?;
+        let records_out = if options.dry_run {
+            compression::RecordsOut::Sink
+        } else {
+            records_out
+                .create()
+                .with_context(|| {
+                    format!("Failed opening output records {}", files.records_out_name)
+                })
+                .map_err(AmendGenesisError::Io)?
+        };
+        let mut records = records::RecordWriter::new(
+            BufWriter::new(records_out),
+            options.output_format,
+            options.pretty,
+        );
+        if let Some(dir) = &options.records_out_dir {
+            let Some(shard_layout) = &loaded.shard_layout else {
+                bail!(
+                    Conflict,
+                    "--records-out-dir needs --shard-layout-file or --shard-layout to split the \
+                    records by"
+                );
+            };
+            if !options.dry_run {
+                let split = split::SplitRecords::create(
+                    dir,
+                    shard_layout.clone(),
+                    options.output_format,
+                    options.pretty,
+                )
+                .map_err(AmendGenesisError::Io)?;
+                records.split(split);
+            }
+        }
+        if options.empty_shard_policy != EmptyShardPolicy::Allow {
+            let shard_layout =
+                loaded.shard_layout.as_ref().unwrap_or(&loaded.genesis.config.shard_layout);
+            records.count_shard_accounts(checks::ShardAccountCounts::new(shard_layout.clone()));
+        }
+        Ok((RecordsInput { reader, progress }, records))
+    }
+
+    /// Reads the validators, --extra-records and the other per-account options, which decide
+    /// which input records are changed or held back while they're streamed.
+    fn prepare_accounts(
+        &self,
+        files: &AmendFiles,
+        loaded: &LoadedGenesis,
+    ) -> Result<Accounts, AmendGenesisError> {
+        let options = &self.options;
+        let genesis = &loaded.genesis;
+        let StorageParams { num_bytes_account, num_extra_bytes_record } = loaded.storage;
+        let renames = options
+            .rename_accounts
+            .as_deref()
+            .map(rename::AccountRenames::from_file)
+            .transpose()
+            .map_err(AmendGenesisError::InputParse)?;
+        let (mut validators, mut validator_keys) = match &self.validators {
+            Some(path) => parse_validators(path)?,
+            None => (vec![], keys::ValidatorKeys::default()),
+        };
+        if let Some(filter) = &loaded.shard_filter {
+            filter.filter_validators(&mut validators).map_err(AmendGenesisError::Conflict)?;
+        }
+        if options.generate_validator_keys {
+            validator_keys = keys::ValidatorKeys::generate(&mut validators);
+        }
+        let kept_validators = if options.append_validators || self.validators.is_none() {
+            for v in validators.iter() {
+                if genesis.config.validators.iter().any(|old| old.account_id == v.account_id) {
+                    bail!(
+                        Conflict,
+                        "validator {} given in --validators is already a validator in the input \
+                        genesis, which --append-validators keeps",
+                        &v.account_id
+                    );
+                }
+            }
+            genesis.config.validators.clone()
+        } else {
+            vec![]
+        };
+        let mut account_filter = match (&options.remove_accounts, &options.keep_only_accounts) {
+            (Some(path), _) => Some(
+                remove::AccountFilter::remove_from_file(path)
+                    .map_err(AmendGenesisError::InputParse)?,
+            ),
+            (None, Some(path)) => Some(
+                remove::AccountFilter::keep_only_from_file(path)
+                    .map_err(AmendGenesisError::InputParse)?,
+            ),
+            (None, None) => None,
+        };
+        if let Some(filter) = &account_filter {
+            filter.check_validators(&validators).map_err(AmendGenesisError::Conflict)?;
+            filter.check_validators(&kept_validators).map_err(AmendGenesisError::Conflict)?;
+        }
+        let mut interners = intern::Interners::default();
+        let wanted = wanted_records(
+            &mut validators,
+            &self.extra_records,
+            num_bytes_account,
+            options.keep_existing_power,
+            options.pledge_conflict_policy,
+            &mut interners,
+        )?;
+        let balance_overrides = options
+            .balance_overrides
+            .as_deref()
+            .map(overrides::BalanceOverrides::from_file)
+            .transpose()
+            .map_err(AmendGenesisError::InputParse)?;
+        if let Some(overrides) = &balance_overrides {
+            let given_both = wanted
+                .iter()
+                .filter(|(account_id, a)| a.balance_given && overrides.contains(account_id))
+                .map(|(account_id, _)| account_id)
+                .min();
+            if let Some(account_id) = given_both {
+                bail!(
+                    Conflict,
+                    "{} is given a balance in both --balance-overrides and --extra-records",
+                    account_id
+                );
+            }
+        }
+
+        let treasury_account = self
+            .genesis_changes
+            .protocol_treasury_account
+            .clone()
+            .unwrap_or_else(|| genesis.config.protocol_treasury_account.clone());
+        let treasury_exists = wanted.contains_key(&treasury_account);
+        if let Some(filter) = &mut account_filter {
+            filter.keep_also(
+                wanted
+                    .keys()
+                    .map(|account_id| AccountId::clone(account_id))
+                    .chain(kept_validators.iter().map(|v| v.account_id.clone()))
+                    .chain([treasury_account.clone()]),
+            );
+        }
+        let chipless_validators = validators
+            .iter()
+            .chain(kept_validators.iter())
+            .map(|v| v.account_id.clone())
+            .filter(|account_id| !wanted.get(account_id).is_some_and(|a| a.has_rsa_keys()))
+            .collect::<HashSet<_>>();
+
+        if options.strip_contracts {
+            let mut with_contracts = wanted
+                .iter()
+                .filter(|(_, r)| {
+                    r.code.is_some()
+                        || !r.data.is_empty()
+                        || r.code_hash.is_some_and(|h| h != CryptoHash::default())
+                })
+                .map(|(account_id, _)| account_id)
+                .collect::<Vec<_>>();
+            with_contracts.sort();
+            if let Some(account_id) = with_contracts.first() {
+                bail!(
+                    Conflict,
+                    "--strip-contracts leaves out all contract code and data, but \
+                    --extra-records gives some for {}",
+                    account_id
+                );
+            }
+        }
+        let replacement_code = match &options.replace_contract_code {
+            Some(path) => {
+                let code = std::fs::read(path)
+                    .with_context(|| format!("failed reading {}", path.display()))
+                    .map_err(AmendGenesisError::Io)?;
+                let code_hash = hash(&code);
+                tracing::info!(
+                    target: "amend-genesis",
+                    "replacing all contract code with the {} bytes in {}, with hash {}",
+                    code.len(),
+                    path.display(),
+                    code_hash
+                );
+                Some((code, code_hash))
+            }
+            None => None,
+        };
+        if let Some(generation) = &options.generate_accounts {
+            let mut generated =
+                wanted.keys().filter(|a| generation.generates(a)).collect::<Vec<_>>();
+            generated.sort();
+            if let Some(account_id) = generated.first() {
+                bail!(
+                    Conflict,
+                    "{} is given in --validators or --extra-records, but is also one of the \
+                    accounts --generate-accounts adds",
+                    account_id
+                );
+            }
+        }
+        let truncator = if options.truncate_contract_state.is_empty() {
+            None
+        } else {
+            Some(
+                truncate::ContractStateTruncator::new(&options.truncate_contract_state)
+                    .map_err(AmendGenesisError::Conflict)?,
+            )
+        };
+
+        let storage_tally = match &files.records_file_tally {
+            Some(path) => {
+                Some(storage::StorageTally::from_file(path, num_extra_bytes_record, |r| {
+                    if let Some(renames) = &renames {
+                        renames.rename_owner(r);
+                    }
+                    match r {
+                        StateRecord::AccessKey { account_id, public_key, access_key } => {
+                            // written with the access key given in --validators or
+                            // --extra-records
+                            let new_key =
+                                wanted.get(account_id).and_then(|a| a.keys.get(public_key));
+                            if let Some(new_key) = new_key {
+                                *access_key = new_key.clone();
+                            }
+                            true
+                        }
+                        StateRecord::Contract { .. } | StateRecord::Data { .. } => {
+                            !options.strip_contracts
+                        }
+                        // replaced by the chip given in --extra-records
+                        StateRecord::Rsa2048Keys { account_id, public_key, .. } => {
+                            !wanted.get(account_id).is_some_and(|a| a.has_rsa_key(public_key))
+                        }
+                        _ => true,
+                    }
+                })?)
+            }
+            None => None,
+        };
+
+        Ok(Accounts {
+            validators,
+            validator_keys,
+            kept_validators,
+            chipless_validators,
+            interners,
+            wanted,
+            renames,
+            account_filter,
+            balance_overrides,
+            treasury_account,
+            treasury_exists,
+            stripped: HashMap::new(),
+            replacement_code,
+            num_replaced_not_adjusted: 0,
+            truncator,
+            storage_tally,
+            nonce_check: self.genesis_changes.genesis_height.map(checks::NonceHeightCheck::new),
+            duplicates: dedupe::DuplicateRecords::new(),
+        })
+    }
+
+    /// Streams the input records to the output, filtering and changing them on the way, and
+    /// holding back the ones of the accounts in `accounts.wanted`.
+    fn stream_records(
+        &self,
+        input: RecordsInput,
+        files: &AmendFiles,
+        loaded: &mut LoadedGenesis,
+        accounts: &mut Accounts,
+        out: &mut AmendOutput,
+        mut record_hook: Option<&mut dyn FnMut(StateRecord) -> RecordAction>,
+    ) -> Result<(), AmendGenesisError> {
+        let options = &self.options;
+        let records_in_name = &files.records_in_name;
+        let StorageParams { num_bytes_account, num_extra_bytes_record } = loaded.storage;
+        let shard_filter = &mut loaded.shard_filter;
+        let Accounts {
+            kept_validators,
+            chipless_validators,
+            interners,
+            wanted,
+            renames,
+            account_filter,
+            balance_overrides,
+            treasury_account,
+            treasury_exists,
+            stripped,
+            replacement_code,
+            num_replaced_not_adjusted,
+            truncator,
+            storage_tally,
+            nonce_check,
+            duplicates,
+            ..
+        } = accounts;
+        let AmendOutput {
+            records,
+            account_index,
+            account_diff,
+            summary_out,
+            outcome,
+            total_supply,
+            scaler,
+        } = out;
+        let RecordsInput { reader, progress: mut input_progress } = input;
+        let generation = options.generate_accounts.as_ref();
+        let mut stats = RecordStats::default();
+
+        outcome.summary.phase = AmendPhase::ProcessingRecords;
+        if let Some(writer) = summary_out.as_mut() {
+            writer.write(&outcome.summary).map_err(AmendGenesisError::Io)?;
+        }
+        records::stream_records(reader, |r| {
+            stats.add(&r);
+            if let Some(delay) = options.record_delay {
+                std::thread::sleep(delay);
+            }
+            outcome.summary.records_processed += 1;
+            input_progress.record();
+            if let Some(writer) = summary_out.as_mut() {
+                if let records::InputRecord::Known(r) = &r {
+                    outcome.summary.last_account_id = Some(state_record_to_account_id(r).clone());
+                }
+                writer.maybe_write(&mut outcome.summary, *total_supply)?;
+            }
+            let r = match r {
+                records::InputRecord::Known(r) => r,
+                records::InputRecord::Unknown { tag, value } => {
+                    if options.strict_record_types {
+                        bail!(
+                            Validation,
+                            "unknown record type {} found in {} with --strict-record-types set",
+                            tag,
+                            records_in_name
+                        );
+                    }
+                    // we don't know what's in here, so it doesn't count towards the total supply
+                    records.write_unknown(tag, &value)?;
+                    return Ok(());
+                }
+            };
+            if duplicates.check(&r) {
+                // without --dedupe-input this fails once all of them are found
+                if options.dedupe_input {
+                    outcome.summary.duplicate_records_dropped += 1;
+                }
+                return Ok(());
+            }
+            let (kept, replacements) = match &mut record_hook {
+                Some(hook) => {
+                    // the diff has to be told about an account the hook drops
+                    let input_account = (account_diff.is_some()
+                        && matches!(r, StateRecord::Account { .. }))
+                    .then(|| r.clone());
+                    match hook(r) {
+                        RecordAction::Keep(r) => (Some(r), Vec::new()),
+                        RecordAction::Drop => {
+                            if let (Some(diff), Some(input)) =
+                                (account_diff.as_mut(), &input_account)
+                            {
+                                diff.removed(input).map_err(AmendGenesisError::Io)?;
+                            }
+                            (None, Vec::new())
+                        }
+                        RecordAction::Replace(replacements) => (None, replacements),
+                    }
+                }
+                None => (Some(r), Vec::new()),
+            };
+            for mut r in kept.into_iter().chain(replacements) {
+                if shard_filter.as_mut().is_some_and(|filter| !filter.keeps(&r)) {
+                    if let Some(diff) = account_diff.as_mut() {
+                        diff.removed(&r).map_err(AmendGenesisError::Io)?;
+                    }
+                    continue;
+                }
+                if let Some(renames) = renames.as_mut() {
+                    renames.apply(&mut r).map_err(AmendGenesisError::Conflict)?;
+                }
+                if let Some(filter) = account_filter.as_mut() {
+                    if filter.removes(&r) {
+                        if let Some(diff) = account_diff.as_mut() {
+                            diff.removed(&r).map_err(AmendGenesisError::Io)?;
+                        }
+                        continue;
+                    }
+                }
+                match &mut r {
+                    StateRecord::AccessKey { account_id, public_key, access_key } => {
+                        if let Some(a) = wanted.get_mut(account_id) {
+                            if let Some(new_key) = a.keys.remove(public_key) {
+                                if new_key != *access_key {
+                                    a.keys_replaced.push(public_key.clone());
+                                }
+                                *access_key = new_key;
+                                outcome.summary.access_keys_injected += 1;
+                            }
+                        }
+                        if let Some(check) = nonce_check.as_mut() {
+                            check.add(account_id, access_key);
+                        }
+                        records.write(&r)?;
+                    }
+                    StateRecord::Account { account_id, account } => {
+                        if *account_id == *treasury_account {
+                            *treasury_exists = true;
+                        }
+                        if generation.is_some_and(|g| g.generates(account_id)) {
+                            bail!(
+                                Conflict,
+                                "{} exists in the input records, but is also one of the accounts \
+                                --generate-accounts adds",
+                                account_id
+                            );
+                        }
+                        // compared against in the --diff-file
+                        let input = account_diff.is_some().then(|| account.clone());
+                        if let Some(overrides) = balance_overrides.as_mut() {
+                            overrides.apply(account_id, account);
+                        }
+                        // without --validators no pledge is returned to its balance
+                        let kept = self.validators.is_none()
+                            || kept_validators.iter().any(|v| &v.account_id == account_id);
+                        // held back like the accounts in --extra-records, to be written with
+                        // the storage usage of the dropped contract state taken out
+                        let has_code = account.code_hash() != CryptoHash::default();
+                        // the treasury account is written last when scaling balances, to be
+                        // given the rounding dust
+                        if truncator.as_ref().is_some_and(|t| t.truncates(account_id))
+                            || (has_code
+                                && (options.strip_contracts || replacement_code.is_some()))
+                            || ((scaler.is_some() || options.balance_treasury)
+                                && *account_id == *treasury_account)
+                        {
+                            wanted.entry(interners.accounts.intern(account_id)).or_default();
+                        }
+                        if let Some(acc) = wanted.get_mut(account_id) {
+                            acc.update_from_existing(account);
+                            if input.is_some() {
+                                acc.input = input;
+                            }
+                            if storage_tally.is_some() {
+                                // the tally of its other records is added when it's written
+                                acc.account.as_mut().unwrap().set_storage_usage(num_bytes_account);
+                            }
+                            if kept {
+                                acc.keep_pledge(account);
+                            }
+                            if options.strip_contracts {
+                                acc.account.as_mut().unwrap().set_code_hash(CryptoHash::default());
+                            }
+                            if let Some((_, code_hash)) = replacement_code.as_ref() {
+                                // an account given code in --extra-records keeps it
+                                if has_code && acc.code.is_none() {
+                                    acc.account.as_mut().unwrap().set_code_hash(*code_hash);
+                                }
+                            }
+                        } else {
+                            if let Some(tally) = storage_tally.as_mut() {
+                                account
+                                    .set_storage_usage(num_bytes_account + tally.take(account_id));
+                            }
+                            if account.pledging() != 0 && !kept {
+                                account.set_amount(account.amount() + account.pledging());
+                                account.set_pledging(0);
+                            }
+                            if let Some(scaler) = scaler.as_mut() {
+                                scaler.scale_account(account);
+                            }
+                            *total_supply += account.amount() + account.pledging();
+                            if let (Some(diff), Some(input)) = (account_diff.as_mut(), &input) {
+                                diff.account(account_id, Some(input), account, vec![], vec![])
+                                    .map_err(AmendGenesisError::Io)?;
+                            }
+                            outcome.summary.add_account(AccountStatus::PassedThrough);
+                            if let Some(index) = account_index.as_mut() {
+                                index
+                                    .write(account_id, account, None, AccountStatus::PassedThrough)
+                                    .map_err(AmendGenesisError::Io)?;
+                            }
+                            records.write(&r)?;
+                        }
+                    }
+                    StateRecord::Contract { account_id, code } if options.strip_contracts => {
+                        *stripped.entry(account_id.clone()).or_default() += code.len() as u64;
+                    }
+                    StateRecord::Data { account_id, data_key, value }
+                        if options.strip_contracts =>
+                    {
+                        *stripped.entry(account_id.clone()).or_default() +=
+                            (data_key.len() + value.len()) as u64 + num_extra_bytes_record;
+                        outcome.summary.contract_keys_dropped += 1;
+                        outcome.summary.contract_bytes_dropped +=
+                            (data_key.len() + value.len()) as u64;
+                    }
+                    StateRecord::Contract { account_id, code } => {
+                        if let Some(records) = wanted.get_mut(account_id) {
+                            if records.code.is_some() {
+                                bail!(
+                                    Conflict,
+                                    "contract code for {} given in both the input records and \
+                                    --extra-records",
+                                    account_id
+                                );
+                            }
+                            if let Some((new_code, _)) = replacement_code.as_ref() {
+                                records.state_dropped += code.len() as u64;
+                                records.code = Some(new_code.clone());
+                                outcome.summary.contracts_replaced += 1;
+                            } else {
+                                records.push_extra_record(r);
+                            }
+                        } else {
+                            if let Some((new_code, _)) = replacement_code.as_ref() {
+                                *code = new_code.clone();
+                                outcome.summary.contracts_replaced += 1;
+                                *num_replaced_not_adjusted += 1;
+                            }
+                            records.write(&r)?;
+                        }
+                    }
+                    StateRecord::Data { .. } => {
+                        if truncator.as_mut().map_or(true, |t| t.apply(&mut r)) {
+                            records.write(&r)?;
+                        }
+                    }
+                    StateRecord::Rsa2048Keys { account_id, public_key, rsa_key } => {
+                        chipless_validators.remove(account_id);
+                        match wanted.get_mut(account_id) {
+                            Some(a) if a.has_rsa_key(public_key) => {
+                                // with --recompute-storage-usage it was never tallied
+                                if storage_tally.is_none() {
+                                    a.state_dropped += borsh::object_length(rsa_key).unwrap()
+                                        as u64
+                                        + num_extra_bytes_record;
+                                }
+                            }
+                            _ => records.write(&r)?,
+                        }
+                    }
+                    // the receipts of accounts that are kept are written as they are, even if
+                    // the account's keys or balance are changed
+                    StateRecord::PostponedReceipt(_)
+                    | StateRecord::DelayedReceipt(_)
+                    | StateRecord::ReceivedData { .. } => {
+                        records.write(&r)?;
+                    }
+                };
+            }
+            Ok(())
+        })
+        .map_err(|e| {
+            AmendGenesisError::from_stream_error(e)
+                .context(format!("Failed processing records from {}", records_in_name))
+        })?;
+        input_progress.finish();
+        if duplicates.num_duplicates() > 0 {
+            if !options.dedupe_input {
+                bail!(
+                    Validation,
+                    "{} has {} duplicate Account or AccessKey records: {} (use --dedupe-input to \
+                    keep only the first of each)",
+                    records_in_name,
+                    duplicates.num_duplicates(),
+                    duplicates.list()
+                );
+            }
+            tracing::warn!(
+                target: "amend-genesis",
+                "left out {} duplicate records in {}: {}",
+                duplicates.num_duplicates(),
+                records_in_name,
+                duplicates.list()
+            );
+        }
+        stats.log();
+        if let Some(renames) = renames.as_ref() {
+            renames.log();
+        }
+        if let Some(filter) = account_filter.as_ref() {
+            filter.log();
+            outcome.summary.receipts_dropped = filter.num_receipts();
+        }
+        if let Some(filter) = shard_filter.as_ref() {
+            filter.log();
+        }
+        if let Some(overrides) = balance_overrides.as_ref() {
+            overrides.log();
+            let missing = overrides.missing();
+            if !missing.is_empty() {
+                let missing = missing.iter().map(|a| a.as_str()).collect::<Vec<_>>().join(", ");
+                if options.strict_overrides {
+                    bail!(
+                        Validation,
+                        "accounts given in --balance-overrides aren't in {}: {}",
+                        records_in_name,
+                        missing
+                    );
+                }
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "accounts given in --balance-overrides aren't in {}, so their balances \
+                    weren't set: {}",
+                    records_in_name,
+                    missing
+                );
+            }
+        }
+
+        if !*treasury_exists {
+            if self.genesis_changes.protocol_treasury_account.is_some() {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "protocol treasury account {} doesn't exist in the input records or extra \
+                    records, adding it with a balance of {}",
+                    treasury_account,
+                    options.protocol_treasury_balance
+                );
+                wanted.insert(
+                    interners.accounts.intern(treasury_account),
+                    AccountRecords::new(options.protocol_treasury_balance, 0, 0, num_bytes_account),
+                );
+            } else {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "protocol treasury account {} doesn't exist in the output records, set one \
+                    that does or have it created with --protocol-treasury-account",
+                    treasury_account
+                );
+            }
+        }
+
+        // with --recompute-storage-usage the contract state left out was never tallied
+        if options.strip_contracts && storage_tally.is_none() {
+            // accounts whose Account record was already written when their contract state
+            // turned up, which can only happen for Data records of accounts without code
+            let mut num_not_lowered = 0;
+            for (account_id, bytes) in std::mem::take(stripped) {
+                match wanted.get_mut(&account_id) {
+                    Some(account_records) => account_records.state_dropped += bytes,
+                    None => num_not_lowered += 1,
+                }
+            }
+            if num_not_lowered > 0 {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "left out the Data records of {} accounts with no contract code, but \
+                    couldn't lower their storage usage since they were already written",
+                    num_not_lowered
+                );
+            }
+        }
+        if *num_replaced_not_adjusted > 0 {
+            tracing::warn!(
+                target: "amend-genesis",
+                "replaced the code of {} Contract records whose accounts have no code_hash or no \
+                Account record, so their code_hash and storage usage couldn't be changed",
+                num_replaced_not_adjusted
+            );
+        }
+        for dropped in truncator.take().map(|t| t.finish()).unwrap_or_default() {
+            tracing::info!(
+                target: "amend-genesis",
+                "truncated the contract state of {}, dropping {} keys and {} bytes",
+                &dropped.account_id,
+                dropped.keys,
+                dropped.bytes
+            );
+            outcome.summary.contract_keys_dropped += dropped.keys;
+            outcome.summary.contract_bytes_dropped += dropped.bytes;
+            match wanted.get_mut(&dropped.account_id) {
+                Some(account_records) => {
+                    account_records.state_dropped +=
+                        dropped.bytes + dropped.keys * num_extra_bytes_record;
+                    account_records.extra_records.extend(dropped.held);
+                }
+                // no Account record in the input, so there's no storage usage to lower
+                None => {
+                    for r in dropped.held.iter() {
+                        records.write(r)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the power of the validators from their pledges with --pledge-to-power, once their
+    /// pledges are known, and warns about the ones with power but no chip.
+    fn build_validators(&self, files: &AmendFiles, accounts: &mut Accounts, out: &mut AmendOutput) {
+        if let Some(ratio) = self.options.pledge_to_power {
+            for validator in accounts.validators.iter_mut() {
+                validator.power = pledge_to_power(validator.pledging, ratio);
+                // validator_records() made sure there's an account
+                let records = accounts.wanted.get_mut(&validator.account_id).unwrap();
+                records.account.as_mut().unwrap().set_power(validator.power);
+                tracing::info!(
+                    target: "amend-genesis",
+                    "converted pledge {} of {} to power {}",
+                    validator.pledging,
+                    validator.account_id,
+                    validator.power
+                );
+            }
+        }
+
+        let mut without_chips = accounts
+            .validators
+            .iter()
+            .chain(accounts.kept_validators.iter())
+            .filter(|v| v.power > 0 && accounts.chipless_validators.contains(&v.account_id))
+            .map(|v| v.account_id.clone())
+            .collect::<Vec<_>>();
+        if !without_chips.is_empty() {
+            without_chips.sort();
+            let names = without_chips.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+            tracing::warn!(
+                target: "amend-genesis",
+                "validators with power have no Rsa2048Keys record in {} or --extra-records, so \
+                they won't be able to prove their power: {}",
+                files.records_in_name,
+                names.join(", ")
+            );
+        }
+        out.outcome.validators_without_chips = without_chips;
+    }
+
+    /// Writes the accounts held back while streaming the input records, followed by the ones
+    /// added with --generate-accounts. Returns the total balances of the accounts whose
+    /// balance was set from --validators or --extra-records, listed if the total supply turns
+    /// out wrong.
+    fn write_new_records(
+        &self,
+        loaded: &LoadedGenesis,
+        accounts: &mut Accounts,
+        out: &mut AmendOutput,
+    ) -> Result<Vec<(AccountId, Balance)>, AmendGenesisError> {
+        let options = &self.options;
+        let StorageParams { num_bytes_account, num_extra_bytes_record } = loaded.storage;
+        let treasury_account = &accounts.treasury_account;
+        let generation = options.generate_accounts.as_ref();
+        if let Some(tally) = &mut accounts.storage_tally {
+            for (account_id, account_records) in accounts.wanted.iter_mut() {
+                account_records.storage_tallied = tally.take(account_id);
+            }
+        }
+
+        // dropped first so that the account IDs are taken back out of their handles without
+        // being copied
+        drop(std::mem::take(&mut accounts.interners));
+        // sorted so that the output records are the same on every run with the same inputs
+        let mut wanted = std::mem::take(&mut accounts.wanted)
+            .into_iter()
+            .map(|(account_id, account_records)| (account_id.into_inner(), account_records))
+            .collect::<Vec<_>>();
+        wanted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if out.scaler.is_some() || options.balance_treasury {
+            if let Some(i) = wanted.iter().position(|(a, _)| a == treasury_account) {
+                let treasury = wanted.remove(i);
+                wanted.push(treasury);
+            }
+        }
+        // the generated accounts are written after the treasury, so it's balanced without them
+        let generated_supply =
+            generation.map_or(0, |g| g.balance.saturating_mul(g.count as Balance));
+        let treasury_balance_to = options
+            .expected_total_supply
+            .filter(|_| options.balance_treasury)
+            .map(|expected| expected.saturating_sub(generated_supply));
+        let mut balances_set = vec![];
+        out.outcome.summary.phase = AmendPhase::WritingNewRecords;
+        for (account_id, account_records) in wanted {
+            let absorbs_dust = account_id == *treasury_account;
+            let balance_to = treasury_balance_to.filter(|_| absorbs_dust);
+            let balance_set = (account_records.balance_given
+                || accounts.validators.iter().any(|v| v.account_id == account_id))
+            .then(|| (account_id.clone(), out.total_supply));
+            account_records.write_out(
+                account_id,
+                out,
+                options,
+                loaded.storage,
+                absorbs_dust,
+                balance_to,
+            )?;
+            if let Some((account_id, supply_before)) = balance_set {
+                balances_set.push((account_id, out.total_supply - supply_before));
+            }
+            if let Some(writer) = &mut out.summary_out {
+                writer.maybe_write(&mut out.outcome.summary, out.total_supply)?;
+            }
+        }
+        if let Some(scaler) = &mut out.scaler {
+            let dust = scaler.take_dust();
+            if dust > 0 {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "no protocol treasury account {} was written to give the {} rounded away \
+                    when scaling balances to, so the total supply is that much lower",
+                    treasury_account,
+                    dust
+                );
+            }
+        }
+
+        if let Some(generation) = generation {
+            let mut keys_file = match &options.generated_keys_out {
+                Some(path) if !options.dry_run => {
+                    Some(generate::KeysFileWriter::create(path).map_err(AmendGenesisError::Io)?)
+                }
+                _ => None,
+            };
+            // made one at a time, since there may be millions
+            for generated in generation.accounts() {
+                let access_key = AccessKey::full_access();
+                let key_bytes = borsh::object_length(&access_key).unwrap() as u64;
+                let storage_usage = num_bytes_account
+                    + generated
+                        .keys
+                        .iter()
+                        .map(|k| k.public_key().len() as u64 + key_bytes + num_extra_bytes_record)
+                        .sum::<u64>();
+                let account =
+                    Account::new(generation.balance, 0, 0, CryptoHash::default(), storage_usage);
+                out.total_supply += generation.balance;
+                out.outcome.summary.add_account(AccountStatus::Added);
+                if let Some(index) = &mut out.account_index {
+                    let num_keys = Some(generated.keys.len() as u64);
+                    index
+                        .write(&generated.account_id, &account, num_keys, AccountStatus::Added)
+                        .map_err(AmendGenesisError::Io)?;
+                }
+                if let Some(diff) = &mut out.account_diff {
+                    let keys_added = generated.keys.iter().map(|k| k.public_key()).collect();
+                    diff.account(&generated.account_id, None, &account, keys_added, vec![])
+                        .map_err(AmendGenesisError::Io)?;
+                }
+                out.records.write(&StateRecord::Account {
+                    account_id: generated.account_id.clone(),
+                    account,
+                })?;
+                for secret_key in generated.keys.iter() {
+                    out.records.write(&StateRecord::AccessKey {
+                        account_id: generated.account_id.clone(),
+                        public_key: secret_key.public_key(),
+                        access_key: access_key.clone(),
+                    })?;
+                }
+                if let Some(keys_file) = &mut keys_file {
+                    keys_file.write(&generated).map_err(AmendGenesisError::Io)?;
+                }
+                if let Some(writer) = &mut out.summary_out {
+                    writer.maybe_write(&mut out.outcome.summary, out.total_supply)?;
+                }
+            }
+            if let Some(keys_file) = keys_file {
+                keys_file.finish().map_err(AmendGenesisError::Io)?;
+            }
+            tracing::info!(
+                target: "amend-genesis",
+                "added {} generated accounts with {} keys each",
+                generation.count,
+                generation.keys_per_account
+            );
+        }
+        Ok(balances_set)
+    }
+
+    /// Applies the validators, the total supply and `GenesisChanges` to the input genesis
+    /// config, and returns it as the output.
+    fn update_genesis(
+        &self,
+        loaded: LoadedGenesis,
+        accounts: &mut Accounts,
+        out: &mut AmendOutput,
+    ) -> Genesis {
+        let genesis_changes = &self.genesis_changes;
+        let LoadedGenesis { mut genesis, chain_id, shard_layout, .. } = loaded;
+        let mut validators = std::mem::take(&mut accounts.validators);
+        let kept_validators = std::mem::take(&mut accounts.kept_validators);
+        if !kept_validators.is_empty() {
+            tracing::info!(
+                target: "amend-genesis",
+                "keeping the {} validators of the input genesis, adding {} to them",
+                kept_validators.len(),
+                validators.len()
+            );
+            validators.splice(0..0, kept_validators);
+        }
+        if let Some(scaler) = &out.scaler {
+            // rounded the same way as the pledges on the validators' accounts
+            for validator in validators.iter_mut() {
+                validator.pledging = scaler.scale_balance(validator.pledging);
+            }
+        }
+        out.outcome.summary.total_supply = out.total_supply;
+        out.outcome.summary.validators_replaced = genesis
+            .config
+            .validators
+            .iter()
+            .filter(|old| !validators.iter().any(|v| v.account_id == old.account_id))
+            .count() as u64;
+        genesis.config.total_supply = out.total_supply;
+        if self.validators.is_some() {
+            genesis.config.num_block_producer_seats = validators.len() as NumSeats;
+        }
+        // here we have already checked that there are no duplicate validators in
+        // wanted_records()
+        genesis.config.validators = validators;
+        if let Some(chain_id) = chain_id {
+            genesis.config.chain_id = chain_id;
+        }
+        if let Some(n) = genesis_changes.num_seats {
+            genesis.config.num_block_producer_seats = n;
+        }
+        if let Some(shard_layout) = shard_layout {
+            genesis.config.avg_hidden_validator_seats_per_shard =
+                shard_layout.shard_ids().into_iter().map(|_| 0).collect();
+            genesis.config.num_block_producer_seats_per_shard = utils::get_num_seats_per_shard(
+                shard_layout.shard_ids().count() as NumShards,
+                genesis.config.num_block_producer_seats,
+            );
+            genesis.config.shard_layout = shard_layout;
+        }
+        if let Some(seats) = &genesis_changes.num_block_producer_seats_per_shard {
+            genesis.config.num_block_producer_seats_per_shard = seats.clone();
+        }
+        if let Some(seats) = &genesis_changes.avg_hidden_validator_seats_per_shard {
+            genesis.config.avg_hidden_validator_seats_per_shard = seats.clone();
+        }
+        if let Some(v) = genesis_changes.protocol_version {
+            genesis.config.protocol_version = v;
+        }
+        if let Some(l) = genesis_changes.epoch_length {
+            genesis.config.epoch_length = l;
+        }
+        if let Some(t) = genesis_changes.transaction_validity_period {
+            genesis.config.transaction_validity_period = t;
+        }
+        if let Some(r) = genesis_changes.protocol_reward_rate {
+            genesis.config.protocol_reward_rate = r;
+        }
+        if let Some(t) = genesis_changes.block_producer_kickout_threshold {
+            genesis.config.block_producer_kickout_threshold = t;
+        }
+        if let Some(t) = genesis_changes.chunk_producer_kickout_threshold {
+            genesis.config.chunk_producer_kickout_threshold = t;
+        }
+        if let Some(p) = genesis_changes.min_gas_price {
+            genesis.config.min_gas_price = p;
+        }
+        if let Some(p) = genesis_changes.max_gas_price {
+            genesis.config.max_gas_price = p;
+        }
+        if let Some(h) = genesis_changes.genesis_height {
+            genesis.config.genesis_height = h;
+        }
+        if let Some(t) = genesis_changes.genesis_time {
+            genesis.config.genesis_time = t;
+        }
+        if let Some(r) = genesis_changes.max_inflation_rate {
+            genesis.config.max_inflation_rate = r;
+        }
+        if let Some(n) = genesis_changes.num_blocks_per_year {
+            genesis.config.num_blocks_per_year = n;
+        }
+        genesis.config.protocol_treasury_account = accounts.treasury_account.clone();
+        genesis
+    }
+
+    /// Checks the output before it's written. Problems --force or the other flags named in
+    /// the errors let through are logged as warnings.
+    fn run_checks(
+        &self,
+        genesis: &Genesis,
+        accounts: &Accounts,
+        out: &AmendOutput,
+        balances_set: Vec<(AccountId, Balance)>,
+    ) -> Result<(), AmendGenesisError> {
+        let options = &self.options;
+        if let Some(expected) = options.expected_total_supply {
+            checks::check_total_supply(out.total_supply, expected, balances_set)
+                .map_err(AmendGenesisError::Validation)?;
+        }
+        if let Some(n) = self.genesis_changes.num_seats {
+            let num_validators = genesis.config.validators.len();
+            let num_shards = genesis.config.shard_layout.shard_ids().count() as NumShards;
+            if let Err(err) = checks::check_num_seats(n, num_validators, num_shards) {
+                if !options.allow_seat_mismatch {
+                    bail!(
+                        Validation,
+                        "{} (use --allow-seat-mismatch to write the output anyway)",
+                        err
+                    );
+                }
+                tracing::warn!(target: "amend-genesis", "{}", err);
+            }
+        }
+        let problems = checks::kickout_threshold_problems(&genesis.config);
+        if !problems.is_empty() {
+            if !options.force {
+                bail!(
+                    Validation,
+                    "the output genesis config would likely get validators kicked out after the \
+                    first epoch (use --force to write it anyway):\n{}",
+                    problems.join("\n")
+                );
+            }
+            for problem in problems.iter() {
+                tracing::warn!(target: "amend-genesis", "{}", problem);
+            }
+        }
+        let nonce_problem =
+            accounts.nonce_check.as_ref().and_then(checks::NonceHeightCheck::problem);
+        if let Some(problem) = nonce_problem {
+            if !options.force {
+                bail!(Validation, "{} (use --force to write the output anyway)", problem);
+            }
+            tracing::warn!(target: "amend-genesis", "{}", problem);
+        }
+        let shard_problem =
+            out.records.shard_accounts().and_then(checks::ShardAccountCounts::problem);
+        if let Some(problem) = shard_problem {
+            if options.empty_shard_policy == EmptyShardPolicy::Error {
+                bail!(
+                    Validation,
+                    "{} (use --warn-empty-shards to write the output anyway)",
+                    problem
+                );
+            }
+            tracing::warn!(target: "amend-genesis", "{}", problem);
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the records, writes the genesis config and the other outputs, and
+    /// checks the output with the options that read it back. Nothing is written with
+    /// --dry-run other than the summary.
+    fn write(
+        &self,
+        files: AmendFiles,
+        genesis: Genesis,
+        out: AmendOutput,
+        validator_keys: &keys::ValidatorKeys,
+    ) -> Result<AmendGenesisOutcome, AmendGenesisError> {
+        let options = &self.options;
+        let AmendOutput {
+            records,
+            account_index,
+            account_diff,
+            mut summary_out,
+            mut outcome,
+            total_supply,
+            ..
+        } = out;
+        outcome.total_supply = total_supply;
+        outcome.validators = genesis.config.validators.clone();
+        outcome.balance_overrides.sort();
+        let (records_written, records_out) = records.end()?;
+        outcome.records_written = records_written;
+        if let Some(diff) = account_diff {
+            diff.finish().map_err(AmendGenesisError::Io)?;
+        }
+        outcome.summary.phase = AmendPhase::Done;
+        outcome.summary.complete = true;
+        if options.dry_run {
+            if let Some(writer) = &mut summary_out {
+                writer.write(&outcome.summary).map_err(AmendGenesisError::Io)?;
+            }
+            return Ok(outcome);
+        }
+        // The genesis config is written last, so that a failure writing the records doesn't
+        // leave one behind pointing at a truncated records file.
+        records_out
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .and_then(compression::RecordsOut::finish)
+            .with_context(|| format!("Failed writing output records {}", files.records_out_name))
+            .map_err(AmendGenesisError::Io)?;
+        if let Some(index) = account_index {
+            index.finish().map_err(AmendGenesisError::Io)?;
+        }
+        write_genesis(&genesis, &files.genesis_file_out)?;
+        if let Some(dir) = &options.keys_out_dir {
+            validator_keys.write(dir, &genesis.config.validators).map_err(AmendGenesisError::Io)?;
+        }
+
+        if let Some(records_file_out) = &files.records_file_out {
+            if options.validate_output {
+                validate::validate_genesis(&genesis.config, records_file_out)
+                    .context("the output genesis failed validation")
+                    .map_err(AmendGenesisError::Validation)?;
+            }
+            if options.smoke_test {
+                smoke_test::run_smoke_test(&files.genesis_file_out, records_file_out)
+                    .context("smoke test of the output genesis failed")
+                    .map_err(AmendGenesisError::Validation)?;
+            }
+            if options.hashes_output() {
+                let hashes = GenesisHashes::compute(&genesis.config, records_file_out)
+                    .map_err(AmendGenesisError::Io)?;
+                tracing::info!(
+                    target: "amend-genesis",
+                    genesis_hash = %hashes.genesis_hash,
+                    genesis_config_hash = %hashes.genesis_config_hash,
+                    "hashed the output genesis"
+                );
+                outcome.genesis_hashes = Some(hashes);
+            }
+        }
+        // the records go first here too, so the genesis config never points at old records
+        let outputs = files.records_in_place.into_iter().chain(files.genesis_in_place).collect();
+        in_place::commit_all(outputs).map_err(AmendGenesisError::Io)?;
+        if let Some(hashes) = &outcome.genesis_hashes {
+            for template in options.template_out.iter() {
+                template.write(hashes).map_err(AmendGenesisError::Io)?;
+            }
+        }
+        if let Some(writer) = &mut summary_out {
+            writer.write(&outcome.summary).map_err(AmendGenesisError::Io)?;
+        }
+        Ok(outcome)
+    }
+}
+
+impl AccountRecords {
+    fn write_out(
+        mut self,
+        account_id: AccountId,
+        out: &mut AmendOutput,
+        options: &AmendOptions,
+        storage: StorageParams,
+        absorbs_dust: bool,
+        balance_to: Option<Balance>,
+    ) -> Result<(), AmendGenesisError> {
+        let StorageParams { num_bytes_account, num_extra_bytes_record } = storage;
+        let default_validator_balance =
+            options.default_validator_balance.unwrap_or(DEFAULT_VALIDATOR_AMOUNT);
+        if self.account.is_none() && !self.keys.is_empty() {
+            if let Some(balance) = options.create_missing_accounts {
+                // the storage usage of the keys is added below, like for any other account
+                self.set_account(balance, 0, 0, num_bytes_account);
+            }
+        }
+        match self.account {
+            Some(mut account) => {
+                // amount_needed is still set only if we found no balance for this validator
+                // anywhere, so all it will have is the default amount
+                if self.amount_needed
+                    && account.pledging() > default_validator_balance
+                    && !options.allow_unfunded_pledges
+                {
+                    bail!(
+                        Validation,
+                        "validator {} has a pledge of {} but no balance in the input records or \
+                        --extra-records, and the default amount of {} it would get instead doesn't \
+                        cover it (use --allow-unfunded-pledges to write it anyway)",
+                        &account_id,
+                        account.pledging(),
+                        default_validator_balance
+                    );
+                }
+                account.set_storage_usage(account.storage_usage() + self.storage_tallied);
+                let num_keys = self.keys.len() as u64;
+                // sorted so that the output doesn't depend on the HashMap's iteration order
+                let mut keys = std::mem::take(&mut self.keys).into_iter().collect::<Vec<_>>();
+                keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+                let keys_added =
+                    keys.iter().map(|(public_key, _)| PublicKey::clone(public_key)).collect();
+                for (public_key, access_key) in keys {
+                    let public_key = public_key.into_inner();
+                    let storage_usage = account.storage_usage()
+                        + public_key.len() as u64
+                        + borsh::object_length(&access_key).unwrap() as u64
+                        + num_extra_bytes_record;
+                    account.set_storage_usage(storage_usage);
+
+                    out.records.write(&StateRecord::AccessKey {
+                        account_id: account_id.clone(),
+                        public_key,
+                        access_key,
+                    })?;
+                }
+                for (data_key, value) in self.data.iter() {
+                    let storage_usage = account.storage_usage()
+                        + data_key.len() as u64
+                        + value.len() as u64
+                        + num_extra_bytes_record;
+                    account.set_storage_usage(storage_usage);
+                }
+                // the other extra records come from the input records, whose storage usage is
+                // already counted
+                for record in self.extra_records.iter() {
+                    if let StateRecord::Rsa2048Keys { rsa_key, .. } = record {
+                        let storage_usage = account.storage_usage()
+                            + borsh::object_length(rsa_key).unwrap() as u64
+                            + num_extra_bytes_record;
+                        account.set_storage_usage(storage_usage);
+                    }
+                }
+                if let Some(code) = &self.code {
+                    account.set_storage_usage(account.storage_usage() + code.len() as u64);
+                }
+                if self.state_dropped > 0 {
+                    // no account takes up less than its Account record
+                    let storage_usage = account.storage_usage();
+                    if storage_usage < self.state_dropped + num_bytes_account {
+                        tracing::warn!(
+                            target: "amend-genesis",
+                            "account {} has a storage usage of {}, less than the {} bytes of \
+                            contract state taken out of it, setting it to {}",
+                            &account_id,
+                            storage_usage,
+                            self.state_dropped,
+                            num_bytes_account
+                        );
+                        account.set_storage_usage(num_bytes_account);
+                    } else {
+                        account.set_storage_usage(storage_usage - self.state_dropped);
+                    }
+                }
+                self.check_code_hash(&account_id, &account)?;
+                if self.amount_needed {
+                    account.set_amount(default_validator_balance);
+                }
+                if let Some(scaler) = &mut out.scaler {
+                    scaler.scale_account(&mut account);
+                    if absorbs_dust {
+                        account.set_amount(account.amount() + scaler.take_dust());
+                    }
+                }
+                // the amount that brings the total supply so far up to balance_to
+                if let Some(balance_to) = balance_to {
+                    let rest = out.total_supply + account.pledging();
+                    if rest > balance_to {
+                        bail!(
+                            Validation,
+                            "--balance-treasury can't make the total supply match \
+                            --expected-total-supply, since without the amount of {} it's already \
+                            {} too high",
+                            &account_id,
+                            rest - balance_to
+                        );
+                    }
+                    account.set_amount(balance_to - rest);
+                }
+                out.total_supply += account.amount() + account.pledging();
+                let status =
+                    if self.existing { AccountStatus::Modified } else { AccountStatus::Added };
+                out.outcome.summary.add_account(status);
+                out.outcome.summary.access_keys_injected += num_keys;
+                if self.existing && self.balance_given {
+                    out.outcome.balance_overrides.push(account_id.clone());
+                }
+                if let Some(index) = &mut out.account_index {
+                    let num_keys = if self.existing { None } else { Some(num_keys) };
+                    index
+                        .write(&account_id, &account, num_keys, status)
+                        .map_err(AmendGenesisError::Io)?;
+                }
+                if let Some(diff) = &mut out.account_diff {
+                    let keys_replaced = std::mem::take(&mut self.keys_replaced);
+                    diff.account(
+                        &account_id,
+                        self.input.as_ref(),
+                        &account,
+                        keys_added,
+                        keys_replaced,
+                    )
+                    .map_err(AmendGenesisError::Io)?;
+                }
+                out.records
+                    .write(&StateRecord::Account { account_id: account_id.clone(), account })?;
+                for record in self.extra_records.iter() {
+                    out.records.write(record)?;
+                }
+                if let Some(code) = self.code {
+                    out.records
+                        .write(&StateRecord::Contract { account_id: account_id.clone(), code })?;
+                }
+                for (data_key, value) in self.data {
+                    out.records.write(&StateRecord::Data {
+                        account_id: account_id.clone(),
+                        data_key,
+                        value,
+                    })?;
+                }
+            }
+            None => {
+                if !self.data.is_empty() {
+                    bail!(
+                        Validation,
+                        "Data records for {} were included in --extra-records, but no Account \
+                        record was found for it in the input records, --extra-records or \
+                        --validators",
+                        &account_id
+                    );
+                }
+                if self.has_rsa_keys() {
+                    bail!(
+                        Validation,
+                        "Rsa2048Keys records for {} were included in --extra-records, but no \
+                        Account record was found for it in the input records, --extra-records or \
+                        --validators",
+                        &account_id
+                    );
+                }
+                tracing::warn!(
+                    "access keys for {} were included in --extra-records, but no Account record \
+                    was found. Not adding them to the output (use --create-missing-accounts to \
+                    add an Account record for it)",
+                    &account_id
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::NaiveDate;
+
+// expanded to the current UTC date in --chain-id and --chain-id-suffix
+const DATE_PLACEHOLDER: &str = "{date}";
+
+fn expand(template: &str, today: NaiveDate) -> String {
+    template.replace(DATE_PLACEHOLDER, &today.format("%Y-%m-%d").to_string())
+}
+
+/// The chain ID to set on the output genesis, if it's changed. `chain_id` replaces the input
+/// one, while `suffix` is appended to it, and only one of them can be given. Both can have a
+/// `{date}` placeholder, which is replaced with `today` as YYYY-MM-DD.
+pub(crate) fn output_chain_id(
+    input: &str,
+    chain_id: Option<&str>,
+    suffix: Option<&str>,
+    today: NaiveDate,
+) -> anyhow::Result<Option<String>> {
+    let chain_id = match (chain_id, suffix) {
+        (Some(_), Some(_)) => {
+            anyhow::bail!("--chain-id and --chain-id-suffix can't be given together")
+        }
+        (Some(chain_id), None) => expand(chain_id, today),
+        (None, Some(suffix)) => format!("{}{}", input, expand(suffix, today)),
+        (None, None) => return Ok(None),
+    };
+    if chain_id.is_empty() {
+        anyhow::bail!("the output chain ID can't be empty");
+    }
+    Ok(Some(chain_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::output_chain_id;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_output_chain_id() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let chain_id = |chain_id, suffix| output_chain_id("mainnet", chain_id, suffix, today);
+
+        assert_eq!(chain_id(None, None).unwrap(), None);
+        assert_eq!(chain_id(Some("forknet"), None).unwrap().unwrap(), "forknet");
+        assert_eq!(
+            chain_id(Some("mainnet-fork-{date}"), None).unwrap().unwrap(),
+            "mainnet-fork-2024-06-01"
+        );
+        assert_eq!(chain_id(None, Some("-fork")).unwrap().unwrap(), "mainnet-fork");
+        assert_eq!(
+            chain_id(None, Some("-fork-{date}")).unwrap().unwrap(),
+            "mainnet-fork-2024-06-01"
+        );
+        assert_eq!(
+            chain_id(Some("{date}-{date}"), None).unwrap().unwrap(),
+            "2024-06-01-2024-06-01"
+        );
+
+        // the full chain ID and the suffix don't combine, whichever would win
+        let err = chain_id(Some("forknet"), Some("-fork")).unwrap_err();
+        assert!(err.to_string().contains("can't be given together"), "{:#}", err);
+        assert!(chain_id(Some(""), None).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use unc_chain_configs::GenesisConfig;
+use unc_parameters::RuntimeConfigStore;
+use unc_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use unc_primitives::types::{AccountId, NumShards};
+use unc_primitives::utils::get_num_seats_per_shard;
+use unc_primitives::version::{ProtocolFeature, ProtocolVersion};
+use unc_primitives_core::account::AccessKey;
+use unc_primitives_core::types::{Balance, BlockHeight, BlockHeightDelta, Nonce, NumSeats};
+
+/// Returns the `num_bytes_account` and `num_extra_bytes_record` storage usage
+/// parameters of the runtime config for the given protocol version.
+pub(crate) fn storage_params_for_protocol(protocol_version: ProtocolVersion) -> (u64, u64) {
+    let store = RuntimeConfigStore::new(None);
+    let config = &store.get_config(protocol_version).fees.storage_usage_config;
+    (config.num_bytes_account, config.num_extra_bytes_record)
+}
+
+/// Checks that the storage usage parameters we're computing `storage_usage` with
+/// match the ones the runtime will use at the given protocol version.
+pub(crate) fn check_storage_params(
+    protocol_version: ProtocolVersion,
+    num_bytes_account: u64,
+    num_extra_bytes_record: u64,
+) -> anyhow::Result<()> {
+    let (want_bytes_account, want_extra_bytes_record) =
+        storage_params_for_protocol(protocol_version);
+    if num_bytes_account != want_bytes_account {
+        anyhow::bail!(
+            "num_bytes_account is {} but the runtime config for protocol version {} has {}",
+            num_bytes_account,
+            protocol_version,
+            want_bytes_account
+        );
+    }
+    if num_extra_bytes_record != want_extra_bytes_record {
+        anyhow::bail!(
+            "num_extra_bytes_record is {} but the runtime config for protocol version {} has {}",
+            num_extra_bytes_record,
+            protocol_version,
+            want_extra_bytes_record
+        );
+    }
+    Ok(())
+}
+
+// The protocol feature the shard layout needs to be enabled at genesis.
+fn shard_layout_feature(shard_layout: &ShardLayout) -> Option<ProtocolFeature> {
+    match shard_layout {
+        ShardLayout::V0(_) => None,
+        ShardLayout::V1(_) if shard_layout.version() >= 2 => {
+            Some(ProtocolFeature::SimpleNightshadeV2)
+        }
+        ShardLayout::V1(_) => Some(ProtocolFeature::SimpleNightshade),
+    }
+}
+
+/// Checks that the `protocol_version` set with --protocol-version supports the rest of the
+/// output genesis: the shard layout, and the storage usage parameters if `default_storage_params`
+/// are the defaults the storage usage is computed with, rather than ones given on purpose.
+/// Account records have the same format at every protocol version, so there's nothing to check
+/// for them. Returns a description of each problem found, naming the protocol version needed.
+pub(crate) fn protocol_version_problems(
+    protocol_version: ProtocolVersion,
+    shard_layout: &ShardLayout,
+    default_storage_params: Option<(u64, u64)>,
+) -> Vec<String> {
+    let mut problems = vec![];
+    if let Some(feature) = shard_layout_feature(shard_layout) {
+        if feature.protocol_version() > protocol_version {
+            problems.push(format!(
+                "the shard layout, with version {}, needs protocol feature {:?}, which is only \
+                enabled from protocol version {}",
+                shard_layout.version(),
+                feature,
+                feature.protocol_version()
+            ));
+        }
+    }
+    if let Some((num_bytes_account, num_extra_bytes_record)) = default_storage_params {
+        let (want_bytes_account, want_extra_bytes_record) =
+            storage_params_for_protocol(protocol_version);
+        if (num_bytes_account, num_extra_bytes_record)
+            != (want_bytes_account, want_extra_bytes_record)
+        {
+            problems.push(format!(
+                "the storage usage is computed with the default num_bytes_account {} and \
+                num_extra_bytes_record {}, but the runtime config of protocol version {} has {} \
+                and {}; set them to match or use --storage-params-from-protocol",
+                num_bytes_account,
+                num_extra_bytes_record,
+                protocol_version,
+                want_bytes_account,
+                want_extra_bytes_record
+            ));
+        }
+    }
+    problems
+}
+
+/// Checks the `num_seats` set with --num-seats against the `num_validators` in the output
+/// genesis config and the `num_shards` of its shard layout. `get_num_seats_per_shard()` rounds
+/// the seats of a shard up to 1, so unless its seats add up to `num_seats` some shards would
+/// only get seats that don't exist.
+pub(crate) fn check_num_seats(
+    num_seats: NumSeats,
+    num_validators: usize,
+    num_shards: NumShards,
+) -> anyhow::Result<()> {
+    if num_seats == 0 {
+        anyhow::bail!("--num-seats must be at least 1");
+    }
+    if num_seats > num_validators as NumSeats {
+        anyhow::bail!(
+            "--num-seats is {} but there are only {} validators to fill them",
+            num_seats,
+            num_validators
+        );
+    }
+    let per_shard = get_num_seats_per_shard(num_shards, num_seats);
+    if per_shard.iter().sum::<NumSeats>() != num_seats {
+        anyhow::bail!(
+            "--num-seats is {} but the shard layout has {} shards, so some of them would get no \
+            seats of their own",
+            num_seats,
+            num_shards
+        );
+    }
+    Ok(())
+}
+
+/// Checks that `seats`, given with `flag` as the seats of each shard, has one for each of the
+/// `num_shards` shards of the output shard layout.
+pub(crate) fn check_seats_per_shard(
+    flag: &str,
+    seats: &[NumSeats],
+    num_shards: usize,
+) -> anyhow::Result<()> {
+    if seats.len() != num_shards {
+        anyhow::bail!(
+            "{} gives the seats of {} shards, but the output shard layout has {}",
+            flag,
+            seats.len(),
+            num_shards
+        );
+    }
+    Ok(())
+}
+
+// how many of the balances set from --validators and --extra-records are listed when the total
+// supply doesn't match --expected-total-supply
+const NUM_BALANCES_LISTED: usize = 5;
+
+/// Checks the `total_supply` of the output against the `expected` one given with
+/// --expected-total-supply. If they differ, the largest of `balances_set`, the total balances
+/// of the accounts whose balance was set from --validators or --extra-records, are listed as
+/// the likeliest reason.
+pub(crate) fn check_total_supply(
+    total_supply: Balance,
+    expected: Balance,
+    mut balances_set: Vec<(AccountId, Balance)>,
+) -> anyhow::Result<()> {
+    let delta = match total_supply.cmp(&expected) {
+        std::cmp::Ordering::Equal => return Ok(()),
+        std::cmp::Ordering::Greater => format!("{} more", total_supply - expected),
+        std::cmp::Ordering::Less => format!("{} less", expected - total_supply),
+    };
+    balances_set.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    let listed = balances_set
+        .iter()
+        .take(NUM_BALANCES_LISTED)
+        .map(|(account_id, balance)| format!("{} ({})", account_id, balance))
+        .collect::<Vec<_>>();
+    let listed = if listed.is_empty() {
+        "no balances were set from --validators or --extra-records".to_string()
+    } else {
+        format!(
+            "the largest balances set from --validators and --extra-records are {}",
+            listed.join(", ")
+        )
+    };
+    anyhow::bail!(
+        "the total supply is {}, {} than the {} given with --expected-total-supply, and {}",
+        total_supply,
+        delta,
+        expected,
+        listed
+    )
+}
+
+// Returns a description of the problem if a producer expected to produce `expected` blocks
+// (or chunks) per epoch would fall below `threshold` percent by missing just one of them.
+// With so few expected blocks the kickout threshold stops meaning anything, and an
+// unlucky validator gets kicked out after the first epoch.
+fn single_miss_problem(
+    what: &str,
+    epoch_length: BlockHeightDelta,
+    num_producers: NumSeats,
+    threshold: u8,
+    threshold_name: &str,
+) -> Option<String> {
+    if threshold == 0 || num_producers == 0 {
+        return None;
+    }
+    let expected = epoch_length / num_producers;
+    if expected == 0 {
+        return Some(format!(
+            "epoch_length {} is less than the number of {}s ({}), so some of them are not expected \
+            to produce anything in an epoch, and {} of {}% is meaningless",
+            epoch_length, what, num_producers, threshold_name, threshold
+        ));
+    }
+    if (expected - 1) * 100 < threshold as u64 * expected {
+        return Some(format!(
+            "with epoch_length {} and {} {}s, each one is expected to produce about {} per epoch, \
+            so missing a single one puts it below the {} of {}%",
+            epoch_length, num_producers, what, expected, threshold_name, threshold
+        ));
+    }
+    None
+}
+
+/// Keeps track of access keys in the output records whose nonces are too high to be used at
+/// the start of a chain with the given genesis height. Transactions included at height `h`
+/// must have a nonce below `h * ACCESS_KEY_NONCE_RANGE_MULTIPLIER`, so if a dump of a chain
+/// is restarted at a lower height, keys created late in the old chain can't sign anything
+/// until the new one catches up.
+pub(crate) struct NonceHeightCheck {
+    genesis_height: BlockHeight,
+    num_too_high: u64,
+    max_nonce: Option<(AccountId, Nonce)>,
+}
+
+impl NonceHeightCheck {
+    pub(crate) fn new(genesis_height: BlockHeight) -> Self {
+        Self { genesis_height, num_too_high: 0, max_nonce: None }
+    }
+
+    pub(crate) fn add(&mut self, account_id: &AccountId, access_key: &AccessKey) {
+        let limit = (self.genesis_height + 1) * AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER;
+        if access_key.nonce < limit {
+            return;
+        }
+        self.num_too_high += 1;
+        if self.max_nonce.as_ref().map_or(true, |(_, n)| *n < access_key.nonce) {
+            self.max_nonce = Some((account_id.clone(), access_key.nonce));
+        }
+    }
+
+    /// Returns a description of the problem if any keys were found with nonces too high
+    pub(crate) fn problem(&self) -> Option<String> {
+        let (account_id, nonce) = self.max_nonce.as_ref()?;
+        Some(format!(
+            "{} access keys in the output records have nonces too high to be used right after \
+            genesis_height {}. The highest one, belonging to {}, has nonce {} and can't sign \
+            transactions until the chain reaches height {}",
+            self.num_too_high,
+            self.genesis_height,
+            account_id,
+            nonce,
+            nonce / AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER + 1
+        ))
+    }
+}
+
+/// Counts the Account records in the output records of each shard of the output shard layout,
+/// to find the shards that --remove-accounts, --keep-only-accounts or a new shard layout left
+/// without any account. Some node versions can't compute the genesis state root of a shard
+/// with no state at all.
+pub(crate) struct ShardAccountCounts {
+    shard_layout: ShardLayout,
+    counts: Vec<u64>,
+}
+
+impl ShardAccountCounts {
+    pub(crate) fn new(shard_layout: ShardLayout) -> Self {
+        let counts = vec![0; shard_layout.shard_ids().count()];
+        Self { shard_layout, counts }
+    }
+
+    pub(crate) fn add(&mut self, account_id: &AccountId) {
+        let shard_id = account_id_to_shard_id(account_id, &self.shard_layout);
+        self.counts[shard_id as usize] += 1;
+    }
+
+    /// Returns a description of the problem if any shard has no accounts, naming the accounts
+    /// each of them covers.
+    pub(crate) fn problem(&self) -> Option<String> {
+        let boundaries = self.shard_layout.boundary_accounts();
+        let empty = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count == 0)
+            .map(|(shard_id, _)| {
+                // the shard covers the accounts from the boundary before it up to the next one
+                let from = shard_id.checked_sub(1).and_then(|i| boundaries.get(i));
+                match (from, boundaries.get(shard_id)) {
+                    (Some(from), Some(to)) => {
+                        format!("shard {} (accounts from {} up to {})", shard_id, from, to)
+                    }
+                    (Some(from), None) => format!("shard {} (accounts from {})", shard_id, from),
+                    (None, Some(to)) => format!("shard {} (accounts before {})", shard_id, to),
+                    (None, None) => format!("shard {}", shard_id),
+                }
+            })
+            .collect::<Vec<_>>();
+        if empty.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{} of the {} shards of the output shard layout have no Account records: {}. Move the \
+            boundary accounts of the shard layout so that every shard has some of the output \
+            accounts, or keep some accounts in these shards",
+            empty.len(),
+            self.counts.len(),
+            empty.join(", ")
+        ))
+    }
+}
+
+/// Checks whether the epoch length and kickout thresholds in `config` are likely to get
+/// validators kicked out just for statistically missing one of the few blocks or chunks
+/// they are assigned. Returns a description of each problem found.
+pub(crate) fn kickout_threshold_problems(config: &GenesisConfig) -> Vec<String> {
+    let num_validators = config.validators.len() as NumSeats;
+    let num_block_producers = std::cmp::min(num_validators, config.num_block_producer_seats);
+    let mut problems = vec![];
+
+    problems.extend(single_miss_problem(
+        "block producer",
+        config.epoch_length,
+        num_block_producers,
+        config.block_producer_kickout_threshold,
+        "block_producer_kickout_threshold",
+    ));
+    for (shard_id, seats) in config.num_block_producer_seats_per_shard.iter().enumerate() {
+        let num_chunk_producers = std::cmp::min(*seats, num_block_producers);
+        if let Some(problem) = single_miss_problem(
+            "chunk producer",
+            config.epoch_length,
+            num_chunk_producers,
+            config.chunk_producer_kickout_threshold,
+            "chunk_producer_kickout_threshold",
+        ) {
+            problems.push(format!("shard {}: {}", shard_id, problem));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use unc_chain_configs::GenesisConfig;
+    use unc_parameters::RuntimeConfigStore;
+    use unc_primitives::shard_layout::ShardLayout;
+    use unc_primitives::types::AccountInfo;
+    use unc_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+    use unc_primitives_core::types::{BlockHeightDelta, NumSeats};
+
+    fn config(
+        num_validators: usize,
+        num_shards: usize,
+        epoch_length: BlockHeightDelta,
+        block_producer_kickout_threshold: u8,
+        chunk_producer_kickout_threshold: u8,
+    ) -> GenesisConfig {
+        let validators = (0..num_validators)
+            .map(|i| AccountInfo {
+                account_id: format!("validator{}", i).parse().unwrap(),
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf".parse().unwrap(),
+                pledging: 1_000_000,
+                power: 0,
+            })
+            .collect();
+        GenesisConfig {
+            epoch_length,
+            validators,
+            num_block_producer_seats: 100,
+            num_block_producer_seats_per_shard: vec![100 as NumSeats; num_shards],
+            block_producer_kickout_threshold,
+            chunk_producer_kickout_threshold,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_kickout_thresholds_ok() {
+        // 50 blocks each, one miss leaves a validator at 98%
+        assert!(super::kickout_threshold_problems(&config(20, 1, 1000, 90, 90)).is_empty());
+        // 10 blocks each, one miss leaves a validator at exactly 90%
+        assert!(super::kickout_threshold_problems(&config(10, 4, 100, 90, 90)).is_empty());
+        // thresholds of zero never kick anyone out
+        assert!(super::kickout_threshold_problems(&config(50, 4, 10, 0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_kickout_thresholds_too_short_epoch() {
+        // every validator is only expected to produce one block and one chunk per shard
+        let problems = super::kickout_threshold_problems(&config(10, 2, 10, 90, 60));
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("block_producer_kickout_threshold"), "{:?}", problems);
+        assert!(problems[1].starts_with("shard 0:"), "{:?}", problems);
+        assert!(problems[2].starts_with("shard 1:"), "{:?}", problems);
+
+        // fewer blocks in the epoch than validators
+        let problems = super::kickout_threshold_problems(&config(20, 1, 10, 50, 0));
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("less than the number of block producers"), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_kickout_thresholds_only_chunks() {
+        // 5 blocks each is enough for a 60% threshold but not for 90%
+        let problems = super::kickout_threshold_problems(&config(4, 1, 20, 60, 90));
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("chunk_producer_kickout_threshold"), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_storage_params() {
+        let store = RuntimeConfigStore::new(None);
+        let config = &store.get_config(PROTOCOL_VERSION).fees.storage_usage_config;
+        let (num_bytes_account, num_extra_bytes_record) =
+            super::storage_params_for_protocol(PROTOCOL_VERSION);
+        assert_eq!(num_bytes_account, config.num_bytes_account);
+        assert_eq!(num_extra_bytes_record, config.num_extra_bytes_record);
+
+        super::check_storage_params(PROTOCOL_VERSION, num_bytes_account, num_extra_bytes_record)
+            .unwrap();
+
+        let err = super::check_storage_params(
+            PROTOCOL_VERSION,
+            num_bytes_account + 1,
+            num_extra_bytes_record,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains(&format!("num_bytes_account is {}", num_bytes_account + 1)), "{}", err);
+        assert!(err.contains(&format!("has {}", num_bytes_account)), "{}", err);
+
+        let err = super::check_storage_params(
+            PROTOCOL_VERSION,
+            num_bytes_account,
+            num_extra_bytes_record + 1,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("num_extra_bytes_record"), "{}", err);
+    }
+
+    #[test]
+    fn test_protocol_version_downgrade() {
+        let layout = ShardLayout::v1_test();
+        let nightshade = ProtocolFeature::SimpleNightshade.protocol_version();
+        assert!(super::protocol_version_problems(nightshade, &layout, None).is_empty());
+        assert!(
+            super::protocol_version_problems(nightshade - 1, &ShardLayout::v0(4, 0), None)
+                .is_empty()
+        );
+
+        let problems = super::protocol_version_problems(nightshade - 1, &layout, None);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("needs protocol feature SimpleNightshade,"), "{:?}", problems);
+        assert!(problems[0].contains(&format!("from protocol version {}", nightshade)));
+
+        let layout_v2 = ShardLayout::v1(vec!["foo".parse().unwrap()], None, 2);
+        let problems = super::protocol_version_problems(nightshade, &layout_v2, None);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("SimpleNightshadeV2"), "{:?}", problems);
+
+        let (num_bytes_account, num_extra_bytes_record) =
+            super::storage_params_for_protocol(nightshade);
+        let params = Some((num_bytes_account, num_extra_bytes_record));
+        assert!(super::protocol_version_problems(nightshade, &layout, params).is_empty());
+        let params = Some((num_bytes_account + 1, num_extra_bytes_record));
+        let problems = super::protocol_version_problems(nightshade, &layout, params);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("--storage-params-from-protocol"), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_total_supply() {
+        super::check_total_supply(1000, 1000, vec![]).unwrap();
+
+        let balances_set = (0..7)
+            .map(|i| (format!("account{}", i).parse().unwrap(), 10 * i as u128))
+            .collect::<Vec<_>>();
+        let err = super::check_total_supply(1000, 990, balances_set).unwrap_err().to_string();
+        assert!(err.contains("1000, 10 more than the 990"), "{}", err);
+        let listed = "account6 (60), account5 (50), account4 (40), account3 (30), account2 (20)";
+        assert!(err.contains(listed), "{}", err);
+        assert!(!err.contains("account1"), "{}", err);
+
+        let err = super::check_total_supply(1000, 1500, vec![]).unwrap_err().to_string();
+        assert!(err.contains("500 less"), "{}", err);
+        assert!(err.contains("no balances were set"), "{}", err);
+    }
+}
@@ -1,5 +1,6 @@
-use unc_primitives::types::NumBlocks;
-use unc_primitives::types::{BlockHeightDelta, NumSeats};
+use chrono::{DateTime, Utc};
+use unc_primitives::types::{AccountId, Balance, NumBlocks, ShardId};
+use unc_primitives::types::{BlockHeight, BlockHeightDelta, NumSeats};
 use unc_primitives::version::ProtocolVersion;
 use num_rational::Rational32;
 use std::path::PathBuf;
@@ -10,14 +11,18 @@ pub struct AmendGenesisCommand {
     /// path to the input genesis file
     #[clap(long)]
     genesis_file_in: PathBuf,
-    /// path to the output genesis file
+    /// path to the output genesis file. It can be the same as --genesis-file-in, which is
+    /// then only replaced once the command has succeeded
     #[clap(long)]
     genesis_file_out: PathBuf,
-    /// path to the input records file. Note that right now this must be provided, and
-    /// this command will not work with a genesis file that itself contains the records
+    /// path to the input records file, or "-" to read them from stdin. Note that right now
+    /// this must be provided, and this command will not work with a genesis file that itself
+    /// contains the records
     #[clap(long)]
     records_file_in: PathBuf,
-    /// path to the output records file
+    /// path to the output records file, or "-" to write them to stdout. With "-", the summary
+    /// printed with --dry-run or --summary-json goes to stderr instead. It can be the same as
+    /// --records-file-in, which is then only replaced once the command has succeeded
     #[clap(long)]
     records_file_out: PathBuf,
     /// path to a JSON list of AccountInfos representing the validators to put in the
@@ -26,22 +31,45 @@ pub struct AmendGenesisCommand {
     ///   "account_id": <ACCOUNT_ID>,
     ///   "public_key": <PUBLIC_KEY>,
     ///   "amount": <STAKE>,
+    ///   "power": <POWER>,
     /// }
+    /// optionally with a "secret_key" matching the public key, for --keys-out-dir. The power
+    /// is set on the validator's account even if it exists in --records-file-in, unless
+    /// --keep-existing-power is given.
+    /// Without this, the validators in --genesis-file-in are kept along with their pledges
     #[clap(long)]
-    validators: PathBuf,
-    /// path to extra records to add to the output state. Right now only Accounts and AccessKey
-    /// records are supported, and any added accounts must have zero `code_hash`
+    validators: Option<PathBuf>,
+    /// path to extra records to add to the output state. Right now only Account, AccessKey,
+    /// Contract, Data and Rsa2048Keys records are supported. An account given here with a nonzero
+    /// `code_hash` must have a matching Contract record either here or in the input records. A
+    /// chip given here replaces the one with the same key in the input records. Can be given
+    /// more than once, as long as each account's Account record and contract code are only in
+    /// one of the files
     #[clap(long)]
-    extra_records: Option<PathBuf>,
-    /// chain ID to set on the output genesis
+    extra_records: Vec<PathBuf>,
+    /// chain ID to set on the output genesis. "{date}" in it is replaced with the current UTC
+    /// date, so "mainnet-fork-{date}" gives e.g. "mainnet-fork-2024-06-01"
     #[clap(long)]
     chain_id: Option<String>,
+    /// string to append to the chain ID of the input genesis, with "{date}" replaced like in
+    /// --chain-id. Can't be given along with --chain-id
+    #[clap(long, conflicts_with = "chain_id")]
+    chain_id_suffix: Option<String>,
     /// protocol version to set on the output genesis
     #[clap(long)]
     protocol_version: Option<ProtocolVersion>,
     /// num_seats to set in the output genesis file
     #[clap(long)]
     num_seats: Option<NumSeats>,
+    /// comma-separated block producer seats of each shard to set in the output genesis file,
+    /// in place of the ones derived from num_seats. There has to be one per shard of the output
+    /// shard layout
+    #[clap(long, use_value_delimiter = true)]
+    num_block_producer_seats_per_shard: Option<Vec<NumSeats>>,
+    /// comma-separated hidden validator seats of each shard to set in the output genesis file.
+    /// There has to be one per shard of the output shard layout
+    #[clap(long, use_value_delimiter = true)]
+    avg_hidden_validator_seats_per_shard: Option<Vec<NumSeats>>,
     /// epoch length to set in the output genesis file
     #[clap(long)]
     epoch_length: Option<BlockHeightDelta>,
@@ -57,9 +85,48 @@ pub struct AmendGenesisCommand {
     /// protocol_reward_rate to set in the output genesis file. Give a ratio here (e.g. "1/10")
     #[clap(long)]
     protocol_reward_rate: Option<Rational32>,
+    /// genesis_height to set in the output genesis file. Fails if there are access keys in the
+    /// output records with nonces too high to be used at this height, unless --force is given
+    #[clap(long)]
+    genesis_height: Option<BlockHeight>,
+    /// genesis_time to set in the output genesis file, in RFC 3339 format
+    /// (e.g. "2024-01-02T03:04:05Z")
+    #[clap(long, value_parser = parse_rfc3339)]
+    genesis_time: Option<DateTime<Utc>>,
+    /// max_inflation_rate to set in the output genesis file. Give a ratio between 0 and 1
+    /// here (e.g. "1/20")
+    #[clap(long)]
+    max_inflation_rate: Option<Rational32>,
+    /// num_blocks_per_year to set in the output genesis file
+    #[clap(long)]
+    num_blocks_per_year: Option<NumBlocks>,
+    /// protocol_treasury_account to set in the output genesis file. If the account doesn't exist
+    /// in the input records or --extra-records, it is created
+    #[clap(long)]
+    protocol_treasury_account: Option<AccountId>,
+    /// balance to give the --protocol-treasury-account if it has to be created
+    #[clap(long, default_value_t = 0, requires = "protocol_treasury_account")]
+    protocol_treasury_balance: Balance,
+    /// fail unless the total_supply of the output genesis is exactly this, listing the largest
+    /// balances set from --validators and --extra-records if it isn't
+    #[clap(long)]
+    expected_total_supply: Option<Balance>,
+    /// set the amount of the protocol treasury account to whatever makes the total_supply
+    /// match --expected-total-supply
+    #[clap(long, requires = "expected_total_supply")]
+    balance_treasury: bool,
     /// optional file that should contain a JSON-serialized shard layout
     #[clap(long)]
     shard_layout_file: Option<PathBuf>,
+    /// built-in shard layout to set in the output genesis, in place of one from
+    /// --shard-layout-file
+    #[clap(long, value_enum, conflicts_with = "shard_layout_file")]
+    shard_layout: Option<crate::NamedShardLayout>,
+    /// only keep the records of the accounts in this shard of the input shard layout, and write
+    /// a genesis with a single shard. Receipts are kept if their receiver is in the shard.
+    /// Validators in --validators whose accounts are in other shards are dropped
+    #[clap(long, conflicts_with_all = &["shard_layout_file", "shard_layout"])]
+    extract_shard: Option<ShardId>,
     /// runtime fees config `num_bytes_account` value. Used to initialize the `storage_usage` field
     /// on accounts in the output state
     #[clap(long)]
@@ -68,20 +135,250 @@ pub struct AmendGenesisCommand {
     /// on accounts in the output state
     #[clap(long)]
     num_extra_bytes_record: Option<u64>,
+    /// check that the `num_bytes_account` and `num_extra_bytes_record` values used match the
+    /// runtime config of the output protocol version
+    #[clap(long)]
+    check_storage_params: bool,
+    /// take the `num_bytes_account` and `num_extra_bytes_record` values from the runtime config
+    /// of the output protocol version
+    #[clap(long, conflicts_with_all = &["num_bytes_account", "num_extra_bytes_record"])]
+    storage_params_from_protocol: bool,
     /// min_gas_price to set in the output genesis file
     #[clap(long)]
     min_gas_price: Option<u128>,
     /// max_gas_price to set in the output genesis file
     #[clap(long)]
     max_gas_price: Option<u128>,
+    /// fail if the input records contain a record type this build doesn't know about. By
+    /// default such records are passed through to the output unchanged
+    #[clap(long)]
+    strict_record_types: bool,
+    /// keep only the first of the Account records of the same account, and of the AccessKey
+    /// records of the same key, in --records-file-in. By default such duplicates are an error
+    #[clap(long)]
+    dedupe_input: bool,
+    /// write the output even if the resulting epoch_length and kickout thresholds look like they
+    /// would get validators kicked out just for missing a single block or chunk, or if some
+    /// access key nonces are too high for --genesis-height
+    #[clap(long)]
+    force: bool,
+    /// write the output even if --num-seats is 0, more than the number of validators, or less
+    /// than the number of shards
+    #[clap(long)]
+    allow_seat_mismatch: bool,
+    /// allow validators that have no balance in the input records or --extra-records to have a
+    /// pledge larger than the --default-validator-balance they are given
+    #[clap(long)]
+    allow_unfunded_pledges: bool,
+    /// balance to give validators that have no balance in the input records or --extra-records.
+    /// Defaults to 10,000 UNC
+    #[clap(long)]
+    default_validator_balance: Option<Balance>,
+    /// add an Account record for accounts that have access keys in --extra-records but no
+    /// Account record anywhere, instead of leaving their keys out of the output. Its storage
+    /// usage is --num-bytes-account plus that of the keys
+    #[clap(long)]
+    create_missing_accounts: bool,
+    /// balance to give the accounts added by --create-missing-accounts. Defaults to 0
+    #[clap(long, requires = "create_missing_accounts")]
+    missing_account_balance: Option<Balance>,
+    /// after writing the output files, compute the genesis state roots from them in a temporary
+    /// store the same way a node does on its first start, and fail if that doesn't work
+    #[clap(long)]
+    smoke_test: bool,
+    /// after writing the output files, hash them the way a node does when it loads them, and
+    /// print the genesis hash and the hash of the genesis config alone
+    #[clap(long)]
+    emit_genesis_hash: bool,
+    /// a template to fill in with the hashes of the output, given as in=<PATH>,out=<PATH>.
+    /// Every {{genesis_hash}} and {{genesis_config_hash}} in the input file is replaced with the
+    /// hash, and the result written to the output file. Can be given more than once
+    #[clap(long)]
+    template_out: Vec<crate::TemplateOut>,
+    /// how to check the input genesis config and records before amending them
+    #[clap(long, value_enum, default_value_t = crate::ValidationMode::UnsafeFast)]
+    validation_mode: crate::ValidationMode,
+    /// after writing the output files, run the checks a node runs with full genesis validation
+    /// on them, and fail if they don't pass
+    #[clap(long)]
+    validate_output: bool,
+    /// path to a JSON map from old to new account IDs. These accounts are renamed in the input
+    /// records, and so is the receiver_id of function call access keys pointing at them. Accounts
+    /// in --validators and --extra-records should be given by their new IDs
+    #[clap(long)]
+    rename_accounts: Option<PathBuf>,
+    /// path to a JSON list of account IDs to remove. Their Account, AccessKey, Contract and Data
+    /// records are left out of the output records, so their balances don't count towards the
+    /// output total supply, and so are the receipts bound for them. Accounts renamed with
+    /// --rename-accounts should be given by their new IDs, and none of these may be listed in
+    /// --validators
+    #[clap(long)]
+    remove_accounts: Option<PathBuf>,
+    /// path to a JSON list of account IDs to keep. All records of other accounts are left out
+    /// of the output records, except for the accounts in --validators and --extra-records and
+    /// the protocol treasury account, which are always kept. Accounts renamed with
+    /// --rename-accounts should be given by their new IDs
+    #[clap(long, conflicts_with = "remove_accounts")]
+    keep_only_accounts: Option<PathBuf>,
+    /// fail if a shard of the output shard layout has no Account records, as --remove-accounts,
+    /// --keep-only-accounts or a new shard layout can leave it. Some node versions handle
+    /// shards with no state poorly when computing the genesis state roots
+    #[clap(long)]
+    require_nonempty_shards: bool,
+    /// like --require-nonempty-shards, but only warn about the shards with no Account records
+    #[clap(long, conflicts_with = "require_nonempty_shards")]
+    warn_empty_shards: bool,
+    /// path to a CSV file with rows of account_id,amount[,pledge], optionally under a header
+    /// row starting with "account_id". The amount, and the pledge if given, are set on the
+    /// Account records of these accounts in --records-file-in before anything else is done
+    /// with them, so a validator in --validators gets its pledge out of the new total balance.
+    /// Accounts renamed with --rename-accounts should be given by their new IDs. Accounts not
+    /// found in --records-file-in are warned about
+    #[clap(long)]
+    balance_overrides: Option<PathBuf>,
+    /// fail if any of the accounts in --balance-overrides isn't found in --records-file-in
+    #[clap(long, requires = "balance_overrides")]
+    strict_overrides: bool,
+    /// set the power of each validator, in the output genesis file and on its account record,
+    /// to its pledge times this ratio (e.g. "1/1000000"), rounded down and capped at the largest
+    /// possible power. Pledges are left as they are
+    #[clap(long)]
+    pledge_to_power: Option<Rational32>,
+    /// path to write an index of the output accounts to, one JSON object per line with the
+    /// balance, storage usage and number of keys of each account, and whether it was added,
+    /// modified or passed through unchanged
+    #[clap(long)]
+    account_index_out: Option<PathBuf>,
+    /// directory to also write the output records to split by shard, as one
+    /// records_shard_<SHARD_ID>.json file per shard of --shard-layout-file or --shard-layout,
+    /// for a faster import. Records go to the shard of their account, or of the receiver for
+    /// receipts
+    #[clap(long)]
+    records_out_dir: Option<PathBuf>,
+    /// path to write a JSON object per line to for each account that was added, removed or
+    /// changed compared to --records-file-in, with its amount, pledge, power, storage usage
+    /// and code_hash before and after, and the keys added or replaced. Also written with
+    /// --dry-run
+    #[clap(long)]
+    diff_file: Option<PathBuf>,
+    /// how to lay out the output records: a single JSON array, which is what nodes read, or
+    /// NDJSON with one record per line. Input records in either format are read regardless
+    #[clap(long, value_enum, default_value_t = crate::RecordsFormat::JsonArray)]
+    output_format: crate::RecordsFormat,
+    /// indent the output records with one field per line, like the output genesis file, so
+    /// that small ones can be reviewed by hand. Takes more space, so it's best left off for
+    /// large records files
+    #[clap(long)]
+    pretty: bool,
+    /// directory to write a validator_key.json for each output validator to, in a
+    /// subdirectory named after its account. Written for the validators that have a
+    /// "secret_key" next to their "public_key" in --validators, or for all of them with
+    /// --generate-keys
+    #[clap(long)]
+    keys_out_dir: Option<PathBuf>,
+    /// give each validator a new randomly generated key pair instead of the public key in
+    /// --validators, and write the validators with their new keys to validators.json in
+    /// --keys-out-dir
+    #[clap(long, requires = "keys_out_dir")]
+    generate_keys: bool,
+    /// keep the validators in --genesis-file-in along with their pledges, and add the ones in
+    /// --validators to them. The number of block producer seats is set to the number of
+    /// validators after merging, unless --num-seats is given
+    #[clap(long, conflicts_with = "extract_shard")]
+    append_validators: bool,
+    /// keep the power that validators existing in the input records already have, instead of
+    /// setting it to the "power" given for them in --validators
+    #[clap(long)]
+    keep_existing_power: bool,
+    /// when a validator's Account record in --extra-records has a pledge other than the one
+    /// in --validators, keep the one in --validators, with the rest of the total balance in
+    /// --extra-records as its amount. Without this or --prefer-extra-records that's an error
+    #[clap(long)]
+    prefer_validators: bool,
+    /// when a validator's Account record in --extra-records has a pledge other than the one
+    /// in --validators, take the pledge and amount in --extra-records, and give the validator
+    /// that pledge in the output genesis config as well
+    #[clap(long, conflicts_with = "prefer_validators")]
+    prefer_extra_records: bool,
+    /// cut down the contract state of an account, given as
+    /// "account_id=<ACCOUNT_ID>,max_keys=<N>,max_value_bytes=<N>" with at least one of the
+    /// limits. Only the first max_keys Data records in trie order are kept, and their values
+    /// are cut to max_value_bytes. The account's storage usage is lowered to match, and its
+    /// balance is left alone. Can be given more than once
+    #[clap(long)]
+    truncate_contract_state: Vec<crate::ContractStateTruncation>,
+    /// leave all Contract and Data records out of the output records, and set the code_hash of
+    /// every account to the default one. Storage usage is lowered by the size of the code and
+    /// data left out, and balances are left alone
+    #[clap(long, conflicts_with = "truncate_contract_state")]
+    strip_contracts: bool,
+    /// replace the code of every Contract record in the input records with the wasm in this
+    /// file, e.g. a no-op contract to neutralize all contracts on a forknet. The code_hash and
+    /// storage usage of each account are changed to match the new code
+    #[clap(long, conflicts_with = "strip_contracts")]
+    replace_contract_code: Option<PathBuf>,
+    /// set the storage_usage of every account to what its Account, AccessKey, Contract and
+    /// Data records in the output add up to, instead of adjusting the one in the input records.
+    /// The input records are read once more to tally them, so they can't come from stdin
+    #[clap(long)]
+    recompute_storage_usage: bool,
+    /// add synthetic accounts with full access keys for load tests, given as
+    /// `count=<N>,balance=<BALANCE>[,prefix=<PREFIX>][,keys_per_account=<K>][,seed=<S>]`. They're
+    /// named `<prefix>000001.test` and so on (prefix defaults to `load`), and their keys are
+    /// derived from the seed, so the same options always give the same accounts
+    #[clap(long)]
+    generate_accounts: Option<crate::AccountGeneration>,
+    /// write the secret keys of the accounts added with --generate-accounts to this file, as
+    /// one JSON object with account_id, public_key and secret_key per line
+    #[clap(long, requires = "generate_accounts")]
+    generated_keys_out: Option<PathBuf>,
+    /// multiply the amount and pledge of every account, and the pledges of the validators, by
+    /// this ratio (e.g. "1/1000"), rounding down. What's rounded away goes to the protocol
+    /// treasury account. Accounts from --generate-accounts get the balance given there
+    #[clap(long)]
+    scale_balances: Option<Rational32>,
+    /// go through the input records and run all the checks on the output, but don't write the
+    /// output files. Prints a summary of what would have been changed instead
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "smoke_test",
+            "validate_output",
+            "account_index_out",
+            "emit_genesis_hash",
+            "template_out",
+        ]
+    )]
+    dry_run: bool,
+    /// print the summary of what was changed as JSON instead of a table. Implies printing it
+    /// even without --dry-run
+    #[clap(long)]
+    summary_json: bool,
+    /// path to keep the summary in as JSON while running, rewritten every
+    /// --summary-interval-secs with the number of records processed so far, the current
+    /// total supply and the last account seen. It has "complete" set once the command is done
+    #[clap(long)]
+    summary_out: Option<PathBuf>,
+    /// how often to rewrite --summary-out, in seconds. Defaults to 10
+    #[clap(long, requires = "summary_out")]
+    summary_interval_secs: Option<u64>,
+    /// log how far through the input records the command is every this many records, with
+    /// the rate and, for input files, the estimated time left. Defaults to 1000000
+    #[clap(long)]
+    progress_interval: Option<u64>,
 }
 
 impl AmendGenesisCommand {
-    pub fn run(self) -> anyhow::Result<()> {
+    /// Runs the command. Callers exiting on failure should use
+    /// `AmendGenesisError::exit_code()` so that scripts can tell the kinds of failures apart.
+    pub fn run(self) -> Result<(), crate::AmendGenesisError> {
         let genesis_changes = crate::GenesisChanges {
             chain_id: self.chain_id,
+            chain_id_suffix: self.chain_id_suffix,
             protocol_version: self.protocol_version,
             num_seats: self.num_seats,
+            num_block_producer_seats_per_shard: self.num_block_producer_seats_per_shard,
+            avg_hidden_validator_seats_per_shard: self.avg_hidden_validator_seats_per_shard,
             epoch_length: self.epoch_length,
             transaction_validity_period: self.transaction_validity_period,
             protocol_reward_rate: self.protocol_reward_rate,
@@ -89,18 +386,119 @@ impl AmendGenesisCommand {
             chunk_producer_kickout_threshold: self.chunk_producer_kickout_threshold,
             min_gas_price: self.min_gas_price,
             max_gas_price: self.max_gas_price,
+            genesis_height: self.genesis_height,
+            genesis_time: self.genesis_time,
+            max_inflation_rate: self.max_inflation_rate,
+            num_blocks_per_year: self.num_blocks_per_year,
+            protocol_treasury_account: self.protocol_treasury_account,
+        };
+        let options = crate::AmendOptions {
+            strict_record_types: self.strict_record_types,
+            dedupe_input: self.dedupe_input,
+            force: self.force,
+            allow_seat_mismatch: self.allow_seat_mismatch,
+            check_storage_params: self.check_storage_params,
+            storage_params_from_protocol: self.storage_params_from_protocol,
+            allow_unfunded_pledges: self.allow_unfunded_pledges,
+            default_validator_balance: self.default_validator_balance,
+            create_missing_accounts: self
+                .create_missing_accounts
+                .then(|| self.missing_account_balance.unwrap_or(0)),
+            smoke_test: self.smoke_test,
+            emit_genesis_hash: self.emit_genesis_hash,
+            template_out: self.template_out,
+            rename_accounts: self.rename_accounts,
+            remove_accounts: self.remove_accounts,
+            keep_only_accounts: self.keep_only_accounts,
+            empty_shard_policy: match (self.require_nonempty_shards, self.warn_empty_shards) {
+                (true, _) => crate::EmptyShardPolicy::Error,
+                (false, true) => crate::EmptyShardPolicy::Warn,
+                (false, false) => crate::EmptyShardPolicy::Allow,
+            },
+            balance_overrides: self.balance_overrides,
+            strict_overrides: self.strict_overrides,
+            account_index_out: self.account_index_out,
+            records_out_dir: self.records_out_dir,
+            diff_file: self.diff_file,
+            pledge_to_power: self.pledge_to_power,
+            protocol_treasury_balance: self.protocol_treasury_balance,
+            expected_total_supply: self.expected_total_supply,
+            balance_treasury: self.balance_treasury,
+            extract_shard: self.extract_shard,
+            validation_mode: self.validation_mode,
+            validate_output: self.validate_output,
+            output_format: self.output_format,
+            pretty: self.pretty,
+            keys_out_dir: self.keys_out_dir,
+            generate_validator_keys: self.generate_keys,
+            append_validators: self.append_validators,
+            keep_existing_power: self.keep_existing_power,
+            pledge_conflict_policy: match (self.prefer_validators, self.prefer_extra_records) {
+                (true, _) => crate::PledgeConflictPolicy::PreferValidators,
+                (false, true) => crate::PledgeConflictPolicy::PreferExtraRecords,
+                (false, false) => crate::PledgeConflictPolicy::Error,
+            },
+            truncate_contract_state: self.truncate_contract_state,
+            strip_contracts: self.strip_contracts,
+            replace_contract_code: self.replace_contract_code,
+            recompute_storage_usage: self.recompute_storage_usage,
+            generate_accounts: self.generate_accounts,
+            generated_keys_out: self.generated_keys_out,
+            scale_balances: self.scale_balances,
+            dry_run: self.dry_run,
+            summary_out: self.summary_out,
+            summary_interval: self.summary_interval_secs.map(std::time::Duration::from_secs),
+            progress_interval: self.progress_interval,
+            record_delay: None,
+        };
+        let records_to_stdout = self.records_file_out.as_os_str() == "-";
+        let mut config = crate::AmendGenesisConfig::new()
+            .genesis_file_in(self.genesis_file_in)
+            .genesis_file_out(self.genesis_file_out)
+            .records_in(crate::RecordsSource::from_arg(self.records_file_in))
+            .records_out(crate::RecordsSink::from_arg(self.records_file_out))
+            .genesis_changes(genesis_changes)
+            .num_bytes_account(self.num_bytes_account.unwrap_or(crate::DEFAULT_NUM_BYTES_ACCOUNT))
+            .num_extra_bytes_record(
+                self.num_extra_bytes_record.unwrap_or(crate::DEFAULT_NUM_EXTRA_BYTES_RECORD),
+            )
+            .options(options);
+        if let Some(path) = self.validators {
+            config = config.validators(path);
+        }
+        for path in self.extra_records {
+            config = config.extra_records(path);
+        }
+        if let Some(path) = self.shard_layout_file {
+            config = config.shard_layout_file(path);
+        }
+        if let Some(shard_layout) = self.shard_layout {
+            config = config.shard_layout(shard_layout);
+        }
+        let outcome = config.amend()?;
+        let mut summary = if self.summary_json {
+            let summary = serde_json::to_string_pretty(&outcome.summary)
+                .map_err(|e| crate::AmendGenesisError::Internal(e.into()))?;
+            format!("{}\n", summary)
+        } else if self.dry_run {
+            outcome.summary.to_string()
+        } else {
+            String::new()
         };
-        crate::amend_genesis(
-            &self.genesis_file_in,
-            &self.genesis_file_out,
-            &self.records_file_in,
-            &self.records_file_out,
-            self.extra_records.as_deref(),
-            &self.validators,
-            self.shard_layout_file.as_deref(),
-            &genesis_changes,
-            self.num_bytes_account.unwrap_or(100),
-            self.num_extra_bytes_record.unwrap_or(40),
-        )
+        if let Some(hashes) = outcome.genesis_hashes.filter(|_| self.emit_genesis_hash) {
+            summary.push_str(&format!("genesis_hash {}\n", hashes.genesis_hash));
+            summary.push_str(&format!("genesis_config_hash {}\n", hashes.genesis_config_hash));
+        }
+        // keep stdout for the records if they're written there
+        if records_to_stdout {
+            eprint!("{}", summary);
+        } else {
+            print!("{}", summary);
+        }
+        Ok(())
     }
 }
+
+fn parse_rfc3339(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(s).map(|t| t.with_timezone(&Utc))
+}
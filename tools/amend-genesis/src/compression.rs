@@ -0,0 +1,189 @@
+use crate::progress::ByteCounter;
+use anyhow::Context;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+// the first two bytes of any gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+pub(crate) fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+// Wraps `reader` in a gzip decoder if `gzip` is set or it starts with the gzip magic bytes.
+fn decompress(reader: impl Read + 'static, gzip: bool) -> anyhow::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf().context("Failed reading the start of the records")?;
+    if gzip || magic.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Opens a records file for reading. If it ends in .gz or starts with the gzip magic bytes,
+/// it's decompressed as it's read.
+pub(crate) fn open_records(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    decompress(File::open(path)?, is_gzip_path(path))
+}
+
+/// Where amend_genesis() reads the input records from.
+pub enum RecordsSource {
+    Path(PathBuf),
+    /// an already open stream such as stdin, which is read through once. Compressed records
+    /// are recognized by the gzip magic bytes. This can't be used with
+    /// `ValidationMode::Full`, which reads the records twice
+    Reader(Box<dyn Read>),
+}
+
+impl RecordsSource {
+    /// The source given on the command line, where `-` means stdin.
+    pub fn from_arg(arg: PathBuf) -> Self {
+        if arg.as_os_str() == "-" {
+            Self::Reader(Box::new(std::io::stdin()))
+        } else {
+            Self::Path(arg)
+        }
+    }
+
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            Self::Reader(_) => None,
+        }
+    }
+
+    /// Opens the records, counting the bytes read from the source in `bytes_read`.
+    pub(crate) fn open(self, bytes_read: &ByteCounter) -> anyhow::Result<Box<dyn Read>> {
+        match self {
+            Self::Path(path) => {
+                decompress(bytes_read.wrap(File::open(&path)?), is_gzip_path(&path))
+            }
+            Self::Reader(reader) => decompress(bytes_read.wrap(reader), false),
+        }
+    }
+}
+
+impl From<&Path> for RecordsSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_owned())
+    }
+}
+
+impl fmt::Display for RecordsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "file {}", path.display()),
+            Self::Reader(_) => write!(f, "stream"),
+        }
+    }
+}
+
+/// Where amend_genesis() writes the output records to.
+pub enum RecordsSink {
+    /// a file, compressed if its name ends in .gz
+    Path(PathBuf),
+    /// an already open stream such as stdout. The records are written uncompressed. This
+    /// can't be used with `AmendOptions::smoke_test` or `AmendOptions::validate_output`,
+    /// which read the output records back
+    Writer(Box<dyn Write>),
+}
+
+impl RecordsSink {
+    /// The sink given on the command line, where `-` means stdout.
+    pub fn from_arg(arg: PathBuf) -> Self {
+        if arg.as_os_str() == "-" {
+            Self::Writer(Box::new(std::io::stdout()))
+        } else {
+            Self::Path(arg)
+        }
+    }
+
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            Self::Writer(_) => None,
+        }
+    }
+
+    pub(crate) fn create(self) -> anyhow::Result<RecordsOut> {
+        match self {
+            Self::Path(path) => RecordsOut::create(&path),
+            Self::Writer(writer) => Ok(RecordsOut::Writer(writer)),
+        }
+    }
+}
+
+impl From<&Path> for RecordsSink {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_owned())
+    }
+}
+
+impl fmt::Display for RecordsSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "file {}", path.display()),
+            Self::Writer(_) => write!(f, "stream"),
+        }
+    }
+}
+
+/// Where the output records are written.
+pub(crate) enum RecordsOut {
+    /// nowhere, for dry runs
+    Sink,
+    File(File),
+    /// a file ending in .gz, compressed as it's written
+    Gzip(GzEncoder<File>),
+    /// a stream given with `RecordsSink::Writer`
+    Writer(Box<dyn Write>),
+}
+
+impl RecordsOut {
+    pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        if is_gzip_path(path) {
+            Ok(Self::Gzip(GzEncoder::new(file, Compression::default())))
+        } else {
+            Ok(Self::File(file))
+        }
+    }
+
+    /// Flushes everything written so far, and for compressed output, ends the gzip stream.
+    /// Files are synced to disk, so that the records are all there before the genesis config
+    /// gets written.
+    pub(crate) fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Sink => Ok(()),
+            Self::File(file) => file.sync_all(),
+            Self::Gzip(encoder) => encoder.finish()?.sync_all(),
+            Self::Writer(mut writer) => writer.flush(),
+        }
+    }
+}
+
+impl Write for RecordsOut {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Sink => Ok(buf.len()),
+            Self::File(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            Self::Writer(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Sink => Ok(()),
+            Self::File(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            Self::Writer(writer) => writer.flush(),
+        }
+    }
+}
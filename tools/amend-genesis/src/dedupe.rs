@@ -0,0 +1,79 @@
+use unc_primitives::hash::hash;
+use unc_primitives::state_record::StateRecord;
+use std::collections::HashSet;
+
+// most duplicates listed in the error, the rest are only counted
+const MAX_LISTED: usize = 20;
+
+/// 128 bits of the hash of what makes `record` unique among the records, which is its account
+/// for Account records and its account and public key for AccessKey records. Other records
+/// aren't checked.
+fn fingerprint(record: &StateRecord) -> Option<u128> {
+    let identity = match record {
+        StateRecord::Account { account_id, .. } => borsh::to_vec(&(0u8, account_id)),
+        StateRecord::AccessKey { account_id, public_key, .. } => {
+            borsh::to_vec(&(1u8, account_id, public_key))
+        }
+        _ => return None,
+    };
+    let hash = hash(&identity.unwrap());
+    Some(u128::from_le_bytes(hash.0[..16].try_into().unwrap()))
+}
+
+fn describe(record: &StateRecord) -> String {
+    match record {
+        StateRecord::Account { account_id, .. } => format!("Account {}", account_id),
+        StateRecord::AccessKey { account_id, public_key, .. } => {
+            format!("AccessKey {} {}", account_id, public_key)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Finds Account records of the same account and AccessKey records of the same key in the input
+/// records, which a corrupted dump can have and which would otherwise all be written, counting
+/// their balances more than once. Only a 128-bit fingerprint is kept for each record seen, a
+/// fraction of the size of the account ID and key, with a chance of two different records
+/// colliding that's negligible even for billions of records.
+pub(crate) struct DuplicateRecords {
+    seen: HashSet<u128>,
+    // the first MAX_LISTED duplicates found
+    listed: Vec<String>,
+    num_duplicates: u64,
+}
+
+impl DuplicateRecords {
+    pub(crate) fn new() -> Self {
+        Self { seen: HashSet::new(), listed: Vec::new(), num_duplicates: 0 }
+    }
+
+    /// Returns whether an Account or AccessKey record for the same account and key as `record`
+    /// was already seen.
+    pub(crate) fn check(&mut self, record: &StateRecord) -> bool {
+        let Some(fingerprint) = fingerprint(record) else {
+            return false;
+        };
+        if self.seen.insert(fingerprint) {
+            return false;
+        }
+        self.num_duplicates += 1;
+        if self.listed.len() < MAX_LISTED {
+            self.listed.push(describe(record));
+        }
+        true
+    }
+
+    pub(crate) fn num_duplicates(&self) -> u64 {
+        self.num_duplicates
+    }
+
+    /// The duplicates found, for logs and errors.
+    pub(crate) fn list(&self) -> String {
+        let mut list = self.listed.join(", ");
+        let rest = self.num_duplicates - self.listed.len() as u64;
+        if rest > 0 {
+            list.push_str(&format!(" and {} more", rest));
+        }
+        list
+    }
+}
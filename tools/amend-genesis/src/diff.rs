@@ -0,0 +1,140 @@
+use anyhow::Context;
+use unc_crypto::PublicKey;
+use unc_primitives::hash::CryptoHash;
+use unc_primitives::state_record::StateRecord;
+use unc_primitives::types::AccountId;
+use unc_primitives_core::account::Account;
+use unc_primitives_core::serialize::dec_format;
+use unc_primitives_core::types::{Balance, Power, StorageUsage};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How an account listed in the --diff-file differs from the input records.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountChange {
+    /// The account doesn't exist in the input records.
+    Added,
+    /// The account exists in both the input and the output records, with different values.
+    Modified,
+    /// The account exists in the input records, but was left out of the output.
+    Removed,
+}
+
+/// The fields of an Account record compared in the --diff-file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountSnapshot {
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+    #[serde(with = "dec_format")]
+    pub pledging: Balance,
+    pub power: Power,
+    pub storage_usage: StorageUsage,
+    pub code_hash: CryptoHash,
+}
+
+impl From<&Account> for AccountSnapshot {
+    fn from(account: &Account) -> Self {
+        Self {
+            amount: account.amount(),
+            pledging: account.pledging(),
+            power: account.power(),
+            storage_usage: account.storage_usage(),
+            code_hash: account.code_hash(),
+        }
+    }
+}
+
+/// One line of the --diff-file, for an account that was added, removed or changed.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountDiffRow {
+    pub account_id: AccountId,
+    pub change: AccountChange,
+    /// None for added accounts
+    pub before: Option<AccountSnapshot>,
+    /// None for removed accounts
+    pub after: Option<AccountSnapshot>,
+    /// Keys the account didn't have in the input records, sorted.
+    pub keys_added: Vec<PublicKey>,
+    /// Keys the account had in the input records whose access key was replaced, sorted.
+    pub keys_replaced: Vec<PublicKey>,
+}
+
+/// Writes an NDJSON row for each account that differs from the input records. Rows are
+/// written as the accounts are, so that nothing is kept around for the accounts passed
+/// through.
+pub(crate) struct AccountDiff {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl AccountDiff {
+    pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+        let out = BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("Failed opening diff file {}", path.display()))?,
+        );
+        Ok(Self { path: path.to_path_buf(), out })
+    }
+
+    fn write(&mut self, row: &AccountDiffRow) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.out, row)
+            .map_err(std::io::Error::from)
+            .and_then(|()| self.out.write_all(b"\n"))
+            .with_context(|| format!("Failed writing to {}", self.path.display()))
+    }
+
+    /// Writes a row for `account_id` if it's new, or if `after` or its keys differ from
+    /// `before`, the Account record found for it in the input records.
+    pub(crate) fn account(
+        &mut self,
+        account_id: &AccountId,
+        before: Option<&Account>,
+        after: &Account,
+        mut keys_added: Vec<PublicKey>,
+        mut keys_replaced: Vec<PublicKey>,
+    ) -> anyhow::Result<()> {
+        let before = before.map(AccountSnapshot::from);
+        let after = AccountSnapshot::from(after);
+        let change = match &before {
+            None => AccountChange::Added,
+            Some(before)
+                if *before == after && keys_added.is_empty() && keys_replaced.is_empty() =>
+            {
+                return Ok(());
+            }
+            Some(_) => AccountChange::Modified,
+        };
+        keys_added.sort();
+        keys_replaced.sort();
+        self.write(&AccountDiffRow {
+            account_id: account_id.clone(),
+            change,
+            before,
+            after: Some(after),
+            keys_added,
+            keys_replaced,
+        })
+    }
+
+    /// Writes a row for the account of `record` if it's an Account record left out of the
+    /// output.
+    pub(crate) fn removed(&mut self, record: &StateRecord) -> anyhow::Result<()> {
+        let StateRecord::Account { account_id, account } = record else {
+            return Ok(());
+        };
+        self.write(&AccountDiffRow {
+            account_id: account_id.clone(),
+            change: AccountChange::Removed,
+            before: Some(account.into()),
+            after: None,
+            keys_added: vec![],
+            keys_replaced: vec![],
+        })
+    }
+
+    pub(crate) fn finish(mut self) -> anyhow::Result<()> {
+        self.out.flush().with_context(|| format!("Failed writing to {}", self.path.display()))
+    }
+}
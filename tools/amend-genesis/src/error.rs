@@ -0,0 +1,102 @@
+use std::fmt;
+
+/// Why amend_genesis() failed, so callers can tell apart the kinds of failures without
+/// looking at the messages. Each variant holds the full error along with its context.
+#[derive(thiserror::Error, Debug)]
+pub enum AmendGenesisError {
+    /// one of the input files couldn't be parsed
+    #[error(transparent)]
+    InputParse(anyhow::Error),
+    /// the inputs are well-formed, but they or the output built from them fail a check
+    #[error(transparent)]
+    Validation(anyhow::Error),
+    /// options or inputs were given that contradict each other
+    #[error(transparent)]
+    Conflict(anyhow::Error),
+    /// reading or writing a file failed
+    #[error(transparent)]
+    Io(anyhow::Error),
+    /// something failed that never should have
+    #[error(transparent)]
+    Internal(anyhow::Error),
+}
+
+impl AmendGenesisError {
+    /// The code the amend-genesis command exits with when it fails with this error. These
+    /// start at 3 since 1 is used for errors from before amend_genesis() is called and clap
+    /// exits with 2 on usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::InputParse(_) => 3,
+            Self::Validation(_) => 4,
+            Self::Conflict(_) => 5,
+            Self::Io(_) => 6,
+            Self::Internal(_) => 7,
+        }
+    }
+
+    pub fn inner(&self) -> &anyhow::Error {
+        match self {
+            Self::InputParse(e)
+            | Self::Validation(e)
+            | Self::Conflict(e)
+            | Self::Io(e)
+            | Self::Internal(e) => e,
+        }
+    }
+
+    pub fn into_inner(self) -> anyhow::Error {
+        match self {
+            Self::InputParse(e)
+            | Self::Validation(e)
+            | Self::Conflict(e)
+            | Self::Io(e)
+            | Self::Internal(e) => e,
+        }
+    }
+
+    /// Adds context to the error, keeping its kind.
+    pub(crate) fn context<C>(self, context: C) -> Self
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        match self {
+            Self::InputParse(e) => Self::InputParse(e.context(context)),
+            Self::Validation(e) => Self::Validation(e.context(context)),
+            Self::Conflict(e) => Self::Conflict(e.context(context)),
+            Self::Io(e) => Self::Io(e.context(context)),
+            Self::Internal(e) => Self::Internal(e.context(context)),
+        }
+    }
+
+    /// Classifies an error returned while streaming records, where the record callback
+    /// returns an AmendGenesisError wrapped in an anyhow::Error, and anything else means the
+    /// records couldn't be parsed.
+    pub(crate) fn from_stream_error(e: anyhow::Error) -> Self {
+        match e.downcast::<Self>() {
+            Ok(e) => e,
+            Err(e) => Self::InputParse(e),
+        }
+    }
+
+    /// Classifies an error serializing output records, which is an IO error unless the
+    /// record itself couldn't be serialized.
+    pub(crate) fn from_serialize_error(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            Self::Io(e.into())
+        } else {
+            Self::Internal(e.into())
+        }
+    }
+}
+
+/// Like anyhow::bail!(), but returns an AmendGenesisError of the given kind. The error is
+/// converted with `into()` so this also works in the record callbacks returning
+/// anyhow::Result.
+macro_rules! bail {
+    ($kind:ident, $($arg:tt)+) => {
+        return Err($crate::AmendGenesisError::$kind(anyhow::anyhow!($($arg)+)).into())
+    };
+}
+
+pub(crate) use bail;
@@ -0,0 +1,77 @@
+use unc_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use unc_primitives::state_record::{state_record_to_account_id, StateRecord};
+use unc_primitives::types::{AccountId, AccountInfo, ShardId};
+
+/// The shard given with --extract-shard. Records are kept if they'd be stored in that shard
+/// under the input shard layout, the same way a node assigns genesis records to shards: by
+/// their account, or by the receiver for receipts. So a function call access key for a
+/// contract in another shard is kept, but a receipt sent from this shard to another one isn't.
+pub(crate) struct ShardFilter {
+    shard_id: ShardId,
+    shard_layout: ShardLayout,
+    // number of records dropped because they belong to another shard
+    num_dropped: u64,
+}
+
+impl ShardFilter {
+    pub(crate) fn new(shard_id: ShardId, shard_layout: ShardLayout) -> anyhow::Result<Self> {
+        if !shard_layout.shard_ids().any(|id| id == shard_id) {
+            anyhow::bail!(
+                "--extract-shard {} given, but the input shard layout only has shards {:?}",
+                shard_id,
+                shard_layout.shard_ids().collect::<Vec<_>>()
+            );
+        }
+        Ok(Self { shard_id, shard_layout, num_dropped: 0 })
+    }
+
+    fn contains(&self, account_id: &AccountId) -> bool {
+        account_id_to_shard_id(account_id, &self.shard_layout) == self.shard_id
+    }
+
+    /// Drops the validators whose accounts are in other shards, and fails if there are none
+    /// left.
+    pub(crate) fn filter_validators(
+        &self,
+        validators: &mut Vec<AccountInfo>,
+    ) -> anyhow::Result<()> {
+        validators.retain(|v| {
+            let keep = self.contains(&v.account_id);
+            if !keep {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "dropping validator {} since its account is not in shard {}",
+                    &v.account_id,
+                    self.shard_id
+                );
+            }
+            keep
+        });
+        if validators.is_empty() {
+            anyhow::bail!(
+                "none of the accounts in --validators are in shard {}. Give a --validators file \
+                with accounts in that shard",
+                self.shard_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns whether `record` is stored in the extracted shard and should be kept.
+    pub(crate) fn keeps(&mut self, record: &StateRecord) -> bool {
+        let keep = self.contains(state_record_to_account_id(record));
+        if !keep {
+            self.num_dropped += 1;
+        }
+        keep
+    }
+
+    pub(crate) fn log(&self) {
+        tracing::info!(
+            target: "amend-genesis",
+            "extracted shard {}, dropping {} records of the other shards",
+            self.shard_id,
+            self.num_dropped
+        );
+    }
+}
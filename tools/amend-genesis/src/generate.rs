@@ -0,0 +1,169 @@
+use anyhow::Context;
+use unc_crypto::{KeyType, PublicKey, SecretKey};
+use unc_primitives::hash::hash;
+use unc_primitives::types::{AccountId, Balance};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+// top level account the generated accounts are created under
+const SUFFIX: &str = ".test";
+
+/// Synthetic accounts to add to the output for load tests, given with --generate-accounts as
+/// `count=<N>,balance=<BALANCE>[,prefix=<PREFIX>][,keys_per_account=<K>][,seed=<S>]`. The
+/// accounts are named `<prefix>000001.test` up to `<prefix><count>.test`, and their full access
+/// keys are derived from the seed, so the same options always give the same accounts and keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountGeneration {
+    pub count: u64,
+    /// defaults to `load`
+    pub prefix: String,
+    pub balance: Balance,
+    /// defaults to 1
+    pub keys_per_account: u64,
+    /// defaults to 0
+    pub seed: u64,
+}
+
+impl FromStr for AccountGeneration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut count = None;
+        let mut prefix = "load".to_string();
+        let mut balance = None;
+        let mut keys_per_account = 1;
+        let mut seed = 0;
+        for field in s.split(',') {
+            let Some((name, value)) = field.split_once('=') else {
+                anyhow::bail!("expected <name>=<value>, got {:?}", field);
+            };
+            let invalid = || format!("invalid {} {}", name, value);
+            match name {
+                "count" => count = Some(value.parse().with_context(invalid)?),
+                "prefix" => prefix = value.to_string(),
+                "balance" => balance = Some(value.parse().with_context(invalid)?),
+                "keys_per_account" => keys_per_account = value.parse().with_context(invalid)?,
+                "seed" => seed = value.parse().with_context(invalid)?,
+                _ => anyhow::bail!(
+                    "unknown field {}, expected count, prefix, balance, keys_per_account or seed",
+                    name
+                ),
+            }
+        }
+        let Some(count) = count else {
+            anyhow::bail!("count=<N> has to be given");
+        };
+        let Some(balance) = balance else {
+            anyhow::bail!("balance=<BALANCE> has to be given");
+        };
+        if count == 0 {
+            anyhow::bail!("count has to be at least 1");
+        }
+        let generation = Self { count, prefix, balance, keys_per_account, seed };
+        // the first and last IDs have the fewest and most digits
+        for i in [1, count] {
+            generation.try_account_id(i)?;
+        }
+        Ok(generation)
+    }
+}
+
+/// One of the accounts given with --generate-accounts, along with its keys.
+pub(crate) struct GeneratedAccount {
+    pub(crate) account_id: AccountId,
+    pub(crate) keys: Vec<SecretKey>,
+}
+
+impl AccountGeneration {
+    fn try_account_id(&self, i: u64) -> anyhow::Result<AccountId> {
+        let account_id = format!("{}{:06}{}", &self.prefix, i, SUFFIX);
+        account_id.parse().with_context(|| format!("invalid generated account ID {}", account_id))
+    }
+
+    /// Whether `account_id` is one of the generated accounts, for telling if the input
+    /// already has an account of the same name without keeping the generated ones around.
+    pub(crate) fn generates(&self, account_id: &AccountId) -> bool {
+        let Some(digits) = account_id
+            .as_str()
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|s| s.strip_suffix(SUFFIX))
+        else {
+            return false;
+        };
+        if digits.len() < 6 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        // leading zeros only pad to six digits
+        match digits.parse::<u64>() {
+            Ok(i) => (1..=self.count).contains(&i) && format!("{:06}", i) == digits,
+            Err(_) => false,
+        }
+    }
+
+    // The key's seed is the hash of everything identifying it, since only the start of the
+    // string given to SecretKey::from_seed() is used.
+    fn secret_key(&self, account_id: &AccountId, key_index: u64) -> SecretKey {
+        let seed = hash(
+            &[
+                &self.seed.to_le_bytes()[..],
+                account_id.as_bytes(),
+                &key_index.to_le_bytes()[..],
+            ]
+            .concat(),
+        );
+        SecretKey::from_seed(KeyType::ED25519, &seed.to_string())
+    }
+
+    /// The generated accounts in order, made as they're iterated over.
+    pub(crate) fn accounts(&self) -> impl Iterator<Item = GeneratedAccount> + '_ {
+        (1..=self.count).map(move |i| {
+            // checked in from_str()
+            let account_id = self.try_account_id(i).unwrap();
+            let keys = (0..self.keys_per_account)
+                .map(|key_index| self.secret_key(&account_id, key_index))
+                .collect();
+            GeneratedAccount { account_id, keys }
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct KeysFileRow<'a> {
+    account_id: &'a AccountId,
+    public_key: &'a PublicKey,
+    secret_key: &'a SecretKey,
+}
+
+/// Writes the keys of the generated accounts to the file given with --generated-keys-out, as
+/// one JSON object per line with the account_id, public_key and secret_key.
+pub(crate) struct KeysFileWriter {
+    out: BufWriter<File>,
+}
+
+impl KeysFileWriter {
+    pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed creating {}", path.display()))?;
+        Ok(Self { out: BufWriter::new(file) })
+    }
+
+    pub(crate) fn write(&mut self, account: &GeneratedAccount) -> anyhow::Result<()> {
+        for secret_key in account.keys.iter() {
+            let row = KeysFileRow {
+                account_id: &account.account_id,
+                public_key: &secret_key.public_key(),
+                secret_key,
+            };
+            serde_json::to_writer(&mut self.out, &row)?;
+            self.out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
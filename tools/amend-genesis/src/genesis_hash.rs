@@ -0,0 +1,96 @@
+use crate::records::{self, InputRecord};
+use anyhow::Context;
+use unc_chain_configs::{GenesisConfig, GenesisJsonHasher};
+use unc_primitives::hash::CryptoHash;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Hashes of the output genesis, computed the same way a node computes them when it loads the
+/// genesis config and records files.
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenesisHashes {
+    /// hash of the genesis config followed by all of the records, which a node stores as its
+    /// genesis hash
+    pub genesis_hash: CryptoHash,
+    /// hash of the genesis config alone
+    pub genesis_config_hash: CryptoHash,
+}
+
+impl GenesisHashes {
+    /// Hashes `config` and the records in `records_file`, going through the records once.
+    pub(crate) fn compute(config: &GenesisConfig, records_file: &Path) -> anyhow::Result<Self> {
+        let mut config_hasher = GenesisJsonHasher::new();
+        config_hasher.process_config(config);
+        let mut hasher = GenesisJsonHasher::new();
+        hasher.process_config(config);
+
+        let reader = crate::compression::open_records(records_file)
+            .with_context(|| format!("Failed opening records file {}", records_file.display()))?;
+        records::stream_records(reader, |r| match r {
+            InputRecord::Known(r) => {
+                hasher.process_record(&r);
+                Ok(())
+            }
+            // a node fails to load records it doesn't know about, so there's no hash to give
+            InputRecord::Unknown { tag, .. } => {
+                anyhow::bail!("unknown record type {} can't be hashed like a node would", tag)
+            }
+        })
+        .with_context(|| format!("Failed hashing the records in {}", records_file.display()))?;
+        Ok(Self { genesis_hash: hasher.finalize(), genesis_config_hash: config_hasher.finalize() })
+    }
+
+    /// `template` with `{{genesis_hash}}` and `{{genesis_config_hash}}` replaced by the hashes.
+    fn fill(&self, template: &str) -> String {
+        template
+            .replace("{{genesis_hash}}", &self.genesis_hash.to_string())
+            .replace("{{genesis_config_hash}}", &self.genesis_config_hash.to_string())
+    }
+}
+
+/// A template file to fill in with the [`GenesisHashes`] of the output, given with
+/// --template-out as `in=<PATH>,out=<PATH>`. Every `{{genesis_hash}}` and
+/// `{{genesis_config_hash}}` in the input file is replaced with the hash, and the result is
+/// written to the output file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TemplateOut {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+impl FromStr for TemplateOut {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut input = None;
+        let mut output = None;
+        for field in s.split(',') {
+            match field.split_once('=') {
+                Some(("in", path)) => input = Some(PathBuf::from(path)),
+                Some(("out", path)) => output = Some(PathBuf::from(path)),
+                _ => anyhow::bail!("expected in=<PATH> or out=<PATH>, got {:?}", field),
+            }
+        }
+        match (input, output) {
+            (Some(input), Some(output)) => Ok(Self { input, output }),
+            _ => anyhow::bail!("both in=<PATH> and out=<PATH> have to be given"),
+        }
+    }
+}
+
+impl TemplateOut {
+    pub(crate) fn write(&self, hashes: &GenesisHashes) -> anyhow::Result<()> {
+        let template = std::fs::read_to_string(&self.input)
+            .with_context(|| format!("Failed reading template {}", self.input.display()))?;
+        let filled = hashes.fill(&template);
+        if filled == template {
+            tracing::warn!(
+                target: "amend-genesis",
+                "template {} has no {{{{genesis_hash}}}} or {{{{genesis_config_hash}}}} in it",
+                self.input.display()
+            );
+        }
+        std::fs::write(&self.output, filled)
+            .with_context(|| format!("Failed writing {}", self.output.display()))
+    }
+}
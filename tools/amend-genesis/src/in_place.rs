@@ -0,0 +1,92 @@
+use anyhow::Context;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempPath;
+
+/// Whether `input` and `output` are the same file. A missing output can't be the input.
+pub(crate) fn same_file(input: &Path, output: &Path) -> bool {
+    match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
+}
+
+/// An output file that is also one of the inputs, such as a records file amended in place.
+/// Writing to it directly would truncate the input before it's read through, so the output is
+/// written to a temporary file next to it instead, which `commit_all()` renames over the
+/// original once amend_genesis() has succeeded. If anything fails before that, the temporary
+/// file is deleted when this is dropped and the original is left as it was.
+pub(crate) struct InPlaceOutput {
+    path: PathBuf,
+    tmp: TempPath,
+}
+
+impl InPlaceOutput {
+    pub(crate) fn new(path: &Path) -> anyhow::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        // the temporary file keeps the name of the original at the end, so that a .gz
+        // extension still turns compression on
+        let mut suffix = std::ffi::OsString::from(".");
+        suffix.push(path.file_name().unwrap_or_default());
+        let tmp = tempfile::Builder::new()
+            .prefix(".amend-genesis-")
+            .suffix(&suffix)
+            .tempfile_in(dir)
+            .with_context(|| format!("Failed creating a temporary file in {}", dir.display()))?
+            .into_temp_path();
+        Ok(Self { path: path.to_path_buf(), tmp })
+    }
+
+    /// Where to write the output until it's committed.
+    pub(crate) fn tmp_path(&self) -> &Path {
+        &self.tmp
+    }
+
+    /// Gives the output the permissions of the original, which the temporary file was
+    /// created without, and syncs it to disk.
+    fn prepare(&self) -> anyhow::Result<()> {
+        let tmp_path: &Path = &self.tmp;
+        let permissions = std::fs::metadata(&self.path)
+            .with_context(|| format!("Failed reading the metadata of {}", self.path.display()))?
+            .permissions();
+        std::fs::set_permissions(tmp_path, permissions).with_context(|| {
+            format!("Failed setting the permissions of {}", tmp_path.display())
+        })?;
+        File::open(tmp_path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed syncing {}", tmp_path.display()))
+    }
+
+    /// Renames the output over the original, once `prepare()` succeeded.
+    fn rename(self) -> anyhow::Result<()> {
+        let tmp_path = self.tmp.to_path_buf();
+        self.tmp.persist(&self.path).map_err(|e| e.error).with_context(|| {
+            format!("Failed renaming {} to {}", tmp_path.display(), self.path.display())
+        })?;
+        // the rename itself is only durable once the directory is synced
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)
+                .and_then(|dir| dir.sync_all())
+                .with_context(|| format!("Failed syncing {}", dir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Renames the outputs over their originals in the given order, once all of them are synced to
+/// disk with the permissions of their originals. The files are replaced one after the other,
+/// not together: if a rename fails, the outputs before it have already replaced their
+/// originals, while the originals of the rest are left as they were.
+pub(crate) fn commit_all(outputs: Vec<InPlaceOutput>) -> anyhow::Result<()> {
+    for output in outputs.iter() {
+        output.prepare()?;
+    }
+    for output in outputs {
+        output.rename()?;
+    }
+    Ok(())
+}
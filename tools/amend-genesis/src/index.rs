@@ -0,0 +1,80 @@
+use anyhow::Context;
+use unc_primitives::types::AccountId;
+use unc_primitives_core::account::Account;
+use unc_primitives_core::serialize::dec_format;
+use unc_primitives_core::types::{Balance, StorageUsage};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What amend-genesis did with an account.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    /// The account doesn't exist in the input records, and was added from --validators
+    /// or --extra-records.
+    Added,
+    /// The account exists in the input records, and --validators or --extra-records
+    /// changed it.
+    Modified,
+    /// The account was copied from the input records, possibly with its pledge returned
+    /// to its balance.
+    PassedThrough,
+}
+
+/// One line of the --account-index-out file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccountIndexRow {
+    pub account_id: AccountId,
+    #[serde(with = "dec_format")]
+    pub amount: Balance,
+    #[serde(with = "dec_format")]
+    pub pledging: Balance,
+    pub storage_usage: StorageUsage,
+    /// Number of access keys of the account. Only known for added accounts, since the
+    /// keys of the others are spread over the input records after all the accounts,
+    /// and counting them would mean keeping a counter for every account.
+    pub num_keys: Option<u64>,
+    pub status: AccountStatus,
+}
+
+/// Writes an NDJSON index row for each account as its Account record is written to
+/// the output records.
+pub(crate) struct AccountIndex {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl AccountIndex {
+    pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+        let out = BufWriter::new(File::create(path).with_context(|| {
+            format!("Failed opening account index file {}", path.display())
+        })?);
+        Ok(Self { path: path.to_path_buf(), out })
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        account_id: &AccountId,
+        account: &Account,
+        num_keys: Option<u64>,
+        status: AccountStatus,
+    ) -> anyhow::Result<()> {
+        let row = AccountIndexRow {
+            account_id: account_id.clone(),
+            amount: account.amount(),
+            pledging: account.pledging(),
+            storage_usage: account.storage_usage(),
+            num_keys,
+            status,
+        };
+        serde_json::to_writer(&mut self.out, &row)
+            .map_err(std::io::Error::from)
+            .and_then(|()| self.out.write_all(b"\n"))
+            .with_context(|| format!("Failed writing to {}", self.path.display()))
+    }
+
+    pub(crate) fn finish(mut self) -> anyhow::Result<()> {
+        self.out.flush().with_context(|| format!("Failed writing to {}", self.path.display()))
+    }
+}
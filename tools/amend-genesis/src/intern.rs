@@ -0,0 +1,260 @@
+use unc_crypto::PublicKey;
+use unc_primitives::types::AccountId;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A value shared with every other [`Interned`] handle made for an equal value by the same
+/// [`Interner`]. It hashes and compares like the value itself, so maps keyed by handles can be
+/// looked up with a plain `&T`.
+pub(crate) struct Interned<T>(Arc<T>);
+
+impl<T> Interned<T> {
+    /// The address of the value, the same for all handles sharing it.
+    #[cfg(test)]
+    fn as_ptr(&self) -> *const T {
+        Arc::as_ptr(&self.0)
+    }
+}
+
+impl<T: Clone> Interned<T> {
+    /// Takes the value out, cloning it only if it's still shared with other handles or with
+    /// the interner.
+    pub(crate) fn into_inner(self) -> T {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| T::clone(&shared))
+    }
+}
+
+impl<T> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Borrow<T> for Interned<T> {
+    fn borrow(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: PartialEq> PartialEq for Interned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || *self.0 == *other.0
+    }
+}
+
+impl<T: Eq> Eq for Interned<T> {}
+
+// the same as the hash of the value, which `Borrow` needs
+impl<T: Hash> Hash for Interned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: Ord> PartialOrd for Interned<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Interned<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Keeps one copy of each distinct value handed to it, so that an account or key given in
+/// several places, like in --validators and in each of the --extra-records files, is stored
+/// once however many maps it ends up in.
+pub(crate) struct Interner<T> {
+    values: HashSet<Interned<T>>,
+}
+
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self { values: HashSet::new() }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Interner<T> {
+    /// The handle for `value`, which is only cloned the first time it's seen.
+    pub(crate) fn intern(&mut self, value: &T) -> Interned<T> {
+        if let Some(interned) = self.values.get(value) {
+            return interned.clone();
+        }
+        let interned = Interned(Arc::new(value.clone()));
+        self.values.insert(interned.clone());
+        interned
+    }
+
+    /// Like `intern()`, for a value that's already owned.
+    pub(crate) fn intern_owned(&mut self, value: T) -> Interned<T> {
+        if let Some(interned) = self.values.get(&value) {
+            return interned.clone();
+        }
+        let interned = Interned(Arc::new(value));
+        self.values.insert(interned.clone());
+        interned
+    }
+}
+
+/// The interners of the account IDs and public keys of the records in --validators and
+/// --extra-records, shared while the records to write are put together.
+#[derive(Default)]
+pub(crate) struct Interners {
+    pub(crate) accounts: Interner<AccountId>,
+    pub(crate) keys: Interner<PublicKey>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::Interners;
+    use unc_crypto::{ED25519PublicKey, PublicKey};
+    use unc_primitives::state_record::StateRecord;
+    use unc_primitives::types::{AccountId, AccountInfo};
+    use unc_primitives_core::account::AccessKey;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::HashSet;
+    use tempfile::NamedTempFile;
+
+    struct CountingAllocator;
+
+    // only allocations made by the thread running `peak_bytes()` are counted, so the other
+    // tests in this binary aren't affected
+    thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+        static LIVE_BYTES: Cell<i64> = const { Cell::new(0) };
+        static PEAK_BYTES: Cell<i64> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            // `try_with` because the thread locals may already be gone while a thread exits.
+            if COUNTING.try_with(Cell::get).unwrap_or(false) {
+                let _ = LIVE_BYTES.try_with(|live| {
+                    live.set(live.get() + layout.size() as i64);
+                    let _ = PEAK_BYTES.try_with(|peak| peak.set(peak.get().max(live.get())));
+                });
+            }
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            if COUNTING.try_with(Cell::get).unwrap_or(false) {
+                let _ = LIVE_BYTES.try_with(|live| live.set(live.get() - layout.size() as i64));
+            }
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Returns what `f` returns, and the most bytes the current thread had allocated at once
+    /// while running it.
+    fn peak_bytes<R>(f: impl FnOnce() -> R) -> (R, i64) {
+        LIVE_BYTES.with(|n| n.set(0));
+        PEAK_BYTES.with(|n| n.set(0));
+        COUNTING.with(|c| c.set(true));
+        let ret = f();
+        COUNTING.with(|c| c.set(false));
+        (ret, PEAK_BYTES.with(Cell::get))
+    }
+
+    const NUM_ACCOUNTS: usize = 200_000;
+
+    fn key(shared: bool, i: usize, validator: bool) -> PublicKey {
+        let mut bytes = [0; 32];
+        if !shared {
+            bytes[..8].copy_from_slice(&(i as u64).to_le_bytes());
+        }
+        bytes[8] = validator as u8;
+        ED25519PublicKey(bytes).into()
+    }
+
+    // Validators each given another key in --extra-records. With `shared` set, all validators
+    // have the same key, and all of them are given the same other key.
+    fn input(shared: bool) -> (Vec<AccountInfo>, NamedTempFile) {
+        let account_id = |i: usize| format!("account{}.unc", i).parse::<AccountId>().unwrap();
+        let validators = (0..NUM_ACCOUNTS)
+            .map(|i| AccountInfo {
+                account_id: account_id(i),
+                public_key: key(shared, i, true),
+                pledging: 1,
+                power: 1,
+            })
+            .collect::<Vec<_>>();
+        let records = (0..NUM_ACCOUNTS)
+            .map(|i| StateRecord::AccessKey {
+                account_id: account_id(i),
+                public_key: key(shared, i, false),
+                access_key: AccessKey::full_access(),
+            })
+            .collect::<Vec<_>>();
+        let mut file = NamedTempFile::new().unwrap();
+        serde_json::to_writer(std::io::BufWriter::new(&mut file), &records).unwrap();
+        (validators, file)
+    }
+
+    #[test]
+    fn test_interned_wanted_records() {
+        let build = |shared| {
+            let (mut validators, file) = input(shared);
+            let files = [file.path().to_path_buf()];
+            let (wanted, peak) = peak_bytes(|| {
+                let mut interners = Interners::default();
+                crate::wanted_records(
+                    &mut validators,
+                    &files,
+                    100,
+                    false,
+                    crate::PledgeConflictPolicy::Error,
+                    &mut interners,
+                )
+                .unwrap()
+            });
+            assert_eq!(wanted.len(), NUM_ACCOUNTS);
+            let keys = wanted
+                .values()
+                .flat_map(|r| r.keys.keys().map(|k| k.as_ptr()))
+                .collect::<HashSet<_>>();
+            (keys.len(), peak)
+        };
+        let (num_shared, shared) = build(true);
+        let (num_distinct, distinct) = build(false);
+        assert_eq!(num_shared, 2);
+        assert_eq!(num_distinct, 2 * NUM_ACCOUNTS);
+        // without interning, both inputs take up the same memory, with every account holding
+        // its own copy of both of its keys
+        let saved = (NUM_ACCOUNTS * std::mem::size_of::<PublicKey>()) as i64;
+        assert!(
+            shared + saved <= distinct,
+            "expected at least {saved} fewer bytes with the keys shared, got {shared} vs {distinct}"
+        );
+    }
+}
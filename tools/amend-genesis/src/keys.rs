@@ -0,0 +1,128 @@
+use anyhow::Context;
+use unc_crypto::{KeyFile, KeyType, SecretKey};
+use unc_primitives::types::{AccountId, AccountInfo};
+use std::collections::HashMap;
+use std::path::Path;
+
+// name of the key file a node reads its validator key from
+const VALIDATOR_KEY_FILE: &str = "validator_key.json";
+
+// Parses the entry at `index` in the validators file, naming the entry and the field that
+// couldn't be parsed on failure, rather than only where in the file it failed.
+fn parse_validator(index: usize, entry: serde_json::Value) -> anyhow::Result<AccountInfo> {
+    let fields = entry.as_object();
+    let account_id = fields
+        .and_then(|fields| fields.get("account_id"))
+        .and_then(serde_json::Value::as_str)
+        .map_or_else(String::new, |account_id| format!(" ({})", account_id));
+    if let Some(error) = fields.and_then(crate::records::invalid_field) {
+        anyhow::bail!("validator {}{}: {}", index, account_id, error);
+    }
+    serde_json::from_value(entry)
+        .with_context(|| format!("invalid validator {}{}", index, account_id))
+}
+
+/// Secret keys of the validators, either given in the --validators file or generated with
+/// --generate-keys, which are written out as validator key files with --keys-out-dir.
+#[derive(Default)]
+pub(crate) struct ValidatorKeys {
+    keys: HashMap<AccountId, SecretKey>,
+    // whether the keys were generated, in which case the validators file given no longer
+    // matches the output and a new one is written next to the keys
+    generated: bool,
+}
+
+impl ValidatorKeys {
+    /// Parses the contents of the --validators file, whose entries may have a `secret_key`
+    /// next to the AccountInfo fields.
+    pub(crate) fn parse_validators(s: &str) -> anyhow::Result<(Vec<AccountInfo>, Self)> {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(s)?;
+        let mut validators = Vec::with_capacity(entries.len());
+        let mut keys = HashMap::new();
+        for (index, mut entry) in entries.into_iter().enumerate() {
+            let secret_key = entry.as_object_mut().and_then(|entry| entry.remove("secret_key"));
+            let validator = parse_validator(index, entry)?;
+            if let Some(secret_key) = secret_key {
+                let secret_key = serde_json::from_value(secret_key).with_context(|| {
+                    format!("invalid secret_key given for {}", &validator.account_id)
+                })?;
+                keys.insert(validator.account_id.clone(), secret_key);
+            }
+            validators.push(validator);
+        }
+        Ok((validators, Self { keys, generated: false }))
+    }
+
+    /// Fails if a secret key given doesn't belong to the public key of its validator.
+    pub(crate) fn check(&self, validators: &[AccountInfo]) -> anyhow::Result<()> {
+        for v in validators.iter() {
+            if let Some(secret_key) = self.keys.get(&v.account_id) {
+                if secret_key.public_key() != v.public_key {
+                    anyhow::bail!(
+                        "the secret_key given for validator {} doesn't match its public_key {}",
+                        &v.account_id,
+                        &v.public_key
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates a new key pair for each validator, and replaces its public key with the new
+    /// one.
+    pub(crate) fn generate(validators: &mut [AccountInfo]) -> Self {
+        let mut keys = HashMap::with_capacity(validators.len());
+        for v in validators.iter_mut() {
+            let secret_key = SecretKey::from_random(KeyType::ED25519);
+            v.public_key = secret_key.public_key();
+            keys.insert(v.account_id.clone(), secret_key);
+        }
+        Self { keys, generated: true }
+    }
+
+    /// Writes `<dir>/<account_id>/validator_key.json` for each of `validators` whose secret
+    /// key we have, and if the keys were generated, `<dir>/validators.json` with the
+    /// validators holding the new public keys.
+    pub(crate) fn write(&self, dir: &Path, validators: &[AccountInfo]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed creating {}", dir.display()))?;
+        let mut num_written = 0;
+        for v in validators.iter() {
+            let Some(secret_key) = self.keys.get(&v.account_id) else {
+                tracing::warn!(
+                    target: "amend-genesis",
+                    "no secret_key given for validator {}, so no key file is written for it",
+                    &v.account_id
+                );
+                continue;
+            };
+            let account_dir = dir.join(v.account_id.as_str());
+            std::fs::create_dir_all(&account_dir)
+                .with_context(|| format!("failed creating {}", account_dir.display()))?;
+            let key_file = KeyFile {
+                account_id: v.account_id.clone(),
+                public_key: v.public_key.clone(),
+                private_key: secret_key.clone(),
+            };
+            let path = account_dir.join(VALIDATOR_KEY_FILE);
+            key_file
+                .write_to_file(&path)
+                .with_context(|| format!("failed writing {}", path.display()))?;
+            num_written += 1;
+        }
+        if self.generated {
+            let path = dir.join("validators.json");
+            let validators = serde_json::to_string_pretty(validators)?;
+            std::fs::write(&path, validators)
+                .with_context(|| format!("failed writing {}", path.display()))?;
+        }
+        tracing::info!(
+            target: "amend-genesis",
+            "wrote {} validator key files to {}",
+            num_written,
+            dir.display()
+        );
+        Ok(())
+    }
+}
@@ -1,25 +1,72 @@
 use anyhow::Context;
 
-use unc_chain_configs::{Genesis, GenesisValidationMode};
+use chrono::{DateTime, Utc};
+use unc_chain_configs::Genesis;
 use unc_crypto::PublicKey;
-use unc_primitives::hash::CryptoHash;
-use unc_primitives::shard_layout::ShardLayout;
+use unc_primitives::hash::{hash, CryptoHash};
 use unc_primitives::state_record::StateRecord;
-use unc_primitives::types::{AccountId, AccountInfo};
-use unc_primitives::utils;
+use unc_primitives::types::{AccountId, AccountInfo, ShardId, StoreKey, StoreValue};
 use unc_primitives::version::ProtocolVersion;
 use unc_primitives_core::account::{AccessKey, Account};
-use unc_primitives_core::types::{Balance, BlockHeightDelta, NumBlocks, NumSeats, NumShards, Power};
+use unc_primitives_core::types::{
+    Balance, BlockHeight, BlockHeightDelta, NumBlocks, NumSeats, Power,
+};
 use num_rational::Rational32;
-use serde::ser::{SerializeSeq, Serializer};
 use std::collections::{hash_map, HashMap};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use error::bail;
+use intern::Interned;
+
+mod amend;
+mod chain_id;
+mod checks;
+mod compression;
 mod cli;
+mod dedupe;
+mod diff;
+mod error;
+mod extract;
+mod generate;
+mod genesis_hash;
+mod in_place;
+mod index;
+mod intern;
+mod keys;
+mod overrides;
+mod progress;
+mod records;
+mod remove;
+mod rename;
+mod scale;
+mod shard_layouts;
+mod smoke_test;
+mod split;
+mod storage;
+mod summary;
+mod truncate;
+mod validate;
 
 pub use cli::AmendGenesisCommand;
+pub use compression::{RecordsSink, RecordsSource};
+pub use diff::{AccountChange, AccountDiffRow, AccountSnapshot};
+pub use error::AmendGenesisError;
+pub use generate::AccountGeneration;
+pub use genesis_hash::{GenesisHashes, TemplateOut};
+pub use index::{AccountIndexRow, AccountStatus};
+pub use records::{RecordStats, RecordsFormat};
+pub use shard_layouts::NamedShardLayout;
+pub use smoke_test::SmokeTestReport;
+pub use summary::{AmendGenesisOutcome, AmendPhase, AmendSummary};
+pub use truncate::ContractStateTruncation;
+pub use validate::ValidationMode;
+
+// the balance given by default to validators that don't appear in the input records or in
+// --extra-records, see `AmendOptions::default_validator_balance`
+const DEFAULT_VALIDATOR_AMOUNT: Balance = 10_000 * framework::config::UNC_BASE;
 
 // while parsing the --extra-records file we will keep track of the records we see for each
 // account here, and then at the end figure out what to put in the storage_usage field
@@ -31,11 +78,49 @@ struct AccountRecords {
     // end up seeing the account listed in the input records file, we'll use the total
     // given there
     amount_needed: bool,
-    keys: HashMap<PublicKey, AccessKey>,
+    // whether the account was found in the input records
+    existing: bool,
+    // whether an Account record was given for it in --extra-records, whose balance then
+    // replaces the one in the input records
+    balance_given: bool,
+    keys: HashMap<Interned<PublicKey>, AccessKey>,
     // code state records must appear after the account state record. So for accounts we're
     // modifying/adding keys for, we will remember any code records (there really should only be one),
-    // and add them to the output only after we write the account record
+    // and add them to the output only after we write the account record. The chips given in
+    // --extra-records are kept here too
     extra_records: Vec<StateRecord>,
+    // contract storage key/value pairs given in --extra-records. Like the code records above,
+    // these are written after the account record
+    data: Vec<(StoreKey, StoreValue)>,
+    // code_hash given on the Account record in --extra-records, which takes precedence
+    // over the one in the input records
+    code_hash: Option<CryptoHash>,
+    // contract code given in --extra-records
+    code: Option<Vec<u8>>,
+    // storage usage of the input Contract and Data records left out with
+    // --truncate-contract-state, --strip-contracts or --replace-contract-code
+    state_dropped: u64,
+    // storage usage of the account's input records other than its Account record, tallied
+    // with --recompute-storage-usage and added in place of the input storage usage
+    storage_tallied: u64,
+    // whether the power was given in --validators, in which case it replaces the one in the
+    // input records
+    power_given: bool,
+    // the Account record found in the input records, to compare against in the --diff-file
+    input: Option<Account>,
+    // keys found in the input records whose access key was replaced by the one in
+    // --validators or --extra-records
+    keys_replaced: Vec<PublicKey>,
+}
+
+/// Same as `Genesis::to_file()`, but returns failures instead of panicking.
+fn write_genesis(genesis: &Genesis, path: &Path) -> Result<(), AmendGenesisError> {
+    let json = serde_json::to_vec_pretty(genesis)
+        .context("Failed serializing the output genesis config")
+        .map_err(AmendGenesisError::Internal)?;
+    std::fs::write(path, json)
+        .with_context(|| format!("Failed writing output genesis config {}", path.display()))
+        .map_err(AmendGenesisError::Io)
 }
 
 // set the total balance to what's in src, keeping the pledging amount the same
@@ -46,6 +131,15 @@ fn set_total_balance(dst: &mut Account, src: &Account) {
     }
 }
 
+// pledge * ratio, rounded down and saturating at Power::MAX
+fn pledge_to_power(pledge: Balance, ratio: Rational32) -> Power {
+    let numer = *ratio.numer() as u128;
+    let denom = *ratio.denom() as u128;
+    let power =
+        (pledge / denom).saturating_mul(numer).saturating_add(pledge % denom * numer / denom);
+    Power::try_from(power).unwrap_or(Power::MAX)
+}
+
 impl AccountRecords {
     fn new(amount: Balance, pledging: Balance, power: Power, num_bytes_account: u64) -> Self {
         let mut ret = Self::default();
@@ -73,8 +167,10 @@ impl AccountRecords {
                 // records. Set the storage usage to reflect whatever's in the original records, and at the
                 // end we will add to the storage usage with any extra keys added for this account
                 account.set_storage_usage(existing.storage_usage());
-                account.set_code_hash(existing.code_hash());
-                account.set_power(existing.power());
+                account.set_code_hash(self.code_hash.unwrap_or(existing.code_hash()));
+                if !self.power_given {
+                    account.set_power(existing.power());
+                }
                 if self.amount_needed {
                     set_total_balance(account, existing);
                 }
@@ -88,143 +184,386 @@ impl AccountRecords {
             }
         }
         self.amount_needed = false;
+        self.existing = true;
+        self.input = Some(existing.clone());
+    }
+
+    // Puts back the pledge and power of `existing` after update_from_existing(), for the
+    // validators of the input genesis kept with --append-validators or without --validators.
+    // The rest of the balance stays in the amount.
+    fn keep_pledge(&mut self, existing: &Account) {
+        let account = self.account.as_mut().unwrap();
+        let total = account.amount() + account.pledging();
+        account.set_pledging(existing.pledging());
+        account.set_amount(total.saturating_sub(existing.pledging()));
+        account.set_power(existing.power());
     }
 
     fn push_extra_record(&mut self, record: StateRecord) {
         self.extra_records.push(record);
     }
 
-    fn write_out<S: SerializeSeq>(
-        self,
-        account_id: AccountId,
-        seq: &mut S,
-        total_supply: &mut Balance,
-        num_extra_bytes_record: u64,
-    ) -> anyhow::Result<()>
-    where
-        <S as SerializeSeq>::Error: Send + Sync + 'static,
-    {
-        match self.account {
-            Some(mut account) => {
-                for (public_key, access_key) in self.keys {
-                    let storage_usage = account.storage_usage()
-                        + public_key.len() as u64
-                        + borsh::object_length(&access_key).unwrap() as u64
-                        + num_extra_bytes_record;
-                    account.set_storage_usage(storage_usage);
-
-                    seq.serialize_element(&StateRecord::AccessKey {
-                        account_id: account_id.clone(),
-                        public_key,
-                        access_key,
-                    })?;
-                }
-                if self.amount_needed {
-                    account.set_amount(10_000 * framework::config::UNC_BASE);
-                }
-                *total_supply += account.amount() + account.pledging();
-                seq.serialize_element(&StateRecord::Account { account_id, account })?;
-                for record in self.extra_records.iter() {
-                    seq.serialize_element(record)?;
-                }
-            }
-            None => {
-                tracing::warn!("access keys for {} were included in --extra-records, but no Account record was found. Not adding them to the output", &account_id);
+    // whether any chips were given for the account in --extra-records
+    fn has_rsa_keys(&self) -> bool {
+        self.extra_records.iter().any(|r| matches!(r, StateRecord::Rsa2048Keys { .. }))
+    }
+
+    // whether a chip with the given key was given for the account in --extra-records
+    fn has_rsa_key(&self, public_key: &PublicKey) -> bool {
+        self.extra_records.iter().any(|r| {
+            matches!(r, StateRecord::Rsa2048Keys { public_key: key, .. } if key == public_key)
+        })
+    }
+
+    // records a chip given in --extra-records, replacing any given before with the same key
+    fn set_rsa_key(&mut self, record: StateRecord) {
+        if let StateRecord::Rsa2048Keys { public_key, .. } = &record {
+            let public_key = public_key.clone();
+            self.extra_records.retain(|r| {
+                !matches!(r, StateRecord::Rsa2048Keys { public_key: key, .. } if *key == public_key)
+            });
+        }
+        self.extra_records.push(record);
+    }
+
+    // records the code_hash given on an Account record in --extra-records
+    fn set_code_hash(&mut self, code_hash: CryptoHash) {
+        self.code_hash = Some(code_hash);
+        if let Some(account) = &mut self.account {
+            account.set_code_hash(code_hash);
+        }
+    }
+
+    // checks that the code_hash of the account matches its contract code, given either in
+    // --extra-records or in the input records
+    fn check_code_hash(
+        &self,
+        account_id: &AccountId,
+        account: &Account,
+    ) -> Result<(), AmendGenesisError> {
+        let code = self.code.as_deref().or_else(|| {
+            self.extra_records.iter().find_map(|r| match r {
+                StateRecord::Contract { code, .. } => Some(code.as_slice()),
+                _ => None,
+            })
+        });
+        match code {
+            Some(code) if hash(code) != account.code_hash() => bail!(
+                Validation,
+                "account {} has code_hash {}, but the hash of its contract code is {}",
+                account_id,
+                account.code_hash(),
+                hash(code)
+            ),
+            None if self.code_hash.is_some_and(|h| h != CryptoHash::default()) => {
+                bail!(
+                    Validation,
+                    "account {} has code_hash {} in --extra-records, but no Contract record was \
+                    found for it in the input records or --extra-records",
+                    account_id,
+                    account.code_hash()
+                )
             }
+            _ => Ok(()),
         }
-        Ok(())
     }
 }
 
 fn validator_records(
     validators: &[AccountInfo],
     num_bytes_account: u64,
-) -> anyhow::Result<HashMap<AccountId, AccountRecords>> {
+    keep_existing_power: bool,
+    interners: &mut intern::Interners,
+) -> Result<HashMap<Interned<AccountId>, AccountRecords>, AmendGenesisError> {
     let mut records = HashMap::new();
     for AccountInfo { account_id, public_key, pledging, power } in validators.iter() {
         let mut r: AccountRecords = AccountRecords::new_validator(*pledging,  *power, *pledging, num_bytes_account);
-        r.keys.insert(public_key.clone(), AccessKey::full_access());
-        if records.insert(account_id.clone(), r).is_some() {
-            anyhow::bail!("validator {} specified twice", account_id);
+        r.power_given = !keep_existing_power;
+        r.keys.insert(interners.keys.intern(public_key), AccessKey::full_access());
+        if records.insert(interners.accounts.intern(account_id), r).is_some() {
+            bail!(Validation, "validator {} specified twice", account_id);
         }
     }
     Ok(records)
 }
 
-fn parse_validators(path: &Path) -> anyhow::Result<Vec<AccountInfo>> {
+fn parse_validators(
+    path: &Path,
+) -> Result<(Vec<AccountInfo>, keys::ValidatorKeys), AmendGenesisError> {
     let validators = std::fs::read_to_string(path)
-        .with_context(|| format!("failed reading from {}", path.display()))?;
-    let validators = serde_json::from_str(&validators)
-        .with_context(|| format!("failed deserializing from {}", path.display()))?;
-    Ok(validators)
+        .with_context(|| format!("failed reading from {}", path.display()))
+        .map_err(AmendGenesisError::Io)?;
+    let (validators, keys) = keys::ValidatorKeys::parse_validators(&validators)
+        .with_context(|| format!("failed deserializing from {}", path.display()))
+        .map_err(AmendGenesisError::InputParse)?;
+    keys.check(&validators).map_err(AmendGenesisError::Validation)?;
+    Ok((validators, keys))
 }
 
 fn parse_extra_records(
     records_file: &Path,
     num_bytes_account: u64,
-) -> anyhow::Result<HashMap<AccountId, AccountRecords>> {
-    let reader =
-        BufReader::new(File::open(records_file).with_context(|| {
-            format!("Failed opening validators file {}", records_file.display())
-        })?);
+    interners: &mut intern::Interners,
+) -> Result<HashMap<Interned<AccountId>, AccountRecords>, AmendGenesisError> {
+    let reader = BufReader::new(
+        File::open(records_file)
+            .with_context(|| format!("Failed opening extra records {}", records_file.display()))
+            .map_err(AmendGenesisError::Io)?,
+    );
     let mut records = HashMap::new();
 
     let mut result = Ok(());
-    unc_chain_configs::stream_records_from_file(reader, |r| {
+    // number of records seen so far, to tell which one an error is about
+    let mut num_records = 0u64;
+    records::stream_records(reader, |r| {
+        let index = num_records;
+        num_records += 1;
+        let r = match r {
+            records::InputRecord::Known(r) => r,
+            records::InputRecord::Unknown { tag, .. } => {
+                result = Err(AmendGenesisError::Validation(anyhow::anyhow!(
+                    "unknown record type {} given in extra records, at record {}",
+                    tag,
+                    index
+                )));
+                return Ok(());
+            }
+        };
         match r {
             StateRecord::Account { account_id, account } => {
-                if account.code_hash() != CryptoHash::default() {
-                    result = Err(anyhow::anyhow!(
-                        "FIXME: accounts in --extra-records with code_hash set not supported"
-                    ));
-                }
-                match records.entry(account_id.clone()) {
-                    hash_map::Entry::Vacant(e) => {
-                        let r = AccountRecords::new(
-                            account.amount(),
-                            account.pledging(),
-                            account.power(),
-                            num_bytes_account,
-                        );
-                        e.insert(r);
-                    }
-                    hash_map::Entry::Occupied(mut e) => {
-                        let r = e.get_mut();
+                let r = match records.entry(interners.accounts.intern(&account_id)) {
+                    hash_map::Entry::Vacant(e) => e.insert(AccountRecords::new(
+                        account.amount(),
+                        account.pledging(),
+                        account.power(),
+                        num_bytes_account,
+                    )),
+                    hash_map::Entry::Occupied(e) => {
+                        let r = e.into_mut();
 
                         if r.account.is_some() {
-                            result = Err(anyhow::anyhow!(
+                            result = Err(AmendGenesisError::Validation(anyhow::anyhow!(
                                 "account {} given twice in extra records",
                                 &account_id
-                            ));
+                            )));
+                            return Ok(());
                         }
                         r.set_account(account.amount(), account.pledging(), account.power(), num_bytes_account);
+                        r
                     }
+                };
+                r.balance_given = true;
+                if account.code_hash() != CryptoHash::default() {
+                    r.set_code_hash(account.code_hash());
+                }
+            }
+            StateRecord::Contract { account_id, code } => {
+                let r = records.entry(interners.accounts.intern(&account_id)).or_default();
+                if r.code.is_some() {
+                    result = Err(AmendGenesisError::Validation(anyhow::anyhow!(
+                        "contract code for {} given twice in extra records",
+                        &account_id
+                    )));
                 }
+                r.code = Some(code);
             }
             StateRecord::AccessKey { account_id, public_key, access_key } => {
-                records.entry(account_id).or_default().keys.insert(public_key, access_key);
+                let public_key = interners.keys.intern_owned(public_key);
+                let r = records.entry(interners.accounts.intern_owned(account_id)).or_default();
+                r.keys.insert(public_key, access_key);
+            }
+            StateRecord::Data { account_id, data_key, value } => {
+                let r = records.entry(interners.accounts.intern_owned(account_id)).or_default();
+                r.data.push((data_key, value));
+            }
+            StateRecord::Rsa2048Keys { account_id, public_key, rsa_key } => {
+                let r = records.entry(interners.accounts.intern(&account_id)).or_default();
+                r.set_rsa_key(StateRecord::Rsa2048Keys { account_id, public_key, rsa_key });
             }
             _ => {
-                result = Err(anyhow::anyhow!(
-                    "FIXME: only Account and AccessKey records are supported in --extra-records"
-                ));
+                result = Err(AmendGenesisError::Validation(anyhow::anyhow!(
+                    "FIXME: only Account, AccessKey, Contract, Data and Rsa2048Keys records are \
+                    supported in --extra-records, but record {} is a {} record",
+                    index,
+                    r.get_type_string()
+                )));
             }
         };
+        Ok(())
     })
-    .context("Failed deserializing records from --extra-records")?;
+    .with_context(|| format!("Failed deserializing extra records {}", records_file.display()))
+    .map_err(AmendGenesisError::InputParse)?;
+    result?;
 
     Ok(records)
 }
 
-fn wanted_records(
+// Parses each of the --extra-records files and merges them. An account can only get its
+// Account record and its contract code from one of the files, but its keys and data can come
+// from several, with a key given again in a later file replacing the earlier one.
+fn parse_all_extra_records(
+    records_files: &[PathBuf],
+    num_bytes_account: u64,
+    interners: &mut intern::Interners,
+) -> Result<HashMap<Interned<AccountId>, AccountRecords>, AmendGenesisError> {
+    let mut records: HashMap<Interned<AccountId>, AccountRecords> = HashMap::new();
+    // the file each Account record and contract code was given in
+    let mut account_files = HashMap::new();
+    let mut code_files = HashMap::new();
+
+    for path in records_files {
+        for (account_id, mut from) in parse_extra_records(path, num_bytes_account, interners)? {
+            if from.account.is_some() {
+                if let Some(first) = account_files.insert(account_id.clone(), path) {
+                    bail!(
+                        Validation,
+                        "account {} given in both extra records {} and {}",
+                        account_id,
+                        first.display(),
+                        path.display()
+                    );
+                }
+            }
+            if from.code.is_some() {
+                if let Some(first) = code_files.insert(account_id.clone(), path) {
+                    bail!(
+                        Validation,
+                        "contract code for {} given in both extra records {} and {}",
+                        account_id,
+                        first.display(),
+                        path.display()
+                    );
+                }
+            }
+            let into = match records.entry(account_id) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(from);
+                    continue;
+                }
+                hash_map::Entry::Occupied(e) => e.into_mut(),
+            };
+            if from.account.is_some() {
+                // keep the records built from the Account record, and move the earlier keys
+                // and data over to them
+                std::mem::swap(into, &mut from);
+                for (public_key, access_key) in from.keys {
+                    into.keys.entry(public_key).or_insert(access_key);
+                }
+                from.data.append(&mut into.data);
+                into.data = from.data;
+                for record in from.extra_records {
+                    let given = match &record {
+                        StateRecord::Rsa2048Keys { public_key, .. } => into.has_rsa_key(public_key),
+                        _ => false,
+                    };
+                    if !given {
+                        into.push_extra_record(record);
+                    }
+                }
+            } else {
+                into.keys.extend(from.keys);
+                into.data.extend(from.data);
+                for record in from.extra_records {
+                    into.set_rsa_key(record);
+                }
+            }
+            if into.code.is_none() {
+                into.code = from.code;
+            }
+        }
+    }
+    Ok(records)
+}
+
+// Finds the validators whose Account record in `extra` has a pledge other than the one in
+// --validators, sorted by account ID. An Account record with no pledge only gives the total
+// balance, so it doesn't conflict with any.
+fn pledge_conflicts(
     validators: &[AccountInfo],
-    extra_records: Option<&Path>,
+    extra: &HashMap<Interned<AccountId>, AccountRecords>,
+) -> Vec<(AccountId, Balance, Account)> {
+    let mut conflicts = validators
+        .iter()
+        .filter_map(|v| {
+            let account = extra.get(&v.account_id)?.account.as_ref()?;
+            (account.pledging() != 0 && account.pledging() != v.pledging)
+                .then(|| (v.account_id.clone(), v.pledging, account.clone()))
+        })
+        .collect::<Vec<_>>();
+    conflicts.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+    conflicts
+}
+
+fn wanted_records(
+    validators: &mut [AccountInfo],
+    extra_records: &[PathBuf],
     num_bytes_account: u64,
-) -> anyhow::Result<HashMap<AccountId, AccountRecords>> {
-    let mut records = validator_records(validators, num_bytes_account)?;
+    keep_existing_power: bool,
+    pledge_conflict_policy: PledgeConflictPolicy,
+    interners: &mut intern::Interners,
+) -> Result<HashMap<Interned<AccountId>, AccountRecords>, AmendGenesisError> {
+    let mut records =
+        validator_records(validators, num_bytes_account, keep_existing_power, interners)?;
+
+    if !extra_records.is_empty() {
+        let extra = parse_all_extra_records(extra_records, num_bytes_account, interners)?;
 
-    if let Some(path) = extra_records {
-        let extra = parse_extra_records(path, num_bytes_account)?;
+        // all checked before anything is merged, so that every conflict is reported at once
+        let conflicts = pledge_conflicts(validators, &extra);
+        if !conflicts.is_empty() && pledge_conflict_policy == PledgeConflictPolicy::Error {
+            let conflicts = conflicts
+                .iter()
+                .map(|(account_id, pledge, account)| {
+                    let total = account.amount() + account.pledging();
+                    format!(
+                        "{} has a pledge of {} in --validators, leaving it an amount of {}, and a \
+                        pledge of {} with an amount of {} in --extra-records",
+                        account_id,
+                        pledge,
+                        total.saturating_sub(*pledge),
+                        account.pledging(),
+                        account.amount()
+                    )
+                })
+                .collect::<Vec<_>>();
+            bail!(
+                Conflict,
+                "{} validators are given different pledges in --validators and --extra-records \
+                (use --prefer-validators or --prefer-extra-records to pick one): {}",
+                conflicts.len(),
+                conflicts.join("; ")
+            );
+        }
+        for (account_id, pledge, account) in conflicts.iter() {
+            match pledge_conflict_policy {
+                PledgeConflictPolicy::Error => unreachable!(),
+                PledgeConflictPolicy::PreferValidators => tracing::info!(
+                    target: "amend-genesis",
+                    "{}: keeping the pledge of {} in --validators over the {} in \
+                    --extra-records, with the rest of its balance of {} as its amount",
+                    account_id,
+                    pledge,
+                    account.pledging(),
+                    account.amount() + account.pledging()
+                ),
+                PledgeConflictPolicy::PreferExtraRecords => {
+                    tracing::info!(
+                        target: "amend-genesis",
+                        "{}: taking the pledge of {} in --extra-records over the {} in \
+                        --validators, which the output genesis config gets as well",
+                        account_id,
+                        account.pledging(),
+                        pledge
+                    );
+                    // the records of every validator were made above
+                    let validator = validators.iter_mut().find(|v| v.account_id == *account_id);
+                    validator.unwrap().pledging = account.pledging();
+                    let validator_account =
+                        records.get_mut(account_id).unwrap().account.as_mut().unwrap();
+                    validator_account.set_pledging(account.pledging());
+                }
+            }
+        }
 
         for (account_id, account_records) in extra {
             match records.entry(account_id) {
@@ -234,8 +573,15 @@ fn wanted_records(
                     if let Some(account) = &account_records.account {
                         set_total_balance(validator_records.account.as_mut().unwrap(), account);
                         validator_records.amount_needed = false;
+                        validator_records.balance_given = true;
+                    }
+                    if let Some(code_hash) = account_records.code_hash {
+                        validator_records.set_code_hash(code_hash);
                     }
+                    validator_records.code = account_records.code;
                     validator_records.keys.extend(account_records.keys);
+                    validator_records.data.extend(account_records.data);
+                    validator_records.extra_records.extend(account_records.extra_records);
                 }
                 hash_map::Entry::Vacant(e) => {
                     e.insert(account_records);
@@ -247,11 +593,51 @@ fn wanted_records(
     Ok(records)
 }
 
-#[derive(Default)]
+/// What to do with a validator whose pledge in the validators file differs from the one on its
+/// Account record in the extra records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PledgeConflictPolicy {
+    /// fail, listing every such validator with both pledges and amounts
+    #[default]
+    Error,
+    /// keep the pledge in the validators file. The account keeps the total balance given in
+    /// the extra records, with what's left after the pledge as its amount
+    PreferValidators,
+    /// take the pledge and the amount in the extra records, and give the validator that
+    /// pledge in the output genesis config as well
+    PreferExtraRecords,
+}
+
+/// What to do when a shard of the output shard layout has no Account records, which some node
+/// versions handle poorly when computing the genesis state roots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyShardPolicy {
+    /// don't check
+    #[default]
+    Allow,
+    /// log a warning naming the empty shards
+    Warn,
+    /// fail, naming the empty shards
+    Error,
+}
+
+#[derive(Clone, Default)]
 pub struct GenesisChanges {
+    /// replaces the chain ID of the input genesis. `{date}` in it is replaced with the current
+    /// UTC date as YYYY-MM-DD
     pub chain_id: Option<String>,
+    /// appended to the chain ID of the input genesis, with `{date}` replaced like in
+    /// `chain_id`. Can't be set along with `chain_id`
+    pub chain_id_suffix: Option<String>,
     pub protocol_version: Option<ProtocolVersion>,
     pub num_seats: Option<NumSeats>,
+    /// block producer seats of each shard of the output shard layout, in place of the ones
+    /// derived from `num_seats`. There has to be one per shard
+    pub num_block_producer_seats_per_shard: Option<Vec<NumSeats>>,
+    /// hidden validator seats of each shard of the output shard layout, in place of those of
+    /// the input genesis, or 0 for every shard with a new shard layout. There has to be one per
+    /// shard
+    pub avg_hidden_validator_seats_per_shard: Option<Vec<NumSeats>>,
     pub epoch_length: Option<BlockHeightDelta>,
     pub transaction_validity_period: Option<NumBlocks>,
     pub protocol_reward_rate: Option<Rational32>,
@@ -259,155 +645,400 @@ pub struct GenesisChanges {
     pub chunk_producer_kickout_threshold: Option<u8>,
     pub min_gas_price: Option<Balance>,
     pub max_gas_price: Option<Balance>,
+    pub genesis_height: Option<BlockHeight>,
+    pub genesis_time: Option<DateTime<Utc>>,
+    pub max_inflation_rate: Option<Rational32>,
+    pub num_blocks_per_year: Option<NumBlocks>,
+    pub protocol_treasury_account: Option<AccountId>,
+}
+
+/// Options controlling how the input records are processed, as opposed to
+/// `GenesisChanges` which are applied to the output genesis config.
+#[derive(Clone, Default)]
+pub struct AmendOptions {
+    /// fail on records of a type this build doesn't know about instead of passing
+    /// them through to the output unchanged
+    pub strict_record_types: bool,
+    /// leave out all but the first of the Account records of the same account, and of the
+    /// AccessKey records of the same key, in the input records, instead of failing on them
+    pub dedupe_input: bool,
+    /// only warn about sanity check failures on the output genesis config instead of failing
+    pub force: bool,
+    /// only warn if `GenesisChanges::num_seats` is 0, more than the number of output
+    /// validators, or too few to give every shard a seat, instead of failing
+    pub allow_seat_mismatch: bool,
+    /// check that `num_bytes_account` and `num_extra_bytes_record` match the runtime config
+    /// of the output protocol version
+    pub check_storage_params: bool,
+    /// ignore the `num_bytes_account` and `num_extra_bytes_record` passed to `amend_genesis()`
+    /// and take them from the runtime config of the output protocol version instead
+    pub storage_params_from_protocol: bool,
+    /// allow validators with no balance in the input records or extra records to have
+    /// a pledge larger than the default amount they're given
+    pub allow_unfunded_pledges: bool,
+    /// balance to give validators with no balance in the input records or extra records.
+    /// Defaults to 10,000 UNC
+    pub default_validator_balance: Option<Balance>,
+    /// give accounts that have access keys in the extra records but no Account record in the
+    /// input records, the extra records or the validators a new Account record with this
+    /// balance, instead of leaving their keys out of the output
+    pub create_missing_accounts: Option<Balance>,
+    /// after writing the output, initialize the genesis state from it in a temporary store
+    /// the same way a node would on its first start, and fail if that doesn't work
+    pub smoke_test: bool,
+    /// after writing the output, hash it the way a node does when it loads it, and return the
+    /// hashes in `AmendGenesisOutcome::genesis_hashes`
+    pub emit_genesis_hash: bool,
+    /// templates to fill in with the hashes of the output, which are computed for them even
+    /// without `emit_genesis_hash`
+    pub template_out: Vec<TemplateOut>,
+    /// JSON map from old to new account IDs. The accounts are renamed in the input records,
+    /// along with the receiver_id of any function call access keys pointing at them
+    pub rename_accounts: Option<PathBuf>,
+    /// JSON list of account IDs whose Account, AccessKey, Contract and Data records are left
+    /// out of the output, along with the receipts and received data bound for them. These
+    /// must not be among the validators
+    pub remove_accounts: Option<PathBuf>,
+    /// JSON list of account IDs to keep. All records of other accounts are left out of the
+    /// output, except for the validators, the protocol treasury account and the accounts in
+    /// the extra records
+    pub keep_only_accounts: Option<PathBuf>,
+    /// what to do if a shard of the output shard layout ends up with no Account records, as
+    /// `remove_accounts`, `keep_only_accounts` or a new shard layout can leave it
+    pub empty_shard_policy: EmptyShardPolicy,
+    /// CSV file with rows of `account_id,amount[,pledge]`, whose balances are set on the
+    /// Account records of those accounts in the input records before anything else is done
+    /// with them. Accounts renamed with `rename_accounts` are given by their new IDs
+    pub balance_overrides: Option<PathBuf>,
+    /// fail if any of the accounts in `balance_overrides` isn't in the input records, instead
+    /// of only warning about it
+    pub strict_overrides: bool,
+    /// write an NDJSON index with a row per output account, see `AccountIndexRow`
+    pub account_index_out: Option<PathBuf>,
+    /// also write the output records split by shard to `records_shard_<shard_id>.json` files
+    /// in this directory, routed by account under the shard layout given with
+    /// `AmendGenesisConfig::shard_layout_file` or `AmendGenesisConfig::shard_layout`, one of
+    /// which has to be set
+    pub records_out_dir: Option<PathBuf>,
+    /// write an NDJSON row for each account added, removed or changed compared to the input
+    /// records, see `AccountDiffRow`. Written with `dry_run` as well
+    pub diff_file: Option<PathBuf>,
+    /// set the power of each validator, both in the output genesis config and on its
+    /// account record, to its pledge times this ratio
+    pub pledge_to_power: Option<Rational32>,
+    /// balance to give the account set with `GenesisChanges::protocol_treasury_account` if it
+    /// has to be created because it doesn't exist in the input records or the extra records
+    pub protocol_treasury_balance: Balance,
+    /// fail unless the total supply of the output is this. The error lists the largest
+    /// balances set from the validators and the extra records
+    pub expected_total_supply: Option<Balance>,
+    /// set the amount of the protocol treasury account to whatever makes the total supply
+    /// `expected_total_supply`, which has to be set
+    pub balance_treasury: bool,
+    /// only keep the records stored in this shard under the input shard layout, along with
+    /// the validators whose accounts are in it, and write a genesis with a single shard
+    pub extract_shard: Option<ShardId>,
+    /// how to check the input genesis config and records before amending them
+    pub validation_mode: ValidationMode,
+    /// after writing the output, run full genesis validation on it and fail if it doesn't pass
+    pub validate_output: bool,
+    /// how to lay out the output records
+    pub output_format: RecordsFormat,
+    /// indent the output records with one field per line, for reading them by hand. Only
+    /// for `RecordsFormat::JsonArray`. The output genesis config is always written this way
+    pub pretty: bool,
+    /// write a validator_key.json for each output validator whose secret key is known to
+    /// `<keys_out_dir>/<account_id>/`. Secret keys can be given with a `secret_key` field
+    /// on the entries of the validators file, or generated with `generate_validator_keys`
+    pub keys_out_dir: Option<PathBuf>,
+    /// give each validator a freshly generated key pair in place of the public key in the
+    /// validators file, both in the output genesis config and in the access keys added for
+    /// it. The validators with their new keys are written to `keys_out_dir`, which must be
+    /// set, as validators.json
+    pub generate_validator_keys: bool,
+    /// keep the validators of the input genesis config, with their pledges, and add the ones
+    /// in the validators file to them instead of replacing them. A validator in both is an
+    /// error
+    pub append_validators: bool,
+    /// keep the power of validators that exist in the input records instead of setting it to
+    /// the one in the validators file. Only validators that don't exist yet get theirs
+    pub keep_existing_power: bool,
+    /// what to do with validators given a different pledge on their Account record in the
+    /// extra records
+    pub pledge_conflict_policy: PledgeConflictPolicy,
+    /// accounts whose Data records are cut down to the given number of keys or value size,
+    /// with their storage usage lowered to match
+    pub truncate_contract_state: Vec<ContractStateTruncation>,
+    /// leave all Contract and Data records out of the output, setting the code_hash of every
+    /// account to the default one and lowering its storage usage to match
+    pub strip_contracts: bool,
+    /// file with wasm code to replace the code of every Contract record in the input records
+    /// with. The code_hash and storage usage of the accounts are changed to match
+    pub replace_contract_code: Option<PathBuf>,
+    /// set the storage usage of every account to the size of its records in the output,
+    /// whatever it is in the input records. The input records are read twice, so they have to
+    /// be given as a file
+    pub recompute_storage_usage: bool,
+    /// synthetic accounts with full access keys to add to the output, for load tests
+    pub generate_accounts: Option<AccountGeneration>,
+    /// write the secret keys of the accounts from `generate_accounts` to this file, as one
+    /// JSON object per line
+    pub generated_keys_out: Option<PathBuf>,
+    /// multiply the amount and pledge of every output account, other than the ones from
+    /// `generate_accounts`, and the pledges of the validators by this ratio, rounding down.
+    /// What's rounded away is given to the protocol treasury account, so the total supply
+    /// is the input one times the ratio, rounded down
+    pub scale_balances: Option<Rational32>,
+    /// go through the input records and run all the checks on the output, but don't write
+    /// any output files. The returned `AmendGenesisOutcome` is the same as without it
+    pub dry_run: bool,
+    /// keep the `AmendSummary` so far in this file while running, rewriting it every
+    /// `summary_interval`. The last one written has `complete` set
+    pub summary_out: Option<PathBuf>,
+    /// how often to rewrite `summary_out`. Defaults to 10 seconds
+    pub summary_interval: Option<Duration>,
+    /// log a progress line with the rate and the estimated time left every this many input
+    /// records. Defaults to 1,000,000
+    pub progress_interval: Option<u64>,
+    // only set by tests, to make runs slow enough to see the summary being rewritten
+    pub(crate) record_delay: Option<Duration>,
+}
+
+/// `AmendGenesisConfig::num_bytes_account` unless set otherwise
+pub const DEFAULT_NUM_BYTES_ACCOUNT: u64 = 100;
+/// `AmendGenesisConfig::num_extra_bytes_record` unless set otherwise
+pub const DEFAULT_NUM_EXTRA_BYTES_RECORD: u64 = 40;
+
+/// The inputs and outputs of amending a genesis/records file created by `dump-state`, along
+/// with what to change. The genesis files and the records have to be set before calling
+/// `amend()`, everything else is optional.
+pub struct AmendGenesisConfig {
+    genesis_file_in: Option<PathBuf>,
+    genesis_file_out: Option<PathBuf>,
+    records_in: Option<RecordsSource>,
+    records_out: Option<RecordsSink>,
+    validators: Option<PathBuf>,
+    extra_records: Vec<PathBuf>,
+    shard_layout_file: Option<PathBuf>,
+    shard_layout: Option<NamedShardLayout>,
+    genesis_changes: GenesisChanges,
+    num_bytes_account: u64,
+    num_extra_bytes_record: u64,
+    options: AmendOptions,
+}
+
+impl Default for AmendGenesisConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AmendGenesisConfig {
+    pub fn new() -> Self {
+        Self {
+            genesis_file_in: None,
+            genesis_file_out: None,
+            records_in: None,
+            records_out: None,
+            validators: None,
+            extra_records: Vec::new(),
+            shard_layout_file: None,
+            shard_layout: None,
+            genesis_changes: GenesisChanges::default(),
+            num_bytes_account: DEFAULT_NUM_BYTES_ACCOUNT,
+            num_extra_bytes_record: DEFAULT_NUM_EXTRA_BYTES_RECORD,
+            options: AmendOptions::default(),
+        }
+    }
+
+    /// genesis config to amend
+    pub fn genesis_file_in(mut self, path: impl Into<PathBuf>) -> Self {
+        self.genesis_file_in = Some(path.into());
+        self
+    }
+
+    /// where to write the amended genesis config
+    pub fn genesis_file_out(mut self, path: impl Into<PathBuf>) -> Self {
+        self.genesis_file_out = Some(path.into());
+        self
+    }
+
+    /// records to amend, either a file or a stream
+    pub fn records_in(mut self, records_in: impl Into<RecordsSource>) -> Self {
+        self.records_in = Some(records_in.into());
+        self
+    }
+
+    /// where to write the amended records, either a file or a stream
+    pub fn records_out(mut self, records_out: impl Into<RecordsSink>) -> Self {
+        self.records_out = Some(records_out.into());
+        self
+    }
+
+    /// JSON list of the validators of the output genesis. Without it the validators of the
+    /// input genesis are kept as they are, along with their pledges
+    pub fn validators(mut self, path: impl Into<PathBuf>) -> Self {
+        self.validators = Some(path.into());
+        self
+    }
+
+    /// records to add to the output, in addition to the accounts of the validators. Can be
+    /// called more than once to merge the records of several files
+    pub fn extra_records(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_records.push(path.into());
+        self
+    }
+
+    /// JSON shard layout to set in the output genesis config
+    pub fn shard_layout_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shard_layout_file = Some(path.into());
+        self
+    }
+
+    /// built-in shard layout to set in the output genesis config, in place of one read from
+    /// `shard_layout_file`. Only one of them can be set
+    pub fn shard_layout(mut self, shard_layout: NamedShardLayout) -> Self {
+        self.shard_layout = Some(shard_layout);
+        self
+    }
+
+    pub fn genesis_changes(mut self, genesis_changes: GenesisChanges) -> Self {
+        self.genesis_changes = genesis_changes;
+        self
+    }
+
+    /// storage usage of an account without any data, used to compute the storage usage of
+    /// accounts. Defaults to `DEFAULT_NUM_BYTES_ACCOUNT`
+    pub fn num_bytes_account(mut self, num_bytes_account: u64) -> Self {
+        self.num_bytes_account = num_bytes_account;
+        self
+    }
+
+    /// storage usage of each record on top of the size of its key and value. Defaults to
+    /// `DEFAULT_NUM_EXTRA_BYTES_RECORD`
+    pub fn num_extra_bytes_record(mut self, num_extra_bytes_record: u64) -> Self {
+        self.num_extra_bytes_record = num_extra_bytes_record;
+        self
+    }
+
+    pub fn options(mut self, options: AmendOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Amends the genesis and records, and returns what was changed. This takes `self` since
+    /// streamed records can only be read or written once. See `AmendGenesisError` for how
+    /// failures are classified.
+    pub fn amend(self) -> Result<AmendGenesisOutcome, AmendGenesisError> {
+        self.amend_impl(None)
+    }
+
+    fn amend_impl(
+        mut self,
+        record_hook: Option<&mut dyn FnMut(StateRecord) -> RecordAction>,
+    ) -> Result<AmendGenesisOutcome, AmendGenesisError> {
+        fn required<T>(value: Option<T>, setter: &str) -> Result<T, AmendGenesisError> {
+            match value {
+                Some(value) => Ok(value),
+                None => bail!(Internal, "AmendGenesisConfig::{}() wasn't called", setter),
+            }
+        }
+
+        let genesis_file_in = required(self.genesis_file_in.take(), "genesis_file_in")?;
+        let genesis_file_out = required(self.genesis_file_out.take(), "genesis_file_out")?;
+        let records_in = required(self.records_in.take(), "records_in")?;
+        let records_out = required(self.records_out.take(), "records_out")?;
+        self.run(genesis_file_in, genesis_file_out, records_in, records_out, record_hook)
+    }
+}
+
+/// What a hook given to `amend_genesis_with()` does with an input record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordAction {
+    /// carry on with this record, which may have been changed
+    Keep(StateRecord),
+    /// leave the record out of the output
+    Drop,
+    /// carry on with these records instead, in this order
+    Replace(Vec<StateRecord>),
+}
+
+/// Like `AmendGenesisConfig::amend()`, but with every input record first passed to `hook`, for
+/// rewrites that the options don't cover. What the hook returns goes through the rest of the
+/// amendment as if it were the input: records left out or added by it don't show up in the
+/// output, and Account records it drops or adds are taken out of or added to the total supply,
+/// while it's the hook's records that the --validators and --extra-records accounts replace.
+///
+/// The hook is called once for each record, in the order of the input records, and the
+/// records it returns are all handled before it's called with the next one. Records dumped
+/// from the state of a node have every Account record before any contract code, access key
+/// or data record, so the hook sees an account before the records belonging to it; that
+/// can't be relied on for records put together by hand. Records of unknown types aren't
+/// passed to the hook. This can't be used with `AmendOptions::recompute_storage_usage`, which
+/// reads the input records once more without the hook.
+pub fn amend_genesis_with(
+    config: AmendGenesisConfig,
+    mut hook: impl FnMut(StateRecord) -> RecordAction,
+) -> Result<AmendGenesisOutcome, AmendGenesisError> {
+    config.amend_impl(Some(&mut hook))
 }
 
-/// Amend a genesis/records file created by `dump-state`.
+/// Amend a genesis/records file created by `dump-state`, with the default `AmendOptions`.
+#[deprecated(note = "use AmendGenesisConfig, which names each of the inputs and outputs")]
 pub fn amend_genesis(
     genesis_file_in: &Path,
     genesis_file_out: &Path,
     records_file_in: &Path,
     records_file_out: &Path,
-    extra_records: Option<&Path>,
+    extra_records: &[PathBuf],
     validators: &Path,
     shard_layout_file: Option<&Path>,
     genesis_changes: &GenesisChanges,
     num_bytes_account: u64,
     num_extra_bytes_record: u64,
 ) -> anyhow::Result<()> {
-    let mut genesis = Genesis::from_file(genesis_file_in, GenesisValidationMode::UnsafeFast)?;
-
-    let shard_layout = if let Some(path) = shard_layout_file {
-        let s = std::fs::read_to_string(path)
-            .with_context(|| format!("failed reading shard layout file {}", path.display()))?;
-        Some(
-            serde_json::from_str::<ShardLayout>(&s)
-                .context("failed deserializing --shard-layout-file")?,
-        )
-    } else {
-        None
-    };
-
-    let reader = BufReader::new(File::open(records_file_in).with_context(|| {
-        format!("Failed opening input records file {}", records_file_in.display())
-    })?);
-    let records_out = BufWriter::new(File::create(records_file_out).with_context(|| {
-        format!("Failed opening output records file {}", records_file_out.display())
-    })?);
-    let mut records_ser = serde_json::Serializer::new(records_out);
-    let mut records_seq = records_ser.serialize_seq(None).unwrap();
-
-    let validators = parse_validators(validators)?;
-    let mut wanted = wanted_records(&validators, extra_records, num_bytes_account)?;
-    let mut total_supply = 0;
-
-    unc_chain_configs::stream_records_from_file(reader, |mut r| {
-        match &mut r {
-            StateRecord::AccessKey { account_id, public_key, access_key } => {
-                if let Some(a) = wanted.get_mut(account_id) {
-                    if let Some(a) = a.keys.remove(public_key) {
-                        *access_key = a;
-                    }
-                }
-                records_seq.serialize_element(&r).unwrap();
-            }
-            StateRecord::Account { account_id, account } => {
-                if let Some(acc) = wanted.get_mut(account_id) {
-                    acc.update_from_existing(account);
-                } else {
-                    if account.pledging() != 0 {
-                        account.set_amount(account.amount() + account.pledging());
-                        account.set_pledging(0);
-                    }
-                    total_supply += account.amount() + account.pledging();
-                    records_seq.serialize_element(&r).unwrap();
-                }
-            }
-            StateRecord::Contract { account_id, .. } => {
-                if let Some(records) = wanted.get_mut(account_id) {
-                    records.push_extra_record(r);
-                } else {
-                    records_seq.serialize_element(&r).unwrap();
-                }
-            }
-            _ => {
-                records_seq.serialize_element(&r).unwrap();
-            }
-        };
-    })?;
-
-    for (account_id, records) in wanted {
-        records.write_out(
-            account_id,
-            &mut records_seq,
-            &mut total_supply,
-            num_extra_bytes_record,
-        )?;
-    }
-
-    genesis.config.total_supply = total_supply;
-    // TODO: give an option to set this
-    genesis.config.num_block_producer_seats = validators.len() as NumSeats;
-    // here we have already checked that there are no duplicate validators in wanted_records()
-    genesis.config.validators = validators;
-    if let Some(chain_id) = &genesis_changes.chain_id {
-        genesis.config.chain_id = chain_id.clone();
-    }
-    if let Some(n) = genesis_changes.num_seats {
-        genesis.config.num_block_producer_seats = n;
-    }
-    if let Some(shard_layout) = shard_layout {
-        genesis.config.avg_hidden_validator_seats_per_shard =
-            shard_layout.shard_ids().into_iter().map(|_| 0).collect();
-        genesis.config.num_block_producer_seats_per_shard = utils::get_num_seats_per_shard(
-            shard_layout.shard_ids().count() as NumShards,
-            genesis.config.num_block_producer_seats,
-        );
-        genesis.config.shard_layout = shard_layout;
-    }
-    if let Some(v) = genesis_changes.protocol_version {
-        genesis.config.protocol_version = v;
-    }
-    if let Some(l) = genesis_changes.epoch_length {
-        genesis.config.epoch_length = l;
-    }
-    if let Some(t) = genesis_changes.transaction_validity_period {
-        genesis.config.transaction_validity_period = t;
-    }
-    if let Some(r) = genesis_changes.protocol_reward_rate {
-        genesis.config.protocol_reward_rate = r;
-    }
-    if let Some(t) = genesis_changes.block_producer_kickout_threshold {
-        genesis.config.block_producer_kickout_threshold = t;
+    let mut config = AmendGenesisConfig::new()
+        .genesis_file_in(genesis_file_in)
+        .genesis_file_out(genesis_file_out)
+        .records_in(records_file_in)
+        .records_out(records_file_out)
+        .validators(validators)
+        .genesis_changes(genesis_changes.clone())
+        .num_bytes_account(num_bytes_account)
+        .num_extra_bytes_record(num_extra_bytes_record);
+    for path in extra_records {
+        config = config.extra_records(path);
     }
-    if let Some(t) = genesis_changes.chunk_producer_kickout_threshold {
-        genesis.config.chunk_producer_kickout_threshold = t;
+    if let Some(path) = shard_layout_file {
+        config = config.shard_layout_file(path);
     }
-    if let Some(p) = genesis_changes.min_gas_price {
-        genesis.config.min_gas_price = p;
-    }
-    if let Some(p) = genesis_changes.max_gas_price {
-        genesis.config.max_gas_price = p;
-    }
-    genesis.to_file(genesis_file_out);
-    records_seq.end()?;
-    Ok(())
+    config.amend().map(|_| ()).map_err(AmendGenesisError::into_inner)
 }
 
 #[cfg(test)]
 mod test {
     use anyhow::Context;
-    use unc_chain_configs::{get_initial_supply, Genesis, GenesisConfig};
-    use unc_primitives::hash::CryptoHash;
-    use unc_primitives::shard_layout::ShardLayout;
-    use unc_primitives::state_record::StateRecord;
+    use unc_chain_configs::{get_initial_supply, Genesis, GenesisConfig, GenesisValidationMode};
+    use unc_crypto::{KeyFile, KeyType, PublicKey, SecretKey};
+    use unc_primitives::action::RegisterRsa2048KeysAction;
+    use unc_primitives::hash::{hash, CryptoHash};
+    use unc_primitives::receipt::Receipt;
+    use unc_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+    use unc_primitives::state_record::{state_record_to_account_id, StateRecord};
     use unc_primitives::static_clock::StaticClock;
     use unc_primitives::types::{AccountId, AccountInfo};
     use unc_primitives::utils;
     use unc_primitives::version::PROTOCOL_VERSION;
-    use unc_primitives_core::account::{AccessKey, Account};
+    use unc_primitives_core::account::{
+        AccessKey, AccessKeyPermission, Account, FunctionCallPermission,
+    };
     use unc_primitives_core::types::{Balance, StorageUsage};
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
     use num_rational::Rational32;
     use std::collections::{HashMap, HashSet};
+    use std::io::{Read, Write};
     use std::str::FromStr;
     use tempfile::NamedTempFile;
 
@@ -438,13 +1069,30 @@ mod test {
             /// Storage used by the given account, includes account id, this struct, access keys and other data.
             storage_usage: StorageUsage,
         },
+        // an account with no pledge, and code_hash set to the hash of `code`
+        AccountWithCode {
+            account_id: &'static str,
+            amount: Balance,
+            storage_usage: StorageUsage,
+            code: &'static [u8],
+        },
         AccessKey {
             account_id: &'static str,
             public_key: &'static str,
         },
+        FunctionCallKey {
+            account_id: &'static str,
+            public_key: &'static str,
+            receiver_id: &'static str,
+        },
         Contract {
             account_id: &'static str,
         },
+        Data {
+            account_id: &'static str,
+            data_key: &'static str,
+            value: &'static str,
+        },
     }
 
     impl TestStateRecord {
@@ -452,7 +1100,11 @@ mod test {
             match &self {
                 Self::Account { account_id, amount, pledging, storage_usage } => {
                     let account =
-                        Account::new(*amount, *pledging, CryptoHash::default(), *storage_usage);
+                        Account::new(*amount, *pledging, 0, CryptoHash::default(), *storage_usage);
+                    StateRecord::Account { account_id: account_id.parse().unwrap(), account }
+                }
+                Self::AccountWithCode { account_id, amount, storage_usage, code } => {
+                    let account = Account::new(*amount, 0, 0, hash(code), *storage_usage);
                     StateRecord::Account { account_id: account_id.parse().unwrap(), account }
                 }
                 Self::AccessKey { account_id, public_key } => StateRecord::AccessKey {
@@ -460,10 +1112,29 @@ mod test {
                     public_key: public_key.parse().unwrap(),
                     access_key: AccessKey::full_access(),
                 },
+                Self::FunctionCallKey { account_id, public_key, receiver_id } => {
+                    StateRecord::AccessKey {
+                        account_id: account_id.parse().unwrap(),
+                        public_key: public_key.parse().unwrap(),
+                        access_key: AccessKey {
+                            nonce: 0,
+                            permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                                allowance: None,
+                                receiver_id: receiver_id.to_string(),
+                                method_names: vec![],
+                            }),
+                        },
+                    }
+                }
                 Self::Contract { account_id } => StateRecord::Contract {
                     account_id: account_id.parse().unwrap(),
                     code: vec![123],
                 },
+                Self::Data { account_id, data_key, value } => StateRecord::Data {
+                    account_id: account_id.parse().unwrap(),
+                    data_key: data_key.as_bytes().to_vec().into(),
+                    value: value.as_bytes().to_vec().into(),
+                },
             }
         }
     }
@@ -476,6 +1147,132 @@ mod test {
         wanted_records: Vec<StateRecord>,
     }
 
+    impl ParsedTestCase {
+        // write the rest of the inputs to temp files and call amend_genesis() on them
+        fn amend(
+            &self,
+            genesis_file_out: &std::path::Path,
+            records_file_out: &std::path::Path,
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<crate::AmendGenesisOutcome> {
+            let validators = serde_json::to_string(&self.validators_in)
+                .context("failed serializing validators")?;
+            self.amend_with_validators(
+                &validators,
+                genesis_file_out,
+                records_file_out,
+                genesis_changes,
+                options,
+            )
+        }
+
+        // same as amend(), but with the given --validators file contents
+        fn amend_with_validators(
+            &self,
+            validators: &str,
+            genesis_file_out: &std::path::Path,
+            records_file_out: &std::path::Path,
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<crate::AmendGenesisOutcome> {
+            self.amend_records(
+                Some(validators),
+                genesis_file_out,
+                self.records_file_in.path().into(),
+                records_file_out.into(),
+                genesis_changes,
+                options,
+                None,
+            )
+        }
+
+        // same as amend(), but without a --validators file
+        fn amend_without_validators(
+            &self,
+            genesis_file_out: &std::path::Path,
+            records_file_out: &std::path::Path,
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<crate::AmendGenesisOutcome> {
+            self.amend_records(
+                None,
+                genesis_file_out,
+                self.records_file_in.path().into(),
+                records_file_out.into(),
+                genesis_changes,
+                options,
+                None,
+            )
+        }
+
+        // same as amend(), but reading the records from `records_in` instead of
+        // `records_file_in` and writing them to `records_out`
+        fn amend_streams(
+            &self,
+            genesis_file_out: &std::path::Path,
+            records_in: crate::RecordsSource,
+            records_out: crate::RecordsSink,
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<crate::AmendGenesisOutcome> {
+            let validators = serde_json::to_string(&self.validators_in)
+                .context("failed serializing validators")?;
+            self.amend_records(
+                Some(&validators),
+                genesis_file_out,
+                records_in,
+                records_out,
+                genesis_changes,
+                options,
+                None,
+            )
+        }
+
+        fn amend_records(
+            &self,
+            validators: Option<&str>,
+            genesis_file_out: &std::path::Path,
+            records_in: crate::RecordsSource,
+            records_out: crate::RecordsSink,
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+            hook: Option<&mut dyn FnMut(StateRecord) -> crate::RecordAction>,
+        ) -> anyhow::Result<crate::AmendGenesisOutcome> {
+            let mut genesis_file_in =
+                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
+            let mut validators_file =
+                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
+            let mut extra_records_file =
+                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
+
+            serde_json::to_writer(&mut extra_records_file, &self.extra_records)
+                .context("failed writing to --extra-records")?;
+            serde_json::to_writer(&mut genesis_file_in, &self.genesis)
+                .context("failed writing to --genesis-file-in")?;
+
+            let mut config = crate::AmendGenesisConfig::new()
+                .genesis_file_in(genesis_file_in.path())
+                .genesis_file_out(genesis_file_out)
+                .records_in(records_in)
+                .records_out(records_out)
+                .extra_records(extra_records_file.path())
+                .genesis_changes(genesis_changes.clone())
+                .options(options.clone());
+            if let Some(validators) = validators {
+                validators_file
+                    .write_all(validators.as_bytes())
+                    .context("failed writing to --validators")?;
+                config = config.validators(validators_file.path());
+            }
+            match hook {
+                Some(hook) => crate::amend_genesis_with(config, hook),
+                None => config.amend(),
+            }
+            .context("amend_genesis() failed")
+        }
+    }
+
     struct TestCase {
         // for convenience, the validators set in the initial genesis file, matching
         // the accounts in records_in with nonzero `pledging`
@@ -497,9 +1294,11 @@ mod test {
         let mut got_accounts = HashMap::new();
         let mut got_keys = HashSet::new();
         let mut got_contracts = HashMap::<AccountId, usize>::new();
+        let mut got_data = HashSet::new();
         let mut wanted_accounts = HashMap::new();
         let mut wanted_keys = HashSet::new();
         let mut wanted_contracts = HashMap::<AccountId, usize>::new();
+        let mut wanted_data = HashSet::new();
 
         for r in got_records {
             match r {
@@ -537,6 +1336,20 @@ mod test {
                     }
                     *got_contracts.entry(account_id).or_default() += 1;
                 }
+                StateRecord::Data { account_id, data_key, value } => {
+                    if !got_accounts.contains_key(&account_id) {
+                        anyhow::bail!(
+                            "account {} has a data state record before the account state record",
+                            &account_id
+                        );
+                    }
+                    if !got_data.insert((account_id.clone(), data_key.to_vec(), value.to_vec())) {
+                        anyhow::bail!(
+                            "two identical data records in the output for {}",
+                            &account_id
+                        );
+                    }
+                }
                 _ => anyhow::bail!("got an unexpected record in the output: {}", r),
             };
         }
@@ -559,6 +1372,9 @@ mod test {
                 StateRecord::Contract { account_id, .. } => {
                     *wanted_contracts.entry(account_id).or_default() += 1;
                 }
+                StateRecord::Data { account_id, data_key, value } => {
+                    wanted_data.insert((account_id, data_key.to_vec(), value.to_vec()));
+                }
                 _ => anyhow::bail!("got an unexpected record in the output: {}", r),
             };
         }
@@ -566,11 +1382,14 @@ mod test {
         assert_eq!(got_accounts, wanted_accounts);
         assert_eq!(got_keys, wanted_keys);
         assert_eq!(got_contracts, wanted_contracts);
+        assert_eq!(got_data, wanted_data);
         Ok(())
     }
 
     impl TestCase {
-        fn parse(&self) -> anyhow::Result<ParsedTestCase> {
+        // raw_records_in are appended to the --records-file-in file as-is, which lets us
+        // put records in there that don't deserialize into a StateRecord
+        fn parse(&self, raw_records_in: &[serde_json::Value]) -> anyhow::Result<ParsedTestCase> {
             let initial_validators = self.initial_validators.iter().map(|v| v.parse()).collect();
             let records_in: Vec<_> = self.records_in.iter().map(|r| r.parse()).collect();
 
@@ -615,7 +1434,16 @@ mod test {
                 max_inflation_rate: framework::config::MAX_INFLATION_RATE,
                 total_supply: get_initial_supply(&records_in),
                 num_blocks_per_year: framework::config::NUM_BLOCKS_PER_YEAR,
-                protocol_treasury_account: "treasury.unc".parse().unwrap(),
+                // the smoke test needs the treasury account to exist
+                protocol_treasury_account: self
+                    .records_in
+                    .iter()
+                    .find_map(|r| match r {
+                        TestStateRecord::Account { account_id, .. } => Some(account_id.parse()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| "treasury.unc".parse())
+                    .unwrap(),
                 fishermen_threshold: framework::config::FISHERMEN_THRESHOLD,
                 shard_layout: shards,
                 min_gas_price: framework::config::MIN_GAS_PRICE,
@@ -624,7 +1452,13 @@ mod test {
 
             let mut records_file_in =
                 tempfile::NamedTempFile::new().context("failed creating tmp file")?;
-            serde_json::to_writer(&mut records_file_in, &records_in)
+            let mut records_file_contents = records_in
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<Result<Vec<_>, _>>()
+                .context("failed serializing records")?;
+            records_file_contents.extend(raw_records_in.iter().cloned());
+            serde_json::to_writer(&mut records_file_in, &records_file_contents)
                 .context("failed writing to --records-file-in")?;
             let genesis = Genesis::new_with_path(genesis_config, records_file_in.path())?;
 
@@ -642,52 +1476,165 @@ mod test {
         // right now we aren't testing that other kinds of records appearing in the input records file
         // will make it into the output, but that part is pretty simple
         fn run(&self) -> anyhow::Result<()> {
-            let ParsedTestCase {
-                genesis,
-                records_file_in,
-                validators_in,
-                extra_records,
-                wanted_records,
-            } = self.parse()?;
+            self.run_with(&[], &crate::AmendOptions::default())
+        }
+
+        // same as run(), but also adds raw_records_in to the input records, and checks that
+        // they are passed through to the output unchanged
+        fn run_with(
+            &self,
+            raw_records_in: &[serde_json::Value],
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<()> {
+            self.run_with_changes(raw_records_in, &crate::GenesisChanges::default(), options)
+                .map(|_| ())
+        }
+
+        // same as run_with(), but also applies genesis_changes, and returns the output genesis
+        // config so the caller can check them
+        fn run_with_changes(
+            &self,
+            raw_records_in: &[serde_json::Value],
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+        ) -> anyhow::Result<GenesisConfig> {
+            self.run_with_validation(
+                raw_records_in,
+                genesis_changes,
+                options,
+                GenesisValidationMode::UnsafeFast,
+            )
+        }
+
+        // same as run_with_changes(), but loads the output genesis and records with the given
+        // validation mode
+        fn run_with_validation(
+            &self,
+            raw_records_in: &[serde_json::Value],
+            genesis_changes: &crate::GenesisChanges,
+            options: &crate::AmendOptions,
+            validation: GenesisValidationMode,
+        ) -> anyhow::Result<GenesisConfig> {
+            let parsed = self.parse(raw_records_in)?;
 
-            let mut genesis_file_in =
-                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
-            let mut validators_file =
-                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
-            let mut extra_records_file =
-                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
             let genesis_file_out =
                 tempfile::NamedTempFile::new().context("failed creating tmp file")?;
             let records_file_out =
                 tempfile::NamedTempFile::new().context("failed creating tmp file")?;
+            let account_index_out =
+                tempfile::NamedTempFile::new().context("failed creating tmp file")?;
+            let options = crate::AmendOptions {
+                account_index_out: Some(account_index_out.path().to_path_buf()),
+                ..options.clone()
+            };
 
-            serde_json::to_writer(&mut validators_file, &validators_in)
-                .context("failed writing to --validators")?;
-            serde_json::to_writer(&mut extra_records_file, &extra_records)
-                .context("failed writing to --extra-records")?;
-            serde_json::to_writer(&mut genesis_file_in, &genesis)
-                .context("failed writing to --genesis-file-in")?;
-
-            crate::amend_genesis(
-                genesis_file_in.path(),
+            parsed.amend(
                 genesis_file_out.path(),
-                records_file_in.path(),
                 records_file_out.path(),
-                Some(extra_records_file.path()),
-                validators_file.path(),
-                None,
-                &crate::GenesisChanges::default(),
-                100,
-                40,
-            )
-            .context("amend_genesis() failed")?;
+                genesis_changes,
+                &options,
+            )?;
 
             let got_records = std::fs::read_to_string(records_file_out.path())
                 .context("failed reading from --records-file-out")?;
-            let got_records: Vec<StateRecord> = serde_json::from_str(&got_records)
+            let got_records: Vec<serde_json::Value> = serde_json::from_str(&got_records)
+                .context("failed deserializing --records-file-out")?;
+            let (got_raw_records, got_records): (Vec<_>, Vec<_>) =
+                got_records.into_iter().partition(|r| raw_records_in.contains(r));
+            assert_eq!(got_raw_records, raw_records_in);
+            let got_records = got_records
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<Result<Vec<StateRecord>, _>>()
                 .context("failed deserializing --records-file-out")?;
 
-            compare_records(got_records, wanted_records)
+            self.check_account_index(
+                account_index_out.path(),
+                &got_records,
+                genesis_changes.protocol_treasury_account.as_ref(),
+            )?;
+            compare_records(got_records, parsed.wanted_records)?;
+
+            let got_genesis =
+                Genesis::from_files(genesis_file_out.path(), records_file_out.path(), validation)
+                    .context("failed reading --genesis-file-out")?;
+            Ok(got_genesis.config)
+        }
+
+        // checks the --account-index-out file against the output records
+        // `treasury` is the account set with --protocol-treasury-account, which is added to
+        // the output if it doesn't exist in the input
+        fn check_account_index(
+            &self,
+            path: &std::path::Path,
+            got_records: &[StateRecord],
+            treasury: Option<&AccountId>,
+        ) -> anyhow::Result<()> {
+            let input_accounts = self
+                .records_in
+                .iter()
+                .filter_map(|r| match r {
+                    TestStateRecord::Account { account_id, .. } => Some(*account_id),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>();
+            let mut wanted_accounts =
+                self.validators_in.iter().map(|v| v.account_id).collect::<HashSet<_>>();
+            wanted_accounts.extend(self.extra_records.iter().map(|r| match r {
+                TestStateRecord::Account { account_id, .. }
+                | TestStateRecord::AccountWithCode { account_id, .. }
+                | TestStateRecord::AccessKey { account_id, .. }
+                | TestStateRecord::FunctionCallKey { account_id, .. }
+                | TestStateRecord::Contract { account_id }
+                | TestStateRecord::Data { account_id, .. } => *account_id,
+            }));
+            wanted_accounts.extend(treasury.map(AccountId::as_str));
+
+            let index = std::fs::read_to_string(path).context("failed reading the account index")?;
+            let mut rows = HashMap::new();
+            for line in index.lines() {
+                let row: crate::AccountIndexRow =
+                    serde_json::from_str(line).context("failed deserializing an index row")?;
+                if rows.insert(row.account_id.clone(), row).is_some() {
+                    anyhow::bail!("two index rows for the same account");
+                }
+            }
+            let mut num_accounts = 0;
+            for r in got_records {
+                let StateRecord::Account { account_id, account } = r else {
+                    continue;
+                };
+                num_accounts += 1;
+                let row = rows
+                    .get(account_id)
+                    .with_context(|| format!("no index row for {}", account_id))?;
+                assert_eq!(
+                    (row.amount, row.pledging, row.storage_usage),
+                    (account.amount(), account.pledging(), account.storage_usage()),
+                    "{}",
+                    account_id
+                );
+                let want_status = if !wanted_accounts.contains(account_id.as_str()) {
+                    crate::AccountStatus::PassedThrough
+                } else if input_accounts.contains(account_id.as_str()) {
+                    crate::AccountStatus::Modified
+                } else {
+                    crate::AccountStatus::Added
+                };
+                assert_eq!(row.status, want_status, "{}", account_id);
+                let want_num_keys = (want_status == crate::AccountStatus::Added).then(|| {
+                    got_records
+                        .iter()
+                        .filter(|r| match r {
+                            StateRecord::AccessKey { account_id: id, .. } => id == account_id,
+                            _ => false,
+                        })
+                        .count() as u64
+                });
+                assert_eq!(row.num_keys, want_num_keys, "{}", account_id);
+            }
+            assert_eq!(rows.len(), num_accounts);
+            Ok(())
         }
     }
 
@@ -758,8 +1705,8 @@ mod test {
             extra_records: &[
                 TestStateRecord::Account {
                     account_id: "foo0",
-                    amount: 100_000_000,
-                    pledging: 50_000_000,
+                    amount: 149_000_000,
+                    pledging: 1_000_000,
                     storage_usage: 0,
                 },
                 TestStateRecord::Account {
@@ -1040,4 +1987,4042 @@ mod test {
             t.run().unwrap();
         }
     }
+
+    #[test]
+    fn test_deterministic_output() {
+        for (i, t) in TEST_CASES.iter().enumerate() {
+            let parsed = t.parse(&[]).unwrap();
+            let changes = crate::GenesisChanges::default();
+            let options = crate::AmendOptions::default();
+            let mut outputs = Vec::new();
+            for _ in 0..2 {
+                let dir = tempfile::tempdir().unwrap();
+                let genesis_file_out = dir.path().join("genesis.json");
+                let records_file_out = dir.path().join("records.json");
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+                outputs.push((
+                    std::fs::read(&genesis_file_out).unwrap(),
+                    std::fs::read(&records_file_out).unwrap(),
+                ));
+            }
+            assert!(outputs[0] == outputs[1], "output of test case {} differs between runs", i);
+        }
+    }
+
+    // the deprecated amend_genesis() writes the same output as AmendGenesisConfig
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_amend_genesis() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_in = dir.path().join("genesis_in.json");
+        let validators = dir.path().join("validators.json");
+        let extra_records = dir.path().join("extra_records.json");
+        std::fs::write(&genesis_file_in, serde_json::to_vec(&parsed.genesis).unwrap()).unwrap();
+        std::fs::write(&validators, serde_json::to_vec(&parsed.validators_in).unwrap()).unwrap();
+        std::fs::write(&extra_records, serde_json::to_vec(&parsed.extra_records).unwrap())
+            .unwrap();
+        let changes = crate::GenesisChanges::default();
+        crate::amend_genesis(
+            &genesis_file_in,
+            &dir.path().join("genesis.json"),
+            parsed.records_file_in.path(),
+            &dir.path().join("records.json"),
+            &[extra_records],
+            &validators,
+            None,
+            &changes,
+            crate::DEFAULT_NUM_BYTES_ACCOUNT,
+            crate::DEFAULT_NUM_EXTRA_BYTES_RECORD,
+        )
+        .unwrap();
+
+        let genesis_file_out = dir.path().join("genesis_config.json");
+        let records_file_out = dir.path().join("records_config.json");
+        let options = crate::AmendOptions::default();
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        let read = |path: &std::path::Path| std::fs::read(path).unwrap();
+        assert_eq!(read(&dir.path().join("genesis.json")), read(&genesis_file_out));
+        assert_eq!(read(&dir.path().join("records.json")), read(&records_file_out));
+    }
+
+    #[test]
+    fn test_emit_genesis_hash() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let template_in = dir.path().join("config.toml.in");
+        let template_out = dir.path().join("config.toml");
+        std::fs::write(
+            &template_in,
+            "genesis_hash = \"{{genesis_hash}}\"\nconfig_hash = \"{{genesis_config_hash}}\"\n",
+        )
+        .unwrap();
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions {
+            emit_genesis_hash: true,
+            template_out: vec![format!(
+                "in={},out={}",
+                template_in.display(),
+                template_out.display()
+            )
+            .parse()
+            .unwrap()],
+            ..Default::default()
+        };
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        let hashes = outcome.genesis_hashes.unwrap();
+
+        // the same hashes a node gets loading the output
+        let genesis = Genesis::from_files(
+            &genesis_file_out,
+            &records_file_out,
+            GenesisValidationMode::UnsafeFast,
+        )
+        .unwrap();
+        assert_eq!(hashes.genesis_hash, genesis.json_hash());
+        let mut hasher = unc_chain_configs::GenesisJsonHasher::new();
+        hasher.process_config(&genesis.config);
+        assert_eq!(hashes.genesis_config_hash, hasher.finalize());
+
+        assert_eq!(
+            std::fs::read_to_string(&template_out).unwrap(),
+            format!(
+                "genesis_hash = \"{}\"\nconfig_hash = \"{}\"\n",
+                hashes.genesis_hash, hashes.genesis_config_hash
+            )
+        );
+
+        let options = crate::AmendOptions { emit_genesis_hash: true, dry_run: true, ..options };
+        let err =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_gzip_records() {
+        let mut parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions { validate_output: true, ..Default::default() };
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        // the input is detected as compressed by its first bytes, since its name doesn't
+        // end in .gz
+        let records_in = std::fs::read(parsed.records_file_in.path()).unwrap();
+        let mut gz_records_in = NamedTempFile::new().unwrap();
+        let mut encoder = GzEncoder::new(&mut gz_records_in, Compression::default());
+        encoder.write_all(&records_in).unwrap();
+        encoder.finish().unwrap();
+        parsed.records_file_in = gz_records_in;
+
+        let gz_genesis_file_out = dir.path().join("genesis-gz.json");
+        let gz_records_file_out = dir.path().join("records.json.gz");
+        parsed.amend(&gz_genesis_file_out, &gz_records_file_out, &changes, &options).unwrap();
+
+        let mut records_out = Vec::new();
+        GzDecoder::new(std::fs::File::open(&gz_records_file_out).unwrap())
+            .read_to_end(&mut records_out)
+            .unwrap();
+        assert_eq!(records_out, std::fs::read(&records_file_out).unwrap());
+        assert_eq!(
+            std::fs::read(&gz_genesis_file_out).unwrap(),
+            std::fs::read(&genesis_file_out).unwrap()
+        );
+
+        let options = crate::AmendOptions { smoke_test: true, ..Default::default() };
+        let err = parsed
+            .amend(&gz_genesis_file_out, &gz_records_file_out, &changes, &options)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("gzip-compressed"), "{:#}", err);
+    }
+
+    // output records kept in memory, readable after amend_genesis() is done with its writer
+    #[derive(Clone, Default)]
+    struct SharedBuf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records_streams() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions::default();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        // the records go through in-memory streams only, compressed or not
+        let records_in = std::fs::read(parsed.records_file_in.path()).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&records_in).unwrap();
+        let gz_records_in = encoder.finish().unwrap();
+        for input in [records_in, gz_records_in] {
+            let stream_genesis_file_out = dir.path().join("genesis-stream.json");
+            let records_out = SharedBuf::default();
+            parsed
+                .amend_streams(
+                    &stream_genesis_file_out,
+                    crate::RecordsSource::Reader(Box::new(std::io::Cursor::new(input))),
+                    crate::RecordsSink::Writer(Box::new(records_out.clone())),
+                    &changes,
+                    &options,
+                )
+                .unwrap();
+            assert_eq!(*records_out.0.borrow(), std::fs::read(&records_file_out).unwrap());
+            assert_eq!(
+                std::fs::read(&stream_genesis_file_out).unwrap(),
+                std::fs::read(&genesis_file_out).unwrap()
+            );
+        }
+
+        // options that read the records twice need files
+        let options = crate::AmendOptions { smoke_test: true, ..Default::default() };
+        let err = parsed
+            .amend_streams(
+                &genesis_file_out,
+                parsed.records_file_in.path().into(),
+                crate::RecordsSink::Writer(Box::new(std::io::sink())),
+                &changes,
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+        let options = crate::AmendOptions {
+            validation_mode: crate::ValidationMode::Full,
+            ..Default::default()
+        };
+        let err = parsed
+            .amend_streams(
+                &genesis_file_out,
+                crate::RecordsSource::Reader(Box::new(std::io::empty())),
+                records_file_out.as_path().into(),
+                &changes,
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    // output records that fail to be written past the first `left` bytes, like on a full disk
+    struct FailingWriter {
+        left: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.left == 0 {
+                return Err(std::io::Error::other("no space left"));
+            }
+            let n = buf.len().min(self.left);
+            self.left -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records_write_failure() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        // the records fit in the BufWriter, so this fails when it's flushed at the end
+        let err = parsed
+            .amend_streams(
+                &genesis_file_out,
+                parsed.records_file_in.path().into(),
+                crate::RecordsSink::Writer(Box::new(FailingWriter { left: 16 })),
+                &crate::GenesisChanges::default(),
+                &crate::AmendOptions::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Io(_))
+        ));
+        assert!(format!("{:#}", err).contains("no space left"), "{:#}", err);
+        assert!(!genesis_file_out.exists());
+    }
+
+    // amends the genesis config and records in `dir` with no changes other than the defaults
+    fn amend_dir_files(
+        dir: &std::path::Path,
+        genesis_file_out: &str,
+        records_file_out: &str,
+    ) -> Result<crate::AmendGenesisOutcome, crate::AmendGenesisError> {
+        crate::AmendGenesisConfig::new()
+            .genesis_file_in(dir.join("genesis.json"))
+            .genesis_file_out(dir.join(genesis_file_out))
+            .records_in(dir.join("records.json").as_path())
+            .records_out(dir.join(records_file_out).as_path())
+            .amend()
+    }
+
+    fn dir_file_names(dir: &std::path::Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_amend_in_place() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("genesis.json"),
+            serde_json::to_vec(&parsed.genesis).unwrap(),
+        )
+        .unwrap();
+        std::fs::copy(parsed.records_file_in.path(), dir.path().join("records.json")).unwrap();
+        // the temporary files are created with 0600, which mustn't end up on the outputs
+        #[cfg(unix)]
+        for name in ["genesis.json", "records.json"] {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(0o644);
+            std::fs::set_permissions(dir.path().join(name), permissions).unwrap();
+        }
+
+        amend_dir_files(dir.path(), "genesis_copy.json", "records_copy.json").unwrap();
+        amend_dir_files(dir.path(), "genesis.json", "records.json").unwrap();
+        let read = |name: &str| std::fs::read(dir.path().join(name)).unwrap();
+        assert_eq!(read("genesis.json"), read("genesis_copy.json"));
+        assert_eq!(read("records.json"), read("records_copy.json"));
+        #[cfg(unix)]
+        for name in ["genesis.json", "records.json"] {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join(name)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644, "{}", name);
+        }
+        // no temporary files are left behind
+        assert_eq!(
+            dir_file_names(dir.path()),
+            ["genesis.json", "genesis_copy.json", "records.json", "records_copy.json"]
+        );
+    }
+
+    #[test]
+    fn test_amend_in_place_failure() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis = serde_json::to_vec(&parsed.genesis).unwrap();
+        std::fs::write(dir.path().join("genesis.json"), &genesis).unwrap();
+        // a record that isn't valid JSON halfway through the file
+        let records: Vec<serde_json::Value> =
+            serde_json::from_reader(std::fs::File::open(parsed.records_file_in.path()).unwrap())
+                .unwrap();
+        let (first, second) = records.split_at(records.len() / 2);
+        let join = |records: &[serde_json::Value]| {
+            records.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(",")
+        };
+        let records = format!("[{},{{\"Account\": }},{}]", join(first), join(second));
+        std::fs::write(dir.path().join("records.json"), &records).unwrap();
+
+        let err = amend_dir_files(dir.path(), "genesis.json", "records.json").unwrap_err();
+        assert!(matches!(err, crate::AmendGenesisError::InputParse(_)), "{:#}", err);
+        assert_eq!(std::fs::read(dir.path().join("genesis.json")).unwrap(), genesis);
+        assert_eq!(std::fs::read_to_string(dir.path().join("records.json")).unwrap(), records);
+        assert_eq!(dir_file_names(dir.path()), ["genesis.json", "records.json"]);
+    }
+
+    // writes `records` to a temp file as NDJSON, one record per line
+    fn ndjson_file(records: &[serde_json::Value]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        for record in records.iter() {
+            serde_json::to_writer(&mut file, record).unwrap();
+            file.write_all(b"\n").unwrap();
+        }
+        file
+    }
+
+    #[test]
+    fn test_ndjson_records() {
+        for t in TEST_CASES.iter() {
+            let mut parsed = t.parse(&[]).unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let changes = crate::GenesisChanges::default();
+            let genesis_file_out = dir.path().join("genesis.json");
+            let records_file_out = dir.path().join("records.json");
+            let options = crate::AmendOptions::default();
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            let array_out = std::fs::read(&records_file_out).unwrap();
+            let array_out: Vec<serde_json::Value> = serde_json::from_slice(&array_out).unwrap();
+
+            // the same records are written one per line, and read back when validating them
+            let ndjson_file_out = dir.path().join("records.ndjson");
+            let options = crate::AmendOptions {
+                output_format: crate::RecordsFormat::Ndjson,
+                validate_output: true,
+                ..Default::default()
+            };
+            parsed.amend(&genesis_file_out, &ndjson_file_out, &changes, &options).unwrap();
+            let ndjson_out = std::fs::read_to_string(&ndjson_file_out).unwrap();
+            let ndjson_out = ndjson_out
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect::<Vec<serde_json::Value>>();
+            assert_eq!(ndjson_out, array_out);
+
+            // input records given as NDJSON give the same output as the array they came from
+            let records_in = std::fs::read(parsed.records_file_in.path()).unwrap();
+            let records_in: Vec<serde_json::Value> = serde_json::from_slice(&records_in).unwrap();
+            parsed.records_file_in = ndjson_file(&records_in);
+            let from_ndjson_file_out = dir.path().join("records-from-ndjson.json");
+            let options = crate::AmendOptions::default();
+            parsed.amend(&genesis_file_out, &from_ndjson_file_out, &changes, &options).unwrap();
+            assert_eq!(
+                std::fs::read(&from_ndjson_file_out).unwrap(),
+                std::fs::read(&records_file_out).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_multiple_extra_records() {
+        let key = |seed: &str| SecretKey::from_seed(KeyType::ED25519, seed).public_key();
+        let account = |account_id: &str, amount: Balance| StateRecord::Account {
+            account_id: account_id.parse().unwrap(),
+            account: Account::new(amount, 0, 0, CryptoHash::default(), 0),
+        };
+        let access_key = |account_id: &str, public_key: &PublicKey| StateRecord::AccessKey {
+            account_id: account_id.parse().unwrap(),
+            public_key: public_key.clone(),
+            access_key: AccessKey::full_access(),
+        };
+        let records_file = |records: &[StateRecord]| {
+            let mut file = NamedTempFile::new().unwrap();
+            serde_json::to_writer(&mut file, records).unwrap();
+            file
+        };
+        let (key0, key1, key2) = (key("key0"), key("key1"), key("key2"));
+        // both files have keys for both accounts, but each has only one Account record
+        let first = records_file(&[
+            account("extra0.unc", 100),
+            access_key("extra0.unc", &key0),
+            access_key("extra1.unc", &key1),
+        ]);
+        let second = records_file(&[
+            access_key("extra0.unc", &key2),
+            account("extra1.unc", 200),
+            access_key("extra1.unc", &key0),
+        ]);
+        let files = [first.path().to_path_buf(), second.path().to_path_buf()];
+        let mut interners = crate::intern::Interners::default();
+        let merged = crate::parse_all_extra_records(&files, 100, &mut interners).unwrap();
+        assert_eq!(merged.len(), 2);
+        let extra0 = &merged[&"extra0.unc".parse::<AccountId>().unwrap()];
+        assert_eq!(extra0.account.as_ref().unwrap().amount(), 100);
+        assert!(extra0.balance_given);
+        let mut keys = extra0.keys.keys().map(|k| PublicKey::clone(k)).collect::<Vec<_>>();
+        keys.sort();
+        let mut want = vec![key0.clone(), key2.clone()];
+        want.sort();
+        assert_eq!(keys, want);
+        let extra1 = &merged[&"extra1.unc".parse::<AccountId>().unwrap()];
+        assert_eq!(extra1.account.as_ref().unwrap().amount(), 200);
+        assert!(extra1.balance_given);
+        let mut keys = extra1.keys.keys().map(|k| PublicKey::clone(k)).collect::<Vec<_>>();
+        keys.sort();
+        let mut want = vec![key0, key1];
+        want.sort();
+        assert_eq!(keys, want);
+
+        // a single file is read the same as before
+        let single = crate::parse_all_extra_records(&files[..1], 100, &mut interners).unwrap();
+        let from_file = crate::parse_extra_records(&files[0], 100, &mut interners).unwrap();
+        assert_eq!(single.len(), from_file.len());
+        for (account_id, want) in from_file.iter() {
+            assert_eq!(single[account_id].account, want.account, "{}", account_id);
+            assert_eq!(single[account_id].keys, want.keys, "{}", account_id);
+        }
+
+        let third = records_file(&[account("extra0.unc", 300)]);
+        let files = [first.path().to_path_buf(), third.path().to_path_buf()];
+        let err = match crate::parse_all_extra_records(&files, 100, &mut interners) {
+            Ok(_) => panic!("extra0.unc given twice was accepted"),
+            Err(err) => err,
+        };
+        assert!(matches!(err, crate::AmendGenesisError::Validation(_)));
+        let want = format!(
+            "account extra0.unc given in both extra records {} and {}",
+            first.path().display(),
+            third.path().display()
+        );
+        assert_eq!(err.to_string(), want);
+    }
+
+    #[test]
+    fn test_ndjson_extra_records() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let mut array_file = NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut array_file, &parsed.extra_records).unwrap();
+        let values = parsed
+            .extra_records
+            .iter()
+            .map(|r| serde_json::to_value(r).unwrap())
+            .collect::<Vec<_>>();
+        let ndjson_file = ndjson_file(&values);
+
+        let mut interners = crate::intern::Interners::default();
+        let from_array =
+            crate::parse_extra_records(array_file.path(), 100, &mut interners).unwrap();
+        let from_ndjson =
+            crate::parse_extra_records(ndjson_file.path(), 100, &mut interners).unwrap();
+        assert!(!from_array.is_empty());
+        assert_eq!(from_array.len(), from_ndjson.len());
+        for (account_id, want) in from_array.iter() {
+            let got = &from_ndjson[account_id];
+            assert_eq!(got.account, want.account, "{}", account_id);
+            assert_eq!(got.keys, want.keys, "{}", account_id);
+            assert_eq!(got.code, want.code, "{}", account_id);
+        }
+
+        let options = crate::AmendOptions {
+            output_format: crate::RecordsFormat::Ndjson,
+            smoke_test: true,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let err = parsed
+            .amend(
+                &dir.path().join("genesis.json"),
+                &dir.path().join("records.ndjson"),
+                &crate::GenesisChanges::default(),
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_entries() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let validators = serde_json::to_value(&parsed.validators_in).unwrap();
+        let broken_validator = |field: &str, value: &str| {
+            let mut validators = validators.clone();
+            validators[1][field] = serde_json::Value::from(value);
+            let err = parsed
+                .amend_with_validators(
+                    &validators.to_string(),
+                    &genesis_file_out,
+                    &records_file_out,
+                    &crate::GenesisChanges::default(),
+                    &crate::AmendOptions::default(),
+                )
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<crate::AmendGenesisError>(),
+                Some(crate::AmendGenesisError::InputParse(_))
+            ));
+            format!("{:#}", err)
+        };
+        let account_id = parsed.validators_in[1].account_id.clone();
+        let err = broken_validator("public_key", "ed25519:not-a-key");
+        let want =
+            format!("validator 1 ({}): invalid public_key \"ed25519:not-a-key\"", account_id);
+        assert!(err.contains(&want), "{}", err);
+        let err = broken_validator("account_id", "Not An Account");
+        assert!(err.contains("validator 1 (Not An Account): invalid account_id"), "{}", err);
+        let err = broken_validator("pledging", "lots");
+        assert!(err.contains(&format!("invalid validator 1 ({})", account_id)), "{}", err);
+
+        let key = SecretKey::from_seed(KeyType::ED25519, "test").public_key();
+        let access_key = |account_id: &str, public_key: &str| {
+            serde_json::json!({
+                "AccessKey": {
+                    "account_id": account_id,
+                    "public_key": public_key,
+                    "access_key": AccessKey::full_access(),
+                }
+            })
+        };
+        let records = [
+            access_key("extra0.unc", &key.to_string()),
+            access_key("extra1.unc", &key.to_string()),
+            access_key("extra2.unc", "ed25519:not-a-key"),
+        ];
+        let mut array_file = NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut array_file, &records).unwrap();
+        // counted the same across the lines of NDJSON
+        let ndjson_file = ndjson_file(&records);
+        for file in [array_file, ndjson_file] {
+            let mut interners = crate::intern::Interners::default();
+            let err = match crate::parse_extra_records(file.path(), 100, &mut interners) {
+                Ok(_) => panic!("a malformed key was accepted"),
+                Err(err) => err,
+            };
+            assert!(matches!(err, crate::AmendGenesisError::InputParse(_)));
+            let err = format!("{:#}", err);
+            let want = "record 2 of extra2.unc: invalid public_key \"ed25519:not-a-key\"";
+            assert!(err.contains(want), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_amend_genesis_config_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = crate::AmendGenesisConfig::new()
+            .genesis_file_in(dir.path().join("genesis-in.json"))
+            .genesis_file_out(dir.path().join("genesis.json"))
+            .records_out(dir.path().join("records.json").as_path())
+            .validators(dir.path().join("validators.json"))
+            .amend()
+            .unwrap_err();
+        assert!(matches!(err, crate::AmendGenesisError::Internal(_)));
+        assert!(err.to_string().contains("records_in"), "{}", err);
+    }
+
+    #[test]
+    fn test_pretty_records() {
+        for t in TEST_CASES.iter() {
+            let parsed = t.parse(&[]).unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let changes = crate::GenesisChanges::default();
+            let genesis_file_out = dir.path().join("genesis.json");
+            let records_file_out = dir.path().join("records.json");
+            let options = crate::AmendOptions::default();
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            let compact_out = std::fs::read(&records_file_out).unwrap();
+            let compact_out: Vec<StateRecord> = serde_json::from_slice(&compact_out).unwrap();
+
+            let pretty_file_out = dir.path().join("records-pretty.json");
+            let options = crate::AmendOptions { pretty: true, ..Default::default() };
+            parsed.amend(&genesis_file_out, &pretty_file_out, &changes, &options).unwrap();
+            let pretty_out = std::fs::read(&pretty_file_out).unwrap();
+            assert!(pretty_out.iter().filter(|b| **b == b'\n').count() > compact_out.len());
+            assert_eq!(pretty_out, serde_json::to_vec_pretty(&compact_out).unwrap());
+            let pretty_out: Vec<StateRecord> = serde_json::from_slice(&pretty_out).unwrap();
+            assert_eq!(pretty_out, compact_out);
+        }
+
+        let options = crate::AmendOptions {
+            pretty: true,
+            output_format: crate::RecordsFormat::Ndjson,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let err = TEST_CASES[0]
+            .parse(&[])
+            .unwrap()
+            .amend(
+                &dir.path().join("genesis.json"),
+                &dir.path().join("records.ndjson"),
+                &crate::GenesisChanges::default(),
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    fn validator_key_file(
+        keys_dir: &std::path::Path,
+        account_id: &AccountId,
+    ) -> std::path::PathBuf {
+        keys_dir.join(account_id.as_str()).join("validator_key.json")
+    }
+
+    #[test]
+    fn test_append_validators() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions { append_validators: true, ..Default::default() };
+        // only foo2 is new, foo0 and foo1 are validators in the input genesis
+        let new_validators = serde_json::to_string(&parsed.validators_in[2..]).unwrap();
+        let outcome = parsed
+            .amend_with_validators(
+                &new_validators,
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap();
+
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        let mut want = parsed.genesis.config.validators.clone();
+        want.extend(parsed.validators_in[2..].iter().cloned());
+        assert_eq!(genesis.config.validators, want);
+        assert_eq!(outcome.validators, want);
+        assert_eq!(genesis.config.num_block_producer_seats, 3);
+        assert_eq!(outcome.summary.validators_replaced, 0);
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        // foo1 isn't in --validators, but keeps its pledge
+        let foo1 = records
+            .iter()
+            .find_map(|r| match r {
+                StateRecord::Account { account_id, account } if account_id.as_str() == "foo1" => {
+                    Some(account)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(foo1.pledging(), 2_000_000);
+        assert_eq!(foo1.amount(), 1_000_000);
+        // foo0 is in the extra records, which set its balance but not its pledge
+        let foo0 = records
+            .iter()
+            .find_map(|r| match r {
+                StateRecord::Account { account_id, account } if account_id.as_str() == "foo0" => {
+                    Some(account)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(foo0.pledging(), 1_000_000);
+        assert_eq!(foo0.amount(), 149_000_000);
+
+        // --num-seats still wins
+        let changes = crate::GenesisChanges { num_seats: Some(10), ..Default::default() };
+        let options = crate::AmendOptions { allow_seat_mismatch: true, ..options };
+        parsed
+            .amend_with_validators(
+                &new_validators,
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap();
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.num_block_producer_seats, 10);
+
+        // foo1 is already a validator
+        let overlapping = serde_json::to_string(&parsed.validators_in[1..]).unwrap();
+        let err = parsed
+            .amend_with_validators(
+                &overlapping,
+                &genesis_file_out,
+                &records_file_out,
+                &crate::GenesisChanges::default(),
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+        assert!(format!("{:#}", err).contains("validator foo1"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_num_seats_validation() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions::default();
+        assert_eq!(parsed.validators_in.len(), 3);
+        assert_eq!(parsed.genesis.config.shard_layout.shard_ids().count(), 4);
+
+        // more seats than validators, no seats at all, and fewer seats than shards
+        for (num_seats, want) in [
+            (10, "--num-seats is 10 but there are only 3 validators"),
+            (0, "--num-seats must be at least 1"),
+            (3, "--num-seats is 3 but the shard layout has 4 shards"),
+        ] {
+            let changes =
+                crate::GenesisChanges { num_seats: Some(num_seats), ..Default::default() };
+            let err = parsed
+                .amend(&genesis_file_out, &records_file_out, &changes, &options)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<crate::AmendGenesisError>(),
+                Some(crate::AmendGenesisError::Validation(_))
+            ));
+            assert!(format!("{:#}", err).contains(want), "{:#}", err);
+        }
+
+        let changes = crate::GenesisChanges { num_seats: Some(10), ..Default::default() };
+        let options = crate::AmendOptions { allow_seat_mismatch: true, ..Default::default() };
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.num_block_producer_seats, 10);
+    }
+
+    #[test]
+    fn test_seats_per_shard() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions::default();
+        assert_eq!(parsed.genesis.config.shard_layout.shard_ids().count(), 4);
+
+        let changes = crate::GenesisChanges {
+            num_block_producer_seats_per_shard: Some(vec![3, 1, 1, 2]),
+            avg_hidden_validator_seats_per_shard: Some(vec![0, 1, 0, 0]),
+            ..Default::default()
+        };
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.num_block_producer_seats_per_shard, vec![3, 1, 1, 2]);
+        assert_eq!(genesis.config.avg_hidden_validator_seats_per_shard, vec![0, 1, 0, 0]);
+
+        for (changes, want) in [
+            (
+                crate::GenesisChanges {
+                    num_block_producer_seats_per_shard: Some(vec![3, 1, 1]),
+                    ..Default::default()
+                },
+                "--num-block-producer-seats-per-shard gives the seats of 3 shards, but the \
+                output shard layout has 4",
+            ),
+            (
+                crate::GenesisChanges {
+                    avg_hidden_validator_seats_per_shard: Some(vec![0; 5]),
+                    ..Default::default()
+                },
+                "--avg-hidden-validator-seats-per-shard gives the seats of 5 shards, but the \
+                output shard layout has 4",
+            ),
+        ] {
+            let err = parsed
+                .amend(&genesis_file_out, &records_file_out, &changes, &options)
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<crate::AmendGenesisError>(),
+                Some(crate::AmendGenesisError::Conflict(_))
+            ));
+            assert!(format!("{:#}", err).contains(want), "{:#}", err);
+        }
+    }
+
+    // foo0 is given a pledge in --extra-records other than the one in --validators, and foo1
+    // only a balance
+    static PLEDGE_CONFLICT_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+        ],
+        validators_in: &[
+            TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            },
+            TestAccountInfo {
+                account_id: "foo1",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                amount: 2_000_000,
+            },
+        ],
+        extra_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 100_000_000,
+                pledging: 50_000_000,
+                storage_usage: 0,
+            },
+            TestStateRecord::Account {
+                account_id: "foo1",
+                amount: 7_000_000,
+                pledging: 0,
+                storage_usage: 0,
+            },
+        ],
+        wanted_records: &[],
+    };
+
+    #[test]
+    fn test_pledge_conflicts() {
+        let parsed = PLEDGE_CONFLICT_TEST_CASE.parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges::default();
+
+        let err = parsed
+            .amend(&genesis_file_out, &records_file_out, &changes, &crate::AmendOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+        let err = format!("{:#}", err);
+        assert!(err.contains("1 validators are given different pledges"), "{}", err);
+        assert!(
+            err.contains(
+                "foo0 has a pledge of 1000000 in --validators, leaving it an amount of 149000000, \
+                and a pledge of 50000000 with an amount of 100000000 in --extra-records"
+            ),
+            "{}",
+            err
+        );
+
+        // (policy, foo0's amount and pledge)
+        let policies = [
+            (crate::PledgeConflictPolicy::PreferValidators, 149_000_000, 1_000_000),
+            (crate::PledgeConflictPolicy::PreferExtraRecords, 100_000_000, 50_000_000),
+        ];
+        for (policy, amount, pledge) in policies {
+            let options =
+                crate::AmendOptions { pledge_conflict_policy: policy, ..Default::default() };
+            let outcome =
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            let records: Vec<StateRecord> =
+                serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap())
+                    .unwrap();
+            let accounts = records
+                .iter()
+                .filter_map(|r| match r {
+                    StateRecord::Account { account_id, account } => {
+                        Some((account_id.as_str(), (account.amount(), account.pledging())))
+                    }
+                    _ => None,
+                })
+                .collect::<HashMap<_, _>>();
+            assert_eq!(accounts["foo0"], (amount, pledge));
+            // the pledge in --validators is taken out of a balance without one
+            assert_eq!(accounts["foo1"], (5_000_000, 2_000_000));
+            // both give the total balance in --extra-records
+            assert_eq!(outcome.total_supply, 150_000_000 + 7_000_000);
+
+            let genesis =
+                Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast).unwrap();
+            let pledges = genesis
+                .config
+                .validators
+                .iter()
+                .map(|v| (v.account_id.as_str(), v.pledging))
+                .collect::<Vec<_>>();
+            assert_eq!(pledges, [("foo0", pledge), ("foo1", 2_000_000)]);
+            assert_eq!(genesis.config.total_supply, outcome.total_supply);
+        }
+    }
+
+    #[test]
+    fn test_validator_power() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges::default();
+        // foo0 and foo1 exist in the input records with no power, foo2 is new
+        let validators = parsed
+            .validators_in
+            .iter()
+            .zip([7, 8, 9])
+            .map(|(v, power)| AccountInfo { power, ..v.clone() })
+            .collect::<Vec<_>>();
+        let validators_json = serde_json::to_string(&validators).unwrap();
+        let power_of = |name: &str| {
+            let records: Vec<StateRecord> =
+                serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap())
+                    .unwrap();
+            records
+                .iter()
+                .find_map(|r| match r {
+                    StateRecord::Account { account_id, account }
+                        if account_id.as_str() == name =>
+                    {
+                        Some(account.power())
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        let options = crate::AmendOptions::default();
+        parsed
+            .amend_with_validators(
+                &validators_json,
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(power_of("foo0"), 7);
+        assert_eq!(power_of("foo1"), 8);
+        assert_eq!(power_of("foo2"), 9);
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.validators, validators);
+
+        let options = crate::AmendOptions { keep_existing_power: true, ..Default::default() };
+        parsed
+            .amend_with_validators(
+                &validators_json,
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(power_of("foo0"), 0);
+        assert_eq!(power_of("foo1"), 0);
+        assert_eq!(power_of("foo2"), 9);
+    }
+
+    #[test]
+    fn test_no_validators() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges {
+            chain_id: Some("amended-chain".to_string()),
+            ..Default::default()
+        };
+        let outcome = parsed
+            .amend_without_validators(
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &crate::AmendOptions::default(),
+            )
+            .unwrap();
+
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.chain_id, "amended-chain");
+        assert_eq!(genesis.config.validators, parsed.genesis.config.validators);
+        assert_eq!(outcome.validators, parsed.genesis.config.validators);
+        assert_eq!(
+            genesis.config.num_block_producer_seats,
+            parsed.genesis.config.num_block_producer_seats
+        );
+        assert_eq!(outcome.summary.validators_replaced, 0);
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        let pledging = |name: &str| {
+            records
+                .iter()
+                .find_map(|r| match r {
+                    StateRecord::Account { account_id, account }
+                        if account_id.as_str() == name =>
+                    {
+                        Some(account.pledging())
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        // neither pledge is returned to its balance, even for foo0 whose balance is set in
+        // the extra records
+        assert_eq!(pledging("foo0"), 1_000_000);
+        assert_eq!(pledging("foo1"), 2_000_000);
+
+        // there are no validators to give keys to
+        let options = crate::AmendOptions {
+            generate_validator_keys: true,
+            keys_out_dir: Some(dir.path().join("keys")),
+            ..Default::default()
+        };
+        let err = parsed
+            .amend_without_validators(&genesis_file_out, &records_file_out, &changes, &options)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+        assert!(format!("{:#}", err).contains("--validators"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_generate_validator_keys() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let keys_dir = dir.path().join("keys");
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions {
+            keys_out_dir: Some(keys_dir.clone()),
+            generate_validator_keys: true,
+            ..Default::default()
+        };
+        let changes = crate::GenesisChanges::default();
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(outcome.validators, genesis.config.validators);
+        let validators_file = std::fs::read_to_string(keys_dir.join("validators.json")).unwrap();
+        let validators: Vec<AccountInfo> = serde_json::from_str(&validators_file).unwrap();
+        assert_eq!(validators, genesis.config.validators);
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+
+        assert_eq!(validators.len(), parsed.validators_in.len());
+        for (v, old) in validators.iter().zip(parsed.validators_in.iter()) {
+            assert_eq!(v.account_id, old.account_id);
+            assert_ne!(v.public_key, old.public_key);
+            let key_file = KeyFile::from_file(&validator_key_file(&keys_dir, &v.account_id))
+                .unwrap();
+            assert_eq!(key_file.account_id, v.account_id);
+            assert_eq!(key_file.public_key, v.public_key);
+            assert_eq!(key_file.private_key.public_key(), v.public_key);
+            assert!(
+                records.iter().any(|r| matches!(
+                    r,
+                    StateRecord::AccessKey { account_id, public_key, .. }
+                        if account_id == &v.account_id && public_key == &v.public_key
+                )),
+                "no access key added for the new key of {}",
+                &v.account_id
+            );
+        }
+
+        let options = crate::AmendOptions { generate_validator_keys: true, ..Default::default() };
+        let err =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_validator_secret_keys() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let keys_dir = dir.path().join("keys");
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options =
+            crate::AmendOptions { keys_out_dir: Some(keys_dir.clone()), ..Default::default() };
+        let changes = crate::GenesisChanges::default();
+
+        let secret_keys = parsed
+            .validators_in
+            .iter()
+            .map(|_| SecretKey::from_random(KeyType::ED25519))
+            .collect::<Vec<_>>();
+        let mut validators = parsed
+            .validators_in
+            .iter()
+            .zip(secret_keys.iter())
+            .map(|(v, secret_key)| {
+                let v = AccountInfo { public_key: secret_key.public_key(), ..v.clone() };
+                let mut v = serde_json::to_value(v).unwrap();
+                v["secret_key"] = serde_json::to_value(secret_key).unwrap();
+                v
+            })
+            .collect::<Vec<_>>();
+        // no key file is written for a validator without a secret key
+        validators.last_mut().unwrap().as_object_mut().unwrap().remove("secret_key");
+        let outcome = parsed
+            .amend_with_validators(
+                &serde_json::to_string(&validators).unwrap(),
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap();
+
+        let (last, rest) = outcome.validators.split_last().unwrap();
+        for (v, secret_key) in rest.iter().zip(secret_keys.iter()) {
+            let key_file = KeyFile::from_file(&validator_key_file(&keys_dir, &v.account_id))
+                .unwrap();
+            assert_eq!(key_file.account_id, v.account_id);
+            assert_eq!(key_file.public_key, v.public_key);
+            assert_eq!(&key_file.private_key, secret_key);
+        }
+        assert!(!validator_key_file(&keys_dir, &last.account_id).exists());
+        assert!(!keys_dir.join("validators.json").exists());
+
+        validators[0]["secret_key"] = serde_json::to_value(&secret_keys[1]).unwrap();
+        let err = parsed
+            .amend_with_validators(
+                &serde_json::to_string(&validators).unwrap(),
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        for t in TEST_CASES.iter() {
+            let parsed = t.parse(&[]).unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let genesis_file_out = dir.path().join("genesis.json");
+            let records_file_out = dir.path().join("records.json");
+            let account_index_out = dir.path().join("index.json");
+            let changes = crate::GenesisChanges::default();
+
+            let options = crate::AmendOptions { dry_run: true, ..Default::default() };
+            let dry_run =
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            assert!(!genesis_file_out.exists());
+            assert!(!records_file_out.exists());
+
+            let options = crate::AmendOptions {
+                account_index_out: Some(account_index_out.clone()),
+                ..Default::default()
+            };
+            let outcome =
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            assert_eq!(dry_run, outcome);
+            let summary = outcome.summary;
+
+            let rows = std::fs::read_to_string(&account_index_out)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str::<crate::AccountIndexRow>(l).unwrap())
+                .collect::<Vec<_>>();
+            let num_rows = |status| rows.iter().filter(|r| r.status == status).count() as u64;
+            assert_eq!(summary.accounts_added, num_rows(crate::AccountStatus::Added));
+            assert_eq!(summary.accounts_modified, num_rows(crate::AccountStatus::Modified));
+            assert_eq!(
+                summary.accounts_passed_through,
+                num_rows(crate::AccountStatus::PassedThrough)
+            );
+
+            let genesis = Genesis::from_files(
+                &genesis_file_out,
+                &records_file_out,
+                GenesisValidationMode::UnsafeFast,
+            )
+            .unwrap();
+            assert_eq!(summary.total_supply, genesis.config.total_supply);
+            let num_replaced = parsed
+                .genesis
+                .config
+                .validators
+                .iter()
+                .filter(|old| {
+                    !genesis.config.validators.iter().any(|v| v.account_id == old.account_id)
+                })
+                .count() as u64;
+            assert_eq!(summary.validators_replaced, num_replaced);
+
+            let mut injected = parsed
+                .validators_in
+                .iter()
+                .map(|v| (v.account_id.clone(), v.public_key.clone()))
+                .collect::<HashSet<_>>();
+            injected.extend(parsed.extra_records.iter().filter_map(|r| match r {
+                StateRecord::AccessKey { account_id, public_key, .. } => {
+                    Some((account_id.clone(), public_key.clone()))
+                }
+                _ => None,
+            }));
+            let mut num_injected = 0;
+            genesis.for_each_record(|r| {
+                if let StateRecord::AccessKey { account_id, public_key, .. } = r {
+                    if injected.contains(&(account_id.clone(), public_key.clone())) {
+                        num_injected += 1;
+                    }
+                }
+            });
+            assert_eq!(summary.access_keys_injected, num_injected);
+        }
+
+        let options = crate::AmendOptions { dry_run: true, smoke_test: true, ..Default::default() };
+        let err = TEST_CASES[0].run_with(&[], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("can't be given together"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_summary_out() {
+        let parsed = TEST_CASES[1].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let summary_out = dir.path().join("summary.json");
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions {
+            summary_out: Some(summary_out.clone()),
+            summary_interval: Some(std::time::Duration::from_millis(1)),
+            record_delay: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let read_summary = || -> Option<crate::AmendSummary> {
+            let summary = std::fs::read(&summary_out).ok()?;
+            Some(serde_json::from_slice(&summary).unwrap())
+        };
+
+        let outcome = std::thread::scope(|s| {
+            let run = s.spawn(|| {
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap()
+            });
+            let mut intermediate = None;
+            while !run.is_finished() {
+                if let Some(summary) = read_summary() {
+                    if summary.records_processed > 0 && !summary.complete {
+                        intermediate.get_or_insert(summary);
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            let intermediate = intermediate.expect("no intermediate summary was written");
+            assert_eq!(intermediate.phase, crate::AmendPhase::ProcessingRecords);
+            assert!(intermediate.last_account_id.is_some());
+            run.join().unwrap()
+        });
+
+        let summary = read_summary().unwrap();
+        assert!(summary.complete);
+        assert_eq!(summary.phase, crate::AmendPhase::Done);
+        assert_eq!(summary, outcome.summary);
+        assert_eq!(summary.records_processed, TEST_CASES[1].records_in.len() as u64);
+    }
+
+    fn read_diff(path: &std::path::Path) -> Vec<crate::AccountDiffRow> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_file() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let diff_file = dir.path().join("diff.json");
+        let changes = crate::GenesisChanges::default();
+        let options =
+            crate::AmendOptions { diff_file: Some(diff_file.clone()), ..Default::default() };
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        // foo1 gets the same pledge and key it already had, and asdf.unc is passed through
+        let rows = read_diff(&diff_file);
+        let changed = rows.iter().map(|r| (r.account_id.as_str(), r.change)).collect::<Vec<_>>();
+        assert_eq!(
+            changed,
+            [
+                ("extra-account.unc", crate::AccountChange::Added),
+                ("foo0", crate::AccountChange::Modified),
+                ("foo2", crate::AccountChange::Added),
+            ]
+        );
+        let foo0 = &rows[1];
+        let before = foo0.before.as_ref().unwrap();
+        let after = foo0.after.as_ref().unwrap();
+        assert_eq!((before.amount, before.pledging), (1_000_000, 1_000_000));
+        assert_eq!((after.amount, after.pledging), (149_000_000, 1_000_000));
+        assert!(foo0.keys_added.is_empty() && foo0.keys_replaced.is_empty());
+        let foo2 = &rows[2];
+        assert!(foo2.before.is_none());
+        assert_eq!(foo2.after.as_ref().unwrap().pledging, 3_000_000);
+        assert_eq!(
+            foo2.keys_added,
+            ["ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8".parse::<PublicKey>().unwrap()]
+        );
+
+        // removed accounts are listed too, and the file is written on dry runs
+        let (options, _file) = remove_options(serde_json::json!(["asdf.unc"]));
+        let options = crate::AmendOptions {
+            diff_file: Some(diff_file.clone()),
+            dry_run: true,
+            ..options
+        };
+        parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        let rows = read_diff(&diff_file);
+        let removed = rows
+            .iter()
+            .filter(|r| r.change == crate::AccountChange::Removed)
+            .map(|r| (r.account_id.as_str(), r.before.as_ref().unwrap().amount))
+            .collect::<Vec<_>>();
+        assert_eq!(removed, [("asdf.unc", 1_234_000)]);
+        assert_eq!(rows.len(), 4);
+    }
+
+    fn records_written(counts: &[(&str, u64)]) -> crate::RecordStats {
+        crate::RecordStats {
+            known: counts.iter().map(|(t, n)| (t.to_string(), *n)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let amend = |t: &TestCase| {
+            t.parse(&[])
+                .unwrap()
+                .amend(
+                    &genesis_file_out,
+                    &records_file_out,
+                    &crate::GenesisChanges::default(),
+                    &crate::AmendOptions::default(),
+                )
+                .unwrap()
+        };
+        let validator_ids = |outcome: &crate::AmendGenesisOutcome| {
+            outcome.validators.iter().map(|v| v.account_id.to_string()).collect::<Vec<_>>()
+        };
+        let foo0: AccountId = "foo0".parse().unwrap();
+
+        let outcome = amend(&TEST_CASES[0]);
+        assert_eq!(outcome.total_supply, 166_234_000 + 10_000 * framework::config::UNC_BASE);
+        assert_eq!(outcome.records_written, records_written(&[("Account", 5), ("AccessKey", 5)]));
+        assert_eq!(validator_ids(&outcome), ["foo0", "foo1", "foo2"]);
+        assert_eq!(outcome.balance_overrides, [foo0.clone()]);
+
+        let outcome = amend(&TEST_CASES[1]);
+        assert_eq!(outcome.total_supply, 415_234_000 + 10_000 * framework::config::UNC_BASE);
+        assert_eq!(outcome.records_written, records_written(&[("Account", 6), ("AccessKey", 8)]));
+        assert_eq!(validator_ids(&outcome), ["foo2", "foo3"]);
+        assert_eq!(outcome.balance_overrides, [foo0.clone()]);
+
+        let outcome = amend(&TEST_CASES[2]);
+        assert_eq!(outcome.total_supply, 100_000_000);
+        assert_eq!(
+            outcome.records_written,
+            records_written(&[("Account", 1), ("AccessKey", 1), ("Contract", 1)])
+        );
+        assert_eq!(validator_ids(&outcome), ["foo0"]);
+        assert_eq!(outcome.balance_overrides, [foo0]);
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges::default();
+        let options = crate::AmendOptions::default();
+
+        let err = parsed
+            .amend_with_validators(
+                "[{\"account_id\": ",
+                &genesis_file_out,
+                &records_file_out,
+                &changes,
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::InputParse(_))
+        ));
+
+        let unwritable = dir.path().join("nonexistent").join("records.json");
+        let err = parsed.amend(&genesis_file_out, &unwritable, &changes, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Io(_))
+        ));
+        assert!(format!("{:#}", err).contains("Failed opening output records file"), "{:#}", err);
+
+        let options = crate::AmendOptions { dry_run: true, smoke_test: true, ..Default::default() };
+        let err = parsed.amend(&genesis_file_out, &records_file_out, &changes, &options);
+        assert!(matches!(
+            err.unwrap_err().downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+
+        let validator = parsed.validators_in[0].clone();
+        parsed.validators_in.push(validator);
+        let err = parsed
+            .amend(&genesis_file_out, &records_file_out, &changes, &crate::AmendOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Validation(_))
+        ));
+        assert!(format!("{:#}", err).contains("specified twice"), "{:#}", err);
+
+        let codes = [
+            crate::AmendGenesisError::Validation(anyhow::anyhow!("")).exit_code(),
+            crate::AmendGenesisError::InputParse(anyhow::anyhow!("")).exit_code(),
+            crate::AmendGenesisError::Conflict(anyhow::anyhow!("")).exit_code(),
+            crate::AmendGenesisError::Io(anyhow::anyhow!("")).exit_code(),
+            crate::AmendGenesisError::Internal(anyhow::anyhow!("")).exit_code(),
+        ];
+        assert_eq!(codes.iter().collect::<HashSet<_>>().len(), codes.len());
+    }
+
+    fn unknown_record() -> serde_json::Value {
+        serde_json::json!({
+            "SomeFutureRecord": {
+                "account_id": "asdf.unc",
+                "value": "aGVsbG8=",
+            }
+        })
+    }
+
+    #[test]
+    fn test_unknown_record_passthrough() {
+        TEST_CASES[0].run_with(&[unknown_record()], &crate::AmendOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_record_strict() {
+        let options = crate::AmendOptions { strict_record_types: true, ..Default::default() };
+        let err = TEST_CASES[0].run_with(&[unknown_record()], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown record type SomeFutureRecord"), "{:#}", err);
+    }
+
+    // an access key record for an account that doesn't exist in TEST_CASES[0]
+    fn dangling_key() -> serde_json::Value {
+        serde_json::json!({
+            "AccessKey": {
+                "account_id": "nonexistent.unc",
+                "public_key": "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                "access_key": {
+                    "nonce": 0,
+                    "permission": "FullAccess",
+                },
+            }
+        })
+    }
+
+    #[test]
+    fn test_validation_mode() {
+        TEST_CASES[0].run_with(&[dangling_key()], &crate::AmendOptions::default()).unwrap();
+
+        let options = crate::AmendOptions {
+            validation_mode: crate::ValidationMode::Full,
+            ..Default::default()
+        };
+        let err = TEST_CASES[0].run_with(&[dangling_key()], &options).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("the input genesis failed validation"), "{}", err);
+        assert!(err.contains("access key account nonexistent.unc does not exist"), "{}", err);
+
+        let options = crate::AmendOptions { validate_output: true, ..Default::default() };
+        let err = TEST_CASES[0].run_with(&[dangling_key()], &options).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("the output genesis failed validation"), "{}", err);
+        assert!(err.contains("access key account nonexistent.unc does not exist"), "{}", err);
+    }
+
+    #[test]
+    fn test_genesis_height_and_time() {
+        let genesis_time = "2024-01-02T03:04:05Z".parse().unwrap();
+        let changes = crate::GenesisChanges {
+            genesis_height: Some(1234),
+            genesis_time: Some(genesis_time),
+            ..Default::default()
+        };
+        let config = TEST_CASES[0]
+            .run_with_changes(&[], &changes, &crate::AmendOptions::default())
+            .unwrap();
+        assert_eq!(config.genesis_height, 1234);
+        assert_eq!(config.genesis_time, genesis_time);
+    }
+
+    #[test]
+    fn test_inflation_params() {
+        let changes = crate::GenesisChanges {
+            max_inflation_rate: Some(Rational32::new(1, 100)),
+            num_blocks_per_year: Some(1234),
+            ..Default::default()
+        };
+        let config = TEST_CASES[0]
+            .run_with_changes(&[], &changes, &crate::AmendOptions::default())
+            .unwrap();
+        assert_eq!(config.max_inflation_rate, Rational32::new(1, 100));
+        assert_eq!(config.num_blocks_per_year, 1234);
+
+        for r in [Rational32::new(-1, 100), Rational32::new(3, 2)] {
+            let changes =
+                crate::GenesisChanges { max_inflation_rate: Some(r), ..Default::default() };
+            let err = TEST_CASES[0]
+                .run_with_changes(&[], &changes, &crate::AmendOptions::default())
+                .unwrap_err();
+            assert!(
+                format!("{:#}", err).contains("max_inflation_rate must be between 0 and 1"),
+                "{:#}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_pledge_to_power_arithmetic() {
+        assert_eq!(crate::pledge_to_power(1_000, Rational32::new(1, 10)), 100);
+        assert_eq!(crate::pledge_to_power(1_005, Rational32::new(3, 10)), 301);
+        assert_eq!(crate::pledge_to_power(7, Rational32::new(0, 1)), 0);
+        assert_eq!(
+            crate::pledge_to_power(10u128.pow(27), Rational32::new(1, 1_000_000_000)),
+            10u64.pow(18)
+        );
+        assert_eq!(crate::pledge_to_power(Balance::MAX, Rational32::new(i32::MAX, 1)), u64::MAX);
+        assert_eq!(crate::pledge_to_power(10u128.pow(20), Rational32::new(1, 1)), u64::MAX);
+    }
+
+    #[test]
+    fn test_pledge_to_power() {
+        let options = crate::AmendOptions {
+            pledge_to_power: Some(Rational32::new(1, 4)),
+            ..Default::default()
+        };
+        let config = TEST_CASES[0]
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        for (validator, info) in config.validators.iter().zip(TEST_CASES[0].validators_in) {
+            assert_eq!(validator.pledging, info.amount);
+            assert_eq!(validator.power, (info.amount / 4) as u64);
+        }
+
+        let options = crate::AmendOptions {
+            pledge_to_power: Some(Rational32::new(-1, 4)),
+            ..Default::default()
+        };
+        let err = TEST_CASES[0].run_with(&[], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("must not be negative"), "{:#}", err);
+    }
+
+    // sets treasury.unc as the protocol treasury account. If `exists` isn't set, it's not in
+    // the input records and has to be created
+    fn treasury_case(exists: bool) -> TestCase {
+        TestCase {
+            initial_validators: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            records_in: if exists {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 1_000_000,
+                        pledging: 1_000_000,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "foo0",
+                        public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                    },
+                    TestStateRecord::Account {
+                        account_id: "treasury.unc",
+                        amount: 5_000,
+                        pledging: 0,
+                        storage_usage: 100,
+                    },
+                ]
+            } else {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 1_000_000,
+                        pledging: 1_000_000,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "foo0",
+                        public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                    },
+                ]
+            },
+            validators_in: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            extra_records: &[],
+            wanted_records: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+                TestStateRecord::Account {
+                    account_id: "treasury.unc",
+                    amount: 5_000,
+                    pledging: 0,
+                    storage_usage: 100,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_protocol_treasury_account() {
+        let changes = crate::GenesisChanges {
+            protocol_treasury_account: Some("treasury.unc".parse().unwrap()),
+            ..Default::default()
+        };
+        // the balance is only used if the account has to be created
+        let options =
+            crate::AmendOptions { protocol_treasury_balance: 5_000, ..Default::default() };
+        for exists in [true, false] {
+            let config = treasury_case(exists).run_with_changes(&[], &changes, &options).unwrap();
+            assert_eq!(config.protocol_treasury_account.as_str(), "treasury.unc");
+            assert_eq!(config.total_supply, 2_005_000);
+        }
+    }
+
+    #[test]
+    fn test_expected_total_supply() {
+        let changes = crate::GenesisChanges {
+            protocol_treasury_account: Some("treasury.unc".parse().unwrap()),
+            ..Default::default()
+        };
+        let options =
+            crate::AmendOptions { expected_total_supply: Some(2_005_000), ..Default::default() };
+        treasury_case(true).run_with_changes(&[], &changes, &options).unwrap();
+
+        let options =
+            crate::AmendOptions { expected_total_supply: Some(2_000_000), ..Default::default() };
+        let err = treasury_case(true).run_with_changes(&[], &changes, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Validation(_))
+        ));
+        let err = format!("{:#}", err);
+        assert!(err.contains("2005000, 5000 more than the 2000000"), "{}", err);
+        // the treasury's balance comes from the input records
+        assert!(err.contains("--extra-records are foo0 (2000000)"), "{}", err);
+
+        let options = crate::AmendOptions { balance_treasury: true, ..Default::default() };
+        let err = treasury_case(true).run_with_changes(&[], &changes, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_balance_treasury() {
+        let changes = crate::GenesisChanges {
+            protocol_treasury_account: Some("treasury.unc".parse().unwrap()),
+            ..Default::default()
+        };
+        let parsed = treasury_case(true).parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions {
+            expected_total_supply: Some(2_100_000),
+            balance_treasury: true,
+            ..Default::default()
+        };
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+        assert_eq!(outcome.total_supply, 2_100_000);
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        let treasury = records
+            .iter()
+            .find_map(|r| match r {
+                StateRecord::Account { account_id, account }
+                    if account_id.as_str() == "treasury.unc" =>
+                {
+                    Some(account)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(treasury.amount(), 100_000);
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.total_supply, 2_100_000);
+
+        // foo0 alone is more than that
+        let options = crate::AmendOptions {
+            expected_total_supply: Some(1_000_000),
+            balance_treasury: true,
+            ..Default::default()
+        };
+        let err =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("already 1000000 too high"), "{:#}", err);
+    }
+
+    // an access key record for an account in TEST_CASES[0] with a nonce that can only be
+    // used after height 5
+    fn high_nonce_key() -> serde_json::Value {
+        serde_json::json!({
+            "AccessKey": {
+                "account_id": "asdf.unc",
+                "public_key": "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                "access_key": {
+                    "nonce": 5 * AccessKey::ACCESS_KEY_NONCE_RANGE_MULTIPLIER + 7,
+                    "permission": "FullAccess",
+                },
+            }
+        })
+    }
+
+    #[test]
+    fn test_genesis_height_nonce_too_high() {
+        let changes = crate::GenesisChanges { genesis_height: Some(0), ..Default::default() };
+        let err = TEST_CASES[0]
+            .run_with_changes(&[high_nonce_key()], &changes, &crate::AmendOptions::default())
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err)
+                .contains("can't sign transactions until the chain reaches height 6"),
+            "{:#}",
+            err
+        );
+
+        let options = crate::AmendOptions { force: true, ..Default::default() };
+        TEST_CASES[0].run_with_changes(&[high_nonce_key()], &changes, &options).unwrap();
+        let changes = crate::GenesisChanges { genesis_height: Some(5), ..Default::default() };
+        TEST_CASES[0]
+            .run_with_changes(&[high_nonce_key()], &changes, &crate::AmendOptions::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_storage_params_from_protocol() {
+        // the test cases are written with the storage params of the current protocol version
+        let options = crate::AmendOptions {
+            check_storage_params: true,
+            storage_params_from_protocol: true,
+            ..Default::default()
+        };
+        for t in TEST_CASES.iter() {
+            t.run_with(&[], &options).unwrap();
+        }
+        let options = crate::AmendOptions { check_storage_params: true, ..Default::default() };
+        for t in TEST_CASES.iter() {
+            t.run_with(&[], &options).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_smoke_test() {
+        let options = crate::AmendOptions { smoke_test: true, ..Default::default() };
+        for t in TEST_CASES.iter() {
+            t.run_with(&[], &options).unwrap();
+        }
+    }
+
+    // renames contract.unc, which user.unc has a function call key for
+    static RENAME_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "contract.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "contract.unc" },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::FunctionCallKey {
+                account_id: "user.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                receiver_id: "contract.unc",
+            },
+            TestStateRecord::FunctionCallKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                receiver_id: "other.unc",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "contract2.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "contract2.unc" },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::FunctionCallKey {
+                account_id: "user.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                receiver_id: "contract2.unc",
+            },
+            TestStateRecord::FunctionCallKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                receiver_id: "other.unc",
+            },
+        ],
+    };
+
+    fn rename_options(renames: serde_json::Value) -> (crate::AmendOptions, NamedTempFile) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut file, &renames).unwrap();
+        let options = crate::AmendOptions {
+            rename_accounts: Some(file.path().to_path_buf()),
+            ..Default::default()
+        };
+        (options, file)
+    }
+
+    #[test]
+    fn test_rename_accounts() {
+        let (options, _file) =
+            rename_options(serde_json::json!({ "contract.unc": "contract2.unc" }));
+        RENAME_TEST_CASE.run_with(&[], &options).unwrap();
+    }
+
+    #[test]
+    fn test_rename_to_existing_account() {
+        let (options, _file) = rename_options(serde_json::json!({ "contract.unc": "user.unc" }));
+        let err = RENAME_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("there's already an account with that ID"),
+            "{:#}",
+            err
+        );
+    }
+
+    // foo0 is a validator whose pledge goes from 1_000_000 to 2_000_000, and both it and
+    // user.unc are given new balances with --balance-overrides
+    static OVERRIDES_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 2_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            // the overridden total of 4_500_000, with the pledge from --validators taken out
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 2_500_000,
+                pledging: 2_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 2_500_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    fn overrides_options(csv: &str, strict: bool) -> (crate::AmendOptions, NamedTempFile) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(csv.as_bytes()).unwrap();
+        let options = crate::AmendOptions {
+            balance_overrides: Some(file.path().to_path_buf()),
+            strict_overrides: strict,
+            ..Default::default()
+        };
+        (options, file)
+    }
+
+    #[test]
+    fn test_balance_overrides() {
+        let csv = "account_id,amount,pledge\nfoo0,4000000,500000\nuser.unc,2500000\n";
+        for strict in [false, true] {
+            let (options, _file) = overrides_options(csv, strict);
+            let config = OVERRIDES_TEST_CASE
+                .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+                .unwrap();
+            assert_eq!(config.total_supply, 7_000_000);
+            assert_eq!(config.validators[0].pledging, 2_000_000);
+        }
+
+        // an account that isn't in the input records is only warned about without
+        // --strict-overrides
+        let csv = format!("{}missing.unc,5\n", csv);
+        let (options, _file) = overrides_options(&csv, false);
+        OVERRIDES_TEST_CASE.run_with(&[], &options).unwrap();
+        let (options, _file) = overrides_options(&csv, true);
+        let err = OVERRIDES_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<crate::AmendGenesisError>(),
+                Some(crate::AmendGenesisError::Validation(_))
+            ),
+            "{:#}",
+            err
+        );
+        assert!(format!("{:#}", err).contains("missing.unc"), "{:#}", err);
+
+        for csv in ["foo0,4000000,500000\nfoo0,1\n", "foo0\n", "foo0,lots\n"] {
+            let (options, _file) = overrides_options(csv, false);
+            let err = OVERRIDES_TEST_CASE.run_with(&[], &options).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<crate::AmendGenesisError>(),
+                    Some(crate::AmendGenesisError::InputParse(_))
+                ),
+                "{:#}",
+                err
+            );
+        }
+    }
+
+    // user.unc's Account record and its access key appear twice in the input records, the
+    // second Account record with a different balance
+    static DUPLICATES_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 9_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    #[test]
+    fn test_duplicate_input_records() {
+        let err = DUPLICATES_TEST_CASE.run_with(&[], &Default::default()).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<crate::AmendGenesisError>(),
+                Some(crate::AmendGenesisError::Validation(_))
+            ),
+            "{:#}",
+            err
+        );
+        let msg = format!("{:#}", err);
+        assert!(msg.contains("2 duplicate"), "{}", msg);
+        assert!(msg.contains("Account user.unc"), "{}", msg);
+        assert!(
+            msg.contains("AccessKey user.unc ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8"),
+            "{}",
+            msg
+        );
+
+        // the first of each is kept, so user.unc's balance is only counted once
+        let options = crate::AmendOptions { dedupe_input: true, ..Default::default() };
+        let config = DUPLICATES_TEST_CASE
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        assert_eq!(config.total_supply, 3_000_000);
+    }
+
+    // renames user.old to user.unc and drops spam.unc with the hook given to
+    // amend_genesis_with()
+    static HOOK_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.old",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.old",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+            TestStateRecord::Account {
+                account_id: "spam.unc",
+                amount: 7_000_000,
+                pledging: 0,
+                storage_usage: 232,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "spam.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+            TestStateRecord::Data { account_id: "spam.unc", data_key: "k", value: "vvvvvvv" },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    #[test]
+    fn test_record_hook() {
+        let parsed = HOOK_TEST_CASE.parse(&[]).unwrap();
+        let old_id: AccountId = "user.old".parse().unwrap();
+        let new_id: AccountId = "user.unc".parse().unwrap();
+        let mut seen = 0;
+        let mut hook = |mut r: StateRecord| {
+            seen += 1;
+            match &mut r {
+                StateRecord::Account { account_id, .. }
+                | StateRecord::AccessKey { account_id, .. }
+                    if *account_id == old_id =>
+                {
+                    *account_id = new_id.clone();
+                }
+                _ => {}
+            }
+            if state_record_to_account_id(&r).as_str() == "spam.unc" {
+                crate::RecordAction::Drop
+            } else {
+                crate::RecordAction::Keep(r)
+            }
+        };
+        let genesis_file_out = NamedTempFile::new().unwrap();
+        let records_file_out = NamedTempFile::new().unwrap();
+        let validators = serde_json::to_string(&parsed.validators_in).unwrap();
+        let outcome = parsed
+            .amend_records(
+                Some(&validators),
+                genesis_file_out.path(),
+                parsed.records_file_in.path().into(),
+                records_file_out.path().into(),
+                &crate::GenesisChanges::default(),
+                &crate::AmendOptions::default(),
+                Some(&mut hook),
+            )
+            .unwrap();
+        assert_eq!(seen, 7);
+        // spam.unc's balance is gone with its Account record
+        assert_eq!(outcome.total_supply, 3_000_000);
+
+        let got_records: Vec<StateRecord> =
+            serde_json::from_reader(std::fs::File::open(records_file_out.path()).unwrap())
+                .unwrap();
+        compare_records(got_records, parsed.wanted_records).unwrap();
+    }
+
+    // removes spam.unc, which has a key, a contract and some data
+    static REMOVE_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "spam.unc",
+                amount: 7_000_000,
+                pledging: 0,
+                storage_usage: 232,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "spam.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+            TestStateRecord::Contract { account_id: "spam.unc" },
+            TestStateRecord::Data { account_id: "spam.unc", data_key: "k", value: "vvvvvvv" },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "user.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "user.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    fn remove_options(accounts: serde_json::Value) -> (crate::AmendOptions, NamedTempFile) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut file, &accounts).unwrap();
+        let options = crate::AmendOptions {
+            remove_accounts: Some(file.path().to_path_buf()),
+            ..Default::default()
+        };
+        (options, file)
+    }
+
+    #[test]
+    fn test_remove_accounts() {
+        // nonexistent.unc isn't in the input records, which is fine
+        let (options, _file) = remove_options(serde_json::json!(["spam.unc", "nonexistent.unc"]));
+        let config = REMOVE_TEST_CASE
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        assert_eq!(config.total_supply, 3_000_000);
+    }
+
+    #[test]
+    fn test_remove_validator() {
+        let (options, _file) = remove_options(serde_json::json!(["spam.unc", "foo0"]));
+        let err = REMOVE_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(
+            format!("{:#}", err)
+                .contains("foo0 is listed in both --remove-accounts and --validators"),
+            "{:#}",
+            err
+        );
+    }
+
+    // receipts and received data for spam.unc are removed with it, and those for the accounts
+    // that are kept, including the validator, are written as they are
+    #[test]
+    fn test_remove_accounts_receipts() {
+        let receipt = |receiver_id: &str| {
+            Box::new(Receipt::new_balance_refund(&receiver_id.parse().unwrap(), 10))
+        };
+        let received_data = |account_id: &str| StateRecord::ReceivedData {
+            account_id: account_id.parse().unwrap(),
+            data_id: hash(account_id.as_bytes()),
+            data: Some(b"data".to_vec()),
+        };
+        let dropped = [
+            StateRecord::DelayedReceipt(receipt("spam.unc")),
+            StateRecord::PostponedReceipt(receipt("spam.unc")),
+            received_data("spam.unc"),
+        ];
+        let kept = [
+            StateRecord::DelayedReceipt(receipt("user.unc")),
+            StateRecord::PostponedReceipt(receipt("foo0")),
+            received_data("user.unc"),
+        ];
+        let raw_records_in = dropped
+            .iter()
+            .chain(kept.iter())
+            .map(|r| serde_json::to_value(r).unwrap())
+            .collect::<Vec<_>>();
+        let parsed = REMOVE_TEST_CASE.parse(&raw_records_in).unwrap();
+        let (options, _file) = remove_options(serde_json::json!(["spam.unc"]));
+        let genesis_file_out = NamedTempFile::new().unwrap();
+        let records_file_out = NamedTempFile::new().unwrap();
+        let outcome = parsed
+            .amend(
+                genesis_file_out.path(),
+                records_file_out.path(),
+                &crate::GenesisChanges::default(),
+                &options,
+            )
+            .unwrap();
+        assert_eq!(outcome.summary.receipts_dropped, 3);
+        assert_eq!(outcome.total_supply, 3_000_000);
+
+        let got_records: Vec<StateRecord> =
+            serde_json::from_reader(std::fs::File::open(records_file_out.path()).unwrap())
+                .unwrap();
+        let (got_receipts, got_records): (Vec<_>, Vec<_>) =
+            got_records.into_iter().partition(|r| {
+                matches!(
+                    r,
+                    StateRecord::DelayedReceipt(_)
+                        | StateRecord::PostponedReceipt(_)
+                        | StateRecord::ReceivedData { .. }
+                )
+            });
+        assert_eq!(got_receipts, kept);
+        compare_records(got_records, parsed.wanted_records).unwrap();
+    }
+
+    #[test]
+    fn test_extra_records_chip() {
+        let public_key: PublicKey =
+            "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf".parse().unwrap();
+        let chip = |args: &[u8]| StateRecord::Rsa2048Keys {
+            account_id: "foo0".parse().unwrap(),
+            public_key: public_key.clone(),
+            rsa_key: RegisterRsa2048KeysAction {
+                public_key: public_key.clone(),
+                operation_type: 0,
+                args: args.to_vec(),
+            },
+        };
+        // the output records, and the storage usage of foo0
+        let run = |raw_records_in: &[serde_json::Value], extra: &[StateRecord]| {
+            let mut parsed = REMOVE_TEST_CASE.parse(raw_records_in).unwrap();
+            parsed.extra_records.extend_from_slice(extra);
+            let genesis_file_out = NamedTempFile::new().unwrap();
+            let records_file_out = NamedTempFile::new().unwrap();
+            parsed
+                .amend(
+                    genesis_file_out.path(),
+                    records_file_out.path(),
+                    &crate::GenesisChanges::default(),
+                    &crate::AmendOptions::default(),
+                )
+                .unwrap();
+            let records: Vec<StateRecord> =
+                serde_json::from_reader(std::fs::File::open(records_file_out.path()).unwrap())
+                    .unwrap();
+            let storage_usage = records
+                .iter()
+                .find_map(|r| match r {
+                    StateRecord::Account { account_id, account }
+                        if account_id.as_str() == "foo0" =>
+                    {
+                        Some(account.storage_usage())
+                    }
+                    _ => None,
+                })
+                .unwrap();
+            (records, storage_usage)
+        };
+        let (_, without_chip) = run(&[], &[]);
+
+        let new_chip = chip(b"miner");
+        let (records, with_chip) = run(&[], &[new_chip.clone()]);
+        let account = records
+            .iter()
+            .position(|r| match r {
+                StateRecord::Account { account_id, .. } => account_id.as_str() == "foo0",
+                _ => false,
+            })
+            .unwrap();
+        assert_eq!(records[account + 1], new_chip);
+        let StateRecord::Rsa2048Keys { rsa_key, .. } = &new_chip else { unreachable!() };
+        assert_eq!(
+            with_chip,
+            without_chip
+                + borsh::object_length(rsa_key).unwrap() as StorageUsage
+                + crate::DEFAULT_NUM_EXTRA_BYTES_RECORD
+        );
+
+        // a chip in the input records with the same key is replaced
+        let old_chip = serde_json::to_value(chip(b"old")).unwrap();
+        let (records, _) = run(&[old_chip], &[new_chip.clone()]);
+        let chips = records
+            .into_iter()
+            .filter(|r| matches!(r, StateRecord::Rsa2048Keys { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(chips, vec![new_chip]);
+    }
+
+    #[test]
+    fn test_validators_without_chips() {
+        let public_key: PublicKey =
+            "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf".parse().unwrap();
+        let chip = StateRecord::Rsa2048Keys {
+            account_id: "foo0".parse().unwrap(),
+            public_key: public_key.clone(),
+            rsa_key: RegisterRsa2048KeysAction {
+                public_key,
+                operation_type: 0,
+                args: b"miner".to_vec(),
+            },
+        };
+        // the outcome and the output records, with foo0 given power from its pledge or not
+        let run = |extra: &[StateRecord], pledge_to_power: Option<Rational32>| {
+            let mut parsed = REMOVE_TEST_CASE.parse(&[]).unwrap();
+            parsed.extra_records.extend_from_slice(extra);
+            let genesis_file_out = NamedTempFile::new().unwrap();
+            let records_file_out = NamedTempFile::new().unwrap();
+            let outcome = parsed
+                .amend(
+                    genesis_file_out.path(),
+                    records_file_out.path(),
+                    &crate::GenesisChanges::default(),
+                    &crate::AmendOptions { pledge_to_power, ..Default::default() },
+                )
+                .unwrap();
+            let records: Vec<StateRecord> =
+                serde_json::from_reader(std::fs::File::open(records_file_out.path()).unwrap())
+                    .unwrap();
+            (outcome, records)
+        };
+        let foo0 = |records: &[StateRecord]| {
+            records
+                .iter()
+                .position(|r| match r {
+                    StateRecord::Account { account_id, .. } => account_id.as_str() == "foo0",
+                    _ => false,
+                })
+                .unwrap()
+        };
+        let ratio = Some(Rational32::new(1, 4));
+
+        // foo0 gets power but has no chip, so it's reported, and still written out with its
+        // power and without a chip
+        let (outcome, records) = run(&[], ratio);
+        assert_eq!(outcome.validators_without_chips, vec!["foo0".parse::<AccountId>().unwrap()]);
+        let StateRecord::Account { account, .. } = &records[foo0(&records)] else {
+            unreachable!()
+        };
+        assert!(account.power() > 0);
+        assert!(!records.iter().any(|r| matches!(r, StateRecord::Rsa2048Keys { .. })));
+
+        // without power, a missing chip isn't a problem
+        let (outcome, _) = run(&[], None);
+        assert!(outcome.validators_without_chips.is_empty());
+
+        // the chip from the extra records is written right after the account
+        let (outcome, records) = run(&[chip.clone()], ratio);
+        assert!(outcome.validators_without_chips.is_empty());
+        assert_eq!(records[foo0(&records) + 1], chip);
+    }
+
+    // keeps keep.unc, and implicitly the validator foo0 (also the treasury account) and the
+    // account added in the extra records. drop.unc and drop2.unc go away with their keys,
+    // contracts and data
+    static KEEP_ONLY_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "keep.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "keep.unc" },
+            TestStateRecord::FunctionCallKey {
+                account_id: "keep.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                receiver_id: "drop.unc",
+            },
+            TestStateRecord::Account {
+                account_id: "drop.unc",
+                amount: 3_000_000,
+                pledging: 0,
+                storage_usage: 232,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "drop.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+            TestStateRecord::Contract { account_id: "drop.unc" },
+            TestStateRecord::Data { account_id: "drop.unc", data_key: "k", value: "vvvvvvv" },
+            TestStateRecord::Account {
+                account_id: "drop2.unc",
+                amount: 4_000_000,
+                pledging: 500,
+                storage_usage: 100,
+            },
+        ],
+        extra_records: &[
+            TestStateRecord::Account {
+                account_id: "extra.unc",
+                amount: 2_000_000,
+                pledging: 0,
+                storage_usage: 0,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "extra.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "keep.unc",
+                amount: 1_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "keep.unc" },
+            TestStateRecord::FunctionCallKey {
+                account_id: "keep.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                receiver_id: "drop.unc",
+            },
+            TestStateRecord::Account {
+                account_id: "extra.unc",
+                amount: 2_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "extra.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    #[test]
+    fn test_keep_only_accounts() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut file, &serde_json::json!(["keep.unc"])).unwrap();
+        let options = crate::AmendOptions {
+            keep_only_accounts: Some(file.path().to_path_buf()),
+            validate_output: true,
+            ..Default::default()
+        };
+        let config = KEEP_ONLY_TEST_CASE
+            .run_with_validation(
+                &[],
+                &crate::GenesisChanges::default(),
+                &options,
+                GenesisValidationMode::Full,
+            )
+            .unwrap();
+        assert_eq!(config.total_supply, 5_000_000);
+
+        let options =
+            crate::AmendOptions { remove_accounts: Some(file.path().to_path_buf()), ..options };
+        let err = KEEP_ONLY_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("can't be given together"), "{:#}", err);
+    }
+
+    // accounts in shards 2 (shard1.test.unc and shard1x.unc), 0 (foo0) and 3 (zzz.unc) of the
+    // 4 shard layout the test cases start with
+    static EXTRACT_SHARD_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[
+            TestAccountInfo {
+                account_id: "shard1.test.unc",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            },
+            TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                amount: 1_000_000,
+            },
+        ],
+        validators_in: &[
+            TestAccountInfo {
+                account_id: "shard1.test.unc",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            },
+            TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                amount: 1_000_000,
+            },
+        ],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "shard1.test.unc",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "shard1.test.unc",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+            TestStateRecord::Data { account_id: "foo0", data_key: "k", value: "v" },
+            TestStateRecord::Account {
+                account_id: "shard1x.unc",
+                amount: 3_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "shard1x.unc" },
+            TestStateRecord::FunctionCallKey {
+                account_id: "shard1x.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                receiver_id: "zzz.unc",
+            },
+            TestStateRecord::Account {
+                account_id: "zzz.unc",
+                amount: 5_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "shard1.test.unc",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "shard1.test.unc",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "shard1x.unc",
+                amount: 3_000_000,
+                pledging: 0,
+                storage_usage: 183,
+            },
+            TestStateRecord::Contract { account_id: "shard1x.unc" },
+            // keys pointing at contracts in other shards are kept
+            TestStateRecord::FunctionCallKey {
+                account_id: "shard1x.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                receiver_id: "zzz.unc",
+            },
+        ],
+    };
+
+    #[test]
+    fn test_extract_shard() {
+        let options =
+            crate::AmendOptions { extract_shard: Some(2), smoke_test: true, ..Default::default() };
+        let config = EXTRACT_SHARD_TEST_CASE
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        assert_eq!(config.shard_layout, ShardLayout::v0_single_shard());
+        assert_eq!(config.num_block_producer_seats_per_shard, vec![1]);
+        assert_eq!(config.avg_hidden_validator_seats_per_shard, vec![0]);
+        assert_eq!(config.num_block_producer_seats, 1);
+        assert_eq!(
+            config.validators.iter().map(|v| v.account_id.as_str()).collect::<Vec<_>>(),
+            vec!["shard1.test.unc"]
+        );
+        assert_eq!(config.total_supply, 5_000_000);
+
+        // there are no validators in shard 3
+        let options = crate::AmendOptions { extract_shard: Some(3), ..Default::default() };
+        let err = EXTRACT_SHARD_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("none of the accounts in --validators"), "{:#}", err);
+
+        let options = crate::AmendOptions { extract_shard: Some(4), ..Default::default() };
+        let err = EXTRACT_SHARD_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("only has shards [0, 1, 2, 3]"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_records_out_dir() {
+        let parsed = EXTRACT_SHARD_TEST_CASE.parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let records_out_dir = dir.path().join("records");
+        let options = crate::AmendOptions {
+            records_out_dir: Some(records_out_dir.clone()),
+            ..Default::default()
+        };
+        let changes = crate::GenesisChanges::default();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let err =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("needs --shard-layout-file"), "{:#}", err);
+
+        // the same layout as the input, with 4 shards
+        let shard_layout = parsed.genesis.config.shard_layout.clone();
+        let shard_layout_file = dir.path().join("shard_layout.json");
+        std::fs::write(&shard_layout_file, serde_json::to_vec(&shard_layout).unwrap()).unwrap();
+        let genesis_file_in = dir.path().join("genesis_in.json");
+        std::fs::write(&genesis_file_in, serde_json::to_vec(&parsed.genesis).unwrap()).unwrap();
+        let validators_file = dir.path().join("validators.json");
+        std::fs::write(&validators_file, serde_json::to_vec(&parsed.validators_in).unwrap())
+            .unwrap();
+        crate::AmendGenesisConfig::new()
+            .genesis_file_in(&genesis_file_in)
+            .genesis_file_out(&genesis_file_out)
+            .records_in(parsed.records_file_in.path())
+            .records_out(records_file_out.as_path())
+            .validators(&validators_file)
+            .shard_layout_file(&shard_layout_file)
+            .options(options)
+            .amend()
+            .unwrap();
+
+        let read_records = |path: &std::path::Path| -> Vec<StateRecord> {
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+        };
+        let mut union = vec![];
+        for shard_id in shard_layout.shard_ids() {
+            let path = records_out_dir.join(crate::split::shard_file_name(shard_id));
+            let records = read_records(&path);
+            for r in records.iter() {
+                let account_id = state_record_to_account_id(r);
+                assert_eq!(account_id_to_shard_id(account_id, &shard_layout), shard_id, "{}", r);
+            }
+            union.extend(records);
+        }
+        let shards = |accounts: &[&str]| {
+            accounts
+                .iter()
+                .map(|a| account_id_to_shard_id(&a.parse().unwrap(), &shard_layout))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shards(&["foo0", "shard1.test.unc", "shard1x.unc", "zzz.unc"]), [0, 2, 2, 3]);
+
+        let single = read_records(&records_file_out);
+        let sorted = |records: Vec<StateRecord>| {
+            let mut records =
+                records.iter().map(|r| serde_json::to_string(r).unwrap()).collect::<Vec<_>>();
+            records.sort();
+            records
+        };
+        assert_eq!(single.len(), union.len());
+        assert_eq!(sorted(single), sorted(union));
+    }
+
+    #[test]
+    fn test_named_shard_layout() {
+        let parsed = TEST_CASES[0].parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_in = dir.path().join("genesis_in.json");
+        std::fs::write(&genesis_file_in, serde_json::to_vec(&parsed.genesis).unwrap()).unwrap();
+        let validators_file = dir.path().join("validators.json");
+        std::fs::write(&validators_file, serde_json::to_vec(&parsed.validators_in).unwrap())
+            .unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let config = || {
+            crate::AmendGenesisConfig::new()
+                .genesis_file_in(&genesis_file_in)
+                .genesis_file_out(&genesis_file_out)
+                .records_in(parsed.records_file_in.path())
+                .records_out(records_file_out.as_path())
+                .validators(&validators_file)
+        };
+
+        config().shard_layout(crate::NamedShardLayout::SimpleNightshadeV1).amend().unwrap();
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        let shard_layout = &genesis.config.shard_layout;
+        assert_eq!(shard_layout, &ShardLayout::get_simple_nightshade_layout());
+        assert_eq!(
+            shard_layout.boundary_accounts().iter().map(|a| a.as_str()).collect::<Vec<_>>(),
+            ["aurora", "aurora-0", "kkuuue2akv_1630967379.unc"]
+        );
+        // the seats are derived the same way as for --shard-layout-file
+        assert_eq!(
+            genesis.config.num_block_producer_seats_per_shard,
+            utils::get_num_seats_per_shard(4, genesis.config.num_block_producer_seats)
+        );
+        assert_eq!(genesis.config.avg_hidden_validator_seats_per_shard, vec![0; 4]);
+
+        config().shard_layout(crate::NamedShardLayout::Single).amend().unwrap();
+        let genesis = Genesis::from_file(&genesis_file_out, GenesisValidationMode::UnsafeFast)
+            .unwrap();
+        assert_eq!(genesis.config.shard_layout, ShardLayout::v0_single_shard());
+
+        let shard_layout_file = dir.path().join("shard_layout.json");
+        std::fs::write(&shard_layout_file, serde_json::to_vec(&ShardLayout::v1_test()).unwrap())
+            .unwrap();
+        let err = config()
+            .shard_layout_file(&shard_layout_file)
+            .shard_layout(crate::NamedShardLayout::Single)
+            .amend()
+            .unwrap_err();
+        assert!(matches!(err, crate::AmendGenesisError::Conflict(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_empty_shards() {
+        let parsed = EXTRACT_SHARD_TEST_CASE.parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        // foo0 is in shard 0, shard1.test.unc and shard1x.unc in shard 1 and zzz.unc in shard 2
+        let shard_layout = ShardLayout::v1(
+            vec!["shard1.test.unc".parse().unwrap(), "zzz.unc".parse().unwrap()],
+            None,
+            1,
+        );
+        let shard_layout_file = dir.path().join("shard_layout.json");
+        std::fs::write(&shard_layout_file, serde_json::to_vec(&shard_layout).unwrap()).unwrap();
+        let genesis_file_in = dir.path().join("genesis_in.json");
+        std::fs::write(&genesis_file_in, serde_json::to_vec(&parsed.genesis).unwrap()).unwrap();
+        let validators_file = dir.path().join("validators.json");
+        std::fs::write(&validators_file, serde_json::to_vec(&parsed.validators_in).unwrap())
+            .unwrap();
+        let amend = |removed: &str, empty_shard_policy| {
+            let (options, _file) = remove_options(serde_json::json!([removed]));
+            crate::AmendGenesisConfig::new()
+                .genesis_file_in(&genesis_file_in)
+                .genesis_file_out(dir.path().join("genesis.json"))
+                .records_in(parsed.records_file_in.path())
+                .records_out(dir.path().join("records.json").as_path())
+                .validators(&validators_file)
+                .shard_layout_file(&shard_layout_file)
+                .options(crate::AmendOptions { empty_shard_policy, ..options })
+                .amend()
+        };
+
+        // shard 1 still has shard1.test.unc
+        amend("shard1x.unc", crate::EmptyShardPolicy::Error).unwrap();
+
+        let err = amend("zzz.unc", crate::EmptyShardPolicy::Error).unwrap_err();
+        assert!(matches!(err, crate::AmendGenesisError::Validation(_)), "{:?}", err);
+        let err = format!("{:#}", err);
+        assert!(err.contains("1 of the 3 shards"), "{}", err);
+        assert!(err.contains("shard 2 (accounts from zzz.unc)"), "{}", err);
+
+        let outcome = amend("zzz.unc", crate::EmptyShardPolicy::Warn).unwrap();
+        assert_eq!(outcome.summary.accounts_passed_through, 1);
+        amend("zzz.unc", crate::EmptyShardPolicy::Allow).unwrap();
+    }
+
+    // a validator with a pledge much larger than the default amount. Funded by --extra-records
+    // if `funded` is set, and appearing nowhere else otherwise
+    fn large_pledge_case(funded: bool) -> TestCase {
+        const PLEDGE: Balance = 10_000_000 * framework::config::UNC_BASE;
+        TestCase {
+            initial_validators: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            records_in: &[TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            }],
+            validators_in: &[TestAccountInfo {
+                account_id: "big-validator.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                amount: PLEDGE,
+            }],
+            extra_records: if funded {
+                &[TestStateRecord::Account {
+                    account_id: "big-validator.unc",
+                    amount: 2 * PLEDGE,
+                    pledging: 0,
+                    storage_usage: 0,
+                }]
+            } else {
+                &[]
+            },
+            wanted_records: if funded {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 2_000_000,
+                        pledging: 0,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::Account {
+                        account_id: "big-validator.unc",
+                        amount: PLEDGE,
+                        pledging: PLEDGE,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "big-validator.unc",
+                        public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                    },
+                ]
+            } else {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 2_000_000,
+                        pledging: 0,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::Account {
+                        account_id: "big-validator.unc",
+                        amount: 10_000 * framework::config::UNC_BASE,
+                        pledging: PLEDGE,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "big-validator.unc",
+                        public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                    },
+                ]
+            },
+        }
+    }
+
+    #[test]
+    fn test_funded_pledge() {
+        large_pledge_case(true).run().unwrap();
+    }
+
+    #[test]
+    fn test_unfunded_pledge() {
+        let err = large_pledge_case(false).run().unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("validator big-validator.unc has a pledge of"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_unfunded_pledge_allowed() {
+        let options = crate::AmendOptions { allow_unfunded_pledges: true, ..Default::default() };
+        large_pledge_case(false).run_with(&[], &options).unwrap();
+    }
+
+    // a new validator with no balance anywhere, which gets the one set with
+    // --default-validator-balance
+    static DEFAULT_VALIDATOR_BALANCE_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[TestStateRecord::Account {
+            account_id: "foo0",
+            amount: 1_000_000,
+            pledging: 1_000_000,
+            storage_usage: 182,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "new-validator.unc",
+            public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            amount: 2_000_000,
+        }],
+        extra_records: &[],
+        wanted_records: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 2_000_000,
+                pledging: 0,
+                storage_usage: 182,
+            },
+            TestStateRecord::Account {
+                account_id: "new-validator.unc",
+                amount: 3_000_000,
+                pledging: 2_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "new-validator.unc",
+                public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+            },
+        ],
+    };
+
+    #[test]
+    fn test_default_validator_balance() {
+        let options = crate::AmendOptions {
+            default_validator_balance: Some(3_000_000),
+            ..Default::default()
+        };
+        let config = DEFAULT_VALIDATOR_BALANCE_TEST_CASE
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        assert_eq!(config.total_supply, 7_000_000);
+
+        // the pledge has to be covered by the configured balance, not the usual default
+        let options = crate::AmendOptions {
+            default_validator_balance: Some(1_000_000),
+            ..Default::default()
+        };
+        let err = DEFAULT_VALIDATOR_BALANCE_TEST_CASE.run_with(&[], &options).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("the default amount of 1000000 it would get"),
+            "{:#}",
+            err
+        );
+    }
+
+    // gives access keys in --extra-records to an account that has no Account record anywhere.
+    // If `create` is set, the output is the one wanted with --create-missing-accounts
+    fn orphan_keys_case(create: bool) -> TestCase {
+        TestCase {
+            initial_validators: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            records_in: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+            ],
+            validators_in: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            extra_records: &[
+                TestStateRecord::AccessKey {
+                    account_id: "orphan.unc",
+                    public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "orphan.unc",
+                    public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                },
+            ],
+            wanted_records: if create {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 1_000_000,
+                        pledging: 1_000_000,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "foo0",
+                        public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                    },
+                    TestStateRecord::Account {
+                        account_id: "orphan.unc",
+                        amount: 5_000_000,
+                        pledging: 0,
+                        // 100 for the account, then 33 + 9 + 40 for each of the two keys
+                        storage_usage: 264,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "orphan.unc",
+                        public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "orphan.unc",
+                        public_key: "ed25519:Eo9W44tRMwcYcoua11yM7Xfr1DjgR4EWQFM3RU27MEX8",
+                    },
+                ]
+            } else {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "foo0",
+                        amount: 1_000_000,
+                        pledging: 1_000_000,
+                        storage_usage: 182,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "foo0",
+                        public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                    },
+                ]
+            },
+        }
+    }
+
+    #[test]
+    fn test_create_missing_accounts() {
+        // without the option the keys are left out of the output
+        let config = orphan_keys_case(false)
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &Default::default())
+            .unwrap();
+        assert_eq!(config.total_supply, 2_000_000);
+
+        let options =
+            crate::AmendOptions { create_missing_accounts: Some(5_000_000), ..Default::default() };
+        let config = orphan_keys_case(true)
+            .run_with_changes(&[], &crate::GenesisChanges::default(), &options)
+            .unwrap();
+        assert_eq!(config.total_supply, 7_000_000);
+    }
+
+    // adds an account with two contract storage entries through --extra-records. If
+    // `with_account` isn't set, the Account record for it is left out
+    fn extra_data_case(with_account: bool) -> TestCase {
+        TestCase {
+            initial_validators: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            records_in: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+            ],
+            validators_in: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            extra_records: if with_account {
+                &[
+                    TestStateRecord::Account {
+                        account_id: "data.unc",
+                        amount: 5_000_000,
+                        pledging: 0,
+                        storage_usage: 0,
+                    },
+                    TestStateRecord::AccessKey {
+                        account_id: "data.unc",
+                        public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                    },
+                    TestStateRecord::Data { account_id: "data.unc", data_key: "a", value: "hello" },
+                    TestStateRecord::Data {
+                        account_id: "data.unc",
+                        data_key: "key2",
+                        value: "value2",
+                    },
+                ]
+            } else {
+                &[TestStateRecord::Data { account_id: "data.unc", data_key: "a", value: "hello" }]
+            },
+            wanted_records: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+                TestStateRecord::Account {
+                    account_id: "data.unc",
+                    amount: 5_000_000,
+                    pledging: 0,
+                    // 182 for the account and its key, then 40 + 1 + 5 and 40 + 4 + 6 for the data
+                    storage_usage: 278,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "data.unc",
+                    public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                },
+                TestStateRecord::Data { account_id: "data.unc", data_key: "a", value: "hello" },
+                TestStateRecord::Data { account_id: "data.unc", data_key: "key2", value: "value2" },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_extra_data_records() {
+        extra_data_case(true).run().unwrap();
+    }
+
+    #[test]
+    fn test_extra_data_records_no_account() {
+        let err = extra_data_case(false).run().unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Data records for data.unc were included"),
+            "{:#}",
+            err
+        );
+    }
+
+    // adds an account with a contract through --extra-records. If `matching` isn't set, the
+    // code_hash on its Account record doesn't match the code in its Contract record
+    fn code_hash_case(matching: bool) -> TestCase {
+        TestCase {
+            initial_validators: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            records_in: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+            ],
+            validators_in: &[TestAccountInfo {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                amount: 1_000_000,
+            }],
+            extra_records: &[
+                TestStateRecord::AccountWithCode {
+                    account_id: "code.unc",
+                    amount: 5_000_000,
+                    storage_usage: 0,
+                    code: if matching { &[123] } else { b"other" },
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "code.unc",
+                    public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                },
+                TestStateRecord::Contract { account_id: "code.unc" },
+            ],
+            wanted_records: &[
+                TestStateRecord::Account {
+                    account_id: "foo0",
+                    amount: 1_000_000,
+                    pledging: 1_000_000,
+                    storage_usage: 182,
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "foo0",
+                    public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+                },
+                TestStateRecord::AccountWithCode {
+                    account_id: "code.unc",
+                    amount: 5_000_000,
+                    // 182 for the account and its key, then 1 for the code
+                    storage_usage: 183,
+                    code: &[123],
+                },
+                TestStateRecord::AccessKey {
+                    account_id: "code.unc",
+                    public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                },
+                TestStateRecord::Contract { account_id: "code.unc" },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_extra_code_hash() {
+        code_hash_case(true).run().unwrap();
+    }
+
+    #[test]
+    fn test_extra_code_hash_mismatch() {
+        let err = code_hash_case(false).run().unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("but the hash of its contract code is"),
+            "{:#}",
+            err
+        );
+    }
+
+    // big.unc gets 100 Data records in the raw input records, given in the test
+    static TRUNCATE_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Account {
+                account_id: "big.unc",
+                amount: 5_000_000,
+                pledging: 0,
+                storage_usage: 100_000,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "big.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[],
+    };
+
+    #[test]
+    fn test_truncate_contract_state() {
+        // given in reverse, so the ones kept have to be picked by key
+        let raw_records = (0..100u8)
+            .rev()
+            .map(|i| {
+                serde_json::to_value(StateRecord::Data {
+                    account_id: "big.unc".parse().unwrap(),
+                    data_key: format!("key{:03}", i).into_bytes().into(),
+                    value: vec![i; 20].into(),
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let parsed = TRUNCATE_TEST_CASE.parse(&raw_records).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions {
+            truncate_contract_state: vec![
+                "account_id=big.unc,max_keys=10,max_value_bytes=8".parse().unwrap()
+            ],
+            ..Default::default()
+        };
+        let changes = crate::GenesisChanges::default();
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        let data = records
+            .iter()
+            .filter_map(|r| match r {
+                StateRecord::Data { data_key, value, .. } => {
+                    Some((data_key.to_vec(), value.to_vec()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let want = (0..10u8)
+            .map(|i| (format!("key{:03}", i).into_bytes(), vec![i; 8]))
+            .collect::<Vec<_>>();
+        assert_eq!(data, want);
+        // the 90 records dropped had 6 byte keys and 20 byte values, and the 10 kept lost 12
+        // bytes of their values each
+        let bytes_dropped = 90 * 26 + 10 * 12;
+        assert_eq!(outcome.summary.contract_keys_dropped, 90);
+        assert_eq!(outcome.summary.contract_bytes_dropped, bytes_dropped);
+        let big = records
+            .iter()
+            .find_map(|r| match r {
+                StateRecord::Account { account_id, account }
+                    if account_id.as_str() == "big.unc" =>
+                {
+                    Some(account)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            big.storage_usage(),
+            100_000 - bytes_dropped - 90 * crate::DEFAULT_NUM_EXTRA_BYTES_RECORD
+        );
+        assert_eq!(big.amount(), 5_000_000);
+        assert_eq!(outcome.total_supply, parsed.genesis.config.total_supply);
+
+        for bad in ["max_keys=10", "account_id=big.unc", "account_id=big.unc,max_bytes=1"] {
+            assert!(bad.parse::<crate::ContractStateTruncation>().is_err(), "{}", bad);
+        }
+    }
+
+    static STRIP_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::AccountWithCode {
+                account_id: "code.unc",
+                amount: 5_000_000,
+                storage_usage: 10_000,
+                code: &[123],
+            },
+            TestStateRecord::AccessKey {
+                account_id: "code.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+            TestStateRecord::Contract { account_id: "code.unc" },
+            TestStateRecord::Data { account_id: "code.unc", data_key: "k", value: "vvvvvvv" },
+            TestStateRecord::Data { account_id: "code.unc", data_key: "key2", value: "value2" },
+        ],
+        extra_records: &[],
+        wanted_records: &[],
+    };
+
+    #[test]
+    fn test_strip_contracts() {
+        let parsed = STRIP_TEST_CASE.parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let options = crate::AmendOptions {
+            strip_contracts: true,
+            validate_output: true,
+            ..Default::default()
+        };
+        let changes = crate::GenesisChanges::default();
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        assert!(
+            !records
+                .iter()
+                .any(|r| matches!(r, StateRecord::Contract { .. } | StateRecord::Data { .. })),
+            "{:?}",
+            records
+        );
+        let account = records
+            .iter()
+            .find_map(|r| match r {
+                StateRecord::Account { account_id, account }
+                    if account_id.as_str() == "code.unc" =>
+                {
+                    Some(account)
+                }
+                _ => None,
+            })
+            .unwrap();
+        // 1 byte of code, and the keys and values of the two Data records along with the
+        // per record overhead
+        let extra = crate::DEFAULT_NUM_EXTRA_BYTES_RECORD;
+        let bytes_dropped = 1 + (1 + 7 + extra) + (4 + 6 + extra);
+        assert_eq!(account.storage_usage(), 10_000 - bytes_dropped);
+        assert_eq!(account.code_hash(), CryptoHash::default());
+        assert_eq!(account.amount(), 5_000_000);
+        assert_eq!(outcome.summary.contract_keys_dropped, 2);
+        assert_eq!(outcome.summary.contract_bytes_dropped, 1 + 7 + 4 + 6);
+        assert_eq!(outcome.total_supply, parsed.genesis.config.total_supply);
+
+        let options = crate::AmendOptions {
+            strip_contracts: true,
+            truncate_contract_state: vec!["account_id=code.unc,max_keys=1".parse().unwrap()],
+            ..Default::default()
+        };
+        let err =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap_err();
+        assert!(matches!(err, crate::AmendGenesisError::Conflict(_)), "{:#}", err);
+    }
+
+    static REPLACE_CODE_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 182,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::AccountWithCode {
+                account_id: "big.unc",
+                amount: 5_000_000,
+                storage_usage: 20_000,
+                code: &[7; 1000],
+            },
+            TestStateRecord::AccountWithCode {
+                account_id: "small.unc",
+                amount: 3_000_000,
+                storage_usage: 10_000,
+                code: &[123],
+            },
+            TestStateRecord::Contract { account_id: "small.unc" },
+            TestStateRecord::AccountWithCode {
+                account_id: "tiny.unc",
+                amount: 1_000,
+                storage_usage: 120,
+                code: &[9; 50],
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[],
+    };
+
+    #[test]
+    fn test_replace_contract_code() {
+        // the Contract records of the accounts with code other than the one TestStateRecord
+        // gives
+        let raw_records = [("big.unc", vec![7; 1000]), ("tiny.unc", vec![9; 50])]
+            .into_iter()
+            .map(|(account_id, code)| {
+                serde_json::to_value(StateRecord::Contract {
+                    account_id: account_id.parse().unwrap(),
+                    code,
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let parsed = REPLACE_CODE_TEST_CASE.parse(&raw_records).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let new_code = b"\0asm\x01\0\0\0".to_vec();
+        let code_file = dir.path().join("noop.wasm");
+        std::fs::write(&code_file, &new_code).unwrap();
+        let options = crate::AmendOptions {
+            replace_contract_code: Some(code_file),
+            validate_output: true,
+            ..Default::default()
+        };
+        let changes = crate::GenesisChanges::default();
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        let mut num_contracts = 0;
+        let mut accounts = HashMap::new();
+        for r in records.iter() {
+            match r {
+                StateRecord::Contract { code, .. } => {
+                    assert_eq!(code, &new_code);
+                    num_contracts += 1;
+                }
+                StateRecord::Account { account_id, account } => {
+                    accounts.insert(account_id.as_str(), account);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(num_contracts, 3);
+        assert_eq!(outcome.summary.contracts_replaced, 3);
+        let new_len = new_code.len() as u64;
+        let big = accounts["big.unc"];
+        let small = accounts["small.unc"];
+        assert_eq!(big.code_hash(), hash(&new_code));
+        assert_eq!(small.code_hash(), big.code_hash());
+        assert_eq!(big.storage_usage(), 20_000 - 1000 + new_len);
+        assert_eq!(small.storage_usage(), 10_000 - 1 + new_len);
+        assert_eq!(big.amount(), 5_000_000);
+        assert_eq!(small.amount(), 3_000_000);
+        // 120 - 50 + 8 would leave less than the Account record itself
+        let tiny = accounts["tiny.unc"];
+        assert_eq!(tiny.code_hash(), hash(&new_code));
+        assert_eq!(tiny.storage_usage(), crate::DEFAULT_NUM_BYTES_ACCOUNT);
+        assert_eq!(outcome.total_supply, parsed.genesis.config.total_supply);
+    }
+
+    static RECOMPUTE_STORAGE_TEST_CASE: TestCase = TestCase {
+        initial_validators: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        validators_in: &[TestAccountInfo {
+            account_id: "foo0",
+            public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            amount: 1_000_000,
+        }],
+        // every storage_usage here is wrong, and the contract state of code.unc comes before
+        // its Account record
+        records_in: &[
+            TestStateRecord::Account {
+                account_id: "foo0",
+                amount: 1_000_000,
+                pledging: 1_000_000,
+                storage_usage: 999,
+            },
+            TestStateRecord::AccessKey {
+                account_id: "foo0",
+                public_key: "ed25519:He7QeRuwizNEhBioYG3u4DZ8jWXyETiyNzFD3MkTjDMf",
+            },
+            TestStateRecord::Data { account_id: "code.unc", data_key: "k", value: "vvvvvvv" },
+            TestStateRecord::AccessKey {
+                account_id: "code.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+            },
+            TestStateRecord::AccountWithCode {
+                account_id: "code.unc",
+                amount: 5_000_000,
+                storage_usage: 1,
+                code: &[123],
+            },
+            TestStateRecord::Contract { account_id: "code.unc" },
+            TestStateRecord::Account {
+                account_id: "plain.unc",
+                amount: 2_000_000,
+                pledging: 0,
+                storage_usage: 5,
+            },
+            TestStateRecord::FunctionCallKey {
+                account_id: "plain.unc",
+                public_key: "ed25519:FXXrTXiKWpXj1R6r5fBvMLpstd8gPyrBq3qMByqKVzKF",
+                receiver_id: "code.unc",
+            },
+        ],
+        extra_records: &[],
+        wanted_records: &[],
+    };
+
+    #[test]
+    fn test_recompute_storage_usage() {
+        let parsed = RECOMPUTE_STORAGE_TEST_CASE.parse(&[]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges::default();
+        let storage_usage = |options: &crate::AmendOptions| {
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, options).unwrap();
+            let records: Vec<StateRecord> =
+                serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap())
+                    .unwrap();
+            let mut storage_usage = HashMap::new();
+            let mut key_bytes = HashMap::new();
+            for r in records {
+                match r {
+                    StateRecord::Account { account_id, account } => {
+                        storage_usage.insert(account_id.to_string(), account.storage_usage());
+                    }
+                    StateRecord::AccessKey { account_id, public_key, access_key } => {
+                        let bytes = public_key.len() as u64
+                            + borsh::object_length(&access_key).unwrap() as u64
+                            + crate::DEFAULT_NUM_EXTRA_BYTES_RECORD;
+                        key_bytes.insert(account_id.to_string(), bytes);
+                    }
+                    _ => {}
+                }
+            }
+            (storage_usage, key_bytes)
+        };
+
+        let (input, _) = storage_usage(&crate::AmendOptions::default());
+        assert_eq!(input["plain.unc"], 5);
+        assert_eq!(input["code.unc"], 1);
+
+        let options = crate::AmendOptions {
+            recompute_storage_usage: true,
+            validate_output: true,
+            ..Default::default()
+        };
+        let (recomputed, key_bytes) = storage_usage(&options);
+        let account = crate::DEFAULT_NUM_BYTES_ACCOUNT;
+        let extra = crate::DEFAULT_NUM_EXTRA_BYTES_RECORD;
+        assert_eq!(recomputed["foo0"], account + key_bytes["foo0"]);
+        // 1 byte of code and one Data record
+        assert_eq!(recomputed["code.unc"], account + key_bytes["code.unc"] + 1 + (1 + 7 + extra));
+        assert_eq!(recomputed["plain.unc"], account + key_bytes["plain.unc"]);
+
+        // the contract state left out with --strip-contracts isn't counted
+        let options = crate::AmendOptions {
+            recompute_storage_usage: true,
+            strip_contracts: true,
+            ..Default::default()
+        };
+        let (stripped, _) = storage_usage(&options);
+        assert_eq!(stripped["code.unc"], account + key_bytes["code.unc"]);
+
+        let options = crate::AmendOptions { recompute_storage_usage: true, ..Default::default() };
+        let err = parsed
+            .amend_streams(
+                &genesis_file_out,
+                crate::RecordsSource::Reader(Box::new(std::io::Cursor::new(
+                    std::fs::read(parsed.records_file_in.path()).unwrap(),
+                ))),
+                records_file_out.as_path().into(),
+                &changes,
+                &options,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::AmendGenesisError>(),
+            Some(crate::AmendGenesisError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_generate_accounts() {
+        let parsed = TRUNCATE_TEST_CASE.parse(&[]).unwrap();
+        let generation: crate::AccountGeneration =
+            "count=10000,prefix=load,balance=1000,keys_per_account=2,seed=7".parse().unwrap();
+        let run = || {
+            let dir = tempfile::tempdir().unwrap();
+            let genesis_file_out = dir.path().join("genesis.json");
+            let records_file_out = dir.path().join("records.json");
+            let keys_file_out = dir.path().join("keys.ndjson");
+            let options = crate::AmendOptions {
+                generate_accounts: Some(generation.clone()),
+                generated_keys_out: Some(keys_file_out.clone()),
+                validate_output: true,
+                ..Default::default()
+            };
+            let changes = crate::GenesisChanges::default();
+            let outcome =
+                parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+            let genesis = GenesisConfig::from_file(&genesis_file_out).unwrap();
+            let records = std::fs::read_to_string(&records_file_out).unwrap();
+            let keys = std::fs::read_to_string(&keys_file_out).unwrap();
+            (outcome, genesis, records, keys)
+        };
+        let (outcome, genesis, raw_records, keys) = run();
+
+        let initial_supply = parsed.genesis.config.total_supply;
+        assert_eq!(outcome.total_supply, initial_supply + 10_000 * 1000);
+        assert_eq!(genesis.total_supply, outcome.total_supply);
+        assert_eq!(outcome.summary.accounts_added, 10_000);
+
+        let records: Vec<StateRecord> = serde_json::from_str(&raw_records).unwrap();
+        let mut public_keys = HashSet::new();
+        let mut num_accounts = 0;
+        for r in records.iter() {
+            match r {
+                StateRecord::Account { account_id, account }
+                    if account_id.as_str().starts_with("load") =>
+                {
+                    assert_eq!(account.amount(), 1000);
+                    num_accounts += 1;
+                }
+                StateRecord::AccessKey { account_id, public_key, .. }
+                    if account_id.as_str().starts_with("load") =>
+                {
+                    assert!(public_keys.insert((account_id.clone(), public_key.clone())));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(num_accounts, 10_000);
+        assert_eq!(public_keys.len(), 20_000);
+
+        let keys = keys
+            .lines()
+            .map(|line| {
+                let row: serde_json::Value = serde_json::from_str(line).unwrap();
+                let account_id: AccountId = row["account_id"].as_str().unwrap().parse().unwrap();
+                let secret_key: SecretKey =
+                    row["secret_key"].as_str().unwrap().parse().unwrap();
+                (account_id, secret_key.public_key())
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(keys, public_keys);
+
+        // the same options give the same accounts and keys
+        let (_, _, raw_records_again, keys_again) = run();
+        assert_eq!(raw_records, raw_records_again);
+        assert_eq!(keys.len(), keys_again.lines().count());
+        assert!(keys_again.lines().all(|line| {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+            let account_id: AccountId = row["account_id"].as_str().unwrap().parse().unwrap();
+            let secret_key: SecretKey = row["secret_key"].as_str().unwrap().parse().unwrap();
+            keys.contains(&(account_id, secret_key.public_key()))
+        }));
+
+        assert!(generation.generates(&"load010000.test".parse().unwrap()));
+        assert!(!generation.generates(&"load010001.test".parse().unwrap()));
+        assert!(!generation.generates(&"load0000001.test".parse().unwrap()));
+        for bad in ["count=10", "balance=1", "count=0,balance=1", "count=1,balance=1,prefix=A"] {
+            assert!(bad.parse::<crate::AccountGeneration>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_scale_balances() {
+        // balances that don't divide evenly by 3
+        let raw_records = [("a.unc", 1_001), ("b.unc", 2_003)]
+            .into_iter()
+            .map(|(account_id, amount)| {
+                serde_json::to_value(StateRecord::Account {
+                    account_id: account_id.parse().unwrap(),
+                    account: Account::new(amount, 0, 0, CryptoHash::default(), 100),
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let parsed = treasury_case(true).parse(&raw_records).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_file_out = dir.path().join("genesis.json");
+        let records_file_out = dir.path().join("records.json");
+        let changes = crate::GenesisChanges {
+            protocol_treasury_account: Some("treasury.unc".parse().unwrap()),
+            ..Default::default()
+        };
+        let options = crate::AmendOptions {
+            scale_balances: Some(Rational32::new(1, 3)),
+            validate_output: true,
+            ..Default::default()
+        };
+        let outcome =
+            parsed.amend(&genesis_file_out, &records_file_out, &changes, &options).unwrap();
+
+        let records: Vec<StateRecord> =
+            serde_json::from_str(&std::fs::read_to_string(&records_file_out).unwrap()).unwrap();
+        let accounts = records
+            .iter()
+            .filter_map(|r| match r {
+                StateRecord::Account { account_id, account } => {
+                    Some((account_id.as_str(), (account.amount(), account.pledging())))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let input_supply = 2_000_000 + 5_000 + 1_001 + 2_003;
+        assert_eq!(outcome.total_supply, input_supply / 3);
+        let sum: Balance = accounts.values().map(|(amount, pledging)| amount + pledging).sum();
+        assert_eq!(sum, outcome.total_supply);
+        assert_eq!(accounts["foo0"], (333_333, 333_333));
+        assert_eq!(accounts["a.unc"], (333, 0));
+        assert_eq!(accounts["b.unc"], (667, 0));
+        // 1 + 1 + 2 + 2 thirds rounded away from the others, and 2 from its own 5_000,
+        // make 2 whole units with 2/3 left over
+        assert_eq!(accounts["treasury.unc"], (1_666 + 2, 0));
+        let genesis = GenesisConfig::from_file(&genesis_file_out).unwrap();
+        assert_eq!(genesis.total_supply, outcome.total_supply);
+        assert_eq!(genesis.validators[0].pledging, 333_333);
+
+        for ratio in [Rational32::new(0, 1), Rational32::new(-1, 2)] {
+            let options =
+                crate::AmendOptions { scale_balances: Some(ratio), ..Default::default() };
+            let err = parsed
+                .amend(&genesis_file_out, &records_file_out, &changes, &options)
+                .unwrap_err();
+            assert!(matches!(err, crate::AmendGenesisError::Validation(_)), "{:#}", err);
+        }
+    }
 }
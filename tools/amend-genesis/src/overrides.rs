@@ -0,0 +1,100 @@
+use anyhow::Context;
+use unc_primitives::types::{AccountId, Balance};
+use unc_primitives_core::account::Account;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BalanceOverride {
+    amount: Balance,
+    // left as it is in the input records if not given
+    pledge: Option<Balance>,
+}
+
+fn parse_balance(field: &str, what: &str, line: u64) -> anyhow::Result<Balance> {
+    field.parse().with_context(|| format!("invalid {} {:?} on line {}", what, field, line))
+}
+
+/// Balances given with --balance-overrides, set on the Account records of the input records
+/// as they're streamed. The file is CSV with rows of `account_id,amount` or
+/// `account_id,amount,pledge`, optionally under a header row starting with `account_id`.
+pub(crate) struct BalanceOverrides {
+    // overrides not applied yet
+    overrides: HashMap<AccountId, BalanceOverride>,
+    // number of Account records whose balance was set
+    num_applied: u64,
+}
+
+impl BalanceOverrides {
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .with_context(|| format!("failed reading from {}", path.display()))?;
+        let mut overrides = HashMap::new();
+        for row in reader.records() {
+            let row = row.with_context(|| format!("failed reading from {}", path.display()))?;
+            let line = row.position().map_or(0, |p| p.line());
+            if line == 1 && row.get(0) == Some("account_id") {
+                continue;
+            }
+            let parse = || -> anyhow::Result<(AccountId, BalanceOverride)> {
+                let (account_id, amount, pledge) = match row.len() {
+                    2 => (&row[0], &row[1], None),
+                    3 => (&row[0], &row[1], Some(&row[2]).filter(|p| !p.is_empty())),
+                    n => anyhow::bail!(
+                        "expected account_id,amount[,pledge] on line {}, got {} fields",
+                        line,
+                        n
+                    ),
+                };
+                let account_id = account_id.parse().with_context(|| {
+                    format!("invalid account ID {:?} on line {}", account_id, line)
+                })?;
+                let amount = parse_balance(amount, "amount", line)?;
+                let pledge = pledge.map(|p| parse_balance(p, "pledge", line)).transpose()?;
+                Ok((account_id, BalanceOverride { amount, pledge }))
+            };
+            let (account_id, balance) =
+                parse().with_context(|| format!("failed parsing {}", path.display()))?;
+            if overrides.insert(account_id.clone(), balance).is_some() {
+                anyhow::bail!("{} is given more than once in {}", account_id, path.display());
+            }
+        }
+        Ok(Self { overrides, num_applied: 0 })
+    }
+
+    pub(crate) fn contains(&self, account_id: &AccountId) -> bool {
+        self.overrides.contains_key(account_id)
+    }
+
+    /// Sets the amount of `account`, and its pledge if one was given, to the ones given for
+    /// `account_id`, if any.
+    pub(crate) fn apply(&mut self, account_id: &AccountId, account: &mut Account) {
+        let Some(balance) = self.overrides.remove(account_id) else {
+            return;
+        };
+        account.set_amount(balance.amount);
+        if let Some(pledge) = balance.pledge {
+            account.set_pledging(pledge);
+        }
+        self.num_applied += 1;
+    }
+
+    /// The accounts given balances that weren't found in the input records, sorted.
+    pub(crate) fn missing(&self) -> Vec<&AccountId> {
+        let mut missing = self.overrides.keys().collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    pub(crate) fn log(&self) {
+        tracing::info!(
+            target: "amend-genesis",
+            "set the balance of {} accounts from --balance-overrides",
+            self.num_applied
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{AmendGenesisError, AmendSummary};
+use anyhow::Context;
+use unc_primitives_core::types::Balance;
+use std::cell::Cell;
+use std::io::Read;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// how often the --summary-out file is rewritten if `AmendOptions::summary_interval` isn't set
+const DEFAULT_SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+// how many input records go by between progress lines if `AmendOptions::progress_interval`
+// isn't set
+const DEFAULT_PROGRESS_INTERVAL: u64 = 1_000_000;
+
+/// Rewrites the --summary-out file with the summary so far every so often while
+/// amend_genesis() runs, so that a run that stalls or dies can be told apart from a slow one.
+pub(crate) struct SummaryWriter {
+    path: PathBuf,
+    interval: Duration,
+    last_write: Instant,
+}
+
+impl SummaryWriter {
+    pub(crate) fn new(path: PathBuf, interval: Option<Duration>) -> Self {
+        Self {
+            path,
+            interval: interval.unwrap_or(DEFAULT_SUMMARY_INTERVAL),
+            last_write: Instant::now(),
+        }
+    }
+
+    /// Writes `summary` with `total_supply` filled in if it's been long enough since the
+    /// last write.
+    pub(crate) fn maybe_write(
+        &mut self,
+        summary: &mut AmendSummary,
+        total_supply: Balance,
+    ) -> Result<(), AmendGenesisError> {
+        if self.last_write.elapsed() < self.interval {
+            return Ok(());
+        }
+        summary.total_supply = total_supply;
+        self.write(summary).map_err(AmendGenesisError::Io)
+    }
+
+    /// Replaces the file with `summary`. It's written to a temporary file next to it first
+    /// and then renamed over it, so readers never see a partially written summary.
+    pub(crate) fn write(&mut self, summary: &AmendSummary) -> anyhow::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let json = serde_json::to_vec_pretty(summary).context("Failed serializing summary")?;
+        std::fs::write(&tmp_path, json)
+            .with_context(|| format!("Failed writing {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path).with_context(|| {
+            format!("Failed renaming {} to {}", tmp_path.display(), self.path.display())
+        })?;
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+/// The number of bytes read so far through the readers wrapped with `wrap()`.
+#[derive(Clone, Default)]
+pub(crate) struct ByteCounter(Rc<Cell<u64>>);
+
+impl ByteCounter {
+    pub(crate) fn wrap<R: Read>(&self, inner: R) -> CountingReader<R> {
+        CountingReader { inner, counter: self.clone() }
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    counter: ByteCounter,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.0.set(self.counter.0.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Logs how far amend_genesis() has got through the input records every so many records,
+/// with the rate so far and, when the size of the input is known, how long the rest should
+/// take. Bytes are counted as read from the input, before decompression, so that they can be
+/// compared with the size of a compressed input file. They run a little ahead of the records,
+/// since the input is read in buffered chunks.
+pub(crate) struct ProgressReporter {
+    interval: u64,
+    records: u64,
+    bytes_read: ByteCounter,
+    total_bytes: Option<u64>,
+    start: Instant,
+}
+
+impl ProgressReporter {
+    /// `total_bytes` is the size of the input, if it's a file.
+    pub(crate) fn new(interval: Option<u64>, total_bytes: Option<u64>) -> Self {
+        Self {
+            interval: interval.unwrap_or(DEFAULT_PROGRESS_INTERVAL).max(1),
+            records: 0,
+            bytes_read: ByteCounter::default(),
+            total_bytes,
+            start: Instant::now(),
+        }
+    }
+
+    /// The counter to wrap the input in, for `line()` to tell how much of it has been read.
+    pub(crate) fn bytes_read(&self) -> &ByteCounter {
+        &self.bytes_read
+    }
+
+    /// Counts an input record, logging a progress line every `interval` records.
+    pub(crate) fn record(&mut self) {
+        self.records += 1;
+        if self.records % self.interval == 0 {
+            tracing::info!(target: "amend-genesis", "{}", self.line());
+        }
+    }
+
+    /// Logs and returns the line with the totals, once all the input records are read.
+    pub(crate) fn finish(&self) -> String {
+        let line = self.line();
+        tracing::info!(target: "amend-genesis", "{}", line);
+        line
+    }
+
+    fn line(&self) -> String {
+        let elapsed = self.start.elapsed();
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 { self.records as f64 / secs } else { 0.0 };
+        let bytes = self.bytes_read.get();
+        let mut line = format!(
+            "processed {} records, {} bytes of input in {:.1?} ({:.0} records/s)",
+            self.records, bytes, elapsed, rate
+        );
+        if let Some(total) = self.total_bytes.filter(|total| bytes > 0 && *total >= bytes) {
+            let left = elapsed.mul_f64((total - bytes) as f64 / bytes as f64);
+            line.push_str(&format!(
+                ", {:.1}% of the input read, about {:.0?} left",
+                bytes as f64 * 100.0 / total as f64,
+                left
+            ));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ProgressReporter;
+    use crate::records::{self, InputRecord};
+    use unc_primitives::hash::CryptoHash;
+    use unc_primitives::state_record::StateRecord;
+    use unc_primitives_core::account::Account;
+
+    #[test]
+    fn test_progress_counts() {
+        let mut input = Vec::new();
+        for i in 0..10 {
+            let record = StateRecord::Account {
+                account_id: format!("account{}", i).parse().unwrap(),
+                account: Account::new(1_000, 0, 0, CryptoHash::default(), 100),
+            };
+            serde_json::to_writer(&mut input, &record).unwrap();
+            input.push(b'\n');
+        }
+
+        let mut progress = ProgressReporter::new(Some(3), Some(input.len() as u64));
+        let reader = progress.bytes_read().wrap(input.as_slice());
+        let mut seen = 0;
+        records::stream_records(reader, |r| {
+            assert!(matches!(r, InputRecord::Known(_)));
+            seen += 1;
+            progress.record();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, 10);
+        let line = progress.finish();
+        let totals = format!("processed 10 records, {} bytes of input in ", input.len());
+        assert!(line.starts_with(&totals), "{}", line);
+        assert!(line.contains("100.0% of the input read"), "{}", line);
+    }
+}
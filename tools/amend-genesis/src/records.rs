@@ -0,0 +1,401 @@
+use crate::checks::ShardAccountCounts;
+use crate::split::SplitRecords;
+use crate::AmendGenesisError;
+use unc_crypto::PublicKey;
+use unc_primitives::state_record::StateRecord;
+use unc_primitives::types::AccountId;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+
+// the variant names of StateRecord this build knows about. Anything else showing up as the
+// single key of a record object is treated as a record type added by a newer dump-state
+const KNOWN_RECORD_TYPES: &[&str] = &[
+    "Account",
+    "Data",
+    "Contract",
+    "AccessKey",
+    "PostponedReceipt",
+    "ReceivedData",
+    "DelayedReceipt",
+    "Rsa2048Keys",
+];
+
+/// A single element of a records file. Records with a variant this build of
+/// amend-genesis doesn't recognize are kept as raw JSON so they can be passed
+/// through to the output unchanged.
+#[derive(Debug)]
+pub(crate) enum InputRecord {
+    Known(StateRecord),
+    Unknown { tag: String, value: serde_json::Value },
+}
+
+impl InputRecord {
+    // Parses the record at `index` in the file. A failure names the index, the account of the
+    // record and the field that couldn't be parsed, if it can tell which.
+    fn from_indexed_value(index: u64, value: serde_json::Value) -> Result<Self, String> {
+        let (tag, fields) = match &value {
+            serde_json::Value::Object(map) if map.len() == 1 => {
+                let (tag, fields) = map.iter().next().unwrap();
+                (Some(tag), fields.as_object())
+            }
+            _ => (None, None),
+        };
+        if let Some(tag) = tag.filter(|tag| !KNOWN_RECORD_TYPES.contains(&tag.as_str())) {
+            return Ok(Self::Unknown { tag: tag.clone(), value });
+        }
+        // deserialized from a reference, since the fields are looked at again on failure
+        StateRecord::deserialize(&value).map(Self::Known).map_err(|e| {
+            let account_id = fields
+                .and_then(|fields| fields.get("account_id"))
+                .and_then(serde_json::Value::as_str);
+            let error = fields.and_then(invalid_field).unwrap_or_else(|| e.to_string());
+            match account_id {
+                Some(account_id) => format!("record {} of {}: {}", index, account_id, error),
+                None => format!("record {}: {}", index, error),
+            }
+        })
+    }
+}
+
+/// Returns which of the account ID and public key fields in `fields` can't be parsed, and
+/// why, for errors that point at the field to fix rather than the whole entry.
+pub(crate) fn invalid_field(fields: &serde_json::Map<String, serde_json::Value>) -> Option<String> {
+    let invalid = |name: &str, error: serde_json::Error| {
+        Some(format!("invalid {} {}: {}", name, &fields[name], error))
+    };
+    if let Some(account_id) = fields.get("account_id") {
+        if let Err(e) = AccountId::deserialize(account_id) {
+            return invalid("account_id", e);
+        }
+    }
+    if let Some(public_key) = fields.get("public_key") {
+        if let Err(e) = PublicKey::deserialize(public_key) {
+            return invalid("public_key", e);
+        }
+    }
+    None
+}
+
+// Deserializes the record at `index` in the file, see `InputRecord::from_indexed_value()`.
+struct IndexedRecord(u64);
+
+impl<'de> DeserializeSeed<'de> for IndexedRecord {
+    type Value = InputRecord;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        InputRecord::from_indexed_value(self.0, value).map_err(de::Error::custom)
+    }
+}
+
+/// Number of records seen per record type, keyed by the StateRecord variant name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecordStats {
+    pub known: BTreeMap<String, u64>,
+    /// records with a type this build doesn't know about, which were passed through unchanged
+    pub unknown: BTreeMap<String, u64>,
+}
+
+impl RecordStats {
+    pub(crate) fn add(&mut self, record: &InputRecord) {
+        match record {
+            InputRecord::Known(r) => *self.known.entry(r.get_type_string()).or_default() += 1,
+            InputRecord::Unknown { tag, .. } => *self.unknown.entry(tag.clone()).or_default() += 1,
+        }
+    }
+
+    pub(crate) fn log(&self) {
+        for (tag, count) in self.known.iter() {
+            tracing::info!(target: "amend-genesis", "{} {} records", count, tag);
+        }
+        for (tag, count) in self.unknown.iter() {
+            tracing::warn!(
+                target: "amend-genesis",
+                "{} records of unknown type {} were passed through unchanged",
+                count,
+                tag
+            );
+        }
+    }
+}
+
+/// How the output records are laid out, given with --output-format.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordsFormat {
+    /// a single JSON array of records, which is what nodes read
+    #[default]
+    JsonArray,
+    /// newline-delimited JSON, with each record on its own line and no surrounding array
+    Ndjson,
+}
+
+/// Writes records to the output records file, counting them by type.
+pub(crate) struct RecordWriter<W> {
+    out: W,
+    format: RecordsFormat,
+    // indent the records like serde_json::to_writer_pretty() would, one field per line
+    pretty: bool,
+    num_written: u64,
+    stats: RecordStats,
+    // the per-shard files in --records-out-dir, which get every record written here too
+    split: Option<Box<SplitRecords>>,
+    // the Account records written to each shard of the output shard layout
+    shard_accounts: Option<ShardAccountCounts>,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub(crate) fn new(out: W, format: RecordsFormat, pretty: bool) -> Self {
+        Self {
+            out,
+            format,
+            pretty,
+            num_written: 0,
+            stats: RecordStats::default(),
+            split: None,
+            shard_accounts: None,
+        }
+    }
+
+    /// Also writes every record to the file of its shard in `split`.
+    pub(crate) fn split(&mut self, split: SplitRecords) {
+        self.split = Some(Box::new(split));
+    }
+
+    /// Also counts the Account records written to each shard in `counts`.
+    pub(crate) fn count_shard_accounts(&mut self, counts: ShardAccountCounts) {
+        self.shard_accounts = Some(counts);
+    }
+
+    pub(crate) fn shard_accounts(&self) -> Option<&ShardAccountCounts> {
+        self.shard_accounts.as_ref()
+    }
+
+    fn write_element(&mut self, value: &impl Serialize) -> Result<(), AmendGenesisError> {
+        if self.format == RecordsFormat::JsonArray {
+            let sep: &[u8] = match (self.num_written, self.pretty) {
+                (0, false) => b"[",
+                (_, false) => b",",
+                (0, true) => b"[\n  ",
+                (_, true) => b",\n  ",
+            };
+            self.out.write_all(sep).map_err(|e| AmendGenesisError::Io(e.into()))?;
+        }
+        if self.pretty {
+            // Each record is serialized on its own, so it's indented one more level by hand
+            // to sit inside the array.
+            let element = serde_json::to_vec_pretty(value)
+                .map_err(AmendGenesisError::from_serialize_error)?;
+            for (i, line) in element.split(|b| *b == b'\n').enumerate() {
+                let sep: &[u8] = if i == 0 { b"" } else { b"\n  " };
+                self.out
+                    .write_all(sep)
+                    .and_then(|()| self.out.write_all(line))
+                    .map_err(|e| AmendGenesisError::Io(e.into()))?;
+            }
+        } else {
+            serde_json::to_writer(&mut self.out, value)
+                .map_err(AmendGenesisError::from_serialize_error)?;
+        }
+        if self.format == RecordsFormat::Ndjson {
+            self.out.write_all(b"\n").map_err(|e| AmendGenesisError::Io(e.into()))?;
+        }
+        self.num_written += 1;
+        Ok(())
+    }
+
+    pub(crate) fn write(&mut self, record: &StateRecord) -> Result<(), AmendGenesisError> {
+        self.write_element(record)?;
+        *self.stats.known.entry(record.get_type_string()).or_default() += 1;
+        if let (Some(counts), StateRecord::Account { account_id, .. }) =
+            (&mut self.shard_accounts, record)
+        {
+            counts.add(account_id);
+        }
+        if let Some(split) = &mut self.split {
+            split.write(record)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a record of a type this build doesn't know about as it was given.
+    pub(crate) fn write_unknown(
+        &mut self,
+        tag: String,
+        value: &serde_json::Value,
+    ) -> Result<(), AmendGenesisError> {
+        self.write_element(value)?;
+        if let Some(split) = &mut self.split {
+            split.write_unknown(tag.clone(), value)?;
+        }
+        *self.stats.unknown.entry(tag).or_default() += 1;
+        Ok(())
+    }
+
+    /// Ends the list of records, and returns the number of records written of each type
+    /// along with the writer. The per-shard files are ended and flushed too.
+    pub(crate) fn end(mut self) -> Result<(RecordStats, W), AmendGenesisError> {
+        if let Some(split) = self.split.take() {
+            split.finish()?;
+        }
+        if self.format == RecordsFormat::JsonArray {
+            let end: &[u8] = match (self.num_written, self.pretty) {
+                (0, _) => b"[]",
+                (_, false) => b"]",
+                (_, true) => b"\n]",
+            };
+            self.out.write_all(end).map_err(|e| AmendGenesisError::Io(e.into()))?;
+        }
+        Ok((self.stats, self.out))
+    }
+}
+
+// Same as the RecordsProcessor in unc-chain-configs, but yields InputRecords and lets
+// the sink return an error, which stops the stream.
+struct RecordsProcessor<'a, F> {
+    sink: &'a mut F,
+    error: &'a mut Option<anyhow::Error>,
+    // number of records seen so far, which is the index of the next one
+    num_records: &'a mut u64,
+}
+
+impl<'de, F: FnMut(InputRecord) -> anyhow::Result<()>> Visitor<'de> for RecordsProcessor<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(
+            "either:\
+        1. array of StateRecord\
+        2. map with records field which is array of StateRecord\
+        3. StateRecord on its own line, in NDJSON",
+        )
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(record) = seq.next_element_seed(IndexedRecord(*self.num_records))? {
+            *self.num_records += 1;
+            if let Err(e) = (self.sink)(record) {
+                *self.error = Some(e);
+                return Err(de::Error::custom("aborted by record callback"));
+            }
+        }
+        Ok(())
+    }
+
+    // Either a map with a records field, or a single record on an NDJSON line, which is a
+    // map with just one key
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut me = Some(self);
+        let mut has_records_field = false;
+        let mut next_key = map.next_key::<String>()?;
+        if let Some(first_key) = next_key.clone().filter(|key| key != "records") {
+            let value = map.next_value::<serde_json::Value>()?;
+            next_key = map.next_key::<String>()?;
+            if next_key.is_none() {
+                let me = me.take().unwrap();
+                let mut record = serde_json::Map::new();
+                record.insert(first_key, value);
+                let record = serde_json::Value::Object(record);
+                let record = InputRecord::from_indexed_value(*me.num_records, record)
+                    .map_err(de::Error::custom)?;
+                *me.num_records += 1;
+                if let Err(e) = (me.sink)(record) {
+                    *me.error = Some(e);
+                    return Err(de::Error::custom("aborted by record callback"));
+                }
+                return Ok(());
+            }
+        }
+        while let Some(key) = next_key.take() {
+            match key.as_str() {
+                "records" => {
+                    let me =
+                        me.take().ok_or_else(|| de::Error::custom("duplicate field: records"))?;
+                    map.next_value_seed(me)?;
+                    has_records_field = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+            next_key = map.next_key::<String>()?;
+        }
+        if has_records_field {
+            Ok(())
+        } else {
+            Err(de::Error::custom("missing field: records"))
+        }
+    }
+}
+
+impl<'de, F: FnMut(InputRecord) -> anyhow::Result<()>> DeserializeSeed<'de>
+    for RecordsProcessor<'_, F>
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+// Skips whitespace, and returns the next byte without consuming it, or None at the end.
+fn peek_non_whitespace(reader: &mut impl BufRead) -> std::io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        let Some(&byte) = buf.first() else {
+            return Ok(None);
+        };
+        if !byte.is_ascii_whitespace() {
+            return Ok(Some(byte));
+        }
+        reader.consume(1);
+    }
+}
+
+/// Streams the records in `reader` to `callback`, stopping at the first error
+/// returned by it. The file can be a JSON with comments, and either hold an array of
+/// records or a map with a records field, or be NDJSON with a record per line. NDJSON is
+/// told apart from an array by its first non-whitespace byte, which is `{` rather than `[`.
+pub(crate) fn stream_records(
+    reader: impl Read,
+    mut callback: impl FnMut(InputRecord) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let reader_without_comments = unc_config_utils::strip_comments_from_json_reader(reader);
+    let mut reader = BufReader::new(reader_without_comments);
+    let mut error = None;
+    let mut num_records = 0;
+    let ndjson = peek_non_whitespace(&mut reader)? == Some(b'{');
+    loop {
+        // each NDJSON line gets a deserializer of its own. Deserializing a map doesn't read
+        // past its closing brace, so nothing of the next line is lost
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+        let records_processor = RecordsProcessor {
+            sink: &mut callback,
+            error: &mut error,
+            num_records: &mut num_records,
+        };
+        let result = deserializer.deserialize_any(records_processor);
+        if let Some(e) = error.take() {
+            return Err(e);
+        }
+        result?;
+        drop(deserializer);
+        if !ndjson || peek_non_whitespace(&mut reader)?.is_none() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::Context;
+use unc_primitives::state_record::StateRecord;
+use unc_primitives::types::{AccountId, AccountInfo};
+use unc_primitives_core::types::Balance;
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FilterKind {
+    /// --remove-accounts: the Account, AccessKey, Contract and Data records of the listed
+    /// accounts are dropped, along with the receipts and received data bound for them
+    Remove,
+    /// --keep-only-accounts: all records of accounts that aren't listed are dropped
+    KeepOnly,
+}
+
+/// Accounts given with --remove-accounts or --keep-only-accounts, whose records are dropped
+/// from the input records as they're streamed.
+pub(crate) struct AccountFilter {
+    kind: FilterKind,
+    accounts: HashSet<AccountId>,
+    // number of Account records dropped
+    num_accounts: u64,
+    // number of other records dropped, other than receipts
+    num_other_records: u64,
+    // number of PostponedReceipt, DelayedReceipt and ReceivedData records dropped
+    num_receipts: u64,
+    // total balance (amount plus pledge) of the dropped accounts
+    balance: Balance,
+}
+
+fn read_accounts(path: &Path) -> anyhow::Result<HashSet<AccountId>> {
+    let accounts = std::fs::read_to_string(path)
+        .with_context(|| format!("failed reading from {}", path.display()))?;
+    serde_json::from_str(&accounts)
+        .with_context(|| format!("failed deserializing from {}", path.display()))
+}
+
+impl AccountFilter {
+    fn new(kind: FilterKind, accounts: HashSet<AccountId>) -> Self {
+        Self { kind, accounts, num_accounts: 0, num_other_records: 0, num_receipts: 0, balance: 0 }
+    }
+
+    pub(crate) fn remove_from_file(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::new(FilterKind::Remove, read_accounts(path)?))
+    }
+
+    pub(crate) fn keep_only_from_file(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::new(FilterKind::KeepOnly, read_accounts(path)?))
+    }
+
+    /// Fails if one of the accounts to remove is also one of the output validators.
+    pub(crate) fn check_validators(&self, validators: &[AccountInfo]) -> anyhow::Result<()> {
+        if self.kind != FilterKind::Remove {
+            return Ok(());
+        }
+        match validators.iter().find(|v| self.accounts.contains(&v.account_id)) {
+            Some(v) => anyhow::bail!(
+                "{} is listed in both --remove-accounts and --validators",
+                &v.account_id
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes sure the records of `accounts` are kept when only listed accounts are kept.
+    /// Used for the accounts the output needs no matter what the list says, like the
+    /// validators.
+    pub(crate) fn keep_also(&mut self, accounts: impl IntoIterator<Item = AccountId>) {
+        if self.kind == FilterKind::KeepOnly {
+            self.accounts.extend(accounts);
+        }
+    }
+
+    fn drops(&self, account_id: &AccountId) -> bool {
+        match self.kind {
+            FilterKind::Remove => self.accounts.contains(account_id),
+            FilterKind::KeepOnly => !self.accounts.contains(account_id),
+        }
+    }
+
+    /// Returns whether `record` belongs to one of the filtered out accounts and should be left
+    /// out of the output.
+    pub(crate) fn removes(&mut self, record: &StateRecord) -> bool {
+        match record {
+            StateRecord::Account { account_id, account } => {
+                if !self.drops(account_id) {
+                    return false;
+                }
+                self.num_accounts += 1;
+                self.balance += account.amount() + account.pledging();
+                true
+            }
+            StateRecord::AccessKey { account_id, .. }
+            | StateRecord::Contract { account_id, .. }
+            | StateRecord::Data { account_id, .. }
+            | StateRecord::Rsa2048Keys { account_id, .. } => {
+                if !self.drops(account_id) {
+                    return false;
+                }
+                self.num_other_records += 1;
+                true
+            }
+            // a receipt left for a removed account would be applied to an account that
+            // doesn't exist anymore
+            StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
+                if !self.drops(&receipt.receiver_id) {
+                    return false;
+                }
+                self.num_receipts += 1;
+                true
+            }
+            // data received for a removed account, which only receipts bound for it would read
+            StateRecord::ReceivedData { account_id, .. } => {
+                if !self.drops(account_id) {
+                    return false;
+                }
+                self.num_receipts += 1;
+                true
+            }
+        }
+    }
+
+    /// Number of receipts and received data records dropped so far for being bound for one of
+    /// the filtered out accounts.
+    pub(crate) fn num_receipts(&self) -> u64 {
+        self.num_receipts
+    }
+
+    pub(crate) fn log(&self) {
+        match self.kind {
+            FilterKind::Remove => tracing::info!(
+                target: "amend-genesis",
+                "removed {} of the {} accounts given in --remove-accounts, with a total balance \
+                of {}, along with {} of their access key, contract and data records and {} \
+                receipts and received data records bound for them",
+                self.num_accounts,
+                self.accounts.len(),
+                self.balance,
+                self.num_other_records,
+                self.num_receipts
+            ),
+            FilterKind::KeepOnly => tracing::info!(
+                target: "amend-genesis",
+                "removed {} accounts not given in --keep-only-accounts, with a total balance of \
+                {}, along with {} other records of theirs and {} receipts and received data \
+                records bound for them",
+                self.num_accounts,
+                self.balance,
+                self.num_other_records,
+                self.num_receipts
+            ),
+        }
+    }
+}
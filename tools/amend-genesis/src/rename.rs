@@ -0,0 +1,109 @@
+use anyhow::Context;
+use unc_primitives::state_record::StateRecord;
+use unc_primitives::types::AccountId;
+use unc_primitives_core::account::AccessKeyPermission;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Account renames given with --rename-accounts, applied to the input records as they're
+/// streamed.
+pub(crate) struct AccountRenames {
+    renames: HashMap<AccountId, AccountId>,
+    // new account ID -> old account ID
+    targets: HashMap<AccountId, AccountId>,
+    // number of function call access keys whose receiver_id was rewritten
+    receiver_rewrites: u64,
+}
+
+impl AccountRenames {
+    pub(crate) fn new(renames: HashMap<AccountId, AccountId>) -> anyhow::Result<Self> {
+        let mut targets = HashMap::new();
+        for (old, new) in renames.iter() {
+            if let Some(other) = targets.insert(new.clone(), old.clone()) {
+                anyhow::bail!("both {} and {} are renamed to {}", other, old, new);
+            }
+        }
+        Ok(Self { renames, targets, receiver_rewrites: 0 })
+    }
+
+    pub(crate) fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let renames = std::fs::read_to_string(path)
+            .with_context(|| format!("failed reading from {}", path.display()))?;
+        let renames = serde_json::from_str(&renames)
+            .with_context(|| format!("failed deserializing from {}", path.display()))?;
+        Self::new(renames)
+    }
+
+    fn rename(&self, account_id: &mut AccountId) {
+        if let Some(new) = self.renames.get(account_id) {
+            *account_id = new.clone();
+        }
+    }
+
+    /// Renames the account `record` belongs to, leaving the rest of it as it is. Unlike
+    /// `apply()`, it checks nothing and counts nothing.
+    pub(crate) fn rename_owner(&self, record: &mut StateRecord) {
+        match record {
+            StateRecord::Account { account_id, .. }
+            | StateRecord::AccessKey { account_id, .. }
+            | StateRecord::Data { account_id, .. }
+            | StateRecord::Contract { account_id, .. }
+            | StateRecord::ReceivedData { account_id, .. }
+            | StateRecord::Rsa2048Keys { account_id, .. } => self.rename(account_id),
+            StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
+                self.rename(&mut receipt.receiver_id)
+            }
+        }
+    }
+
+    /// Renames the accounts `record` refers to, including the receiver of function call
+    /// access keys.
+    pub(crate) fn apply(&mut self, record: &mut StateRecord) -> anyhow::Result<()> {
+        match record {
+            StateRecord::Account { account_id, .. } => {
+                if !self.renames.contains_key(account_id) {
+                    if let Some(old) = self.targets.get(account_id) {
+                        anyhow::bail!(
+                            "{} is renamed to {}, but there's already an account with that ID",
+                            old,
+                            account_id
+                        );
+                    }
+                }
+                self.rename(account_id);
+            }
+            StateRecord::AccessKey { account_id, access_key, .. } => {
+                self.rename(account_id);
+                if let AccessKeyPermission::FunctionCall(permission) = &mut access_key.permission {
+                    let new = permission
+                        .receiver_id
+                        .parse::<AccountId>()
+                        .ok()
+                        .and_then(|receiver_id| self.renames.get(&receiver_id));
+                    if let Some(new) = new {
+                        permission.receiver_id = new.to_string();
+                        self.receiver_rewrites += 1;
+                    }
+                }
+            }
+            StateRecord::Data { account_id, .. }
+            | StateRecord::Contract { account_id, .. }
+            | StateRecord::ReceivedData { account_id, .. }
+            | StateRecord::Rsa2048Keys { account_id, .. } => self.rename(account_id),
+            StateRecord::PostponedReceipt(receipt) | StateRecord::DelayedReceipt(receipt) => {
+                self.rename(&mut receipt.predecessor_id);
+                self.rename(&mut receipt.receiver_id);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn log(&self) {
+        tracing::info!(
+            target: "amend-genesis",
+            "renamed {} accounts, and rewrote the receiver of {} function call access keys",
+            self.renames.len(),
+            self.receiver_rewrites
+        );
+    }
+}
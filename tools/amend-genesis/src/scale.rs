@@ -0,0 +1,50 @@
+use num_rational::Rational32;
+use unc_primitives::types::Balance;
+use unc_primitives_core::account::Account;
+
+/// `balance` times `ratio`, rounded down, along with what was rounded away times the ratio's
+/// denominator. Doesn't overflow as long as the result fits.
+fn scale(balance: Balance, ratio: Rational32) -> (Balance, u128) {
+    let numer = *ratio.numer() as u128;
+    let denom = *ratio.denom() as u128;
+    let rest = balance % denom * numer;
+    ((balance / denom) * numer + rest / denom, rest % denom)
+}
+
+/// Scales balances with --scale-balances, keeping track of the dust rounded away so that it
+/// can be given to the protocol treasury account. That keeps the output total supply the
+/// input one times the ratio, rounded down.
+pub(crate) struct BalanceScaler {
+    ratio: Rational32,
+    // the dust so far, times the ratio's denominator
+    dust: u128,
+}
+
+impl BalanceScaler {
+    pub(crate) fn new(ratio: Rational32) -> Self {
+        Self { ratio, dust: 0 }
+    }
+
+    /// Scales a balance that isn't counted in the total supply, such as a pledge in the
+    /// genesis config's validators. Its dust isn't kept.
+    pub(crate) fn scale_balance(&self, balance: Balance) -> Balance {
+        scale(balance, self.ratio).0
+    }
+
+    /// Scales the amount and pledge of `account`.
+    pub(crate) fn scale_account(&mut self, account: &mut Account) {
+        let (amount, amount_dust) = scale(account.amount(), self.ratio);
+        let (pledging, pledging_dust) = scale(account.pledging(), self.ratio);
+        account.set_amount(amount);
+        account.set_pledging(pledging);
+        self.dust += amount_dust + pledging_dust;
+    }
+
+    /// Returns the whole units of dust collected so far, leaving what's left of a unit.
+    pub(crate) fn take_dust(&mut self) -> Balance {
+        let denom = *self.ratio.denom() as u128;
+        let dust = self.dust / denom;
+        self.dust %= denom;
+        dust
+    }
+}
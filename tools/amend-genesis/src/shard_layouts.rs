@@ -0,0 +1,23 @@
+use unc_primitives::shard_layout::ShardLayout;
+
+/// A shard layout built into the tool, given with --shard-layout instead of writing it to a
+/// file for --shard-layout-file.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamedShardLayout {
+    /// a single shard
+    Single,
+    /// the four shards of simple nightshade
+    SimpleNightshadeV1,
+    /// simple nightshade with its last shard split in two, for five shards
+    SimpleNightshadeV2,
+}
+
+impl NamedShardLayout {
+    pub fn shard_layout(self) -> ShardLayout {
+        match self {
+            Self::Single => ShardLayout::v0_single_shard(),
+            Self::SimpleNightshadeV1 => ShardLayout::get_simple_nightshade_layout(),
+            Self::SimpleNightshadeV2 => ShardLayout::get_simple_nightshade_layout_v2(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Context;
+use unc_chain_configs::{Genesis, GenesisValidationMode};
+use unc_primitives::types::StateRoot;
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What we got from initializing the genesis state from the output files.
+#[derive(Debug)]
+pub struct SmokeTestReport {
+    /// Genesis state root of each shard, indexed by shard ID.
+    pub state_roots: Vec<StateRoot>,
+    /// Time it took to compute all of the state roots.
+    pub elapsed: Duration,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Initializes the genesis state from the given genesis and records files in a temporary
+/// store, going through the same code a node runs on its first start, and fails if that
+/// doesn't succeed.
+pub(crate) fn run_smoke_test(
+    genesis_file: &Path,
+    records_file: &Path,
+) -> anyhow::Result<SmokeTestReport> {
+    let genesis =
+        Genesis::from_files(genesis_file, records_file, GenesisValidationMode::UnsafeFast)
+            .context("failed loading the output genesis for the smoke test")?;
+    let store = unc_store::test_utils::create_test_store();
+
+    let start = Instant::now();
+    // initialize_genesis_state() panics on any problem, since there's nothing else a node
+    // could do about it
+    std::panic::catch_unwind(AssertUnwindSafe(|| {
+        unc_store::genesis::initialize_genesis_state(store.clone(), &genesis, None)
+    }))
+    .map_err(|payload| {
+        anyhow::anyhow!("initializing the genesis state failed: {}", panic_message(&*payload))
+    })?;
+    let elapsed = start.elapsed();
+
+    let state_roots = unc_store::get_genesis_state_roots(&store)
+        .context("failed reading genesis state roots")?
+        .context("no genesis state roots were written by the smoke test")?;
+    for (shard_id, state_root) in state_roots.iter().enumerate() {
+        tracing::info!(target: "amend-genesis", shard_id, %state_root, "smoke test state root");
+    }
+    tracing::info!(target: "amend-genesis", ?elapsed, "smoke test passed");
+    Ok(SmokeTestReport { state_roots, elapsed })
+}
@@ -0,0 +1,82 @@
+use crate::records::{RecordWriter, RecordsFormat};
+use crate::AmendGenesisError;
+use anyhow::Context;
+use unc_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use unc_primitives::state_record::{state_record_to_account_id, StateRecord};
+use unc_primitives::types::ShardId;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the file in --records-out-dir holding the records of `shard_id`.
+pub(crate) fn shard_file_name(shard_id: ShardId) -> String {
+    format!("records_shard_{}.json", shard_id)
+}
+
+/// Writes the output records once more to a file per shard in --records-out-dir, so that
+/// each shard's records can be imported on their own. Records go to the shard their account
+/// is in under the output shard layout, or the receiver's for receipts, the same way a node
+/// assigns genesis records to shards. Records of unknown types go to shard 0.
+pub(crate) struct SplitRecords {
+    dir: PathBuf,
+    shard_layout: ShardLayout,
+    writers: BTreeMap<ShardId, RecordWriter<BufWriter<File>>>,
+}
+
+impl SplitRecords {
+    pub(crate) fn create(
+        dir: &Path,
+        shard_layout: ShardLayout,
+        format: RecordsFormat,
+        pretty: bool,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed creating {}", dir.display()))?;
+        let mut writers = BTreeMap::new();
+        for shard_id in shard_layout.shard_ids() {
+            let path = dir.join(shard_file_name(shard_id));
+            let file = File::create(&path)
+                .with_context(|| format!("Failed opening output records {}", path.display()))?;
+            writers.insert(shard_id, RecordWriter::new(BufWriter::new(file), format, pretty));
+        }
+        Ok(Self { dir: dir.to_path_buf(), shard_layout, writers })
+    }
+
+    fn writer(&mut self, shard_id: ShardId) -> &mut RecordWriter<BufWriter<File>> {
+        // there's a writer for every shard of the layout
+        self.writers.get_mut(&shard_id).unwrap()
+    }
+
+    pub(crate) fn write(&mut self, record: &StateRecord) -> Result<(), AmendGenesisError> {
+        let account_id = state_record_to_account_id(record);
+        let shard_id = account_id_to_shard_id(account_id, &self.shard_layout);
+        self.writer(shard_id).write(record)
+    }
+
+    pub(crate) fn write_unknown(
+        &mut self,
+        tag: String,
+        value: &serde_json::Value,
+    ) -> Result<(), AmendGenesisError> {
+        self.writer(0).write_unknown(tag, value)
+    }
+
+    /// Ends the records of every shard and flushes them.
+    pub(crate) fn finish(self) -> Result<(), AmendGenesisError> {
+        for (shard_id, writer) in self.writers {
+            let (stats, mut out) = writer.end()?;
+            let path = self.dir.join(shard_file_name(shard_id));
+            out.flush()
+                .with_context(|| format!("Failed writing {}", path.display()))
+                .map_err(AmendGenesisError::Io)?;
+            tracing::info!(
+                target: "amend-genesis",
+                "wrote {} records to {}",
+                stats.known.values().chain(stats.unknown.values()).sum::<u64>(),
+                shard_file_name(shard_id)
+            );
+        }
+        Ok(())
+    }
+}
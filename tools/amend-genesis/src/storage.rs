@@ -0,0 +1,86 @@
+use crate::records::{self, InputRecord};
+use crate::AmendGenesisError;
+use anyhow::Context;
+use unc_primitives::state_record::{state_record_to_account_id, StateRecord};
+use unc_primitives::types::{AccountId, StorageUsage};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The bytes `record` adds to the storage usage of its account, counted the same way the
+/// runtime does for genesis records. Receipts don't count towards any account.
+fn record_storage_usage(record: &StateRecord, num_extra_bytes_record: u64) -> u64 {
+    match record {
+        StateRecord::AccessKey { public_key, access_key, .. } => {
+            public_key.len() as u64
+                + borsh::object_length(access_key).unwrap() as u64
+                + num_extra_bytes_record
+        }
+        StateRecord::Contract { code, .. } => code.len() as u64,
+        StateRecord::Data { data_key, value, .. } => {
+            (data_key.len() + value.len()) as u64 + num_extra_bytes_record
+        }
+        StateRecord::Rsa2048Keys { rsa_key, .. } => {
+            borsh::object_length(rsa_key).unwrap() as u64 + num_extra_bytes_record
+        }
+        _ => 0,
+    }
+}
+
+/// Storage usage of the access keys, contract code and contract data of each account in the
+/// input records, for --recompute-storage-usage. Since those records can come before or after
+/// the Account record they belong to, they're tallied in a first pass over the input records,
+/// and each account's tally is taken out when its Account record turns up in the second pass.
+/// Only the tallies of accounts not written yet are kept around.
+pub(crate) struct StorageTally {
+    usage: HashMap<AccountId, StorageUsage>,
+}
+
+impl StorageTally {
+    /// Tallies the records in `path`. `prepare` is given each record before it's counted, to
+    /// change it the way the second pass will, and returns false for records that won't be
+    /// written.
+    pub(crate) fn from_file(
+        path: &Path,
+        num_extra_bytes_record: u64,
+        mut prepare: impl FnMut(&mut StateRecord) -> bool,
+    ) -> Result<Self, AmendGenesisError> {
+        let reader = crate::compression::open_records(path)
+            .with_context(|| {
+                format!("Failed opening records file {} to tally storage usage", path.display())
+            })
+            .map_err(AmendGenesisError::Io)?;
+        let mut usage = HashMap::<AccountId, StorageUsage>::new();
+        records::stream_records(reader, |r| {
+            let InputRecord::Known(mut r) = r else {
+                return Ok(());
+            };
+            if !prepare(&mut r) {
+                return Ok(());
+            }
+            let bytes = record_storage_usage(&r, num_extra_bytes_record);
+            if bytes > 0 {
+                *usage.entry(state_record_to_account_id(&r).clone()).or_default() += bytes;
+            }
+            Ok(())
+        })
+        .map_err(|e| {
+            AmendGenesisError::from_stream_error(e).context(format!(
+                "Failed tallying the storage usage of the records in {}",
+                path.display()
+            ))
+        })?;
+        tracing::info!(
+            target: "amend-genesis",
+            "tallied the storage usage of the records of {} accounts in {}",
+            usage.len(),
+            path.display()
+        );
+        Ok(Self { usage })
+    }
+
+    /// Takes out the storage usage of the records of `account_id`, apart from its Account
+    /// record.
+    pub(crate) fn take(&mut self, account_id: &AccountId) -> StorageUsage {
+        self.usage.remove(account_id).unwrap_or_default()
+    }
+}
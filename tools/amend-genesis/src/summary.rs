@@ -0,0 +1,123 @@
+use crate::{AccountStatus, GenesisHashes, RecordStats};
+use unc_primitives::types::{AccountId, AccountInfo};
+use unc_primitives_core::serialize::dec_format;
+use unc_primitives_core::types::Balance;
+use std::fmt;
+
+/// Everything amend_genesis() reports back about what it did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AmendGenesisOutcome {
+    /// the counts the amend-genesis command prints with --dry-run or --summary-json
+    pub summary: AmendSummary,
+    /// total_supply set in the output genesis config
+    pub total_supply: Balance,
+    /// number of records written to the output records file of each type
+    pub records_written: RecordStats,
+    /// validators set in the output genesis config
+    pub validators: Vec<AccountInfo>,
+    /// accounts in the input records whose balance was replaced by the one given in the
+    /// extra records, sorted by account ID
+    pub balance_overrides: Vec<AccountId>,
+    /// validators with power but no Rsa2048Keys record in the input records or the extra
+    /// records, so they won't be able to prove their power, sorted by account ID
+    pub validators_without_chips: Vec<AccountId>,
+    /// hashes of the output, with `AmendOptions::emit_genesis_hash` or
+    /// `AmendOptions::template_out` set
+    pub genesis_hashes: Option<GenesisHashes>,
+}
+
+/// What amend_genesis() was doing when a summary was written.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AmendPhase {
+    /// Reading the input genesis and the files given in the options.
+    #[default]
+    Started,
+    /// Going through the input records.
+    ProcessingRecords,
+    /// Writing the accounts from the validators and the extra records.
+    WritingNewRecords,
+    /// Done, with the output written unless it was a dry run.
+    Done,
+}
+
+/// What amend_genesis() did to the input, or would have done with `AmendOptions::dry_run` set.
+/// The same summary is written to `AmendOptions::summary_out` while it's running, with
+/// `complete` unset.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AmendSummary {
+    /// accounts that don't exist in the input records, added from the validators, the extra
+    /// records or `AmendOptions::generate_accounts`
+    pub accounts_added: u64,
+    /// accounts in the input records changed by the validators or the extra records
+    pub accounts_modified: u64,
+    /// accounts copied from the input records, possibly with their pledge returned to
+    /// their balance
+    pub accounts_passed_through: u64,
+    /// access keys from the validators or the extra records written to the output, whether
+    /// they're new or replace a key of the same account in the input records
+    pub access_keys_injected: u64,
+    /// validators in the input genesis config that aren't validators in the output
+    pub validators_replaced: u64,
+    /// Data records left out with `AmendOptions::truncate_contract_state` or
+    /// `AmendOptions::strip_contracts`
+    pub contract_keys_dropped: u64,
+    /// bytes of keys and values of the Data records left out, whether whole records or the
+    /// end of their values. Doesn't include contract code left out with
+    /// `AmendOptions::strip_contracts`
+    pub contract_bytes_dropped: u64,
+    /// Contract records whose code was replaced with `AmendOptions::replace_contract_code`
+    pub contracts_replaced: u64,
+    /// Account and AccessKey records left out with `AmendOptions::dedupe_input` for repeating
+    /// an earlier one
+    #[serde(default)]
+    pub duplicate_records_dropped: u64,
+    /// PostponedReceipt, DelayedReceipt and ReceivedData records left out for being bound for
+    /// an account removed with `AmendOptions::remove_accounts` or
+    /// `AmendOptions::keep_only_accounts`
+    #[serde(default)]
+    pub receipts_dropped: u64,
+    /// total supply of the output records, or of the ones written so far if not `complete`
+    #[serde(with = "dec_format")]
+    pub total_supply: Balance,
+    /// records read from the input records file
+    pub records_processed: u64,
+    pub phase: AmendPhase,
+    /// account of the last input record read. Only kept track of with
+    /// `AmendOptions::summary_out` set
+    pub last_account_id: Option<AccountId>,
+    /// set once amend_genesis() is done, in which case the counts above are final
+    pub complete: bool,
+}
+
+impl AmendSummary {
+    pub(crate) fn add_account(&mut self, status: AccountStatus) {
+        match status {
+            AccountStatus::Added => self.accounts_added += 1,
+            AccountStatus::Modified => self.accounts_modified += 1,
+            AccountStatus::PassedThrough => self.accounts_passed_through += 1,
+        }
+    }
+}
+
+impl fmt::Display for AmendSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = [
+            ("accounts added", self.accounts_added.to_string()),
+            ("accounts modified", self.accounts_modified.to_string()),
+            ("accounts passed through", self.accounts_passed_through.to_string()),
+            ("access keys injected", self.access_keys_injected.to_string()),
+            ("validators replaced", self.validators_replaced.to_string()),
+            ("contract keys dropped", self.contract_keys_dropped.to_string()),
+            ("contract bytes dropped", self.contract_bytes_dropped.to_string()),
+            ("contracts replaced", self.contracts_replaced.to_string()),
+            ("duplicates dropped", self.duplicate_records_dropped.to_string()),
+            ("receipts dropped", self.receipts_dropped.to_string()),
+            ("total supply", self.total_supply.to_string()),
+        ];
+        for (name, value) in rows.iter() {
+            writeln!(f, "{:<24} {:>40}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::Context;
+use unc_primitives::state_record::StateRecord;
+use unc_primitives::types::{AccountId, StoreKey, StoreValue};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// How much of the contract state of an account to keep, given with --truncate-contract-state
+/// as `account_id=<ACCOUNT_ID>,max_keys=<N>,max_value_bytes=<N>`. At least one of the limits
+/// has to be given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractStateTruncation {
+    pub account_id: AccountId,
+    /// keep only this many of the account's Data records, the first ones in trie order
+    pub max_keys: Option<u64>,
+    /// cut the value of each Data record kept down to this many bytes
+    pub max_value_bytes: Option<u64>,
+}
+
+impl FromStr for ContractStateTruncation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut account_id = None;
+        let mut max_keys = None;
+        let mut max_value_bytes = None;
+        for field in s.split(',') {
+            let Some((name, value)) = field.split_once('=') else {
+                anyhow::bail!("expected <name>=<value>, got {:?}", field);
+            };
+            match name {
+                "account_id" => {
+                    account_id = Some(
+                        value.parse().with_context(|| format!("invalid account_id {}", value))?,
+                    )
+                }
+                "max_keys" => {
+                    max_keys =
+                        Some(value.parse().with_context(|| format!("invalid max_keys {}", value))?)
+                }
+                "max_value_bytes" => {
+                    max_value_bytes = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("invalid max_value_bytes {}", value))?,
+                    )
+                }
+                _ => anyhow::bail!(
+                    "unknown field {}, expected account_id, max_keys or max_value_bytes",
+                    name
+                ),
+            }
+        }
+        let Some(account_id) = account_id else {
+            anyhow::bail!("account_id=<ACCOUNT_ID> has to be given");
+        };
+        if max_keys.is_none() && max_value_bytes.is_none() {
+            anyhow::bail!("at least one of max_keys and max_value_bytes has to be given");
+        }
+        Ok(Self { account_id, max_keys, max_value_bytes })
+    }
+}
+
+// what's been seen of the contract state of one account being truncated
+struct TruncatedState {
+    max_keys: Option<u64>,
+    max_value_bytes: Option<u64>,
+    // with max_keys set, the Data records kept so far. They're held here until all the input
+    // records have been seen, so that the ones kept are the first in trie order (sorted by
+    // key) no matter what order the input records are in
+    held: BTreeMap<Vec<u8>, StoreValue>,
+    // number of Data records of the account in the input, and the size of their keys and
+    // values
+    keys_in: u64,
+    bytes_in: u64,
+    // the same for the Data records written to the output without being held
+    keys_out: u64,
+    bytes_out: u64,
+}
+
+/// The contract state left out of the output for one account by --truncate-contract-state.
+pub(crate) struct DroppedState {
+    pub(crate) account_id: AccountId,
+    pub(crate) keys: u64,
+    /// size of the keys and values dropped, not counting the per record overhead
+    pub(crate) bytes: u64,
+    /// the Data records kept with max_keys set, to write after the account's Account record
+    pub(crate) held: Vec<StateRecord>,
+}
+
+/// Truncates the Data records of the accounts given with --truncate-contract-state as
+/// they're streamed.
+pub(crate) struct ContractStateTruncator {
+    accounts: HashMap<AccountId, TruncatedState>,
+}
+
+impl ContractStateTruncator {
+    pub(crate) fn new(truncations: &[ContractStateTruncation]) -> anyhow::Result<Self> {
+        let mut accounts = HashMap::new();
+        for t in truncations.iter() {
+            let state = TruncatedState {
+                max_keys: t.max_keys,
+                max_value_bytes: t.max_value_bytes,
+                held: BTreeMap::new(),
+                keys_in: 0,
+                bytes_in: 0,
+                keys_out: 0,
+                bytes_out: 0,
+            };
+            if accounts.insert(t.account_id.clone(), state).is_some() {
+                anyhow::bail!(
+                    "--truncate-contract-state given twice for account {}",
+                    &t.account_id
+                );
+            }
+        }
+        Ok(Self { accounts })
+    }
+
+    pub(crate) fn truncates(&self, account_id: &AccountId) -> bool {
+        self.accounts.contains_key(account_id)
+    }
+
+    /// Applies the limits to `record` if it's a Data record of one of the truncated accounts,
+    /// and returns whether it should be written to the output now. It isn't if it's dropped,
+    /// or held to be returned by `finish()`.
+    pub(crate) fn apply(&mut self, record: &mut StateRecord) -> bool {
+        let StateRecord::Data { account_id, data_key, value } = record else {
+            return true;
+        };
+        let Some(state) = self.accounts.get_mut(account_id) else {
+            return true;
+        };
+        state.keys_in += 1;
+        state.bytes_in += (data_key.len() + value.len()) as u64;
+        if let Some(max) = state.max_value_bytes {
+            if value.len() as u64 > max {
+                *value = StoreValue::from(value[..max as usize].to_vec());
+            }
+        }
+        let Some(max_keys) = state.max_keys else {
+            state.keys_out += 1;
+            state.bytes_out += (data_key.len() + value.len()) as u64;
+            return true;
+        };
+        let key: Vec<u8> = data_key.to_vec();
+        if (state.held.len() as u64) < max_keys {
+            state.held.insert(key, value.clone());
+        } else if state.held.last_key_value().is_some_and(|(last, _)| key < *last) {
+            state.held.pop_last();
+            state.held.insert(key, value.clone());
+        }
+        false
+    }
+
+    /// Returns what was dropped for each of the truncated accounts, along with the Data
+    /// records held back, sorted by account ID.
+    pub(crate) fn finish(self) -> Vec<DroppedState> {
+        let mut dropped = self
+            .accounts
+            .into_iter()
+            .map(|(account_id, mut state)| {
+                let held = std::mem::take(&mut state.held)
+                    .into_iter()
+                    .map(|(data_key, value)| {
+                        state.keys_out += 1;
+                        state.bytes_out += (data_key.len() + value.len()) as u64;
+                        StateRecord::Data {
+                            account_id: account_id.clone(),
+                            data_key: StoreKey::from(data_key),
+                            value,
+                        }
+                    })
+                    .collect();
+                if state.keys_in == 0 {
+                    tracing::warn!(
+                        target: "amend-genesis",
+                        "no Data records found for {} given in --truncate-contract-state",
+                        &account_id
+                    );
+                }
+                DroppedState {
+                    account_id,
+                    keys: state.keys_in - state.keys_out,
+                    bytes: state.bytes_in - state.bytes_out,
+                    held,
+                }
+            })
+            .collect::<Vec<_>>();
+        dropped.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        dropped
+    }
+}
@@ -0,0 +1,46 @@
+use crate::compression;
+use crate::records::{self, InputRecord};
+use anyhow::Context;
+use unc_chain_configs::genesis_validate::GenesisValidator;
+use unc_chain_configs::GenesisConfig;
+use std::path::Path;
+
+/// How to check the input genesis, given with --validation-mode.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// run the same checks on the input genesis config and records as a node does with
+    /// full genesis validation, and fail if any of them don't pass
+    Full,
+    /// don't check the input
+    #[default]
+    UnsafeFast,
+}
+
+/// Runs the checks a node runs with full genesis validation on `config` and the records in
+/// `records_file`. Unlike loading the genesis with `GenesisValidationMode::Full`, a records file
+/// that can't be parsed is reported as an error rather than a panic, and records of types this
+/// build doesn't know about are skipped.
+pub(crate) fn validate_genesis(config: &GenesisConfig, records_file: &Path) -> anyhow::Result<()> {
+    let reader = compression::open_records(records_file).with_context(|| {
+        format!("Failed opening records file {} for validation", records_file.display())
+    })?;
+    let mut validator = GenesisValidator::new(config);
+    let mut num_unknown = 0;
+    records::stream_records(reader, |r| {
+        match r {
+            InputRecord::Known(r) => validator.process_record(&r),
+            InputRecord::Unknown { .. } => num_unknown += 1,
+        }
+        Ok(())
+    })
+    .with_context(|| format!("Failed reading records from {}", records_file.display()))?;
+    if num_unknown > 0 {
+        tracing::warn!(
+            target: "amend-genesis",
+            "skipped {} records of unknown types in {} while validating",
+            num_unknown,
+            records_file.display()
+        );
+    }
+    Ok(validator.finish()?)
+}
@@ -603,6 +603,9 @@ impl ForkNetworkCommand {
                         }
                         index_delayed_receipt += 1;
                     }
+                    // TODO: move the chips of the implicit accounts mapped above to their new
+                    // accounts too
+                    StateRecord::Rsa2048Keys { .. } => {}
                 }
                 records_parsed += 1;
             } else {
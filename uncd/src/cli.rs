@@ -115,7 +115,11 @@ impl NeardCmd {
                 cmd.run()?;
             }
             NeardSubCommand::AmendGenesis(cmd) => {
-                cmd.run()?;
+                if let Err(err) = cmd.run() {
+                    let code = err.exit_code();
+                    eprintln!("Error: {:?}", err.into_inner());
+                    std::process::exit(code);
+                }
             }
             NeardSubCommand::ColdStore(cmd) => {
                 cmd.run(&home_dir)?;